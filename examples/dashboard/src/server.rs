@@ -39,12 +39,12 @@ impl OmniPaxosServer {
             tokio::select! {
                 biased;
 
-                _ = op_tick_interval.tick() => { self.omni_paxos.lock().unwrap().tick(); },
+                _ = op_tick_interval.tick() => { self.omni_paxos.lock().unwrap().tick().expect("Failed to tick"); },
                 _ = outgoing_interval.tick() => { self.send_outgoing_msgs().await; },
                 _ = op_ui_tick_interval.tick() => {
                     self.omni_paxos_ui.tick(self.omni_paxos.lock().unwrap().get_ui_states());
                 },
-                Some(in_msg) = self.incoming.recv() => { self.omni_paxos.lock().unwrap().handle_incoming(in_msg); },
+                Some(in_msg) = self.incoming.recv() => { self.omni_paxos.lock().unwrap().handle_incoming(in_msg).expect("Failed to handle message"); },
                 else => { }
             }
         }
@@ -37,9 +37,9 @@ impl OmniPaxosServer {
             tokio::select! {
                 biased;
 
-                _ = tick_interval.tick() => { self.omni_paxos.lock().unwrap().tick(); },
+                _ = tick_interval.tick() => { self.omni_paxos.lock().unwrap().tick().expect("Failed to tick"); },
                 _ = outgoing_interval.tick() => { self.send_outgoing_msgs().await; },
-                Some(in_msg) = self.incoming.recv() => { self.omni_paxos.lock().unwrap().handle_incoming(in_msg); },
+                Some(in_msg) = self.incoming.recv() => { self.omni_paxos.lock().unwrap().handle_incoming(in_msg).expect("Failed to handle message"); },
                 else => { }
             }
         }
@@ -0,0 +1,505 @@
+use crate::{
+    storage::{Entry, Storage},
+    util::{ConfigurationId, NodeId},
+    ClusterConfig, OmniPaxos, ReconfigurationStatus,
+};
+use std::{
+    collections::HashMap,
+    fmt::Write as _,
+    io::{self, BufRead, BufReader, Read, Write},
+    net::{SocketAddr, TcpListener, TcpStream, ToSocketAddrs},
+    time::{Duration, Instant},
+};
+
+/// How long the admin server waits for a client to send its whole request before it gives up on
+/// it.
+const REQUEST_TIMEOUT: Duration = Duration::from_millis(100);
+/// The largest request, including its headers, that the admin server reads.
+const MAX_REQUEST_SIZE: u64 = 64 * 1024;
+/// The most connections that a single call of [`AdminServer::poll`] serves, so that a flood of
+/// connections can't keep the caller from ticking. The other connections wait for the next call.
+const MAX_CONNECTIONS_PER_POLL: usize = 4;
+
+/// An operation of the admin API. Every request carries all its arguments, so the API has no
+/// sessions and any transport, e.g., the [`AdminServer`] or a gRPC service of the application,
+/// can translate its requests into this and call [`handle_admin_request`].
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum AdminRequest {
+    /// Returns the status of the server as JSON.
+    Status,
+    /// Returns the indexes and the leadership of the server in the Prometheus text format.
+    Metrics,
+    /// Calls [`OmniPaxos::snapshot`].
+    Snapshot {
+        /// The index to snapshot up to, or the decided index if `None`.
+        compact_idx: Option<usize>,
+        /// Whether only this server takes the snapshot.
+        local_only: bool,
+    },
+    /// Calls [`OmniPaxos::trim`].
+    Trim {
+        /// The index to trim up to, or the index accepted by all servers if `None`.
+        trim_idx: Option<usize>,
+    },
+    /// Calls [`OmniPaxos::step_down`] if this server is the leader, so that the server with the
+    /// highest priority takes over.
+    TransferLeadership,
+    /// Calls [`OmniPaxos::set_priority`], e.g., to raise the priority of the server that should
+    /// take over before the leadership is transferred.
+    SetPriority(u32),
+    /// Calls [`OmniPaxos::reconfigure`] with a cluster of `nodes`. The other fields of the new
+    /// [`ClusterConfig`] are the defaults.
+    Reconfigure {
+        /// The id of the new configuration.
+        configuration_id: ConfigurationId,
+        /// The servers of the new configuration.
+        nodes: Vec<NodeId>,
+    },
+}
+
+impl AdminRequest {
+    /// Parses the request from the HTTP `method`, the `path` of the endpoint, and the parameters
+    /// of the query string. Returns the response to send instead if the request is invalid.
+    pub fn parse(
+        method: &str,
+        path: &str,
+        params: &HashMap<String, String>,
+    ) -> Result<Self, AdminResponse> {
+        let request = match (method, path) {
+            ("GET", "/status") => AdminRequest::Status,
+            ("GET", "/metrics") => AdminRequest::Metrics,
+            ("POST", "/snapshot") => AdminRequest::Snapshot {
+                compact_idx: optional_param(params, "idx")?,
+                local_only: optional_param(params, "local")?.unwrap_or(false),
+            },
+            ("POST", "/trim") => AdminRequest::Trim {
+                trim_idx: optional_param(params, "idx")?,
+            },
+            ("POST", "/transfer-leadership") => AdminRequest::TransferLeadership,
+            ("POST", "/priority") => AdminRequest::SetPriority(param(params, "value")?),
+            ("POST", "/reconfigure") => AdminRequest::Reconfigure {
+                configuration_id: param(params, "configuration_id")?,
+                nodes: param::<String>(params, "nodes")?
+                    .split(',')
+                    .map(|pid| pid.trim().parse())
+                    .collect::<Result<_, _>>()
+                    .map_err(|_| AdminResponse::error(400, "invalid parameter `nodes`"))?,
+            },
+            (_, "/status" | "/metrics") => {
+                return Err(AdminResponse::error(405, "use GET for this endpoint"))
+            }
+            (_, "/snapshot" | "/trim" | "/transfer-leadership" | "/priority" | "/reconfigure") => {
+                return Err(AdminResponse::error(405, "use POST for this endpoint"))
+            }
+            _ => return Err(AdminResponse::error(404, "unknown endpoint")),
+        };
+        Ok(request)
+    }
+}
+
+fn param<V: std::str::FromStr>(
+    params: &HashMap<String, String>,
+    name: &str,
+) -> Result<V, AdminResponse> {
+    optional_param(params, name)?
+        .ok_or_else(|| AdminResponse::error(400, &format!("missing parameter `{}`", name)))
+}
+
+fn optional_param<V: std::str::FromStr>(
+    params: &HashMap<String, String>,
+    name: &str,
+) -> Result<Option<V>, AdminResponse> {
+    params
+        .get(name)
+        .map(|value| value.parse())
+        .transpose()
+        .map_err(|_| AdminResponse::error(400, &format!("invalid parameter `{}`", name)))
+}
+
+/// The response to an [`AdminRequest`].
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct AdminResponse {
+    /// The HTTP status code, e.g., 409 if the server refused the operation.
+    pub status: u16,
+    /// The content type of `body`.
+    pub content_type: &'static str,
+    /// The body of the response.
+    pub body: String,
+}
+
+impl AdminResponse {
+    fn json(status: u16, body: String) -> Self {
+        Self {
+            status,
+            content_type: "application/json",
+            body,
+        }
+    }
+
+    fn ok() -> Self {
+        Self::json(200, "{\"ok\":true}".to_string())
+    }
+
+    fn error(status: u16, message: &str) -> Self {
+        Self::json(status, format!("{{\"error\":{}}}", json_string(message)))
+    }
+
+    fn reason(&self) -> &'static str {
+        match self.status {
+            200 => "OK",
+            400 => "Bad Request",
+            401 => "Unauthorized",
+            404 => "Not Found",
+            405 => "Method Not Allowed",
+            409 => "Conflict",
+            _ => "Error",
+        }
+    }
+}
+
+/// Runs `request` on `omnipaxos` and returns the response. Operations that `omnipaxos` refuses,
+/// e.g., a trim past the decided index, are answered with the status 409 and the error.
+pub fn handle_admin_request<T, B>(
+    omnipaxos: &mut OmniPaxos<T, B>,
+    request: &AdminRequest,
+) -> AdminResponse
+where
+    T: Entry,
+    B: Storage<T>,
+{
+    match request {
+        AdminRequest::Status => AdminResponse::json(200, status(omnipaxos)),
+        AdminRequest::Metrics => AdminResponse {
+            status: 200,
+            content_type: "text/plain; version=0.0.4",
+            body: metrics(omnipaxos),
+        },
+        AdminRequest::Snapshot {
+            compact_idx,
+            local_only,
+        } => match omnipaxos.snapshot(*compact_idx, *local_only) {
+            Ok(()) => AdminResponse::ok(),
+            Err(e) => AdminResponse::error(409, &e.to_string()),
+        },
+        AdminRequest::Trim { trim_idx } => match omnipaxos.trim(*trim_idx) {
+            Ok(()) => AdminResponse::ok(),
+            Err(e) => AdminResponse::error(409, &e.to_string()),
+        },
+        AdminRequest::TransferLeadership => {
+            let pid = omnipaxos.get_ui_states().current_ballot.pid;
+            if omnipaxos.get_current_leader() == Some(pid) {
+                omnipaxos.step_down();
+                AdminResponse::ok()
+            } else {
+                AdminResponse::error(409, "this server is not the leader")
+            }
+        }
+        AdminRequest::SetPriority(priority) => {
+            omnipaxos.set_priority(*priority);
+            AdminResponse::ok()
+        }
+        AdminRequest::Reconfigure {
+            configuration_id,
+            nodes,
+        } => {
+            let config = ClusterConfig {
+                configuration_id: *configuration_id,
+                nodes: nodes.clone(),
+                ..Default::default()
+            };
+            match omnipaxos.reconfigure(config, None) {
+                Ok(status) => AdminResponse::json(
+                    200,
+                    format!("{{\"reconfiguration\":{}}}", reconfiguration(&status)),
+                ),
+                Err(e) => AdminResponse::error(409, &e.to_string()),
+            }
+        }
+    }
+}
+
+fn status<T, B>(omnipaxos: &OmniPaxos<T, B>) -> String
+where
+    T: Entry,
+    B: Storage<T>,
+{
+    let states = omnipaxos.get_ui_states();
+    let leader = omnipaxos
+        .get_current_leader()
+        .map_or("null".to_string(), |pid| pid.to_string());
+    let reconfiguration = omnipaxos
+        .reconfiguration_status()
+        .map_or("null".to_string(), |s| reconfiguration(&s));
+    let fatal_error = omnipaxos
+        .get_fatal_error()
+        .map_or("null".to_string(), |e| json_string(&e.to_string()));
+    let promise = omnipaxos.get_promise();
+    let memory = omnipaxos.get_memory_usage();
+    format!(
+        "{{\"pid\":{},\"leader\":{},\"promise\":{{\"config_id\":{},\"n\":{},\"pid\":{}}},\
+         \"decided_idx\":{},\"compacted_idx\":{},\"reconfiguration\":{},\"fatal_error\":{},\
+         \"memory_usage\":{{\"outgoing\":{},\"pending_proposals\":{},\"caches\":{},\"sync\":{},\
+         \"total\":{}}}}}",
+        states.current_ballot.pid,
+        leader,
+        promise.config_id,
+        promise.n,
+        promise.pid,
+        omnipaxos.get_decided_idx(),
+        omnipaxos.get_compacted_idx(),
+        reconfiguration,
+        fatal_error,
+        memory.outgoing,
+        memory.pending_proposals,
+        memory.caches,
+        memory.sync,
+        memory.total(),
+    )
+}
+
+fn reconfiguration(status: &ReconfigurationStatus) -> String {
+    match status {
+        ReconfigurationStatus::Proposed => "\"proposed\"".to_string(),
+        ReconfigurationStatus::Accepted => "\"accepted\"".to_string(),
+        ReconfigurationStatus::Decided => "\"decided\"".to_string(),
+        ReconfigurationStatus::Rejected(reason) => {
+            format!("{{\"rejected\":{}}}", json_string(reason))
+        }
+    }
+}
+
+fn metrics<T, B>(omnipaxos: &OmniPaxos<T, B>) -> String
+where
+    T: Entry,
+    B: Storage<T>,
+{
+    let pid = omnipaxos.get_ui_states().current_ballot.pid;
+    let is_leader = omnipaxos.get_current_leader() == Some(pid);
+    let gauges = [
+        (crate::metrics::IS_LEADER, u64::from(is_leader)),
+        (
+            crate::metrics::DECIDED_IDX,
+            omnipaxos.get_decided_idx() as u64,
+        ),
+        (
+            crate::metrics::COMPACTED_IDX,
+            omnipaxos.get_compacted_idx() as u64,
+        ),
+        (
+            crate::metrics::MEMORY_USAGE,
+            omnipaxos.get_memory_usage().total() as u64,
+        ),
+    ];
+    let mut out = String::new();
+    for (name, value) in gauges {
+        let _ = writeln!(out, "# TYPE {} gauge", name);
+        let _ = writeln!(out, "{}{{pid=\"{}\"}} {}", name, pid, value);
+    }
+    out
+}
+
+fn json_string(s: &str) -> String {
+    let mut out = String::with_capacity(s.len() + 2);
+    out.push('"');
+    for c in s.chars() {
+        match c {
+            '"' => out.push_str("\\\""),
+            '\\' => out.push_str("\\\\"),
+            '\n' => out.push_str("\\n"),
+            c if c.is_control() => {
+                let _ = write!(out, "\\u{:04x}", c as u32);
+            }
+            c => out.push(c),
+        }
+    }
+    out.push('"');
+    out
+}
+
+/// A minimal HTTP server for the admin API. It does not own a thread: the application calls
+/// [`AdminServer::poll`] with its `OmniPaxos` instance, e.g., next to `tick()`, and the pending
+/// requests are answered from there. Every connection carries a single request. The endpoints
+/// are:
+///
+/// | Endpoint | Parameters | Operation |
+/// | --- | --- | --- |
+/// | `GET /status` | | [`AdminRequest::Status`] |
+/// | `GET /metrics` | | [`AdminRequest::Metrics`] |
+/// | `POST /snapshot` | `idx`, `local` | [`AdminRequest::Snapshot`] |
+/// | `POST /trim` | `idx` | [`AdminRequest::Trim`] |
+/// | `POST /transfer-leadership` | | [`AdminRequest::TransferLeadership`] |
+/// | `POST /priority` | `value` | [`AdminRequest::SetPriority`] |
+/// | `POST /reconfigure` | `configuration_id`, `nodes` as a comma-separated list | [`AdminRequest::Reconfigure`] |
+///
+/// The parameters are passed in the query string. The admin API can stop the cluster, so it should
+/// only be reachable by operators, e.g., bound to localhost, or protected with a token.
+#[derive(Debug)]
+pub struct AdminServer {
+    listener: TcpListener,
+    token: Option<String>,
+}
+
+impl AdminServer {
+    /// Binds the admin server to `addr`.
+    pub fn bind<A: ToSocketAddrs>(addr: A) -> io::Result<Self> {
+        let listener = TcpListener::bind(addr)?;
+        listener.set_nonblocking(true)?;
+        Ok(Self {
+            listener,
+            token: None,
+        })
+    }
+
+    /// Requires every request to carry `token` in an `Authorization: Bearer` header.
+    pub fn with_token(mut self, token: String) -> Self {
+        self.token = Some(token);
+        self
+    }
+
+    /// Returns the address the server is bound to, e.g., if it was bound to port 0.
+    pub fn local_addr(&self) -> io::Result<SocketAddr> {
+        self.listener.local_addr()
+    }
+
+    /// Answers the requests of up to 4 pending connections with `omnipaxos`. Returns the number
+    /// of answered requests. Waits at most 100ms per connection for its whole request, so a call
+    /// blocks for at most 400ms.
+    pub fn poll<T, B>(&mut self, omnipaxos: &mut OmniPaxos<T, B>) -> io::Result<usize>
+    where
+        T: Entry,
+        B: Storage<T>,
+    {
+        let mut answered = 0;
+        for _ in 0..MAX_CONNECTIONS_PER_POLL {
+            let stream = match self.listener.accept() {
+                Ok((stream, _)) => stream,
+                Err(e) if e.kind() == io::ErrorKind::WouldBlock => break,
+                Err(e) => return Err(e),
+            };
+            // A client that misbehaves only loses its own request
+            if self.serve(stream, omnipaxos).is_ok() {
+                answered += 1;
+            }
+        }
+        Ok(answered)
+    }
+
+    fn serve<T, B>(&self, mut stream: TcpStream, omnipaxos: &mut OmniPaxos<T, B>) -> io::Result<()>
+    where
+        T: Entry,
+        B: Storage<T>,
+    {
+        stream.set_nonblocking(false)?;
+        let reader = DeadlineReader {
+            stream: &stream,
+            deadline: Instant::now() + REQUEST_TIMEOUT,
+        };
+        let response = match self.read_request(reader)? {
+            Ok(request) => handle_admin_request(omnipaxos, &request),
+            Err(response) => response,
+        };
+        write!(
+            stream,
+            "HTTP/1.1 {} {}\r\nContent-Type: {}\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+            response.status,
+            response.reason(),
+            response.content_type,
+            response.body.len(),
+            response.body
+        )?;
+        stream.flush()
+    }
+
+    /// Reads the request line and the headers of an HTTP request. The body is ignored.
+    fn read_request(
+        &self,
+        stream: DeadlineReader,
+    ) -> io::Result<Result<AdminRequest, AdminResponse>> {
+        let mut reader = BufReader::new(stream.take(MAX_REQUEST_SIZE));
+        let mut line = String::new();
+        reader.read_line(&mut line)?;
+        let mut parts = line.split_whitespace();
+        let (Some(method), Some(target)) = (parts.next(), parts.next()) else {
+            return Ok(Err(AdminResponse::error(400, "invalid request line")));
+        };
+        let (method, target) = (method.to_string(), target.to_string());
+        let mut authorized = self.token.is_none();
+        loop {
+            line.clear();
+            if reader.read_line(&mut line)? == 0 || line.trim().is_empty() {
+                break;
+            }
+            if let Some((name, value)) = line.split_once(':') {
+                let bearer = value.trim().strip_prefix("Bearer ");
+                if let (Some(bearer), Some(token)) = (bearer, &self.token) {
+                    if name.eq_ignore_ascii_case("authorization")
+                        && constant_time_eq(bearer.as_bytes(), token.as_bytes())
+                    {
+                        authorized = true;
+                    }
+                }
+            }
+        }
+        if !authorized {
+            return Ok(Err(AdminResponse::error(401, "missing or invalid token")));
+        }
+        let (path, query) = target.split_once('?').unwrap_or((&target, ""));
+        let params = query
+            .split('&')
+            .filter_map(|pair| pair.split_once('='))
+            .map(|(name, value)| Some((percent_decode(name)?, percent_decode(value)?)))
+            .collect::<Option<_>>();
+        let Some(params) = params else {
+            return Ok(Err(AdminResponse::error(400, "invalid query string")));
+        };
+        Ok(AdminRequest::parse(&method, path, &params))
+    }
+}
+
+/// Reads from a stream until the deadline of the request, however the reads are spread out.
+struct DeadlineReader<'a> {
+    stream: &'a TcpStream,
+    deadline: Instant,
+}
+
+impl Read for DeadlineReader<'_> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let remaining = self.deadline.saturating_duration_since(Instant::now());
+        if remaining.is_zero() {
+            return Err(io::ErrorKind::TimedOut.into());
+        }
+        self.stream.set_read_timeout(Some(remaining))?;
+        self.stream.read(buf)
+    }
+}
+
+/// Compares `input` with `secret` in a time that only depends on the length of `secret`.
+fn constant_time_eq(input: &[u8], secret: &[u8]) -> bool {
+    let mut diff = input.len() ^ secret.len();
+    for (i, s) in secret.iter().enumerate() {
+        diff |= usize::from(input.get(i).copied().unwrap_or(0) ^ s);
+    }
+    diff == 0
+}
+
+/// Decodes a component of a query string, i.e., `+` as a space and `%XX` as the byte `XX`.
+/// Returns `None` if the encoding or the decoded UTF-8 is invalid.
+fn percent_decode(s: &str) -> Option<String> {
+    let mut bytes = Vec::with_capacity(s.len());
+    let mut iter = s.bytes();
+    while let Some(b) = iter.next() {
+        match b {
+            b'+' => bytes.push(b' '),
+            b'%' => {
+                let hex = [iter.next()?, iter.next()?];
+                if !hex.iter().all(u8::is_ascii_hexdigit) {
+                    return None;
+                }
+                let hex = std::str::from_utf8(&hex).ok()?;
+                bytes.push(u8::from_str_radix(hex, 16).ok()?);
+            }
+            b => bytes.push(b),
+        }
+    }
+    String::from_utf8(bytes).ok()
+}
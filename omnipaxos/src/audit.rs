@@ -0,0 +1,103 @@
+use crate::util::{defaults::AUDIT_RECORDS, ConfigurationId, NodeId};
+#[cfg(feature = "serde")]
+use serde::{Deserialize, Serialize};
+use std::{collections::VecDeque, fmt::Display, time::SystemTime};
+
+/// An administrative operation that was initiated on a server, see [`AuditRecord`].
+#[derive(Clone, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+#[non_exhaustive]
+pub enum AuditAction {
+    /// [`OmniPaxos::trim`](crate::OmniPaxos::trim) with the given trim index.
+    Trim {
+        /// The requested trim index.
+        trim_idx: Option<usize>,
+    },
+    /// [`OmniPaxos::snapshot`](crate::OmniPaxos::snapshot) with the given arguments.
+    Snapshot {
+        /// The requested compaction index.
+        compact_idx: Option<usize>,
+        /// Whether only this server was asked to snapshot.
+        local_only: bool,
+    },
+    /// [`OmniPaxos::reconfigure`](crate::OmniPaxos::reconfigure) to the given configuration.
+    Reconfigure {
+        /// The id of the proposed configuration.
+        configuration_id: ConfigurationId,
+        /// The servers of the proposed configuration.
+        nodes: Vec<NodeId>,
+    },
+    /// [`OmniPaxos::step_down`](crate::OmniPaxos::step_down), i.e., a leadership transfer.
+    StepDown,
+    /// [`OmniPaxos::set_priority`](crate::OmniPaxos::set_priority) to the given priority.
+    SetPriority(u32),
+    /// [`OmniPaxos::fail_recover`](crate::OmniPaxos::fail_recover).
+    FailRecover,
+    /// `OmniPaxos::unsafe_truncate_after` from the given index.
+    Truncate {
+        /// The index from which the log was truncated.
+        idx: usize,
+    },
+    /// [`OmniPaxos::bootstrap_from_peer`](crate::OmniPaxos::bootstrap_from_peer) from the given
+    /// server.
+    BootstrapFromPeer(NodeId),
+    /// [`OmniPaxos::recover_seamlessly`](crate::OmniPaxos::recover_seamlessly).
+    RecoverSeamlessly,
+    /// [`OmniPaxos::swap_storage`](crate::OmniPaxos::swap_storage).
+    SwapStorage,
+    /// [`OmniPaxos::set_standby`](crate::OmniPaxos::set_standby) to the given server.
+    SetStandby(NodeId),
+}
+
+/// A record of an administrative operation in the audit log of a server, see
+/// [`OmniPaxos::get_audit_log`](crate::OmniPaxos::get_audit_log).
+#[derive(Clone, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct AuditRecord {
+    /// When the operation was initiated.
+    pub time: SystemTime,
+    /// Who initiated the operation, as set with
+    /// [`OmniPaxos::set_audit_initiator`](crate::OmniPaxos::set_audit_initiator), or `None` if no
+    /// initiator was set.
+    pub initiator: Option<String>,
+    /// The operation.
+    pub action: AuditAction,
+    /// The error the operation failed with, or `None` if it succeeded.
+    pub error: Option<String>,
+}
+
+/// The most recent administrative operations of a server and the initiator of the next ones.
+#[derive(Debug, Default)]
+pub(crate) struct AuditLog {
+    initiator: Option<String>,
+    records: VecDeque<AuditRecord>,
+}
+
+impl AuditLog {
+    pub(crate) fn set_initiator(&mut self, initiator: Option<String>) {
+        self.initiator = initiator;
+    }
+
+    /// Records `action` with the outcome `result`. The oldest record is dropped once the log holds
+    /// `AUDIT_RECORDS` records.
+    pub(crate) fn record<R, E: Display>(&mut self, action: AuditAction, result: &Result<R, E>) {
+        if self.records.len() == AUDIT_RECORDS {
+            self.records.pop_front();
+        }
+        self.records.push_back(AuditRecord {
+            time: SystemTime::now(),
+            initiator: self.initiator.clone(),
+            action,
+            error: result.as_ref().err().map(|e| e.to_string()),
+        });
+    }
+
+    /// Records `action`, which can't fail.
+    pub(crate) fn record_ok(&mut self, action: AuditAction) {
+        self.record(action, &Ok::<(), String>(()))
+    }
+
+    pub(crate) fn get_records(&self) -> Vec<AuditRecord> {
+        self.records.iter().cloned().collect()
+    }
+}
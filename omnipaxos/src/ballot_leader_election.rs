@@ -1,9 +1,16 @@
-use std::cmp::Ordering;
+use std::{
+    cmp::Ordering,
+    collections::{HashMap, VecDeque},
+    iter,
+};
 
 /// Ballot Leader Election algorithm for electing new leaders
 use crate::{
     sequence_paxos::{Phase, Role},
-    util::{defaults::*, ConfigurationId, FlexibleQuorum, Quorum},
+    util::{
+        defaults::*, ConfigFingerprint, ConfigurationId, FlexibleQuorum, Namespace, OutgoingQueues,
+        Quorum, Region,
+    },
 };
 
 #[cfg(feature = "logging")]
@@ -13,14 +20,15 @@ use crate::{
         BLEMessage, HeartbeatMsg, HeartbeatReply, HeartbeatRequest,
     },
     util::NodeId,
-    OmniPaxosConfig,
+    ConfigMismatch, OmniPaxosConfig,
 };
 #[cfg(feature = "serde")]
 use serde::{Deserialize, Serialize};
 #[cfg(feature = "logging")]
-use slog::{info, trace, Logger};
+use slog::{info, trace, warn, Logger};
 
-/// Used to define a Sequence Paxos epoch
+/// Used to define a Sequence Paxos epoch. Ballots are ordered by their configuration first, so a
+/// ballot of a previous configuration is always smaller than any ballot of a later one.
 #[derive(Clone, Copy, Eq, Debug, Default, PartialEq)]
 #[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
 pub struct Ballot {
@@ -52,7 +60,12 @@ impl Ballot {
 
 impl Ord for Ballot {
     fn cmp(&self, other: &Self) -> Ordering {
-        (self.n, self.priority, self.pid).cmp(&(other.n, other.priority, other.pid))
+        (self.config_id, self.n, self.priority, self.pid).cmp(&(
+            other.config_id,
+            other.n,
+            other.priority,
+            other.pid,
+        ))
     }
 }
 
@@ -84,15 +97,45 @@ pub(crate) struct BallotLeaderElection {
     prev_replies: Vec<HeartbeatReply>,
     /// Holds the current ballot of this instance.
     current_ballot: Ballot,
+    /// The highest round this instance used before it was recovered. It is never used again, even
+    /// if the recovered promise is older.
+    recovered_round: u32,
     /// The current leader of this instance.
     leader: Ballot,
     /// A happy node either sees that it is, is connected to, or sees evidence of a potential leader
     /// for the cluster. If a node is unhappy then it is seeking a new leader.
     happy: bool,
+    /// Whether this instance gives up its leadership until another server takes over.
+    stepping_down: bool,
+    /// The standby promise of the next round if a hot-standby leader was designated by the
+    /// current leader. Only set on the standby itself once it is ready to take over.
+    standby: Option<Ballot>,
+    /// The number of servers, including this one, that replied in the last heartbeat round.
+    connectivity: usize,
+    /// Whether the servers that replied in the last heartbeat round form a quorum. Only then can
+    /// this instance become the leader.
+    quorum_connected: bool,
     /// The number of replicas inside the cluster whose heartbeats are needed to become and remain the leader.
     quorum: Quorum,
     /// Vector which holds all the outgoing messages of the BLE instance.
-    outgoing: Vec<BLEMessage>,
+    outgoing: OutgoingQueues<BLEMessage>,
+    /// The configuration of this instance that is sent with every heartbeat request.
+    fingerprint: ConfigFingerprint,
+    /// The namespace of the cluster that is sent with every message.
+    namespace: Option<Namespace>,
+    /// The servers whose last heartbeat request had a different configuration than this instance.
+    mismatched_peers: HashMap<NodeId, ConfigFingerprint>,
+    /// The detected configuration mismatches that have not been taken by the user yet.
+    config_mismatches: VecDeque<ConfigMismatch>,
+    /// The payload of the application that is sent with every heartbeat request and reply.
+    heartbeat_payload: Option<Vec<u8>>,
+    /// The latest payload received from each peer with a heartbeat.
+    peer_payloads: HashMap<NodeId, Vec<u8>>,
+    /// The compression the transport of this server can decompress, advertised with every
+    /// heartbeat request and reply.
+    compression: Option<String>,
+    /// The compression each peer advertised with its latest heartbeat since it last reconnected.
+    peer_compressions: HashMap<NodeId, String>,
     /// Logger used to output the status of the component.
     #[cfg(feature = "logging")]
     logger: Logger,
@@ -100,15 +143,31 @@ pub(crate) struct BallotLeaderElection {
 
 impl BallotLeaderElection {
     /// Construct a new BallotLeaderElection node
-    pub(crate) fn with(config: BLEConfig, recovered_leader: Option<Ballot>) -> Self {
+    pub(crate) fn with(
+        config: BLEConfig,
+        recovered_leader: Option<Ballot>,
+        recovered_ballot: Option<Ballot>,
+    ) -> Self {
         let config_id = config.configuration_id;
         let pid = config.pid;
         let peers = config.peers;
         let num_nodes = &peers.len() + 1;
-        let quorum = Quorum::with(config.flexible_quorum, num_nodes);
-        let mut initial_ballot = Ballot::with(config_id, INITIAL_ROUND, config.priority, pid);
+        let quorum = Quorum::with(
+            config.flexible_quorum,
+            config.hierarchical_quorum,
+            &config.regions,
+            num_nodes,
+        );
+        let recovered_round = recovered_ballot.map_or(RECOVERY_ROUND, |b| b.n);
+        let mut initial_ballot = Ballot::with(
+            config_id,
+            INITIAL_ROUND.max(recovered_round + 1),
+            config.priority,
+            pid,
+        );
         let initial_leader = match recovered_leader {
-            Some(b) if b != Ballot::default() => {
+            // A promise of a previous configuration is smaller than any ballot of this one
+            Some(b) if b != Ballot::default() && b.config_id == config_id => {
                 // Prevents a recovered server from retaining BLE leadership with the same ballot.
                 initial_ballot.n = RECOVERY_ROUND;
                 b
@@ -123,10 +182,25 @@ impl BallotLeaderElection {
             heartbeat_replies: Vec::with_capacity(num_nodes),
             prev_replies: Vec::with_capacity(num_nodes),
             current_ballot: initial_ballot,
+            recovered_round,
             leader: initial_leader,
             happy: true,
+            stepping_down: false,
+            standby: None,
+            // Assume full connectivity until the first heartbeat round has completed so that the
+            // first election is not delayed
+            connectivity: num_nodes,
+            quorum_connected: true,
             quorum,
-            outgoing: Vec::with_capacity(config.buffer_size),
+            outgoing: OutgoingQueues::with(config.buffer_size),
+            fingerprint: config.fingerprint,
+            namespace: config.namespace,
+            mismatched_peers: HashMap::new(),
+            config_mismatches: VecDeque::new(),
+            heartbeat_payload: None,
+            peer_payloads: HashMap::new(),
+            compression: config.compression,
+            peer_compressions: HashMap::new(),
             #[cfg(feature = "logging")]
             logger: {
                 if let Some(logger) = config.custom_logger {
@@ -156,9 +230,68 @@ impl BallotLeaderElection {
         self.current_ballot.priority = p;
     }
 
+    /// Reconciles the recovered view of this instance with the promise that Sequence Paxos
+    /// recovered from the storage. A promise of another server is followed as the leader. If the
+    /// promise is this server's own ballot from before the restart, its leadership is given up: the
+    /// round is never used again, even if the persisted ballot is older, and this instance reports
+    /// itself unhappy so that a new leader is elected right away instead of after the old
+    /// leadership timed out. Returns whether a new election is started, which is also the case for
+    /// a promise of another configuration that this instance does not follow.
+    pub(crate) fn reconcile_with_promise(&mut self, promise: Ballot) -> bool {
+        if promise == Ballot::default() {
+            return false;
+        }
+        if promise.config_id != self.configuration_id {
+            return true;
+        }
+        if promise.pid != self.pid {
+            self.leader = self.leader.max(promise);
+            return false;
+        }
+        #[cfg(feature = "logging")]
+        info!(
+            self.logger,
+            "Recovered own leadership of {:?}, starting a new election", promise
+        );
+        self.recovered_round = self.recovered_round.max(promise.n);
+        self.current_ballot.n = RECOVERY_ROUND;
+        self.leader = promise;
+        self.happy = false;
+        true
+    }
+
+    /// Gives up the leadership of this instance if it is the leader. It then reports itself as
+    /// unhappy and does not take over again until another server has been elected.
+    pub(crate) fn step_down(&mut self) {
+        if self.leader == self.current_ballot {
+            self.stepping_down = true;
+        }
+    }
+
+    /// Sets the standby promise of the round after the current leader's, if any. A ready standby takes over as soon as it loses its leader, while the other servers wait for
+    /// it as long as it is connected, and otherwise skip its round.
+    pub(crate) fn set_standby(&mut self, standby: Option<Ballot>) {
+        self.standby = standby.filter(|b| b.config_id == self.configuration_id);
+    }
+
     /// Returns outgoing messages
     pub(crate) fn get_outgoing_msgs(&mut self) -> Vec<BLEMessage> {
-        std::mem::take(&mut self.outgoing)
+        self.outgoing.take_all()
+    }
+
+    /// Returns the outgoing messages to `pid`.
+    pub(crate) fn get_outgoing_msgs_for(&mut self, pid: NodeId) -> Vec<BLEMessage> {
+        self.outgoing.take_for(pid)
+    }
+
+    /// Puts `msgs` back in front of the outgoing messages.
+    pub(crate) fn requeue_outgoing_msgs(&mut self, msgs: Vec<BLEMessage>) {
+        self.outgoing.requeue(msgs);
+    }
+
+    /// The number of queued outgoing messages.
+    pub(crate) fn num_outgoing_msgs(&self) -> usize {
+        self.outgoing.num_queued()
     }
 
     /// Handle an incoming message.
@@ -167,7 +300,7 @@ impl BallotLeaderElection {
     pub(crate) fn handle(&mut self, m: BLEMessage) {
         match m.msg {
             HeartbeatMsg::Request(req) => self.handle_request(m.from, req),
-            HeartbeatMsg::Reply(rep) => self.handle_reply(rep),
+            HeartbeatMsg::Reply(rep) => self.handle_reply(m.from, rep),
         }
     }
 
@@ -184,22 +317,37 @@ impl BallotLeaderElection {
         for peer in &self.peers {
             let hb_request = HeartbeatRequest {
                 round: self.hb_round,
+                fingerprint: self.fingerprint,
+                payload: self.heartbeat_payload.clone(),
+                compression: self.compression.clone(),
             };
             self.outgoing.push(BLEMessage {
                 from: self.pid,
                 to: *peer,
+                namespace: self.namespace,
                 msg: HeartbeatMsg::Request(hb_request),
             });
         }
     }
 
+    /// Discards the replies of the current heartbeat round and starts a new one, e.g., after a
+    /// clock jump, so that the next election timeout only acts on fresh heartbeats.
+    pub(crate) fn restart_hb_round(&mut self) {
+        self.heartbeat_replies.clear();
+        self.new_hb_round();
+    }
+
     /// End of a heartbeat round. Returns current leader and election status.
     pub(crate) fn hb_timeout(
         &mut self,
         seq_paxos_state: &(Role, Phase),
         seq_paxos_promise: Ballot,
     ) -> Option<Ballot> {
+        self.update_connectivity();
         self.update_leader();
+        if self.leader != self.current_ballot {
+            self.stepping_down = false;
+        }
         self.update_happiness(seq_paxos_state);
         self.check_takeover();
         self.new_hb_round();
@@ -208,15 +356,40 @@ impl BallotLeaderElection {
             self.leader = seq_paxos_promise;
             self.happy = true;
         }
-        if self.leader == self.current_ballot {
+        if self.leader == self.current_ballot && self.quorum_connected {
             Some(self.current_ballot)
         } else {
             None
         }
     }
 
+    fn update_connectivity(&mut self) {
+        self.connectivity = self.heartbeat_replies.len() + 1;
+        let connected = self
+            .heartbeat_replies
+            .iter()
+            .map(|hb_reply| hb_reply.ballot.pid)
+            .chain(iter::once(self.pid));
+        self.quorum_connected = self.quorum.is_prepare_quorum(connected);
+    }
+
+    /// Follows the largest ballot of the servers that are quorum-connected, so that a server that
+    /// only reaches a minority cannot draw the rest of the cluster away from a working leader. A
+    /// leader that reports that it lost its quorum is not followed anymore.
     fn update_leader(&mut self) {
-        let max_reply_ballot = self.heartbeat_replies.iter().map(|r| r.ballot).max();
+        let leader_lost_quorum = self
+            .heartbeat_replies
+            .iter()
+            .any(|r| r.ballot == self.leader && !r.quorum_connected);
+        if leader_lost_quorum {
+            self.leader = self.current_ballot;
+        }
+        let max_reply_ballot = self
+            .heartbeat_replies
+            .iter()
+            .filter(|r| r.quorum_connected)
+            .map(|r| r.ballot)
+            .max();
         if let Some(max) = max_reply_ballot {
             if max > self.leader {
                 self.leader = max;
@@ -225,17 +398,18 @@ impl BallotLeaderElection {
     }
 
     fn update_happiness(&mut self, seq_paxos_state: &(Role, Phase)) {
-        self.happy = if self.leader == self.current_ballot {
-            let potential_followers = self
+        self.happy = if self.stepping_down {
+            false
+        } else if self.leader == self.current_ballot {
+            let potential_quorum = self
                 .heartbeat_replies
                 .iter()
                 .filter(|hb_reply| hb_reply.leader <= self.current_ballot)
-                .count();
+                .map(|hb_reply| hb_reply.ballot.pid)
+                .chain(iter::once(self.pid));
             let can_form_quorum = match seq_paxos_state {
-                (Role::Leader, Phase::Accept) => {
-                    self.quorum.is_accept_quorum(potential_followers + 1)
-                }
-                _ => self.quorum.is_prepare_quorum(potential_followers + 1),
+                (Role::Leader, Phase::Accept) => self.quorum.is_accept_quorum(potential_quorum),
+                _ => self.quorum.is_prepare_quorum(potential_quorum),
             };
             if can_form_quorum {
                 true
@@ -254,15 +428,27 @@ impl BallotLeaderElection {
     }
 
     fn check_takeover(&mut self) {
-        if !self.happy {
+        if !self.happy && !self.stepping_down {
             let all_neighbors_unhappy = self.heartbeat_replies.iter().all(|r| !r.happy);
-            let im_quorum_connected = self
-                .quorum
-                .is_prepare_quorum(self.heartbeat_replies.len() + 1);
-            if all_neighbors_unhappy && im_quorum_connected {
+            let may_take_over = match self.standby {
+                Some(standby) if standby.pid == self.pid => true,
+                Some(standby) => {
+                    let standby_connected = self
+                        .heartbeat_replies
+                        .iter()
+                        .any(|r| r.ballot.pid == standby.pid && r.quorum_connected);
+                    all_neighbors_unhappy && !standby_connected
+                }
+                None => all_neighbors_unhappy,
+            };
+            if may_take_over && self.quorum_connected {
                 // We increment past our leader instead of max of unhappy ballots because we
                 // assume we have already checked leader for this round so they should be equal
-                self.current_ballot.n = self.leader.n + 1;
+                self.current_ballot.n = self.leader.n.max(self.recovered_round) + 1;
+                // The round of the standby is refused to any other server
+                if let Some(standby) = self.standby.filter(|s| s.pid != self.pid) {
+                    self.current_ballot.n = self.current_ballot.n.max(standby.n + 1);
+                }
                 self.leader = self.current_ballot;
                 self.happy = true;
             }
@@ -270,25 +456,112 @@ impl BallotLeaderElection {
     }
 
     fn handle_request(&mut self, from: NodeId, req: HeartbeatRequest) {
+        if !self.check_fingerprint(from, req.fingerprint) {
+            return;
+        }
+        self.set_peer_payload(from, req.payload);
+        self.set_peer_compression(from, req.compression);
         let hb_reply = HeartbeatReply {
             round: req.round,
             ballot: self.current_ballot,
             leader: self.leader,
             happy: self.happy,
+            connectivity: self.connectivity,
+            quorum_connected: self.quorum_connected,
+            payload: self.heartbeat_payload.clone(),
+            compression: self.compression.clone(),
         };
         self.outgoing.push(BLEMessage {
             from: self.pid,
             to: from,
+            namespace: self.namespace,
             msg: HeartbeatMsg::Reply(hb_reply),
         });
     }
 
-    fn handle_reply(&mut self, rep: HeartbeatReply) {
+    fn handle_reply(&mut self, from: NodeId, rep: HeartbeatReply) {
         if rep.round == self.hb_round && rep.ballot.config_id == self.configuration_id {
+            self.set_peer_payload(from, rep.payload.clone());
+            self.set_peer_compression(from, rep.compression.clone());
             self.heartbeat_replies.push(rep);
         }
     }
 
+    fn set_peer_payload(&mut self, pid: NodeId, payload: Option<Vec<u8>>) {
+        match payload {
+            Some(payload) => self.peer_payloads.insert(pid, payload),
+            None => self.peer_payloads.remove(&pid),
+        };
+    }
+
+    /// Sets the payload that is attached to the heartbeats of this instance from the next one on.
+    pub(crate) fn set_heartbeat_payload(&mut self, payload: Option<Vec<u8>>) {
+        self.heartbeat_payload = payload;
+    }
+
+    pub(crate) fn get_peer_payload(&self, pid: NodeId) -> Option<&[u8]> {
+        self.peer_payloads.get(&pid).map(Vec::as_slice)
+    }
+
+    fn set_peer_compression(&mut self, pid: NodeId, compression: Option<String>) {
+        match compression {
+            Some(compression) => self.peer_compressions.insert(pid, compression),
+            None => self.peer_compressions.remove(&pid),
+        };
+    }
+
+    /// Forgets the compression advertised by `pid`, as the server may have been replaced by one
+    /// that does not support it while it was disconnected. It is negotiated again with the next
+    /// heartbeat.
+    pub(crate) fn reconnected(&mut self, pid: NodeId) {
+        self.peer_compressions.remove(&pid);
+    }
+
+    /// Returns whether `pid` advertised the same compression as this server.
+    pub(crate) fn peer_accepts_compression(&self, pid: NodeId) -> bool {
+        match (&self.compression, self.peer_compressions.get(&pid)) {
+            (Some(own), Some(peer)) => own == peer,
+            _ => false,
+        }
+    }
+
+    /// Returns whether `fingerprint` of server `from` matches the configuration of this instance,
+    /// and reports a mismatch the first time it is seen.
+    fn check_fingerprint(&mut self, from: NodeId, fingerprint: ConfigFingerprint) -> bool {
+        if fingerprint == self.fingerprint {
+            self.mismatched_peers.remove(&from);
+            return true;
+        }
+        if self.mismatched_peers.insert(from, fingerprint) != Some(fingerprint) {
+            #[cfg(feature = "logging")]
+            warn!(
+                self.logger,
+                "Server {} has a different configuration: {:?}, ours: {:?}",
+                from,
+                fingerprint,
+                self.fingerprint
+            );
+            if self.config_mismatches.len() == CONFIG_MISMATCH_EVENTS {
+                self.config_mismatches.pop_front();
+            }
+            self.config_mismatches.push_back(ConfigMismatch {
+                pid: from,
+                local: self.fingerprint,
+                remote: fingerprint,
+            });
+        }
+        false
+    }
+
+    /// Whether the last heartbeat request of `pid` had a different configuration.
+    pub(crate) fn is_mismatched(&self, pid: NodeId) -> bool {
+        self.mismatched_peers.contains_key(&pid)
+    }
+
+    pub(crate) fn take_config_mismatches(&mut self) -> Vec<ConfigMismatch> {
+        self.config_mismatches.drain(..).collect()
+    }
+
     pub(crate) fn get_current_ballot(&self) -> Ballot {
         self.current_ballot
     }
@@ -296,6 +569,13 @@ impl BallotLeaderElection {
     pub(crate) fn get_ballots(&self) -> Vec<HeartbeatReply> {
         self.prev_replies.clone()
     }
+
+    /// Whether `pid` replied to the heartbeats of the previous round.
+    pub(crate) fn is_connected(&self, pid: NodeId) -> bool {
+        self.prev_replies
+            .iter()
+            .any(|reply| reply.ballot.pid == pid)
+    }
 }
 
 /// Configuration for `BallotLeaderElection`.
@@ -305,7 +585,12 @@ impl BallotLeaderElection {
 /// * `peers`: The peers of this node i.e. the `pid`s of the other servers in the configuration.
 /// * `priority`: Set custom priority for this node to be elected as the leader.
 /// * `flexible_quorum` : Defines read and write quorum sizes. Can be used for different latency vs fault tolerance tradeoffs.
+/// * `hierarchical_quorum`: Whether quorums are formed by a majority of nodes in a majority of `regions`.
+/// * `regions`: The regions the nodes are located in.
 /// * `buffer_size`: The buffer size for outgoing messages.
+/// * `fingerprint`: The configuration of this node that is sent to its peers.
+/// * `namespace`: The namespace of the cluster that is sent with every message.
+/// * `compression`: The compression the transport of this node can decompress.
 /// * `logger_file_path`: The path where the default logger logs events.
 #[derive(Clone, Debug)]
 pub(crate) struct BLEConfig {
@@ -314,7 +599,12 @@ pub(crate) struct BLEConfig {
     peers: Vec<NodeId>,
     priority: u32,
     flexible_quorum: Option<FlexibleQuorum>,
+    hierarchical_quorum: bool,
+    regions: Vec<Region>,
     buffer_size: usize,
+    fingerprint: ConfigFingerprint,
+    namespace: Option<Namespace>,
+    compression: Option<String>,
     #[cfg(feature = "logging")]
     logger_file_path: Option<String>,
     #[cfg(feature = "logging")]
@@ -324,6 +614,7 @@ pub(crate) struct BLEConfig {
 impl From<OmniPaxosConfig> for BLEConfig {
     fn from(config: OmniPaxosConfig) -> Self {
         let pid = config.server_config.pid;
+        let fingerprint = ConfigFingerprint::of(&config.cluster_config);
         let peers = config
             .cluster_config
             .nodes
@@ -337,7 +628,12 @@ impl From<OmniPaxosConfig> for BLEConfig {
             peers,
             priority: config.server_config.leader_priority,
             flexible_quorum: config.cluster_config.flexible_quorum,
+            hierarchical_quorum: config.cluster_config.hierarchical_quorum,
+            regions: config.cluster_config.regions,
             buffer_size: BLE_BUFFER_SIZE,
+            fingerprint,
+            namespace: config.cluster_config.namespace,
+            compression: config.server_config.compression,
             #[cfg(feature = "logging")]
             logger_file_path: config.server_config.logger_file_path,
             #[cfg(feature = "logging")]
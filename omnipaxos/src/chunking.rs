@@ -0,0 +1,107 @@
+use crate::{
+    storage::Entry,
+    util::{LogEntry, NodeId},
+};
+#[cfg(feature = "serde")]
+use serde::{Deserialize, Serialize};
+
+/// Identifies a chunk of an entry that was split with [`crate::OmniPaxos::append_chunked`].
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct ChunkInfo {
+    /// The server that split the entry.
+    pub pid: NodeId,
+    /// Identifies the split entry among the entries split by `pid`.
+    pub group: u64,
+    /// The position of this chunk in the split entry.
+    pub index: u32,
+    /// The total number of chunks of the split entry.
+    pub count: u32,
+}
+
+/// An entry type that can be split into several smaller entries and reassembled again. This makes it
+/// possible to replicate entries larger than what the transport can handle in a single message.
+/// The chunks of an entry are proposed together so they are always decided contiguously in the log.
+pub trait Chunkable: Entry {
+    /// Serializes the entry into the bytes that are split into chunks.
+    fn to_bytes(&self) -> Vec<u8>;
+
+    /// Deserializes an entry from the bytes of all its chunks concatenated.
+    fn from_bytes(bytes: Vec<u8>) -> Self;
+
+    /// Creates a chunk entry that holds the `data` of the chunk described by `info`.
+    fn chunk(info: ChunkInfo, data: Vec<u8>) -> Self;
+
+    /// Returns the chunk description and data if this entry is a chunk, otherwise `None`.
+    fn as_chunk(&self) -> Option<(ChunkInfo, &[u8])>;
+}
+
+/// Splits the serialized entry `bytes` into chunk entries of at most `max_chunk_size` bytes each.
+pub(crate) fn split<T: Chunkable>(
+    bytes: &[u8],
+    max_chunk_size: usize,
+    pid: NodeId,
+    group: u64,
+) -> Vec<T> {
+    let count = bytes.len().div_ceil(max_chunk_size) as u32;
+    bytes
+        .chunks(max_chunk_size)
+        .enumerate()
+        .map(|(index, data)| {
+            let info = ChunkInfo {
+                pid,
+                group,
+                index: index as u32,
+                count,
+            };
+            T::chunk(info, data.to_vec())
+        })
+        .collect()
+}
+
+/// Reassembles the chunk entries in `entries` into the entries that were split. Chunks that can
+/// never be completed (e.g., because the earlier chunks have been compacted) are dropped. Returns
+/// the reassembled entries and the number of trailing chunk entries that were withheld because the
+/// rest of their chunks are not decided yet.
+pub(crate) fn reassemble<T: Chunkable>(entries: Vec<LogEntry<T>>) -> (Vec<LogEntry<T>>, usize) {
+    let mut reassembled = Vec::with_capacity(entries.len());
+    let mut pending: Option<(ChunkInfo, Vec<u8>)> = None;
+    for entry in entries {
+        let (info, data) = match &entry {
+            LogEntry::Decided(e) => match e.as_chunk() {
+                Some(chunk) => chunk,
+                None => {
+                    pending = None;
+                    reassembled.push(entry);
+                    continue;
+                }
+            },
+            _ => {
+                pending = None;
+                reassembled.push(entry);
+                continue;
+            }
+        };
+        match pending.as_mut() {
+            _ if info.index == 0 => pending = Some((info, data.to_vec())),
+            Some((p, buf))
+                if p.pid == info.pid && p.group == info.group && p.index + 1 == info.index =>
+            {
+                p.index = info.index;
+                buf.extend_from_slice(data);
+            }
+            _ => {
+                pending = None;
+                continue;
+            }
+        }
+        if let Some((p, _)) = &pending {
+            if p.index + 1 == p.count {
+                let (_, buf) = pending.take().unwrap();
+                reassembled.push(LogEntry::Decided(T::from_bytes(buf)));
+            }
+        }
+    }
+    let withheld = pending.map_or(0, |(p, _)| p.index as usize + 1);
+    (reassembled, withheld)
+}
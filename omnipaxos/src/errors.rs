@@ -1,6 +1,12 @@
-#[cfg(feature = "toml_config")]
-use std::io;
-use std::{error, fmt};
+use crate::{
+    ballot_leader_election::Ballot,
+    settings::ReplicatedSettings,
+    storage::Entry,
+    util::{ConfigurationId, NodeId},
+    ClusterConfig, ReadConsistency, ReadToken,
+};
+use std::{error, fmt, io};
+use thiserror::Error;
 #[cfg(feature = "toml_config")]
 use toml;
 
@@ -55,6 +61,593 @@ impl From<toml::de::Error> for ConfigError {
     }
 }
 
+/// An error indicating why an [`OmniPaxos`](crate::OmniPaxos) server could not be built.
+#[derive(Debug, Error)]
+#[non_exhaustive]
+pub enum BuildErr {
+    /// The configuration is invalid.
+    #[error(transparent)]
+    Config(#[from] ConfigError),
+    /// The state of the server could not be recovered from its storage.
+    #[error("could not recover the state of the server: {0}")]
+    Storage(FatalErr),
+}
+
+impl From<FatalErr> for BuildErr {
+    fn from(err: FatalErr) -> BuildErr {
+        BuildErr::Storage(err)
+    }
+}
+
+impl From<ProtocolError> for BuildErr {
+    fn from(err: ProtocolError) -> BuildErr {
+        BuildErr::Storage(err.fatal_error().clone())
+    }
+}
+
+/// An error indicating why a proposal of a log entry or of new settings failed. Returns the
+/// failed proposal.
+#[derive(Debug, Error)]
+#[non_exhaustive]
+pub enum ProposeErr<T>
+where
+    T: Entry,
+{
+    /// Couldn't propose entry because a reconfiguration is pending. Returns the failed, proposed entry.
+    #[error("the configuration is stopped by a pending reconfiguration")]
+    PendingReconfigEntry(T),
+    /// Couldn't propose reconfiguration because a reconfiguration is already pending. Returns the
+    /// failed, proposed cluster config and metadata.
+    #[deprecated(note = "`reconfigure` returns `ReconfigErr::Pending` instead")]
+    #[error("a reconfiguration is already pending")]
+    PendingReconfigConfig(ClusterConfig, Option<Vec<u8>>),
+    /// Couldn't propose reconfiguration because of an invalid cluster config. Contains the config
+    /// error and the failed, proposed cluster config and metadata.
+    #[deprecated(note = "`reconfigure` returns `ReconfigErr::InvalidConfig` instead")]
+    #[error("invalid cluster config: {0}")]
+    ConfigError(ConfigError, ClusterConfig, Option<Vec<u8>>),
+    /// Couldn't propose reconfiguration because it was refused by the `reconfiguration_validator`.
+    /// Contains the reason and the failed, proposed cluster config and metadata.
+    #[deprecated(note = "`reconfigure` returns `ReconfigErr::Rejected` instead")]
+    #[error("the reconfiguration was rejected: {0}")]
+    ReconfigurationRejected(String, ClusterConfig, Option<Vec<u8>>),
+    /// Couldn't propose entry because the storage exceeds the `storage_size_limit`, or its log
+    /// fills the capacity of the storage. Returns the failed, proposed entry.
+    #[error("the storage is full")]
+    Full(T),
+    /// Couldn't propose entry because this server is not the leader and its
+    /// `follower_append_policy` is `Reject`. Returns the failed, proposed entry and the leader, if
+    /// one is known.
+    #[error("this server is not the leader")]
+    NotLeader(T, Option<NodeId>),
+    /// Couldn't propose entry because this server has not caught up with the log observed with
+    /// the [`ReadToken`] of the client. Returns the failed, proposed entry and the token of this
+    /// server.
+    #[error("this server is behind the read token")]
+    BehindToken(T, ReadToken),
+    /// Couldn't propose entry because it is larger than the `max_entry_size` of this server.
+    /// Returns the failed, proposed entry, its size as given by [`Entry::size_hint`], and the
+    /// limit.
+    #[error("the entry of {size} bytes exceeds the limit of {limit} bytes")]
+    EntryTooLarge {
+        /// The failed, proposed entry.
+        entry: T,
+        /// The size of the entry.
+        size: usize,
+        /// The `max_entry_size` of this server.
+        limit: usize,
+    },
+    /// Couldn't propose settings change because of invalid settings. Contains the config error
+    /// and the failed, proposed settings.
+    #[error("invalid settings: {0}")]
+    InvalidSettings(ConfigError, ReplicatedSettings),
+    /// Couldn't propose entry because the application halted this server after a [`FatalErr`],
+    /// see [`crate::OmniPaxos::halt`]. Returns the failed, proposed entry.
+    #[error("this server has halted")]
+    Halted(T),
+    /// The storage failed while proposing, after the proposal was taken over. The entry or
+    /// reconfiguration might still be replicated if the leader appended it before the failure.
+    #[error(transparent)]
+    Failed(#[from] ProtocolError),
+    /// Couldn't propose entry because its deadline has already passed. Returns the failed,
+    /// proposed entry.
+    #[error("the deadline of the entry has passed")]
+    DeadlineExceeded(T),
+    /// Couldn't propose entry because the buffers of this server exceed its `memory_limit`.
+    /// Returns the failed, proposed entry.
+    #[error("the memory limit is exceeded")]
+    MemoryLimitExceeded(T),
+    /// Couldn't propose entry because the `max_chunk_size` it should be split into is 0. Returns
+    /// the failed, proposed entry.
+    #[error("the chunk size must not be 0")]
+    InvalidChunkSize(T),
+}
+
+#[allow(deprecated)]
+impl<T: Entry> From<ReconfigErr> for ProposeErr<T> {
+    fn from(err: ReconfigErr) -> Self {
+        match err {
+            ReconfigErr::Pending {
+                config, metadata, ..
+            } => ProposeErr::PendingReconfigConfig(*config, metadata),
+            ReconfigErr::InvalidConfig {
+                error,
+                config,
+                metadata,
+            } => ProposeErr::ConfigError(error, *config, metadata),
+            ReconfigErr::Rejected {
+                reason,
+                config,
+                metadata,
+            } => ProposeErr::ReconfigurationRejected(reason, *config, metadata),
+            ReconfigErr::Failed(err) => ProposeErr::Failed(err),
+        }
+    }
+}
+
+/// An error indicating why a reconfiguration could not be proposed. Returns the failed, proposed
+/// cluster config and metadata.
+#[derive(Debug, Error)]
+#[non_exhaustive]
+pub enum ReconfigErr {
+    /// A different reconfiguration to the configuration `pending` is already pending.
+    #[error("a reconfiguration to configuration {pending} is already pending")]
+    Pending {
+        /// The id of the configuration of the pending reconfiguration.
+        pending: ConfigurationId,
+        /// The proposed cluster config.
+        config: Box<ClusterConfig>,
+        /// The proposed metadata.
+        metadata: Option<Vec<u8>>,
+    },
+    /// The proposed cluster config is invalid.
+    #[error("invalid cluster config: {error}")]
+    InvalidConfig {
+        /// Why the cluster config is invalid.
+        error: ConfigError,
+        /// The proposed cluster config.
+        config: Box<ClusterConfig>,
+        /// The proposed metadata.
+        metadata: Option<Vec<u8>>,
+    },
+    /// The reconfiguration was refused by the `reconfiguration_validator`.
+    #[error("the reconfiguration was rejected: {reason}")]
+    Rejected {
+        /// The reason returned by the validator.
+        reason: String,
+        /// The proposed cluster config.
+        config: Box<ClusterConfig>,
+        /// The proposed metadata.
+        metadata: Option<Vec<u8>>,
+    },
+    /// The storage failed while proposing the reconfiguration, which might still be replicated
+    /// if the leader appended it before the failure.
+    #[error(transparent)]
+    Failed(#[from] ProtocolError),
+}
+
+/// An error indicating why the log could not be trimmed or snapshotted.
+#[derive(Copy, Clone, Debug, Error)]
+#[non_exhaustive]
+pub enum CompactionErr {
+    /// Snapshot was called with an index that is not decided yet. Returns the currently decided index.
+    #[error("the index is not decided yet, the decided index is {0}")]
+    UndecidedIndex(usize),
+    /// Snapshot was called with an index which is already trimmed. Returns the currently compacted index.
+    #[error("the index is already compacted, the compacted index is {0}")]
+    TrimmedIndex(usize),
+    /// Trim was called with an index that is not decided by all servers yet. Returns the index decided by ALL servers currently.
+    #[error("the index is not decided by all servers, they have decided up to {0}")]
+    NotAllDecided(usize),
+    /// Trim was called at a follower node. Trim must be called by the leader, which is the returned NodeId.
+    #[error("only the leader can trim, the current leader is {0}")]
+    NotCurrentLeader(NodeId),
+    /// Snapshot was called but the entries don't support snapshots, or the `no_snapshots` feature
+    /// is enabled.
+    #[error("the entries don't support snapshots")]
+    SnapshotsUnsupported,
+    /// The log is pinned by a [`PinGuard`](crate::storage::PinGuard) at the returned index, which
+    /// the compaction would remove.
+    #[error("the log is pinned at index {0}")]
+    Pinned(usize),
+    /// The storage failed while trying to compact the log. Returns the operation that failed, e.g.,
+    /// "trim the log". The error of the storage is logged with the `logging` feature.
+    #[error("the storage failed while trying to {0}")]
+    Storage(&'static str),
+}
+
+/// An error indicating why a log entry could not be read.
+#[derive(Debug, Error)]
+#[non_exhaustive]
+pub enum ReadErr {
+    /// The ballot given to [`OmniPaxos::read_fenced`](crate::OmniPaxos::read_fenced) is no longer
+    /// promised.
+    #[error("the ballot is no longer promised, the current promise is {current:?}")]
+    StaleBallot {
+        /// The ballot currently promised by this server.
+        current: Ballot,
+    },
+    /// This server can't serve reads with the requested consistency yet.
+    #[error("{0:?} reads are not supported")]
+    UnsupportedConsistency(ReadConsistency),
+    /// This server has not caught up with the log observed with the [`ReadToken`] given to
+    /// [`OmniPaxos::read_monotonic`](crate::OmniPaxos::read_monotonic), e.g., because the client
+    /// was switched to a server that is behind.
+    #[error("this server is behind the read token: {token:?}, its own token is {current:?}")]
+    BehindToken {
+        /// The token given to the read.
+        token: ReadToken,
+        /// The token of this server.
+        current: ReadToken,
+    },
+    /// The storage failed while reading the log.
+    #[error(transparent)]
+    Storage(#[from] StorageErr),
+}
+
+/// An error indicating why a snapshot could not be sealed or opened, see
+/// [`crate::sealing::SnapshotSealer`].
+#[cfg(feature = "snapshot_sealing")]
+#[derive(Debug, Error)]
+#[non_exhaustive]
+pub enum SealingErr {
+    /// The snapshot could not be serialized or deserialized.
+    #[error("failed to encode or decode the snapshot: {0}")]
+    Codec(#[from] Box<bincode::ErrorKind>),
+    /// The sealer failed to encrypt, decrypt, or sign the snapshot.
+    #[error("failed to {op} the snapshot: {source}")]
+    Sealer {
+        /// What the sealer failed to do, i.e., "encrypt", "decrypt", or "sign".
+        op: &'static str,
+        /// The error returned by the sealer.
+        source: Box<dyn error::Error>,
+    },
+    /// The signature of the snapshot is invalid, e.g., because it was tampered with or signed
+    /// with an unknown key.
+    #[error("invalid snapshot signature: {0}")]
+    InvalidSignature(Box<dyn error::Error>),
+    /// The snapshot was sealed for another position in the log than the one it was sent for.
+    #[error("the snapshot was sealed for index {sealed}, not {expected}")]
+    IndexMismatch {
+        /// The compacted index that the snapshot was sealed with.
+        sealed: usize,
+        /// The compacted index that the snapshot was sent for.
+        expected: usize,
+    },
+    /// The snapshot is not sealed although this server requires sealed snapshots.
+    #[error("the snapshot is not sealed")]
+    NotSealed,
+    /// The snapshot is sealed but this server has no sealer to open it.
+    #[error("no snapshot sealer is set to open the sealed snapshot")]
+    NoSealer,
+}
+
+/// An error indicating why a frame could not be written or read, see
+/// [`crate::framing::FrameCodec`].
+#[cfg(feature = "framing")]
+#[derive(Debug, Error)]
+#[non_exhaustive]
+pub enum FramingErr {
+    /// Reading or writing the frame failed.
+    #[error("frame I/O failed: {0}")]
+    Io(#[from] io::Error),
+    /// The message could not be serialized or deserialized.
+    #[error("failed to encode or decode the message: {0}")]
+    Codec(#[from] Box<bincode::ErrorKind>),
+    /// The payload of the frame is larger than the `max_frame_size`. A received length this large
+    /// usually means that the stream is corrupt or out of step.
+    #[error("frame of {size} bytes exceeds the maximum of {max} bytes")]
+    TooLarge {
+        /// The size of the payload.
+        size: usize,
+        /// The configured `max_frame_size`.
+        max: usize,
+    },
+    /// The checksum of the received frame does not match its content, i.e., it was corrupted.
+    #[error("frame checksum {actual:#010x} does not match {expected:#010x}")]
+    ChecksumMismatch {
+        /// The checksum sent with the frame.
+        expected: u32,
+        /// The checksum of the received content.
+        actual: u32,
+    },
+    /// The frame has flags set that this version does not know.
+    #[error("unknown frame flags {0:#04x}")]
+    UnknownFlags(u8),
+    /// The frame is compressed but the codec has no compressor to decompress it.
+    #[error("no compressor is set to decompress the frame")]
+    NoCompressor,
+    /// The compressor failed to compress or decompress the payload.
+    #[error("failed to {op} the frame: {source}")]
+    Compressor {
+        /// What the compressor failed to do, i.e., "compress" or "decompress".
+        op: &'static str,
+        /// The error returned by the compressor.
+        source: Box<dyn error::Error>,
+    },
+}
+
+/// An error indicating why an archive or a journal could not be written, read, restored, or
+/// replayed.
+#[derive(Debug, Error)]
+#[non_exhaustive]
+pub enum ArchiveErr {
+    /// Reading or writing the archive failed.
+    #[error("archive I/O failed: {0}")]
+    Io(#[from] io::Error),
+    /// The data does not start with the magic bytes of an archive.
+    #[error("not an OmniPaxos archive")]
+    InvalidMagic,
+    /// The archive was written with a version of the format that is not supported.
+    #[error("unsupported archive format version {0}")]
+    UnsupportedVersion(u16),
+    /// The archive is truncated or malformed.
+    #[error("corrupt archive: {0}")]
+    Corrupt(String),
+    /// The codec failed to encode or decode an entry or the snapshot.
+    #[error("failed to encode or decode the {what}: {source}")]
+    Codec {
+        /// What was encoded or decoded, i.e., "entry" or "snapshot".
+        what: &'static str,
+        /// The error returned by the codec.
+        source: Box<dyn error::Error>,
+    },
+    /// The storage failed while the archive was written or restored.
+    #[error(transparent)]
+    Storage(#[from] StorageErr),
+    /// The call with the given sequence number of a journal passes entries or a snapshot that were
+    /// only journaled as hashes, so the journal can't be replayed from there on.
+    #[error("call {0} of the journal only contains the hashes of its entries")]
+    HashedPayload(u64),
+}
+
+/// An error indicating why the state of a Raft server could not be imported.
+#[derive(Debug, Error)]
+#[non_exhaustive]
+pub enum ImportErr {
+    /// The Raft log is not consistent, e.g., it has gaps.
+    #[error("invalid Raft log: {0}")]
+    InvalidLog(String),
+    /// The mapping failed to convert the Raft entry or snapshot at `index`.
+    #[error("failed to map the Raft entry at index {index}: {source}")]
+    Mapping {
+        /// The Raft index of the entry, or the last index included in the snapshot.
+        index: u64,
+        /// The error returned by the mapping.
+        source: Box<dyn error::Error>,
+    },
+    /// The storage failed while the state was imported.
+    #[error(transparent)]
+    Storage(#[from] StorageErr),
+}
+
+/// An error indicating why the decided log could not be replayed into a state machine.
+#[derive(Debug, Error)]
+#[non_exhaustive]
+pub enum ReplayErr {
+    /// The entries before the compacted index were trimmed without a snapshot. Returns the
+    /// compacted index.
+    #[error("the log is trimmed without a snapshot up to index {0}")]
+    Trimmed(usize),
+    /// The state machine failed to apply the entry at `idx`.
+    #[error("failed to apply the entry at index {idx}: {source}")]
+    Apply {
+        /// The index of the entry, or the compacted index if the snapshot failed to apply.
+        idx: usize,
+        /// The error returned by the state machine.
+        source: Box<dyn error::Error>,
+    },
+    /// The storage failed while the log was read.
+    #[error(transparent)]
+    Storage(#[from] StorageErr),
+}
+
+/// An error indicating why a server could not switch to another storage.
+#[derive(Debug, Error)]
+#[non_exhaustive]
+pub enum StorageMigrationErr {
+    /// The copied state differs from the state of the current storage.
+    #[error("the copied storage differs: {0}")]
+    Mismatch(String),
+    /// The new storage has not persisted all copied entries yet.
+    #[error("only the entries before index {durable_idx} of {log_len} are durable")]
+    NotDurable {
+        /// The durable index of the new storage.
+        durable_idx: usize,
+        /// The length of the copied log, including the compacted entries.
+        log_len: usize,
+    },
+    /// A storage failed while the state was copied or compared.
+    #[error(transparent)]
+    Storage(#[from] StorageErr),
+    /// The batched entries could not be flushed to the current storage before switching.
+    #[error(transparent)]
+    Failed(#[from] ProtocolError),
+}
+
+/// An error indicating why the application state could not be handed off to the next
+/// configuration.
+#[derive(Debug, Error)]
+#[non_exhaustive]
+pub enum HandoffErr {
+    /// The configuration has not been stopped by a decided StopSign yet.
+    #[error("the configuration is not stopped yet")]
+    NotReconfigured,
+    /// The decided StopSign did not request a handoff.
+    #[error("the reconfiguration did not request a handoff")]
+    NotRequested,
+    /// The entries don't support snapshots.
+    #[error("the entries don't support snapshots")]
+    SnapshotsUnsupported,
+    /// A chunk of the snapshot for a different configuration was received.
+    #[error("expected a chunk for configuration {expected}, found one for {found}")]
+    WrongConfiguration {
+        /// The configuration of the manifest.
+        expected: ConfigurationId,
+        /// The configuration of the chunk.
+        found: ConfigurationId,
+    },
+    /// The chunk with this index does not match the manifest.
+    #[error("invalid chunk {0}")]
+    InvalidChunk(u32),
+    /// The number of chunks that have not been received yet.
+    #[error("{0} chunks are missing")]
+    Incomplete(usize),
+    /// The received snapshot does not match the digest of the manifest.
+    #[error("the snapshot does not match the digest of the manifest")]
+    DigestMismatch,
+    /// The codec failed to encode or decode the snapshot.
+    #[error("failed to encode or decode the snapshot: {0}")]
+    Codec(Box<dyn error::Error>),
+    /// The storage failed while the snapshot was created.
+    #[error(transparent)]
+    Storage(#[from] StorageErr),
+}
+
+/// An error returned by the storage, together with the operation that failed.
+#[derive(Debug, Error)]
+#[error("storage error while trying to {op}: {source}")]
+pub struct StorageErr {
+    /// The operation that failed, e.g., "trim the log".
+    pub op: &'static str,
+    /// The error returned by the storage.
+    pub source: Box<dyn error::Error>,
+}
+
+impl StorageErr {
+    pub(crate) fn with(op: &'static str, source: Box<dyn error::Error>) -> Self {
+        Self { op, source }
+    }
+}
+
+/// An error indicating why a [`ConfigurationManager`](crate::reconfiguration::ConfigurationManager)
+/// could not start the instance of the next configuration.
+#[derive(Debug, Error)]
+#[non_exhaustive]
+pub enum TransitionErr {
+    /// The decided StopSign of the current configuration has not been delivered yet, i.e., the
+    /// entries of the current configuration are not all delivered.
+    #[error("the StopSign of the current configuration has not been delivered yet")]
+    NotStopped,
+    /// The instance of the next configuration could not be built.
+    #[error("invalid next configuration: {0}")]
+    InvalidConfig(BuildErr),
+}
+
+/// An error indicating why a hot-standby leader could not be designated.
+#[derive(Debug, Error)]
+#[non_exhaustive]
+pub enum StandbyErr {
+    /// This server is not the leader in the accept phase.
+    #[error("this server is not the leader")]
+    NotLeader,
+    /// The server is not one of the other servers of the configuration.
+    #[error("server {0} is not a peer of this server")]
+    NotPeer(NodeId),
+    /// A standby was already designated in the current leadership.
+    #[error("server {0} is already the standby of this leadership")]
+    AlreadyDesignated(NodeId),
+    /// The configuration is stopped by a pending reconfiguration.
+    #[error("the configuration is stopped by a pending reconfiguration")]
+    Reconfigured,
+    /// The storage failed to persist the standby promise of this server, e.g., because it does
+    /// not implement [`StateStorage::set_standby_promise`](crate::storage::StateStorage::set_standby_promise).
+    #[error(transparent)]
+    Storage(#[from] StorageErr),
+}
+
+/// An error of the storage or a violated invariant that failed a handler of
+/// [`crate::OmniPaxos`]. The application may halt the server after it with
+/// [`crate::OmniPaxos::halt`], after which the server neither accesses its storage nor sends
+/// messages anymore and must be restarted from its storage.
+#[derive(Clone, Debug, Error, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum FatalErr {
+    /// A read or write of the storage failed.
+    #[error("{msg}: {error}")]
+    Storage {
+        /// What failed, e.g., [`crate::util::WRITE_ERROR_MSG`].
+        msg: &'static str,
+        /// The error returned by the storage.
+        error: String,
+    },
+    /// An internal invariant was violated, which indicates a bug in OmniPaxos.
+    #[error("{0}")]
+    Internal(&'static str),
+}
+
+/// An error returned by the handlers of [`crate::OmniPaxos`], e.g.,
+/// [`crate::OmniPaxos::handle_incoming`] and [`crate::OmniPaxos::tick`]. The server keeps running
+/// after a failed call, so the application can decide whether to retry, e.g., after a transient
+/// storage failure, to halt the server with [`crate::OmniPaxos::halt`] and restart it from its
+/// storage, or to shut down. With the `no_panic` feature, the server halts itself after a
+/// [`ProtocolError::Failed`] instead.
+#[derive(Clone, Debug, Error, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum ProtocolError {
+    /// The call failed with this error.
+    #[error(transparent)]
+    Failed(#[from] FatalErr),
+    /// The call was ignored because the application halted the server after this error.
+    #[error("this server has halted after: {0}")]
+    Halted(FatalErr),
+}
+
+impl ProtocolError {
+    /// Returns the error that failed the call or after which the server was halted.
+    pub fn fatal_error(&self) -> &FatalErr {
+        match self {
+            ProtocolError::Failed(err) | ProtocolError::Halted(err) => err,
+        }
+    }
+
+    /// Returns whether the storage failed, in which case the call can be retried or the server
+    /// restarted from the storage once the storage is healthy again. Otherwise, an internal
+    /// invariant was violated, which indicates a bug in OmniPaxos.
+    pub fn is_storage_failure(&self) -> bool {
+        matches!(self.fatal_error(), FatalErr::Storage { .. })
+    }
+}
+
+/// An error that may hold the [`FatalErr`] of a failed call, after which a server compiled with
+/// the `no_panic` feature halts.
+#[cfg(feature = "no_panic")]
+pub(crate) trait Failure {
+    /// Returns the error that failed the call, or `None` if it didn't fail with a [`FatalErr`] or
+    /// the server had already halted.
+    fn failure(&self) -> Option<&FatalErr>;
+}
+
+#[cfg(feature = "no_panic")]
+impl Failure for ProtocolError {
+    fn failure(&self) -> Option<&FatalErr> {
+        match self {
+            ProtocolError::Failed(err) => Some(err),
+            ProtocolError::Halted(_) => None,
+        }
+    }
+}
+
+#[cfg(feature = "no_panic")]
+impl<T: Entry> Failure for ProposeErr<T> {
+    fn failure(&self) -> Option<&FatalErr> {
+        match self {
+            ProposeErr::Failed(err) => err.failure(),
+            _ => None,
+        }
+    }
+}
+
+#[cfg(feature = "no_panic")]
+impl Failure for ReconfigErr {
+    fn failure(&self) -> Option<&FatalErr> {
+        match self {
+            ReconfigErr::Failed(err) => err.failure(),
+            _ => None,
+        }
+    }
+}
+
 #[allow(missing_docs)]
 macro_rules! valid_config {
     ($pred:expr,$err_str:expr) => {
@@ -64,3 +657,18 @@ macro_rules! valid_config {
     };
 }
 pub(crate) use valid_config;
+
+/// Turns the failed result of a storage operation of `SequencePaxos` into a [`FatalErr`], which
+/// the handlers return to the application as a [`ProtocolError`] with `?`.
+pub(crate) trait OrFatal<T> {
+    fn or_fatal(self, msg: &'static str) -> Result<T, FatalErr>;
+}
+
+impl<T, E: fmt::Display> OrFatal<T> for Result<T, E> {
+    fn or_fatal(self, msg: &'static str) -> Result<T, FatalErr> {
+        self.map_err(|e| FatalErr::Storage {
+            msg,
+            error: e.to_string(),
+        })
+    }
+}
@@ -0,0 +1,246 @@
+use crate::{
+    errors::FramingErr,
+    messages::Message,
+    storage::{Entry, Storage},
+    OmniPaxos,
+};
+use serde::{de::DeserializeOwned, Serialize};
+use std::{
+    error::Error,
+    fmt::Debug,
+    io::{Read, Write},
+    iter,
+    sync::Arc,
+};
+
+/// The length of the header of a frame: the length of the payload (4 bytes), the flags (1 byte),
+/// and the checksum (4 bytes), all big-endian.
+pub const HEADER_LEN: usize = 9;
+
+/// The default maximum size of the payload of a frame.
+pub const DEFAULT_MAX_FRAME_SIZE: usize = 64 << 20;
+
+/// The flag of a frame whose payload is compressed.
+const COMPRESSED: u8 = 1;
+
+/// Compresses the payloads of frames, e.g., with the compression library the application already
+/// uses. OmniPaxos does not implement any compression itself. Both ends of a connection must use
+/// the same compressor.
+pub trait FrameCompressor: Debug + Send + Sync {
+    /// Compresses `bytes`.
+    fn compress(&self, bytes: &[u8]) -> Result<Vec<u8>, Box<dyn Error>>;
+
+    /// Decompresses `bytes` that were compressed by [`compress`](Self::compress).
+    fn decompress(&self, bytes: &[u8]) -> Result<Vec<u8>, Box<dyn Error>>;
+}
+
+/// Frames serialized messages for transports over raw byte streams or datagrams, e.g., TCP or
+/// UDP, that do not preserve message boundaries or detect corruption by themselves. Every frame
+/// is prefixed with the length of its payload and a CRC-32 checksum, so that partial reads are
+/// waited out and corrupted frames are rejected before [`crate::OmniPaxos::handle_incoming`] sees
+/// them. A stream whose frame is rejected is out of step and should be reconnected, which
+/// OmniPaxos recovers from like from any lost message.
+///
+/// Payloads of at least `compression_threshold` bytes are compressed with the `compressor`, if
+/// any, unless that doesn't make them smaller. In clusters whose servers don't all have the same
+/// compressor, e.g., during a rolling upgrade, only the frames to the servers that accept them are
+/// compressed, see [`encode_with`](Self::encode_with).
+#[derive(Clone, Debug)]
+pub struct FrameCodec {
+    /// The maximum size of the payload of a frame before compression. Larger frames are refused
+    /// when they are written and rejected when their header is read, before their payload is
+    /// buffered.
+    pub max_frame_size: usize,
+    /// Compresses the payloads of the frames. Required to read compressed frames.
+    pub compressor: Option<Arc<dyn FrameCompressor>>,
+    /// The minimum size of a payload that is compressed.
+    pub compression_threshold: usize,
+}
+
+impl Default for FrameCodec {
+    fn default() -> Self {
+        Self {
+            max_frame_size: DEFAULT_MAX_FRAME_SIZE,
+            compressor: None,
+            compression_threshold: 1024,
+        }
+    }
+}
+
+impl FrameCodec {
+    /// Frames `payload`.
+    pub fn encode(&self, payload: &[u8]) -> Result<Vec<u8>, FramingErr> {
+        self.encode_with(payload, true)
+    }
+
+    /// Frames `payload`, but only compresses it if `compress` is set, e.g., to
+    /// [`OmniPaxos::peer_accepts_compression`](crate::OmniPaxos::peer_accepts_compression) of the
+    /// receiver of the frame.
+    pub fn encode_with(&self, payload: &[u8], compress: bool) -> Result<Vec<u8>, FramingErr> {
+        self.check_size(payload.len())?;
+        let compressed = match &self.compressor {
+            Some(compressor) if compress && payload.len() >= self.compression_threshold => {
+                let compressed =
+                    compressor
+                        .compress(payload)
+                        .map_err(|source| FramingErr::Compressor {
+                            op: "compress",
+                            source,
+                        })?;
+                Some(compressed).filter(|c| c.len() < payload.len())
+            }
+            _ => None,
+        };
+        let (flags, body) = match &compressed {
+            Some(c) => (COMPRESSED, c.as_slice()),
+            None => (0, payload),
+        };
+        let mut frame = Vec::with_capacity(HEADER_LEN + body.len());
+        frame.extend_from_slice(&(body.len() as u32).to_be_bytes());
+        frame.push(flags);
+        frame.extend_from_slice(&crc32(flags, body).to_be_bytes());
+        frame.extend_from_slice(body);
+        Ok(frame)
+    }
+
+    /// Reads the first frame of `bytes`, e.g., the bytes received on a stream so far. Returns the
+    /// payload and the number of bytes of the frame, which the caller drops before decoding the
+    /// next frame, or `None` if the frame is incomplete.
+    pub fn decode(&self, bytes: &[u8]) -> Result<Option<(Vec<u8>, usize)>, FramingErr> {
+        if bytes.len() < HEADER_LEN {
+            return Ok(None);
+        }
+        let (len, flags, checksum) = self.parse_header(&bytes[..HEADER_LEN])?;
+        let frame_len = HEADER_LEN + len;
+        if bytes.len() < frame_len {
+            return Ok(None);
+        }
+        let payload = self.open(flags, checksum, &bytes[HEADER_LEN..frame_len])?;
+        Ok(Some((payload, frame_len)))
+    }
+
+    /// Frames `payload` and writes it to `writer`.
+    pub fn write_frame<W: Write>(&self, mut writer: W, payload: &[u8]) -> Result<(), FramingErr> {
+        writer.write_all(&self.encode(payload)?)?;
+        Ok(())
+    }
+
+    /// Reads the next frame from `reader` and returns its payload. Blocks until the whole frame
+    /// is read.
+    pub fn read_frame<R: Read>(&self, mut reader: R) -> Result<Vec<u8>, FramingErr> {
+        let mut header = [0; HEADER_LEN];
+        reader.read_exact(&mut header)?;
+        let (len, flags, checksum) = self.parse_header(&header)?;
+        let mut body = vec![0; len];
+        reader.read_exact(&mut body)?;
+        self.open(flags, checksum, &body)
+    }
+
+    /// Serializes `msg`, e.g., a [`crate::messages::Message`], and frames it.
+    pub fn encode_message<M: Serialize>(&self, msg: &M) -> Result<Vec<u8>, FramingErr> {
+        self.encode(&bincode::serialize(msg)?)
+    }
+
+    /// Serializes `msg` and frames it, but only compresses it if its receiver accepts compressed
+    /// frames as negotiated by `omnipaxos`, see
+    /// [`OmniPaxos::peer_accepts_compression`](crate::OmniPaxos::peer_accepts_compression).
+    pub fn encode_message_to<T, B>(
+        &self,
+        msg: &Message<T>,
+        omnipaxos: &OmniPaxos<T, B>,
+    ) -> Result<Vec<u8>, FramingErr>
+    where
+        T: Entry + Serialize,
+        B: Storage<T>,
+    {
+        let compress = omnipaxos.peer_accepts_compression(msg.get_receiver());
+        self.encode_with(&bincode::serialize(msg)?, compress)
+    }
+
+    /// Reads the first frame of `bytes` as in [`decode`](Self::decode) and deserializes it into a
+    /// message.
+    pub fn decode_message<M: DeserializeOwned>(
+        &self,
+        bytes: &[u8],
+    ) -> Result<Option<(M, usize)>, FramingErr> {
+        match self.decode(bytes)? {
+            Some((payload, frame_len)) => Ok(Some((bincode::deserialize(&payload)?, frame_len))),
+            None => Ok(None),
+        }
+    }
+
+    fn check_size(&self, size: usize) -> Result<(), FramingErr> {
+        if size > self.max_frame_size || size > u32::MAX as usize {
+            return Err(FramingErr::TooLarge {
+                size,
+                max: self.max_frame_size,
+            });
+        }
+        Ok(())
+    }
+
+    fn parse_header(&self, header: &[u8]) -> Result<(usize, u8, u32), FramingErr> {
+        let len = u32::from_be_bytes(header[0..4].try_into().unwrap()) as usize;
+        let flags = header[4];
+        let checksum = u32::from_be_bytes(header[5..9].try_into().unwrap());
+        self.check_size(len)?;
+        if flags & !COMPRESSED != 0 {
+            return Err(FramingErr::UnknownFlags(flags));
+        }
+        Ok((len, flags, checksum))
+    }
+
+    /// Verifies the checksum of `body` and decompresses it if needed.
+    fn open(&self, flags: u8, checksum: u32, body: &[u8]) -> Result<Vec<u8>, FramingErr> {
+        let actual = crc32(flags, body);
+        if actual != checksum {
+            return Err(FramingErr::ChecksumMismatch {
+                expected: checksum,
+                actual,
+            });
+        }
+        if flags & COMPRESSED == 0 {
+            return Ok(body.to_vec());
+        }
+        let compressor = self.compressor.as_ref().ok_or(FramingErr::NoCompressor)?;
+        let payload = compressor
+            .decompress(body)
+            .map_err(|source| FramingErr::Compressor {
+                op: "decompress",
+                source,
+            })?;
+        self.check_size(payload.len())?;
+        Ok(payload)
+    }
+}
+
+/// The table of the CRC-32 (IEEE 802.3) polynomial, as the checksum must be the same on all
+/// servers regardless of their Rust version.
+const CRC_TABLE: [u32; 256] = crc_table();
+
+const fn crc_table() -> [u32; 256] {
+    let mut table = [0; 256];
+    let mut i = 0;
+    while i < 256 {
+        let mut crc = i as u32;
+        let mut bit = 0;
+        while bit < 8 {
+            crc = if crc & 1 == 1 {
+                (crc >> 1) ^ 0xEDB8_8320
+            } else {
+                crc >> 1
+            };
+            bit += 1;
+        }
+        table[i] = crc;
+        i += 1;
+    }
+    table
+}
+
+/// The checksum of a frame, which covers its flags and its payload.
+fn crc32(flags: u8, body: &[u8]) -> u32 {
+    !iter::once(&flags).chain(body).fold(!0, |crc, b| {
+        CRC_TABLE[((crc ^ *b as u32) & 0xFF) as usize] ^ (crc >> 8)
+    })
+}
@@ -0,0 +1,194 @@
+use crate::{
+    errors::{ProtocolError, ReadErr, StorageErr},
+    messages::Message,
+    storage::{Entry, Storage},
+    util::{LogEntry, NodeId},
+    ClusterConfig, OmniPaxos, ProposeErr, ReconfigErr, ReconfigurationStatus,
+};
+use std::time::Duration;
+use thiserror::Error;
+use tokio::{
+    sync::{
+        mpsc::{self, error::TrySendError},
+        oneshot,
+    },
+    task::JoinHandle,
+    time,
+};
+
+/// The number of requests that can be queued for the OmniPaxos task before callers have to wait.
+const REQUEST_BUFFER_SIZE: usize = 1000;
+
+type Request<T, B> = Box<dyn FnOnce(&mut OmniPaxos<T, B>) + Send>;
+
+/// Error returned by an [`OmniPaxosHandle`].
+#[derive(Debug, Error)]
+#[non_exhaustive]
+pub enum HandleError<T>
+where
+    T: Entry,
+{
+    /// The task running the OmniPaxos instance has stopped.
+    #[error("the OmniPaxos task has stopped")]
+    Stopped,
+    /// The proposal failed.
+    #[error(transparent)]
+    Propose(ProposeErr<T>),
+    /// The reconfiguration failed.
+    #[error(transparent)]
+    Reconfig(ReconfigErr),
+    /// The read failed.
+    #[error(transparent)]
+    Read(ReadErr),
+}
+
+/// Handle to an OmniPaxos instance that is run on its own tokio task. The task ticks the
+/// instance every `tick_period`, handles the messages received on `incoming`, and sends the
+/// outgoing messages of the instance on `outgoing`. The user only has to move messages between
+/// these channels and the network.
+///
+/// The handle can be cloned and used from several tasks. The OmniPaxos task stops once all
+/// handles have been dropped or the receiver of `outgoing` has been closed, or with the error if
+/// handling a tick or message fails. The task never waits for `outgoing`: if it is full, the
+/// message is dropped like on an unreliable network, and the instance resends it later.
+pub struct OmniPaxosHandle<T, B>
+where
+    T: Entry,
+    B: Storage<T>,
+{
+    requests: mpsc::Sender<Request<T, B>>,
+}
+
+impl<T, B> Clone for OmniPaxosHandle<T, B>
+where
+    T: Entry,
+    B: Storage<T>,
+{
+    fn clone(&self) -> Self {
+        Self {
+            requests: self.requests.clone(),
+        }
+    }
+}
+
+impl<T, B> OmniPaxosHandle<T, B>
+where
+    T: Entry + Send + 'static,
+    B: Storage<T> + 'static,
+    OmniPaxos<T, B>: Send,
+    Message<T>: Send,
+    LogEntry<T>: Send,
+{
+    /// Spawns a task on the current tokio runtime that runs `omni_paxos`. Returns a handle to the
+    /// instance and the `JoinHandle` of the task.
+    pub fn spawn(
+        omni_paxos: OmniPaxos<T, B>,
+        tick_period: Duration,
+        incoming: mpsc::Receiver<Message<T>>,
+        outgoing: mpsc::Sender<Message<T>>,
+    ) -> (Self, JoinHandle<Result<(), ProtocolError>>) {
+        let (requests, request_receiver) = mpsc::channel(REQUEST_BUFFER_SIZE);
+        let task = tokio::spawn(Self::run(
+            omni_paxos,
+            tick_period,
+            request_receiver,
+            incoming,
+            outgoing,
+        ));
+        (Self { requests }, task)
+    }
+
+    async fn run(
+        mut omni_paxos: OmniPaxos<T, B>,
+        tick_period: Duration,
+        mut requests: mpsc::Receiver<Request<T, B>>,
+        mut incoming: mpsc::Receiver<Message<T>>,
+        outgoing: mpsc::Sender<Message<T>>,
+    ) -> Result<(), ProtocolError> {
+        let mut tick_interval = time::interval(tick_period);
+        loop {
+            tokio::select! {
+                _ = tick_interval.tick() => omni_paxos.tick()?,
+                Some(msg) = incoming.recv() => omni_paxos.handle_incoming(msg)?,
+                req = requests.recv() => match req {
+                    Some(req) => req(&mut omni_paxos),
+                    None => break,
+                },
+            }
+            for msg in omni_paxos.outgoing_messages() {
+                match outgoing.try_send(msg) {
+                    Ok(()) | Err(TrySendError::Full(_)) => {}
+                    Err(TrySendError::Closed(_)) => return Ok(()),
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Runs `f` on the OmniPaxos instance and returns its result.
+    async fn call<R, F>(&self, f: F) -> Result<R, HandleError<T>>
+    where
+        F: FnOnce(&mut OmniPaxos<T, B>) -> R + Send + 'static,
+        R: Send + 'static,
+    {
+        let (reply, result) = oneshot::channel();
+        let req: Request<T, B> = Box::new(move |omni_paxos| {
+            let _ = reply.send(f(omni_paxos));
+        });
+        self.requests
+            .send(req)
+            .await
+            .map_err(|_| HandleError::Stopped)?;
+        result.await.map_err(|_| HandleError::Stopped)
+    }
+
+    /// Appends an entry to the replicated log. See [`OmniPaxos::append`].
+    pub async fn append(&self, entry: T) -> Result<(), HandleError<T>> {
+        self.call(move |op| op.append(entry))
+            .await?
+            .map_err(HandleError::Propose)
+    }
+
+    /// Proposes a cluster reconfiguration. See [`OmniPaxos::reconfigure`].
+    pub async fn reconfigure(
+        &self,
+        new_configuration: ClusterConfig,
+        metadata: Option<Vec<u8>>,
+    ) -> Result<ReconfigurationStatus, HandleError<T>> {
+        self.call(move |op| op.reconfigure(new_configuration, metadata))
+            .await?
+            .map_err(HandleError::Reconfig)
+    }
+
+    /// Reads the entry at index `idx` in the log. See [`OmniPaxos::try_read`]. Since the errors
+    /// of the storage can't be sent between threads, the error of a failed read only keeps its
+    /// message.
+    pub async fn read(&self, idx: usize) -> Result<Option<LogEntry<T>>, HandleError<T>> {
+        self.call(move |op| {
+            op.try_read(idx).map_err(|e| match e {
+                ReadErr::Storage(e) => (e.op, e.source.to_string()),
+                e => ("read log entries", e.to_string()),
+            })
+        })
+        .await?
+        .map_err(|(op, msg)| HandleError::Read(StorageErr::with(op, msg.into()).into()))
+    }
+
+    /// Reads the decided entries from `from_idx` in the log. See [`OmniPaxos::read_decided_suffix`].
+    pub async fn read_decided_suffix(
+        &self,
+        from_idx: usize,
+    ) -> Result<Option<Vec<LogEntry<T>>>, HandleError<T>> {
+        self.call(move |op| op.read_decided_suffix(from_idx)).await
+    }
+
+    /// Returns the decided index.
+    pub async fn get_decided_idx(&self) -> Result<usize, HandleError<T>> {
+        self.call(|op| op.get_decided_idx()).await
+    }
+
+    /// Returns the id of the current leader.
+    pub async fn get_current_leader(&self) -> Result<Option<NodeId>, HandleError<T>> {
+        self.call(|op| op.get_current_leader()).await
+    }
+}
@@ -0,0 +1,222 @@
+use crate::{
+    errors::HandoffErr,
+    storage::{archive::ArchiveCodec, Entry},
+    util::ConfigurationId,
+};
+#[cfg(feature = "serde")]
+use serde::{Deserialize, Serialize};
+
+/// Prefixes the StopSign metadata of a reconfiguration with a handoff.
+const HANDOFF_MAGIC: [u8; 4] = *b"OPXH";
+
+/// Requests the handoff of the application state to the next configuration. It is proposed as
+/// part of the StopSign metadata by [`OmniPaxos::reconfigure_with_handoff`](crate::OmniPaxos::reconfigure_with_handoff),
+/// together with the metadata of the application.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct HandoffRef {
+    /// The maximum number of bytes of a [`HandoffChunk`].
+    pub chunk_size: usize,
+}
+
+impl HandoffRef {
+    /// Encodes the handoff together with the application's `metadata` into StopSign metadata.
+    pub fn encode(&self, metadata: Option<Vec<u8>>) -> Vec<u8> {
+        let mut bytes = HANDOFF_MAGIC.to_vec();
+        bytes.extend_from_slice(&(self.chunk_size as u64).to_le_bytes());
+        if let Some(metadata) = metadata {
+            bytes.push(1);
+            bytes.extend_from_slice(&metadata);
+        } else {
+            bytes.push(0);
+        }
+        bytes
+    }
+
+    /// Decodes the StopSign `metadata` encoded by [`HandoffRef::encode`]. Returns the handoff and
+    /// the application's metadata, or `None` if the StopSign did not request a handoff.
+    pub fn decode(metadata: &[u8]) -> Option<(Self, Option<Vec<u8>>)> {
+        let rest = metadata.strip_prefix(&HANDOFF_MAGIC)?;
+        let (chunk_size, rest) = rest.split_first_chunk::<8>()?;
+        let chunk_size = usize::try_from(u64::from_le_bytes(*chunk_size)).ok()?;
+        let metadata = match rest.split_first()? {
+            (0, []) => None,
+            (1, metadata) => Some(metadata.to_vec()),
+            _ => return None,
+        };
+        Some((Self { chunk_size }, metadata))
+    }
+}
+
+/// Describes the final snapshot of a configuration that is handed off to the next one. It is sent
+/// to the servers of the next configuration before the chunks.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct HandoffManifest {
+    /// The id of the configuration the snapshot is handed off to.
+    pub configuration_id: ConfigurationId,
+    /// The number of log entries included in the snapshot, i.e., the index of the StopSign.
+    pub decided_idx: usize,
+    /// The size of the encoded snapshot in bytes.
+    pub size: usize,
+    /// The maximum number of bytes of a chunk.
+    pub chunk_size: usize,
+    /// The FNV-1a hash of the encoded snapshot.
+    pub digest: u64,
+}
+
+impl HandoffManifest {
+    /// The number of chunks of the snapshot.
+    pub fn num_chunks(&self) -> u32 {
+        self.size.div_ceil(self.chunk_size) as u32
+    }
+}
+
+/// A part of the encoded snapshot of a handoff.
+#[derive(Clone, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct HandoffChunk {
+    /// The id of the configuration the snapshot is handed off to.
+    pub configuration_id: ConfigurationId,
+    /// The position of the chunk in the snapshot.
+    pub index: u32,
+    /// The bytes of the chunk.
+    pub data: Vec<u8>,
+}
+
+/// The final snapshot of a stopped configuration, split into chunks that can be sent to the
+/// servers of the next configuration. Created by [`OmniPaxos::create_handoff`](crate::OmniPaxos::create_handoff).
+#[derive(Clone, Debug)]
+pub struct HandoffSender {
+    manifest: HandoffManifest,
+    bytes: Vec<u8>,
+}
+
+impl HandoffSender {
+    pub(crate) fn with(
+        configuration_id: ConfigurationId,
+        decided_idx: usize,
+        chunk_size: usize,
+        bytes: Vec<u8>,
+    ) -> Self {
+        let manifest = HandoffManifest {
+            configuration_id,
+            decided_idx,
+            size: bytes.len(),
+            chunk_size: chunk_size.max(1),
+            digest: digest(&bytes),
+        };
+        Self { manifest, bytes }
+    }
+
+    /// Returns the manifest to send to the servers of the next configuration.
+    pub fn manifest(&self) -> HandoffManifest {
+        self.manifest
+    }
+
+    /// Returns the chunk at `index`, e.g., to resend a chunk that a receiver is missing.
+    pub fn chunk(&self, index: u32) -> Option<HandoffChunk> {
+        let chunk_size = self.manifest.chunk_size;
+        let from = (index as usize).checked_mul(chunk_size)?;
+        if from >= self.bytes.len() {
+            return None;
+        }
+        let to = (from + chunk_size).min(self.bytes.len());
+        Some(HandoffChunk {
+            configuration_id: self.manifest.configuration_id,
+            index,
+            data: self.bytes[from..to].to_vec(),
+        })
+    }
+
+    /// Returns all chunks of the snapshot.
+    pub fn chunks(&self) -> impl Iterator<Item = HandoffChunk> + '_ {
+        (0..self.manifest.num_chunks()).filter_map(|index| self.chunk(index))
+    }
+}
+
+/// Reassembles the final snapshot of the previous configuration at a server of the next
+/// configuration from the chunks sent by a [`HandoffSender`]. The chunks can be received in any
+/// order and more than once.
+#[derive(Clone, Debug)]
+pub struct HandoffReceiver {
+    manifest: HandoffManifest,
+    chunks: Vec<Option<Vec<u8>>>,
+}
+
+impl HandoffReceiver {
+    /// Creates a receiver for the snapshot described by `manifest`.
+    pub fn with(manifest: HandoffManifest) -> Self {
+        Self {
+            manifest,
+            chunks: vec![None; manifest.num_chunks() as usize],
+        }
+    }
+
+    /// Returns the manifest of the snapshot that is received.
+    pub fn manifest(&self) -> HandoffManifest {
+        self.manifest
+    }
+
+    /// Stores the `chunk`. Fails if the chunk does not belong to the snapshot of the manifest.
+    pub fn handle_chunk(&mut self, chunk: HandoffChunk) -> Result<(), HandoffErr> {
+        let HandoffManifest {
+            configuration_id,
+            size,
+            chunk_size,
+            ..
+        } = self.manifest;
+        if chunk.configuration_id != configuration_id {
+            return Err(HandoffErr::WrongConfiguration {
+                expected: configuration_id,
+                found: chunk.configuration_id,
+            });
+        }
+        let index = chunk.index as usize;
+        let expected_len = chunk_size.min(size.saturating_sub(index * chunk_size));
+        match self.chunks.get_mut(index) {
+            Some(slot) if chunk.data.len() == expected_len => {
+                *slot = Some(chunk.data);
+                Ok(())
+            }
+            _ => Err(HandoffErr::InvalidChunk(chunk.index)),
+        }
+    }
+
+    /// Returns the indexes of the chunks that have not been received yet.
+    pub fn missing_chunks(&self) -> Vec<u32> {
+        (0..self.chunks.len() as u32)
+            .filter(|i| self.chunks[*i as usize].is_none())
+            .collect()
+    }
+
+    /// Returns whether all chunks have been received.
+    pub fn is_complete(&self) -> bool {
+        self.chunks.iter().all(Option::is_some)
+    }
+
+    /// Checks the received snapshot against the digest of the manifest and decodes it with
+    /// `codec`. Returns the decoded snapshot together with the number of log entries it includes.
+    pub fn finish<T, C>(self, codec: &C) -> Result<(usize, T::Snapshot), HandoffErr>
+    where
+        T: Entry,
+        C: ArchiveCodec<T>,
+    {
+        let missing = self.missing_chunks().len();
+        if missing > 0 {
+            return Err(HandoffErr::Incomplete(missing));
+        }
+        let bytes: Vec<u8> = self.chunks.into_iter().flatten().flatten().collect();
+        if digest(&bytes) != self.manifest.digest {
+            return Err(HandoffErr::DigestMismatch);
+        }
+        let snapshot = codec.decode_snapshot(&bytes).map_err(HandoffErr::Codec)?;
+        Ok((self.manifest.decided_idx, snapshot))
+    }
+}
+
+/// FNV-1a, as the digest must be the same on all servers regardless of their Rust version.
+fn digest(bytes: &[u8]) -> u64 {
+    bytes.iter().fold(0xcbf29ce484222325, |hash, b| {
+        (hash ^ *b as u64).wrapping_mul(0x100000001b3)
+    })
+}
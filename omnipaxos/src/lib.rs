@@ -8,24 +8,75 @@
 //! * `batch_accept` - Batch multiple log entries into a single message to reduce overhead.
 //! * `logging` - System-wide logging with the slog crate
 //! * `toml_config` - Create an OmniPaxos instance from a TOML configuration file
+//! * `tokio` - Run an OmniPaxos instance on its own tokio task and interact with it through an async [`handle::OmniPaxosHandle`].
+//! * `operator_tools` - Advanced operator APIs that can violate the guarantees of OmniPaxos if misused, such as [`OmniPaxos::unsafe_truncate_after`].
+//! * `admin` - A minimal HTTP server for an admin API with status, metrics, snapshot, trim, transfer-leadership, and reconfigure endpoints. See [`admin::AdminServer`].
+//! * `prometheus` - A [`metrics::MetricsSink`] that renders the metrics in the Prometheus text exposition format.
+//! * `statsd` - A [`metrics::MetricsSink`] that sends the metrics to a StatsD server.
+//! * `pipeline_events` - Timestamped events for every stage of the replication pipeline that log entries pass through, for latency breakdowns. See [`OmniPaxos::take_pipeline_events`].
+//! * `no_snapshots` - Remove the snapshot code paths at compile time for entries that don't use snapshots, e.g., with [`storage::NoSnapshot`]. Snapshotting the log fails with [`errors::CompactionErr::SnapshotsUnsupported`]. All servers of a cluster must be built with the same setting.
+//! * `snapshot_sealing` - Encrypt and sign the snapshots sent to other servers with the keys of the application, and verify them before they are installed. See [`sealing::SnapshotSealer`]. Implies `serde`.
+//! * `framing` - Length-prefixed, checksummed, and optionally compressed frames for sending serialized messages over raw TCP or UDP transports. See [`framing::FrameCodec`]. Implies `serde`.
+//! * `no_panic` - Halt the server as soon as a handler or proposal fails with an [`errors::FatalErr`], for processes that must never continue from a state that might differ from the storage. See [`OmniPaxos::halt`].
+//! * `spill` - Spill large outgoing sync messages to a temporary file once they exceed a memory budget. See [`OmniPaxos::spill_syncs_to_disk`]. Implies `serde`.
 //! * `serde` - Serialization and deserialization of messages and internal structs with serde. Disable this if you want to implement your own custom ser/deserialization or want to store data that is not serde-supported.
 
 #![cfg_attr(docsrs, feature(doc_auto_cfg))]
 #![deny(missing_docs)]
+// The deprecated variants of `ProposeErr` carry the `ClusterConfig` by value.
+#![allow(clippy::result_large_err)]
+#[cfg(feature = "admin")]
+/// An admin API that translates operator requests into the calls of an OmniPaxos server.
+pub mod admin;
+/// Structs for the audit log of the administrative operations on a server.
+pub mod audit;
 /// Trait and struct related to the leader election in Omni-Paxos.
 pub mod ballot_leader_election;
+/// Traits and structs for replicating entries that are too large to be sent in a single message.
+pub mod chunking;
 /// OmniPaxos error definitions
 pub mod errors;
+pub use errors::{CompactionErr, ProposeErr, ReadErr, ReconfigErr, StorageErr};
+#[cfg(feature = "framing")]
+/// Length-prefixed and checksummed framing of serialized messages for custom transports.
+pub mod framing;
+#[cfg(feature = "tokio")]
+/// An async handle to an OmniPaxos instance that runs on its own tokio task.
+pub mod handle;
+/// Structs for handing over the application state to the next configuration.
+pub mod handoff;
 /// The different messages OmniPaxos servers can communicate to each other with.
 pub mod messages;
+/// Traits and structs for exporting the metrics of an OmniPaxos server.
+pub mod metrics;
 /// The user-facing OmniPaxos struct.
 mod omni_paxos;
 pub use omni_paxos::*;
+#[cfg(feature = "pipeline_events")]
+/// Events for profiling the stages of the replication pipeline.
+pub mod pipeline;
+/// A read-only handle to the log of an OmniPaxos server.
+mod reader;
+pub use reader::{OmniPaxosReader, ReadBatch};
+/// Structs for delivering the decided entries of consecutive configurations in order.
+pub mod reconfiguration;
 
+#[cfg(feature = "snapshot_sealing")]
+/// Traits and structs for encrypting and signing the snapshots sent to other servers.
+pub mod sealing;
 /// The core replication algorithm of OmniPaxos.
 pub(crate) mod sequence_paxos;
+/// Traits and structs for changing the settings of all servers through the replicated log.
+pub mod settings;
+/// Traits and structs for streaming the decided log to external systems.
+pub mod shipping;
+#[cfg(feature = "spill")]
+/// Spilling outgoing messages to disk.
+pub(crate) mod spill;
 /// Traits and structs related to the backend storage of an OmniPaxos server.
 pub mod storage;
+/// Explanations of the trade-offs made by the configuration presets of a server.
+pub mod tuning;
 
 #[cfg(feature = "unicache")]
 /// Traits, structs, and types related to the unicache.
@@ -34,6 +85,8 @@ pub mod unicache;
 pub mod util;
 /// A module containing helper functions and structs.
 pub mod utils;
+/// Structs for comparing the logs of a quorum of servers at an index.
+pub mod witness;
 
 #[cfg(feature = "macros")]
 #[allow(unused_imports)]
@@ -46,3 +99,10 @@ pub mod macros {
     #[doc(hidden)]
     pub use omnipaxos_macros::*;
 }
+
+#[cfg(feature = "serde")]
+#[doc(hidden)]
+/// Re-exports for the code generated by the macros.
+pub mod __private {
+    pub use serde;
+}
@@ -1,7 +1,7 @@
 use crate::{
     messages::{ballot_leader_election::BLEMessage, sequence_paxos::PaxosMessage},
     storage::Entry,
-    util::NodeId,
+    util::{Namespace, NodeId},
 };
 #[cfg(feature = "serde")]
 use serde::{Deserialize, Serialize};
@@ -11,11 +11,13 @@ pub mod sequence_paxos {
     use crate::{
         ballot_leader_election::Ballot,
         storage::{Entry, StopSign},
-        util::{LogSync, NodeId, SequenceNumber},
+        util::{LogSync, Namespace, NodeId, OriginToken, SequenceNumber},
+        witness::Witness,
+        CommitLatency, ProposalRejectionCause,
     };
     #[cfg(feature = "serde")]
     use serde::{Deserialize, Serialize};
-    use std::fmt::Debug;
+    use std::{fmt::Debug, time::SystemTime};
 
     /// Message sent by a follower on crash-recovery or dropped messages to request its leader to re-prepare them.
     #[derive(Copy, Clone, Debug)]
@@ -80,6 +82,66 @@ pub mod sequence_paxos {
         pub unicache: T::UniCache,
     }
 
+    /// A portion of the leader's log sent to a follower that is too far behind to be synchronized
+    /// with a single [`AcceptSync`]. The follower requests the following portions with
+    /// [`SyncRequest`] and the last one is sent as an [`AcceptSync`].
+    #[derive(Clone, Debug)]
+    #[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+    pub struct SyncPortion<T>
+    where
+        T: Entry,
+    {
+        /// The current round.
+        pub n: Ballot,
+        /// The decided index
+        pub decided_idx: usize,
+        /// The log update which the follower applies to its log.
+        pub log_sync: LogSync<T>,
+        /// The length of the leader's log when the portion was sent.
+        pub sync_end_idx: usize,
+    }
+
+    /// Message sent by a follower that is catching up to request the portion of the leader's log
+    /// from `from_idx` to `to_idx`. The leader might send the portion in several messages.
+    #[derive(Copy, Clone, Debug)]
+    #[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+    pub struct SyncRequest {
+        /// The current round.
+        pub n: Ballot,
+        /// The log index the requested portion starts at.
+        pub from_idx: usize,
+        /// The log index the requested portion ends at (exclusive).
+        pub to_idx: usize,
+    }
+
+    /// Message sent periodically by a follower in pull mode to request the entries after its
+    /// accepted index.
+    #[derive(Copy, Clone, Debug)]
+    #[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+    pub struct PullRequest {
+        /// The current round.
+        pub n: Ballot,
+        /// The decided index of the follower.
+        pub decided_idx: usize,
+        /// The accepted index of the follower.
+        pub accepted_idx: usize,
+    }
+
+    /// Message sent by the leader in response to a [`PullRequest`].
+    #[derive(Clone, Debug)]
+    #[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+    pub struct PullResponse<T>
+    where
+        T: Entry,
+    {
+        /// The current round.
+        pub n: Ballot,
+        /// The decided index.
+        pub decided_idx: usize,
+        /// The entries after the follower's accepted index.
+        pub log_sync: LogSync<T>,
+    }
+
     /// Message with entries to be replicated and the latest decided index sent by the leader in the accept phase.
     #[derive(Clone, Debug)]
     #[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
@@ -101,6 +163,28 @@ pub mod sequence_paxos {
         pub entries: Vec<T::EncodeResult>,
     }
 
+    /// Message with entries to be replicated sent by the leader to the relay of a remote region.
+    /// The relay forwards an [`AcceptDecide`] to each of the followers in `seq_nums`.
+    #[derive(Clone, Debug)]
+    #[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+    pub struct RelayAcceptDecide<T>
+    where
+        T: Entry,
+    {
+        /// The current round.
+        pub n: Ballot,
+        /// The followers to forward the entries to and the sequence number of their message.
+        pub seq_nums: Vec<(NodeId, SequenceNumber)>,
+        /// The decided index.
+        pub decided_idx: usize,
+        #[cfg(not(feature = "unicache"))]
+        /// Entries to be replicated.
+        pub entries: Vec<T>,
+        #[cfg(feature = "unicache")]
+        /// Entries to be replicated.
+        pub entries: Vec<T::EncodeResult>,
+    }
+
     /// Message sent by follower to leader when entries has been accepted.
     #[derive(Copy, Clone, Debug)]
     #[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
@@ -109,6 +193,8 @@ pub mod sequence_paxos {
         pub n: Ballot,
         /// The accepted index.
         pub accepted_idx: usize,
+        /// The decided index of the follower when it sent this message.
+        pub decided_idx: usize,
     }
 
     /// Message sent by leader to followers to decide up to a certain index in the log.
@@ -123,6 +209,21 @@ pub mod sequence_paxos {
         pub decided_idx: usize,
     }
 
+    /// Message sent periodically by the leader to followers with a fingerprint of the entries it
+    /// decided since its previous `DecidedFingerprint`.
+    #[derive(Copy, Clone, Debug)]
+    #[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+    pub struct DecidedFingerprint {
+        /// The current round.
+        pub n: Ballot,
+        /// The first index of the fingerprinted entries.
+        pub from_idx: usize,
+        /// The end (exclusive) of the fingerprinted entries.
+        pub to_idx: usize,
+        /// The fingerprint of the decided entries in [`from_idx`, `to_idx`).
+        pub fingerprint: u64,
+    }
+
     /// Message sent by leader to followers to accept a StopSign
     #[derive(Clone, Debug)]
     #[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
@@ -135,6 +236,140 @@ pub mod sequence_paxos {
         pub ss: StopSign,
     }
 
+    /// Message sent by leader to the proposer of a StopSign that failed the reconfiguration validation.
+    #[derive(Clone, Debug)]
+    #[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+    pub struct RejectStopSign {
+        /// The rejected StopSign.
+        pub ss: StopSign,
+        /// The reason the StopSign was rejected.
+        pub reason: String,
+    }
+
+    /// Message sent by a wiped server to a healthy replica to request its decided state.
+    #[derive(Copy, Clone, Debug)]
+    #[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+    pub struct BootstrapRequest;
+
+    /// Message sent by a healthy replica in response to a [`BootstrapRequest`].
+    #[derive(Clone, Debug)]
+    #[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+    pub struct BootstrapResponse<T>
+    where
+        T: Entry,
+    {
+        /// The round promised by the replica.
+        pub n: Ballot,
+        /// The round in which the replica last accepted entries.
+        pub accepted_round: Ballot,
+        /// The decided index of the replica.
+        pub decided_idx: usize,
+        /// The decided snapshot, entries and StopSign of the replica.
+        pub log_sync: LogSync<T>,
+    }
+
+    /// Message sent by a wiped server to the other servers to query their state before it takes
+    /// part in the protocol again.
+    #[derive(Copy, Clone, Debug)]
+    #[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+    pub struct RecoveryQuery;
+
+    /// Message sent by a healthy replica in response to a [`RecoveryQuery`].
+    #[derive(Copy, Clone, Debug)]
+    #[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+    pub struct RecoveryState {
+        /// The round promised by the replica.
+        pub n: Ballot,
+        /// The decided index of the replica.
+        pub decided_idx: usize,
+    }
+
+    /// Message sent to the other servers to request their log at an index for a witness read.
+    #[derive(Copy, Clone, Debug)]
+    #[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+    pub struct WitnessReadRequest {
+        /// The id of the witness read.
+        pub id: u64,
+        /// The read log index.
+        pub idx: usize,
+    }
+
+    /// Message sent in response to a [`WitnessReadRequest`].
+    #[derive(Clone, Debug)]
+    #[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+    pub struct WitnessReadResponse<T>
+    where
+        T: Entry,
+    {
+        /// The id of the witness read.
+        pub id: u64,
+        /// The log of the responding server at the read index.
+        pub witness: Witness<T>,
+    }
+
+    /// Message sent by the leader to a backup server with a snapshot of the decided log.
+    #[derive(Clone, Debug)]
+    #[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+    pub struct BackupSnapshot<T>
+    where
+        T: Entry,
+    {
+        /// The current round.
+        pub n: Ballot,
+        /// The decided index of the backup that a `Delta` snapshot is based on.
+        pub from_idx: usize,
+        /// The decided index of the leader.
+        pub decided_idx: usize,
+        /// The snapshot of the decided log and the decided StopSign, if any.
+        pub log_sync: LogSync<T>,
+    }
+
+    /// Message sent by a backup server to acknowledge the decided index it has stored.
+    #[derive(Copy, Clone, Debug)]
+    #[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+    pub struct BackupAck {
+        /// The round of the acknowledged [`BackupSnapshot`].
+        pub n: Ballot,
+        /// The decided index of the backup.
+        pub decided_idx: usize,
+    }
+
+    /// Message sent by the leader to designate a hot-standby leader. The followers promise not to
+    /// accept a prepare of the next round from any other server than the standby.
+    #[derive(Copy, Clone, Debug)]
+    #[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+    pub struct Standby {
+        /// The current round.
+        pub n: Ballot,
+        /// The designated standby.
+        pub standby: NodeId,
+        /// Whether the standby can take over without a prepare phase. Only sent to the standby.
+        pub ready: bool,
+    }
+
+    /// Message sent by a follower to acknowledge that it durably stored the promise of a [`Standby`].
+    #[derive(Copy, Clone, Debug)]
+    #[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+    pub struct StandbyAck {
+        /// The round of the acknowledged [`Standby`].
+        pub n: Ballot,
+    }
+
+    /// Message sent by a hot-standby leader that takes over from the leader of round `n_prev`
+    /// without a prepare phase. Followers that are still in round `n_prev` apply the AcceptSync
+    /// directly, all others handle it as a prepare of the new round.
+    #[derive(Clone, Debug)]
+    #[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+    pub struct Takeover<T>
+    where
+        T: Entry,
+    {
+        /// The round of the previous leader.
+        pub n_prev: Ballot,
+        /// The AcceptSync of the new round.
+        pub acc_sync: AcceptSync<T>,
+    }
+
     /// Message sent by follower to leader when accepting an entry is rejected.
     /// This happens when the follower is promised to a greater leader.
     #[derive(Clone, Debug)]
@@ -142,6 +377,64 @@ pub mod sequence_paxos {
     pub struct NotAccepted {
         /// The follower's current ballot
         pub n: Ballot,
+        /// If set, a Prepare was refused because the follower promised its round to this
+        /// hot-standby leader.
+        #[cfg_attr(feature = "serde", serde(default))]
+        pub standby: Option<NodeId>,
+    }
+
+    /// The `OriginToken` or the deadline of a proposal and the server it was appended on.
+    #[derive(Copy, Clone, Debug, PartialEq, Eq)]
+    #[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+    pub struct ProposalOrigin {
+        /// The position of the proposal among the forwarded entries.
+        pub idx: usize,
+        /// The server that the proposal was appended on.
+        pub pid: NodeId,
+        /// The token the proposal was appended with, if any.
+        pub token: Option<OriginToken>,
+        /// The time after which the proposal is dropped instead of appended to the log, if any.
+        #[cfg_attr(feature = "serde", serde(default))]
+        pub deadline: Option<SystemTime>,
+    }
+
+    impl ProposalOrigin {
+        /// Whether the deadline of the proposal has passed at `now`.
+        pub(crate) fn is_expired(&self, now: SystemTime) -> bool {
+            self.deadline.is_some_and(|deadline| deadline <= now)
+        }
+    }
+
+    /// Forwards client proposals to the leader, some of which were appended with an `OriginToken`
+    /// or a deadline, or together with the latency the forwarding server observed for its previous proposals.
+    #[derive(Clone, Debug)]
+    #[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+    pub struct TokenProposalForward<T>
+    where
+        T: Entry,
+    {
+        /// The forwarded proposals.
+        pub entries: Vec<T>,
+        /// The origins of the proposals that were appended with a token or a deadline.
+        pub origins: Vec<ProposalOrigin>,
+        /// The latency from forwarding proposals until they were decided, as observed by the
+        /// forwarding server. A hint for placing the leader close to where proposals originate.
+        #[cfg_attr(feature = "serde", serde(default))]
+        pub latency: Option<CommitLatency>,
+    }
+
+    /// Returns proposals that were dropped before they were decided to the server they were
+    /// appended on.
+    #[derive(Clone, Debug)]
+    #[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+    pub struct RejectedProposals<T>
+    where
+        T: Entry,
+    {
+        /// Why the proposals were dropped.
+        pub cause: ProposalRejectionCause,
+        /// The dropped proposals and the tokens they were appended with.
+        pub proposals: Vec<(T, Option<OriginToken>)>,
     }
 
     /// Compaction Request
@@ -167,15 +460,38 @@ pub mod sequence_paxos {
         Prepare(Prepare),
         Promise(Promise<T>),
         AcceptSync(AcceptSync<T>),
+        SyncPortion(SyncPortion<T>),
+        SyncRequest(SyncRequest),
+        PullRequest(PullRequest),
+        PullResponse(PullResponse<T>),
         AcceptDecide(AcceptDecide<T>),
+        RelayAcceptDecide(RelayAcceptDecide<T>),
         Accepted(Accepted),
         NotAccepted(NotAccepted),
         Decide(Decide),
+        DecidedFingerprint(DecidedFingerprint),
         /// Forward client proposals to the leader.
         ProposalForward(Vec<T>),
+        TokenProposalForward(TokenProposalForward<T>),
+        /// The tokens of the proposals that were decided, by their log index, sent by the leader to
+        /// the server the proposals were appended on.
+        DecidedTokens(Vec<(usize, OriginToken)>),
+        RejectedProposals(RejectedProposals<T>),
         Compaction(Compaction),
         AcceptStopSign(AcceptStopSign),
         ForwardStopSign(StopSign),
+        RejectStopSign(RejectStopSign),
+        BootstrapRequest(BootstrapRequest),
+        BootstrapResponse(BootstrapResponse<T>),
+        RecoveryQuery(RecoveryQuery),
+        RecoveryState(RecoveryState),
+        BackupSnapshot(BackupSnapshot<T>),
+        BackupAck(BackupAck),
+        Standby(Standby),
+        StandbyAck(StandbyAck),
+        Takeover(Takeover<T>),
+        WitnessReadRequest(WitnessReadRequest),
+        WitnessReadResponse(WitnessReadResponse<T>),
     }
 
     /// A struct for a Paxos message that also includes sender and receiver.
@@ -189,15 +505,80 @@ pub mod sequence_paxos {
         pub from: NodeId,
         /// Receiver of `msg`.
         pub to: NodeId,
+        /// The namespace of the cluster of the sender.
+        #[cfg_attr(feature = "serde", serde(default))]
+        pub namespace: Option<Namespace>,
         /// The message content.
         pub msg: PaxosMsg<T>,
     }
+
+    impl<T> PaxosMessage<T>
+    where
+        T: Entry,
+    {
+        /// The approximate size of the message in memory in bytes, i.e., its inline size and the
+        /// size of the entries it carries as given by [`Entry::size_hint`].
+        pub(crate) fn size_hint(&self) -> usize {
+            std::mem::size_of::<Self>() + self.msg.payload_size()
+        }
+    }
+
+    impl<T> PaxosMsg<T>
+    where
+        T: Entry,
+    {
+        /// The approximate size of the message in memory in bytes, see
+        /// [`PaxosMessage::size_hint`].
+        pub(crate) fn size_hint(&self) -> usize {
+            std::mem::size_of::<Self>() + self.payload_size()
+        }
+
+        fn payload_size(&self) -> usize {
+            match self {
+                PaxosMsg::Promise(p) => p.log_sync.as_ref().map_or(0, LogSync::size_hint),
+                PaxosMsg::AcceptSync(a) => a.log_sync.size_hint(),
+                PaxosMsg::Takeover(t) => t.acc_sync.log_sync.size_hint(),
+                PaxosMsg::SyncPortion(s) => s.log_sync.size_hint(),
+                PaxosMsg::PullResponse(p) => p.log_sync.size_hint(),
+                PaxosMsg::BootstrapResponse(b) => b.log_sync.size_hint(),
+                PaxosMsg::BackupSnapshot(b) => b.log_sync.size_hint(),
+                #[cfg(not(feature = "unicache"))]
+                PaxosMsg::AcceptDecide(a) => entries_size(&a.entries),
+                #[cfg(not(feature = "unicache"))]
+                PaxosMsg::RelayAcceptDecide(r) => entries_size(&r.entries),
+                #[cfg(feature = "unicache")]
+                PaxosMsg::AcceptDecide(a) => std::mem::size_of_val(a.entries.as_slice()),
+                #[cfg(feature = "unicache")]
+                PaxosMsg::RelayAcceptDecide(r) => std::mem::size_of_val(r.entries.as_slice()),
+                PaxosMsg::ProposalForward(entries) => entries_size(entries),
+                PaxosMsg::TokenProposalForward(f) => {
+                    entries_size(&f.entries) + std::mem::size_of_val(f.origins.as_slice())
+                }
+                PaxosMsg::RejectedProposals(r) => r
+                    .proposals
+                    .iter()
+                    .map(|(e, token)| e.size_hint() + std::mem::size_of_val(token))
+                    .sum(),
+                PaxosMsg::WitnessReadResponse(r) => {
+                    r.witness.entry.as_ref().map_or(0, Entry::size_hint)
+                }
+                _ => 0,
+            }
+        }
+    }
+
+    fn entries_size<T: Entry>(entries: &[T]) -> usize {
+        entries.iter().map(|e| e.size_hint()).sum()
+    }
 }
 
 /// The different messages BLE uses to communicate with other servers.
 pub mod ballot_leader_election {
 
-    use crate::{ballot_leader_election::Ballot, util::NodeId};
+    use crate::{
+        ballot_leader_election::Ballot,
+        util::{ConfigFingerprint, Namespace, NodeId},
+    };
     #[cfg(feature = "serde")]
     use serde::{Deserialize, Serialize};
 
@@ -216,6 +597,16 @@ pub mod ballot_leader_election {
     pub struct HeartbeatRequest {
         /// Number of the current round.
         pub round: u32,
+        /// The configuration of the requesting server. Requests with a different configuration
+        /// than the receiver's are not replied to.
+        pub fingerprint: ConfigFingerprint,
+        /// The payload the application of the requesting server attached to its heartbeats.
+        #[cfg_attr(feature = "serde", serde(default))]
+        pub payload: Option<Vec<u8>>,
+        /// The compression the transport of the requesting server can decompress. Missing in the
+        /// requests of servers that don't negotiate compression.
+        #[cfg_attr(feature = "serde", serde(default))]
+        pub compression: Option<String>,
     }
 
     /// Replies
@@ -230,6 +621,19 @@ pub mod ballot_leader_election {
         pub leader: Ballot,
         /// Whether the replying server sees a need for a new leader
         pub happy: bool,
+        /// The number of servers, including itself, that the replying server received heartbeats
+        /// from in its last heartbeat round.
+        pub connectivity: usize,
+        /// Whether the servers the replying server is connected to form a quorum. Only such
+        /// servers are eligible as leaders.
+        pub quorum_connected: bool,
+        /// The payload the application of the replying server attached to its heartbeats.
+        #[cfg_attr(feature = "serde", serde(default))]
+        pub payload: Option<Vec<u8>>,
+        /// The compression the transport of the replying server can decompress. Missing in the
+        /// replies of servers that don't negotiate compression.
+        #[cfg_attr(feature = "serde", serde(default))]
+        pub compression: Option<String>,
     }
 
     /// A struct for a Paxos message that also includes sender and receiver.
@@ -240,6 +644,9 @@ pub mod ballot_leader_election {
         pub from: NodeId,
         /// Receiver of `msg`.
         pub to: NodeId,
+        /// The namespace of the cluster of the sender.
+        #[cfg_attr(feature = "serde", serde(default))]
+        pub namespace: Option<Namespace>,
         /// The message content.
         pub msg: HeartbeatMsg,
     }
@@ -276,4 +683,13 @@ where
             Message::BLE(b) => b.to,
         }
     }
+
+    /// Get the namespace of the cluster that sent the message. A process that hosts the clusters
+    /// of several tenants can use it to pass the message to the right `OmniPaxos` instance.
+    pub fn get_namespace(&self) -> Option<Namespace> {
+        match self {
+            Message::SequencePaxos(p) => p.namespace,
+            Message::BLE(b) => b.namespace,
+        }
+    }
 }
@@ -0,0 +1,107 @@
+use std::{fmt::Debug, sync::Arc};
+
+#[cfg(feature = "prometheus")]
+mod prometheus;
+#[cfg(feature = "statsd")]
+mod statsd;
+#[cfg(feature = "prometheus")]
+pub use prometheus::PrometheusSink;
+#[cfg(feature = "statsd")]
+pub use statsd::StatsdSink;
+
+/// Counter of the messages returned by the `outgoing_*` functions of a server.
+pub const MESSAGES_SENT: &str = "omnipaxos_messages_sent_total";
+/// Counter of the messages passed to `handle_incoming()` of a server.
+pub const MESSAGES_RECEIVED: &str = "omnipaxos_messages_received_total";
+/// Counter of the incoming messages a server rejected, e.g., because they came from a server that
+/// is not part of the cluster. See [`crate::OmniPaxos::take_misbehaving_peers`].
+pub const MESSAGES_REJECTED: &str = "omnipaxos_messages_rejected_total";
+/// Counter of the accept-phase messages a follower dropped because they were outdated, e.g.,
+/// duplicates or messages from a previous session with the leader.
+pub const MESSAGES_OUTDATED: &str = "omnipaxos_messages_outdated_total";
+/// Counter of the accept-phase messages a follower dropped because preceding messages from the
+/// leader were lost. Each of them makes the follower resynchronize with the leader.
+pub const MESSAGES_DROPPED_PRECEDING: &str = "omnipaxos_messages_dropped_preceding_total";
+/// Counter of the outdated accept-phase messages a follower had already received. They are also
+/// counted as [`MESSAGES_OUTDATED`].
+pub const MESSAGES_DUPLICATE: &str = "omnipaxos_messages_duplicate_total";
+/// Counter of the accept-phase messages that arrived ahead of preceding messages and were held by
+/// a follower until those arrived. See `reorder_window` in [`crate::ServerConfig`].
+pub const MESSAGES_REORDERED: &str = "omnipaxos_messages_reordered_total";
+/// Counter of the times the leader resynchronized a follower that was already synchronized in
+/// the same leadership, e.g., because messages to it were lost. See
+/// [`crate::OmniPaxos::get_sequence_recovery_stats`].
+pub const SESSION_RESETS: &str = "omnipaxos_session_resets_total";
+/// Counter of the fingerprints from the leader that differed from the decided entries of a
+/// follower. See [`crate::OmniPaxos::take_divergence_alarms`].
+pub const DIVERGENCE_ALARMS: &str = "omnipaxos_divergence_alarms_total";
+/// Counter of the times a server has observed a new leader. Checked on every call to `tick()`.
+pub const LEADER_CHANGES: &str = "omnipaxos_leader_changes_total";
+/// Counter of the clock jumps a server has detected. See [`crate::OmniPaxos::take_clock_jumps`].
+pub const CLOCK_JUMPS: &str = "omnipaxos_clock_jumps_total";
+/// Gauge of whether a server is the current leader (1) or not (0).
+pub const IS_LEADER: &str = "omnipaxos_is_leader";
+/// Gauge of the decided index of a server.
+pub const DECIDED_IDX: &str = "omnipaxos_decided_idx";
+/// Gauge of the accepted index of a server.
+pub const ACCEPTED_IDX: &str = "omnipaxos_accepted_idx";
+/// Gauge of the compacted index of a server.
+pub const COMPACTED_IDX: &str = "omnipaxos_compacted_idx";
+/// Gauge of the outgoing messages queued at a server that have not been taken by the user yet.
+pub const OUTGOING_MESSAGES: &str = "omnipaxos_outgoing_messages";
+/// Gauge of the proposals a server buffers until there is a leader to append or forward them to.
+pub const PENDING_PROPOSALS: &str = "omnipaxos_pending_proposals";
+/// Gauge of the largest number of entries the leader has accepted but a follower has not. Always 0
+/// at followers. See [`crate::OmniPaxos::get_outgoing_stats`] for the number of each follower.
+pub const MAX_IN_FLIGHT_ENTRIES: &str = "omnipaxos_max_in_flight_entries";
+/// Gauge of the approximate number of bytes occupied by the in-memory buffers of a server. See
+/// [`crate::OmniPaxos::get_memory_usage`].
+pub const MEMORY_USAGE: &str = "omnipaxos_memory_usage_bytes";
+/// Histogram of the commit latencies in seconds measured by the leader. See
+/// [`crate::OmniPaxos::get_commit_latency`].
+pub const COMMIT_LATENCY: &str = "omnipaxos_commit_latency_seconds";
+/// Histogram of the latencies in seconds from when a follower forwards proposals until they are
+/// decided in its log. See [`crate::OmniPaxos::get_proposal_affinity`].
+pub const FORWARD_LATENCY: &str = "omnipaxos_forward_latency_seconds";
+
+/// A destination for the metrics of an OmniPaxos server, e.g., an adapter to the metrics library
+/// the application already uses. The sink is called with one of the metric names defined in this
+/// module whenever the metric changes. The gauges are reported on every call to `tick()`.
+pub trait MetricsSink: Debug + Send + Sync {
+    /// Increments the counter `name` by `value`.
+    fn counter(&self, name: &'static str, value: u64);
+
+    /// Sets the gauge `name` to `value`.
+    fn gauge(&self, name: &'static str, value: f64);
+
+    /// Records `value` as an observation of the histogram `name`.
+    fn histogram(&self, name: &'static str, value: f64);
+}
+
+/// Reports metrics to the configured [`MetricsSink`], if there is one.
+#[derive(Clone, Debug, Default)]
+pub(crate) struct Metrics(Option<Arc<dyn MetricsSink>>);
+
+impl Metrics {
+    pub(crate) fn with(sink: Option<Arc<dyn MetricsSink>>) -> Self {
+        Self(sink)
+    }
+
+    pub(crate) fn counter(&self, name: &'static str, value: u64) {
+        if let Some(sink) = &self.0 {
+            sink.counter(name, value);
+        }
+    }
+
+    pub(crate) fn gauge(&self, name: &'static str, value: f64) {
+        if let Some(sink) = &self.0 {
+            sink.gauge(name, value);
+        }
+    }
+
+    pub(crate) fn histogram(&self, name: &'static str, value: f64) {
+        if let Some(sink) = &self.0 {
+            sink.histogram(name, value);
+        }
+    }
+}
@@ -0,0 +1,139 @@
+use super::MetricsSink;
+use std::{collections::BTreeMap, fmt::Write, sync::Mutex};
+
+/// The default upper bounds of the histogram buckets, the same as the Prometheus client libraries.
+const DEFAULT_BUCKETS: [f64; 11] = [
+    0.005, 0.01, 0.025, 0.05, 0.1, 0.25, 0.5, 1.0, 2.5, 5.0, 10.0,
+];
+
+#[derive(Debug)]
+enum Metric {
+    Counter(u64),
+    Gauge(f64),
+    Histogram {
+        /// The number of observations per bucket, not cumulative.
+        buckets: Vec<u64>,
+        sum: f64,
+        count: u64,
+    },
+}
+
+/// A [`MetricsSink`] that keeps the latest values of the metrics and renders them in the
+/// Prometheus text exposition format, e.g., to be served on the `/metrics` endpoint of the
+/// application.
+#[derive(Debug)]
+pub struct PrometheusSink {
+    labels: Vec<(String, String)>,
+    buckets: Vec<f64>,
+    metrics: Mutex<BTreeMap<&'static str, Metric>>,
+}
+
+impl PrometheusSink {
+    /// Creates a sink that adds `labels` to all metrics, e.g., the `pid` of the server.
+    pub fn with(labels: &[(&str, &str)]) -> Self {
+        Self {
+            labels: labels
+                .iter()
+                .map(|(name, value)| (name.to_string(), value.to_string()))
+                .collect(),
+            buckets: DEFAULT_BUCKETS.to_vec(),
+            metrics: Mutex::default(),
+        }
+    }
+
+    /// Sets the upper bounds of the histogram buckets. Must be sorted in increasing order.
+    pub fn with_buckets(mut self, buckets: Vec<f64>) -> Self {
+        self.buckets = buckets;
+        self
+    }
+
+    /// Renders all metrics that have been reported so far.
+    pub fn render(&self) -> String {
+        let metrics = self.metrics.lock().unwrap();
+        let mut out = String::new();
+        for (name, metric) in metrics.iter() {
+            match metric {
+                Metric::Counter(value) => {
+                    let _ = writeln!(out, "# TYPE {} counter", name);
+                    let _ = writeln!(out, "{}{} {}", name, self.render_labels(None), value);
+                }
+                Metric::Gauge(value) => {
+                    let _ = writeln!(out, "# TYPE {} gauge", name);
+                    let _ = writeln!(out, "{}{} {}", name, self.render_labels(None), value);
+                }
+                Metric::Histogram {
+                    buckets,
+                    sum,
+                    count,
+                } => {
+                    let _ = writeln!(out, "# TYPE {} histogram", name);
+                    let mut cumulative = 0;
+                    for (bound, observations) in self.buckets.iter().zip(buckets) {
+                        cumulative += observations;
+                        let le = self.render_labels(Some(&bound.to_string()));
+                        let _ = writeln!(out, "{}_bucket{} {}", name, le, cumulative);
+                    }
+                    let le = self.render_labels(Some("+Inf"));
+                    let _ = writeln!(out, "{}_bucket{} {}", name, le, count);
+                    let labels = self.render_labels(None);
+                    let _ = writeln!(out, "{}_sum{} {}", name, labels, sum);
+                    let _ = writeln!(out, "{}_count{} {}", name, labels, count);
+                }
+            }
+        }
+        out
+    }
+
+    fn render_labels(&self, le: Option<&str>) -> String {
+        let mut labels: Vec<String> = self
+            .labels
+            .iter()
+            .map(|(name, value)| format!("{}=\"{}\"", name, value))
+            .collect();
+        if let Some(le) = le {
+            labels.push(format!("le=\"{}\"", le));
+        }
+        match labels.is_empty() {
+            true => String::new(),
+            false => format!("{{{}}}", labels.join(",")),
+        }
+    }
+}
+
+impl MetricsSink for PrometheusSink {
+    fn counter(&self, name: &'static str, value: u64) {
+        let mut metrics = self.metrics.lock().unwrap();
+        match metrics.entry(name).or_insert(Metric::Counter(0)) {
+            Metric::Counter(total) => *total += value,
+            other => *other = Metric::Counter(value),
+        }
+    }
+
+    fn gauge(&self, name: &'static str, value: f64) {
+        self.metrics
+            .lock()
+            .unwrap()
+            .insert(name, Metric::Gauge(value));
+    }
+
+    fn histogram(&self, name: &'static str, value: f64) {
+        let mut metrics = self.metrics.lock().unwrap();
+        let metric = metrics.entry(name).or_insert_with(|| Metric::Histogram {
+            buckets: vec![0; self.buckets.len()],
+            sum: 0.0,
+            count: 0,
+        });
+        if let Metric::Histogram {
+            buckets,
+            sum,
+            count,
+        } = metric
+        {
+            if let Some(i) = self.buckets.iter().position(|bound| value <= *bound) {
+                buckets[i] += 1;
+            }
+            *sum += value;
+            *count += 1;
+        }
+    }
+}
@@ -0,0 +1,46 @@
+use super::MetricsSink;
+use std::{
+    io,
+    net::{ToSocketAddrs, UdpSocket},
+};
+
+/// A [`MetricsSink`] that sends the metrics to a StatsD server over UDP. The metrics are sent as
+/// they are reported, without aggregation, and lost packets are not retried.
+#[derive(Debug)]
+pub struct StatsdSink {
+    socket: UdpSocket,
+    prefix: String,
+}
+
+impl StatsdSink {
+    /// Creates a sink that sends the metrics to the StatsD server at `addr`. The names of the
+    /// metrics are prefixed with `prefix`, e.g., `"node1."`.
+    pub fn with<A: ToSocketAddrs>(addr: A, prefix: &str) -> io::Result<Self> {
+        let socket = UdpSocket::bind(("0.0.0.0", 0))?;
+        socket.connect(addr)?;
+        Ok(Self {
+            socket,
+            prefix: prefix.to_string(),
+        })
+    }
+
+    fn send(&self, name: &str, value: &str, metric_type: &str) {
+        let line = format!("{}{}:{}|{}", self.prefix, name, value, metric_type);
+        // Metrics are best effort and must not affect the server.
+        let _ = self.socket.send(line.as_bytes());
+    }
+}
+
+impl MetricsSink for StatsdSink {
+    fn counter(&self, name: &'static str, value: u64) {
+        self.send(name, &value.to_string(), "c");
+    }
+
+    fn gauge(&self, name: &'static str, value: f64) {
+        self.send(name, &value.to_string(), "g");
+    }
+
+    fn histogram(&self, name: &'static str, value: f64) {
+        self.send(name, &value.to_string(), "h");
+    }
+}
@@ -1,14 +1,43 @@
+#[cfg(feature = "pipeline_events")]
+use crate::pipeline::PipelineEvent;
+#[cfg(feature = "snapshot_sealing")]
+use crate::sealing::SnapshotSealer;
+#[cfg(feature = "spill")]
+use crate::spill::{self, SpillCodec, SpillFile};
 use crate::{
+    audit::{AuditAction, AuditLog, AuditRecord},
     ballot_leader_election::{Ballot, BallotLeaderElection},
-    errors::{valid_config, ConfigError},
-    messages::Message,
-    sequence_paxos::SequencePaxos,
-    storage::{Entry, StopSign, Storage},
+    chunking::{self, Chunkable},
+    errors::{
+        valid_config, BuildErr, CompactionErr, ConfigError, FatalErr, HandoffErr, OrFatal,
+        ProposeErr, ProtocolError, ReadErr, ReconfigErr, StandbyErr, StorageErr,
+        StorageMigrationErr,
+    },
+    handoff::{HandoffRef, HandoffSender},
+    messages::{ballot_leader_election::BLEMessage, Message},
+    metrics::{self, Metrics, MetricsSink},
+    reader::OmniPaxosReader,
+    sequence_paxos::{Role, SequencePaxos},
+    settings::{self, ReplicatedSettings, SettingsEntry},
+    shipping::{DecideHook, DecideHookConfig, DecideShipper},
+    storage::{
+        archive::ArchiveCodec, use_snapshots, Entry, PinGuard, SnapshotViolation, StopSign,
+        Storage, StorageResult, TrimWorker, VerificationReport,
+    },
+    tuning::{self, TuningReport},
     util::{
-        defaults::{BUFFER_SIZE, ELECTION_TIMEOUT, FLUSH_BATCH_TIMEOUT, RESEND_MESSAGE_TIMEOUT},
-        ConfigurationId, FlexibleQuorum, LogEntry, LogicalClock, NodeId,
+        self,
+        defaults::{
+            BUFFER_SIZE, COMPACTION_TIMEOUT, DECIDED_CACHE_SIZE, ELECTION_TIMEOUT,
+            FLUSH_BATCH_TIMEOUT, FORWARD_BATCH_TIMEOUT, MISBEHAVING_PEER_THRESHOLD,
+            RESEND_MESSAGE_TIMEOUT, SLOW_STORAGE_TIMEOUT, SYNC_PREFETCH, VERIFICATION_BATCH_SIZE,
+        },
+        ClockJumpDetector, ConfigFingerprint, ConfigurationId, FlexibleQuorum, IndexedLogEntry,
+        LogEntry, LogEntryRef, LogicalClock, Namespace, NodeId, OriginToken, Region,
+        SlowStorageDetector,
     },
     utils::{ui, ui::ClusterState},
+    witness::WitnessReport,
 };
 #[cfg(any(feature = "toml_config", feature = "serde"))]
 use serde::Deserialize;
@@ -17,10 +46,15 @@ use serde::Serialize;
 #[cfg(feature = "toml_config")]
 use std::fs;
 use std::{
+    collections::HashMap,
     error::Error,
     fmt::{Debug, Display},
-    ops::RangeBounds,
+    ops::{Bound, RangeBounds},
+    sync::Arc,
+    time::{Duration, SystemTime},
 };
+#[cfg(feature = "spill")]
+use std::{io, path::Path};
 #[cfg(feature = "toml_config")]
 use toml;
 
@@ -42,8 +76,12 @@ impl OmniPaxosConfig {
         self.cluster_config.validate()?;
         self.server_config.validate()?;
         valid_config!(
-            self.cluster_config.nodes.contains(&self.server_config.pid),
-            "Nodes must include own server pid"
+            self.cluster_config.nodes.contains(&self.server_config.pid)
+                || self
+                    .cluster_config
+                    .backup_nodes
+                    .contains(&self.server_config.pid),
+            "Nodes or backup nodes must include own server pid"
         );
         Ok(())
     }
@@ -57,8 +95,9 @@ impl OmniPaxosConfig {
         Ok(config)
     }
 
-    /// Checks all configuration fields and returns the local OmniPaxos node if successful.
-    pub fn build<T, B>(self, storage: B) -> Result<OmniPaxos<T, B>, ConfigError>
+    /// Checks all configuration fields and returns the local OmniPaxos node if successful. Fails
+    /// with [`BuildErr::Storage`] if the state of the server can't be recovered from `storage`.
+    pub fn build<T, B>(self, storage: B) -> Result<OmniPaxos<T, B>, BuildErr>
     where
         T: Entry,
         B: Storage<T>,
@@ -67,16 +106,53 @@ impl OmniPaxosConfig {
         // Use stored ballot as initial BLE leader
         let recovered_leader = storage
             .get_promise()
-            .expect("storage error while trying to read promise");
-        Ok(OmniPaxos {
-            ble: BallotLeaderElection::with(self.clone().into(), recovered_leader),
+            .or_fatal("storage error while trying to read promise")?;
+        let recovered_ballot = storage
+            .get_ble_ballot()
+            .or_fatal("storage error while trying to read BLE ballot")?;
+        let mut op = OmniPaxos {
+            ble: BallotLeaderElection::with(
+                self.clone().into(),
+                recovered_leader,
+                recovered_ballot,
+            ),
             election_clock: LogicalClock::with(self.server_config.election_tick_timeout),
             resend_message_clock: LogicalClock::with(
                 self.server_config.resend_message_tick_timeout,
             ),
             flush_batch_clock: LogicalClock::with(self.server_config.flush_batch_tick_timeout),
-            seq_paxos: SequencePaxos::with(self.into(), storage),
-        })
+            forward_batch_clock: LogicalClock::with(self.server_config.forward_batch_tick_timeout),
+            pull_clock: self.server_config.pull_tick_timeout.map(LogicalClock::with),
+            verification_clock: self
+                .server_config
+                .verification_tick_timeout
+                .map(LogicalClock::with),
+            fingerprint_clock: self
+                .server_config
+                .fingerprint_tick_timeout
+                .map(LogicalClock::with),
+            backup_clock: self
+                .server_config
+                .backup_tick_timeout
+                .map(LogicalClock::with),
+            compaction_clock: LogicalClock::with(self.server_config.compaction_tick_timeout),
+            slow_storage: SlowStorageDetector::with(
+                self.server_config.slow_storage_latency,
+                self.server_config.slow_storage_tick_timeout,
+                self.server_config.step_down_on_slow_storage,
+            ),
+            clock_jumps: ClockJumpDetector::with(self.server_config.clock_jump_threshold),
+            chunk_group: None,
+            metrics: Metrics::with(self.server_config.metrics_sink.clone()),
+            metrics_leader: Ballot::default(),
+            audit_log: AuditLog::default(),
+            startup_reconciliation: None,
+            decide_shipper: None,
+            seq_paxos: SequencePaxos::with(self.into(), storage)?,
+        };
+        op.reconcile_startup_state()?;
+        op.persist_ble_ballot()?;
+        Ok(op)
     }
 }
 
@@ -85,6 +161,10 @@ impl OmniPaxosConfig {
 /// * `configuration_id`: The identifier for the cluster configuration that this OmniPaxos server is part of.
 /// * `nodes`: The nodes in the cluster i.e. the `pid`s of the other servers in the configuration.
 /// * `flexible_quorum` : Defines read and write quorum sizes. Can be used for different latency vs fault tolerance tradeoffs.
+/// * `regions`: The regions the nodes are located in. Used to relay entries to remote regions.
+/// * `hierarchical_quorum`: If true, quorums consist of a majority of the nodes in each of a majority of the `regions`.
+/// * `backup_nodes`: Servers that only receive snapshots of the decided log from the leader.
+/// * `namespace`: The tenant this cluster belongs to. Messages from other namespaces are rejected.
 #[derive(Clone, Debug, PartialEq, Default)]
 #[cfg_attr(any(feature = "serde", feature = "toml_config"), derive(Deserialize))]
 #[cfg_attr(feature = "toml_config", serde(default))]
@@ -97,6 +177,19 @@ pub struct ClusterConfig {
     pub nodes: Vec<NodeId>,
     /// Defines read and write quorum sizes. Can be used for different latency vs fault tolerance tradeoffs.
     pub flexible_quorum: Option<FlexibleQuorum>,
+    /// The regions the nodes are located in. A node can be part of at most one region.
+    pub regions: Vec<Region>,
+    /// If true, quorums consist of a majority of the nodes in each of a majority of the `regions`
+    /// instead of a majority of all nodes. Requires every node to be part of a region.
+    pub hierarchical_quorum: bool,
+    /// Servers that are not part of the cluster but receive snapshots of the decided log from the
+    /// leader every `backup_tick_timeout` ticks, e.g., off-site backups. They neither take part in
+    /// the leader election nor in quorums. Requires the entries to support snapshots.
+    pub backup_nodes: Vec<NodeId>,
+    /// The tenant this cluster belongs to, which is sent with every message. Messages from another
+    /// namespace are rejected, so that the clusters of several tenants can share connections, e.g.,
+    /// with overlapping pids and configuration ids, without cross-talk.
+    pub namespace: Option<Namespace>,
 }
 
 impl ClusterConfig {
@@ -127,6 +220,52 @@ impl ClusterConfig {
                 "Read quorum size must be >= the write quorum size."
             );
         }
+        #[cfg(feature = "no_snapshots")]
+        valid_config!(
+            self.backup_nodes.is_empty(),
+            "Backup nodes require snapshots, which the no_snapshots feature disables"
+        );
+        for pid in &self.backup_nodes {
+            valid_config!(*pid != 0, "Backup node pid cannot be 0");
+            valid_config!(
+                !self.nodes.contains(pid),
+                "Backup nodes cannot be part of the cluster nodes"
+            );
+        }
+        let mut region_nodes = vec![];
+        for region in &self.regions {
+            for pid in &region.nodes {
+                valid_config!(
+                    self.nodes.contains(pid),
+                    "Region nodes must be part of the cluster"
+                );
+                valid_config!(
+                    !region_nodes.contains(pid),
+                    "A node cannot be part of multiple regions"
+                );
+                region_nodes.push(*pid);
+            }
+            if let Some(relay) = region.relay {
+                valid_config!(
+                    region.nodes.contains(&relay),
+                    "The relay of a region must be one of its nodes"
+                );
+            }
+        }
+        if self.hierarchical_quorum {
+            valid_config!(
+                self.flexible_quorum.is_none(),
+                "Hierarchical quorums cannot be combined with flexible quorums"
+            );
+            valid_config!(
+                region_nodes.len() == num_nodes,
+                "Hierarchical quorums require every node to be part of a region"
+            );
+            valid_config!(
+                self.regions.iter().all(|r| !r.nodes.is_empty()),
+                "Hierarchical quorums require every region to have at least one node"
+            );
+        }
         Ok(())
     }
 
@@ -136,7 +275,7 @@ impl ClusterConfig {
         self,
         server_config: ServerConfig,
         with_storage: B,
-    ) -> Result<OmniPaxos<T, B>, ConfigError>
+    ) -> Result<OmniPaxos<T, B>, BuildErr>
     where
         T: Entry,
         B: Storage<T>,
@@ -157,8 +296,45 @@ impl ClusterConfig {
 /// * `resend_message_tick_timeout`: The number of calls to `tick()` before a message is considered dropped and thus resent. Must not be 0.
 /// * `buffer_size`: The buffer size for outgoing messages.
 /// * `batch_size`: The size of the buffer for log batching. The default is 1, which means no batching.
+/// * `batch_byte_size`: If not 0, the log batch is also flushed once its entries are `batch_byte_size` bytes large.
+/// * `forward_batch_size`: The number of proposals a follower batches before forwarding them to the leader. The default is 1, which means no batching.
+/// * `forward_batch_byte_size`: If not 0, the maximum number of bytes of proposals forwarded to the leader in a single message.
+/// * `forward_batch_tick_timeout`: The number of calls to `tick()` before the batched proposals are forwarded to the leader. Must not be 0.
+/// * `decided_cache_size`: The number of entries at the tail of the log, decided or not, kept in memory to serve reads without accessing storage.
+/// * `sync_batch_size`: The maximum number of log entries sent at once when synchronizing a follower that is behind.
+/// * `sync_batch_byte_size`: If not 0, the maximum number of bytes of log entries sent at once when synchronizing a follower that is behind.
+/// * `sync_prefetch`: The number of log portions a follower requests ahead while catching up.
+/// * `max_sync_suffix_entries`: If set, a follower whose log diverges from the leader's by more than `max_sync_suffix_entries` entries is synchronized with a snapshot instead of the entries.
+/// * `pull_tick_timeout`: If set, this server pulls new log entries from the leader every `pull_tick_timeout` ticks instead of the leader pushing them.
+/// * `verification_tick_timeout`: If set, a batch of the stored log is re-read and verified every `verification_tick_timeout` ticks.
+/// * `verification_batch_size`: The number of log entries verified at every `verification_tick_timeout`.
+/// * `fingerprint_tick_timeout`: If set, the leader sends a fingerprint of the newly decided entries to the followers every `fingerprint_tick_timeout` ticks to detect diverged logs.
+/// * `slow_follower_lag`: If set, the leader reports followers that are more than `slow_follower_lag` entries behind it.
+/// * `slow_follower_tick_timeout`: If set, the leader reports followers that take more than `slow_follower_tick_timeout` ticks to catch up.
+/// * `backup_tick_timeout`: If set, the leader sends snapshots to the `backup_nodes` every `backup_tick_timeout` ticks.
+/// * `slow_storage_latency`: If set, the leader reports its storage as slow once its appends take longer than `slow_storage_latency` for `slow_storage_tick_timeout` ticks.
+/// * `slow_storage_tick_timeout`: The number of ticks the appends of the leader must be slow before its storage is reported. Must not be 0.
+/// * `step_down_on_slow_storage`: Whether the leader steps down once its storage is reported as slow.
+/// * `storage_size_limit`: If set, the number of bytes the storage may occupy before `low_space_policy` is applied.
+/// * `low_space_policy`: What this server does when its storage exceeds `storage_size_limit`.
+/// * `follower_append_policy`: What this server does with the entries appended to it while it is not the leader.
+/// * `max_entry_size`: If set, the maximum size in bytes of an entry appended to or forwarded to this server.
+/// * `memory_limit`: If set, the approximate number of bytes the buffers of this server may occupy before it sheds load.
+/// * `reorder_window`: The number of preceding messages from the leader that an incoming message may overtake before the follower resynchronizes.
+/// * `compaction_tick_timeout`: The number of calls to `tick()` between checks of the `storage_size_limit` and `snapshot_interval`. Must not be 0.
+/// * `trim_mode`: When the compacted entries are removed from the storage.
+/// * `check_snapshots`: Whether the `Snapshot` implementation is checked whenever snapshots are created. Enabled by default in debug builds.
+/// * `snapshot_batch_size`: If not 0, the maximum number of log entries read into memory at once when creating a snapshot.
+/// * `track_entry_ballots`: Whether this server keeps track of the ballot in which each log entry was first appended.
+/// * `snapshot_interval`: If set, this server snapshots its decided log every `snapshot_interval` entries, staggered with the other servers.
+/// * `misbehaving_peer_threshold`: The number of rejected messages from a peer after which it is reported as misbehaving. Must not be 0.
 /// * `logger_file_path`: The path where the default logger logs events.
 /// * `leader_priority` : Custom priority for this node to be elected as the leader.
+/// * `compression`: The compression the transport of this node can decompress, which is negotiated with the other servers.
+/// * `clock_jump_threshold`: If set, gaps between the calls to `tick()` and jumps of the wall clock longer than this make the server distrust its timing until a fresh heartbeat round has completed.
+/// * `reconfiguration_validator`: Check that a proposed reconfiguration must pass before it is accepted.
+/// * `metrics_sink`: If set, the metrics of this server are reported to it.
+/// * `snapshot_sealer`: If set, the snapshots sent to and received from other servers are encrypted and signed with it.
 #[derive(Clone, Debug)]
 #[cfg_attr(feature = "toml_config", derive(Deserialize), serde(default))]
 pub struct ServerConfig {
@@ -172,10 +348,189 @@ pub struct ServerConfig {
     pub buffer_size: usize,
     /// The size of the buffer for log batching. The default is 1, which means no batching.
     pub batch_size: usize,
+    /// The approximate size in bytes, as given by [`Entry::size_hint`], at which the batched log
+    /// entries are flushed even if there are fewer than `batch_size` of them. Set to 0 to only
+    /// limit the batches by `batch_size`.
+    pub batch_byte_size: usize,
     /// The number of calls to `tick()` before the batched log entries are flushed.
     pub flush_batch_tick_timeout: u64,
+    /// The number of proposals appended on this server while it is a follower that are batched
+    /// before they are forwarded to the leader in a single message, which saves the leader from
+    /// handling a message per proposal when many clients write through the followers. The default
+    /// is 1, which means no batching. Must not be 0.
+    pub forward_batch_size: usize,
+    /// The maximum approximate size in bytes, as given by [`Entry::size_hint`], of the proposals
+    /// forwarded to the leader in a single message. A batch is forwarded early instead of growing
+    /// beyond it, but the chunks of a single `append_chunked()` are never split. Set to 0 to only
+    /// limit the batches by `forward_batch_size`.
+    pub forward_batch_byte_size: usize,
+    /// The number of calls to `tick()` before the batched proposals are forwarded to the leader,
+    /// even if there are fewer than `forward_batch_size` of them. Must not be 0.
+    pub forward_batch_tick_timeout: u64,
+    /// The number of recent log entries kept in memory so that reads of the decided suffix (e.g.,
+    /// `read_decided_suffix()` called every tick) do not hit the storage. Set to 0 to disable.
+    pub decided_cache_size: usize,
+    /// The maximum number of log entries the leader sends in a single message when synchronizing
+    /// a follower. A follower that is further behind fetches the rest of the log in portions of
+    /// this size. Set to 0 to always synchronize with a single message.
+    pub sync_batch_size: usize,
+    /// The maximum approximate size in bytes, as given by [`Entry::size_hint`], of the log entries
+    /// the leader sends in a single message when synchronizing a follower. A message holds at
+    /// least one entry, even if it is larger. Set to 0 to only limit the messages by
+    /// `sync_batch_size`.
+    pub sync_batch_byte_size: usize,
+    /// The number of log portions a follower requests ahead of the one it is currently applying
+    /// while catching up with the leader. Must not be 0.
+    pub sync_prefetch: usize,
+    /// If set, the leader synchronizes a follower whose log diverges from its own by more than
+    /// `max_sync_suffix_entries` entries with a snapshot of the decided entries, even if it still
+    /// has the entries, which bounds the volume of the synchronization and the entries the leader
+    /// reads for it after a long outage. A follower that diverges by fewer entries is synchronized
+    /// with the entries, unless they are compacted. If not set, the decided entries are always
+    /// sent as a snapshot, except to followers in pull mode, which receive the entries unless they
+    /// are compacted. Has no effect if the entries are not snapshotted, see
+    /// [`Snapshot::use_snapshots`](crate::storage::Snapshot::use_snapshots).
+    pub max_sync_suffix_entries: Option<usize>,
+    /// If set, this server is replicated in pull mode: while following a leader, it requests the
+    /// entries after its accepted index every `pull_tick_timeout` calls to `tick()` and the leader
+    /// stops pushing new entries to it. Useful when the leader can't initiate connections to this
+    /// server, e.g., because it is behind a NAT or firewall. Must not be 0.
+    pub pull_tick_timeout: Option<u64>,
+    /// If set, the stored log is verified in the background to detect corruption before it is
+    /// needed, e.g., to synchronize another server. Every `verification_tick_timeout` calls to
+    /// `tick()`, the next `verification_batch_size` entries are re-read from the storage and
+    /// verified, and the stored snapshot is verified whenever a pass over the log starts. The
+    /// results can be polled with `get_verification_report()`. Must not be 0.
+    pub verification_tick_timeout: Option<u64>,
+    /// The number of log entries verified at every `verification_tick_timeout`. Must not be 0.
+    pub verification_batch_size: usize,
+    /// If set, the decided logs of the servers are continuously compared to detect a violation of
+    /// safety, e.g., because of a bug or a corrupted storage. Every `fingerprint_tick_timeout`
+    /// calls to `tick()`, the leader sends the followers a fingerprint of the entries it decided
+    /// since its previous fingerprint, and each follower compares it with the fingerprint of the
+    /// same entries in its own log. The mismatches can be polled with `take_divergence_alarms()`.
+    /// Requires the entries to implement [`Entry::fingerprint`]. Must not be 0.
+    pub fingerprint_tick_timeout: Option<u64>,
+    /// If set, the leader reports a follower as slow once its accepted index is more than
+    /// `slow_follower_lag` entries behind the leader's. The reports can be polled with
+    /// `take_slow_follower_events()`.
+    pub slow_follower_lag: Option<usize>,
+    /// If set, the leader reports a follower as slow once it has taken `slow_follower_tick_timeout`
+    /// calls to `tick()` without catching up to the entries the leader had accepted when the
+    /// follower fell behind. Must not be 0.
+    pub slow_follower_tick_timeout: Option<u64>,
+    /// If set and this server is the leader, it sends a snapshot of the decided log to every backup
+    /// node that is behind every `backup_tick_timeout` calls to `tick()`. Must not be 0.
+    pub backup_tick_timeout: Option<u64>,
+    /// If set, the leader reports its storage as slow once appending the accepted entries to it
+    /// has taken longer than `slow_storage_latency` on average in `slow_storage_tick_timeout`
+    /// consecutive calls to `tick()`. The reports can be polled with `take_slow_storage_events()`.
+    pub slow_storage_latency: Option<Duration>,
+    /// The number of consecutive calls to `tick()` in which the appends of the leader must be
+    /// slow before its storage is reported. The calls without appends are not counted. Must not
+    /// be 0.
+    pub slow_storage_tick_timeout: u64,
+    /// Whether the leader steps down once its storage is reported as slow, so that a server with
+    /// a healthier storage takes over instead of the slow storage delaying every decision. A
+    /// server steps down at most once until its storage is fast again, so that it does not keep
+    /// giving up its leadership if it is elected again, e.g., because all storages are slow.
+    pub step_down_on_slow_storage: bool,
+    /// If set, `low_space_policy` is applied once `LogStorage::approximate_size()` exceeds
+    /// `storage_size_limit` bytes, e.g., to stop appending before the disk is full. The size is
+    /// checked every `compaction_tick_timeout` calls to `tick()`. Must not be 0.
+    pub storage_size_limit: Option<u64>,
+    /// What this server does when its storage exceeds `storage_size_limit`.
+    pub low_space_policy: LowSpacePolicy,
+    /// What this server does with the entries appended to it while it is not the leader. By
+    /// default, they are forwarded to the leader.
+    pub follower_append_policy: FollowerAppendPolicy,
+    /// If set, the maximum approximate size in bytes, as given by [`Entry::size_hint`], of a
+    /// single log entry. Larger entries are refused by `append()` with
+    /// [`ProposeErr::EntryTooLarge`], and dropped with
+    /// [`ProposalRejectionCause::EntryTooLarge`] if they are forwarded to this server as the
+    /// leader, so that they can't break the network or storage of the cluster. Must not be 0.
+    pub max_entry_size: Option<usize>,
+    /// If set, the approximate number of bytes that the in-memory buffers of this server may
+    /// occupy, as reported by [`OmniPaxos::get_memory_usage`]. The usage is checked on every call
+    /// to `tick()`. Once it exceeds the limit, the cache of the log tail is dropped and, if that is
+    /// not enough, appends are refused with [`ProposeErr::MemoryLimitExceeded`] and forwarded
+    /// proposals are dropped with [`ProposalRejectionCause::MemoryLimitExceeded`] until the usage
+    /// is below the limit again. Must not be 0.
+    pub memory_limit: Option<usize>,
+    /// The number of preceding messages from the leader that an accept-phase message may overtake.
+    /// Such a message is held until the messages before it arrive, instead of making this server
+    /// resynchronize with the leader as if they were lost. If the gap is not filled within two
+    /// `resend_message_tick_timeout`s, the server resynchronizes anyway. Set it if the network
+    /// occasionally reorders messages. By default 0, i.e., no reordering is tolerated.
+    pub reorder_window: usize,
+    /// If set, this server automatically snapshots its decided log every `snapshot_interval`
+    /// entries. The snapshot indexes of the servers are staggered by their position in the
+    /// cluster's `nodes`, so that the servers don't all stall on a snapshot at the same time. Has no
+    /// effect if the entry type does not use snapshots. Must not be 0.
+    pub snapshot_interval: Option<usize>,
+    /// The number of calls to `tick()` before this server checks whether its storage exceeds the
+    /// `storage_size_limit` and whether it is scheduled to snapshot its log. Must not be 0.
+    pub compaction_tick_timeout: u64,
+    /// When the entries that are trimmed or snapshotted are removed from the storage. By default,
+    /// they are removed by `trim()` and `snapshot()` themselves. Removing a large part of the log
+    /// can be slow, e.g., a range delete in RocksDB, so it can instead be deferred to the
+    /// compaction timer or to a [`TrimWorker`] run by the application.
+    pub trim_mode: TrimMode,
+    /// Whether this server checks that its [`Snapshot`](crate::storage::Snapshot) implementation
+    /// is consistent whenever it creates a snapshot from log entries it still has: merging the
+    /// snapshot of a prefix of the entries with the snapshot of the rest must be equivalent to the
+    /// snapshot of all entries. Otherwise, replicas that apply a delta snapshot silently diverge
+    /// from the ones that create the snapshot themselves. The violations can be taken with
+    /// [`OmniPaxos::take_snapshot_violations`]. Requires [`Snapshot::is_equivalent`](crate::storage::Snapshot::is_equivalent)
+    /// to be implemented and re-creates snapshots, so it is only enabled by default in debug builds.
+    pub check_snapshots: bool,
+    /// The maximum number of log entries that are read into memory at once when creating a
+    /// snapshot, e.g., when snapshotting a long log or merging a delta snapshot while catching up.
+    /// The snapshot is built by merging the snapshots of the batches, and when it compacts the log
+    /// of this server, the intermediate snapshot is persisted after every batch so that only it and
+    /// one batch of entries are held in memory. Set to 0 to read all entries at once.
+    pub snapshot_batch_size: usize,
+    /// Whether this server keeps track of the ballot in which each entry was first appended to the
+    /// log, i.e., which leader wrote it, similar to the term of an entry in Raft. The ballots can
+    /// be read with [`OmniPaxos::get_entry_ballot`] and are sent along when synchronizing the log
+    /// of another server. They are only kept in memory, so the ballots of the entries appended
+    /// before this server was restarted are unknown.
+    pub track_entry_ballots: bool,
+    /// Incoming messages that could corrupt the state of this server, e.g., from a server that is
+    /// not part of the cluster, are rejected and counted per sender. A sender is reported as a
+    /// [`MisbehavingPeer`] every time the number of its rejected messages reaches a multiple of
+    /// `misbehaving_peer_threshold`. Must not be 0.
+    pub misbehaving_peer_threshold: u64,
     /// Custom priority for this node to be elected as the leader.
     pub leader_priority: u32,
+    /// If set, the name of the compression that the transport of this server can decompress, e.g.,
+    /// `"zstd"`. It is advertised to the other servers with the heartbeats, so that the transports
+    /// only compress the messages to the servers that advertised the same compression, see
+    /// [`OmniPaxos::peer_accepts_compression`]. Servers that don't advertise a compression, e.g.,
+    /// of an older version, keep receiving uncompressed messages.
+    pub compression: Option<String>,
+    /// If set, the server compares the monotonic and the wall clock on every call to `tick()` and
+    /// detects a clock jump when `tick()` was not called for longer than `clock_jump_threshold`,
+    /// e.g., because the process or the VM was paused, or when the wall clock moved more than
+    /// `clock_jump_threshold` further or less than the monotonic clock, e.g., after a hibernation.
+    /// See [`OmniPaxos::take_clock_jumps`]. Should be several times the period of `tick()`. Must
+    /// not be 0.
+    pub clock_jump_threshold: Option<Duration>,
+    /// If set, a proposed reconfiguration is only accepted if it passes this check. The check is
+    /// run by the proposing server and by the leader before it accepts the `StopSign`, so it should
+    /// be set to the same function on all servers of the cluster.
+    #[cfg_attr(feature = "toml_config", serde(skip_deserializing))]
+    pub reconfiguration_validator: Option<ReconfigurationValidator>,
+    /// If set, the metrics of this server, e.g., its decided index and the commit latencies, are
+    /// reported to this sink. See [`crate::metrics`] for the reported metrics.
+    #[cfg_attr(feature = "toml_config", serde(skip_deserializing))]
+    pub metrics_sink: Option<Arc<dyn MetricsSink>>,
+    /// If set, the snapshots this server sends to other servers are encrypted and signed with this
+    /// sealer, and the snapshots it receives are only installed if they were sealed with it. Must
+    /// be set on all servers of the cluster. See [`crate::sealing::SnapshotSealer`].
+    #[cfg(feature = "snapshot_sealing")]
+    #[cfg_attr(feature = "toml_config", serde(skip_deserializing))]
+    pub snapshot_sealer: Option<Arc<dyn SnapshotSealer>>,
     /// The path where the default logger logs events.
     #[cfg(feature = "logging")]
     pub logger_file_path: Option<String>,
@@ -191,6 +546,78 @@ impl ServerConfig {
         valid_config!(self.pid != 0, "Server pid cannot be 0");
         valid_config!(self.buffer_size != 0, "Buffer size must be greater than 0");
         valid_config!(self.batch_size != 0, "Batch size must be greater than 0");
+        valid_config!(
+            self.forward_batch_size != 0,
+            "Forward batch size must be greater than 0"
+        );
+        valid_config!(
+            self.forward_batch_tick_timeout != 0,
+            "Forward batch tick timeout must be greater than 0"
+        );
+        valid_config!(
+            self.sync_prefetch != 0,
+            "Sync prefetch must be greater than 0"
+        );
+        valid_config!(
+            self.pull_tick_timeout != Some(0),
+            "Pull tick timeout must be greater than 0"
+        );
+        valid_config!(
+            self.verification_tick_timeout != Some(0),
+            "Verification tick timeout must be greater than 0"
+        );
+        valid_config!(
+            self.verification_batch_size != 0,
+            "Verification batch size must be greater than 0"
+        );
+        valid_config!(
+            self.fingerprint_tick_timeout != Some(0),
+            "Fingerprint tick timeout must be greater than 0"
+        );
+        valid_config!(
+            self.slow_follower_tick_timeout != Some(0),
+            "Slow follower tick timeout must be greater than 0"
+        );
+        valid_config!(
+            self.backup_tick_timeout != Some(0),
+            "Backup tick timeout must be greater than 0"
+        );
+        valid_config!(
+            self.slow_storage_tick_timeout != 0,
+            "Slow storage tick timeout must be greater than 0"
+        );
+        valid_config!(
+            self.clock_jump_threshold != Some(Duration::ZERO),
+            "Clock jump threshold must be greater than 0"
+        );
+        valid_config!(
+            self.storage_size_limit != Some(0),
+            "Storage size limit must be greater than 0"
+        );
+        valid_config!(
+            self.follower_append_policy != FollowerAppendPolicy::Buffer { tick_timeout: 0 },
+            "Follower append tick timeout must be greater than 0"
+        );
+        valid_config!(
+            self.max_entry_size != Some(0),
+            "Max entry size must be greater than 0"
+        );
+        valid_config!(
+            self.memory_limit != Some(0),
+            "Memory limit must be greater than 0"
+        );
+        valid_config!(
+            self.snapshot_interval != Some(0),
+            "Snapshot interval must be greater than 0"
+        );
+        valid_config!(
+            self.compaction_tick_timeout != 0,
+            "Compaction tick timeout must be greater than 0"
+        );
+        valid_config!(
+            self.misbehaving_peer_threshold != 0,
+            "Misbehaving peer threshold must be greater than 0"
+        );
         valid_config!(
             self.election_tick_timeout != 0,
             "Election tick timeout must be greater than 0"
@@ -201,6 +628,73 @@ impl ServerConfig {
         );
         Ok(())
     }
+
+    /// A preset for a cluster within a data center whose clients wait for every decision: each
+    /// entry is replicated as soon as it is appended, and a failed leader is replaced within 5
+    /// ticks. The tick timeouts of the presets assume that `tick()` is called every 10ms. Set the
+    /// `pid` with `ServerConfig { pid, ..ServerConfig::low_latency() }`.
+    pub fn low_latency() -> Self {
+        Self {
+            election_tick_timeout: 5,
+            resend_message_tick_timeout: 100,
+            batch_size: 1,
+            batch_byte_size: 0,
+            flush_batch_tick_timeout: 1,
+            // A follower that is behind is synchronized in portions, so that a large message does
+            // not delay the messages of the entries that are being decided
+            sync_batch_byte_size: 1 << 20,
+            ..Default::default()
+        }
+    }
+
+    /// A preset for a cluster within a data center with a high load of proposals: entries are
+    /// appended and forwarded to the leader in batches of up to 1000 entries or 1 MiB, but at least
+    /// every 10 ticks, followers are synchronized in large portions, and trimmed entries are
+    /// removed outside of `trim()`.
+    pub fn high_throughput() -> Self {
+        Self {
+            batch_size: 1000,
+            batch_byte_size: 1 << 20,
+            flush_batch_tick_timeout: 10,
+            forward_batch_size: 1000,
+            forward_batch_byte_size: 1 << 20,
+            forward_batch_tick_timeout: 10,
+            decided_cache_size: 10_000,
+            sync_batch_size: 10_000,
+            sync_batch_byte_size: 16 << 20,
+            sync_prefetch: 8,
+            trim_mode: TrimMode::Tick,
+            ..Default::default()
+        }
+    }
+
+    /// A preset for a cluster that spans data centers: the election timeout of 50 ticks tolerates
+    /// the delays of a wide-area network, entries are appended in small batches to use fewer
+    /// messages per round trip, more log portions are requested ahead while catching up, and
+    /// reordered messages are tolerated.
+    pub fn wan() -> Self {
+        Self {
+            election_tick_timeout: 50,
+            resend_message_tick_timeout: 500,
+            batch_size: 100,
+            batch_byte_size: 256 << 10,
+            flush_batch_tick_timeout: 5,
+            forward_batch_size: 100,
+            forward_batch_byte_size: 256 << 10,
+            forward_batch_tick_timeout: 5,
+            sync_batch_byte_size: 4 << 20,
+            sync_prefetch: 16,
+            reorder_window: 32,
+            ..Default::default()
+        }
+    }
+
+    /// Explains the trade-offs this configuration makes between latency, throughput, and
+    /// robustness, and warns about settings that work against each other, e.g., a batch that waits
+    /// for longer than the election timeout. The presets don't cause any warnings.
+    pub fn tuning_report(&self) -> TuningReport {
+        tuning::report(self)
+    }
 }
 
 impl Default for ServerConfig {
@@ -211,8 +705,46 @@ impl Default for ServerConfig {
             resend_message_tick_timeout: RESEND_MESSAGE_TIMEOUT,
             buffer_size: BUFFER_SIZE,
             batch_size: 1,
+            batch_byte_size: 0,
             flush_batch_tick_timeout: FLUSH_BATCH_TIMEOUT,
+            forward_batch_size: 1,
+            forward_batch_byte_size: 0,
+            forward_batch_tick_timeout: FORWARD_BATCH_TIMEOUT,
+            decided_cache_size: DECIDED_CACHE_SIZE,
+            sync_batch_size: 0,
+            sync_batch_byte_size: 0,
+            sync_prefetch: SYNC_PREFETCH,
+            max_sync_suffix_entries: None,
+            pull_tick_timeout: None,
+            verification_tick_timeout: None,
+            verification_batch_size: VERIFICATION_BATCH_SIZE,
+            fingerprint_tick_timeout: None,
+            slow_follower_lag: None,
+            slow_follower_tick_timeout: None,
+            backup_tick_timeout: None,
+            slow_storage_latency: None,
+            slow_storage_tick_timeout: SLOW_STORAGE_TIMEOUT,
+            step_down_on_slow_storage: false,
+            storage_size_limit: None,
+            low_space_policy: LowSpacePolicy::default(),
+            follower_append_policy: FollowerAppendPolicy::default(),
+            max_entry_size: None,
+            memory_limit: None,
+            reorder_window: 0,
+            trim_mode: TrimMode::default(),
+            check_snapshots: cfg!(debug_assertions),
+            snapshot_batch_size: 0,
+            track_entry_ballots: false,
+            snapshot_interval: None,
+            compaction_tick_timeout: COMPACTION_TIMEOUT,
+            misbehaving_peer_threshold: MISBEHAVING_PEER_THRESHOLD,
             leader_priority: 0,
+            compression: None,
+            clock_jump_threshold: None,
+            reconfiguration_validator: None,
+            metrics_sink: None,
+            #[cfg(feature = "snapshot_sealing")]
+            snapshot_sealer: None,
             #[cfg(feature = "logging")]
             logger_file_path: None,
             #[cfg(feature = "logging")]
@@ -221,6 +753,11 @@ impl Default for ServerConfig {
     }
 }
 
+/// Check run before a reconfiguration is accepted. Takes the current cluster configuration and the
+/// `StopSign` of the proposed reconfiguration, and returns the reason if the reconfiguration is
+/// refused, e.g., because it would remove the last node of a region.
+pub type ReconfigurationValidator = fn(&ClusterConfig, &StopSign) -> Result<(), String>;
+
 /// The `OmniPaxos` struct represents an OmniPaxos server. Maintains the replicated log that can be read from and appended to.
 /// It also handles incoming messages and produces outgoing messages that you need to fetch and send periodically using your own network implementation.
 pub struct OmniPaxos<T, B>
@@ -233,6 +770,23 @@ where
     election_clock: LogicalClock,
     resend_message_clock: LogicalClock,
     flush_batch_clock: LogicalClock,
+    forward_batch_clock: LogicalClock,
+    pull_clock: Option<LogicalClock>,
+    verification_clock: Option<LogicalClock>,
+    fingerprint_clock: Option<LogicalClock>,
+    backup_clock: Option<LogicalClock>,
+    compaction_clock: LogicalClock,
+    slow_storage: SlowStorageDetector,
+    clock_jumps: ClockJumpDetector,
+    // The group of the next entry split by this server, derived from its log on the first split
+    chunk_group: Option<u64>,
+    metrics: Metrics,
+    // The leader when the metrics were last reported
+    metrics_leader: Ballot,
+    audit_log: AuditLog,
+    // How the recovered state was reconciled when this server was built, until it is taken
+    startup_reconciliation: Option<StartupReconciliation>,
+    decide_shipper: Option<DecideShipper<T>>,
 }
 
 impl<T, B> OmniPaxos<T, B>
@@ -244,7 +798,12 @@ where
     /// # Arguments
     /// * `trim_index` - Deletes all entries up to [`trim_index`], if the [`trim_index`] is `None` then the minimum index accepted by **ALL** servers will be used as the [`trim_index`].
     pub fn trim(&mut self, trim_index: Option<usize>) -> Result<(), CompactionErr> {
-        self.seq_paxos.trim(trim_index)
+        let result = self.seq_paxos.trim(trim_index);
+        let action = AuditAction::Trim {
+            trim_idx: trim_index,
+        };
+        self.audit_log.record(action, &result);
+        result
     }
 
     /// Trim the log and create a snapshot. ** Note: only up to the `decided_idx` can be snapshotted **
@@ -256,7 +815,13 @@ where
         compact_idx: Option<usize>,
         local_only: bool,
     ) -> Result<(), CompactionErr> {
-        self.seq_paxos.snapshot(compact_idx, local_only)
+        let result = self.seq_paxos.snapshot(compact_idx, local_only);
+        let action = AuditAction::Snapshot {
+            compact_idx,
+            local_only,
+        };
+        self.audit_log.record(action, &result);
+        result
     }
 
     /// Return the decided index. 0 means that no entry has been decided.
@@ -264,11 +829,34 @@ where
         self.seq_paxos.get_decided_idx()
     }
 
+    /// Appends the batched entries and blocks until the storage has persisted all writes so far,
+    /// see [`LogStorage::flush`](crate::storage::LogStorage::flush). Returns the durable accepted
+    /// index, i.e., the log up to this index (exclusive) survives a crash of this server. This is
+    /// a precise durability watermark for applications that coordinate with external systems,
+    /// e.g., that publish offsets. Entries that are still batched because this server is not in
+    /// the accept phase are not included.
+    pub fn flush_and_get_durable_idx(&mut self) -> Result<usize, StorageErr> {
+        self.seq_paxos
+            .flush_and_get_durable_idx()
+            .map_err(|e| StorageErr::with("flush the storage", e))
+    }
+
     /// Return trim index from storage.
     pub fn get_compacted_idx(&self) -> usize {
         self.seq_paxos.get_compacted_idx()
     }
 
+    /// Returns the configuration of the cluster this server is part of.
+    pub fn get_cluster_config(&self) -> &ClusterConfig {
+        self.seq_paxos.get_cluster_config()
+    }
+
+    /// Returns the results of the background verification of the stored log. See
+    /// `verification_tick_timeout` in [`ServerConfig`].
+    pub fn get_verification_report(&self) -> VerificationReport {
+        self.seq_paxos.get_verification_report().clone()
+    }
+
     /// Returns the id of the current leader.
     pub fn get_current_leader(&self) -> Option<NodeId> {
         let promised_pid = self.seq_paxos.get_promise().pid;
@@ -284,8 +872,179 @@ where
         self.seq_paxos.get_promise()
     }
 
+    /// Returns the commit latency of the entries appended while this server was the leader, or
+    /// `None` if none of them is decided yet. A high latency can indicate that a slow follower or
+    /// disk is slowing down the quorum.
+    pub fn get_commit_latency(&self) -> Option<CommitLatency> {
+        self.seq_paxos.commit_latency.get_commit_latency()
+    }
+
+    /// Returns the number of proposals this server received from each server, including the ones
+    /// appended at this server, together with the latency each server observed for its proposals.
+    /// The followers report their latency when forwarding proposals, so at the leader this shows
+    /// where the proposals originate and how long they take from there, e.g., to move the
+    /// leadership closer to the servers most proposals come from.
+    pub fn get_proposal_affinity(&self) -> HashMap<NodeId, ProposalAffinity> {
+        self.seq_paxos.get_proposal_affinity()
+    }
+
+    /// Returns the events of the log entries passing the stages of the replication pipeline on
+    /// this server since the last call, in the order they happened. The time between the stages
+    /// of the same entries, across the events of all servers, breaks down where the replication
+    /// latency is spent. At most the latest 10000 events are kept, so this should be polled
+    /// regularly.
+    #[cfg(feature = "pipeline_events")]
+    pub fn take_pipeline_events(&mut self) -> Vec<PipelineEvent> {
+        self.seq_paxos.internal_storage.pipeline().take_events()
+    }
+
+    /// Returns the events about slow followers detected since the last call, if this server has
+    /// been the leader. Requires `slow_follower_lag` or `slow_follower_tick_timeout` to be set in
+    /// [`ServerConfig`]. A follower is reported once when it becomes slow and once when it has
+    /// caught up again. At most the latest 1000 events are kept, so this should be polled
+    /// regularly.
+    pub fn take_slow_follower_events(&mut self) -> Vec<SlowFollowerEvent> {
+        self.seq_paxos.slow_followers.take_events()
+    }
+
+    /// Returns the events about the storage of this server being slow while it was the leader
+    /// since the last call. Requires `slow_storage_latency` to be set in [`ServerConfig`]. The
+    /// storage is reported once when it becomes slow and once when it is fast again. At most the
+    /// latest 1000 events are kept, so this should be polled regularly.
+    pub fn take_slow_storage_events(&mut self) -> Vec<SlowStorageEvent> {
+        self.slow_storage.take_events()
+    }
+
+    /// Returns the clock jumps this server detected since the last call. Requires
+    /// `clock_jump_threshold` to be set in [`ServerConfig`]. At most the latest 1000 jumps are
+    /// kept, so this should be polled regularly.
+    pub fn take_clock_jumps(&mut self) -> Vec<ClockJump> {
+        self.clock_jumps.take_jumps()
+    }
+
+    /// Returns the entries appended to this server that were dropped since the last call because no
+    /// leader was known within the `tick_timeout` of the [`FollowerAppendPolicy::Buffer`] policy,
+    /// together with their token if they were appended with [`OmniPaxos::append_with_token`].
+    /// The entries dropped for other reasons are kept for [`OmniPaxos::take_rejected_proposals`].
+    pub fn take_expired_proposals(&mut self) -> Vec<(T, Option<OriginToken>)> {
+        self.seq_paxos.take_expired_proposals()
+    }
+
+    /// Returns the entries appended to this server that were dropped since the last call, together
+    /// with why they were dropped, e.g., so that the application can retry them or answer the
+    /// client with an error. An entry dropped at the leader is returned to the server it was
+    /// appended on. An entry that is overwritten after a leader change is only returned, with
+    /// [`ProposalRejectionCause::Truncated`], if it was appended with
+    /// [`OmniPaxos::append_with_token`], since the other entries are not tracked once they are in
+    /// the log. Rejections are best-effort: an entry that is lost together with a crashed leader
+    /// is not returned.
+    pub fn take_rejected_proposals(&mut self) -> Vec<RejectedProposal<T>> {
+        self.seq_paxos.take_rejected_proposals()
+    }
+
+    /// Returns the peers reported as misbehaving since the last call. A peer is reported every
+    /// time the number of its messages this server rejected reaches a multiple of the
+    /// `misbehaving_peer_threshold` in [`ServerConfig`]. At most the latest 1000 reports are kept,
+    /// so this should be polled regularly.
+    pub fn take_misbehaving_peers(&mut self) -> Vec<MisbehavingPeer> {
+        self.seq_paxos.rejected_messages.take_events()
+    }
+
+    /// Returns the violations of the [`Snapshot`](crate::storage::Snapshot) contract found since
+    /// the last call. Requires `check_snapshots` to be enabled in [`ServerConfig`]. At most the
+    /// latest 1000 violations are kept.
+    pub fn take_snapshot_violations(&mut self) -> Vec<SnapshotViolation> {
+        self.seq_paxos.internal_storage.take_snapshot_violations()
+    }
+
+    /// Returns the ballot of the leader that first appended the entry at `idx` to the log. Returns
+    /// `None` if `idx` is out of bounds, if `track_entry_ballots` is disabled in [`ServerConfig`],
+    /// or if the ballot is unknown, e.g., because the entry was appended before this server was
+    /// restarted. The ballot of an undecided entry changes if the entry is overwritten.
+    pub fn get_entry_ballot(&self, idx: usize) -> Option<Ballot> {
+        self.seq_paxos.internal_storage.get_entry_ballot(idx)
+    }
+
+    /// Returns the ballots of the leaders that first appended the entries from `from_idx` on, as
+    /// the index of the first entry each leader appended together with its ballot, or `None` if
+    /// the ballot is unknown. The ballot of an index applies to all entries up to the next index
+    /// in the list. Empty if `track_entry_ballots` is disabled in [`ServerConfig`] or `from_idx`
+    /// is out of bounds.
+    pub fn get_entry_ballots(&self, from_idx: usize) -> Vec<(usize, Option<Ballot>)> {
+        self.seq_paxos.internal_storage.get_entry_ballots(from_idx)
+    }
+
+    /// Starts a witness read of the log index `idx` across the cluster and returns its id. The
+    /// entry at `idx` is read from the log of this server, and the other servers are requested
+    /// to send their entry and ballots at the index. This is a debugging tool to verify a
+    /// suspected divergence of the logs, e.g., during an incident. The read does not go through
+    /// consensus and does not change the log.
+    pub fn start_witness_read(&mut self, idx: usize) -> u64 {
+        self.seq_paxos.start_witness_read(idx)
+    }
+
+    /// Returns the report of the witness read `id` once a read quorum of servers, including this
+    /// one, has replied, or `None` otherwise. The report contains the replies received until it
+    /// is taken. At most 100 witness reads are collected at the same time, and the oldest ones
+    /// are abandoned.
+    pub fn take_witness_report(&mut self, id: u64) -> Option<WitnessReport<T>> {
+        self.seq_paxos.take_witness_report(id)
+    }
+
+    /// Returns the occupancy of the outgoing queues and proposal buffers of this server and the
+    /// number of messages it dropped because they arrived out of order. Growing queues and
+    /// in-flight windows show backpressure building up before it causes timeouts.
+    pub fn get_outgoing_stats(&self) -> OutgoingStats {
+        OutgoingStats {
+            queued_messages: self.ble.num_outgoing_msgs() + self.seq_paxos.num_outgoing_msgs(),
+            pending_proposals: self.seq_paxos.num_buffered_proposals(),
+            dependent_proposals: self.seq_paxos.num_dependent_proposals(),
+            in_flight: self.seq_paxos.get_in_flight(),
+            outdated_messages: self.seq_paxos.outdated_messages,
+            dropped_preceding_messages: self.seq_paxos.dropped_preceding_messages,
+            duplicate_messages: self.seq_paxos.duplicate_messages,
+            reordered_messages: self.seq_paxos.reordered_messages,
+        }
+    }
+
+    /// Returns the approximate number of bytes occupied by the in-memory buffers of this server,
+    /// e.g., to check that its footprint fits a memory-constrained service. The buffers can be
+    /// capped with `memory_limit` in [`ServerConfig`].
+    pub fn get_memory_usage(&self) -> MemoryUsage {
+        let mut usage = self.seq_paxos.get_memory_usage();
+        usage.outgoing += self.ble_memory_usage();
+        usage
+    }
+
+    fn ble_memory_usage(&self) -> usize {
+        self.ble.num_outgoing_msgs() * std::mem::size_of::<BLEMessage>()
+    }
+
+    /// Returns the sequence-number recovery events of the accept-phase messages exchanged with
+    /// each peer, i.e., the messages from the leader that were outdated or revealed that
+    /// preceding messages were lost, and the followers that the leader had to resynchronize.
+    /// They indicate a lossy transport, since every such event makes the follower resynchronize
+    /// with the leader, which hurts the throughput.
+    pub fn get_sequence_recovery_stats(&self) -> HashMap<NodeId, SequenceRecoveryStats> {
+        self.seq_paxos.sequence_recovery.get_all()
+    }
+
+    /// Returns the number of messages from `pid` that this server rejected, e.g., because they
+    /// claimed impossible log indexes.
+    pub fn get_rejected_message_count(&self, pid: NodeId) -> u64 {
+        self.seq_paxos.rejected_messages.get(pid)
+    }
+
     /// Returns the outgoing messages from this server. The messages should then be sent via the network implementation.
     pub fn outgoing_messages(&mut self) -> Vec<Message<T>> {
+        let msgs = self.take_outgoing_messages();
+        self.metrics
+            .counter(metrics::MESSAGES_SENT, msgs.len() as u64);
+        msgs
+    }
+
+    fn take_outgoing_messages(&mut self) -> Vec<Message<T>> {
+        let halted = self.seq_paxos.is_halted();
         let paxos_msgs = self
             .seq_paxos
             .get_outgoing_msgs()
@@ -296,46 +1055,414 @@ where
             .get_outgoing_msgs()
             .into_iter()
             .map(|b| Message::BLE(b));
-        ble_msgs.chain(paxos_msgs).collect()
+        match halted {
+            // The messages of a halted server might stem from a state that was not persisted
+            true => vec![],
+            false => ble_msgs.chain(paxos_msgs).collect(),
+        }
+    }
+
+    /// Returns the outgoing messages from this server to `pid`. The messages to other servers stay
+    /// queued, so that a transport can drain the connection to each server independently and a
+    /// blocked connection does not delay the messages to the others.
+    pub fn outgoing_for(&mut self, pid: NodeId) -> Vec<Message<T>> {
+        let paxos_msgs = self
+            .seq_paxos
+            .get_outgoing_msgs_for(pid)
+            .into_iter()
+            .map(|p| Message::SequencePaxos(p));
+        let ble_msgs = self
+            .ble
+            .get_outgoing_msgs_for(pid)
+            .into_iter()
+            .map(|b| Message::BLE(b));
+        let msgs: Vec<Message<T>> = match self.seq_paxos.is_halted() {
+            true => vec![],
+            false => ble_msgs.chain(paxos_msgs).collect(),
+        };
+        self.metrics
+            .counter(metrics::MESSAGES_SENT, msgs.len() as u64);
+        msgs
     }
 
-    /// Read entry at index `idx` in the log. Returns `None` if `idx` is out of bounds.
+    /// Returns the outgoing messages from this server that fit in the given budget and leaves the
+    /// rest queued for the next call. At most `max_messages` messages are returned, whose total
+    /// size according to `message_size` does not exceed `max_bytes`. The first message is returned
+    /// even if it is larger than `max_bytes`, so that a large message can't block the queue.
+    /// Useful for transports with bounded send buffers that need to pace themselves.
+    pub fn outgoing_messages_within<F>(
+        &mut self,
+        max_messages: usize,
+        max_bytes: usize,
+        mut message_size: F,
+    ) -> Vec<Message<T>>
+    where
+        F: FnMut(&Message<T>) -> usize,
+    {
+        let mut msgs = self.take_outgoing_messages();
+        let mut num_msgs = 0;
+        let mut num_bytes = 0;
+        for msg in msgs.iter().take(max_messages) {
+            let size = message_size(msg);
+            if num_msgs > 0 && num_bytes + size > max_bytes {
+                break;
+            }
+            num_bytes += size;
+            num_msgs += 1;
+        }
+        let rest = msgs.split_off(num_msgs);
+        if !rest.is_empty() {
+            let mut ble_rest = vec![];
+            let mut paxos_rest = vec![];
+            for msg in rest {
+                match msg {
+                    Message::BLE(b) => ble_rest.push(b),
+                    Message::SequencePaxos(p) => paxos_rest.push(p),
+                }
+            }
+            self.ble.requeue_outgoing_msgs(ble_rest);
+            self.seq_paxos.requeue_outgoing_msgs(paxos_rest);
+        }
+        self.metrics
+            .counter(metrics::MESSAGES_SENT, msgs.len() as u64);
+        msgs
+    }
+
+    /// Read entry at index `idx` in the log. Returns `None` if `idx` is out of bounds or the
+    /// storage fails, i.e., the error of a failed read is discarded with `.ok()`. Use
+    /// [`try_read`](Self::try_read) to tell them apart. This is a [`ReadConsistency::Local`] read,
+    /// see [`read_with`](Self::read_with) for the other levels.
     pub fn read(&self, idx: usize) -> Option<LogEntry<T>> {
-        match self
+        self.try_read(idx).ok().flatten()
+    }
+
+    /// Reads the entry at index `idx` like [`OmniPaxos::read`], but returns a
+    /// [`ReadErr::Storage`] instead of `None` if the read fails, so that a failed storage can be
+    /// told apart from an index that is out of bounds.
+    pub fn try_read(&self, idx: usize) -> Result<Option<LogEntry<T>>, ReadErr> {
+        self.read_with(idx, ReadConsistency::Local)
+    }
+
+    /// Calls `f` with the entry at index `idx` in the log and returns its result, or `Ok(None)` if
+    /// `idx` is out of bounds. Unlike [`read`](Self::read), the entry is borrowed instead of cloned
+    /// if it is in the cache of recent entries or if the storage supports it (see
+    /// [`LogStorage::get_entry_ref`](crate::storage::LogStorage::get_entry_ref)), which avoids
+    /// copying large entries in read-heavy workloads.
+    /// The storage might be locked while `f` is called, so `f` must not read from this server.
+    pub fn read_ref<F, R>(&self, idx: usize, f: F) -> Result<Option<R>, ReadErr>
+    where
+        F: FnOnce(LogEntryRef<'_, T>) -> R,
+    {
+        self.seq_paxos
+            .internal_storage
+            .read_ref(idx, f)
+            .map_err(|e| StorageErr::with("read log entries", e).into())
+    }
+
+    /// Read entry at index `idx` in the log if the promised ballot of this server, i.e., the
+    /// ballot of the leader it follows or leads with, is still `ballot`. This allows checking that
+    /// the leadership observed with [`OmniPaxos::get_promise`] has not changed before reading.
+    /// Returns `Ok(None)` if `idx` is out of bounds.
+    pub fn read_fenced(&self, idx: usize, ballot: Ballot) -> Result<Option<LogEntry<T>>, ReadErr> {
+        let current = self.seq_paxos.get_promise();
+        if current != ballot {
+            return Err(ReadErr::StaleBallot { current });
+        }
+        let entries = self
             .seq_paxos
             .internal_storage
             .read(idx..idx + 1)
-            .expect("storage error while trying to read log entries")
-        {
-            Some(mut v) => v.pop(),
-            None => None,
+            .map_err(|e| StorageErr::with("read log entries", e))?;
+        Ok(entries.and_then(|mut v| v.pop()))
+    }
+
+    /// Returns the token of the log this server has decided so far. See [`ReadToken`].
+    pub fn get_read_token(&self) -> ReadToken {
+        ReadToken {
+            decided_idx: self.seq_paxos.internal_storage.get_decided_idx(),
+            ballot: self.seq_paxos.get_promise(),
         }
     }
 
-    /// Read entries in the range `r` in the log. Returns `None` if `r` is out of bounds.
-    pub fn read_entries<R>(&self, r: R) -> Option<Vec<LogEntry<T>>>
+    /// Checks that this server has caught up with the log observed with `token` and returns the
+    /// token of its own log.
+    fn check_read_token(&self, token: &ReadToken) -> Result<ReadToken, ReadToken> {
+        let current = self.get_read_token();
+        if token.is_covered_by(&current) {
+            Ok(current)
+        } else {
+            Err(current)
+        }
+    }
+
+    /// Read entry at index `idx` in the log if this server has caught up with the log observed
+    /// with `token`, e.g., by a previous read of the same client at another server. Returns the
+    /// entry, or `None` if `idx` is out of bounds, together with the token to pass to the next
+    /// read. Fails with [`ReadErr::BehindToken`] if this server is behind the token.
+    pub fn read_monotonic(
+        &self,
+        idx: usize,
+        token: ReadToken,
+    ) -> Result<(Option<LogEntry<T>>, ReadToken), ReadErr> {
+        let (entries, token) = self.read_entries_monotonic(idx..idx + 1, token)?;
+        Ok((entries.and_then(|mut v| v.pop()), token))
+    }
+
+    /// Like [`read_monotonic`](Self::read_monotonic), but reads the entries in the range `r`.
+    #[allow(clippy::type_complexity)]
+    pub fn read_entries_monotonic<R>(
+        &self,
+        r: R,
+        token: ReadToken,
+    ) -> Result<(Option<Vec<LogEntry<T>>>, ReadToken), ReadErr>
     where
         R: RangeBounds<usize>,
     {
-        self.seq_paxos
+        let current = self
+            .check_read_token(&token)
+            .map_err(|current| ReadErr::BehindToken { token, current })?;
+        let entries = self
+            .seq_paxos
             .internal_storage
             .read(r)
-            .expect("storage error while trying to read log entries")
+            .map_err(|e| StorageErr::with("read log entries", e))?;
+        Ok((entries, current))
+    }
+
+    /// Read entries in the range `r` in the log. Returns `None` if `r` is out of bounds or the
+    /// storage fails, i.e., the error of a failed read is discarded with `.ok()`. This is a
+    /// [`ReadConsistency::Local`] read, see [`read_entries_with`](Self::read_entries_with) for the
+    /// other levels, which also returns the error.
+    pub fn read_entries<R>(&self, r: R) -> Option<Vec<LogEntry<T>>>
+    where
+        R: RangeBounds<usize>,
+    {
+        self.seq_paxos.internal_storage.read(r).ok().flatten()
+    }
+
+    /// Read entry at index `idx` in the log with the given `consistency`. Returns `Ok(None)` if
+    /// `idx` is out of bounds.
+    pub fn read_with(
+        &self,
+        idx: usize,
+        consistency: ReadConsistency,
+    ) -> Result<Option<LogEntry<T>>, ReadErr> {
+        let entries = self.read_entries_with(idx..idx + 1, consistency)?;
+        Ok(entries.and_then(|mut v| v.pop()))
     }
 
-    /// Read all decided entries starting at `from_idx` (inclusive) in the log. Returns `None` if `from_idx` is out of bounds.
+    /// Read entries in the range `r` in the log with the given `consistency`. Returns `Ok(None)`
+    /// if `r` is out of bounds. Only [`ReadConsistency::Local`] reads are served for now, the
+    /// other levels return [`ReadErr::UnsupportedConsistency`].
+    pub fn read_entries_with<R>(
+        &self,
+        r: R,
+        consistency: ReadConsistency,
+    ) -> Result<Option<Vec<LogEntry<T>>>, ReadErr>
+    where
+        R: RangeBounds<usize>,
+    {
+        match consistency {
+            ReadConsistency::Local => self
+                .seq_paxos
+                .internal_storage
+                .read(r)
+                .map_err(|e| StorageErr::with("read log entries", e).into()),
+            ReadConsistency::LeaderLease | ReadConsistency::Linearizable => {
+                Err(ReadErr::UnsupportedConsistency(consistency))
+            }
+        }
+    }
+
+    /// Read all decided entries starting at `from_idx` (inclusive) in the log. Returns `None` if
+    /// `from_idx` is out of bounds or the storage fails, i.e., the error of a failed read is
+    /// discarded with `.ok()`. Use [`read_entries_with`](Self::read_entries_with) up to
+    /// [`get_decided_idx`](Self::get_decided_idx) to get the error.
+    ///
+    /// If the configuration is stopped, its decided StopSign is the last entry of the log, i.e.,
+    /// it is read after all the entries of this configuration and no entry is ever decided after
+    /// it. See [`ConfigurationManager`](crate::reconfiguration::ConfigurationManager) to continue
+    /// reading with the next configuration.
     pub fn read_decided_suffix(&self, from_idx: usize) -> Option<Vec<LogEntry<T>>> {
         self.seq_paxos
             .internal_storage
             .read_decided_suffix(from_idx)
-            .expect("storage error while trying to read decided log suffix")
+            .ok()
+            .flatten()
+    }
+
+    /// Like [`read_entries`](Self::read_entries), but returns every entry together with its index
+    /// in the log.
+    pub fn read_entries_indexed<R>(&self, r: R) -> Option<Vec<IndexedLogEntry<T>>>
+    where
+        R: RangeBounds<usize>,
+    {
+        let from_idx = match r.start_bound() {
+            Bound::Included(i) => *i,
+            Bound::Excluded(e) => *e + 1,
+            Bound::Unbounded => 0,
+        };
+        self.read_entries(r)
+            .map(|entries| util::with_indexes(from_idx, entries))
+    }
+
+    /// Like [`read_decided_suffix`](Self::read_decided_suffix), but returns every entry together
+    /// with its index in the log.
+    pub fn read_decided_suffix_indexed(&self, from_idx: usize) -> Option<Vec<IndexedLogEntry<T>>> {
+        self.read_decided_suffix(from_idx)
+            .map(|entries| util::with_indexes(from_idx, entries))
+    }
+
+    /// Returns a read-only handle to the log of this server that can be used from other threads
+    /// while this server handles messages. See [`OmniPaxosReader`].
+    pub fn reader(&self) -> OmniPaxosReader<T, B> {
+        let internal_storage = &self.seq_paxos.internal_storage;
+        OmniPaxosReader::with(
+            internal_storage.get_shared_storage(),
+            internal_storage.get_published_indexes(),
+        )
+    }
+
+    /// Returns a handle that removes the compacted entries from the storage when the `trim_mode`
+    /// is [`TrimMode::Worker`]. See [`TrimWorker`].
+    pub fn trim_worker(&self) -> TrimWorker<T, B> {
+        let internal_storage = &self.seq_paxos.internal_storage;
+        TrimWorker::with(
+            internal_storage.get_shared_storage(),
+            internal_storage.get_deferred_trim(),
+        )
+    }
+
+    /// Pins the log at `from_idx`: while the returned guard is alive, this server neither trims
+    /// nor snapshots its log past `from_idx`, e.g., so that a backup job or a slow consumer can
+    /// read the entries from `from_idx` on. `trim()` and `snapshot()` past the lowest pinned index
+    /// fail with [`CompactionErr::Pinned`], and automatic compactions are skipped. Pinning an index
+    /// below the compacted index holds back any further compaction. See [`PinGuard`].
+    pub fn pin_log(&self, from_idx: usize) -> PinGuard {
+        self.seq_paxos.internal_storage.pin_log(from_idx)
+    }
+
+    /// Sets the hook that is called with the newly decided ranges of the log, e.g., to stream them
+    /// to an external system. Shipping starts at the `from_idx` of `config`, and the log is pinned
+    /// from the first entry that was not shipped yet, see [`OmniPaxos::pin_log`]. Replaces the
+    /// previous hook, if any. See [`DecideHook`].
+    pub fn set_decide_hook<H>(&mut self, hook: H, config: DecideHookConfig)
+    where
+        H: DecideHook<T> + 'static,
+    {
+        let pin = self.pin_log(config.from_idx);
+        self.decide_shipper = Some(DecideShipper::with(Box::new(hook), config, pin));
+        self.ship_decided();
+    }
+
+    /// Removes the hook set with [`OmniPaxos::set_decide_hook`] and releases its hold on the log.
+    pub fn remove_decide_hook(&mut self) -> Option<Box<dyn DecideHook<T>>> {
+        self.decide_shipper.take().map(DecideShipper::into_hook)
+    }
+
+    /// Returns the index up to which (exclusive) the decided log was shipped to the hook set with
+    /// [`OmniPaxos::set_decide_hook`], or `None` if no hook is set.
+    pub fn get_shipped_idx(&self) -> Option<usize> {
+        self.decide_shipper.as_ref().map(DecideShipper::shipped_idx)
+    }
+
+    /// Offers the decided entries that were not shipped yet to the decide hook, until it pushes
+    /// back or everything decided is shipped.
+    fn ship_decided(&mut self) {
+        let Some(mut shipper) = self.decide_shipper.take() else {
+            return;
+        };
+        while let Some((from_idx, to_idx)) = shipper.next_range(self.get_decided_idx()) {
+            let Some(entries) = self.read_entries(from_idx..to_idx) else {
+                break;
+            };
+            if !shipper.ship(entries, self.get_compacted_idx()) || shipper.shipped_idx() == from_idx
+            {
+                break;
+            }
+        }
+        shipper.repin(|idx| self.pin_log(idx));
+        self.decide_shipper = Some(shipper);
     }
 
-    /// Handle an incoming message
-    pub fn handle_incoming(&mut self, m: Message<T>) {
-        match m {
-            Message::SequencePaxos(p) => self.seq_paxos.handle(p),
-            Message::BLE(b) => self.ble.handle(b),
+    /// Handle an incoming message. Returns the error if the storage failed while handling it, or
+    /// if the application halted this server with [`OmniPaxos::halt`].
+    pub fn handle_incoming(&mut self, m: Message<T>) -> Result<(), ProtocolError> {
+        self.handle_message(m)?;
+        self.ship_decided();
+        Ok(())
+    }
+
+    /// Handles the incoming `messages` in order and returns the outcome of each message in the
+    /// same order. A message that is rejected or ignored does not affect the others, and the
+    /// decided entries are only offered to the decide hook once for the whole batch.
+    pub fn handle_incoming_batch(
+        &mut self,
+        messages: Vec<Message<T>>,
+    ) -> BatchResult<MessageOutcome> {
+        let outcomes = messages
+            .into_iter()
+            .map(|m| {
+                self.handle_message(m)
+                    .unwrap_or_else(MessageOutcome::Failed)
+            })
+            .collect();
+        self.ship_decided();
+        BatchResult { outcomes }
+    }
+
+    fn handle_message(&mut self, m: Message<T>) -> Result<MessageOutcome, ProtocolError> {
+        self.check_halted()?;
+        self.metrics.counter(metrics::MESSAGES_RECEIVED, 1);
+        let outcome = match m {
+            // Servers with a different configuration are not part of this cluster
+            Message::SequencePaxos(p) if self.ble.is_mismatched(p.from) => MessageOutcome::Ignored,
+            Message::SequencePaxos(p) => match self.seq_paxos.validate_message(&p) {
+                Ok(()) => {
+                    let res = self.seq_paxos.handle(p);
+                    self.seq_paxos.halt_if_failed(res)?
+                }
+                Err(cause) => {
+                    self.seq_paxos.reject_message(p.from, cause);
+                    MessageOutcome::Rejected(cause)
+                }
+            },
+            // Backups don't take part in the leader election
+            Message::BLE(_) if self.seq_paxos.is_backup() => MessageOutcome::Ignored,
+            Message::BLE(b) => match self.seq_paxos.validate_ble_message(&b) {
+                Ok(()) => {
+                    self.ble.handle(b);
+                    MessageOutcome::Handled
+                }
+                Err(cause) => {
+                    self.seq_paxos.reject_message(b.from, cause);
+                    MessageOutcome::Rejected(cause)
+                }
+            },
+        };
+        Ok(outcome)
+    }
+
+    /// Halts this server after `err`, e.g., after a handler failed with it and the application
+    /// decides not to retry. A halted server fails incoming messages and timers with
+    /// [`ProtocolError::Halted`], sends no messages, rejects proposals with
+    /// [`ProposeErr::Halted`], and must be restarted from its storage to recover. Only the first
+    /// error is kept. With the `no_panic` feature, the server halts itself after any failed call.
+    pub fn halt(&mut self, err: FatalErr) {
+        self.seq_paxos.internal_storage.halt(err);
+    }
+
+    /// Returns the error after which the application halted this server with
+    /// [`OmniPaxos::halt`], or `None` if it is running.
+    pub fn get_fatal_error(&self) -> Option<FatalErr> {
+        self.seq_paxos.internal_storage.get_fatal_error()
+    }
+
+    /// Fails with [`ProtocolError::Halted`] if the application halted this server.
+    fn check_halted(&self) -> Result<(), ProtocolError> {
+        match self.get_fatal_error() {
+            Some(err) => Err(ProtocolError::Halted(err)),
+            None => Ok(()),
         }
     }
 
@@ -344,74 +1471,580 @@ where
         self.seq_paxos.is_reconfigured()
     }
 
+    /// Returns the status of the pending reconfiguration, i.e., the reconfiguration proposed by this
+    /// server or accepted from the leader. Returns `None` if there is no pending reconfiguration.
+    pub fn reconfiguration_status(&self) -> Option<ReconfigurationStatus> {
+        self.seq_paxos.reconfiguration_status()
+    }
+
     /// Append an entry to the replicated log.
     pub fn append(&mut self, entry: T) -> Result<(), ProposeErr<T>> {
         self.seq_paxos.append(entry)
     }
 
+    /// Append each of the `entries` to the replicated log as with [`OmniPaxos::append`] and return
+    /// the outcome of each entry in the same order. Unlike appending the entries one by one and
+    /// stopping at the first error, an entry that is rejected, e.g., because it is too large or
+    /// the storage got full, does not affect the entries before or after it.
+    pub fn append_batch(&mut self, entries: Vec<T>) -> BatchResult<AppendOutcome<T>> {
+        BatchResult {
+            outcomes: self.seq_paxos.append_batch(entries),
+        }
+    }
+
+    /// Append an entry to the replicated log and attach the opaque `token` to it. The token is
+    /// forwarded to the leader together with the entry and, once the entry is decided, returned to
+    /// this server by [`OmniPaxos::take_decided_tokens`] together with the log index of the entry.
+    /// This makes it possible to answer the client that proposed the entry without keeping track
+    /// of the proposals in the application. Tokens are delivered at most once: if the leader
+    /// changes before the entry is decided, the token is lost even if the entry is decided later.
+    pub fn append_with_token(&mut self, entry: T, token: OriginToken) -> Result<(), ProposeErr<T>> {
+        self.seq_paxos.append_with_token(entry, token)
+    }
+
+    /// Append an entry to the replicated log unless it can't be appended by the leader before
+    /// `deadline`, e.g., because there is no leader or the proposals before it are still waiting.
+    /// Fails with [`ProposeErr::DeadlineExceeded`] if the deadline has already passed. Otherwise, an
+    /// entry that misses its deadline is dropped and returned by
+    /// [`OmniPaxos::take_rejected_proposals`] with [`ProposalRejectionCause::DeadlineExceeded`],
+    /// and is never decided. The deadline is compared with the clock of the leader, so the clocks of
+    /// the servers should be synchronized. Once the leader has appended the entry to its log, the
+    /// deadline no longer applies and the entry may still be decided after it.
+    pub fn append_with_deadline(
+        &mut self,
+        entry: T,
+        deadline: SystemTime,
+    ) -> Result<(), ProposeErr<T>> {
+        self.seq_paxos.append_with_deadline(entry, deadline)
+    }
+
+    /// Append an entry to the replicated log if this server has caught up with the log observed
+    /// with `token`, e.g., so that an entry that depends on what a client has read is not appended
+    /// at a server that is behind. Fails with [`ProposeErr::BehindToken`] otherwise.
+    pub fn append_monotonic(&mut self, entry: T, token: ReadToken) -> Result<(), ProposeErr<T>> {
+        match self.check_read_token(&token) {
+            Ok(_) => self.seq_paxos.append(entry),
+            Err(current) => Err(ProposeErr::BehindToken(entry, current)),
+        }
+    }
+
+    /// Returns the tokens of the entries appended with [`OmniPaxos::append_with_token`] on this
+    /// server that have been decided since the last call, together with the log index of each
+    /// entry. A token is only returned once its entry is also decided on this server, i.e., the
+    /// entry can be read at the returned index.
+    pub fn take_decided_tokens(&mut self) -> Vec<(usize, OriginToken)> {
+        self.seq_paxos.take_decided_tokens()
+    }
+
+    /// Returns the log index of the last settings change applied by this server and all the
+    /// settings changes applied so far merged together, or `None` if no change has been applied.
+    /// See [`OmniPaxos::enable_replicated_settings`].
+    pub fn get_applied_settings(&self) -> Option<(usize, &ReplicatedSettings)> {
+        self.seq_paxos.get_applied_settings()
+    }
+
     /// Propose a cluster reconfiguration. Returns an error if the current configuration has already been stopped
-    /// by a previous reconfiguration request or if the `new_configuration` is invalid.
+    /// by a different reconfiguration request or if the `new_configuration` is invalid.
     /// `new_configuration` defines the cluster-wide configuration settings for the **next** cluster.
     /// `metadata` is optional data to commit alongside the reconfiguration.
+    ///
+    /// Proposing the same reconfiguration more than once is idempotent: it is only proposed once and
+    /// the current [`ReconfigurationStatus`] of it is returned.
     pub fn reconfigure(
         &mut self,
         new_configuration: ClusterConfig,
         metadata: Option<Vec<u8>>,
-    ) -> Result<(), ProposeErr<T>> {
-        if let Err(config_error) = new_configuration.validate() {
-            return Err(ProposeErr::ConfigError(
-                config_error,
-                new_configuration,
+    ) -> Result<ReconfigurationStatus, ReconfigErr> {
+        let action = AuditAction::Reconfigure {
+            configuration_id: new_configuration.configuration_id,
+            nodes: new_configuration.nodes.clone(),
+        };
+        let result = match new_configuration.validate() {
+            Err(error) => Err(ReconfigErr::InvalidConfig {
+                error,
+                config: Box::new(new_configuration),
                 metadata,
-            ));
+            }),
+            Ok(()) => {
+                let res = self.seq_paxos.reconfigure(new_configuration, metadata);
+                self.seq_paxos.halt_if_failed(res)
+            }
+        };
+        self.audit_log.record(action, &result);
+        result
+    }
+
+    /// Like [`OmniPaxos::reconfigure`], but also requests that the application state is handed
+    /// off to the new configuration, so that its servers can start from the state of this one
+    /// without copying it out-of-band. Once the StopSign is decided, the servers of this
+    /// configuration can create the final snapshot with [`OmniPaxos::create_handoff`], which is
+    /// transferred in chunks of at most `chunk_size` bytes. The handoff is encoded into the
+    /// metadata of the StopSign, see [`HandoffRef::decode`] to get back the given `metadata`.
+    pub fn reconfigure_with_handoff(
+        &mut self,
+        new_configuration: ClusterConfig,
+        metadata: Option<Vec<u8>>,
+        chunk_size: usize,
+    ) -> Result<ReconfigurationStatus, ReconfigErr> {
+        let handoff = HandoffRef {
+            chunk_size: chunk_size.max(1),
+        };
+        self.reconfigure(new_configuration, Some(handoff.encode(metadata)))
+    }
+
+    /// Creates the final snapshot of this configuration, i.e., of all entries before the decided
+    /// StopSign, encoded with `codec` and split into chunks for the servers of the next
+    /// configuration. Requires the reconfiguration to be proposed with
+    /// [`OmniPaxos::reconfigure_with_handoff`].
+    pub fn create_handoff<C>(&self, codec: &C) -> Result<HandoffSender, HandoffErr>
+    where
+        C: ArchiveCodec<T>,
+    {
+        let ss = self.is_reconfigured().ok_or(HandoffErr::NotReconfigured)?;
+        let (handoff, _) = ss
+            .metadata
+            .as_deref()
+            .and_then(HandoffRef::decode)
+            .ok_or(HandoffErr::NotRequested)?;
+        if !use_snapshots::<T>() {
+            return Err(HandoffErr::SnapshotsUnsupported);
         }
-        self.seq_paxos.reconfigure(new_configuration, metadata)
+        let internal_storage = &self.seq_paxos.internal_storage;
+        let decided_idx = internal_storage.get_decided_idx_without_stopsign();
+        let snapshot = internal_storage
+            .create_snapshot(decided_idx)
+            .map_err(|e| StorageErr::with("create the handoff snapshot", e))?;
+        let bytes = codec
+            .encode_snapshot(&snapshot)
+            .map_err(HandoffErr::Codec)?;
+        Ok(HandoffSender::with(
+            ss.next_config.configuration_id,
+            decided_idx,
+            handoff.chunk_size,
+            bytes,
+        ))
     }
 
     /// Handles re-establishing a connection to a previously disconnected peer.
     /// This should only be called if the underlying network implementation indicates that a connection has been re-established.
     pub fn reconnected(&mut self, pid: NodeId) {
+        self.ble.reconnected(pid);
         self.seq_paxos.reconnected(pid)
     }
 
+    /// Makes this server recover its state from the leader, as it does when restarting from its
+    /// storage. Useful if the state of this server is suspected to be stale, e.g., after its
+    /// storage was restored. The server stops replicating until the leader has synchronized its
+    /// log. Has no effect on the leader. The progress is reported by [`OmniPaxos::recovery_progress`].
+    pub fn fail_recover(&mut self) {
+        self.seq_paxos.fail_recover();
+        self.audit_log.record_ok(AuditAction::FailRecover);
+    }
+
+    /// Discards the undecided entries of this follower from `idx` (inclusive) onwards, together
+    /// with an accepted but undecided reconfiguration, and then recovers the discarded part of the
+    /// log from the leader as in [`OmniPaxos::fail_recover`]. Useful for repairing a server whose
+    /// storage returned garbage for its latest entries without rebuilding the whole replica.
+    ///
+    /// # Warning
+    /// The discarded entries might already have been counted by the leader to decide them. This is
+    /// only safe if they are also accepted by enough other servers to form a quorum without this
+    /// server, e.g., if all other servers are up-to-date. Only decided entries are protected: the
+    /// truncation is refused at the leader, for decided indices and beyond the end of the log.
+    #[cfg(feature = "operator_tools")]
+    pub fn unsafe_truncate_after(&mut self, idx: usize) -> Result<(), TruncationErr> {
+        let result = self.seq_paxos.unsafe_truncate_after(idx);
+        self.audit_log
+            .record(AuditAction::Truncate { idx }, &result);
+        result
+    }
+
+    /// Initializes the empty storage of this server, e.g., after it was wiped, with the decided
+    /// state of the healthy replica `pid`: its snapshot, decided log entries, reconfiguration, and
+    /// round. This server then recovers the rest of the log from the leader before it takes part
+    /// in the replication as usual. The request is resent every `resend_message_tick_timeout`
+    /// ticks until `pid` responds. The progress is reported by [`OmniPaxos::recovery_progress`].
+    pub fn bootstrap_from_peer(&mut self, pid: NodeId) -> Result<(), BootstrapErr> {
+        let result = self.seq_paxos.bootstrap_from_peer(pid);
+        self.audit_log
+            .record(AuditAction::BootstrapFromPeer(pid), &result);
+        result
+    }
+
+    /// Initializes the empty storage of this server, e.g., after it was wiped, from a quorum of
+    /// the other servers. Unlike [`OmniPaxos::bootstrap_from_peer`], this server neither promises
+    /// nor accepts anything until a quorum has reported its promised round and decided index.
+    /// It then adopts the highest promise, so that it can't promise a round that it rejected
+    /// before it lost its state, and bootstraps from the server with the highest decided index.
+    /// A leader elected in the meantime is only followed afterwards. The queries are resent every
+    /// `resend_message_tick_timeout` ticks until a quorum responds.
+    pub fn recover_seamlessly(&mut self) -> Result<(), BootstrapErr> {
+        let result = self.seq_paxos.recover_seamlessly();
+        self.audit_log
+            .record(AuditAction::RecoverSeamlessly, &result);
+        result
+    }
+
+    /// Migrates this server to `storage` while it keeps running in the cluster, e.g., from an
+    /// in-memory to a persistent storage. The batched entries are flushed, and the state of the
+    /// current storage is copied into `storage`, which should be empty, and verified. Only then
+    /// does the server switch to `storage` and return the previous storage. The storage is locked
+    /// while it is copied, which blocks the readers and the trim worker. If the copy fails or
+    /// differs, e.g., because `storage` has not persisted all entries yet, the server keeps its
+    /// current storage. To migrate between different `Storage` implementations, `B` can be a
+    /// `Box<dyn Storage<T>>`. See [`crate::storage::migration::copy_storage`] to copy the state of a
+    /// stopped server instead.
+    pub fn swap_storage(&mut self, storage: B) -> Result<B, StorageMigrationErr>
+    where
+        T: PartialEq,
+    {
+        let result = self.seq_paxos.swap_storage(storage);
+        self.audit_log.record(AuditAction::SwapStorage, &result);
+        result
+    }
+
+    /// Returns the progress of the ongoing recovery, or of the last recovery if its completion has
+    /// not been taken with [`OmniPaxos::take_completed_recovery`]. Returns `None` if this server
+    /// has not been recovering.
+    pub fn recovery_progress(&self) -> Option<RecoveryProgress> {
+        self.seq_paxos.recovery_progress().cloned()
+    }
+
+    /// Returns the servers that were detected to run with a different cluster configuration than
+    /// this server since the last call, e.g., because they were accidentally pointed at the wrong
+    /// cluster. Such servers are excluded from the leader election and their messages are ignored
+    /// until they send a heartbeat with a matching configuration. Each mismatch is only reported
+    /// once, unless the configuration of the server changes.
+    pub fn take_config_mismatches(&mut self) -> Vec<ConfigMismatch> {
+        self.ble.take_config_mismatches()
+    }
+
+    /// Returns the fingerprints from the leader that differed from the fingerprint of the same
+    /// decided entries in the log of this server since the last call. Requires
+    /// `fingerprint_tick_timeout` to be set in [`ServerConfig`] of the leader. Any alarm means that
+    /// the decided logs of the servers diverged, so the affected servers should be taken out of
+    /// service and investigated. At most the latest 1000 alarms are kept, so this should be polled
+    /// regularly.
+    pub fn take_divergence_alarms(&mut self) -> Vec<DivergenceAlarm> {
+        self.seq_paxos.divergence.take_alarms()
+    }
+
+    /// Returns the progress of the last recovery once it has completed. The completion is only
+    /// returned once, which makes it possible to react to it, e.g., by resuming to serve reads.
+    pub fn take_completed_recovery(&mut self) -> Option<RecoveryProgress> {
+        self.seq_paxos.take_completed_recovery()
+    }
+
+    /// Returns how the view of the leader election was reconciled with the promise and accepted
+    /// round that Sequence Paxos recovered from the storage when this server was built. It is only
+    /// returned once.
+    pub fn take_startup_reconciliation(&mut self) -> Option<StartupReconciliation> {
+        self.startup_reconciliation.take()
+    }
+
+    /// Cross-checks the view of the leader election with the promise and accepted round that
+    /// Sequence Paxos recovered from the storage. Contradicting states are reconciled explicitly,
+    /// by forcing the recover phase or a new election, instead of waiting for them to converge.
+    fn reconcile_startup_state(&mut self) -> Result<(), ProtocolError> {
+        let recovered_promise = self.seq_paxos.get_promise();
+        let accepted_round = self.seq_paxos.internal_storage.get_accepted_round();
+        let forced_recovery = self.seq_paxos.force_recovery_if_inconsistent()?;
+        let promise = self.seq_paxos.get_promise();
+        let new_election = self.ble.reconcile_with_promise(promise);
+        let reconciliation = if forced_recovery {
+            StartupReconciliation::ForcedRecovery {
+                recovered_promise: (recovered_promise != Ballot::default())
+                    .then_some(recovered_promise),
+                accepted_round,
+                new_election,
+            }
+        } else if promise == Ballot::default() {
+            StartupReconciliation::Fresh
+        } else if new_election {
+            StartupReconciliation::NewElection { promise }
+        } else {
+            StartupReconciliation::Recover { promise }
+        };
+        self.startup_reconciliation = Some(reconciliation);
+        Ok(())
+    }
+
     /// Increments the internal logical clock. This drives the processes for leader changes, resending dropped messages, and flushing batched log entries.
     /// Each of these is triggered every `election_tick_timeout`, `resend_message_tick_timeout`, and `flush_batch_tick_timeout` number of calls to this function
-    /// (See how to configure these timeouts in `ServerConfig`).
-    pub fn tick(&mut self) {
+    /// (See how to configure these timeouts in `ServerConfig`). To drive the timers from the application's own scheduler instead, see [`OmniPaxos::handle_timer`].
+    /// Returns the error if the storage failed in a triggered process, or if the application halted this server with [`OmniPaxos::halt`].
+    pub fn tick(&mut self) -> Result<(), ProtocolError> {
+        self.check_halted()?;
+        if self.clock_jumps.tick().is_some() {
+            self.handle_clock_jump();
+        }
         if self.election_clock.tick_and_check_timeout() {
-            self.election_timeout();
+            self.handle_timer(Timer::Election)?;
         }
         if self.resend_message_clock.tick_and_check_timeout() {
-            self.seq_paxos.resend_message_timeout();
+            self.handle_timer(Timer::ResendMessage)?;
         }
         if self.flush_batch_clock.tick_and_check_timeout() {
-            self.seq_paxos.flush_batch_timeout();
+            self.handle_timer(Timer::FlushBatch)?;
+        }
+        if self.forward_batch_clock.tick_and_check_timeout() {
+            self.handle_timer(Timer::ForwardBatch)?;
         }
+        if self
+            .pull_clock
+            .as_mut()
+            .is_some_and(|c| c.tick_and_check_timeout())
+        {
+            self.handle_timer(Timer::Pull)?;
+        }
+        if self
+            .verification_clock
+            .as_mut()
+            .is_some_and(|c| c.tick_and_check_timeout())
+        {
+            self.handle_timer(Timer::Verification)?;
+        }
+        if self
+            .fingerprint_clock
+            .as_mut()
+            .is_some_and(|c| c.tick_and_check_timeout())
+        {
+            self.handle_timer(Timer::Fingerprint)?;
+        }
+        if self
+            .backup_clock
+            .as_mut()
+            .is_some_and(|c| c.tick_and_check_timeout())
+        {
+            self.handle_timer(Timer::Backup)?;
+        }
+        if self.compaction_clock.tick_and_check_timeout() {
+            self.handle_timer(Timer::Compaction)?;
+        }
+        self.handle_timer(Timer::SlowFollowers)?;
+        self.handle_timer(Timer::Durability)?;
+        self.handle_timer(Timer::BufferedProposals)?;
+        self.handle_timer(Timer::SlowStorage)?;
+        if let Some(shipper) = self.decide_shipper.as_mut() {
+            shipper.tick();
+        }
+        self.ship_decided();
+        let ble_memory_usage = self.ble_memory_usage();
+        self.seq_paxos.check_memory_limit(ble_memory_usage);
+        self.report_metrics();
+        Ok(())
+    }
+
+    /// Stops acting on the timing observed before a clock jump: the replies of the current heartbeat
+    /// round are discarded and a new round gets a full election timeout, so that the leadership
+    /// is only confirmed or taken over with fresh heartbeats, and the latencies being measured are
+    /// discarded, so that the gap neither makes the storage look slow nor distorts the commit
+    /// latencies.
+    fn handle_clock_jump(&mut self) {
+        self.metrics.counter(metrics::CLOCK_JUMPS, 1);
+        self.ble.restart_hb_round();
+        self.election_clock.reset();
+        self.seq_paxos.discard_latency_samples();
+    }
+
+    fn report_metrics(&mut self) {
+        let leader = self.seq_paxos.get_promise();
+        if leader != self.metrics_leader {
+            self.metrics_leader = leader;
+            self.metrics.counter(metrics::LEADER_CHANGES, 1);
+        }
+        let is_leader = self.get_current_leader() == Some(self.seq_paxos.get_pid());
+        self.metrics
+            .gauge(metrics::IS_LEADER, f64::from(u8::from(is_leader)));
+        self.metrics
+            .gauge(metrics::DECIDED_IDX, self.get_decided_idx() as f64);
+        self.metrics.gauge(
+            metrics::ACCEPTED_IDX,
+            self.seq_paxos.internal_storage.get_accepted_idx() as f64,
+        );
+        self.metrics
+            .gauge(metrics::COMPACTED_IDX, self.get_compacted_idx() as f64);
+        let stats = self.get_outgoing_stats();
+        self.metrics
+            .gauge(metrics::OUTGOING_MESSAGES, stats.queued_messages as f64);
+        self.metrics
+            .gauge(metrics::PENDING_PROPOSALS, stats.pending_proposals as f64);
+        let max_in_flight = stats.in_flight.values().max().copied().unwrap_or(0);
+        self.metrics
+            .gauge(metrics::MAX_IN_FLIGHT_ENTRIES, max_in_flight as f64);
+        self.metrics.gauge(
+            metrics::MEMORY_USAGE,
+            self.get_memory_usage().total() as f64,
+        );
+    }
+
+    /// Runs the process of `timer` immediately, regardless of how many times `tick()` has been
+    /// called. This makes it possible to drive each timer from the application's own scheduler or
+    /// clock with its own period, e.g., a short period for `Timer::FlushBatch` and a long one for
+    /// `Timer::Compaction`, instead of calling `tick()`. The `*_tick_timeout` of a timer that is
+    /// driven this way has no effect, as long as `tick()` is not called as well. Returns the error
+    /// like [`OmniPaxos::tick`].
+    pub fn handle_timer(&mut self, timer: Timer) -> Result<(), ProtocolError> {
+        self.check_halted()?;
+        let res = self.run_timer(timer);
+        self.seq_paxos.halt_if_failed(res)
+    }
+
+    fn run_timer(&mut self, timer: Timer) -> Result<(), ProtocolError> {
+        match timer {
+            Timer::Election => {
+                if !self.seq_paxos.is_backup() {
+                    self.election_timeout()?;
+                }
+            }
+            Timer::ResendMessage => self.seq_paxos.resend_message_timeout(),
+            Timer::FlushBatch => self.seq_paxos.flush_batch_timeout()?,
+            Timer::ForwardBatch => self.seq_paxos.flush_forward_batch(),
+            Timer::Pull => {
+                if self.pull_clock.is_some() {
+                    self.seq_paxos.pull_timeout()?;
+                }
+            }
+            Timer::Verification => self.seq_paxos.verification_timeout(),
+            Timer::Fingerprint => self.seq_paxos.fingerprint_timeout()?,
+            Timer::Backup => self.seq_paxos.backup_timeout()?,
+            Timer::Compaction => {
+                self.seq_paxos.check_storage_size()?;
+                self.seq_paxos.check_storage_capacity();
+                self.seq_paxos.apply_replicated_settings()?;
+                self.seq_paxos.check_snapshot_schedule();
+                self.seq_paxos.trim_timeout()?;
+            }
+            Timer::Durability => self.seq_paxos.durability_timeout()?,
+            Timer::BufferedProposals => self.seq_paxos.buffered_proposals_timeout(),
+            Timer::SlowStorage => {
+                let latency = self.seq_paxos.internal_storage.take_append_latency();
+                let is_leader = self.seq_paxos.get_state().0 == Role::Leader;
+                if self.slow_storage.tick(latency, is_leader) {
+                    self.ble.step_down();
+                }
+            }
+            Timer::SlowFollowers => {
+                if self.seq_paxos.slow_followers.is_enabled() {
+                    let ble = &self.ble;
+                    self.seq_paxos
+                        .check_slow_followers(|pid| ble.is_connected(pid));
+                }
+            }
+        }
+        Ok(())
     }
 
     /*** BLE calls ***/
     /// Update the custom priority used in the Ballot for this server. Note that changing the
     /// priority triggers a leader re-election.
     pub fn set_priority(&mut self, p: u32) {
-        self.ble.set_priority(p)
+        self.ble.set_priority(p);
+        self.audit_log.record_ok(AuditAction::SetPriority(p));
+    }
+
+    /// Makes this server give up its leadership if it is the leader, so that another server takes
+    /// over, e.g., before this server is shut down for maintenance. The other servers elect a new
+    /// leader at the next election timeouts. This server stays the leader until then, or if no
+    /// other server can be elected.
+    pub fn step_down(&mut self) {
+        self.ble.step_down();
+        self.audit_log.record_ok(AuditAction::StepDown);
+    }
+
+    /// Designates `standby` as the hot-standby leader of this server's leadership. The other
+    /// servers durably promise not to elect any other server in the next round, and once the
+    /// standby is ready, every entry is only decided after it was accepted by the standby. If this
+    /// server fails, the standby then takes over right away without a prepare phase, i.e.,
+    /// without collecting the logs of a quorum first. The standby can be designated once per
+    /// leadership, and the storage of every server must implement
+    /// [`StateStorage::set_standby_promise`](crate::storage::StateStorage::set_standby_promise).
+    pub fn set_standby(&mut self, standby: NodeId) -> Result<(), StandbyErr> {
+        let result = self.seq_paxos.set_standby(standby);
+        self.audit_log
+            .record(AuditAction::SetStandby(standby), &result);
+        result
+    }
+
+    /// Returns the hot-standby leader designated by this server as the leader and whether it is
+    /// ready to take over without a prepare phase.
+    pub fn get_standby(&self) -> Option<(NodeId, bool)> {
+        self.seq_paxos.get_standby()
+    }
+
+    /// Sets who initiates the following administrative operations on this server, e.g., the name
+    /// of an operator or tool, which is recorded in the audit log. Set to `None` if unknown.
+    pub fn set_audit_initiator(&mut self, initiator: Option<String>) {
+        self.audit_log.set_initiator(initiator)
+    }
+
+    /// Returns the audit log of this server: the most recent administrative operations, such as
+    /// `trim()`, `snapshot()`, `reconfigure()`, and `step_down()`, in the order in which they were
+    /// initiated, with their time, initiator, and outcome, e.g., for compliance or post-incident
+    /// reviews. The log is kept in memory and holds the last 1000 operations.
+    pub fn get_audit_log(&self) -> Vec<AuditRecord> {
+        self.audit_log.get_records()
+    }
+
+    /// Attaches `payload` to the heartbeats and heartbeat replies of this server, e.g., its load or
+    /// applied index, so that the other servers can base placement or routing decisions on it
+    /// without a separate gossip system. The payload is sent from the next heartbeat on and should
+    /// be small, as it is sent to every other server once per election timeout. Set to `None` to
+    /// stop sending it.
+    pub fn set_heartbeat_payload(&mut self, payload: Option<Vec<u8>>) {
+        self.ble.set_heartbeat_payload(payload)
+    }
+
+    /// Returns the latest payload that server `pid` attached to its heartbeats, see
+    /// [`OmniPaxos::set_heartbeat_payload`], or `None` if it did not attach one.
+    pub fn get_peer_payload(&self, pid: NodeId) -> Option<&[u8]> {
+        self.ble.get_peer_payload(pid)
+    }
+
+    /// Returns whether the messages to server `pid` may be compressed, i.e., whether `pid`
+    /// advertised the same [`ServerConfig::compression`] as this server with its latest heartbeat.
+    /// The compression is negotiated with the first heartbeats after a server starts or
+    /// [reconnects](OmniPaxos::reconnected), until which the messages to it should be sent
+    /// uncompressed, e.g., with `FrameCodec::encode_with` of the `framing` feature.
+    pub fn peer_accepts_compression(&self, pid: NodeId) -> bool {
+        self.ble.peer_accepts_compression(pid)
     }
 
     /// If the heartbeat of a leader is not received when election_timeout() is called, the server might attempt to become the leader.
     /// It is also used for the election process, where the server checks if it can become the leader.
     /// For instance if `election_timeout()` is called every 100ms, then if the leader fails, the servers will detect it after 100ms and elect a new server after another 100ms if possible.
-    fn election_timeout(&mut self) {
-        if let Some(new_leader) = self
+    fn election_timeout(&mut self) -> Result<(), ProtocolError> {
+        if self.seq_paxos.take_preempted() {
+            self.ble.step_down();
+        }
+        self.ble.set_standby(self.seq_paxos.get_election_standby());
+        let new_leader = self
             .ble
-            .hb_timeout(self.seq_paxos.get_state(), self.seq_paxos.get_promise())
-        {
-            self.seq_paxos.handle_leader(new_leader);
+            .hb_timeout(self.seq_paxos.get_state(), self.seq_paxos.get_promise());
+        self.persist_ble_ballot()?;
+        if let Some(new_leader) = new_leader {
+            self.seq_paxos.handle_leader(new_leader)?;
         }
+        Ok(())
+    }
+
+    /// Persists the current ballot of BLE so that it is never reused after a restart.
+    fn persist_ble_ballot(&mut self) -> Result<(), ProtocolError> {
+        self.seq_paxos
+            .set_ble_ballot(self.ble.get_current_ballot())?;
+        Ok(())
     }
 
     /// Returns the current states of the OmniPaxos instance for OmniPaxos UI to display.
     pub fn get_ui_states(&self) -> ui::OmniPaxosStates {
         let mut cluster_state = ClusterState::from(self.seq_paxos.get_leader_state());
         cluster_state.heartbeats = self.ble.get_ballots();
+        let pid = self.seq_paxos.get_pid();
+        if self.get_current_leader() == Some(pid) {
+            if let Some(decided_idx) = cluster_state.decided_indexes.get_mut(pid as usize) {
+                *decided_idx = self.get_decided_idx();
+            }
+        }
 
         ui::OmniPaxosStates {
             current_ballot: self.ble.get_current_ballot(),
@@ -419,42 +2052,745 @@ where
             decided_idx: self.get_decided_idx(),
             heartbeats: self.ble.get_ballots(),
             cluster_state,
+            commit_latency: self.get_commit_latency(),
+            proposal_affinity: self.get_proposal_affinity(),
         }
     }
 }
 
-/// An error indicating a failed proposal due to the current cluster configuration being already stopped
-/// or due to an invalid proposed configuration. Returns the failed proposal.
-#[derive(Debug)]
-pub enum ProposeErr<T>
+#[cfg(feature = "spill")]
+impl<T, B> OmniPaxos<T, B>
 where
-    T: Entry,
+    T: Entry + Serialize + for<'a> Deserialize<'a>,
+    B: Storage<T>,
+{
+    /// Spill outgoing sync messages, which may carry a large part of the log or a whole snapshot
+    /// to a follower that is catching up, to a temporary file in `dir` once the sync messages held
+    /// in memory exceed `memory_budget` bytes (as serialized by bincode). Spilled messages are read
+    /// back as they are taken with [`OmniPaxos::outgoing_messages`] or [`OmniPaxos::outgoing_for`].
+    /// The file is emptied whenever all spilled messages have been taken and removed when this
+    /// server is dropped. If writing to the file fails, the message is kept in memory instead.
+    pub fn spill_syncs_to_disk(&mut self, dir: &Path, memory_budget: u64) -> io::Result<()> {
+        let codec = SpillCodec {
+            spill_size: spill::sync_msg_size::<T>,
+            encode: spill::encode,
+            decode: spill::decode,
+        };
+        let spill = SpillFile::create(dir, memory_budget, codec)?;
+        self.seq_paxos.enable_spill(spill);
+        Ok(())
+    }
+}
+
+impl<T, B> OmniPaxos<T, B>
+where
+    T: Chunkable,
+    B: Storage<T>,
+{
+    /// Append an entry to the replicated log, split into chunk entries of at most `max_chunk_size`
+    /// bytes if it is larger than that. All chunks are proposed together and are thus decided
+    /// contiguously in the log. Use [`OmniPaxos::read_decided_suffix_reassembled`] to read the
+    /// entry back in one piece. Fails with [`ProposeErr::InvalidChunkSize`] if `max_chunk_size` is
+    /// 0.
+    pub fn append_chunked(&mut self, entry: T, max_chunk_size: usize) -> Result<(), ProposeErr<T>> {
+        if max_chunk_size == 0 {
+            return Err(ProposeErr::InvalidChunkSize(entry));
+        }
+        let entry = self.seq_paxos.check_appendable(entry)?;
+        let bytes = entry.to_bytes();
+        if bytes.len() <= max_chunk_size {
+            return self.append(entry);
+        }
+        let group = match self.chunk_group {
+            Some(group) => group,
+            None => self
+                .last_chunk_group()
+                .or_fatal(util::READ_ERROR_MSG)
+                .map_err(|e| ProposeErr::Failed(e.into()))?
+                .map_or(0, |group| group + 1),
+        };
+        let chunks = chunking::split(&bytes, max_chunk_size, self.seq_paxos.get_pid(), group);
+        let oversized_chunk = chunks
+            .iter()
+            .find_map(|chunk| self.seq_paxos.exceeds_max_entry_size(chunk));
+        if let Some((size, limit)) = oversized_chunk {
+            return Err(ProposeErr::EntryTooLarge { entry, size, limit });
+        }
+        self.chunk_group = Some(group + 1);
+        let res = self.seq_paxos.propose_entries(chunks);
+        self.seq_paxos.halt_if_failed(res)?;
+        Ok(())
+    }
+
+    /// Returns the highest group of the entries split by this server that are in its log, so that
+    /// the groups of a restarted server continue after those of the entries it split before.
+    fn last_chunk_group(&self) -> StorageResult<Option<u64>> {
+        let pid = self.seq_paxos.get_pid();
+        let storage = &self.seq_paxos.internal_storage;
+        let entries =
+            storage.get_entries(storage.get_compacted_idx(), storage.get_accepted_idx())?;
+        Ok(entries
+            .iter()
+            .filter_map(|entry| entry.as_chunk())
+            .filter(|(info, _)| info.pid == pid)
+            .map(|(info, _)| info.group)
+            .max())
+    }
+
+    /// Read all decided entries starting at `from_idx` (inclusive) in the log with chunked entries
+    /// reassembled. Chunks of an entry that is not completely decided yet are not returned. If
+    /// `from_idx` is in the middle of a chunked entry, the read starts at its first chunk so that
+    /// the entry is returned whole. Returns the entries together with the log index to continue
+    /// reading from, or `None` if `from_idx` is out of bounds.
+    pub fn read_decided_suffix_reassembled(
+        &self,
+        from_idx: usize,
+    ) -> Option<(Vec<LogEntry<T>>, usize)> {
+        let decided_idx = self.get_decided_idx();
+        let start_idx = match self.read(from_idx) {
+            Some(LogEntry::Decided(entry)) => entry.as_chunk().map_or(from_idx, |(info, _)| {
+                (from_idx - info.index as usize).max(self.get_compacted_idx())
+            }),
+            _ => from_idx,
+        };
+        let entries = self.read_decided_suffix(start_idx)?;
+        let (entries, withheld) = chunking::reassemble(entries);
+        Some((entries, decided_idx - withheld))
+    }
+}
+
+impl<T, B> OmniPaxos<T, B>
+where
+    T: SettingsEntry,
+    B: Storage<T>,
 {
-    /// Couldn't propose entry because a reconfiguration is pending. Returns the failed, proposed entry.
-    PendingReconfigEntry(T),
-    /// Couldn't propose reconfiguration because a reconfiguration is already pending. Returns the failed, proposed `ClusterConfig` and the metadata.
-    /// cluster config and metadata.
-    PendingReconfigConfig(ClusterConfig, Option<Vec<u8>>),
-    /// Couldn't propose reconfiguration because of an invalid cluster config. Contains the config
-    /// error and the failed, proposed cluster config and metadata.
-    ConfigError(ConfigError, ClusterConfig, Option<Vec<u8>>),
-}
-
-/// An error returning the proposal that was failed due to that the current configuration is stopped.
-#[derive(Copy, Clone, Debug)]
-pub enum CompactionErr {
-    /// Snapshot was called with an index that is not decided yet. Returns the currently decided index.
-    UndecidedIndex(usize),
-    /// Snapshot was called with an index which is already trimmed. Returns the currently compacted index.
-    TrimmedIndex(usize),
-    /// Trim was called with an index that is not decided by all servers yet. Returns the index decided by ALL servers currently.
-    NotAllDecided(usize),
-    /// Trim was called at a follower node. Trim must be called by the leader, which is the returned NodeId.
-    NotCurrentLeader(NodeId),
-}
-
-impl Error for CompactionErr {}
-impl Display for CompactionErr {
+    /// Start applying the settings changes decided in the log to this server. From then on, every
+    /// decided entry that holds a [`ReplicatedSettings`] change updates the settings of this server
+    /// as it is decided, so all servers that enabled replicated settings switch at the same log
+    /// index. Changes in the already compacted prefix of the log are not applied, so a recovered
+    /// server should be started with a `ServerConfig` that includes them. Returns the error if the
+    /// storage fails while the decided changes are applied.
+    pub fn enable_replicated_settings(&mut self) -> Result<(), ProtocolError> {
+        let res = self
+            .seq_paxos
+            .enable_replicated_settings(settings::extract::<T>);
+        self.seq_paxos.halt_if_failed(res)
+    }
+
+    /// Propose a change of the settings of all servers. The change is appended to the log as an
+    /// entry created with [`SettingsEntry::settings`] and takes effect on each server as it is
+    /// decided. Returns an error if the `settings` are invalid or the entry could not be appended.
+    pub fn propose_settings(&mut self, settings: ReplicatedSettings) -> Result<(), ProposeErr<T>> {
+        if let Err(config_error) = settings.validate() {
+            return Err(ProposeErr::InvalidSettings(config_error, settings));
+        }
+        self.append(T::settings(settings))
+    }
+}
+
+/// A server that runs with a different cluster configuration than this server.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct ConfigMismatch {
+    /// The server with the different configuration.
+    pub pid: NodeId,
+    /// The configuration of this server.
+    pub local: ConfigFingerprint,
+    /// The configuration of the server `pid`.
+    pub remote: ConfigFingerprint,
+}
+
+/// A fingerprint from the leader that differed from the fingerprint of the same decided entries in
+/// the log of this server, see [`OmniPaxos::take_divergence_alarms`].
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct DivergenceAlarm {
+    /// The leader that sent the fingerprint.
+    pub leader: NodeId,
+    /// The first index of the fingerprinted entries.
+    pub from_idx: usize,
+    /// The end (exclusive) of the fingerprinted entries.
+    pub to_idx: usize,
+    /// The fingerprint of the entries in the log of the leader.
+    pub leader_fingerprint: u64,
+    /// The fingerprint of the entries in the log of this server.
+    pub local_fingerprint: u64,
+}
+
+/// A timer of an `OmniPaxos` server, i.e., a process that has to be run periodically. The timers
+/// are run by `tick()` based on their `*_tick_timeout` in the [`ServerConfig`], or can be run
+/// individually with [`OmniPaxos::handle_timer`].
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+pub enum Timer {
+    /// Updates the leader election, e.g., to detect a failed leader. Run every
+    /// `election_tick_timeout` ticks.
+    Election,
+    /// Resends messages that might have been dropped. Run every `resend_message_tick_timeout` ticks.
+    ResendMessage,
+    /// Flushes the batched log entries. Run every `flush_batch_tick_timeout` ticks.
+    FlushBatch,
+    /// Forwards the batched proposals to the leader. Run every `forward_batch_tick_timeout` ticks.
+    ForwardBatch,
+    /// Pulls new log entries from the leader if `pull_tick_timeout` is set. Run every
+    /// `pull_tick_timeout` ticks.
+    Pull,
+    /// Verifies the next batch of the stored log. Run every `verification_tick_timeout` ticks.
+    Verification,
+    /// Sends a fingerprint of the newly decided entries to the followers if this server is the
+    /// leader. Run every `fingerprint_tick_timeout` ticks.
+    Fingerprint,
+    /// Sends snapshots to the backup nodes if this server is the leader. Run every
+    /// `backup_tick_timeout` ticks.
+    Backup,
+    /// Applies the `low_space_policy`, decided settings changes, and `snapshot_interval`. Run every
+    /// `compaction_tick_timeout` ticks.
+    Compaction,
+    /// Checks for slow followers if this server is the leader. Run on every tick, i.e., the
+    /// `slow_follower_tick_timeout` counts the runs of this timer.
+    SlowFollowers,
+    /// Reports the accepted entries that became durable if the storage persists its writes in the
+    /// background. Run on every tick. See
+    /// [`LogStorage::get_durable_idx`](crate::storage::LogStorage::get_durable_idx).
+    Durability,
+    /// Reports the storage as slow and steps down if `slow_storage_latency` is set. Run on every
+    /// tick, i.e., the `slow_storage_tick_timeout` counts the runs of this timer.
+    SlowStorage,
+    /// Drops the entries whose deadline has passed, see [`OmniPaxos::append_with_deadline`], and the
+    /// entries that have been buffered for too long if the `follower_append_policy` is
+    /// [`FollowerAppendPolicy::Buffer`]. Run on every tick, i.e., its `tick_timeout` counts the runs
+    /// of this timer.
+    BufferedProposals,
+}
+
+/// What a server does when its storage exceeds the `storage_size_limit` of its [`ServerConfig`].
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "toml_config", derive(Deserialize))]
+pub enum LowSpacePolicy {
+    /// Refuse new proposals on this server with [`ProposeErr::Full`] until the storage is below
+    /// the limit again.
+    #[default]
+    RefuseAppends,
+    /// Compact the decided log first, i.e., snapshot it locally if the entries support snapshots
+    /// or otherwise trim it if this server is the leader. New proposals are refused only if the
+    /// storage still exceeds the limit afterwards.
+    CompactThenRefuseAppends,
+}
+
+/// What a server does with the entries appended to it while it is not the leader.
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "toml_config", derive(Deserialize))]
+pub enum FollowerAppendPolicy {
+    /// Forward the entries to the leader. While no leader is known, they are buffered until one
+    /// is elected.
+    #[default]
+    Forward,
+    /// Refuse the entries with [`ProposeErr::NotLeader`], e.g., so that the client retries at the
+    /// leader.
+    Reject,
+    /// Forward the entries to the leader like `Forward`, but drop the entries that have been
+    /// buffered for `tick_timeout` calls to `tick()` without a leader being known. The dropped
+    /// entries can be taken with [`OmniPaxos::take_expired_proposals`] or
+    /// [`OmniPaxos::take_rejected_proposals`].
+    Buffer {
+        /// The number of calls to `tick()` an entry is buffered for at most. Must not be 0.
+        tick_timeout: u64,
+    },
+}
+
+/// When the entries that are trimmed or snapshotted are removed from the storage. In all modes,
+/// the log is compacted as soon as `trim()` or `snapshot()` returns, i.e., the compacted index
+/// and snapshot are updated in the storage and the compacted entries are no longer read. Only the
+/// removal of the entries with [`LogStorage::trim`](crate::storage::LogStorage::trim) is
+/// deferred.
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "toml_config", derive(Deserialize))]
+pub enum TrimMode {
+    /// Remove the entries as part of `trim()` and `snapshot()`.
+    #[default]
+    Immediate,
+    /// Remove the entries on the next compaction timer, i.e., within `compaction_tick_timeout`
+    /// calls to `tick()`.
+    Tick,
+    /// Only remove the entries when the [`TrimWorker`] returned by [`OmniPaxos::trim_worker`] is
+    /// run, e.g., on a thread or executor of the application.
+    Worker,
+}
+
+/// The progress of a reconfiguration, i.e., of its `StopSign`.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum ReconfigurationStatus {
+    /// The reconfiguration has been proposed by this server but not been accepted yet.
+    Proposed,
+    /// The reconfiguration has been accepted by this server but is not decided yet.
+    Accepted,
+    /// The reconfiguration is decided and the current configuration is stopped.
+    Decided,
+    /// The reconfiguration proposed by this server was refused by the leader's
+    /// `reconfiguration_validator`. Contains the reason.
+    Rejected(String),
+}
+
+/// The progress of a server recovering its state from the other servers, e.g., after restarting
+/// from its storage or after [`OmniPaxos::fail_recover`].
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct RecoveryProgress {
+    /// The length of the log of this server when the recovery started.
+    pub start_idx: usize,
+    /// The number of log entries restored from `source` so far.
+    pub entries_restored: usize,
+    /// The server that the state is restored from, i.e., the leader. `None` until the leader has
+    /// responded.
+    pub source: Option<NodeId>,
+    /// Whether the recovery has completed, i.e., this server is replicating the log of the
+    /// leader again.
+    pub completed: bool,
+}
+
+/// How the view of the leader election was reconciled with the state that Sequence Paxos
+/// recovered from the storage when a server was built, see
+/// [`OmniPaxos::take_startup_reconciliation`].
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum StartupReconciliation {
+    /// Nothing was promised before, so the server starts without recovering.
+    Fresh,
+    /// The leader election follows the recovered promise and the server recovers its log from
+    /// that leader.
+    Recover {
+        /// The recovered promise.
+        promise: Ballot,
+    },
+    /// The leader election does not follow the recovered promise, since it is the ballot of this
+    /// server from before the restart or of a previous configuration. A new leader is elected right
+    /// away and the server recovers its log from it.
+    NewElection {
+        /// The recovered promise.
+        promise: Ballot,
+    },
+    /// The recovered promise was missing although entries were accepted, or older than the
+    /// accepted round, e.g., because the storage lost a write. The promise was raised to the
+    /// accepted round and the server was forced to recover its log.
+    ForcedRecovery {
+        /// The promise that was recovered from the storage, if any.
+        recovered_promise: Option<Ballot>,
+        /// The recovered accepted round. The promise was raised to it if it was older.
+        accepted_round: Ballot,
+        /// Whether a new leader is elected, as for [`StartupReconciliation::NewElection`].
+        new_election: bool,
+    },
+}
+
+/// The latency from when the leader appends entries until they are decided, over the most
+/// recently decided appends on this server while it was the leader.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct CommitLatency {
+    /// The median commit latency.
+    pub p50: Duration,
+    /// The 99th percentile commit latency.
+    pub p99: Duration,
+    /// The number of appends the percentiles are computed over.
+    pub samples: usize,
+}
+
+/// The proposals that a server received from another server, or appended itself, and the latency
+/// that server observed for its proposals. See [`OmniPaxos::get_proposal_affinity`].
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
+pub struct ProposalAffinity {
+    /// The number of proposals received from the server.
+    pub proposals: u64,
+    /// The latest latency the server reported for its proposals, from when it forwarded them until
+    /// they were decided in its log. For this server itself, its commit latency while it is the
+    /// leader. `None` until the server has reported one.
+    pub latency: Option<CommitLatency>,
+}
+
+/// The staleness a read from the log may have, in the order of increasing latency.
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
+pub enum ReadConsistency {
+    /// Reads the log of this server without contacting other servers. The decided entries are
+    /// final, but a follower or a deposed leader might not have the latest decided entries.
+    #[default]
+    Local,
+    /// Reads the log of the leader while it holds a lease, i.e., while no other server can have
+    /// been elected. Requires the leader to have bounded clock drift.
+    LeaderLease,
+    /// Reads the log of the leader after confirming with a quorum that it is still the leader,
+    /// so that all entries decided before the read started are returned.
+    Linearizable,
+}
+
+/// An opaque token of the log a client has observed, i.e., the decided index and the ballot of
+/// the server it last read from. Passing the token to the next read or append of the client, e.g.,
+/// with [`OmniPaxos::read_monotonic`], guarantees that the client never observes an older log than
+/// before, even if it is switched to a server that is behind.
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct ReadToken {
+    decided_idx: usize,
+    ballot: Ballot,
+}
+
+impl ReadToken {
+    /// Whether a server with the log of `current` has observed everything observed with this
+    /// token. A server of a later configuration has, since the log of a configuration is decided
+    /// before the next one starts.
+    fn is_covered_by(&self, current: &ReadToken) -> bool {
+        current.ballot.config_id > self.ballot.config_id
+            || (current.ballot >= self.ballot && current.decided_idx >= self.decided_idx)
+    }
+}
+
+/// The occupancy of the outgoing queues and proposal buffers of a server, see
+/// [`OmniPaxos::get_outgoing_stats`].
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct OutgoingStats {
+    /// The outgoing messages that have not been taken by the user yet.
+    pub queued_messages: usize,
+    /// The proposals buffered until there is a leader to append or forward them to.
+    pub pending_proposals: usize,
+    /// The proposals held by the leader until the entries they depend on are decided, see
+    /// [`Entry::dependency`].
+    pub dependent_proposals: usize,
+    /// The number of entries the leader has accepted but each promised follower has not. Empty if
+    /// this server is not the leader.
+    pub in_flight: HashMap<NodeId, usize>,
+    /// The messages from the leader that were dropped because they were outdated, e.g.,
+    /// duplicates or messages from a previous session.
+    pub outdated_messages: u64,
+    /// The messages from the leader that were dropped because preceding messages were lost.
+    pub dropped_preceding_messages: u64,
+    /// The outdated messages from the leader that had already been received.
+    pub duplicate_messages: u64,
+    /// The messages from the leader that arrived ahead of preceding messages and were handled once
+    /// those arrived, see `reorder_window` in [`ServerConfig`].
+    pub reordered_messages: u64,
+}
+
+/// The sequence-number recovery events of the accept-phase messages exchanged with a peer, see
+/// [`OmniPaxos::get_sequence_recovery_stats`].
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
+pub struct SequenceRecoveryStats {
+    /// The messages from the peer as the leader that were dropped because they were from a
+    /// previous session. Unlike the `outdated_messages` of [`OutgoingStats`], duplicates of the
+    /// current session don't count.
+    pub outdated_messages: u64,
+    /// The messages from the peer as the leader that were dropped because preceding messages were
+    /// lost. Each of them made this server resynchronize with the leader.
+    pub dropped_preceding_messages: u64,
+    /// The times this server as the leader resynchronized the peer after it had already been
+    /// synchronized in the same leadership, which restarts the session of their accept sequence.
+    pub session_resets: u64,
+}
+
+/// The approximate number of bytes occupied by the in-memory buffers of a server, see
+/// [`OmniPaxos::get_memory_usage`]. The entries count with their [`Entry::size_hint`].
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
+pub struct MemoryUsage {
+    /// The outgoing messages that have not been taken by the user yet. Messages spilled to disk
+    /// don't count.
+    pub outgoing: usize,
+    /// The proposals buffered, batched for forwarding, or held until their dependencies are
+    /// decided, and the entries the leader batched before flushing them to storage.
+    pub pending_proposals: usize,
+    /// The recently appended entries kept in the cache of the log tail, see `decided_cache_size`
+    /// in [`ServerConfig`].
+    pub caches: usize,
+    /// The log sync of the last Promise of a follower and the messages from the leader that
+    /// overtook preceding ones, see `reorder_window` in [`ServerConfig`].
+    pub sync: usize,
+}
+
+impl MemoryUsage {
+    /// The total number of bytes occupied by the buffers.
+    pub fn total(&self) -> usize {
+        self.outgoing + self.pending_proposals + self.caches + self.sync
+    }
+}
+
+/// The likely reason why a follower is behind the leader.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum SlowFollowerCause {
+    /// The follower did not reply to the leader election heartbeats, i.e., it is disconnected or
+    /// has crashed.
+    Disconnected,
+    /// The follower rejected the leader's messages because it promised another leader.
+    Nacks,
+    /// The follower is connected but has not acknowledged the entries, e.g., because its storage
+    /// or the link to it is slow.
+    NoAcks,
+}
+
+/// An advisory event about a follower that is behind the leader, see
+/// [`OmniPaxos::take_slow_follower_events`].
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum SlowFollowerEvent {
+    /// The follower exceeded the configured lag.
+    Slow {
+        /// The id of the follower.
+        pid: NodeId,
+        /// The number of entries the follower is behind the leader.
+        lag: usize,
+        /// The number of ticks since the follower fell behind.
+        lagging_ticks: u64,
+        /// The likely cause.
+        cause: SlowFollowerCause,
+    },
+    /// The follower that was reported slow is within the configured lag again.
+    CaughtUp(NodeId),
+}
+
+/// A jump of the clocks of a server detected on `tick()`, see [`OmniPaxos::take_clock_jumps`].
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum ClockJump {
+    /// `tick()` was not called for `gap` according to the monotonic clock, e.g., because the
+    /// process or the VM was paused.
+    Suspended {
+        /// The time since the previous tick.
+        gap: Duration,
+    },
+    /// The wall clock advanced `offset` more than the monotonic clock since the previous tick,
+    /// e.g., because the machine hibernated or the wall clock was corrected.
+    WallClockForward {
+        /// How much further the wall clock advanced.
+        offset: Duration,
+    },
+    /// The wall clock advanced `offset` less than the monotonic clock since the previous tick,
+    /// i.e., it was set back.
+    WallClockBackward {
+        /// How much less the wall clock advanced.
+        offset: Duration,
+    },
+}
+
+/// An event about the storage of the leader being slow, see
+/// [`OmniPaxos::take_slow_storage_events`].
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum SlowStorageEvent {
+    /// The appends of the leader exceeded the `slow_storage_latency` for the
+    /// `slow_storage_tick_timeout`.
+    Slow {
+        /// The average latency of the appends in the last tick.
+        latency: Duration,
+        /// Whether the leader stepped down because of it.
+        stepped_down: bool,
+    },
+    /// The storage that was reported slow is within the `slow_storage_latency` again.
+    Recovered,
+}
+
+/// Why an incoming message was rejected, see [`OmniPaxos::take_misbehaving_peers`].
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum RejectedMessageCause {
+    /// The sender is not a server of the cluster.
+    NonMember,
+    /// The message carries a ballot of a server that is not a server of the cluster.
+    NonMemberBallot,
+    /// The message claims a decided index that is larger than its accepted index.
+    DecidedBeyondAccepted,
+    /// The message claims to have accepted entries beyond the end of the log of this leader.
+    AcceptedBeyondLog,
+    /// The message was sent by a cluster of another namespace, see `namespace` in
+    /// [`ClusterConfig`].
+    ForeignNamespace,
+    /// The message carries a snapshot that is not sealed with the `snapshot_sealer` in the
+    /// [`ServerConfig`] or fails to open with it, e.g., because its signature is invalid.
+    InvalidSnapshot,
+}
+
+/// A peer whose rejected messages reached a multiple of the `misbehaving_peer_threshold`, see
+/// [`OmniPaxos::take_misbehaving_peers`].
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct MisbehavingPeer {
+    /// The sender of the rejected messages.
+    pub pid: NodeId,
+    /// The total number of messages rejected from `pid`.
+    pub rejected: u64,
+    /// Why the last message from `pid` was rejected.
+    pub cause: RejectedMessageCause,
+}
+
+/// Why a proposal was dropped before it was decided, see [`OmniPaxos::take_rejected_proposals`].
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub enum ProposalRejectionCause {
+    /// The proposal was buffered for longer than the `tick_timeout` of the
+    /// [`FollowerAppendPolicy::Buffer`] policy without a leader being known.
+    Expired,
+    /// The leader dropped the forwarded proposal because its storage reached the
+    /// `storage_size_limit`.
+    StorageFull,
+    /// The leader dropped the proposal because the cluster is being reconfigured, i.e., no more
+    /// entries are appended to the log of the current configuration.
+    Reconfigured,
+    /// The proposal was appended to the log by a leader, but overwritten before it was decided
+    /// after another server became the leader. Detected when the log of the former leader is
+    /// synchronized with the new leader and no longer reaches the proposal or, if
+    /// `track_entry_ballots` is enabled in [`ServerConfig`], holds an entry of another leader at
+    /// its index.
+    Truncated,
+    /// The leader dropped the forwarded proposal because it is larger than its `max_entry_size`.
+    EntryTooLarge,
+    /// The leader dropped the forwarded proposal because its buffers exceeded its `memory_limit`.
+    MemoryLimitExceeded,
+    /// The proposal was not appended by a leader before the deadline it was appended with, see
+    /// [`OmniPaxos::append_with_deadline`].
+    DeadlineExceeded,
+}
+
+/// A proposal appended on this server that was dropped before it was decided, see
+/// [`OmniPaxos::take_rejected_proposals`].
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct RejectedProposal<T> {
+    /// The dropped entry.
+    pub entry: T,
+    /// The token the entry was appended with, if it was appended with
+    /// [`OmniPaxos::append_with_token`].
+    pub token: Option<OriginToken>,
+    /// Why the entry was dropped.
+    pub cause: ProposalRejectionCause,
+}
+
+/// The outcome of an entry appended with [`OmniPaxos::append_batch`].
+#[derive(Debug)]
+pub enum AppendOutcome<T: Entry> {
+    /// The entry was appended to the log of this server, which is the leader.
+    Accepted,
+    /// The entry was forwarded to the leader, or held until a leader is elected, the leader has
+    /// synchronized the log, or the entry it depends on is decided. The entry is either decided
+    /// later or returned by [`OmniPaxos::take_rejected_proposals`].
+    Deferred,
+    /// The entry was not appended. Returns the error, which holds the entry.
+    Rejected(ProposeErr<T>),
+}
+
+/// The outcome of a message handled with [`OmniPaxos::handle_incoming_batch`].
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum MessageOutcome {
+    /// The message was handled.
+    Handled,
+    /// The message was dropped without being handled, e.g., because it is from a server with a
+    /// different configuration.
+    Ignored,
+    /// The message was rejected, see [`OmniPaxos::take_misbehaving_peers`].
+    Rejected(RejectedMessageCause),
+    /// Handling the message failed like [`OmniPaxos::handle_incoming`] does.
+    Failed(ProtocolError),
+}
+
+/// The outcomes of the items of a batched operation, in the order of the items.
+#[derive(Debug)]
+pub struct BatchResult<O> {
+    /// The outcome of each item.
+    pub outcomes: Vec<O>,
+}
+
+impl<O> BatchResult<O> {
+    /// Returns the number of items in the batch.
+    pub fn len(&self) -> usize {
+        self.outcomes.len()
+    }
+
+    /// Returns whether the batch was empty.
+    pub fn is_empty(&self) -> bool {
+        self.outcomes.is_empty()
+    }
+}
+
+impl<T: Entry> BatchResult<AppendOutcome<T>> {
+    /// Returns the number of entries that were appended to the log of this server.
+    pub fn num_accepted(&self) -> usize {
+        self.count(|o| matches!(o, AppendOutcome::Accepted))
+    }
+
+    /// Returns the number of entries that were forwarded or held.
+    pub fn num_deferred(&self) -> usize {
+        self.count(|o| matches!(o, AppendOutcome::Deferred))
+    }
+
+    /// Returns whether no entry was rejected.
+    pub fn is_ok(&self) -> bool {
+        !self
+            .outcomes
+            .iter()
+            .any(|o| matches!(o, AppendOutcome::Rejected(_)))
+    }
+
+    /// Returns the errors of the rejected entries together with their position in the batch.
+    pub fn into_rejected(self) -> Vec<(usize, ProposeErr<T>)> {
+        self.outcomes
+            .into_iter()
+            .enumerate()
+            .filter_map(|(i, o)| match o {
+                AppendOutcome::Rejected(e) => Some((i, e)),
+                _ => None,
+            })
+            .collect()
+    }
+
+    fn count(&self, f: impl Fn(&AppendOutcome<T>) -> bool) -> usize {
+        self.outcomes.iter().filter(|o| f(o)).count()
+    }
+}
+
+impl BatchResult<MessageOutcome> {
+    /// Returns the number of messages that were handled.
+    pub fn num_handled(&self) -> usize {
+        self.outcomes
+            .iter()
+            .filter(|o| **o == MessageOutcome::Handled)
+            .count()
+    }
+
+    /// Returns the causes of the rejected messages together with their position in the batch.
+    pub fn rejected(&self) -> Vec<(usize, RejectedMessageCause)> {
+        self.outcomes
+            .iter()
+            .enumerate()
+            .filter_map(|(i, o)| match o {
+                MessageOutcome::Rejected(cause) => Some((i, *cause)),
+                _ => None,
+            })
+            .collect()
+    }
+}
+
+/// An error indicating why [`OmniPaxos::unsafe_truncate_after`] refused to truncate the log.
+#[cfg(feature = "operator_tools")]
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum TruncationErr {
+    /// The log of the leader can't be truncated. Returns the pid of the leader, i.e., this server.
+    CurrentLeader(NodeId),
+    /// The index is decided and the entries from it can thus not be discarded. Returns the
+    /// currently decided index.
+    DecidedIndex(usize),
+    /// The index is beyond the end of the log. Returns the currently accepted index.
+    OutOfBounds(usize),
+    /// The storage failed while truncating the log.
+    Failed(ProtocolError),
+}
+
+#[cfg(feature = "operator_tools")]
+impl Error for TruncationErr {}
+#[cfg(feature = "operator_tools")]
+impl Display for TruncationErr {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        Debug::fmt(self, f)
+    }
+}
+
+/// An error indicating why [`OmniPaxos::bootstrap_from_peer`] or [`OmniPaxos::recover_seamlessly`]
+/// could not be started.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum BootstrapErr {
+    /// The storage of this server is not empty, i.e., it has already promised or accepted entries.
+    NotWiped,
+    /// The given server is not a peer of this server in the current configuration.
+    UnknownPeer(NodeId),
+    /// The other servers can't form a quorum without this server.
+    NoQuorum,
+}
+
+impl Error for BootstrapErr {}
+impl Display for BootstrapErr {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         Debug::fmt(self, f)
     }
@@ -0,0 +1,83 @@
+use crate::util::defaults::PIPELINE_EVENTS;
+use std::{collections::VecDeque, time::Instant};
+
+/// A stage of the replication pipeline that log entries pass through, in order.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub enum PipelineStage {
+    /// The leader assigned the entries their position in the log. The entries might still be
+    /// buffered in the batch of the leader. Only observed by the leader.
+    Proposed,
+    /// The entries were written to the storage of this server.
+    Appended,
+    /// A quorum of servers has accepted the entries. Only observed by the leader.
+    Replicated,
+    /// This server learned that the entries are decided, i.e., before the decided index is
+    /// written to its storage.
+    Decided,
+    /// The decided index was written to the storage of this server, so that the entries are
+    /// returned by reads from this server and its [`OmniPaxosReader`](crate::OmniPaxosReader)s.
+    Readable,
+}
+
+/// The log entries `from_idx..to_idx` reached `stage` on this server at `time`.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct PipelineEvent {
+    /// The stage the entries reached.
+    pub stage: PipelineStage,
+    /// The index of the first entry.
+    pub from_idx: usize,
+    /// The index after the last entry.
+    pub to_idx: usize,
+    /// When the entries reached the stage.
+    pub time: Instant,
+}
+
+/// Turns the advancing indexes of the log into the events of the entries passing each stage.
+#[derive(Debug, Default)]
+pub(crate) struct PipelineTracker {
+    // The index up to which the entries have reached each stage
+    reached: [usize; 5],
+    events: VecDeque<PipelineEvent>,
+}
+
+impl PipelineTracker {
+    /// Creates a tracker for a log whose entries up to `accepted_idx` are accepted, and up to
+    /// `decided_idx` are decided, without any events for them.
+    pub(crate) fn starting_at(accepted_idx: usize, decided_idx: usize) -> Self {
+        let mut tracker = Self::default();
+        tracker.reset(PipelineStage::Proposed, accepted_idx);
+        tracker.reset(PipelineStage::Appended, accepted_idx);
+        tracker.reset(PipelineStage::Replicated, decided_idx);
+        tracker.reset(PipelineStage::Decided, decided_idx);
+        tracker.reset(PipelineStage::Readable, decided_idx);
+        tracker
+    }
+
+    /// Sets the index up to which the entries have reached `stage` without an event, e.g., for the
+    /// entries that a new leader takes over.
+    pub(crate) fn reset(&mut self, stage: PipelineStage, idx: usize) {
+        self.reached[stage as usize] = idx;
+    }
+
+    /// Records that the entries up to `idx` have reached `stage`. An index below the previous one,
+    /// e.g., after the log was truncated, only resets the stage.
+    pub(crate) fn reached(&mut self, stage: PipelineStage, idx: usize) {
+        let reached = &mut self.reached[stage as usize];
+        if idx > *reached {
+            if self.events.len() == PIPELINE_EVENTS {
+                self.events.pop_front();
+            }
+            self.events.push_back(PipelineEvent {
+                stage,
+                from_idx: *reached,
+                to_idx: idx,
+                time: Instant::now(),
+            });
+        }
+        *reached = idx;
+    }
+
+    pub(crate) fn take_events(&mut self) -> Vec<PipelineEvent> {
+        self.events.drain(..).collect()
+    }
+}
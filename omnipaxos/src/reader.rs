@@ -0,0 +1,219 @@
+use crate::{
+    ballot_leader_election::Ballot,
+    storage::{internal_storage::PublishedIndexes, Entry, Storage, StorageResult},
+    util::{
+        self, IndexedLogEntry, LogEntry, NodeId, SnapshottedEntry, LOCK_POISONED_MSG,
+        READ_ERROR_MSG,
+    },
+};
+use std::{
+    marker::PhantomData,
+    sync::{Arc, Mutex, MutexGuard},
+};
+
+/// A read-only handle to the log of an [`OmniPaxos`](crate::OmniPaxos) server, created with
+/// [`OmniPaxos::reader`](crate::OmniPaxos::reader). The reader can be cloned cheaply and moved to
+/// other threads, where it reads the decided log and status of the server while the server
+/// itself keeps handling messages.
+///
+/// Every call of a reader sees a consistent snapshot of the log, i.e., it is never interleaved
+/// with a write of the server. Consecutive calls might however observe different states of the
+/// log, use [`OmniPaxosReader::batch`] to make several reads from the same state. The indexes of
+/// the log are read without locking the storage, so that status queries and metrics never wait
+/// for the server to handle messages.
+pub struct OmniPaxosReader<T, B>
+where
+    T: Entry,
+    B: Storage<T>,
+{
+    storage: Arc<Mutex<B>>,
+    indexes: Arc<PublishedIndexes>,
+    _t: PhantomData<T>,
+}
+
+impl<T, B> Clone for OmniPaxosReader<T, B>
+where
+    T: Entry,
+    B: Storage<T>,
+{
+    fn clone(&self) -> Self {
+        Self {
+            storage: Arc::clone(&self.storage),
+            indexes: Arc::clone(&self.indexes),
+            _t: PhantomData,
+        }
+    }
+}
+
+impl<T, B> OmniPaxosReader<T, B>
+where
+    T: Entry,
+    B: Storage<T>,
+{
+    pub(crate) fn with(storage: Arc<Mutex<B>>, indexes: Arc<PublishedIndexes>) -> Self {
+        Self {
+            storage,
+            indexes,
+            _t: PhantomData,
+        }
+    }
+
+    /// Returns the decided index.
+    pub fn get_decided_idx(&self) -> usize {
+        self.indexes.get_decided_idx()
+    }
+
+    /// Returns the accepted index, i.e., the length of the log including the entries that are not
+    /// decided yet.
+    pub fn get_accepted_idx(&self) -> usize {
+        self.indexes.get_accepted_idx()
+    }
+
+    /// Returns the index of the last compacted entry.
+    pub fn get_compacted_idx(&self) -> usize {
+        self.indexes.get_compacted_idx()
+    }
+
+    /// Returns the promised ballot of the server.
+    pub fn get_promise(&self) -> Ballot {
+        let storage = self.storage.lock().expect(LOCK_POISONED_MSG);
+        storage
+            .get_promise()
+            .expect(READ_ERROR_MSG)
+            .unwrap_or_default()
+    }
+
+    /// Returns the id of the leader the server has promised to.
+    pub fn get_current_leader(&self) -> Option<NodeId> {
+        match self.get_promise().pid {
+            0 => None,
+            pid => Some(pid),
+        }
+    }
+
+    /// Read the decided entry at index `idx` in the log. Returns `None` if `idx` is not decided.
+    pub fn read(&self, idx: usize) -> Option<LogEntry<T>> {
+        self.batch().read(idx)
+    }
+
+    /// Read all decided entries starting at `from_idx` (inclusive) in the log. Returns `None` if
+    /// `from_idx` is out of bounds.
+    pub fn read_decided_suffix(&self, from_idx: usize) -> Option<Vec<LogEntry<T>>> {
+        self.batch().read_decided_suffix(from_idx)
+    }
+
+    /// Like [`read_decided_suffix`](Self::read_decided_suffix), but returns every entry together
+    /// with its index in the log.
+    pub fn read_decided_suffix_indexed(&self, from_idx: usize) -> Option<Vec<IndexedLogEntry<T>>> {
+        self.read_decided_suffix(from_idx)
+            .map(|entries| util::with_indexes(from_idx, entries))
+    }
+
+    /// Returns a view of the log in which all reads observe the same state of the log. See
+    /// [`ReadBatch`].
+    pub fn batch(&self) -> ReadBatch<'_, T, B> {
+        ReadBatch {
+            storage: self.storage.lock().expect(LOCK_POISONED_MSG),
+            _t: PhantomData,
+        }
+    }
+}
+
+/// A view of the log of an [`OmniPaxos`](crate::OmniPaxos) server created with
+/// [`OmniPaxosReader::batch`], in which all reads observe the same state of the log, e.g., so that
+/// an apply loop reads the decided index and the entries up to it without the log being
+/// compacted in between.
+///
+/// The view holds the lock of the storage, i.e., the server waits for the view to be dropped
+/// before it writes to the storage again, e.g., to append or decide entries. The view should thus
+/// only be kept while reading and be dropped before the read entries are applied.
+pub struct ReadBatch<'a, T, B>
+where
+    T: Entry,
+    B: Storage<T>,
+{
+    storage: MutexGuard<'a, B>,
+    _t: PhantomData<T>,
+}
+
+impl<T, B> ReadBatch<'_, T, B>
+where
+    T: Entry,
+    B: Storage<T>,
+{
+    /// Returns the decided index.
+    pub fn get_decided_idx(&self) -> usize {
+        self.storage.get_decided_idx().expect(READ_ERROR_MSG)
+    }
+
+    /// Returns the index of the last compacted entry.
+    pub fn get_compacted_idx(&self) -> usize {
+        self.storage.get_compacted_idx().expect(READ_ERROR_MSG)
+    }
+
+    /// Read the decided entry at index `idx` in the log. Returns `None` if `idx` is not decided.
+    pub fn read(&self, idx: usize) -> Option<LogEntry<T>> {
+        self.read_decided(idx, Some(idx + 1))
+            .expect(READ_ERROR_MSG)
+            .and_then(|mut entries| entries.pop())
+    }
+
+    /// Read the decided entries from `from_idx` (inclusive) to `to_idx` (exclusive) in the log,
+    /// e.g., to apply a bounded number of entries at a time. Returns `None` if `from_idx` is not
+    /// decided.
+    pub fn read_decided_range(&self, from_idx: usize, to_idx: usize) -> Option<Vec<LogEntry<T>>> {
+        self.read_decided(from_idx, Some(to_idx))
+            .expect(READ_ERROR_MSG)
+    }
+
+    /// Read all decided entries starting at `from_idx` (inclusive) in the log. Returns `None` if
+    /// `from_idx` is out of bounds.
+    pub fn read_decided_suffix(&self, from_idx: usize) -> Option<Vec<LogEntry<T>>> {
+        self.read_decided(from_idx, None).expect(READ_ERROR_MSG)
+    }
+
+    /// Like [`read_decided_suffix`](Self::read_decided_suffix), but returns every entry together
+    /// with its index in the log.
+    pub fn read_decided_suffix_indexed(&self, from_idx: usize) -> Option<Vec<IndexedLogEntry<T>>> {
+        self.read_decided_suffix(from_idx)
+            .map(|entries| util::with_indexes(from_idx, entries))
+    }
+
+    /// Reads the decided entries from `from_idx` to `to_idx` (exclusive), or to the decided index
+    /// if `to_idx` is `None`.
+    fn read_decided(
+        &self,
+        from_idx: usize,
+        to_idx: Option<usize>,
+    ) -> StorageResult<Option<Vec<LogEntry<T>>>> {
+        let storage = &self.storage;
+        let decided_idx = storage.get_decided_idx()?;
+        let to_idx = to_idx.map_or(decided_idx, |idx| idx.min(decided_idx));
+        if from_idx >= to_idx {
+            return Ok(None);
+        }
+        let compacted_idx = storage.get_compacted_idx()?;
+        let log_len = compacted_idx + storage.get_log_len()?;
+        let mut entries = vec![];
+        if from_idx < compacted_idx {
+            let compacted = match storage.get_snapshot()? {
+                Some(s) => LogEntry::Snapshotted(SnapshottedEntry::with(compacted_idx, s)),
+                None => LogEntry::Trimmed(compacted_idx),
+            };
+            entries.push(compacted);
+        }
+        let entries_from = from_idx.max(compacted_idx);
+        let entries_to = to_idx.min(log_len);
+        if entries_from < entries_to {
+            let decided = storage.get_entries(entries_from, entries_to)?;
+            entries.extend(decided.into_iter().map(LogEntry::Decided));
+        }
+        // A decided StopSign is the last entry of the log
+        if to_idx > log_len {
+            if let Some(ss) = storage.get_stopsign()? {
+                entries.push(LogEntry::StopSign(ss, true));
+            }
+        }
+        Ok(Some(entries))
+    }
+}
@@ -0,0 +1,141 @@
+use crate::{
+    errors::TransitionErr,
+    storage::{Entry, StopSign, Storage},
+    util::{ConfigurationId, LogEntry},
+    OmniPaxos, ServerConfig,
+};
+
+/// A decided entry delivered by a [`ConfigurationManager`], together with the configuration it was
+/// decided in and its index in the log of that configuration.
+#[derive(Debug, Clone)]
+pub struct ConfiguredEntry<T>
+where
+    T: Entry,
+{
+    /// The id of the configuration the entry was decided in.
+    pub configuration_id: ConfigurationId,
+    /// The index of the entry in the log of the configuration. A `Trimmed` or `Snapshotted` entry
+    /// covers all indexes from `idx` up to its compacted index.
+    pub idx: usize,
+    /// The decided entry.
+    pub entry: LogEntry<T>,
+}
+
+/// Delivers the decided entries of a server across reconfigurations as a single stream, so that
+/// an apply loop doesn't have to stitch the logs of consecutive [`OmniPaxos`] instances together.
+///
+/// The stream of a configuration delivers all of its decided entries in the order of the log, and
+/// then its decided StopSign as the last entry. Nothing is delivered after the StopSign until the
+/// instance of the next configuration is started with [`ConfigurationManager::start_next`], after
+/// which the stream continues with the entries of the next configuration from the start of its
+/// log. The entries of a configuration are thus never interleaved with those of another.
+pub struct ConfigurationManager<T, B>
+where
+    T: Entry,
+    B: Storage<T>,
+{
+    current: OmniPaxos<T, B>,
+    // The index of the next entry of the current configuration to deliver
+    next_idx: usize,
+    // The decided StopSign of the current configuration, once it is delivered
+    stopsign: Option<StopSign>,
+}
+
+impl<T, B> ConfigurationManager<T, B>
+where
+    T: Entry,
+    B: Storage<T>,
+{
+    /// Creates a manager that delivers the decided entries of `omni_paxos` starting at `from_idx`,
+    /// e.g., the index up to which the application has applied the log before a restart.
+    pub fn with(omni_paxos: OmniPaxos<T, B>, from_idx: usize) -> Self {
+        Self {
+            current: omni_paxos,
+            next_idx: from_idx,
+            stopsign: None,
+        }
+    }
+
+    /// Returns the instance of the current configuration.
+    pub fn current(&self) -> &OmniPaxos<T, B> {
+        &self.current
+    }
+
+    /// Returns the instance of the current configuration, e.g., to append entries or handle
+    /// messages.
+    pub fn current_mut(&mut self) -> &mut OmniPaxos<T, B> {
+        &mut self.current
+    }
+
+    /// Returns the id of the current configuration.
+    pub fn get_configuration_id(&self) -> ConfigurationId {
+        self.current.get_cluster_config().configuration_id
+    }
+
+    /// Returns the index of the next entry of the current configuration to deliver.
+    pub fn get_delivered_idx(&self) -> usize {
+        self.next_idx
+    }
+
+    /// Returns the decided StopSign of the current configuration if it has been delivered, i.e.,
+    /// the instance of the next configuration can be started.
+    pub fn get_delivered_stopsign(&self) -> Option<&StopSign> {
+        self.stopsign.as_ref()
+    }
+
+    /// Returns the decided entries of the current configuration that were not delivered yet. The
+    /// decided StopSign is the last entry returned for a configuration, after which no entries
+    /// are returned until [`ConfigurationManager::start_next`] is called.
+    pub fn poll_decided(&mut self) -> Vec<ConfiguredEntry<T>> {
+        if self.stopsign.is_some() {
+            return vec![];
+        }
+        let configuration_id = self.get_configuration_id();
+        let Some(entries) = self.current.read_decided_suffix_indexed(self.next_idx) else {
+            return vec![];
+        };
+        let mut delivered = Vec::with_capacity(entries.len());
+        for e in entries {
+            self.next_idx = match &e.entry {
+                LogEntry::Trimmed(compacted_idx) => *compacted_idx,
+                LogEntry::Snapshotted(s) => s.trimmed_idx,
+                _ => e.idx + 1,
+            };
+            if let LogEntry::StopSign(ss, _) = &e.entry {
+                self.stopsign = Some(ss.clone());
+            }
+            delivered.push(ConfiguredEntry {
+                configuration_id,
+                idx: e.idx,
+                entry: e.entry,
+            });
+            if self.stopsign.is_some() {
+                break;
+            }
+        }
+        delivered
+    }
+
+    /// Starts the instance of the next configuration with `server_config` and `storage` once the
+    /// decided StopSign of the current configuration has been delivered by
+    /// [`ConfigurationManager::poll_decided`]. The instance is built from the cluster config in
+    /// the StopSign, and the stream continues with its entries from index `0`. Returns the
+    /// instance of the stopped configuration, which should keep handling the messages of its
+    /// configuration for a while so that the servers that are behind can also decide the
+    /// StopSign.
+    pub fn start_next(
+        &mut self,
+        server_config: ServerConfig,
+        storage: B,
+    ) -> Result<OmniPaxos<T, B>, TransitionErr> {
+        let ss = self.stopsign.as_ref().ok_or(TransitionErr::NotStopped)?;
+        let next = ss
+            .next_config
+            .clone()
+            .build_for_server(server_config, storage)
+            .map_err(TransitionErr::InvalidConfig)?;
+        self.stopsign = None;
+        self.next_idx = 0;
+        Ok(std::mem::replace(&mut self.current, next))
+    }
+}
@@ -0,0 +1,124 @@
+use crate::{
+    errors::SealingErr,
+    storage::{Entry, SnapshotType},
+};
+use serde::{Deserialize, Serialize};
+use std::{error::Error, fmt::Debug};
+
+/// Encrypts and signs the snapshots that are sent to other servers, e.g., over links between data
+/// centers that are not trusted, with the key material of the application, e.g., keys fetched
+/// from a key management service. A snapshot is serialized, encrypted with the current key, and
+/// signed together with the position in the log it is sent for. The receiver verifies the
+/// signature before it decrypts and installs the snapshot, and rejects unsealed snapshots.
+///
+/// Keys are referred to by an id that is sent along with the snapshot, so that they can be rotated
+/// while the receivers still know the previous keys. The sealer must be set on all servers of the
+/// cluster with `snapshot_sealer` in the [`crate::ServerConfig`]. OmniPaxos does not implement any
+/// cryptography itself.
+pub trait SnapshotSealer: Debug + Send + Sync {
+    /// The id of the key that new snapshots are sealed with.
+    fn current_key_id(&self) -> u64;
+
+    /// Encrypts `plaintext` with the key `key_id`.
+    fn encrypt(&self, key_id: u64, plaintext: &[u8]) -> Result<Vec<u8>, Box<dyn Error>>;
+
+    /// Decrypts `ciphertext` that was encrypted with the key `key_id`.
+    fn decrypt(&self, key_id: u64, ciphertext: &[u8]) -> Result<Vec<u8>, Box<dyn Error>>;
+
+    /// Signs `bytes` with the key `key_id`.
+    fn sign(&self, key_id: u64, bytes: &[u8]) -> Result<Vec<u8>, Box<dyn Error>>;
+
+    /// Verifies that `signature` is a valid signature of `bytes` with the key `key_id`, and
+    /// returns an error if not, e.g., if the key is unknown.
+    fn verify(&self, key_id: u64, bytes: &[u8], signature: &[u8]) -> Result<(), Box<dyn Error>>;
+}
+
+/// A snapshot that was encrypted and signed with a [`SnapshotSealer`].
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct SealedSnapshot {
+    /// The id of the key that the snapshot was sealed with.
+    pub key_id: u64,
+    /// Whether the snapshot is a delta to the snapshot of the receiver or a complete snapshot.
+    pub delta: bool,
+    /// The compacted index of the snapshot, i.e., the position in the log it was sealed for.
+    pub compacted_idx: usize,
+    /// The encrypted snapshot.
+    pub ciphertext: Vec<u8>,
+    /// The signature of the other fields.
+    pub signature: Vec<u8>,
+}
+
+impl SealedSnapshot {
+    /// The bytes that are signed. Everything but the signature is covered, so that the snapshot
+    /// can neither be modified nor installed at another position of the log.
+    fn signed_bytes(&self) -> Vec<u8> {
+        let mut bytes = Vec::with_capacity(17 + self.ciphertext.len());
+        bytes.extend_from_slice(&self.key_id.to_be_bytes());
+        bytes.push(self.delta as u8);
+        bytes.extend_from_slice(&(self.compacted_idx as u64).to_be_bytes());
+        bytes.extend_from_slice(&self.ciphertext);
+        bytes
+    }
+}
+
+/// Encrypts and signs `snapshot` for the compacted index `compacted_idx` with the current key of
+/// `sealer`. An already sealed snapshot is returned as it is.
+pub fn seal<T: Entry>(
+    sealer: &dyn SnapshotSealer,
+    snapshot: &SnapshotType<T>,
+    compacted_idx: usize,
+) -> Result<SealedSnapshot, SealingErr> {
+    let (delta, snapshot) = match snapshot {
+        SnapshotType::Complete(s) => (false, s),
+        SnapshotType::Delta(s) => (true, s),
+        SnapshotType::Sealed(sealed) => return Ok(sealed.as_ref().clone()),
+    };
+    let key_id = sealer.current_key_id();
+    let plaintext = bincode::serialize(snapshot)?;
+    let ciphertext = sealer
+        .encrypt(key_id, &plaintext)
+        .map_err(|source| SealingErr::Sealer {
+            op: "encrypt",
+            source,
+        })?;
+    let mut sealed = SealedSnapshot {
+        key_id,
+        delta,
+        compacted_idx,
+        ciphertext,
+        signature: vec![],
+    };
+    sealed.signature = sealer
+        .sign(key_id, &sealed.signed_bytes())
+        .map_err(|source| SealingErr::Sealer { op: "sign", source })?;
+    Ok(sealed)
+}
+
+/// Verifies the signature of `sealed` and that it was sealed for the compacted index
+/// `compacted_idx`, and then decrypts it with `sealer`.
+pub fn open<T: Entry>(
+    sealer: &dyn SnapshotSealer,
+    sealed: &SealedSnapshot,
+    compacted_idx: usize,
+) -> Result<SnapshotType<T>, SealingErr> {
+    sealer
+        .verify(sealed.key_id, &sealed.signed_bytes(), &sealed.signature)
+        .map_err(SealingErr::InvalidSignature)?;
+    if sealed.compacted_idx != compacted_idx {
+        return Err(SealingErr::IndexMismatch {
+            sealed: sealed.compacted_idx,
+            expected: compacted_idx,
+        });
+    }
+    let plaintext = sealer
+        .decrypt(sealed.key_id, &sealed.ciphertext)
+        .map_err(|source| SealingErr::Sealer {
+            op: "decrypt",
+            source,
+        })?;
+    let snapshot = bincode::deserialize(&plaintext)?;
+    Ok(match sealed.delta {
+        true => SnapshotType::Delta(snapshot),
+        false => SnapshotType::Complete(snapshot),
+    })
+}
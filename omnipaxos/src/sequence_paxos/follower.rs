@@ -2,7 +2,13 @@ use super::super::ballot_leader_election::Ballot;
 
 use super::*;
 
-use crate::util::{MessageStatus, WRITE_ERROR_MSG};
+#[cfg(feature = "operator_tools")]
+use crate::TruncationErr;
+use crate::{
+    errors::{FatalErr, OrFatal, ProtocolError},
+    storage::SnapshotType,
+    util::{MessageStatus, READ_ERROR_MSG, WRITE_ERROR_MSG},
+};
 
 impl<T, B> SequencePaxos<T, B>
 where
@@ -10,26 +16,47 @@ where
     B: Storage<T>,
 {
     /*** Follower ***/
-    pub(crate) fn handle_prepare(&mut self, prep: Prepare, from: NodeId) {
+    pub(crate) fn handle_prepare(
+        &mut self,
+        prep: Prepare,
+        from: NodeId,
+    ) -> Result<(), ProtocolError> {
+        // A promise of a server that lost its state might contradict the promises it made before
+        if self.quorum_recovery.is_some() {
+            return Ok(());
+        }
         let old_promise = self.internal_storage.get_promise();
+        if old_promise < prep.n {
+            if let Some(standby) = self.blocking_standby(prep.n) {
+                #[cfg(feature = "logging")]
+                debug!(
+                    self.logger,
+                    "Refusing Prepare {:?}: its round is promised to standby {}", prep.n, standby
+                );
+                let not_acc = NotAccepted {
+                    n: old_promise,
+                    standby: Some(standby),
+                };
+                self.outgoing.push(PaxosMessage {
+                    from: self.pid,
+                    to: from,
+                    namespace: self.cluster_config.namespace,
+                    msg: PaxosMsg::NotAccepted(not_acc),
+                });
+                return Ok(());
+            }
+        }
         if old_promise < prep.n || (old_promise == prep.n && self.state.1 == Phase::Recover) {
-            // Flush any pending writes
-            // Don't have to handle flushed entries here because we will sync with followers
-            let _ = self.internal_storage.flush_batch().expect(WRITE_ERROR_MSG);
-            self.internal_storage
-                .set_promise(prep.n)
-                .expect(WRITE_ERROR_MSG);
-            self.state = (Role::Follower, Phase::Prepare);
-            self.current_seq_num = SequenceNumber::default();
+            self.enter_prepare_phase(prep.n, from)?;
             let na = self.internal_storage.get_accepted_round();
             let accepted_idx = self.internal_storage.get_accepted_idx();
             let log_sync = if na > prep.n_accepted {
                 // I'm more up to date: send leader what he is missing after his decided index.
-                Some(self.create_log_sync(prep.decided_idx, prep.decided_idx))
+                Some(self.create_log_sync(prep.decided_idx, prep.decided_idx)?)
             } else if na == prep.n_accepted && accepted_idx > prep.accepted_idx {
                 // I'm more up to date and in same round: send leader what he is missing after his
                 // accepted index.
-                Some(self.create_log_sync(prep.accepted_idx, prep.decided_idx))
+                Some(self.create_log_sync(prep.accepted_idx, prep.decided_idx)?)
             } else {
                 // I'm equally or less up to date
                 None
@@ -45,140 +72,548 @@ where
             self.outgoing.push(PaxosMessage {
                 from: self.pid,
                 to: from,
+                namespace: self.cluster_config.namespace,
                 msg: PaxosMsg::Promise(promise),
             });
+        } else if old_promise == prep.n && self.state == (Role::Follower, Phase::Prepare) {
+            // The leader resends its Prepare if our Promise was lost. Resend the cached Promise of
+            // this ballot instead of reading the log sync from storage again.
+            if let Some(promise) = self
+                .cached_promise_message
+                .as_ref()
+                .filter(|promise| promise.n == prep.n)
+            {
+                self.outgoing.push(PaxosMessage {
+                    from: self.pid,
+                    to: from,
+                    namespace: self.cluster_config.namespace,
+                    msg: PaxosMsg::Promise(promise.clone()),
+                });
+            }
+        }
+        Ok(())
+    }
+
+    /// Promises `n` and prepares to be synchronized by its leader `from`.
+    fn enter_prepare_phase(&mut self, n: Ballot, from: NodeId) -> Result<(), ProtocolError> {
+        // Flush any pending writes together with the promise
+        // Don't have to handle flushed entries here because we will sync with followers
+        let _ = self
+            .internal_storage
+            .flush_batch_and_set_promise(n)
+            .or_fatal(WRITE_ERROR_MSG)?;
+        if self.state.1 == Phase::Recover {
+            if let Some(recovery) = self.recovery.as_mut() {
+                recovery.source = Some(from);
+            }
+        }
+        self.state = (Role::Follower, Phase::Prepare);
+        // The Prepare of the new leader can arrive before this server learns about its election
+        self.origins.supersede_placed();
+        // The proposals held as the leader are forwarded once the new leader is synchronized
+        let (entries, origins) = self.dependents.take_all();
+        if !entries.is_empty() {
+            self.buffer_proposals(entries, origins);
+        }
+        self.current_seq_num = SequenceNumber::default();
+        self.held_messages.clear();
+        self.requested_sync_idx = None;
+        Ok(())
+    }
+
+    /// Handles the AcceptSync of a hot-standby leader that takes over from the leader of
+    /// `n_prev`. It is applied without a prepare phase if this server is still in that round, has
+    /// promised the new round to the standby, and already holds the log up to where the AcceptSync
+    /// starts. Otherwise, it is handled like a Prepare of the new round.
+    pub(crate) fn handle_takeover(
+        &mut self,
+        takeover: Takeover<T>,
+        from: NodeId,
+    ) -> Result<(), ProtocolError> {
+        let Takeover { n_prev, acc_sync } = takeover;
+        let n = acc_sync.n;
+        if n.pid != from {
+            return Ok(());
+        }
+        let promised_standby = self
+            .internal_storage
+            .get_standby_promise()
+            .is_some_and(|c| c.config_id == n.config_id && c.n == n.n && c.pid == n.pid);
+        let log_sync = &acc_sync.log_sync;
+        let can_take_over = promised_standby
+            && self.quorum_recovery.is_none()
+            && self.state.1 != Phase::Recover
+            && self.get_promise() == n_prev
+            && self.internal_storage.get_accepted_round() == n_prev
+            && log_sync.decided_snapshot.is_none()
+            && self.internal_storage.get_compacted_idx() <= log_sync.sync_idx
+            && log_sync.sync_idx <= self.internal_storage.get_accepted_idx();
+        if can_take_over {
+            #[cfg(feature = "logging")]
+            info!(
+                self.logger,
+                "Standby {} takes over from {:?} with {:?}", from, n_prev, n
+            );
+            self.enter_prepare_phase(n, from)?;
+            self.handle_acceptsync(acc_sync, from)?;
+        } else {
+            let prep = Prepare {
+                n,
+                decided_idx: acc_sync.decided_idx,
+                n_accepted: n_prev,
+                accepted_idx: log_sync.sync_idx + log_sync.suffix.len(),
+            };
+            self.handle_prepare(prep, from)?;
         }
+        Ok(())
     }
 
-    pub(crate) fn handle_acceptsync(&mut self, accsync: AcceptSync<T>, from: NodeId) {
+    /// Stores the standby promise of the leader's round and acknowledges it, or records that this
+    /// server is the ready standby.
+    pub(crate) fn handle_standby(&mut self, standby: Standby, from: NodeId) {
+        if standby.n.pid != from
+            || standby.n != self.get_promise()
+            || standby.n != self.internal_storage.get_accepted_round()
+            || self.state != (Role::Follower, Phase::Accept)
+        {
+            return;
+        }
+        if standby.ready {
+            if standby.standby == self.pid {
+                #[cfg(feature = "logging")]
+                info!(self.logger, "Ready to take over from {:?}", standby.n);
+                self.standby_ready = Some(standby.n);
+            }
+            return;
+        }
+        let n = standby.n;
+        let promise = Ballot::with(n.config_id, n.n + 1, 0, standby.standby);
+        if let Err(_e) = self.internal_storage.set_standby_promise(Some(promise)) {
+            // Without the promise, the leader does not make the standby ready
+            #[cfg(feature = "logging")]
+            warn!(self.logger, "Failed to store the standby promise: {}", _e);
+            return;
+        }
+        self.outgoing.push(PaxosMessage {
+            from: self.pid,
+            to: from,
+            namespace: self.cluster_config.namespace,
+            msg: PaxosMsg::StandbyAck(StandbyAck { n }),
+        });
+    }
+
+    pub(crate) fn handle_acceptsync(
+        &mut self,
+        accsync: AcceptSync<T>,
+        from: NodeId,
+    ) -> Result<(), ProtocolError> {
         if self.check_valid_ballot(accsync.n) && self.state == (Role::Follower, Phase::Prepare) {
+            // A final AcceptSync that overtook the preceding portion can't be applied yet.
+            let log_sync = &accsync.log_sync;
+            if log_sync.decided_snapshot.is_none()
+                && log_sync.sync_idx > self.internal_storage.get_accepted_idx()
+            {
+                return Ok(());
+            }
             self.cached_promise_message = None;
+            self.requested_sync_idx = None;
+            self.record_restored_entries(from, log_sync);
+            self.read_superseded_proposals(self.get_superseded_idx(log_sync));
+            // The leader might not know yet that this server decided more, e.g., if the promise of
+            // this server arrived after the leader was elected by the others.
+            let decided_idx = accsync
+                .decided_idx
+                .max(self.internal_storage.get_decided_idx());
             let new_accepted_idx = self
                 .internal_storage
-                .sync_log(accsync.n, accsync.decided_idx, Some(accsync.log_sync))
-                .expect(WRITE_ERROR_MSG);
+                .sync_log(accsync.n, decided_idx, Some(accsync.log_sync))
+                .or_fatal(WRITE_ERROR_MSG)?;
+            self.reject_truncated_proposals(new_accepted_idx);
             if self.internal_storage.get_stopsign().is_none() {
                 self.forward_buffered_proposals();
             }
+            self.reported_accepted_idx = self.durable_accepted_idx(new_accepted_idx)?;
             let accepted = Accepted {
                 n: accsync.n,
-                accepted_idx: new_accepted_idx,
+                accepted_idx: self.reported_accepted_idx,
+                decided_idx: decided_idx.min(self.reported_accepted_idx),
             };
             self.state = (Role::Follower, Phase::Accept);
             self.current_seq_num = accsync.seq_num;
-            let cached_idx = self.outgoing.len();
-            self.latest_accepted_meta = Some((accsync.n, cached_idx));
-            self.outgoing.push(PaxosMessage {
+            self.held_messages.clear();
+            let cached_idx = self.outgoing.push(PaxosMessage {
                 from: self.pid,
                 to: from,
+                namespace: self.cluster_config.namespace,
                 msg: PaxosMsg::Accepted(accepted),
             });
+            self.latest_accepted_meta = Some((accsync.n, cached_idx));
             #[cfg(feature = "unicache")]
             self.internal_storage.set_unicache(accsync.unicache);
+            self.resolve_recovered_stopsign();
         }
+        Ok(())
+    }
+
+    pub(crate) fn handle_sync_portion(
+        &mut self,
+        portion: SyncPortion<T>,
+        from: NodeId,
+    ) -> Result<(), ProtocolError> {
+        if !self.check_valid_ballot(portion.n) || self.state != (Role::Follower, Phase::Prepare) {
+            return Ok(());
+        }
+        // Every portion after the first must continue where the previous one ended.
+        if self.requested_sync_idx.is_some()
+            && portion.log_sync.sync_idx != self.internal_storage.get_accepted_idx()
+        {
+            return Ok(());
+        }
+        let portion_len = portion.log_sync.suffix.len();
+        self.record_restored_entries(from, &portion.log_sync);
+        self.read_superseded_proposals(self.get_superseded_idx(&portion.log_sync));
+        let decided_idx = portion
+            .decided_idx
+            .min(portion.log_sync.sync_idx + portion_len)
+            .max(self.internal_storage.get_decided_idx());
+        let new_accepted_idx = self
+            .internal_storage
+            .sync_log(portion.n, decided_idx, Some(portion.log_sync))
+            .or_fatal(WRITE_ERROR_MSG)?;
+        // If the promise is resent, the leader restarts the synchronization from here.
+        if let Some(promise) = self.cached_promise_message.as_mut() {
+            promise.n_accepted = portion.n;
+            promise.decided_idx = self.internal_storage.get_decided_idx();
+            promise.accepted_idx = new_accepted_idx;
+            promise.log_sync = None;
+        }
+        // Prefetch the following portions instead of requesting them one at a time. The requested
+        // ranges are adjacent, so the portions continue each other even if the leader splits them.
+        let mut next_idx = self
+            .requested_sync_idx
+            .map_or(new_accepted_idx, |idx| idx.max(new_accepted_idx));
+        let prefetch_end = new_accepted_idx + self.sync_prefetch * portion_len.max(1);
+        while next_idx < portion.sync_end_idx && next_idx < prefetch_end {
+            let to_idx = next_idx + portion_len.max(1);
+            self.outgoing.push(PaxosMessage {
+                from: self.pid,
+                to: from,
+                namespace: self.cluster_config.namespace,
+                msg: PaxosMsg::SyncRequest(SyncRequest {
+                    n: portion.n,
+                    from_idx: next_idx,
+                    to_idx,
+                }),
+            });
+            next_idx = to_idx;
+        }
+        self.requested_sync_idx = Some(next_idx);
+        Ok(())
+    }
+
+    pub(crate) fn send_pull_request(&mut self) -> Result<(), ProtocolError> {
+        self.flush_batch_follower()?;
+        let req = PullRequest {
+            n: self.get_promise(),
+            decided_idx: self.internal_storage.get_decided_idx(),
+            accepted_idx: self.internal_storage.get_accepted_idx(),
+        };
+        self.outgoing.push(PaxosMessage {
+            from: self.pid,
+            to: req.n.pid,
+            namespace: self.cluster_config.namespace,
+            msg: PaxosMsg::PullRequest(req),
+        });
+        Ok(())
+    }
+
+    pub(crate) fn handle_pull_response(
+        &mut self,
+        resp: PullResponse<T>,
+    ) -> Result<(), ProtocolError> {
+        if !self.check_valid_ballot(resp.n) || self.state != (Role::Follower, Phase::Accept) {
+            return Ok(());
+        }
+        self.flush_batch_follower()?;
+        let accepted_idx = self.internal_storage.get_accepted_idx();
+        let log_sync = &resp.log_sync;
+        if log_sync.decided_snapshot.is_none() && log_sync.sync_idx > accepted_idx {
+            return Ok(());
+        }
+        let sync_end_idx =
+            log_sync.sync_idx + log_sync.suffix.len() + usize::from(log_sync.stopsign.is_some());
+        // Only apply responses that extend the log, so that accepted entries are never removed.
+        if sync_end_idx > accepted_idx {
+            let decided_idx = resp
+                .decided_idx
+                .min(sync_end_idx)
+                .max(self.internal_storage.get_decided_idx());
+            let new_accepted_idx = self
+                .internal_storage
+                .sync_log(resp.n, decided_idx, Some(resp.log_sync))
+                .or_fatal(WRITE_ERROR_MSG)?;
+            self.reply_accepted(resp.n, new_accepted_idx)?;
+        } else {
+            self.update_decided_idx_and_get_accepted_idx(resp.decided_idx)?;
+        }
+        Ok(())
     }
 
     fn forward_buffered_proposals(&mut self) {
-        let proposals = std::mem::take(&mut self.buffered_proposals);
+        let (proposals, origins) = self.take_buffered_proposals();
         if !proposals.is_empty() {
-            self.forward_proposals(proposals);
+            self.forward_proposals_with_origins(proposals, origins);
         }
     }
 
-    pub(crate) fn handle_acceptdecide(&mut self, acc_dec: AcceptDecide<T>) {
-        if self.check_valid_ballot(acc_dec.n)
-            && self.state == (Role::Follower, Phase::Accept)
-            && self.handle_sequence_num(acc_dec.seq_num, acc_dec.n.pid) == MessageStatus::Expected
-        {
-            #[cfg(not(feature = "unicache"))]
-            let entries = acc_dec.entries;
-            #[cfg(feature = "unicache")]
-            let entries = self.internal_storage.decode_entries(acc_dec.entries);
-            let mut new_accepted_idx = self
-                .internal_storage
-                .append_entries_and_get_accepted_idx(entries)
-                .expect(WRITE_ERROR_MSG);
-            let flushed_after_decide =
-                self.update_decided_idx_and_get_accepted_idx(acc_dec.decided_idx);
-            if flushed_after_decide.is_some() {
-                new_accepted_idx = flushed_after_decide;
+    pub(crate) fn handle_acceptdecide(
+        &mut self,
+        acc_dec: AcceptDecide<T>,
+    ) -> Result<(), ProtocolError> {
+        if !self.check_valid_ballot(acc_dec.n) || self.state != (Role::Follower, Phase::Accept) {
+            return Ok(());
+        }
+        match self.handle_sequence_num(acc_dec.seq_num, acc_dec.n.pid) {
+            MessageStatus::Expected => self.accept_entries(acc_dec)?,
+            MessageStatus::Reordered => {
+                self.hold_message(acc_dec.seq_num, PaxosMsg::AcceptDecide(acc_dec))
             }
-            if let Some(idx) = new_accepted_idx {
-                self.reply_accepted(acc_dec.n, idx);
+            _ => (),
+        }
+        Ok(())
+    }
+
+    fn accept_entries(&mut self, acc_dec: AcceptDecide<T>) -> Result<(), ProtocolError> {
+        #[cfg(not(feature = "unicache"))]
+        let entries = acc_dec.entries;
+        #[cfg(feature = "unicache")]
+        let entries = self.internal_storage.decode_entries(acc_dec.entries);
+        let mut new_accepted_idx = self
+            .internal_storage
+            .append_entries_and_get_accepted_idx(entries)
+            .or_fatal(WRITE_ERROR_MSG)?;
+        // The entries are accepted, so a resent message must not append them again
+        self.current_seq_num = acc_dec.seq_num;
+        let flushed_after_decide =
+            self.update_decided_idx_and_get_accepted_idx(acc_dec.decided_idx)?;
+        if flushed_after_decide.is_some() {
+            new_accepted_idx = flushed_after_decide;
+        }
+        if let Some(idx) = new_accepted_idx {
+            self.reply_accepted(acc_dec.n, idx)?;
+        }
+        Ok(())
+    }
+
+    /// Handles entries that this server relays to the other followers of its region. The entries
+    /// are forwarded to every other follower as a regular `AcceptDecide`. Like an `AcceptDecide`,
+    /// a relay from a stale leader or one received outside of the accept phase is dropped.
+    pub(crate) fn handle_relay_acceptdecide(
+        &mut self,
+        relay: RelayAcceptDecide<T>,
+    ) -> Result<(), ProtocolError> {
+        if !self.check_valid_ballot(relay.n) || self.state != (Role::Follower, Phase::Accept) {
+            return Ok(());
+        }
+        for (pid, seq_num) in relay.seq_nums {
+            let acc_dec = AcceptDecide {
+                n: relay.n,
+                seq_num,
+                decided_idx: relay.decided_idx,
+                entries: relay.entries.clone(),
+            };
+            if pid == self.pid {
+                self.handle_acceptdecide(acc_dec)?;
+            } else {
+                self.outgoing.push(PaxosMessage {
+                    from: self.pid,
+                    to: pid,
+                    namespace: self.cluster_config.namespace,
+                    msg: PaxosMsg::AcceptDecide(acc_dec),
+                });
             }
         }
+        Ok(())
     }
 
-    pub(crate) fn handle_accept_stopsign(&mut self, acc_ss: AcceptStopSign) {
-        if self.check_valid_ballot(acc_ss.n)
-            && self.state == (Role::Follower, Phase::Accept)
-            && self.handle_sequence_num(acc_ss.seq_num, acc_ss.n.pid) == MessageStatus::Expected
-        {
-            // Flush entries before appending stopsign. The accepted index is ignored here as
-            // it will be updated when appending stopsign.
-            let _ = self.internal_storage.flush_batch().expect(WRITE_ERROR_MSG);
-            let new_accepted_idx = self
-                .internal_storage
-                .set_stopsign(Some(acc_ss.ss))
-                .expect(WRITE_ERROR_MSG);
-            self.reply_accepted(acc_ss.n, new_accepted_idx);
+    pub(crate) fn handle_accept_stopsign(
+        &mut self,
+        acc_ss: AcceptStopSign,
+    ) -> Result<(), ProtocolError> {
+        if !self.check_valid_ballot(acc_ss.n) || self.state != (Role::Follower, Phase::Accept) {
+            return Ok(());
+        }
+        match self.handle_sequence_num(acc_ss.seq_num, acc_ss.n.pid) {
+            MessageStatus::Expected => {
+                // Flush entries in the same write as the stopsign.
+                let new_accepted_idx = self
+                    .internal_storage
+                    .flush_batch_and_set_stopsign(acc_ss.ss)
+                    .or_fatal(WRITE_ERROR_MSG)?;
+                self.current_seq_num = acc_ss.seq_num;
+                self.reply_accepted(acc_ss.n, new_accepted_idx)?;
+            }
+            MessageStatus::Reordered => {
+                self.hold_message(acc_ss.seq_num, PaxosMsg::AcceptStopSign(acc_ss))
+            }
+            _ => (),
         }
+        Ok(())
     }
 
-    pub(crate) fn handle_decide(&mut self, dec: Decide) {
-        if self.check_valid_ballot(dec.n)
-            && self.state.1 == Phase::Accept
-            && self.handle_sequence_num(dec.seq_num, dec.n.pid) == MessageStatus::Expected
-        {
-            let new_accepted_idx = self.update_decided_idx_and_get_accepted_idx(dec.decided_idx);
-            if let Some(idx) = new_accepted_idx {
-                self.reply_accepted(dec.n, idx);
+    pub(crate) fn handle_decide(&mut self, dec: Decide) -> Result<(), ProtocolError> {
+        if !self.check_valid_ballot(dec.n) || self.state.1 != Phase::Accept {
+            return Ok(());
+        }
+        match self.handle_sequence_num(dec.seq_num, dec.n.pid) {
+            MessageStatus::Expected => {
+                let new_accepted_idx =
+                    self.update_decided_idx_and_get_accepted_idx(dec.decided_idx)?;
+                self.current_seq_num = dec.seq_num;
+                if let Some(idx) = new_accepted_idx {
+                    self.reply_accepted(dec.n, idx)?;
+                }
+            }
+            MessageStatus::Reordered => self.hold_message(dec.seq_num, PaxosMsg::Decide(dec)),
+            _ => (),
+        }
+        Ok(())
+    }
+
+    pub(crate) fn handle_decided_fingerprint(
+        &mut self,
+        fp: DecidedFingerprint,
+    ) -> Result<(), ProtocolError> {
+        if self.state.0 != Role::Follower || fp.n != self.internal_storage.get_promise() {
+            return Ok(());
+        }
+        // The fingerprint that was received before its entries were decided on this server
+        if let Some((leader, pending)) = self.divergence.take_pending() {
+            if pending.n == fp.n {
+                self.check_decided_fingerprint(leader, pending)?;
             }
         }
+        self.check_decided_fingerprint(fp.n.pid, fp)?;
+        Ok(())
+    }
+
+    /// Compares the fingerprint from the leader with the fingerprint of the same entries in the
+    /// log of this server, unless they are not decided yet or already compacted.
+    fn check_decided_fingerprint(
+        &mut self,
+        leader: NodeId,
+        fp: DecidedFingerprint,
+    ) -> Result<(), ProtocolError> {
+        if fp.from_idx >= fp.to_idx || fp.from_idx < self.internal_storage.get_compacted_idx() {
+            return Ok(());
+        }
+        if fp.to_idx > self.internal_storage.get_decided_idx() {
+            self.divergence.set_pending(leader, fp);
+            return Ok(());
+        }
+        let entries = self
+            .internal_storage
+            .get_entries(fp.from_idx, fp.to_idx)
+            .or_fatal(READ_ERROR_MSG)?;
+        let local_fingerprint = match fingerprint_entries(&entries) {
+            Some(local) if local != fp.fingerprint => local,
+            _ => return Ok(()),
+        };
+        #[cfg(feature = "logging")]
+        warn!(
+            self.logger,
+            "The decided entries in [{}, {}) diverged from the log of leader {}",
+            fp.from_idx,
+            fp.to_idx,
+            leader
+        );
+        self.metrics.counter(metrics::DIVERGENCE_ALARMS, 1);
+        self.divergence.alarm(DivergenceAlarm {
+            leader,
+            from_idx: fp.from_idx,
+            to_idx: fp.to_idx,
+            leader_fingerprint: fp.fingerprint,
+            local_fingerprint,
+        });
+        Ok(())
     }
 
     /// To maintain decided index <= accepted index, batched entries may be flushed.
     /// Returns `Some(new_accepted_idx)` if entries are flushed, otherwise `None`.
-    fn update_decided_idx_and_get_accepted_idx(&mut self, new_decided_idx: usize) -> Option<usize> {
+    fn update_decided_idx_and_get_accepted_idx(
+        &mut self,
+        new_decided_idx: usize,
+    ) -> Result<Option<usize>, ProtocolError> {
         if new_decided_idx <= self.internal_storage.get_decided_idx() {
-            return None;
+            return Ok(None);
         }
-        if new_decided_idx > self.internal_storage.get_accepted_idx() {
-            let new_accepted_idx = self.internal_storage.flush_batch().expect(WRITE_ERROR_MSG);
-            self.internal_storage
-                .set_decided_idx(new_decided_idx.min(new_accepted_idx))
-                .expect(WRITE_ERROR_MSG);
+        let flushed = if new_decided_idx > self.internal_storage.get_accepted_idx() {
+            let new_accepted_idx = self
+                .internal_storage
+                .flush_batch_and_set_decided_idx(new_decided_idx)
+                .or_fatal(WRITE_ERROR_MSG)?;
             Some(new_accepted_idx)
         } else {
             self.internal_storage
                 .set_decided_idx(new_decided_idx)
-                .expect(WRITE_ERROR_MSG);
+                .or_fatal(WRITE_ERROR_MSG)?;
             None
-        }
+        };
+        self.forward_latency.decided(
+            self.internal_storage.get_decided_idx(),
+            metrics::FORWARD_LATENCY,
+            &self.metrics,
+        );
+        self.check_storage_capacity();
+        Ok(flushed)
     }
 
-    fn reply_accepted(&mut self, n: Ballot, accepted_idx: usize) {
+    /// Limits `accepted_idx` to the entries that the storage has persisted.
+    fn durable_accepted_idx(&self, accepted_idx: usize) -> Result<usize, ProtocolError> {
+        let durable_idx = self
+            .internal_storage
+            .get_durable_accepted_idx()
+            .or_fatal(READ_ERROR_MSG)?;
+        Ok(accepted_idx.min(durable_idx))
+    }
+
+    fn reply_accepted(&mut self, n: Ballot, accepted_idx: usize) -> Result<(), ProtocolError> {
+        let accepted_idx = self.durable_accepted_idx(accepted_idx)?;
+        self.reported_accepted_idx = accepted_idx;
+        // The decided index can be ahead of the durable log, but the leader rejects a decided
+        // index beyond the reported accepted index
+        let decided_idx = self.internal_storage.get_decided_idx().min(accepted_idx);
         match &self.latest_accepted_meta {
             Some((round, outgoing_idx)) if round == &n => {
                 let PaxosMessage { msg, .. } = self.outgoing.get_mut(*outgoing_idx).unwrap();
                 match msg {
                     PaxosMsg::Accepted(a) => {
                         a.accepted_idx = accepted_idx;
+                        a.decided_idx = decided_idx;
+                    }
+                    _ => {
+                        return Err(
+                            FatalErr::Internal("Cached idx is not an Accepted Message<T>!").into(),
+                        )
                     }
-                    _ => panic!("Cached idx is not an Accepted Message<T>!"),
                 }
             }
             _ => {
-                let accepted = Accepted { n, accepted_idx };
-                let cached_idx = self.outgoing.len();
-                self.latest_accepted_meta = Some((n, cached_idx));
-                self.outgoing.push(PaxosMessage {
+                let accepted = Accepted {
+                    n,
+                    accepted_idx,
+                    decided_idx,
+                };
+                let cached_idx = self.outgoing.push(PaxosMessage {
                     from: self.pid,
                     to: n.pid,
+                    namespace: self.cluster_config.namespace,
                     msg: PaxosMsg::Accepted(accepted),
                 });
+                self.latest_accepted_meta = Some((n, cached_idx));
             }
         };
+        Ok(())
     }
 
     /// Also returns whether the message's ballot was promised
@@ -187,7 +622,10 @@ where
         match my_promise.cmp(&message_ballot) {
             std::cmp::Ordering::Equal => true,
             std::cmp::Ordering::Greater => {
-                let not_acc = NotAccepted { n: my_promise };
+                let not_acc = NotAccepted {
+                    n: my_promise,
+                    standby: None,
+                };
                 #[cfg(feature = "logging")]
                 trace!(
                     self.logger,
@@ -198,6 +636,7 @@ where
                 self.outgoing.push(PaxosMessage {
                     from: self.pid,
                     to: message_ballot.pid,
+                    namespace: self.cluster_config.namespace,
                     msg: PaxosMsg::NotAccepted(not_acc),
                 });
                 false
@@ -215,17 +654,64 @@ where
         }
     }
 
-    /// Also returns the MessageStatus of the sequence based on the incoming sequence number.
+    /// Also returns the MessageStatus of the sequence based on the incoming sequence number. The
+    /// caller advances `current_seq_num` to an expected message once its writes succeeded, so
+    /// that the message is still expected if the leader resends it after a failed write.
     fn handle_sequence_num(&mut self, seq_num: SequenceNumber, from: NodeId) -> MessageStatus {
-        let msg_status = self.current_seq_num.check_msg_status(seq_num);
+        let msg_status = self
+            .current_seq_num
+            .check_msg_status(seq_num, self.held_messages.window());
         match msg_status {
-            MessageStatus::Expected => self.current_seq_num = seq_num,
-            MessageStatus::DroppedPreceding => self.reconnected(from),
-            MessageStatus::Outdated => (),
+            MessageStatus::DroppedPreceding => self.dropped_preceding(from),
+            MessageStatus::Outdated => {
+                self.outdated_messages += 1;
+                self.sequence_recovery.outdated(from);
+                self.metrics.counter(metrics::MESSAGES_OUTDATED, 1);
+            }
+            MessageStatus::Duplicate => self.duplicate_message(),
+            MessageStatus::Expected | MessageStatus::Reordered => (),
         };
         msg_status
     }
 
+    fn dropped_preceding(&mut self, from: NodeId) {
+        self.dropped_preceding_messages += 1;
+        self.sequence_recovery.dropped_preceding(from);
+        self.metrics.counter(metrics::MESSAGES_DROPPED_PRECEDING, 1);
+        self.held_messages.clear();
+        self.reconnected(from);
+    }
+
+    /// Duplicates are also counted as outdated messages.
+    fn duplicate_message(&mut self) {
+        self.outdated_messages += 1;
+        self.metrics.counter(metrics::MESSAGES_OUTDATED, 1);
+        self.duplicate_messages += 1;
+        self.metrics.counter(metrics::MESSAGES_DUPLICATE, 1);
+    }
+
+    /// Holds a message that overtook preceding messages until they arrive.
+    fn hold_message(&mut self, seq_num: SequenceNumber, msg: PaxosMsg<T>) {
+        if !self.held_messages.hold(seq_num, msg) {
+            self.duplicate_message();
+        }
+    }
+
+    /// Handles the held messages whose preceding messages have arrived.
+    pub(crate) fn handle_held_messages(&mut self) -> Result<(), ProtocolError> {
+        while let Some(msg) = self.held_messages.pop_next(self.current_seq_num) {
+            self.reordered_messages += 1;
+            self.metrics.counter(metrics::MESSAGES_REORDERED, 1);
+            match msg {
+                PaxosMsg::AcceptDecide(acc_dec) => self.handle_acceptdecide(acc_dec)?,
+                PaxosMsg::AcceptStopSign(acc_ss) => self.handle_accept_stopsign(acc_ss)?,
+                PaxosMsg::Decide(dec) => self.handle_decide(dec)?,
+                _ => return Err(FatalErr::Internal("Only sequenced messages are held").into()),
+            }
+        }
+        Ok(())
+    }
+
     pub(crate) fn resend_messages_follower(&mut self) {
         match self.state.1 {
             Phase::Prepare => {
@@ -235,6 +721,7 @@ where
                         self.outgoing.push(PaxosMessage {
                             from: self.pid,
                             to: promise.n.pid,
+                            namespace: self.cluster_config.namespace,
                             msg: PaxosMsg::Promise(promise.clone()),
                         });
                     }
@@ -243,7 +730,7 @@ where
                         // cached the promise sent as a response to the prepare
                         #[cfg(feature = "logging")]
                         warn!(self.logger, "In Prepare phase without a cached promise!");
-                        self.state = (Role::Follower, Phase::Recover);
+                        self.start_recovery();
                         self.send_preparereq_to_all_peers();
                     }
                 }
@@ -252,8 +739,343 @@ where
                 // Resend PrepareReq
                 self.send_preparereq_to_all_peers();
             }
-            Phase::Accept => (),
-            Phase::None => (),
+            Phase::Accept => {
+                // The messages that the held ones overtook were most likely lost
+                if self.held_messages.stalled(self.current_seq_num) {
+                    let leader = self.get_current_leader();
+                    self.dropped_preceding(leader);
+                }
+            }
+            Phase::None => {
+                if let Some(peer) = self.bootstrap_peer {
+                    self.send_bootstrap_request(peer);
+                } else if self.quorum_recovery.is_some() {
+                    self.send_recovery_queries();
+                }
+            }
+        }
+    }
+
+    /// Makes this follower recover its state from the leader.
+    pub(crate) fn fail_recover(&mut self) {
+        if self.state.0 == Role::Leader {
+            return;
+        }
+        self.start_recovery();
+        self.recover_stopsign();
+        self.send_preparereq_to_all_peers();
+    }
+
+    /// Forces the recover phase when the promise recovered from the storage contradicts the
+    /// accepted state, i.e., it is missing although entries were accepted or it is older than the
+    /// accepted round, e.g., because the storage lost a write. The promise is first raised to the
+    /// accepted round so that this server never promises a round lower than one it has accepted
+    /// entries in. Returns whether recovery was forced.
+    pub(crate) fn force_recovery_if_inconsistent(&mut self) -> Result<bool, ProtocolError> {
+        let promise = self.get_promise();
+        let accepted_round = self.internal_storage.get_accepted_round();
+        let lost_promise =
+            promise == Ballot::default() && self.internal_storage.get_accepted_idx() > 0;
+        if accepted_round <= promise && !lost_promise {
+            return Ok(false);
+        }
+        #[cfg(feature = "logging")]
+        warn!(
+            self.logger,
+            "Recovered promise {:?} contradicts accepted round {:?}, forcing recovery",
+            promise,
+            accepted_round
+        );
+        if accepted_round > promise {
+            self.internal_storage
+                .set_promise(accepted_round)
+                .or_fatal(WRITE_ERROR_MSG)?;
+        }
+        self.start_recovery();
+        self.recover_stopsign();
+        self.send_preparereq_to_all_peers();
+        Ok(true)
+    }
+
+    /// Discards the undecided entries from `idx` onwards and recovers them from the leader.
+    #[cfg(feature = "operator_tools")]
+    pub(crate) fn unsafe_truncate_after(&mut self, idx: usize) -> Result<(), TruncationErr> {
+        if self.state.0 == Role::Leader {
+            return Err(TruncationErr::CurrentLeader(self.pid));
+        }
+        self.internal_storage
+            .flush_batch()
+            .or_fatal(WRITE_ERROR_MSG)
+            .map_err(|e| TruncationErr::Failed(e.into()))?;
+        let decided_idx = self.internal_storage.get_decided_idx();
+        let accepted_idx = self.internal_storage.get_accepted_idx();
+        if idx < decided_idx {
+            return Err(TruncationErr::DecidedIndex(decided_idx));
+        }
+        if idx > accepted_idx {
+            return Err(TruncationErr::OutOfBounds(accepted_idx));
+        }
+        #[cfg(feature = "logging")]
+        warn!(
+            self.logger,
+            "Truncating the log from {} to {} on operator request", accepted_idx, idx
+        );
+        self.internal_storage
+            .truncate_after(idx)
+            .or_fatal(WRITE_ERROR_MSG)
+            .map_err(|e| TruncationErr::Failed(e.into()))?;
+        self.latest_accepted_meta = None;
+        self.fail_recover();
+        Ok(())
+    }
+
+    /// Requests the decided state of `pid` to initialize the empty storage of this server.
+    pub(crate) fn bootstrap_from_peer(&mut self, pid: NodeId) -> Result<(), BootstrapErr> {
+        if !self.peers.contains(&pid) {
+            return Err(BootstrapErr::UnknownPeer(pid));
+        }
+        if !self.is_wiped() {
+            return Err(BootstrapErr::NotWiped);
+        }
+        self.bootstrap_peer = Some(pid);
+        self.send_bootstrap_request(pid);
+        Ok(())
+    }
+
+    /// Queries a quorum of the other servers for their promise and decided index before this
+    /// wiped server takes part in the protocol again.
+    pub(crate) fn recover_seamlessly(&mut self) -> Result<(), BootstrapErr> {
+        if !self.is_wiped() {
+            return Err(BootstrapErr::NotWiped);
+        }
+        // The state of this server is lost, so the others must form a quorum by themselves
+        if !self
+            .leader_state
+            .quorum
+            .is_prepare_quorum(self.peers.iter().copied())
+        {
+            return Err(BootstrapErr::NoQuorum);
+        }
+        self.bootstrap_peer = None;
+        self.quorum_recovery = Some(QuorumRecovery::default());
+        self.send_recovery_queries();
+        Ok(())
+    }
+
+    fn is_wiped(&self) -> bool {
+        self.state == (Role::Follower, Phase::None)
+            && self.internal_storage.get_promise() == Ballot::default()
+            && self.internal_storage.get_accepted_idx() == 0
+            && self.internal_storage.get_compacted_idx() == 0
+    }
+
+    fn send_bootstrap_request(&mut self, to: NodeId) {
+        self.outgoing.push(PaxosMessage {
+            from: self.pid,
+            to,
+            namespace: self.cluster_config.namespace,
+            msg: PaxosMsg::BootstrapRequest(BootstrapRequest),
+        });
+    }
+
+    pub(crate) fn handle_bootstrap_request(&mut self, from: NodeId) -> Result<(), ProtocolError> {
+        // A server that is not initialized itself can't help
+        if !self.is_initialized() {
+            return Ok(());
+        }
+        let resp = BootstrapResponse {
+            n: self.internal_storage.get_promise(),
+            accepted_round: self.internal_storage.get_accepted_round(),
+            decided_idx: self.internal_storage.get_decided_idx(),
+            log_sync: self.create_decided_log_sync()?,
+        };
+        self.outgoing.push(PaxosMessage {
+            from: self.pid,
+            to: from,
+            namespace: self.cluster_config.namespace,
+            msg: PaxosMsg::BootstrapResponse(resp),
+        });
+        Ok(())
+    }
+
+    pub(crate) fn handle_bootstrap_response(
+        &mut self,
+        resp: BootstrapResponse<T>,
+        from: NodeId,
+    ) -> Result<(), ProtocolError> {
+        if self.bootstrap_peer != Some(from) {
+            return Ok(());
+        }
+        self.bootstrap_peer = None;
+        let deferred_leader = self
+            .quorum_recovery
+            .take()
+            .and_then(|recovery| recovery.deferred_leader);
+        // The leader might have synchronized this server in the meantime
+        if self.state != (Role::Follower, Phase::None) {
+            return Ok(());
+        }
+        self.start_recovery();
+        self.record_restored_entries(from, &resp.log_sync);
+        self.internal_storage
+            .sync_log(resp.accepted_round, resp.decided_idx, Some(resp.log_sync))
+            .or_fatal(WRITE_ERROR_MSG)?;
+        // A recovery from a quorum might have seen a higher promise than the one of `from`
+        let promise = resp.n.max(self.internal_storage.get_promise());
+        self.internal_storage
+            .set_promise(promise)
+            .or_fatal(WRITE_ERROR_MSG)?;
+        self.send_preparereq_to_all_peers();
+        if let Some(n) = deferred_leader {
+            self.handle_leader(n)?;
+        }
+        Ok(())
+    }
+
+    fn is_initialized(&self) -> bool {
+        self.bootstrap_peer.is_none()
+            && self.quorum_recovery.is_none()
+            && self.internal_storage.get_promise() != Ballot::default()
+    }
+
+    fn send_recovery_queries(&mut self) {
+        let Some(recovery) = &self.quorum_recovery else {
+            return;
+        };
+        for pid in &self.peers {
+            if !recovery.states.contains_key(pid) {
+                self.outgoing.push(PaxosMessage {
+                    from: self.pid,
+                    to: *pid,
+                    namespace: self.cluster_config.namespace,
+                    msg: PaxosMsg::RecoveryQuery(RecoveryQuery),
+                });
+            }
+        }
+    }
+
+    pub(crate) fn handle_recovery_query(&mut self, from: NodeId) {
+        // A server that is not initialized itself can't help
+        if !self.is_initialized() {
+            return;
+        }
+        let state = RecoveryState {
+            n: self.internal_storage.get_promise(),
+            decided_idx: self.internal_storage.get_decided_idx(),
+        };
+        self.outgoing.push(PaxosMessage {
+            from: self.pid,
+            to: from,
+            namespace: self.cluster_config.namespace,
+            msg: PaxosMsg::RecoveryState(state),
+        });
+    }
+
+    /// Once a quorum has replied, adopts the highest promise so that this server never promises a
+    /// round it might have rejected before it was wiped, and bootstraps from the most up-to-date
+    /// server.
+    pub(crate) fn handle_recovery_state(
+        &mut self,
+        state: RecoveryState,
+        from: NodeId,
+    ) -> Result<(), ProtocolError> {
+        if self.bootstrap_peer.is_some() {
+            return Ok(());
+        }
+        let Some(recovery) = self.quorum_recovery.as_mut() else {
+            return Ok(());
+        };
+        recovery.states.insert(from, state);
+        if !self
+            .leader_state
+            .quorum
+            .is_prepare_quorum(recovery.states.keys().copied())
+        {
+            return Ok(());
+        }
+        let promise = recovery
+            .states
+            .values()
+            .map(|s| s.n)
+            .max()
+            .unwrap_or_default();
+        let peer = recovery
+            .states
+            .iter()
+            .max_by_key(|(pid, s)| (s.decided_idx, **pid))
+            .map(|(pid, _)| *pid)
+            .ok_or(FatalErr::Internal(
+                "No server replied to the recovery query",
+            ))?;
+        #[cfg(feature = "logging")]
+        info!(
+            self.logger,
+            "Recovered promise {:?} from a quorum, bootstrapping from {}", promise, peer
+        );
+        self.internal_storage
+            .set_promise(promise)
+            .or_fatal(WRITE_ERROR_MSG)?;
+        self.bootstrap_peer = Some(peer);
+        self.send_bootstrap_request(peer);
+        Ok(())
+    }
+
+    /// Installs the snapshot of the leader if this server is a backup and acknowledges its decided
+    /// index.
+    pub(crate) fn handle_backup_snapshot(
+        &mut self,
+        bs: BackupSnapshot<T>,
+        from: NodeId,
+    ) -> Result<(), ProtocolError> {
+        if !self.is_backup {
+            return Ok(());
+        }
+        let decided_idx = self.internal_storage.get_decided_idx();
+        // A delta can only be applied to the state it was created from
+        let applicable = match bs.log_sync.decided_snapshot {
+            Some(SnapshotType::Delta(_)) => bs.from_idx == decided_idx,
+            _ => true,
+        };
+        if applicable && bs.decided_idx > decided_idx {
+            self.internal_storage
+                .sync_log(bs.n, bs.decided_idx, Some(bs.log_sync))
+                .or_fatal(WRITE_ERROR_MSG)?;
+        }
+        let ack = BackupAck {
+            n: bs.n,
+            decided_idx: self.internal_storage.get_decided_idx(),
+        };
+        self.outgoing.push(PaxosMessage {
+            from: self.pid,
+            to: from,
+            namespace: self.cluster_config.namespace,
+            msg: PaxosMsg::BackupAck(ack),
+        });
+        Ok(())
+    }
+
+    /// Counts the entries that `log_sync` restores, including the decided entries that it restores
+    /// as a snapshot, towards the progress of the recovery.
+    fn record_restored_entries(&mut self, from: NodeId, log_sync: &LogSync<T>) {
+        let num_snapshotted = match log_sync.decided_snapshot {
+            Some(_) => log_sync
+                .sync_idx
+                .saturating_sub(self.internal_storage.get_decided_idx()),
+            None => 0,
+        };
+        let num_entries = num_snapshotted + log_sync.suffix.len();
+        if let Some(recovery) = self.recovery.as_mut().filter(|r| !r.completed) {
+            recovery.source = Some(from);
+            recovery.entries_restored += num_entries;
+        }
+    }
+
+    /// The index from which the log of this server is overwritten by `log_sync`. A decided
+    /// snapshot also replaces the entries before its `sync_idx` that were not decided here.
+    fn get_superseded_idx(&self, log_sync: &LogSync<T>) -> usize {
+        match log_sync.decided_snapshot {
+            Some(_) => self.internal_storage.get_decided_idx(),
+            None => log_sync.sync_idx,
         }
     }
 
@@ -265,16 +1087,29 @@ where
             self.outgoing.push(PaxosMessage {
                 from: self.pid,
                 to: *peer,
+                namespace: self.cluster_config.namespace,
                 msg: PaxosMsg::PrepareReq(prepreq),
             });
         }
     }
 
-    pub(crate) fn flush_batch_follower(&mut self) {
+    pub(crate) fn durability_timeout_follower(&mut self) -> Result<(), ProtocolError> {
+        let durable_idx = self.durable_accepted_idx(self.internal_storage.get_accepted_idx())?;
+        if durable_idx > self.reported_accepted_idx {
+            self.reply_accepted(self.get_promise(), durable_idx)?;
+        }
+        Ok(())
+    }
+
+    pub(crate) fn flush_batch_follower(&mut self) -> Result<(), ProtocolError> {
         let accepted_idx = self.internal_storage.get_accepted_idx();
-        let new_accepted_idx = self.internal_storage.flush_batch().expect(WRITE_ERROR_MSG);
+        let new_accepted_idx = self
+            .internal_storage
+            .flush_batch()
+            .or_fatal(WRITE_ERROR_MSG)?;
         if new_accepted_idx > accepted_idx {
-            self.reply_accepted(self.get_promise(), new_accepted_idx);
+            self.reply_accepted(self.get_promise(), new_accepted_idx)?;
         }
+        Ok(())
     }
 }
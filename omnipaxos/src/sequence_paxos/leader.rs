@@ -2,7 +2,12 @@ use super::super::{
     ballot_leader_election::Ballot,
     util::{LeaderState, PromiseMetaData},
 };
-use crate::util::{AcceptedMetaData, WRITE_ERROR_MSG};
+#[cfg(feature = "pipeline_events")]
+use crate::pipeline::PipelineStage;
+use crate::{
+    errors::{FatalErr, OrFatal, ProtocolError},
+    util::{AcceptedMetaData, READ_ERROR_MSG, WRITE_ERROR_MSG},
+};
 
 use super::*;
 
@@ -13,19 +18,46 @@ where
 {
     /// Handle a new leader. Should be called when the leader election has elected a new leader with the ballot `n`
     /*** Leader ***/
-    pub(crate) fn handle_leader(&mut self, n: Ballot) {
+    pub(crate) fn handle_leader(&mut self, n: Ballot) -> Result<(), ProtocolError> {
         if n <= self.leader_state.n_leader || n <= self.internal_storage.get_promise() {
-            return;
+            return Ok(());
+        }
+        if let Some(recovery) = self.quorum_recovery.as_mut() {
+            // Taken over once the state of this server is recovered
+            recovery.deferred_leader = Some(n);
+            return Ok(());
         }
         #[cfg(feature = "logging")]
         debug!(self.logger, "Newly elected leader: {:?}", n);
+        self.commit_latency.clear_pending();
+        self.forward_latency.clear_pending();
+        self.origins.supersede_placed();
+        self.slow_followers.reset();
+        self.divergence.reset(self.get_decided_idx());
         if self.pid == n.pid {
-            self.leader_state =
-                LeaderState::with(n, self.leader_state.max_pid, self.leader_state.quorum);
-            // Flush any pending writes
+            if let Some(_standby) = self.blocking_standby(n) {
+                #[cfg(feature = "logging")]
+                info!(
+                    self.logger,
+                    "Not leading {:?}: its round is promised to standby {}", n, _standby
+                );
+                self.preempted = true;
+                return Ok(());
+            }
+            let takeover_from = self.get_takeover_round(n);
+            self.leader_state = LeaderState::with(
+                n,
+                self.leader_state.max_pid,
+                self.leader_state.quorum.clone(),
+            );
+            // Flush any pending writes together with the promise
             // Don't have to handle flushed entries here because we will sync with followers
-            let _ = self.internal_storage.flush_batch().expect(WRITE_ERROR_MSG);
-            self.internal_storage.set_promise(n).expect(WRITE_ERROR_MSG);
+            let _ = self
+                .internal_storage
+                .flush_batch_and_set_promise(n)
+                .or_fatal(WRITE_ERROR_MSG)?;
+            // Propose the proposals batched to be forwarded as this server's own
+            self.flush_forward_batch();
             /* insert my promise */
             let na = self.internal_storage.get_accepted_round();
             let decided_idx = self.get_decided_idx();
@@ -40,6 +72,10 @@ where
             self.leader_state.set_promise(my_promise, self.pid, true);
             /* initialise longest chosen sequence and update state */
             self.state = (Role::Leader, Phase::Prepare);
+            if let Some(prev) = takeover_from {
+                self.take_over(prev)?;
+                return Ok(());
+            }
             let prep = Prepare {
                 n,
                 decided_idx,
@@ -51,16 +87,58 @@ where
                 self.outgoing.push(PaxosMessage {
                     from: self.pid,
                     to: *pid,
+                    namespace: self.cluster_config.namespace,
                     msg: PaxosMsg::Prepare(prep),
                 });
             }
         } else {
             self.become_follower();
         }
+        Ok(())
+    }
+
+    /// The round of the current leader if this server is its ready standby and can take over
+    /// with `n` without a prepare phase.
+    fn get_takeover_round(&self, n: Ballot) -> Option<Ballot> {
+        let prev = self.get_promise();
+        let ready = self.standby_ready == Some(prev)
+            && self.state == (Role::Follower, Phase::Accept)
+            && self.internal_storage.get_accepted_round() == prev
+            && self.internal_storage.get_stopsign().is_none()
+            && n.config_id == prev.config_id
+            && n.n == prev.n + 1;
+        ready.then_some(prev)
+    }
+
+    /// Takes over from the leader of round `prev` as its hot-standby leader. Every follower is
+    /// assumed to be in that round with at least the decided log of this server, so they are
+    /// synchronized right away. The followers that are not reply with a Promise as to a Prepare.
+    fn take_over(&mut self, prev: Ballot) -> Result<(), ProtocolError> {
+        let n = self.leader_state.n_leader;
+        let decided_idx = self.get_decided_idx();
+        for pid in self.peers.clone() {
+            let promise = Promise {
+                n,
+                n_accepted: prev,
+                decided_idx,
+                accepted_idx: decided_idx,
+                log_sync: None,
+            };
+            self.leader_state.set_promise(promise, pid, false);
+        }
+        self.standby_ready = None;
+        self.leader_state.takeover_from = Some(prev);
+        self.handle_majority_promises()?;
+        self.leader_state.takeover_from = None;
+        Ok(())
     }
 
     pub(crate) fn become_follower(&mut self) {
         self.state.0 = Role::Follower;
+        self.commit_latency.clear_pending();
+        self.forward_latency.clear_pending();
+        self.origins.supersede_placed();
+        self.slow_followers.reset();
     }
 
     pub(crate) fn handle_preparereq(&mut self, prepreq: PrepareReq, from: NodeId) {
@@ -73,25 +151,117 @@ where
         }
     }
 
-    pub(crate) fn handle_forwarded_proposal(&mut self, mut entries: Vec<T>) {
-        if !self.accepted_reconfiguration() {
-            match self.state {
-                (Role::Leader, Phase::Prepare) => self.buffered_proposals.append(&mut entries),
-                (Role::Leader, Phase::Accept) => self.accept_entries_leader(entries),
-                _ => self.forward_proposals(entries),
+    pub(crate) fn handle_forwarded_proposal(
+        &mut self,
+        entries: Vec<T>,
+        origins: Vec<ProposalOrigin>,
+        from: NodeId,
+        latency: Option<CommitLatency>,
+    ) -> Result<(), ProtocolError> {
+        self.proposal_affinity
+            .proposed(from, entries.len(), latency);
+        if self.is_storage_full() {
+            #[cfg(feature = "logging")]
+            warn!(
+                self.logger,
+                "Dropping {} forwarded proposals: storage is full",
+                entries.len()
+            );
+            self.reject_proposals(entries, origins, from, ProposalRejectionCause::StorageFull);
+        } else if self.is_memory_exceeded() {
+            #[cfg(feature = "logging")]
+            warn!(
+                self.logger,
+                "Dropping {} forwarded proposals: memory limit is exceeded",
+                entries.len()
+            );
+            let cause = ProposalRejectionCause::MemoryLimitExceeded;
+            self.reject_proposals(entries, origins, from, cause);
+        } else if self.accepted_reconfiguration() {
+            self.reject_proposals(entries, origins, from, ProposalRejectionCause::Reconfigured);
+        } else if self.max_entry_size.is_some() {
+            let ((entries, origins), (oversized, oversized_origins)) =
+                self.split_oversized(entries, origins);
+            if !oversized.is_empty() {
+                #[cfg(feature = "logging")]
+                warn!(
+                    self.logger,
+                    "Dropping {} forwarded proposals from {}: entries are too large",
+                    oversized.len(),
+                    from
+                );
+                let cause = ProposalRejectionCause::EntryTooLarge;
+                self.reject_proposals(oversized, oversized_origins, from, cause);
+            }
+            if !entries.is_empty() {
+                self.propose_with_origins(entries, origins)?;
             }
+        } else {
+            self.propose_with_origins(entries, origins)?;
         }
+        Ok(())
     }
 
-    pub(crate) fn handle_forwarded_stopsign(&mut self, ss: StopSign) {
+    /// Splits the forwarded `entries` into those within the `max_entry_size` and those larger than
+    /// it, each with their origins re-indexed.
+    #[allow(clippy::type_complexity)]
+    fn split_oversized(
+        &self,
+        entries: Vec<T>,
+        origins: Vec<ProposalOrigin>,
+    ) -> ((Vec<T>, Vec<ProposalOrigin>), (Vec<T>, Vec<ProposalOrigin>)) {
+        let mut entry_origins: Vec<Option<ProposalOrigin>> = vec![None; entries.len()];
+        for o in origins {
+            if let Some(origin) = entry_origins.get_mut(o.idx) {
+                *origin = Some(o);
+            }
+        }
+        let mut accepted = (vec![], vec![]);
+        let mut oversized = (vec![], vec![]);
+        for (entry, origin) in entries.into_iter().zip(entry_origins) {
+            let (entries, origins) = match self.exceeds_max_entry_size(&entry) {
+                Some(_) => &mut oversized,
+                None => &mut accepted,
+            };
+            if let Some(origin) = origin {
+                origins.push(ProposalOrigin {
+                    idx: entries.len(),
+                    ..origin
+                });
+            }
+            entries.push(entry);
+        }
+        (accepted, oversized)
+    }
+
+    pub(crate) fn handle_forwarded_stopsign(
+        &mut self,
+        ss: StopSign,
+        from: NodeId,
+    ) -> Result<(), ProtocolError> {
         if self.accepted_reconfiguration() {
-            return;
+            return Ok(());
+        }
+        if let Err(reason) = self.validate_stopsign(&ss) {
+            #[cfg(feature = "logging")]
+            info!(
+                self.logger,
+                "Rejecting reconfiguration {:?} from {}: {}", ss.next_config.nodes, from, reason
+            );
+            self.outgoing.push(PaxosMessage {
+                from: self.pid,
+                to: from,
+                namespace: self.cluster_config.namespace,
+                msg: PaxosMsg::RejectStopSign(RejectStopSign { ss, reason }),
+            });
+            return Ok(());
         }
         match self.state {
             (Role::Leader, Phase::Prepare) => self.buffered_stopsign = Some(ss),
-            (Role::Leader, Phase::Accept) => self.accept_stopsign_leader(ss),
+            (Role::Leader, Phase::Accept) => self.accept_stopsign_leader(ss)?,
             _ => self.forward_stopsign(ss),
         }
+        Ok(())
     }
 
     pub(crate) fn send_prepare(&mut self, to: NodeId) {
@@ -104,50 +274,91 @@ where
         self.outgoing.push(PaxosMessage {
             from: self.pid,
             to,
+            namespace: self.cluster_config.namespace,
             msg: PaxosMsg::Prepare(prep),
         });
     }
 
-    pub(crate) fn accept_entry_leader(&mut self, entry: T) {
+    pub(crate) fn accept_entry_leader(&mut self, entry: T) -> Result<(), ProtocolError> {
+        #[cfg(feature = "pipeline_events")]
+        self.record_proposed(1);
         let accepted_metadata = self
             .internal_storage
             .append_entry_with_batching(entry)
-            .expect(WRITE_ERROR_MSG);
+            .or_fatal(WRITE_ERROR_MSG)?;
+        self.commit_latency
+            .appended(self.internal_storage.get_appended_idx());
         if let Some(metadata) = accepted_metadata {
-            self.leader_state
-                .set_accepted_idx(self.pid, metadata.accepted_idx);
-            self.send_acceptdecide(metadata);
+            self.set_own_accepted_idx(metadata.accepted_idx)?;
+            self.send_acceptdecide(metadata)?;
         }
+        Ok(())
     }
 
-    pub(crate) fn accept_entries_leader(&mut self, entries: Vec<T>) {
+    pub(crate) fn accept_entries_leader(&mut self, entries: Vec<T>) -> Result<(), ProtocolError> {
+        #[cfg(feature = "pipeline_events")]
+        self.record_proposed(entries.len());
         let accepted_metadata = self
             .internal_storage
             .append_entries_with_batching(entries)
-            .expect(WRITE_ERROR_MSG);
+            .or_fatal(WRITE_ERROR_MSG)?;
+        self.commit_latency
+            .appended(self.internal_storage.get_appended_idx());
         if let Some(metadata) = accepted_metadata {
-            self.leader_state
-                .set_accepted_idx(self.pid, metadata.accepted_idx);
-            self.send_acceptdecide(metadata);
+            self.set_own_accepted_idx(metadata.accepted_idx)?;
+            self.send_acceptdecide(metadata)?;
+        }
+        Ok(())
+    }
+
+    /// Records that the next `num_entries` entries to be appended to the log of the leader are
+    /// proposed. Must be called before they are appended, which might write them to the storage.
+    #[cfg(feature = "pipeline_events")]
+    fn record_proposed(&mut self, num_entries: usize) {
+        let proposed_idx = self.internal_storage.get_appended_idx() + num_entries;
+        self.internal_storage
+            .pipeline()
+            .reached(PipelineStage::Proposed, proposed_idx);
+    }
+
+    /// Returns the tokens of the proposals that are decided at `decided_idx` to the servers that
+    /// the proposals were appended on.
+    fn send_decided_tokens(&mut self, decided_idx: usize) {
+        let mut tokens: HashMap<NodeId, Vec<(usize, OriginToken)>> = HashMap::new();
+        for (idx, (pid, token)) in self.origins.take_placed_decided(decided_idx) {
+            tokens.entry(pid).or_default().push((idx, token));
+        }
+        for (pid, tokens) in tokens {
+            if pid == self.pid {
+                self.origins.decided(tokens);
+            } else {
+                self.outgoing.push(PaxosMessage {
+                    from: self.pid,
+                    to: pid,
+                    namespace: self.cluster_config.namespace,
+                    msg: PaxosMsg::DecidedTokens(tokens),
+                });
+            }
         }
     }
 
-    pub(crate) fn accept_stopsign_leader(&mut self, ss: StopSign) {
+    pub(crate) fn accept_stopsign_leader(&mut self, ss: StopSign) -> Result<(), ProtocolError> {
         let accepted_metadata = self
             .internal_storage
             .append_stopsign(ss.clone())
-            .expect(WRITE_ERROR_MSG);
+            .or_fatal(WRITE_ERROR_MSG)?;
         if let Some(metadata) = accepted_metadata {
-            self.send_acceptdecide(metadata);
+            self.send_acceptdecide(metadata)?;
         }
         let accepted_idx = self.internal_storage.get_accepted_idx();
-        self.leader_state.set_accepted_idx(self.pid, accepted_idx);
-        for pid in self.leader_state.get_promised_followers() {
+        self.set_own_accepted_idx(accepted_idx)?;
+        for pid in self.leader_state.get_push_followers() {
             self.send_accept_stopsign(pid, ss.clone(), false);
         }
+        Ok(())
     }
 
-    fn send_accsync(&mut self, to: NodeId) {
+    fn send_accsync(&mut self, to: NodeId) -> Result<(), ProtocolError> {
         let current_n = self.leader_state.n_leader;
         let PromiseMetaData {
             n_accepted: prev_round_max_promise_n,
@@ -159,11 +370,20 @@ where
             accepted_idx: followers_accepted_idx,
             pid,
             ..
-        } = self.leader_state.get_promise_meta(to);
-        let followers_decided_idx = self
-            .leader_state
-            .get_decided_idx(*pid)
-            .expect("Received PromiseMetaData but not found in ld");
+        } = match self.leader_state.get_promise_meta(to) {
+            Some(promise_meta) => promise_meta,
+            None => {
+                return Err(FatalErr::Internal("No Metadata found for promised follower").into())
+            }
+        };
+        let followers_decided_idx = match self.leader_state.get_decided_idx(*pid) {
+            Some(decided_idx) => decided_idx,
+            None => {
+                return Err(
+                    FatalErr::Internal("Received PromiseMetaData but not found in ld").into(),
+                )
+            }
+        };
         // Follower can have valid accepted entries depending on which leader they were previously following
         let followers_valid_entries_idx = if *followers_promise_n == current_n {
             *followers_accepted_idx
@@ -172,7 +392,35 @@ where
         } else {
             followers_decided_idx
         };
-        let log_sync = self.create_log_sync(followers_valid_entries_idx, followers_decided_idx);
+        let mut log_sync =
+            self.create_log_sync_portion(followers_valid_entries_idx, followers_decided_idx, true)?;
+        let portion_len = self.get_sync_portion_len(&log_sync.suffix);
+        log_sync.suffix.truncate(portion_len);
+        if log_sync.sync_idx + log_sync.suffix.len() < self.get_log_len()
+            && self.leader_state.takeover_from.is_some()
+        {
+            // A takeover can't be split into portions, so the follower is prepared instead.
+            self.leader_state.reset_promise(to);
+            self.send_prepare(to);
+        } else if log_sync.sync_idx + log_sync.suffix.len() < self.get_log_len() {
+            // Too far behind: send the first portion and let the follower request the rest.
+            log_sync.stopsign = None;
+            self.send_sync_portion(to, log_sync);
+        } else {
+            self.send_accsync_with_log_sync(to, log_sync);
+        }
+        Ok(())
+    }
+
+    fn send_accsync_with_log_sync(&mut self, to: NodeId, log_sync: LogSync<T>) {
+        let current_n = self.leader_state.n_leader;
+        self.leader_state.set_partially_synced(to, false);
+        self.leader_state.set_batch_accept_meta(to, None);
+        // A follower that was already synchronized in this leadership had to be resynchronized
+        if self.leader_state.get_seq_num(to).session > 0 {
+            self.sequence_recovery.session_reset(to);
+            self.metrics.counter(metrics::SESSION_RESETS, 1);
+        }
         self.leader_state.increment_seq_num_session(to);
         let acc_sync = AcceptSync {
             n: current_n,
@@ -182,23 +430,227 @@ where
             #[cfg(feature = "unicache")]
             unicache: self.internal_storage.get_unicache(),
         };
-        let msg = PaxosMessage {
+        let msg = match self.leader_state.takeover_from {
+            Some(n_prev) => PaxosMsg::Takeover(Takeover { n_prev, acc_sync }),
+            None => PaxosMsg::AcceptSync(acc_sync),
+        };
+        self.outgoing.push(PaxosMessage {
             from: self.pid,
             to,
-            msg: PaxosMsg::AcceptSync(acc_sync),
+            namespace: self.cluster_config.namespace,
+            msg,
+        });
+    }
+
+    fn send_sync_portion(&mut self, to: NodeId, log_sync: LogSync<T>) {
+        self.leader_state.set_partially_synced(to, true);
+        let portion = SyncPortion {
+            n: self.leader_state.n_leader,
+            decided_idx: self.get_decided_idx(),
+            log_sync,
+            sync_end_idx: self.get_log_len(),
         };
-        self.outgoing.push(msg);
+        self.outgoing.push(PaxosMessage {
+            from: self.pid,
+            to,
+            namespace: self.cluster_config.namespace,
+            msg: PaxosMsg::SyncPortion(portion),
+        });
+    }
+
+    /// The number of the first `entries` that fit in a single synchronization message, i.e., at
+    /// most `sync_batch_size` entries of at most `sync_batch_byte_size` bytes, but at least one.
+    fn get_sync_portion_len(&self, entries: &[T]) -> usize {
+        let mut len = match self.sync_batch_size {
+            0 => entries.len(),
+            batch_size => entries.len().min(batch_size),
+        };
+        if self.sync_batch_byte_size > 0 {
+            let mut bytes = 0;
+            for (i, entry) in entries[..len].iter().enumerate() {
+                bytes += entry.size_hint();
+                if bytes > self.sync_batch_byte_size {
+                    len = i.max(1);
+                    break;
+                }
+            }
+        }
+        len
     }
 
-    fn send_acceptdecide(&mut self, accepted: AcceptedMetaData<T>) {
+    /// The length of the log excluding any StopSign.
+    fn get_log_len(&self) -> usize {
+        self.internal_storage.get_log_end()
+    }
+
+    pub(crate) fn handle_sync_request(
+        &mut self,
+        req: SyncRequest,
+        from: NodeId,
+    ) -> Result<(), ProtocolError> {
+        if req.n != self.leader_state.n_leader
+            || self.state != (Role::Leader, Phase::Accept)
+            || !self.leader_state.is_partially_synced(from)
+        {
+            return Ok(());
+        }
+        let log_len = self.get_log_len();
+        // Requests that can't be served are ignored. If needed, the follower restarts the
+        // synchronization by resending its promise.
+        if req.from_idx < self.internal_storage.get_compacted_idx()
+            || req.from_idx > log_len
+            || req.to_idx <= req.from_idx
+        {
+            return Ok(());
+        }
+        // The whole requested range is sent, split into as many portions as needed, so that the
+        // next request of the follower continues where the last portion ends. Each portion is read
+        // separately, so that a long range doesn't require a single huge read.
+        let to_idx = req.to_idx.min(log_len);
+        let max_bytes = match self.sync_batch_byte_size {
+            0 => usize::MAX,
+            max_bytes => max_bytes,
+        };
+        let mut sync_idx = req.from_idx;
+        while sync_idx < to_idx {
+            let portion_end = match self.sync_batch_size {
+                0 => to_idx,
+                batch_size => to_idx.min(sync_idx + batch_size),
+            };
+            let hint = self
+                .internal_storage
+                .get_entries_hint(sync_idx, portion_end, max_bytes)
+                .or_fatal(READ_ERROR_MSG)?;
+            let mut entries = hint.entries;
+            if entries.is_empty() {
+                return Ok(());
+            }
+            entries.truncate(self.get_sync_portion_len(&entries));
+            let portion_len = entries.len();
+            let mut log_sync = LogSync {
+                decided_snapshot: None,
+                suffix: entries,
+                sync_idx,
+                stopsign: None,
+                suffix_ballots: self.internal_storage.get_entry_ballots(sync_idx),
+            };
+            sync_idx += portion_len;
+            if sync_idx == log_len {
+                log_sync.stopsign = self.internal_storage.get_stopsign();
+                self.send_accsync_with_log_sync(from, log_sync);
+                return Ok(());
+            }
+            self.send_sync_portion(from, log_sync);
+        }
+        Ok(())
+    }
+
+    pub(crate) fn handle_pull_request(
+        &mut self,
+        req: PullRequest,
+        from: NodeId,
+    ) -> Result<(), ProtocolError> {
+        if req.n != self.leader_state.n_leader
+            || self.state != (Role::Leader, Phase::Accept)
+            || self.leader_state.get_decided_idx(from).is_none()
+        {
+            return Ok(());
+        }
+        self.leader_state.set_pull_follower(from);
+        let log_len = self.get_log_len();
+        let from_idx = req.accepted_idx.min(log_len);
+        let log_sync = if from_idx < self.internal_storage.get_compacted_idx()
+            || (use_snapshots::<T>() && self.exceeds_max_sync_suffix(from_idx))
+        {
+            self.create_log_sync(from_idx, req.decided_idx)?
+        } else {
+            let to_idx = match self.sync_batch_size {
+                0 => log_len,
+                batch_size => log_len.min(from_idx + batch_size),
+            };
+            let mut suffix = self
+                .internal_storage
+                .get_entries(from_idx, to_idx)
+                .or_fatal(READ_ERROR_MSG)?;
+            suffix.truncate(self.get_sync_portion_len(&suffix));
+            let stopsign = match from_idx + suffix.len() == log_len {
+                true => self.internal_storage.get_stopsign(),
+                false => None,
+            };
+            LogSync {
+                decided_snapshot: None,
+                suffix,
+                sync_idx: from_idx,
+                stopsign,
+                suffix_ballots: self.internal_storage.get_entry_ballots(from_idx),
+            }
+        };
+        let resp = PullResponse {
+            n: self.leader_state.n_leader,
+            decided_idx: self.get_decided_idx(),
+            log_sync,
+        };
+        self.outgoing.push(PaxosMessage {
+            from: self.pid,
+            to: from,
+            namespace: self.cluster_config.namespace,
+            msg: PaxosMsg::PullResponse(resp),
+        });
+        Ok(())
+    }
+
+    /// Groups the followers that new entries are pushed to by the node the entries are sent to.
+    /// The followers of a remote region with a relay are reached through the relay, all other
+    /// followers directly.
+    fn get_push_groups(&self) -> Vec<(NodeId, Vec<NodeId>)> {
+        let followers = self.leader_state.get_push_followers();
+        let mut groups = vec![];
+        let mut relayed = vec![];
+        for region in &self.regions {
+            match region.relay {
+                Some(relay) if followers.contains(&relay) && !region.nodes.contains(&self.pid) => {
+                    let targets: Vec<NodeId> = region
+                        .nodes
+                        .iter()
+                        .filter(|pid| followers.contains(pid))
+                        .copied()
+                        .collect();
+                    if targets.len() > 1 {
+                        relayed.extend_from_slice(&targets);
+                        groups.push((relay, targets));
+                    }
+                }
+                _ => {}
+            }
+        }
+        let direct = followers
+            .into_iter()
+            .filter(|pid| !relayed.contains(pid))
+            .map(|pid| (pid, vec![pid]));
+        groups.extend(direct);
+        groups
+    }
+
+    fn send_acceptdecide(&mut self, accepted: AcceptedMetaData<T>) -> Result<(), ProtocolError> {
         let decided_idx = self.internal_storage.get_decided_idx();
-        for pid in self.leader_state.get_promised_followers() {
+        for (to, targets) in self.get_push_groups() {
+            if targets.len() > 1 {
+                self.send_relay_acceptdecide(to, targets, &accepted, decided_idx)?;
+                continue;
+            }
+            let pid = to;
             let cached_acceptdecide = match self.leader_state.get_batch_accept_meta(pid) {
                 Some((bal, msg_idx)) if bal == self.leader_state.n_leader => {
                     let PaxosMessage { msg, .. } = self.outgoing.get_mut(msg_idx).unwrap();
                     match msg {
                         PaxosMsg::AcceptDecide(acc) => Some(acc),
-                        _ => panic!("Cached index is not an AcceptDecide!"),
+                        // The follower was previously reached through a relay
+                        PaxosMsg::RelayAcceptDecide(_) => None,
+                        _ => {
+                            return Err(
+                                FatalErr::Internal("Cached index is not an AcceptDecide!").into()
+                            )
+                        }
                     }
                 }
                 _ => None,
@@ -212,7 +664,7 @@ where
                 // Add new AcceptDecide message to follower
                 None => {
                     self.leader_state
-                        .set_batch_accept_meta(pid, Some(self.outgoing.len()));
+                        .set_batch_accept_meta(pid, Some(self.outgoing.next_idx(pid)));
                     let acc = AcceptDecide {
                         n: self.leader_state.n_leader,
                         seq_num: self.leader_state.next_seq_num(pid),
@@ -222,11 +674,80 @@ where
                     self.outgoing.push(PaxosMessage {
                         from: self.pid,
                         to: pid,
+                        namespace: self.cluster_config.namespace,
                         msg: PaxosMsg::AcceptDecide(acc),
                     });
                 }
             }
         }
+        Ok(())
+    }
+
+    fn send_relay_acceptdecide(
+        &mut self,
+        relay: NodeId,
+        targets: Vec<NodeId>,
+        accepted: &AcceptedMetaData<T>,
+        decided_idx: usize,
+    ) -> Result<(), ProtocolError> {
+        let n_leader = self.leader_state.n_leader;
+        // The cached message can only be extended if it is still relayed to the same followers
+        let cached_msg_idx = match self.leader_state.get_batch_accept_meta(relay) {
+            Some((bal, msg_idx))
+                if bal == n_leader
+                    && targets.iter().all(|pid| {
+                        self.leader_state.get_batch_accept_meta(*pid) == Some((bal, msg_idx))
+                    }) =>
+            {
+                Some(msg_idx)
+            }
+            _ => None,
+        };
+        let cached_relay = match cached_msg_idx {
+            Some(msg_idx) => {
+                let PaxosMessage { msg, .. } = self.outgoing.get_mut(msg_idx).unwrap();
+                match msg {
+                    PaxosMsg::RelayAcceptDecide(acc) => Some(acc),
+                    _ => {
+                        return Err(
+                            FatalErr::Internal("Cached index is not a RelayAcceptDecide!").into(),
+                        )
+                    }
+                }
+            }
+            None => None,
+        };
+        match cached_relay {
+            // Modify existing RelayAcceptDecide message to relay
+            Some(acc) => {
+                acc.entries.append(accepted.entries.clone().as_mut());
+                acc.decided_idx = decided_idx;
+            }
+            // Add new RelayAcceptDecide message to relay
+            None => {
+                let msg_idx = self.outgoing.next_idx(relay);
+                let seq_nums = targets
+                    .into_iter()
+                    .map(|pid| {
+                        self.leader_state.set_batch_accept_meta(pid, Some(msg_idx));
+                        (pid, self.leader_state.next_seq_num(pid))
+                    })
+                    .collect();
+                let acc = RelayAcceptDecide {
+                    n: n_leader,
+                    seq_nums,
+                    decided_idx,
+                    entries: accepted.entries.clone(),
+                };
+                self.outgoing.push(PaxosMessage {
+                    from: self.pid,
+                    to: relay,
+                    namespace: self.cluster_config.namespace,
+                    msg: PaxosMsg::RelayAcceptDecide(acc),
+                });
+            }
+        }
+        Ok(())
     }
 
     fn send_accept_stopsign(&mut self, to: NodeId, ss: StopSign, resend: bool) {
@@ -242,11 +763,26 @@ where
         self.outgoing.push(PaxosMessage {
             from: self.pid,
             to,
+            namespace: self.cluster_config.namespace,
             msg: acc_ss,
         });
     }
 
     pub(crate) fn send_decide(&mut self, to: NodeId, decided_idx: usize, resend: bool) {
+        // Coalesce with a Decide that is still queued to `to` and has the latest sequence number,
+        // so that the follower receives the same sequence of messages with one message less.
+        let n_leader = self.leader_state.n_leader;
+        let seq_num = self.leader_state.get_seq_num(to);
+        if let Some(PaxosMessage {
+            msg: PaxosMsg::Decide(d),
+            ..
+        }) = self.outgoing.last_mut(to)
+        {
+            if d.n == n_leader && d.seq_num == seq_num {
+                d.decided_idx = d.decided_idx.max(decided_idx);
+                return;
+            }
+        }
         let seq_num = match resend {
             true => self.leader_state.get_seq_num(to),
             false => self.leader_state.next_seq_num(to),
@@ -259,41 +795,77 @@ where
         self.outgoing.push(PaxosMessage {
             from: self.pid,
             to,
+            namespace: self.cluster_config.namespace,
             msg: PaxosMsg::Decide(d),
         });
     }
 
-    fn handle_majority_promises(&mut self) {
+    fn handle_majority_promises(&mut self) -> Result<(), ProtocolError> {
         let max_promise_sync = self.leader_state.take_max_promise_sync();
         let decided_idx = self.leader_state.get_max_decided_idx();
+        let sync_idx = max_promise_sync
+            .as_ref()
+            .map_or(self.internal_storage.get_accepted_idx(), |sync| {
+                sync.sync_idx
+            });
+        self.read_superseded_proposals(sync_idx);
         let mut new_accepted_idx = self
             .internal_storage
             .sync_log(self.leader_state.n_leader, decided_idx, max_promise_sync)
-            .expect(WRITE_ERROR_MSG);
-        if !self.accepted_reconfiguration() {
-            if !self.buffered_proposals.is_empty() {
-                let entries = std::mem::take(&mut self.buffered_proposals);
+            .or_fatal(WRITE_ERROR_MSG)?;
+        self.reject_truncated_proposals(new_accepted_idx);
+        #[cfg(feature = "pipeline_events")]
+        {
+            // The recovered entries were proposed and replicated by previous leaders
+            let pipeline = self.internal_storage.pipeline();
+            pipeline.reset(PipelineStage::Proposed, new_accepted_idx);
+            pipeline.reset(PipelineStage::Replicated, decided_idx);
+        }
+        self.resolve_recovered_stopsign();
+        if self.accepted_reconfiguration() {
+            let (entries, origins) = self.take_buffered_proposals();
+            if !entries.is_empty() {
+                self.reject_proposals(
+                    entries,
+                    origins,
+                    self.pid,
+                    ProposalRejectionCause::Reconfigured,
+                );
+            }
+        } else {
+            let (entries, origins) = self.take_buffered_proposals();
+            let (entries, origins) = self.drop_expired(entries, origins);
+            let (entries, origins) = self.hold_dependents(entries, origins);
+            if !entries.is_empty() {
+                self.origins.placed(new_accepted_idx, origins);
+                #[cfg(feature = "pipeline_events")]
+                self.record_proposed(entries.len());
                 new_accepted_idx = self
                     .internal_storage
                     .append_entries_without_batching(entries)
-                    .expect(WRITE_ERROR_MSG);
+                    .or_fatal(WRITE_ERROR_MSG)?;
+                self.commit_latency.appended(new_accepted_idx);
             }
             if let Some(ss) = self.buffered_stopsign.take() {
                 self.internal_storage
                     .append_stopsign(ss)
-                    .expect(WRITE_ERROR_MSG);
+                    .or_fatal(WRITE_ERROR_MSG)?;
                 new_accepted_idx = self.internal_storage.get_accepted_idx();
             }
         }
         self.state = (Role::Leader, Phase::Accept);
-        self.leader_state
-            .set_accepted_idx(self.pid, new_accepted_idx);
+        self.set_own_accepted_idx(new_accepted_idx)?;
         for pid in self.leader_state.get_promised_followers() {
-            self.send_accsync(pid);
+            self.send_accsync(pid)?;
         }
+        Ok(())
     }
 
-    pub(crate) fn handle_promise_prepare(&mut self, prom: Promise<T>, from: NodeId) {
+    pub(crate) fn handle_promise_prepare(
+        &mut self,
+        prom: Promise<T>,
+        from: NodeId,
+    ) -> Result<(), ProtocolError> {
         #[cfg(feature = "logging")]
         debug!(
             self.logger,
@@ -302,12 +874,17 @@ where
         if prom.n == self.leader_state.n_leader {
             let received_majority = self.leader_state.set_promise(prom, from, true);
             if received_majority {
-                self.handle_majority_promises();
+                self.handle_majority_promises()?;
             }
         }
+        Ok(())
     }
 
-    pub(crate) fn handle_promise_accept(&mut self, prom: Promise<T>, from: NodeId) {
+    pub(crate) fn handle_promise_accept(
+        &mut self,
+        prom: Promise<T>,
+        from: NodeId,
+    ) -> Result<(), ProtocolError> {
         #[cfg(feature = "logging")]
         {
             let (r, p) = &self.state;
@@ -318,11 +895,16 @@ where
         }
         if prom.n == self.leader_state.n_leader {
             self.leader_state.set_promise(prom, from, false);
-            self.send_accsync(from);
+            self.send_accsync(from)?;
         }
+        Ok(())
     }
 
-    pub(crate) fn handle_accepted(&mut self, accepted: Accepted, from: NodeId) {
+    pub(crate) fn handle_accepted(
+        &mut self,
+        accepted: Accepted,
+        from: NodeId,
+    ) -> Result<(), ProtocolError> {
         #[cfg(feature = "logging")]
         trace!(
             self.logger,
@@ -335,33 +917,173 @@ where
         if accepted.n == self.leader_state.n_leader && self.state == (Role::Leader, Phase::Accept) {
             self.leader_state
                 .set_accepted_idx(from, accepted.accepted_idx);
-            if accepted.accepted_idx > self.internal_storage.get_decided_idx()
-                && self.leader_state.is_chosen(accepted.accepted_idx)
-            {
-                let decided_idx = accepted.accepted_idx;
-                self.internal_storage
-                    .set_decided_idx(decided_idx)
-                    .expect(WRITE_ERROR_MSG);
-                for pid in self.leader_state.get_promised_followers() {
-                    match self.leader_state.get_batch_accept_meta(pid) {
-                        Some((bal, msg_idx)) if bal == self.leader_state.n_leader => {
-                            let PaxosMessage { msg, .. } = self.outgoing.get_mut(msg_idx).unwrap();
-                            match msg {
-                                PaxosMsg::AcceptDecide(acc) => acc.decided_idx = decided_idx,
-                                _ => panic!("Cached index is not an AcceptDecide!"),
+            self.leader_state
+                .set_follower_decided_idx(from, accepted.decided_idx);
+            self.decide_if_chosen(accepted.accepted_idx)?;
+            self.check_standby_ready();
+        }
+        Ok(())
+    }
+
+    /// Decides the log up to `accepted_idx` if a quorum has accepted it.
+    fn decide_if_chosen(&mut self, accepted_idx: usize) -> Result<(), ProtocolError> {
+        if accepted_idx > self.internal_storage.get_decided_idx()
+            && self.leader_state.is_chosen(accepted_idx)
+        {
+            let decided_idx = accepted_idx;
+            #[cfg(feature = "pipeline_events")]
+            self.internal_storage
+                .pipeline()
+                .reached(PipelineStage::Replicated, decided_idx);
+            self.internal_storage
+                .set_decided_idx(decided_idx)
+                .or_fatal(WRITE_ERROR_MSG)?;
+            self.commit_latency
+                .decided(decided_idx, metrics::COMMIT_LATENCY, &self.metrics);
+            self.check_storage_capacity();
+            self.send_decided_tokens(decided_idx);
+            for pid in self.leader_state.get_push_followers() {
+                match self.leader_state.get_batch_accept_meta(pid) {
+                    Some((bal, msg_idx)) if bal == self.leader_state.n_leader => {
+                        let PaxosMessage { msg, .. } = self.outgoing.get_mut(msg_idx).unwrap();
+                        match msg {
+                            PaxosMsg::AcceptDecide(acc) => acc.decided_idx = decided_idx,
+                            PaxosMsg::RelayAcceptDecide(acc) => acc.decided_idx = decided_idx,
+                            _ => {
+                                return Err(FatalErr::Internal(
+                                    "Cached index is not an AcceptDecide!",
+                                )
+                                .into())
                             }
                         }
-                        _ => self.send_decide(pid, decided_idx, false),
-                    };
-                }
+                    }
+                    _ => self.send_decide(pid, decided_idx, false),
+                };
             }
+            self.release_dependents()?;
         }
+        Ok(())
     }
 
     pub(crate) fn handle_notaccepted(&mut self, not_acc: NotAccepted, from: NodeId) {
+        if self.state.0 == Role::Leader {
+            self.slow_followers.nacked(from);
+        }
         if self.state.0 == Role::Leader && self.leader_state.n_leader < not_acc.n {
             self.leader_state.lost_promise(from);
         }
+        if not_acc.standby.is_some() && self.state == (Role::Leader, Phase::Prepare) {
+            self.leader_state.lost_promise(from);
+            if !self.leader_state.can_form_prepare_quorum() {
+                #[cfg(feature = "logging")]
+                info!(
+                    self.logger,
+                    "Round of {:?} is promised to standby {:?}",
+                    self.leader_state.n_leader,
+                    not_acc.standby
+                );
+                self.preempted = true;
+            }
+        }
+    }
+
+    /// Designates `standby` as the hot-standby leader of this leadership. Once it can take over,
+    /// every entry is only decided after the standby accepted it.
+    pub(crate) fn set_standby(&mut self, standby: NodeId) -> Result<(), StandbyErr> {
+        if self.state != (Role::Leader, Phase::Accept) {
+            return Err(StandbyErr::NotLeader);
+        }
+        if !self.peers.contains(&standby) {
+            return Err(StandbyErr::NotPeer(standby));
+        }
+        if let Some(designated) = self.leader_state.standby {
+            return Err(StandbyErr::AlreadyDesignated(designated));
+        }
+        if self.accepted_reconfiguration() {
+            return Err(StandbyErr::Reconfigured);
+        }
+        let n = self.leader_state.n_leader;
+        let promise = Ballot::with(n.config_id, n.n + 1, 0, standby);
+        self.internal_storage
+            .set_standby_promise(Some(promise))
+            .map_err(|e| StorageErr::with("store the standby promise", e))?;
+        self.leader_state.standby = Some(standby);
+        self.leader_state.set_standby_promised(self.pid);
+        for pid in self.peers.clone() {
+            self.send_standby(pid, false);
+        }
+        Ok(())
+    }
+
+    /// The designated standby of this leadership and whether it is ready to take over.
+    pub(crate) fn get_standby(&self) -> Option<(NodeId, bool)> {
+        match self.state.0 {
+            Role::Leader => self
+                .leader_state
+                .standby
+                .map(|standby| (standby, self.leader_state.standby_ready)),
+            Role::Follower => None,
+        }
+    }
+
+    fn send_standby(&mut self, to: NodeId, ready: bool) {
+        let standby = Standby {
+            n: self.leader_state.n_leader,
+            standby: self.leader_state.standby.unwrap(),
+            ready,
+        };
+        self.outgoing.push(PaxosMessage {
+            from: self.pid,
+            to,
+            namespace: self.cluster_config.namespace,
+            msg: PaxosMsg::Standby(standby),
+        });
+    }
+
+    pub(crate) fn handle_standby_ack(&mut self, ack: StandbyAck, from: NodeId) {
+        if self.state == (Role::Leader, Phase::Accept)
+            && ack.n == self.leader_state.n_leader
+            && self.leader_state.standby.is_some()
+        {
+            self.leader_state.set_standby_promised(from);
+            self.check_standby_ready();
+        }
+    }
+
+    /// Makes the standby ready to take over once an accept quorum promised it the next round and
+    /// it has accepted every decided entry.
+    fn check_standby_ready(&mut self) {
+        let standby = match self.leader_state.standby {
+            Some(standby) if !self.leader_state.standby_ready => standby,
+            _ => return,
+        };
+        if self.leader_state.is_standby_promised(standby)
+            && self.leader_state.is_standby_promise_quorum()
+            && self.leader_state.get_accepted_idx(standby)
+                >= self.internal_storage.get_decided_idx()
+        {
+            #[cfg(feature = "logging")]
+            info!(self.logger, "Standby {} is ready", standby);
+            self.leader_state.standby_ready = true;
+            self.send_standby(standby, true);
+        }
+    }
+
+    /// Resends the standby designation to the peers that have not acknowledged it, and the
+    /// readiness to the standby.
+    fn resend_standby(&mut self) {
+        let standby = match self.leader_state.standby {
+            Some(standby) => standby,
+            None => return,
+        };
+        for pid in self.peers.clone() {
+            if !self.leader_state.is_standby_promised(pid) {
+                self.send_standby(pid, false);
+            }
+        }
+        if self.leader_state.standby_ready {
+            self.send_standby(standby, true);
+        }
     }
 
     pub(crate) fn resend_messages_leader(&mut self) {
@@ -374,10 +1096,11 @@ where
                 }
             }
             Phase::Accept => {
+                self.resend_standby();
                 // Resend AcceptStopSign or StopSign's decide
                 if let Some(ss) = self.internal_storage.get_stopsign() {
                     let decided_idx = self.internal_storage.get_decided_idx();
-                    for follower in self.leader_state.get_promised_followers() {
+                    for follower in self.leader_state.get_push_followers() {
                         if self.internal_storage.stopsign_is_decided() {
                             self.send_decide(follower, decided_idx, true);
                         } else if self.leader_state.get_accepted_idx(follower)
@@ -398,15 +1121,98 @@ where
         }
     }
 
-    pub(crate) fn flush_batch_leader(&mut self) {
+    /// Sends a snapshot of the decided log to every backup that has not acknowledged it yet.
+    #[cfg(not(feature = "no_snapshots"))]
+    pub(crate) fn backup_timeout(&mut self) -> Result<(), ProtocolError> {
+        if self.state != (Role::Leader, Phase::Accept) || !use_snapshots::<T>() {
+            return Ok(());
+        }
+        let decided_idx = self.internal_storage.get_decided_idx();
+        for backup in self.backups.clone() {
+            let from_idx = self.backup_decided_idx.get(&backup).copied().unwrap_or(0);
+            if from_idx >= decided_idx {
+                continue;
+            }
+            let (decided_snapshot, log_decided_idx) = self
+                .internal_storage
+                .create_diff_snapshot(from_idx)
+                .or_fatal(READ_ERROR_MSG)?;
+            #[cfg(feature = "snapshot_sealing")]
+            let decided_snapshot = self
+                .seal_snapshot(decided_snapshot, log_decided_idx)
+                .or_fatal(SEAL_ERROR_MSG)?;
+            let stopsign = if self.internal_storage.stopsign_is_decided() {
+                self.internal_storage.get_stopsign()
+            } else {
+                None
+            };
+            let bs = BackupSnapshot {
+                n: self.leader_state.n_leader,
+                from_idx,
+                decided_idx,
+                log_sync: LogSync {
+                    decided_snapshot,
+                    suffix: vec![],
+                    sync_idx: log_decided_idx,
+                    stopsign,
+                    suffix_ballots: vec![],
+                },
+            };
+            self.outgoing.push(PaxosMessage {
+                from: self.pid,
+                to: backup,
+                namespace: self.cluster_config.namespace,
+                msg: PaxosMsg::BackupSnapshot(bs),
+            });
+        }
+        Ok(())
+    }
+
+    /// Backups only receive snapshots, which the `no_snapshots` feature removes.
+    #[cfg(feature = "no_snapshots")]
+    pub(crate) fn backup_timeout(&mut self) -> Result<(), ProtocolError> {
+        Ok(())
+    }
+
+    pub(crate) fn handle_backup_ack(&mut self, ack: BackupAck, from: NodeId) {
+        if self.state.0 == Role::Leader && ack.n == self.leader_state.n_leader {
+            self.backup_decided_idx.insert(from, ack.decided_idx);
+        }
+    }
+
+    /// Sets the accepted index of this server, limited to the entries that its storage has
+    /// persisted.
+    fn set_own_accepted_idx(&mut self, accepted_idx: usize) -> Result<(), ProtocolError> {
+        let durable_idx = self
+            .internal_storage
+            .get_durable_accepted_idx()
+            .or_fatal(READ_ERROR_MSG)?;
+        self.leader_state
+            .set_accepted_idx(self.pid, accepted_idx.min(durable_idx));
+        Ok(())
+    }
+
+    pub(crate) fn durability_timeout_leader(&mut self) -> Result<(), ProtocolError> {
+        let durable_idx = self
+            .internal_storage
+            .get_durable_accepted_idx()
+            .or_fatal(READ_ERROR_MSG)?;
+        if durable_idx > self.leader_state.get_accepted_idx(self.pid) {
+            self.leader_state.set_accepted_idx(self.pid, durable_idx);
+            self.decide_if_chosen(durable_idx)?;
+        }
+        Ok(())
+    }
+
+    pub(crate) fn flush_batch_leader(&mut self) -> Result<(), ProtocolError> {
         let accepted_metadata = self
             .internal_storage
             .flush_batch_and_get_entries()
-            .expect(WRITE_ERROR_MSG);
+            .or_fatal(WRITE_ERROR_MSG)?;
         if let Some(metadata) = accepted_metadata {
-            self.leader_state
-                .set_accepted_idx(self.pid, metadata.accepted_idx);
-            self.send_acceptdecide(metadata);
+            self.set_own_accepted_idx(metadata.accepted_idx)?;
+            self.send_acceptdecide(metadata)?;
         }
+        Ok(())
     }
 }
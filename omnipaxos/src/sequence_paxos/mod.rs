@@ -1,22 +1,57 @@
 use super::{ballot_leader_election::Ballot, messages::sequence_paxos::*, util::LeaderState};
+#[cfg(feature = "no_panic")]
+use crate::errors::Failure;
+#[cfg(feature = "spill")]
+use crate::spill::SpillFile;
+#[cfg(all(feature = "snapshot_sealing", not(feature = "no_snapshots")))]
+use crate::util::SEAL_ERROR_MSG;
 #[cfg(feature = "logging")]
 use crate::utils::logger::create_logger;
+#[cfg(feature = "snapshot_sealing")]
 use crate::{
+    errors::SealingErr,
+    sealing::{self, SnapshotSealer},
+    storage::SnapshotType,
+    RejectedMessageCause,
+};
+use crate::{
+    errors::{BuildErr, OrFatal, ProtocolError, StandbyErr, StorageMigrationErr},
+    metrics::{self, Metrics, MetricsSink},
+    settings::ReplicatedSettings,
     storage::{
         internal_storage::{InternalStorage, InternalStorageConfig},
-        Entry, Snapshot, StopSign, Storage,
+        use_snapshots,
+        verification::{StorageVerifier, VerificationReport},
+        Entry, StopSign, Storage, StorageResult,
     },
     util::{
-        FlexibleQuorum, LogSync, NodeId, Quorum, SequenceNumber, READ_ERROR_MSG, WRITE_ERROR_MSG,
+        fingerprint_entries, split_proposals, CommitLatencyTracker, DependentProposals,
+        DivergenceDetector, FlexibleQuorum, ForwardBatch, LogEntry, LogSync, NodeId, OriginToken,
+        OriginTracker, OutgoingIdx, OutgoingQueues, ProposalAffinityTracker, Quorum, Region,
+        RejectedMessageTracker, ReorderBuffer, SequenceNumber, SequenceRecoveryTracker,
+        SlowFollowerDetector, READ_ERROR_MSG, WRITE_ERROR_MSG,
     },
-    ClusterConfig, CompactionErr, OmniPaxosConfig, ProposeErr,
+    witness::{Witness, WitnessReads, WitnessReport},
+    AppendOutcome, BootstrapErr, ClusterConfig, CommitLatency, CompactionErr, DivergenceAlarm,
+    FollowerAppendPolicy, LowSpacePolicy, MemoryUsage, MessageOutcome, OmniPaxosConfig,
+    ProposalAffinity, ProposalRejectionCause, ProposeErr, ReconfigErr, ReconfigurationStatus,
+    ReconfigurationValidator, RecoveryProgress, RejectedProposal, StorageErr, TrimMode,
 };
 #[cfg(feature = "logging")]
 use slog::{debug, info, trace, warn, Logger};
-use std::{fmt::Debug, vec};
+use std::{
+    collections::{HashMap, VecDeque},
+    error::Error,
+    fmt::Debug,
+    iter,
+    sync::Arc,
+    time::SystemTime,
+    vec,
+};
 
 pub mod follower;
 pub mod leader;
+pub mod validation;
 
 /// a Sequence Paxos replica. Maintains local state of the replicated log, handles incoming messages and produces outgoing messages that the user has to fetch periodically and send using a network implementation.
 /// User also has to periodically fetch the decided entries that are guaranteed to be strongly consistent and linearizable, and therefore also safe to be used in the higher level application.
@@ -29,16 +64,110 @@ where
     pub(crate) internal_storage: InternalStorage<B, T>,
     pid: NodeId,
     peers: Vec<NodeId>, // excluding self pid
+    // The backup servers of the cluster, excluding self pid
+    #[cfg_attr(feature = "no_snapshots", allow(dead_code))]
+    backups: Vec<NodeId>,
+    is_backup: bool,
+    // The decided index acknowledged by each backup
+    backup_decided_idx: HashMap<NodeId, usize>,
+    regions: Vec<Region>,
     state: (Role, Phase),
     buffered_proposals: Vec<T>,
+    // The origins of the buffered proposals that were appended with a token
+    buffered_origins: Vec<ProposalOrigin>,
+    // The tick at which each group of buffered proposals was buffered and the size of the group
+    buffered_ticks: VecDeque<(u64, usize)>,
+    // The proposals batched to be forwarded to the leader together
+    forward_batch: ForwardBatch<T>,
+    // The proposals held as the leader until the entries they depend on are decided
+    dependents: DependentProposals<T>,
+    // The number of runs of the buffered proposals timer
+    buffer_clock: u64,
+    // The proposals appended on this server that were dropped before they were decided
+    rejected_proposals: Vec<RejectedProposal<T>>,
+    // The proposals with a token this server appended as a former leader that the ongoing
+    // synchronization of its log overwrites, with their log index as the index of their origin
+    superseded_proposals: Vec<(T, ProposalOrigin, Option<Ballot>)>,
     buffered_stopsign: Option<StopSign>,
-    outgoing: Vec<PaxosMessage<T>>,
+    // The StopSign proposed by this server that has not been accepted yet
+    proposed_stopsign: Option<StopSign>,
+    // the accepted but undecided StopSign this server had when it recovered, until its log is
+    // synchronized with the leader
+    recovered_stopsign: Option<StopSign>,
+    // The reason the leader rejected the StopSign last proposed by this server
+    rejected_stopsign_reason: Option<String>,
+    cluster_config: ClusterConfig,
+    reconfiguration_validator: Option<ReconfigurationValidator>,
+    outgoing: OutgoingQueues<PaxosMessage<T>>,
     leader_state: LeaderState<T>,
-    latest_accepted_meta: Option<(Ballot, usize)>,
+    latest_accepted_meta: Option<(Ballot, OutgoingIdx)>,
+    // The accepted index in the latest Accepted sent to the leader. It is lower than the accepted
+    // index if the storage has not persisted all accepted entries yet.
+    reported_accepted_idx: usize,
     // Keeps track of sequence of accepts from leader where AcceptSync = 1
     current_seq_num: SequenceNumber,
+    // The accept-phase messages from the leader that overtook preceding ones
+    held_messages: ReorderBuffer<PaxosMsg<T>>,
     cached_promise_message: Option<Promise<T>>,
-    buffer_size: usize,
+    // The round of the leader that made this server its ready hot-standby leader
+    standby_ready: Option<Ballot>,
+    // Whether the ballot of this server can't win the prepare phase because its round is promised
+    // to a hot-standby leader
+    preempted: bool,
+    sync_batch_size: usize,
+    sync_batch_byte_size: usize,
+    sync_prefetch: usize,
+    max_sync_suffix_entries: Option<usize>,
+    // The index after the last log portion requested from the leader while catching up
+    requested_sync_idx: Option<usize>,
+    storage_verifier: StorageVerifier,
+    // The progress of the ongoing or last recovery
+    recovery: Option<RecoveryProgress>,
+    // The peer that this wiped server requested its initial state from
+    bootstrap_peer: Option<NodeId>,
+    // The state of the other servers that this wiped server recovers from before it takes part in
+    // the protocol again
+    quorum_recovery: Option<QuorumRecovery>,
+    witness_reads: WitnessReads<T>,
+    pub(crate) commit_latency: CommitLatencyTracker,
+    // The time from forwarding proposals until they are decided in the log of this server
+    forward_latency: CommitLatencyTracker,
+    proposal_affinity: ProposalAffinityTracker,
+    origins: OriginTracker,
+    pub(crate) slow_followers: SlowFollowerDetector,
+    pub(crate) rejected_messages: RejectedMessageTracker,
+    pub(crate) divergence: DivergenceDetector,
+    // The accept-phase messages from the leader that were dropped because they were outdated or
+    // revealed that preceding messages were lost
+    pub(crate) outdated_messages: u64,
+    pub(crate) dropped_preceding_messages: u64,
+    // The outdated messages that had already been received, and the held messages that were
+    // handled once the messages they overtook arrived
+    pub(crate) duplicate_messages: u64,
+    pub(crate) reordered_messages: u64,
+    pub(crate) sequence_recovery: SequenceRecoveryTracker,
+    metrics: Metrics,
+    // Encrypts and signs the snapshots sent to and opens the snapshots received from other servers
+    #[cfg(feature = "snapshot_sealing")]
+    snapshot_sealer: Option<Arc<dyn SnapshotSealer>>,
+    storage_size_limit: Option<u64>,
+    low_space_policy: LowSpacePolicy,
+    follower_append_policy: FollowerAppendPolicy,
+    max_entry_size: Option<usize>,
+    // Whether the storage exceeded the `storage_size_limit` when it was last checked
+    storage_full: bool,
+    memory_limit: Option<usize>,
+    // Whether the buffers exceeded the `memory_limit` when they were last checked
+    memory_exceeded: bool,
+    snapshot_interval: Option<usize>,
+    // The index, modulo `snapshot_interval`, at which this server snapshots its log
+    snapshot_offset: usize,
+    // Extracts the settings changes from decided entries once replicated settings are enabled
+    settings_extractor: Option<fn(&T) -> Option<ReplicatedSettings>>,
+    // The log index up to which the decided entries have been checked for settings changes
+    settings_checked_idx: usize,
+    // The index of the last applied settings change and all applied changes merged
+    applied_settings: Option<(usize, ReplicatedSettings)>,
     #[cfg(feature = "logging")]
     logger: Logger,
 }
@@ -50,18 +179,25 @@ where
 {
     /*** User functions ***/
     /// Creates a Sequence Paxos replica.
-    pub(crate) fn with(config: SequencePaxosConfig, storage: B) -> Self {
+    pub(crate) fn with(config: SequencePaxosConfig, storage: B) -> Result<Self, BuildErr> {
         let pid = config.pid;
         let peers = config.peers;
         let num_nodes = &peers.len() + 1;
-        let quorum = Quorum::with(config.flexible_quorum, num_nodes);
+        let quorum = Quorum::with(
+            config.flexible_quorum,
+            config.hierarchical_quorum,
+            &config.regions,
+            num_nodes,
+        );
         let max_peer_pid = peers.iter().max().unwrap();
         let max_pid = *std::cmp::max(max_peer_pid, &pid) as usize;
-        let mut outgoing = Vec::with_capacity(config.buffer_size);
-        let (state, leader) = match storage
+        let mut outgoing = OutgoingQueues::with(config.buffer_size);
+        let snapshot_offset =
+            Self::snapshot_offset(pid, &config.cluster_config.nodes, config.snapshot_interval);
+        let promise = storage
             .get_promise()
-            .expect("storage error while trying to read promise")
-        {
+            .or_fatal("storage error while trying to read promise")?;
+        let (state, leader) = match promise {
             // if we recover a promise from storage then we must do failure recovery
             Some(b) => {
                 let state = (Role::Follower, Phase::Recover);
@@ -70,6 +206,7 @@ where
                     outgoing.push(PaxosMessage {
                         from: pid,
                         to: *peer_pid,
+                        namespace: config.cluster_config.namespace,
                         msg: PaxosMsg::PrepareReq(prepreq),
                     });
                 }
@@ -79,6 +216,14 @@ where
         };
         let internal_storage_config = InternalStorageConfig {
             batch_size: config.batch_size,
+            batch_byte_size: config.batch_byte_size,
+            decided_cache_size: config.decided_cache_size,
+            trim_mode: config.trim_mode,
+            #[cfg(not(feature = "no_snapshots"))]
+            check_snapshots: config.check_snapshots,
+            #[cfg(not(feature = "no_snapshots"))]
+            snapshot_batch_size: config.snapshot_batch_size,
+            track_entry_ballots: config.track_entry_ballots,
         };
         let mut paxos = SequencePaxos {
             internal_storage: InternalStorage::with(
@@ -86,18 +231,86 @@ where
                 internal_storage_config,
                 #[cfg(feature = "unicache")]
                 pid,
-            ),
+            )
+            .or_fatal("Failed to load cache from storage.")?,
             pid,
             peers,
+            backups: config
+                .backup_nodes
+                .iter()
+                .copied()
+                .filter(|p| *p != pid)
+                .collect(),
+            is_backup: config.backup_nodes.contains(&pid),
+            backup_decided_idx: HashMap::new(),
+            regions: config.regions,
             state,
             buffered_proposals: vec![],
+            buffered_origins: vec![],
+            buffered_ticks: VecDeque::new(),
+            forward_batch: ForwardBatch::with(
+                config.forward_batch_size,
+                config.forward_batch_byte_size,
+            ),
+            dependents: DependentProposals::default(),
+            buffer_clock: 0,
+            rejected_proposals: vec![],
+            superseded_proposals: vec![],
             buffered_stopsign: None,
+            proposed_stopsign: None,
+            recovered_stopsign: None,
+            rejected_stopsign_reason: None,
+            cluster_config: config.cluster_config,
+            reconfiguration_validator: config.reconfiguration_validator,
             outgoing,
             leader_state: LeaderState::<T>::with(leader, max_pid, quorum),
             latest_accepted_meta: None,
+            reported_accepted_idx: 0,
             current_seq_num: SequenceNumber::default(),
+            held_messages: ReorderBuffer::with(config.reorder_window as u64),
             cached_promise_message: None,
-            buffer_size: config.buffer_size,
+            standby_ready: None,
+            preempted: false,
+            sync_batch_size: config.sync_batch_size,
+            sync_batch_byte_size: config.sync_batch_byte_size,
+            sync_prefetch: config.sync_prefetch,
+            max_sync_suffix_entries: config.max_sync_suffix_entries,
+            requested_sync_idx: None,
+            storage_verifier: StorageVerifier::with(config.verification_batch_size),
+            recovery: None,
+            bootstrap_peer: None,
+            quorum_recovery: None,
+            witness_reads: WitnessReads::default(),
+            commit_latency: CommitLatencyTracker::default(),
+            forward_latency: CommitLatencyTracker::default(),
+            proposal_affinity: ProposalAffinityTracker::default(),
+            origins: OriginTracker::default(),
+            slow_followers: SlowFollowerDetector::with(
+                config.slow_follower_lag,
+                config.slow_follower_tick_timeout,
+            ),
+            rejected_messages: RejectedMessageTracker::with(config.misbehaving_peer_threshold),
+            divergence: DivergenceDetector::default(),
+            outdated_messages: 0,
+            dropped_preceding_messages: 0,
+            duplicate_messages: 0,
+            reordered_messages: 0,
+            sequence_recovery: SequenceRecoveryTracker::default(),
+            metrics: Metrics::with(config.metrics_sink),
+            #[cfg(feature = "snapshot_sealing")]
+            snapshot_sealer: config.snapshot_sealer,
+            storage_size_limit: config.storage_size_limit,
+            low_space_policy: config.low_space_policy,
+            follower_append_policy: config.follower_append_policy,
+            max_entry_size: config.max_entry_size,
+            storage_full: false,
+            memory_limit: config.memory_limit,
+            memory_exceeded: false,
+            snapshot_interval: config.snapshot_interval,
+            snapshot_offset,
+            settings_extractor: None,
+            settings_checked_idx: 0,
+            applied_settings: None,
             #[cfg(feature = "logging")]
             logger: {
                 if let Some(logger) = config.custom_logger {
@@ -113,11 +326,15 @@ where
         paxos
             .internal_storage
             .set_promise(leader)
-            .expect(WRITE_ERROR_MSG);
+            .or_fatal(WRITE_ERROR_MSG)?;
+        if paxos.state.1 == Phase::Recover {
+            paxos.start_recovery();
+            paxos.recover_stopsign();
+        }
         #[cfg(feature = "logging")]
         {
             info!(paxos.logger, "Paxos component pid: {} created!", pid);
-            if let Quorum::Flexible(flex_quorum) = quorum {
+            if let Quorum::Flexible(flex_quorum) = paxos.leader_state.quorum {
                 if flex_quorum.read_quorum_size > num_nodes - flex_quorum.write_quorum_size + 1 {
                     warn!(
                         paxos.logger,
@@ -125,17 +342,51 @@ where
                 }
             }
         }
-        paxos
+        Ok(paxos)
     }
 
     pub(crate) fn get_state(&self) -> &(Role, Phase) {
         &self.state
     }
 
+    /// Persists the ballot the leader election uses before it is sent to other servers.
+    pub(crate) fn set_ble_ballot(&mut self, ballot: Ballot) -> Result<(), ProtocolError> {
+        self.internal_storage
+            .set_ble_ballot(ballot)
+            .or_fatal(WRITE_ERROR_MSG)?;
+        Ok(())
+    }
+
     pub(crate) fn get_promise(&self) -> Ballot {
         self.internal_storage.get_promise()
     }
 
+    /// The standby promise of the round after the current promise, which the leader election uses
+    /// to let the hot-standby leader take over. The standby promise of this server itself is only
+    /// returned once it is ready to take over without a prepare phase.
+    pub(crate) fn get_election_standby(&self) -> Option<Ballot> {
+        let promise = self.get_promise();
+        self.internal_storage
+            .get_standby_promise()
+            .filter(|c| c.config_id == promise.config_id && c.n == promise.n + 1)
+            .filter(|c| c.pid != self.pid || self.standby_ready == Some(promise))
+    }
+
+    /// The hot-standby leader that this server promised the round of `n` to, if `n` is a ballot of
+    /// another server in that round or before it.
+    pub(crate) fn blocking_standby(&self, n: Ballot) -> Option<NodeId> {
+        self.internal_storage
+            .get_standby_promise()
+            .filter(|c| c.config_id == n.config_id && n.n <= c.n && n.pid != c.pid)
+            .map(|c| c.pid)
+    }
+
+    /// Returns whether the ballot of this server was preempted by a standby promise since the last
+    /// call, in which case it should give up its leadership.
+    pub(crate) fn take_preempted(&mut self) -> bool {
+        std::mem::take(&mut self.preempted)
+    }
+
     /// Initiates the trim process.
     /// # Arguments
     /// * `trim_idx` - Deletes all entries up to [`trim_idx`], if the [`trim_idx`] is `None` then the minimum index accepted by **ALL** servers will be used as the [`trim_idx`].
@@ -165,14 +416,12 @@ where
                         self.outgoing.push(PaxosMessage {
                             from: self.pid,
                             to: *pid,
+                            namespace: self.cluster_config.namespace,
                             msg,
                         });
                     }
                 }
-                result.map_err(|e| {
-                    *e.downcast()
-                        .expect("storage error while trying to trim log")
-                })
+                result.map_err(|e| self.compaction_err(e, "trim the log"))
             }
             _ => Err(CompactionErr::NotCurrentLeader(self.get_current_leader())),
         }
@@ -195,14 +444,25 @@ where
                 self.outgoing.push(PaxosMessage {
                     from: self.pid,
                     to: *pid,
+                    namespace: self.cluster_config.namespace,
                     msg,
                 });
             }
         }
-        result.map_err(|e| {
-            *e.downcast()
-                .expect("storage error while trying to snapshot log")
-        })
+        result.map_err(|e| self.compaction_err(e, "snapshot the log"))
+    }
+
+    /// Returns the `CompactionErr` that the internal storage failed with, or logs the error of the
+    /// storage backend if it failed while trying to `op`.
+    fn compaction_err(&self, e: Box<dyn Error>, op: &'static str) -> CompactionErr {
+        match e.downcast::<CompactionErr>() {
+            Ok(e) => *e,
+            Err(_e) => {
+                #[cfg(feature = "logging")]
+                warn!(self.logger, "Storage failed while trying to {}: {}", op, _e);
+                CompactionErr::Storage(op)
+            }
+        }
     }
 
     /// Return the decided index.
@@ -215,6 +475,10 @@ where
         self.internal_storage.get_compacted_idx()
     }
 
+    pub(crate) fn get_cluster_config(&self) -> &ClusterConfig {
+        &self.cluster_config
+    }
+
     fn handle_compaction(&mut self, c: Compaction) {
         // try trimming and snapshotting forwarded compaction. Errors are ignored as that the data will still be kept.
         match c {
@@ -237,44 +501,654 @@ where
         }
     }
 
+    /// Reports the accepted entries that the storage has persisted since they were written, if it
+    /// acknowledges writes before they are durable. See
+    /// [`LogStorage::get_durable_idx`](crate::storage::LogStorage::get_durable_idx).
+    pub(crate) fn durability_timeout(&mut self) -> Result<(), ProtocolError> {
+        match self.state {
+            (Role::Leader, Phase::Accept) => self.durability_timeout_leader()?,
+            (Role::Follower, Phase::Accept) => self.durability_timeout_follower()?,
+            _ => (),
+        }
+        Ok(())
+    }
+
+    /// Appends the batched entries, waits until the storage has persisted them, and reports them
+    /// as accepted. Returns the durable accepted index.
+    pub(crate) fn flush_and_get_durable_idx(&mut self) -> StorageResult<usize> {
+        self.flush_batch_timeout()?;
+        self.internal_storage.flush()?;
+        self.durability_timeout()?;
+        self.internal_storage.get_durable_accepted_idx()
+    }
+
+    /// Requests the entries after the accepted index from the leader if following in pull mode.
+    pub(crate) fn pull_timeout(&mut self) -> Result<(), ProtocolError> {
+        if self.state == (Role::Follower, Phase::Accept) {
+            self.send_pull_request()?;
+        }
+        Ok(())
+    }
+
+    /// Verifies the next batch of the stored log and snapshot.
+    pub(crate) fn verification_timeout(&mut self) {
+        let failures = self.internal_storage.verify(&mut self.storage_verifier);
+        #[cfg(feature = "logging")]
+        for failure in &failures {
+            warn!(self.logger, "Storage verification failed: {:?}", failure);
+        }
+        #[cfg(not(feature = "logging"))]
+        let _ = failures;
+    }
+
+    /// Sends the fingerprint of the entries decided since the previous fingerprint to the
+    /// followers if this server is the leader.
+    pub(crate) fn fingerprint_timeout(&mut self) -> Result<(), ProtocolError> {
+        if self.state != (Role::Leader, Phase::Accept) {
+            return Ok(());
+        }
+        let from_idx = self
+            .divergence
+            .get_next_idx()
+            .max(self.internal_storage.get_compacted_idx());
+        let to_idx = self.internal_storage.get_decided_idx();
+        if from_idx >= to_idx {
+            return Ok(());
+        }
+        self.divergence.set_next_idx(to_idx);
+        let entries = self
+            .internal_storage
+            .get_entries(from_idx, to_idx)
+            .or_fatal(READ_ERROR_MSG)?;
+        let Some(fingerprint) = fingerprint_entries(&entries) else {
+            return Ok(());
+        };
+        let fp = DecidedFingerprint {
+            n: self.leader_state.n_leader,
+            from_idx,
+            to_idx,
+            fingerprint,
+        };
+        for pid in &self.peers {
+            self.outgoing.push(PaxosMessage {
+                from: self.pid,
+                to: *pid,
+                namespace: self.cluster_config.namespace,
+                msg: PaxosMsg::DecidedFingerprint(fp),
+            });
+        }
+        Ok(())
+    }
+
+    /// Updates how far each follower is behind and reports the ones that became slow or caught up.
+    pub(crate) fn check_slow_followers<F>(&mut self, is_connected: F)
+    where
+        F: Fn(NodeId) -> bool,
+    {
+        if self.state != (Role::Leader, Phase::Accept) {
+            return;
+        }
+        let leader_idx = self.leader_state.get_accepted_idx(self.pid);
+        self.slow_followers.tick();
+        for pid in &self.peers {
+            let follower_idx = self.leader_state.get_accepted_idx(*pid);
+            self.slow_followers
+                .update(*pid, follower_idx, leader_idx, is_connected(*pid));
+        }
+    }
+
+    /// Checks whether the storage exceeds the `storage_size_limit` and applies the
+    /// `low_space_policy` if it does.
+    pub(crate) fn check_storage_size(&mut self) -> Result<(), ProtocolError> {
+        let limit = match self.storage_size_limit {
+            Some(limit) => limit,
+            None => return Ok(()),
+        };
+        let mut full = self.storage_exceeds(limit)?;
+        if full && self.low_space_policy == LowSpacePolicy::CompactThenRefuseAppends {
+            self.compact_decided_log();
+            full = self.storage_exceeds(limit)?;
+        }
+        #[cfg(feature = "logging")]
+        match (self.storage_full, full) {
+            (false, true) => warn!(
+                self.logger,
+                "Storage exceeds the size limit of {} bytes, refusing proposals", limit
+            ),
+            (true, false) => info!(
+                self.logger,
+                "Storage is below the size limit of {} bytes again", limit
+            ),
+            _ => (),
+        }
+        self.storage_full = full;
+        Ok(())
+    }
+
+    /// Checks whether the buffers of this server, together with the `other` bytes it uses outside
+    /// of Sequence Paxos, exceed the `memory_limit`. If they do, the log tail cache is dropped first
+    /// and new proposals are refused if that is not enough.
+    pub(crate) fn check_memory_limit(&mut self, other: usize) {
+        let limit = match self.memory_limit {
+            Some(limit) => limit,
+            None => return,
+        };
+        let mut exceeded = self.get_memory_usage().total() + other > limit;
+        if exceeded {
+            self.internal_storage.clear_tail_cache();
+            exceeded = self.get_memory_usage().total() + other > limit;
+        }
+        #[cfg(feature = "logging")]
+        match (self.memory_exceeded, exceeded) {
+            (false, true) => warn!(
+                self.logger,
+                "Buffers exceed the memory limit of {} bytes, refusing proposals", limit
+            ),
+            (true, false) => info!(
+                self.logger,
+                "Buffers are below the memory limit of {} bytes again", limit
+            ),
+            _ => (),
+        }
+        self.memory_exceeded = exceeded;
+    }
+
+    /// Whether new proposals are refused because the buffers exceeded the `memory_limit` when they
+    /// were last checked.
+    pub(crate) fn is_memory_exceeded(&self) -> bool {
+        self.memory_exceeded
+    }
+
+    /// Returns the approximate number of bytes occupied by the buffers of this Sequence Paxos.
+    pub(crate) fn get_memory_usage(&self) -> MemoryUsage {
+        let buffered: usize = self.buffered_proposals.iter().map(|e| e.size_hint()).sum();
+        let cached_promise = self
+            .cached_promise_message
+            .as_ref()
+            .and_then(|p| p.log_sync.as_ref())
+            .map_or(0, LogSync::size_hint);
+        MemoryUsage {
+            outgoing: self.outgoing.memory_usage(PaxosMessage::size_hint),
+            pending_proposals: buffered
+                + self.forward_batch.size_hint()
+                + self.dependents.size_hint()
+                + self.internal_storage.get_batch_memory_usage(),
+            caches: self.internal_storage.get_cache_memory_usage(),
+            sync: self.held_messages.memory_usage(PaxosMsg::size_hint) + cached_promise,
+        }
+    }
+
+    /// Compacts the decided log once the log fills half of the capacity of the storage, so that the
+    /// storage does not run full as long as the entries are decided and can be compacted.
+    pub(crate) fn check_storage_capacity(&mut self) {
+        if let Some(capacity) = self.internal_storage.get_capacity() {
+            if self.internal_storage.get_uncompacted_len() * 2 >= capacity {
+                self.compact_decided_log();
+            }
+        }
+    }
+
+    /// Removes the compacted entries from the storage if the `trim_mode` defers their removal to
+    /// the compaction timer.
+    pub(crate) fn trim_timeout(&mut self) -> Result<(), ProtocolError> {
+        if self.internal_storage.get_trim_mode() == TrimMode::Tick {
+            self.internal_storage
+                .run_deferred_trim()
+                .or_fatal(WRITE_ERROR_MSG)?;
+        }
+        Ok(())
+    }
+
+    fn storage_exceeds(&self, limit: u64) -> Result<bool, ProtocolError> {
+        Ok(self
+            .internal_storage
+            .approximate_size()
+            .or_fatal(READ_ERROR_MSG)?
+            .is_some_and(|size| size > limit))
+    }
+
+    /// Compacts as much of the decided log as this server can compact by itself.
+    fn compact_decided_log(&mut self) {
+        let compacted_idx = self.internal_storage.get_compacted_idx();
+        if use_snapshots::<T>() {
+            if self.internal_storage.get_decided_idx() > compacted_idx {
+                let _ = self.snapshot(None, true);
+            }
+        } else if self.state.0 == Role::Leader
+            && *self.leader_state.get_min_all_accepted_idx() > compacted_idx
+        {
+            let _ = self.trim(None);
+        }
+    }
+
+    /// Snapshots the decided log if it has passed the next index at which this server is scheduled
+    /// to snapshot. The scheduled indexes are `snapshot_offset` plus a multiple of `snapshot_interval`.
+    pub(crate) fn check_snapshot_schedule(&mut self) {
+        let interval = match self.snapshot_interval {
+            Some(interval) if use_snapshots::<T>() => interval,
+            _ => return,
+        };
+        let decided_idx = self.internal_storage.get_decided_idx();
+        if decided_idx < self.snapshot_offset {
+            return;
+        }
+        let scheduled_idx =
+            self.snapshot_offset + (decided_idx - self.snapshot_offset) / interval * interval;
+        if scheduled_idx > self.internal_storage.get_compacted_idx() {
+            #[cfg(feature = "logging")]
+            debug!(self.logger, "Scheduled snapshot at idx: {}", scheduled_idx);
+            let _ = self.snapshot(Some(scheduled_idx), true);
+        }
+    }
+
+    /// Spreads the snapshot indexes of the `nodes` evenly over the `snapshot_interval`.
+    fn snapshot_offset(pid: NodeId, nodes: &[NodeId], snapshot_interval: Option<usize>) -> usize {
+        match snapshot_interval {
+            Some(interval) => {
+                let rank = nodes.iter().filter(|p| **p < pid).count();
+                rank * interval / nodes.len().max(1)
+            }
+            None => 0,
+        }
+    }
+
+    /// Starts applying the settings changes that `extractor` finds in the decided entries.
+    /// Changes in the already compacted prefix of the log cannot be read and are not applied.
+    pub(crate) fn enable_replicated_settings(
+        &mut self,
+        extractor: fn(&T) -> Option<ReplicatedSettings>,
+    ) -> Result<(), ProtocolError> {
+        self.settings_extractor = Some(extractor);
+        self.settings_checked_idx = self.internal_storage.get_compacted_idx();
+        self.apply_replicated_settings()
+    }
+
+    /// Applies the settings changes that have been decided since the last check.
+    pub(crate) fn apply_replicated_settings(&mut self) -> Result<(), ProtocolError> {
+        let extractor = match self.settings_extractor {
+            Some(extractor) => extractor,
+            None => return Ok(()),
+        };
+        let decided_idx = self.internal_storage.get_decided_idx();
+        let from_idx = self
+            .settings_checked_idx
+            .max(self.internal_storage.get_compacted_idx());
+        if from_idx >= decided_idx {
+            return Ok(());
+        }
+        let entries = self
+            .internal_storage
+            .get_entries(from_idx, decided_idx)
+            .or_fatal(READ_ERROR_MSG)?;
+        for (offset, entry) in entries.iter().enumerate() {
+            if let Some(settings) = extractor(entry) {
+                self.apply_settings(from_idx + offset, settings);
+            }
+        }
+        self.settings_checked_idx = decided_idx;
+        Ok(())
+    }
+
+    fn apply_settings(&mut self, idx: usize, settings: ReplicatedSettings) {
+        if let Err(_e) = settings.validate() {
+            #[cfg(feature = "logging")]
+            warn!(
+                self.logger,
+                "Ignoring invalid settings at idx {}: {}", idx, _e
+            );
+            return;
+        }
+        #[cfg(feature = "logging")]
+        info!(
+            self.logger,
+            "Applying settings at idx {}: {:?}", idx, settings
+        );
+        if let Some(batch_size) = settings.batch_size {
+            self.internal_storage.set_batch_size(batch_size);
+        }
+        if let Some(sync_batch_size) = settings.sync_batch_size {
+            self.sync_batch_size = sync_batch_size;
+        }
+        if let Some(snapshot_interval) = settings.snapshot_interval {
+            self.snapshot_interval = snapshot_interval;
+            self.snapshot_offset =
+                Self::snapshot_offset(self.pid, &self.cluster_config.nodes, snapshot_interval);
+        }
+        match &mut self.applied_settings {
+            Some((applied_idx, applied)) => {
+                *applied_idx = idx;
+                applied.merge(&settings);
+            }
+            None => self.applied_settings = Some((idx, settings)),
+        }
+    }
+
+    pub(crate) fn get_applied_settings(&self) -> Option<(usize, &ReplicatedSettings)> {
+        self.applied_settings.as_ref().map(|(idx, s)| (*idx, s))
+    }
+
+    /// Whether the application halted this server after a fatal error.
+    pub(crate) fn is_halted(&self) -> bool {
+        self.internal_storage.get_fatal_error().is_some()
+    }
+
+    /// Halts this server if `res` failed with a [`FatalErr`](crate::errors::FatalErr) and the
+    /// `no_panic` feature is enabled, so that it never continues from a state that might differ
+    /// from its storage. Otherwise, the server keeps running and the application decides whether
+    /// to retry or to halt it.
+    #[cfg(feature = "no_panic")]
+    pub(crate) fn halt_if_failed<R, E: Failure>(&self, res: Result<R, E>) -> Result<R, E> {
+        if let Some(err) = res.as_ref().err().and_then(Failure::failure) {
+            self.internal_storage.halt(err.clone());
+        }
+        res
+    }
+
+    #[cfg(not(feature = "no_panic"))]
+    pub(crate) fn halt_if_failed<R, E>(&self, res: Result<R, E>) -> Result<R, E> {
+        res
+    }
+
+    /// Whether the storage exceeds the `storage_size_limit`, or the log fills the capacity of the
+    /// storage.
+    pub(crate) fn is_storage_full(&self) -> bool {
+        self.storage_full
+            || self
+                .internal_storage
+                .get_capacity()
+                .is_some_and(|capacity| self.internal_storage.get_uncompacted_len() >= capacity)
+    }
+
+    pub(crate) fn get_verification_report(&self) -> &VerificationReport {
+        self.storage_verifier.get_report()
+    }
+
+    /// Flushes the batched entries and switches to `storage` once it holds a verified copy of the
+    /// state. Returns the previous storage.
+    pub(crate) fn swap_storage(&mut self, storage: B) -> Result<B, StorageMigrationErr>
+    where
+        T: PartialEq,
+    {
+        self.flush_batch_timeout()?;
+        self.internal_storage.swap_storage(storage)
+    }
+
+    /// Starts a witness read of `idx` by requesting the log of all other servers at the index.
+    pub(crate) fn start_witness_read(&mut self, idx: usize) -> u64 {
+        let witness = self.create_witness(idx);
+        let id = self.witness_reads.start(idx, self.pid, witness);
+        for peer in self.peers.clone() {
+            self.outgoing.push(PaxosMessage {
+                from: self.pid,
+                to: peer,
+                namespace: self.cluster_config.namespace,
+                msg: PaxosMsg::WitnessReadRequest(WitnessReadRequest { id, idx }),
+            });
+        }
+        id
+    }
+
+    /// Returns the report of the witness read `id` once a read quorum of servers has replied.
+    pub(crate) fn take_witness_report(&mut self, id: u64) -> Option<WitnessReport<T>> {
+        let witnesses = self.witness_reads.witnesses(id)?;
+        if !self.leader_state.quorum.is_prepare_quorum(witnesses) {
+            return None;
+        }
+        self.witness_reads.take(id)
+    }
+
+    fn create_witness(&self, idx: usize) -> Witness<T> {
+        let decided_idx = self.internal_storage.get_decided_idx();
+        let compacted_idx = self.internal_storage.get_compacted_idx();
+        // Compacted entries are not read, as that would create a snapshot
+        let read = match idx < compacted_idx {
+            true => Ok(None),
+            false => self.internal_storage.read(idx..idx + 1),
+        };
+        let (entry, error) = match read {
+            Ok(Some(mut entries)) => match entries.pop() {
+                Some(LogEntry::Decided(e)) | Some(LogEntry::Undecided(e)) => (Some(e), None),
+                _ => (None, None),
+            },
+            Ok(None) => (None, None),
+            Err(e) => (None, Some(e.to_string())),
+        };
+        Witness {
+            entry,
+            decided: idx < decided_idx,
+            entry_ballot: self.internal_storage.get_entry_ballot(idx),
+            accepted_round: self.internal_storage.get_accepted_round(),
+            accepted_idx: self.internal_storage.get_accepted_idx(),
+            decided_idx,
+            compacted_idx,
+            error,
+        }
+    }
+
+    fn handle_witness_read_request(&mut self, req: WitnessReadRequest, from: NodeId) {
+        let resp = WitnessReadResponse {
+            id: req.id,
+            witness: self.create_witness(req.idx),
+        };
+        self.outgoing.push(PaxosMessage {
+            from: self.pid,
+            to: from,
+            namespace: self.cluster_config.namespace,
+            msg: PaxosMsg::WitnessReadResponse(resp),
+        });
+    }
+
+    fn handle_witness_read_response(&mut self, resp: WitnessReadResponse<T>, from: NodeId) {
+        if self.peers.contains(&from) {
+            self.witness_reads.add(resp.id, from, resp.witness);
+        }
+    }
+
     /// Flushes any batched log entries and sends their corresponding Accept or Accepted messages.
-    pub(crate) fn flush_batch_timeout(&mut self) {
+    pub(crate) fn flush_batch_timeout(&mut self) -> Result<(), ProtocolError> {
         match self.state {
-            (Role::Leader, Phase::Accept) => self.flush_batch_leader(),
-            (Role::Follower, Phase::Accept) => self.flush_batch_follower(),
+            (Role::Leader, Phase::Accept) => self.flush_batch_leader()?,
+            (Role::Follower, Phase::Accept) => self.flush_batch_follower()?,
             _ => (),
         }
+        Ok(())
     }
 
     /// Returns the outgoing messages from this replica. The messages should then be sent via the network implementation.
     pub(crate) fn get_outgoing_msgs(&mut self) -> Vec<PaxosMessage<T>> {
-        let mut outgoing = Vec::with_capacity(self.buffer_size);
-        std::mem::swap(&mut self.outgoing, &mut outgoing);
         self.leader_state.reset_batch_accept_meta();
         self.latest_accepted_meta = None;
-        outgoing
+        self.outgoing.take_all()
+    }
+
+    /// Returns the outgoing messages to `pid`. The messages to other peers stay queued.
+    pub(crate) fn get_outgoing_msgs_for(&mut self, pid: NodeId) -> Vec<PaxosMessage<T>> {
+        self.leader_state.reset_batch_accept_meta_to(pid);
+        if matches!(self.latest_accepted_meta, Some((_, (to, _))) if to == pid) {
+            self.latest_accepted_meta = None;
+        }
+        self.outgoing.take_for(pid)
+    }
+
+    /// Spill the outgoing sync messages queued from now on to `spill`.
+    #[cfg(feature = "spill")]
+    pub(crate) fn enable_spill(&mut self, spill: SpillFile<PaxosMessage<T>>) {
+        self.outgoing.enable_spill(spill);
+    }
+
+    /// Puts `msgs` back in front of the outgoing messages, e.g., if they did not fit in the
+    /// budget of the user.
+    pub(crate) fn requeue_outgoing_msgs(&mut self, msgs: Vec<PaxosMessage<T>>) {
+        self.outgoing.requeue(msgs);
+        // Cached messages are identified by their position in `outgoing`, which just changed.
+        self.leader_state.reset_batch_accept_meta();
+        self.latest_accepted_meta = None;
+    }
+
+    /// The number of queued outgoing messages.
+    pub(crate) fn num_outgoing_msgs(&self) -> usize {
+        self.outgoing.num_queued()
+    }
+
+    /// The number of proposals buffered until there is a leader to append or forward them to.
+    pub(crate) fn num_buffered_proposals(&self) -> usize {
+        self.buffered_proposals.len()
+    }
+
+    /// The number of entries this leader has accepted but each promised follower has not. Empty
+    /// if this server is not the leader in the accept phase.
+    pub(crate) fn get_in_flight(&self) -> HashMap<NodeId, usize> {
+        if self.state != (Role::Leader, Phase::Accept) {
+            return HashMap::new();
+        }
+        let leader_idx = self.leader_state.get_accepted_idx(self.pid);
+        self.leader_state
+            .get_promised_followers()
+            .into_iter()
+            .map(|pid| {
+                let follower_idx = self.leader_state.get_accepted_idx(pid);
+                (pid, leader_idx.saturating_sub(follower_idx))
+            })
+            .collect()
+    }
+
+    pub(crate) fn recovery_progress(&self) -> Option<&RecoveryProgress> {
+        self.recovery.as_ref()
+    }
+
+    pub(crate) fn take_completed_recovery(&mut self) -> Option<RecoveryProgress> {
+        match &self.recovery {
+            Some(recovery) if recovery.completed => self.recovery.take(),
+            _ => None,
+        }
+    }
+
+    /// Enters the recover phase. Keeps reporting the progress of an ongoing recovery.
+    pub(crate) fn start_recovery(&mut self) {
+        self.state = (Role::Follower, Phase::Recover);
+        if !matches!(&self.recovery, Some(recovery) if !recovery.completed) {
+            self.recovery = Some(RecoveryProgress {
+                start_idx: self.internal_storage.get_accepted_idx(),
+                entries_restored: 0,
+                source: None,
+                completed: false,
+            });
+        }
+    }
+
+    /// Remembers the accepted StopSign if it is not decided yet, so that the reconfiguration is
+    /// proposed again if it turns out to not be chosen once the log is synchronized.
+    pub(crate) fn recover_stopsign(&mut self) {
+        if !self.internal_storage.stopsign_is_decided() {
+            self.recovered_stopsign = self.internal_storage.get_stopsign();
+            #[cfg(feature = "logging")]
+            if let Some(ss) = &self.recovered_stopsign {
+                info!(
+                    self.logger,
+                    "Recovered undecided reconfiguration {:?}", ss.next_config.nodes
+                );
+            }
+        }
     }
 
-    /// Handle an incoming message.
-    pub(crate) fn handle(&mut self, m: PaxosMessage<T>) {
+    /// Resolves the StopSign remembered by `recover_stopsign` after the log was synchronized with
+    /// the leader. If the synchronized log still contains a StopSign, it is decided as usual.
+    /// Otherwise, the recovered StopSign was not chosen and is proposed again.
+    pub(crate) fn resolve_recovered_stopsign(&mut self) {
+        let ss = match self.recovered_stopsign.take() {
+            Some(ss) if self.internal_storage.get_stopsign().is_none() => ss,
+            _ => return,
+        };
+        #[cfg(feature = "logging")]
+        info!(
+            self.logger,
+            "Proposing recovered reconfiguration {:?} again", ss.next_config.nodes
+        );
+        self.proposed_stopsign = Some(ss.clone());
+        match self.state.0 {
+            Role::Leader if self.buffered_stopsign.is_none() => self.buffered_stopsign = Some(ss),
+            Role::Leader => (),
+            Role::Follower => self.forward_stopsign(ss),
+        }
+    }
+
+    fn check_recovery_completed(&mut self) {
+        if let Some(recovery) = self.recovery.as_mut() {
+            if !recovery.completed && self.state.1 == Phase::Accept {
+                recovery.completed = true;
+                #[cfg(feature = "logging")]
+                info!(
+                    self.logger,
+                    "Recovery completed: {} entries restored from {:?}",
+                    recovery.entries_restored,
+                    recovery.source
+                );
+            }
+        }
+    }
+
+    /// Handle an incoming message. Returns why the message was rejected if it carries a snapshot
+    /// that fails to open, or the error if the storage failed while handling it.
+    pub(crate) fn handle(&mut self, m: PaxosMessage<T>) -> Result<MessageOutcome, ProtocolError> {
+        #[cfg(feature = "snapshot_sealing")]
+        let m = {
+            let mut m = m;
+            if let Err(_e) = self.open_sealed_snapshot(&mut m) {
+                #[cfg(feature = "logging")]
+                warn!(self.logger, "Invalid snapshot from {}: {}", m.from, _e);
+                let cause = RejectedMessageCause::InvalidSnapshot;
+                self.reject_message(m.from, cause);
+                return Ok(MessageOutcome::Rejected(cause));
+            }
+            m
+        };
         match m.msg {
             PaxosMsg::PrepareReq(prepreq) => self.handle_preparereq(prepreq, m.from),
-            PaxosMsg::Prepare(prep) => self.handle_prepare(prep, m.from),
+            PaxosMsg::Prepare(prep) => self.handle_prepare(prep, m.from)?,
             PaxosMsg::Promise(prom) => match &self.state {
-                (Role::Leader, Phase::Prepare) => self.handle_promise_prepare(prom, m.from),
-                (Role::Leader, Phase::Accept) => self.handle_promise_accept(prom, m.from),
+                (Role::Leader, Phase::Prepare) => self.handle_promise_prepare(prom, m.from)?,
+                (Role::Leader, Phase::Accept) => self.handle_promise_accept(prom, m.from)?,
                 _ => {}
             },
-            PaxosMsg::AcceptSync(acc_sync) => self.handle_acceptsync(acc_sync, m.from),
-            PaxosMsg::AcceptDecide(acc) => self.handle_acceptdecide(acc),
+            PaxosMsg::AcceptSync(acc_sync) => self.handle_acceptsync(acc_sync, m.from)?,
+            PaxosMsg::SyncPortion(portion) => self.handle_sync_portion(portion, m.from)?,
+            PaxosMsg::SyncRequest(req) => self.handle_sync_request(req, m.from)?,
+            PaxosMsg::PullRequest(req) => self.handle_pull_request(req, m.from)?,
+            PaxosMsg::PullResponse(resp) => self.handle_pull_response(resp)?,
+            PaxosMsg::AcceptDecide(acc) => self.handle_acceptdecide(acc)?,
+            PaxosMsg::RelayAcceptDecide(relay) => self.handle_relay_acceptdecide(relay)?,
             PaxosMsg::NotAccepted(not_acc) => self.handle_notaccepted(not_acc, m.from),
-            PaxosMsg::Accepted(accepted) => self.handle_accepted(accepted, m.from),
-            PaxosMsg::Decide(d) => self.handle_decide(d),
-            PaxosMsg::ProposalForward(proposals) => self.handle_forwarded_proposal(proposals),
+            PaxosMsg::Accepted(accepted) => self.handle_accepted(accepted, m.from)?,
+            PaxosMsg::Decide(d) => self.handle_decide(d)?,
+            PaxosMsg::DecidedFingerprint(fp) => self.handle_decided_fingerprint(fp)?,
+            PaxosMsg::ProposalForward(proposals) => {
+                self.handle_forwarded_proposal(proposals, vec![], m.from, None)?
+            }
+            PaxosMsg::TokenProposalForward(tpf) => {
+                self.handle_forwarded_proposal(tpf.entries, tpf.origins, m.from, tpf.latency)?
+            }
+            PaxosMsg::DecidedTokens(tokens) => self.origins.decided(tokens),
+            PaxosMsg::RejectedProposals(rejected) => self.handle_rejected_proposals(rejected),
             PaxosMsg::Compaction(c) => self.handle_compaction(c),
-            PaxosMsg::AcceptStopSign(acc_ss) => self.handle_accept_stopsign(acc_ss),
-            PaxosMsg::ForwardStopSign(f_ss) => self.handle_forwarded_stopsign(f_ss),
+            PaxosMsg::AcceptStopSign(acc_ss) => self.handle_accept_stopsign(acc_ss)?,
+            PaxosMsg::ForwardStopSign(f_ss) => self.handle_forwarded_stopsign(f_ss, m.from)?,
+            PaxosMsg::RejectStopSign(rej_ss) => self.handle_rejected_stopsign(rej_ss),
+            PaxosMsg::BootstrapRequest(_) => self.handle_bootstrap_request(m.from)?,
+            PaxosMsg::BootstrapResponse(resp) => self.handle_bootstrap_response(resp, m.from)?,
+            PaxosMsg::RecoveryQuery(_) => self.handle_recovery_query(m.from),
+            PaxosMsg::RecoveryState(state) => self.handle_recovery_state(state, m.from)?,
+            PaxosMsg::BackupSnapshot(bs) => self.handle_backup_snapshot(bs, m.from)?,
+            PaxosMsg::BackupAck(ack) => self.handle_backup_ack(ack, m.from),
+            PaxosMsg::Standby(standby) => self.handle_standby(standby, m.from),
+            PaxosMsg::StandbyAck(ack) => self.handle_standby_ack(ack, m.from),
+            PaxosMsg::Takeover(takeover) => self.handle_takeover(takeover, m.from)?,
+            PaxosMsg::WitnessReadRequest(req) => self.handle_witness_read_request(req, m.from),
+            PaxosMsg::WitnessReadResponse(resp) => self.handle_witness_read_response(resp, m.from),
         }
+        self.handle_held_messages()?;
+        self.apply_replicated_settings()?;
+        self.check_recovery_completed();
+        Ok(MessageOutcome::Handled)
     }
 
     /// Returns whether this Sequence Paxos has been reconfigured
@@ -286,56 +1160,231 @@ where
     }
 
     /// Returns whether this Sequence Paxos instance is stopped, i.e. if it has been reconfigured.
-    fn accepted_reconfiguration(&self) -> bool {
+    pub(crate) fn accepted_reconfiguration(&self) -> bool {
         self.internal_storage.get_stopsign().is_some()
     }
 
     /// Append an entry to the replicated log.
     pub(crate) fn append(&mut self, entry: T) -> Result<(), ProposeErr<T>> {
-        if self.accepted_reconfiguration() {
+        let entry = self.check_appendable(entry)?;
+        if let Some((size, limit)) = self.exceeds_max_entry_size(&entry) {
+            return Err(ProposeErr::EntryTooLarge { entry, size, limit });
+        }
+        self.proposal_affinity.proposed(self.pid, 1, None);
+        let res = self.propose_entry(entry);
+        self.halt_if_failed(res)?;
+        Ok(())
+    }
+
+    /// Returns `entry` back if this server currently accepts new entries, or the error to reject
+    /// it with if it has halted, is stopped by a reconfiguration, is out of storage or memory, or
+    /// is a follower that refuses appends. Checked by every way of appending an entry.
+    pub(crate) fn check_appendable(&self, entry: T) -> Result<T, ProposeErr<T>> {
+        if self.is_halted() {
+            Err(ProposeErr::Halted(entry))
+        } else if self.accepted_reconfiguration() {
             Err(ProposeErr::PendingReconfigEntry(entry))
+        } else if self.is_storage_full() {
+            Err(ProposeErr::Full(entry))
+        } else if self.memory_exceeded {
+            Err(ProposeErr::MemoryLimitExceeded(entry))
+        } else if self.rejects_appends() {
+            Err(ProposeErr::NotLeader(entry, self.known_leader()))
         } else {
-            self.propose_entry(entry);
+            Ok(entry)
+        }
+    }
+
+    /// Appends each of the `entries` as with [`SequencePaxos::append`] and returns the outcome of
+    /// each entry in the same order. A rejected entry does not affect the others.
+    pub(crate) fn append_batch(&mut self, entries: Vec<T>) -> Vec<AppendOutcome<T>> {
+        entries
+            .into_iter()
+            .map(|entry| {
+                let accepts = self.state == (Role::Leader, Phase::Accept)
+                    && self.pending_dependency(&entry).is_none();
+                match self.append(entry) {
+                    Ok(()) if accepts => AppendOutcome::Accepted,
+                    Ok(()) => AppendOutcome::Deferred,
+                    Err(e) => AppendOutcome::Rejected(e),
+                }
+            })
+            .collect()
+    }
+
+    /// Returns the size of `entry` and the `max_entry_size` if the entry is larger than that.
+    pub(crate) fn exceeds_max_entry_size(&self, entry: &T) -> Option<(usize, usize)> {
+        let limit = self.max_entry_size?;
+        let size = entry.size_hint();
+        (size > limit).then_some((size, limit))
+    }
+
+    /// Append an entry to the replicated log and attach `token` to it.
+    pub(crate) fn append_with_token(
+        &mut self,
+        entry: T,
+        token: OriginToken,
+    ) -> Result<(), ProposeErr<T>> {
+        self.append_with_origin(entry, Some(token), None)
+    }
+
+    /// Append an entry to the replicated log unless its `deadline` passes before the leader appends
+    /// it to its log.
+    pub(crate) fn append_with_deadline(
+        &mut self,
+        entry: T,
+        deadline: SystemTime,
+    ) -> Result<(), ProposeErr<T>> {
+        self.append_with_origin(entry, None, Some(deadline))
+    }
+
+    fn append_with_origin(
+        &mut self,
+        entry: T,
+        token: Option<OriginToken>,
+        deadline: Option<SystemTime>,
+    ) -> Result<(), ProposeErr<T>> {
+        let entry = self.check_appendable(entry)?;
+        if deadline.is_some_and(|deadline| deadline <= SystemTime::now()) {
+            Err(ProposeErr::DeadlineExceeded(entry))
+        } else if let Some((size, limit)) = self.exceeds_max_entry_size(&entry) {
+            Err(ProposeErr::EntryTooLarge { entry, size, limit })
+        } else {
+            let origin = ProposalOrigin {
+                idx: 0,
+                pid: self.pid,
+                token,
+                deadline,
+            };
+            self.proposal_affinity.proposed(self.pid, 1, None);
+            let res = self.propose_with_origins(vec![entry], vec![origin]);
+            self.halt_if_failed(res)?;
             Ok(())
         }
     }
 
-    /// Propose a reconfiguration. Returns an error if already stopped or `new_config` is invalid.
+    /// Whether entries appended on this server are refused because it is not the leader.
+    pub(crate) fn rejects_appends(&self) -> bool {
+        self.follower_append_policy == FollowerAppendPolicy::Reject && self.state.0 != Role::Leader
+    }
+
+    /// Returns the leader if it is known and not this server.
+    pub(crate) fn known_leader(&self) -> Option<NodeId> {
+        let leader = self.get_current_leader();
+        (leader > 0 && leader != self.pid).then_some(leader)
+    }
+
+    /// Returns the tokens of the proposals appended on this server that are decided in its log.
+    pub(crate) fn take_decided_tokens(&mut self) -> Vec<(usize, OriginToken)> {
+        self.origins
+            .take_decided(self.internal_storage.get_decided_idx())
+    }
+
+    /// Returns the status of the pending reconfiguration, if any.
+    pub(crate) fn reconfiguration_status(&self) -> Option<ReconfigurationStatus> {
+        if self.internal_storage.get_stopsign().is_some() {
+            if self.internal_storage.stopsign_is_decided() {
+                Some(ReconfigurationStatus::Decided)
+            } else {
+                Some(ReconfigurationStatus::Accepted)
+            }
+        } else if self.proposed_stopsign.is_some() {
+            Some(ReconfigurationStatus::Proposed)
+        } else {
+            self.rejected_stopsign_reason
+                .clone()
+                .map(ReconfigurationStatus::Rejected)
+        }
+    }
+
+    /// Runs the user's reconfiguration validator on `ss`, if any.
+    fn validate_stopsign(&self, ss: &StopSign) -> Result<(), String> {
+        match self.reconfiguration_validator {
+            Some(validator) => validator(&self.cluster_config, ss),
+            None => Ok(()),
+        }
+    }
+
+    fn handle_rejected_stopsign(&mut self, rej_ss: RejectStopSign) {
+        if self.proposed_stopsign.as_ref() == Some(&rej_ss.ss) && !self.accepted_reconfiguration() {
+            #[cfg(feature = "logging")]
+            warn!(
+                self.logger,
+                "Reconfiguration {:?} rejected by leader: {}",
+                rej_ss.ss.next_config.nodes,
+                rej_ss.reason
+            );
+            self.proposed_stopsign = None;
+            self.rejected_stopsign_reason = Some(rej_ss.reason);
+        }
+    }
+
+    /// Propose a reconfiguration. Returns an error if already stopped by another reconfiguration.
+    /// Proposing the same reconfiguration again returns its status without proposing it twice.
     /// `new_config` defines the cluster-wide configuration settings for the next cluster.
     /// `metadata` is optional data to commit alongside the reconfiguration.
     pub(crate) fn reconfigure(
         &mut self,
         new_config: ClusterConfig,
         metadata: Option<Vec<u8>>,
-    ) -> Result<(), ProposeErr<T>> {
-        if self.accepted_reconfiguration() {
-            return Err(ProposeErr::PendingReconfigConfig(new_config, metadata));
+    ) -> Result<ReconfigurationStatus, ReconfigErr> {
+        let ss = StopSign::with(new_config, metadata);
+        if let Some(accepted_ss) = self.internal_storage.get_stopsign() {
+            return if accepted_ss == ss {
+                Ok(self.reconfiguration_status().unwrap())
+            } else {
+                Err(ReconfigErr::Pending {
+                    pending: accepted_ss.next_config.configuration_id,
+                    config: Box::new(ss.next_config),
+                    metadata: ss.metadata,
+                })
+            };
+        }
+        if self.proposed_stopsign.as_ref() == Some(&ss) {
+            return Ok(ReconfigurationStatus::Proposed);
+        }
+        if let Err(reason) = self.validate_stopsign(&ss) {
+            return Err(ReconfigErr::Rejected {
+                reason,
+                config: Box::new(ss.next_config),
+                metadata: ss.metadata,
+            });
         }
         #[cfg(feature = "logging")]
         info!(
             self.logger,
-            "Accepting reconfiguration {:?}", new_config.nodes
+            "Accepting reconfiguration {:?}", ss.next_config.nodes
         );
-        let ss = StopSign::with(new_config, metadata);
+        self.proposed_stopsign = Some(ss.clone());
+        self.rejected_stopsign_reason = None;
         match self.state {
             (Role::Leader, Phase::Prepare) => self.buffered_stopsign = Some(ss),
-            (Role::Leader, Phase::Accept) => self.accept_stopsign_leader(ss),
+            (Role::Leader, Phase::Accept) => self.accept_stopsign_leader(ss)?,
             _ => self.forward_stopsign(ss),
         }
-        Ok(())
+        Ok(self.reconfiguration_status().unwrap())
     }
 
     fn get_current_leader(&self) -> NodeId {
         self.get_promise().pid
     }
 
+    /// Whether this server is a backup that only receives snapshots from the leader.
+    pub(crate) fn is_backup(&self) -> bool {
+        self.is_backup
+    }
+
+    pub(crate) fn get_pid(&self) -> NodeId {
+        self.pid
+    }
+
     /// Handles re-establishing a connection to a previously disconnected peer.
     /// This should only be called if the underlying network implementation indicates that a connection has been re-established.
     pub(crate) fn reconnected(&mut self, pid: NodeId) {
         if pid == self.pid {
             return;
         } else if pid == self.get_current_leader() {
-            self.state = (Role::Follower, Phase::Recover);
+            self.start_recovery();
         }
         let prepreq = PrepareReq {
             n: self.get_promise(),
@@ -343,38 +1392,501 @@ where
         self.outgoing.push(PaxosMessage {
             from: self.pid,
             to: pid,
+            namespace: self.cluster_config.namespace,
             msg: PaxosMsg::PrepareReq(prepreq),
         });
     }
 
-    fn propose_entry(&mut self, entry: T) {
+    fn propose_entry(&mut self, entry: T) -> Result<(), ProtocolError> {
         match self.state {
-            (Role::Leader, Phase::Prepare) => self.buffered_proposals.push(entry),
-            (Role::Leader, Phase::Accept) => self.accept_entry_leader(entry),
+            (Role::Leader, Phase::Prepare | Phase::Accept) => match self.pending_dependency(&entry)
+            {
+                Some(dependency) => self.dependents.hold(dependency, vec![entry], vec![]),
+                None if self.state.1 == Phase::Accept => self.accept_entry_leader(entry)?,
+                None => self.buffer_proposals(vec![entry], vec![]),
+            },
             _ => self.forward_proposals(vec![entry]),
         }
+        Ok(())
+    }
+
+    /// Proposes `entries` together so that they are decided contiguously in the log.
+    pub(crate) fn propose_entries(&mut self, entries: Vec<T>) -> Result<(), ProtocolError> {
+        match self.state {
+            (Role::Leader, Phase::Prepare | Phase::Accept) => {
+                let dependency = entries
+                    .iter()
+                    .filter_map(|e| self.pending_dependency(e))
+                    .max();
+                match dependency {
+                    Some(dependency) => self.dependents.hold(dependency, entries, vec![]),
+                    None if self.state.1 == Phase::Accept => self.accept_entries_leader(entries)?,
+                    None => self.buffer_proposals(entries, vec![]),
+                }
+            }
+            _ => self.forward_proposals(entries),
+        }
+        Ok(())
+    }
+
+    /// Proposes `entries`, some of which were appended with a token or a deadline as described by
+    /// `origins`.
+    fn propose_with_origins(
+        &mut self,
+        entries: Vec<T>,
+        origins: Vec<ProposalOrigin>,
+    ) -> Result<(), ProtocolError> {
+        match self.state {
+            // Entries whose dependency is not decided yet are held in every phase of the leader
+            (Role::Leader, Phase::Prepare) => {
+                let (entries, origins) = self.hold_dependents(entries, origins);
+                if !entries.is_empty() {
+                    self.buffer_proposals(entries, origins);
+                }
+            }
+            (Role::Leader, Phase::Accept) => {
+                let (entries, origins) = self.drop_expired(entries, origins);
+                let (entries, origins) = self.hold_dependents(entries, origins);
+                if !entries.is_empty() {
+                    let start_idx = self.internal_storage.get_appended_idx();
+                    self.accept_entries_leader(entries)?;
+                    self.origins.placed(start_idx, origins);
+                }
+            }
+            _ => self.forward_proposals_with_origins(entries, origins),
+        }
+        Ok(())
+    }
+
+    /// Drops the `entries` whose deadline has passed before the leader could append them and
+    /// returns the others, each with their origins re-indexed.
+    pub(crate) fn drop_expired(
+        &mut self,
+        entries: Vec<T>,
+        origins: Vec<ProposalOrigin>,
+    ) -> (Vec<T>, Vec<ProposalOrigin>) {
+        if origins.iter().all(|o| o.deadline.is_none()) {
+            return (entries, origins);
+        }
+        let now = SystemTime::now();
+        let (ready, (expired, expired_origins)) =
+            split_proposals(entries, origins, |o| o.is_some_and(|o| o.is_expired(now)));
+        if !expired.is_empty() {
+            #[cfg(feature = "logging")]
+            debug!(
+                self.logger,
+                "Dropping {} proposals whose deadline has passed",
+                expired.len()
+            );
+            let cause = ProposalRejectionCause::DeadlineExceeded;
+            self.reject_proposals(expired, expired_origins, self.pid, cause);
+        }
+        ready
+    }
+
+    /// Drops the proposals waiting on this server whose deadline has passed, i.e., the buffered
+    /// and batched proposals and the proposals held until their dependency is decided.
+    fn expire_deadlines(&mut self) {
+        let now = SystemTime::now();
+        let mut expired = self.expire_buffered_proposals(now);
+        for (mut entries, origins) in [
+            self.forward_batch.take_expired(now),
+            self.dependents.take_expired(now),
+        ] {
+            let offset = expired.0.len();
+            expired
+                .1
+                .extend(origins.into_iter().map(|o| ProposalOrigin {
+                    idx: offset + o.idx,
+                    ..o
+                }));
+            expired.0.append(&mut entries);
+        }
+        let (entries, origins) = expired;
+        if entries.is_empty() {
+            return;
+        }
+        #[cfg(feature = "logging")]
+        debug!(
+            self.logger,
+            "Dropping {} proposals whose deadline has passed",
+            entries.len()
+        );
+        let cause = ProposalRejectionCause::DeadlineExceeded;
+        self.reject_proposals(entries, origins, self.pid, cause);
+    }
+
+    /// Removes and returns the buffered proposals whose deadline has passed at `now`.
+    fn expire_buffered_proposals(&mut self, now: SystemTime) -> (Vec<T>, Vec<ProposalOrigin>) {
+        if !self.buffered_origins.iter().any(|o| o.is_expired(now)) {
+            return (vec![], vec![]);
+        }
+        let expired_idx: Vec<usize> = self
+            .buffered_origins
+            .iter()
+            .filter(|o| o.is_expired(now))
+            .map(|o| o.idx)
+            .collect();
+        let ticks: Vec<u64> = self
+            .buffered_ticks
+            .iter()
+            .flat_map(|&(tick, len)| iter::repeat_n(tick, len))
+            .enumerate()
+            .filter(|(idx, _)| !expired_idx.contains(idx))
+            .map(|(_, tick)| tick)
+            .collect();
+        let (entries, origins) = self.take_buffered_proposals();
+        let (kept, expired) =
+            split_proposals(entries, origins, |o| o.is_some_and(|o| o.is_expired(now)));
+        (self.buffered_proposals, self.buffered_origins) = kept;
+        for tick in ticks {
+            match self.buffered_ticks.back_mut() {
+                Some((t, len)) if *t == tick => *len += 1,
+                _ => self.buffered_ticks.push_back((tick, 1)),
+            }
+        }
+        expired
+    }
+
+    /// Returns the index that `entry` depends on if that entry is not decided yet.
+    fn pending_dependency(&self, entry: &T) -> Option<usize> {
+        entry
+            .dependency()
+            .filter(|idx| *idx >= self.internal_storage.get_decided_idx())
+    }
+
+    /// Holds the `entries` whose dependency is not decided yet and returns the others, each with
+    /// their origins re-indexed.
+    pub(crate) fn hold_dependents(
+        &mut self,
+        entries: Vec<T>,
+        origins: Vec<ProposalOrigin>,
+    ) -> (Vec<T>, Vec<ProposalOrigin>) {
+        if entries.iter().all(|e| self.pending_dependency(e).is_none()) {
+            return (entries, origins);
+        }
+        let mut entry_origins: Vec<Option<ProposalOrigin>> = vec![None; entries.len()];
+        for o in origins {
+            if let Some(origin) = entry_origins.get_mut(o.idx) {
+                *origin = Some(o);
+            }
+        }
+        let mut ready = (vec![], vec![]);
+        for (entry, origin) in entries.into_iter().zip(entry_origins) {
+            match self.pending_dependency(&entry) {
+                Some(dependency) => {
+                    let origins = origin
+                        .map(|o| vec![ProposalOrigin { idx: 0, ..o }])
+                        .unwrap_or_default();
+                    self.dependents.hold(dependency, vec![entry], origins);
+                }
+                None => {
+                    if let Some(origin) = origin {
+                        ready.1.push(ProposalOrigin {
+                            idx: ready.0.len(),
+                            ..origin
+                        });
+                    }
+                    ready.0.push(entry);
+                }
+            }
+        }
+        ready
+    }
+
+    /// Appends the held proposals whose dependencies are decided, in the order they were proposed.
+    pub(crate) fn release_dependents(&mut self) -> Result<(), ProtocolError> {
+        let decided_idx = self.internal_storage.get_decided_idx();
+        for (entries, origins) in self.dependents.take_ready(decided_idx) {
+            if self.accepted_reconfiguration() {
+                let cause = ProposalRejectionCause::Reconfigured;
+                self.reject_proposals(entries, origins, self.pid, cause);
+            } else {
+                self.propose_with_origins(entries, origins)?;
+            }
+        }
+        Ok(())
+    }
+
+    /// The number of proposals held as the leader until the entries they depend on are decided.
+    pub(crate) fn num_dependent_proposals(&self) -> usize {
+        self.dependents.num_held()
+    }
+
+    fn buffer_proposals(&mut self, mut entries: Vec<T>, origins: Vec<ProposalOrigin>) {
+        let offset = self.buffered_proposals.len();
+        self.buffered_origins
+            .extend(origins.into_iter().map(|o| ProposalOrigin {
+                idx: offset + o.idx,
+                ..o
+            }));
+        self.buffered_ticks
+            .push_back((self.buffer_clock, entries.len()));
+        self.buffered_proposals.append(&mut entries);
+    }
+
+    /// Takes the buffered proposals and their origins.
+    pub(crate) fn take_buffered_proposals(&mut self) -> (Vec<T>, Vec<ProposalOrigin>) {
+        self.buffered_ticks.clear();
+        (
+            std::mem::take(&mut self.buffered_proposals),
+            std::mem::take(&mut self.buffered_origins),
+        )
+    }
+
+    /// Discards the latencies that are being measured, e.g., after a clock jump that would distort
+    /// them.
+    pub(crate) fn discard_latency_samples(&mut self) {
+        self.commit_latency.clear_pending();
+        self.forward_latency.clear_pending();
+        self.internal_storage.take_append_latency();
+    }
+
+    /// Drops the proposals whose deadline has passed, and the proposals that have been buffered for
+    /// `tick_timeout` runs of the timer while this server is not the leader if the follower append
+    /// policy is `Buffer`.
+    pub(crate) fn buffered_proposals_timeout(&mut self) {
+        self.expire_deadlines();
+        let FollowerAppendPolicy::Buffer { tick_timeout } = self.follower_append_policy else {
+            return;
+        };
+        self.buffer_clock += 1;
+        if self.state.0 == Role::Leader {
+            return;
+        }
+        let mut expired = 0;
+        while let Some(&(tick, len)) = self.buffered_ticks.front() {
+            if self.buffer_clock - tick < tick_timeout {
+                break;
+            }
+            expired += len;
+            self.buffered_ticks.pop_front();
+        }
+        if expired == 0 {
+            return;
+        }
+        let mut origins = vec![];
+        for o in std::mem::take(&mut self.buffered_origins) {
+            if o.idx >= expired {
+                self.buffered_origins.push(ProposalOrigin {
+                    idx: o.idx - expired,
+                    ..o
+                });
+            } else {
+                origins.push(o);
+            }
+        }
+        #[cfg(feature = "logging")]
+        warn!(
+            self.logger,
+            "Dropping {} proposals buffered without a known leader", expired
+        );
+        let entries = self.buffered_proposals.drain(..expired).collect();
+        self.reject_proposals(entries, origins, self.pid, ProposalRejectionCause::Expired);
+    }
+
+    /// Returns the dropped `entries` to the servers they were appended on, which are described by
+    /// `origins` for the entries appended with a token or a deadline and assumed to be `from` for
+    /// the others.
+    pub(crate) fn reject_proposals(
+        &mut self,
+        entries: Vec<T>,
+        origins: Vec<ProposalOrigin>,
+        from: NodeId,
+        cause: ProposalRejectionCause,
+    ) {
+        let mut proposers = vec![(from, None); entries.len()];
+        for o in origins {
+            if let Some(proposer) = proposers.get_mut(o.idx) {
+                *proposer = (o.pid, o.token);
+            }
+        }
+        let mut rejected: HashMap<NodeId, Vec<(T, Option<OriginToken>)>> = HashMap::new();
+        for (entry, (pid, token)) in entries.into_iter().zip(proposers) {
+            rejected.entry(pid).or_default().push((entry, token));
+        }
+        for (pid, proposals) in rejected {
+            if pid == self.pid {
+                self.handle_rejected_proposals(RejectedProposals { cause, proposals });
+            } else {
+                self.outgoing.push(PaxosMessage {
+                    from: self.pid,
+                    to: pid,
+                    namespace: self.cluster_config.namespace,
+                    msg: PaxosMsg::RejectedProposals(RejectedProposals { cause, proposals }),
+                });
+            }
+        }
+    }
+
+    fn handle_rejected_proposals(&mut self, rejected: RejectedProposals<T>) {
+        let cause = rejected.cause;
+        self.rejected_proposals
+            .extend(
+                rejected
+                    .proposals
+                    .into_iter()
+                    .map(|(entry, token)| RejectedProposal {
+                        entry,
+                        token,
+                        cause,
+                    }),
+            );
+    }
+
+    pub(crate) fn take_rejected_proposals(&mut self) -> Vec<RejectedProposal<T>> {
+        std::mem::take(&mut self.rejected_proposals)
+    }
+
+    pub(crate) fn take_expired_proposals(&mut self) -> Vec<(T, Option<OriginToken>)> {
+        let (expired, others): (Vec<_>, Vec<_>) = std::mem::take(&mut self.rejected_proposals)
+            .into_iter()
+            .partition(|r| r.cause == ProposalRejectionCause::Expired);
+        self.rejected_proposals = others;
+        expired.into_iter().map(|r| (r.entry, r.token)).collect()
+    }
+
+    /// Reads the undecided proposals with a token that this server appended as a former leader
+    /// from `sync_idx` on, before its log is overwritten from there by the new leader.
+    pub(crate) fn read_superseded_proposals(&mut self, sync_idx: usize) {
+        let from_idx = sync_idx.max(self.internal_storage.get_decided_idx());
+        for (idx, (pid, token)) in self.origins.take_superseded(from_idx) {
+            if let Ok(Some(entry)) = self
+                .internal_storage
+                .get_entries(idx, idx + 1)
+                .map(|mut e| e.pop())
+            {
+                let origin = ProposalOrigin {
+                    idx,
+                    pid,
+                    token: Some(token),
+                    deadline: None,
+                };
+                let ballot = self.internal_storage.get_entry_ballot(idx);
+                self.superseded_proposals.push((entry, origin, ballot));
+            }
+        }
+    }
+
+    /// Rejects the superseded proposals that are no longer in the log after it was synchronized
+    /// with the new leader up to `accepted_idx`. If the ballots of the entries are tracked, this
+    /// includes the proposals that were replaced by entries of another leader.
+    pub(crate) fn reject_truncated_proposals(&mut self, accepted_idx: usize) {
+        let (entries, origins): (Vec<_>, Vec<_>) = std::mem::take(&mut self.superseded_proposals)
+            .into_iter()
+            .filter(|(_, origin, ballot)| {
+                origin.idx >= accepted_idx
+                    || ballot.is_some_and(|b| {
+                        self.internal_storage
+                            .get_entry_ballot(origin.idx)
+                            .is_some_and(|n| n != b)
+                    })
+            })
+            .enumerate()
+            .map(|(idx, (entry, origin, _))| (entry, ProposalOrigin { idx, ..origin }))
+            .unzip();
+        if entries.is_empty() {
+            return;
+        }
+        #[cfg(feature = "logging")]
+        warn!(
+            self.logger,
+            "{} proposals were truncated after a leader change",
+            entries.len()
+        );
+        self.reject_proposals(
+            entries,
+            origins,
+            self.pid,
+            ProposalRejectionCause::Truncated,
+        );
+    }
+
+    pub(crate) fn get_proposal_affinity(&self) -> HashMap<NodeId, ProposalAffinity> {
+        let mut affinity = self.proposal_affinity.get();
+        let latency = if self.state.0 == Role::Leader {
+            self.commit_latency.get_commit_latency()
+        } else {
+            self.forward_latency.get_commit_latency()
+        };
+        if let Some(own) = affinity.get_mut(&self.pid) {
+            own.latency = latency;
+        }
+        affinity
     }
 
     pub(crate) fn get_leader_state(&self) -> &LeaderState<T> {
         &self.leader_state
     }
 
-    pub(crate) fn forward_proposals(&mut self, mut entries: Vec<T>) {
+    pub(crate) fn forward_proposals(&mut self, entries: Vec<T>) {
+        self.forward_proposals_with_origins(entries, vec![]);
+    }
+
+    pub(crate) fn forward_proposals_with_origins(
+        &mut self,
+        entries: Vec<T>,
+        origins: Vec<ProposalOrigin>,
+    ) {
         let leader = self.get_current_leader();
         if leader > 0 && self.pid != leader {
-            let pf = PaxosMsg::ProposalForward(entries);
-            let msg = PaxosMessage {
-                from: self.pid,
-                to: leader,
-                msg: pf,
-            };
-            self.outgoing.push(msg);
+            for (entries, origins) in self.forward_batch.push(entries, origins) {
+                self.send_forwarded_proposals(leader, entries, origins);
+            }
         } else {
-            self.buffered_proposals.append(&mut entries);
+            // Keep the batched proposals ahead of the new ones
+            self.flush_forward_batch();
+            self.buffer_proposals(entries, origins);
         }
     }
 
+    /// Forwards the batched proposals to the leader, or buffers them if this server is the leader
+    /// or no leader is known. Run every `forward_batch_tick_timeout` ticks.
+    pub(crate) fn flush_forward_batch(&mut self) {
+        if self.forward_batch.is_empty() {
+            return;
+        }
+        let (entries, origins) = self.forward_batch.take();
+        let leader = self.get_current_leader();
+        if leader > 0 && self.pid != leader {
+            self.send_forwarded_proposals(leader, entries, origins);
+        } else {
+            self.buffer_proposals(entries, origins);
+        }
+    }
+
+    fn send_forwarded_proposals(
+        &mut self,
+        leader: NodeId,
+        entries: Vec<T>,
+        origins: Vec<ProposalOrigin>,
+    ) {
+        // The forwarded entries are appended after the log of the leader, so they are decided
+        // here no earlier than once the decided index covers them after the current log.
+        self.forward_latency
+            .appended(self.internal_storage.get_appended_idx() + entries.len());
+        let latency = self.forward_latency.get_commit_latency();
+        let pf = if origins.is_empty() && latency.is_none() {
+            PaxosMsg::ProposalForward(entries)
+        } else {
+            PaxosMsg::TokenProposalForward(TokenProposalForward {
+                entries,
+                origins,
+                latency,
+            })
+        };
+        let msg = PaxosMessage {
+            from: self.pid,
+            to: leader,
+            namespace: self.cluster_config.namespace,
+            msg: pf,
+        };
+        self.outgoing.push(msg);
+    }
+
     pub(crate) fn forward_stopsign(&mut self, ss: StopSign) {
+        // The batched proposals would be rejected by the leader once it has the StopSign
+        self.flush_forward_batch();
         let leader = self.get_current_leader();
         if leader > 0 && self.pid != leader {
             #[cfg(feature = "logging")]
@@ -383,6 +1895,7 @@ where
             let msg = PaxosMessage {
                 from: self.pid,
                 to: leader,
+                namespace: self.cluster_config.namespace,
                 msg: fs,
             };
             self.outgoing.push(msg);
@@ -390,6 +1903,33 @@ where
             self.buffered_stopsign = Some(ss);
         }
     }
+    /// Whether a log that has the entries up to `common_prefix_idx` in common with the log of this
+    /// server diverges from it by more than `max_sync_suffix_entries` entries.
+    pub(crate) fn exceeds_max_sync_suffix(&self, common_prefix_idx: usize) -> bool {
+        self.max_sync_suffix_entries.is_some_and(|max| {
+            self.internal_storage
+                .get_accepted_idx()
+                .saturating_sub(common_prefix_idx)
+                > max
+        })
+    }
+
+    /// Returns the decided part of what [`SequencePaxos::create_log_sync`] returns for an empty log.
+    fn create_decided_log_sync(&self) -> Result<LogSync<T>, ProtocolError> {
+        let mut log_sync = self.create_log_sync(0, 0)?;
+        let decided_idx = self.internal_storage.get_decided_idx();
+        let decided_log_len = if self.internal_storage.stopsign_is_decided() {
+            decided_idx - 1
+        } else {
+            log_sync.stopsign = None;
+            decided_idx
+        };
+        log_sync
+            .suffix
+            .truncate(decided_log_len.saturating_sub(log_sync.sync_idx));
+        Ok(log_sync)
+    }
+
     /// Returns `LogSync`, a struct to help other servers synchronize their log to correspond to the
     /// current state of our own log. The `common_prefix_idx` marks where in the log the other server
     /// needs to be sync from.
@@ -397,33 +1937,104 @@ where
         &self,
         common_prefix_idx: usize,
         other_logs_decided_idx: usize,
-    ) -> LogSync<T> {
-        let decided_idx = self.internal_storage.get_decided_idx();
-        let (decided_snapshot, suffix, sync_idx) =
-            if T::Snapshot::use_snapshots() && decided_idx > common_prefix_idx {
-                // Note: We snapshot from the other log's decided index and not the common prefix because
-                // snapshots currently only work on decided entries.
-                let (delta_snapshot, compacted_idx) = self
-                    .internal_storage
-                    .create_diff_snapshot(other_logs_decided_idx)
-                    .expect(READ_ERROR_MSG);
-                let suffix = self
-                    .internal_storage
-                    .get_suffix(decided_idx)
-                    .expect(READ_ERROR_MSG);
-                (delta_snapshot, suffix, compacted_idx)
-            } else {
-                let suffix = self
-                    .internal_storage
-                    .get_suffix(common_prefix_idx)
-                    .expect(READ_ERROR_MSG);
-                (None, suffix, common_prefix_idx)
-            };
-        LogSync {
+    ) -> Result<LogSync<T>, ProtocolError> {
+        self.create_log_sync_portion(common_prefix_idx, other_logs_decided_idx, false)
+    }
+
+    /// Like [`SequencePaxos::create_log_sync`], but if `first_portion_only`, the suffix only
+    /// contains the entries that fit in a single synchronization message, so that the rest of a
+    /// long suffix is not read before the follower requests it.
+    #[cfg_attr(feature = "no_snapshots", allow(unused_variables))]
+    fn create_log_sync_portion(
+        &self,
+        common_prefix_idx: usize,
+        other_logs_decided_idx: usize,
+        first_portion_only: bool,
+    ) -> Result<LogSync<T>, ProtocolError> {
+        let log_sync = |decided_snapshot, suffix, sync_idx| LogSync {
             decided_snapshot,
             suffix,
             sync_idx,
             stopsign: self.internal_storage.get_stopsign(),
+            suffix_ballots: self.internal_storage.get_entry_ballots(sync_idx),
+        };
+        let decided_idx = self.internal_storage.get_decided_idx();
+        #[cfg(not(feature = "no_snapshots"))]
+        if use_snapshots::<T>()
+            && decided_idx > common_prefix_idx
+            && (self.max_sync_suffix_entries.is_none()
+                || common_prefix_idx < self.internal_storage.get_compacted_idx()
+                || self.exceeds_max_sync_suffix(common_prefix_idx))
+        {
+            // Note: We snapshot from the other log's decided index and not the common prefix because
+            // snapshots currently only work on decided entries.
+            let (delta_snapshot, compacted_idx) = self
+                .internal_storage
+                .create_diff_snapshot(other_logs_decided_idx)
+                .or_fatal(READ_ERROR_MSG)?;
+            #[cfg(feature = "snapshot_sealing")]
+            let delta_snapshot = self
+                .seal_snapshot(delta_snapshot, compacted_idx)
+                .or_fatal(SEAL_ERROR_MSG)?;
+            let suffix = self
+                .read_suffix(decided_idx, first_portion_only)
+                .or_fatal(READ_ERROR_MSG)?;
+            let mut sync = log_sync(delta_snapshot, suffix, compacted_idx);
+            // The ballots of the snapshotted entries are sent along with the ones of the suffix
+            sync.suffix_ballots = self
+                .internal_storage
+                .get_entry_ballots(other_logs_decided_idx);
+            return Ok(sync);
+        }
+        let suffix = self
+            .read_suffix(common_prefix_idx, first_portion_only)
+            .or_fatal(READ_ERROR_MSG)?;
+        Ok(log_sync(None, suffix, common_prefix_idx))
+    }
+
+    /// Reads the log from `from` to its end. If the size of synchronization messages is limited,
+    /// the log is read in portions of `sync_batch_byte_size` bytes with
+    /// [`LogStorage::get_entries_hint`](crate::storage::LogStorage::get_entries_hint) instead of a
+    /// single range read, and only the first portion if `first_portion_only`.
+    fn read_suffix(&self, from: usize, first_portion_only: bool) -> StorageResult<Vec<T>> {
+        if self.sync_batch_size == 0 && self.sync_batch_byte_size == 0 {
+            return self.internal_storage.get_suffix(from);
+        }
+        let max_bytes = match self.sync_batch_byte_size {
+            0 => usize::MAX,
+            max_bytes => max_bytes,
+        };
+        let log_end = self.internal_storage.get_log_end();
+        if !first_portion_only {
+            return self
+                .internal_storage
+                .get_entries_in_portions(from, log_end, max_bytes);
+        }
+        let to = match self.sync_batch_size {
+            0 => log_end,
+            batch_size => log_end.min(from + batch_size),
+        };
+        if from >= to {
+            return Ok(vec![]);
+        }
+        self.internal_storage
+            .get_entries_hint(from, to, max_bytes)
+            .map(|hint| hint.entries)
+    }
+
+    /// Encrypts and signs `snapshot` for `compacted_idx` if a `snapshot_sealer` is set.
+    #[cfg(all(feature = "snapshot_sealing", not(feature = "no_snapshots")))]
+    pub(crate) fn seal_snapshot(
+        &self,
+        snapshot: Option<SnapshotType<T>>,
+        compacted_idx: usize,
+    ) -> Result<Option<SnapshotType<T>>, SealingErr> {
+        match (&self.snapshot_sealer, snapshot) {
+            (Some(sealer), Some(snapshot)) => {
+                let sealed = sealing::seal(sealer.as_ref(), &snapshot, compacted_idx)?;
+                Ok(Some(SnapshotType::Sealed(Box::new(sealed))))
+            }
+            (_, snapshot) => Ok(snapshot),
         }
     }
 }
@@ -442,21 +2053,90 @@ pub(crate) enum Role {
     Leader,
 }
 
+/// The progress of a wiped server that recovers its state from a quorum of the other servers.
+#[derive(Debug, Default)]
+struct QuorumRecovery {
+    // The state of the servers that replied to the query
+    states: HashMap<NodeId, RecoveryState>,
+    // The ballot of a leader elected before the state was recovered
+    deferred_leader: Option<Ballot>,
+}
+
 /// Configuration for `SequencePaxos`.
 /// # Fields
 /// * `pid`: The unique identifier of this node. Must not be 0.
 /// * `peers`: The peers of this node i.e. the `pid`s of the other servers in the configuration.
 /// * `flexible_quorum` : Defines read and write quorum sizes. Can be used for different latency vs fault tolerance tradeoffs.
+/// * `hierarchical_quorum`: Whether quorums are formed by a majority of nodes in a majority of `regions`.
+/// * `backup_nodes`: The servers that receive snapshots of the decided log from the leader.
+/// * `regions`: The regions the nodes are located in.
 /// * `buffer_size`: The buffer size for outgoing messages.
 /// * `batch_size`: The size of the buffer for log batching. The default is 1, which means no batching.
+/// * `batch_byte_size`: The approximate number of bytes at which the log batch is flushed.
+/// * `forward_batch_size`: The number of proposals at which the batch forwarded to the leader is sent.
+/// * `forward_batch_byte_size`: The approximate maximum number of bytes of a batch forwarded to the leader.
+/// * `decided_cache_size`: The number of entries at the tail of the log kept in memory.
+/// * `sync_batch_size`: The maximum number of log entries sent at once when synchronizing a follower.
+/// * `sync_batch_byte_size`: The approximate maximum number of bytes sent at once when synchronizing a follower.
+/// * `sync_prefetch`: The number of log portions requested ahead while catching up.
+/// * `max_sync_suffix_entries`: The number of entries a log may diverge by before it is synchronized with a snapshot.
+/// * `verification_batch_size`: The number of log entries verified per storage verification run.
+/// * `slow_follower_lag`: The number of entries a follower may be behind before it is reported.
+/// * `slow_follower_tick_timeout`: The number of ticks a follower may take to catch up before it is reported.
+/// * `storage_size_limit`: The number of bytes the storage may occupy before `low_space_policy` is applied.
+/// * `low_space_policy`: What to do when the storage exceeds `storage_size_limit`.
+/// * `follower_append_policy`: What to do with the entries appended while not being the leader.
+/// * `max_entry_size`: The maximum size in bytes of an appended or forwarded entry.
+/// * `memory_limit`: The approximate number of bytes the buffers may occupy before load is shed.
+/// * `reorder_window`: The number of messages from the leader that an incoming message may overtake.
+/// * `trim_mode`: When the compacted entries are removed from the storage.
+/// * `check_snapshots`: Whether the `Snapshot` implementation is checked when snapshots are created.
+/// * `snapshot_batch_size`: The maximum number of log entries read at once when creating a snapshot.
+/// * `snapshot_interval`: The number of decided entries between the automatic snapshots of this server.
+/// * `misbehaving_peer_threshold`: The number of rejected messages after which a peer is reported.
+/// * `metrics_sink`: The sink the metrics of this server are reported to.
+/// * `snapshot_sealer`: Encrypts and signs the snapshots sent to other servers.
 /// * `logger_file_path`: The path where the default logger logs events.
 #[derive(Clone, Debug)]
 pub(crate) struct SequencePaxosConfig {
     pid: NodeId,
     peers: Vec<NodeId>,
+    backup_nodes: Vec<NodeId>,
     buffer_size: usize,
     pub(crate) batch_size: usize,
+    batch_byte_size: usize,
+    forward_batch_size: usize,
+    forward_batch_byte_size: usize,
+    decided_cache_size: usize,
+    sync_batch_size: usize,
+    sync_batch_byte_size: usize,
+    sync_prefetch: usize,
+    max_sync_suffix_entries: Option<usize>,
+    verification_batch_size: usize,
+    slow_follower_lag: Option<usize>,
+    slow_follower_tick_timeout: Option<u64>,
+    storage_size_limit: Option<u64>,
+    low_space_policy: LowSpacePolicy,
+    follower_append_policy: FollowerAppendPolicy,
+    max_entry_size: Option<usize>,
+    memory_limit: Option<usize>,
+    reorder_window: usize,
+    trim_mode: TrimMode,
+    #[cfg(not(feature = "no_snapshots"))]
+    check_snapshots: bool,
+    #[cfg(not(feature = "no_snapshots"))]
+    snapshot_batch_size: usize,
+    track_entry_ballots: bool,
+    snapshot_interval: Option<usize>,
+    misbehaving_peer_threshold: u64,
     flexible_quorum: Option<FlexibleQuorum>,
+    hierarchical_quorum: bool,
+    regions: Vec<Region>,
+    cluster_config: ClusterConfig,
+    reconfiguration_validator: Option<ReconfigurationValidator>,
+    metrics_sink: Option<Arc<dyn MetricsSink>>,
+    #[cfg(feature = "snapshot_sealing")]
+    snapshot_sealer: Option<Arc<dyn SnapshotSealer>>,
     #[cfg(feature = "logging")]
     logger_file_path: Option<String>,
     #[cfg(feature = "logging")]
@@ -466,6 +2146,7 @@ pub(crate) struct SequencePaxosConfig {
 impl From<OmniPaxosConfig> for SequencePaxosConfig {
     fn from(config: OmniPaxosConfig) -> Self {
         let pid = config.server_config.pid;
+        let cluster_config = config.cluster_config.clone();
         let peers = config
             .cluster_config
             .nodes
@@ -475,9 +2156,42 @@ impl From<OmniPaxosConfig> for SequencePaxosConfig {
         SequencePaxosConfig {
             pid,
             peers,
+            backup_nodes: config.cluster_config.backup_nodes,
             flexible_quorum: config.cluster_config.flexible_quorum,
+            hierarchical_quorum: config.cluster_config.hierarchical_quorum,
+            regions: config.cluster_config.regions,
+            cluster_config,
+            reconfiguration_validator: config.server_config.reconfiguration_validator,
+            metrics_sink: config.server_config.metrics_sink,
+            #[cfg(feature = "snapshot_sealing")]
+            snapshot_sealer: config.server_config.snapshot_sealer,
             buffer_size: config.server_config.buffer_size,
             batch_size: config.server_config.batch_size,
+            batch_byte_size: config.server_config.batch_byte_size,
+            forward_batch_size: config.server_config.forward_batch_size,
+            forward_batch_byte_size: config.server_config.forward_batch_byte_size,
+            decided_cache_size: config.server_config.decided_cache_size,
+            sync_batch_size: config.server_config.sync_batch_size,
+            sync_batch_byte_size: config.server_config.sync_batch_byte_size,
+            sync_prefetch: config.server_config.sync_prefetch,
+            max_sync_suffix_entries: config.server_config.max_sync_suffix_entries,
+            verification_batch_size: config.server_config.verification_batch_size,
+            slow_follower_lag: config.server_config.slow_follower_lag,
+            slow_follower_tick_timeout: config.server_config.slow_follower_tick_timeout,
+            storage_size_limit: config.server_config.storage_size_limit,
+            low_space_policy: config.server_config.low_space_policy,
+            follower_append_policy: config.server_config.follower_append_policy,
+            max_entry_size: config.server_config.max_entry_size,
+            memory_limit: config.server_config.memory_limit,
+            reorder_window: config.server_config.reorder_window,
+            trim_mode: config.server_config.trim_mode,
+            #[cfg(not(feature = "no_snapshots"))]
+            check_snapshots: config.server_config.check_snapshots,
+            #[cfg(not(feature = "no_snapshots"))]
+            snapshot_batch_size: config.server_config.snapshot_batch_size,
+            track_entry_ballots: config.server_config.track_entry_ballots,
+            snapshot_interval: config.server_config.snapshot_interval,
+            misbehaving_peer_threshold: config.server_config.misbehaving_peer_threshold,
             #[cfg(feature = "logging")]
             logger_file_path: config.server_config.logger_file_path,
             #[cfg(feature = "logging")]
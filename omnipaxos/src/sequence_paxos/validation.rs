@@ -0,0 +1,178 @@
+use super::super::ballot_leader_election::Ballot;
+
+use super::*;
+
+use crate::{
+    messages::ballot_leader_election::{BLEMessage, HeartbeatMsg},
+    util::Namespace,
+    RejectedMessageCause,
+};
+
+impl<T, B> SequencePaxos<T, B>
+where
+    T: Entry,
+    B: Storage<T>,
+{
+    /*** Validation ***/
+    /// Checks that an incoming message is plausible, i.e., that it is sent by a server of the
+    /// cluster and does not claim log indexes that no correct server could have sent. Handling an
+    /// implausible message could otherwise corrupt the state of this server or make it panic.
+    pub(crate) fn validate_message(&self, m: &PaxosMessage<T>) -> Result<(), RejectedMessageCause> {
+        self.check_namespace(m.namespace)?;
+        if !self.is_member(m.from) {
+            return Err(RejectedMessageCause::NonMember);
+        }
+        let n = match &m.msg {
+            PaxosMsg::PrepareReq(prepreq) => prepreq.n,
+            PaxosMsg::Prepare(prep) => {
+                Self::check_indexes(prep.decided_idx, prep.accepted_idx)?;
+                prep.n
+            }
+            PaxosMsg::Promise(prom) => {
+                Self::check_indexes(prom.decided_idx, prom.accepted_idx)?;
+                prom.n
+            }
+            PaxosMsg::PullRequest(req) => {
+                Self::check_indexes(req.decided_idx, req.accepted_idx)?;
+                req.n
+            }
+            PaxosMsg::Accepted(accepted) => {
+                Self::check_indexes(accepted.decided_idx, accepted.accepted_idx)?;
+                // Followers only accept entries that the leader sent them
+                if self.state.0 == Role::Leader
+                    && accepted.n == self.leader_state.n_leader
+                    && accepted.accepted_idx > self.internal_storage.get_accepted_idx()
+                {
+                    return Err(RejectedMessageCause::AcceptedBeyondLog);
+                }
+                accepted.n
+            }
+            PaxosMsg::AcceptSync(acc_sync) => acc_sync.n,
+            PaxosMsg::SyncPortion(portion) => portion.n,
+            PaxosMsg::SyncRequest(req) => req.n,
+            PaxosMsg::PullResponse(resp) => resp.n,
+            PaxosMsg::AcceptDecide(acc) => acc.n,
+            PaxosMsg::RelayAcceptDecide(relay) => relay.n,
+            PaxosMsg::NotAccepted(not_acc) => not_acc.n,
+            PaxosMsg::Decide(d) => d.n,
+            PaxosMsg::DecidedFingerprint(fp) => fp.n,
+            PaxosMsg::AcceptStopSign(acc_ss) => acc_ss.n,
+            PaxosMsg::BootstrapResponse(resp) => resp.n,
+            PaxosMsg::RecoveryState(state) => state.n,
+            PaxosMsg::BackupSnapshot(bs) => bs.n,
+            PaxosMsg::BackupAck(ack) => ack.n,
+            PaxosMsg::Standby(standby) => {
+                if !self.cluster_config.nodes.contains(&standby.standby) {
+                    return Err(RejectedMessageCause::NonMember);
+                }
+                standby.n
+            }
+            PaxosMsg::StandbyAck(ack) => ack.n,
+            PaxosMsg::Takeover(takeover) => {
+                self.check_ballot(takeover.n_prev)?;
+                takeover.acc_sync.n
+            }
+            PaxosMsg::ProposalForward(_)
+            | PaxosMsg::TokenProposalForward(_)
+            | PaxosMsg::DecidedTokens(_)
+            | PaxosMsg::RejectedProposals(_)
+            | PaxosMsg::Compaction(_)
+            | PaxosMsg::ForwardStopSign(_)
+            | PaxosMsg::RejectStopSign(_)
+            | PaxosMsg::BootstrapRequest(_)
+            | PaxosMsg::RecoveryQuery(_)
+            | PaxosMsg::WitnessReadRequest(_)
+            | PaxosMsg::WitnessReadResponse(_) => return Ok(()),
+        };
+        self.check_ballot(n)
+    }
+
+    /// Checks that the ballots of an incoming heartbeat reply belong to servers of the cluster, so
+    /// that no server outside of it can be elected. Heartbeat requests only trigger a reply and
+    /// are left to the leader election.
+    pub(crate) fn validate_ble_message(&self, m: &BLEMessage) -> Result<(), RejectedMessageCause> {
+        self.check_namespace(m.namespace)?;
+        match &m.msg {
+            HeartbeatMsg::Request(_) => Ok(()),
+            HeartbeatMsg::Reply(rep) => {
+                if !self.cluster_config.nodes.contains(&m.from) {
+                    return Err(RejectedMessageCause::NonMember);
+                }
+                self.check_ballot(rep.ballot)?;
+                self.check_ballot(rep.leader)
+            }
+        }
+    }
+
+    /// Replaces the sealed snapshot of an incoming message with the opened snapshot. If a
+    /// `snapshot_sealer` is set, snapshots that are not sealed are refused.
+    #[cfg(feature = "snapshot_sealing")]
+    pub(crate) fn open_sealed_snapshot(&self, m: &mut PaxosMessage<T>) -> Result<(), SealingErr> {
+        let log_sync = match &mut m.msg {
+            PaxosMsg::Promise(prom) => match prom.log_sync.as_mut() {
+                Some(log_sync) => log_sync,
+                None => return Ok(()),
+            },
+            PaxosMsg::AcceptSync(acc_sync) => &mut acc_sync.log_sync,
+            PaxosMsg::Takeover(takeover) => &mut takeover.acc_sync.log_sync,
+            PaxosMsg::SyncPortion(portion) => &mut portion.log_sync,
+            PaxosMsg::PullResponse(resp) => &mut resp.log_sync,
+            PaxosMsg::BootstrapResponse(resp) => &mut resp.log_sync,
+            PaxosMsg::BackupSnapshot(bs) => &mut bs.log_sync,
+            _ => return Ok(()),
+        };
+        let snapshot = match log_sync.decided_snapshot.take() {
+            Some(snapshot) => snapshot,
+            None => return Ok(()),
+        };
+        let opened = match (&self.snapshot_sealer, snapshot) {
+            (Some(sealer), SnapshotType::Sealed(sealed)) => {
+                sealing::open(sealer.as_ref(), &sealed, log_sync.sync_idx)?
+            }
+            (Some(_), _) => return Err(SealingErr::NotSealed),
+            (None, SnapshotType::Sealed(_)) => return Err(SealingErr::NoSealer),
+            (None, snapshot) => snapshot,
+        };
+        log_sync.decided_snapshot = Some(opened);
+        Ok(())
+    }
+
+    /// Counts the rejected message from `from` and drops it.
+    pub(crate) fn reject_message(&mut self, from: NodeId, cause: RejectedMessageCause) {
+        #[cfg(feature = "logging")]
+        warn!(self.logger, "Rejected message from {}: {:?}", from, cause);
+        self.metrics.counter(metrics::MESSAGES_REJECTED, 1);
+        self.rejected_messages.reject(from, cause);
+    }
+
+    fn is_member(&self, pid: NodeId) -> bool {
+        pid != self.pid
+            && (self.cluster_config.nodes.contains(&pid)
+                || self.cluster_config.backup_nodes.contains(&pid))
+    }
+
+    /// Messages of other tenants must not be handled even if their sender has the pid of a server
+    /// of this cluster.
+    fn check_namespace(&self, namespace: Option<Namespace>) -> Result<(), RejectedMessageCause> {
+        match namespace == self.cluster_config.namespace {
+            true => Ok(()),
+            false => Err(RejectedMessageCause::ForeignNamespace),
+        }
+    }
+
+    /// Only the servers of the cluster can be leaders. The default ballot is sent before any
+    /// leader has been elected.
+    fn check_ballot(&self, n: Ballot) -> Result<(), RejectedMessageCause> {
+        if n != Ballot::default() && !self.cluster_config.nodes.contains(&n.pid) {
+            return Err(RejectedMessageCause::NonMemberBallot);
+        }
+        Ok(())
+    }
+
+    fn check_indexes(decided_idx: usize, accepted_idx: usize) -> Result<(), RejectedMessageCause> {
+        if decided_idx > accepted_idx {
+            return Err(RejectedMessageCause::DecidedBeyondAccepted);
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,65 @@
+use crate::{
+    errors::{valid_config, ConfigError},
+    storage::Entry,
+};
+#[cfg(feature = "serde")]
+use serde::{Deserialize, Serialize};
+
+/// A change of cluster-wide settings that is decided through the replicated log. Every server
+/// applies it when it is decided, so all servers switch to the new settings at the same log index.
+/// Fields that are `None` are left unchanged.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct ReplicatedSettings {
+    /// The new `batch_size` of the servers. Must not be 0.
+    pub batch_size: Option<usize>,
+    /// The new `sync_batch_size` of the servers. Set to 0 to always synchronize with a single message.
+    pub sync_batch_size: Option<usize>,
+    /// The new `snapshot_interval` of the servers. `Some(None)` disables the automatic snapshots.
+    /// The interval must not be 0.
+    pub snapshot_interval: Option<Option<usize>>,
+}
+
+impl ReplicatedSettings {
+    /// Checks that all the set fields of the settings are valid.
+    pub fn validate(&self) -> Result<(), ConfigError> {
+        valid_config!(
+            self.batch_size != Some(0),
+            "Batch size must be greater than 0"
+        );
+        valid_config!(
+            self.snapshot_interval != Some(Some(0)),
+            "Snapshot interval must be greater than 0"
+        );
+        Ok(())
+    }
+
+    /// Overwrites the fields of `self` with the set fields of `other`.
+    pub(crate) fn merge(&mut self, other: &ReplicatedSettings) {
+        if other.batch_size.is_some() {
+            self.batch_size = other.batch_size;
+        }
+        if other.sync_batch_size.is_some() {
+            self.sync_batch_size = other.sync_batch_size;
+        }
+        if other.snapshot_interval.is_some() {
+            self.snapshot_interval = other.snapshot_interval;
+        }
+    }
+}
+
+/// An entry type that can hold a [`ReplicatedSettings`] change. Proposing such an entry with
+/// [`crate::OmniPaxos::propose_settings`] changes the settings of all servers that have called
+/// [`crate::OmniPaxos::enable_replicated_settings`] once the entry is decided.
+pub trait SettingsEntry: Entry {
+    /// Creates an entry that holds the settings change `settings`.
+    fn settings(settings: ReplicatedSettings) -> Self;
+
+    /// Returns the settings change if this entry holds one, otherwise `None`.
+    fn as_settings(&self) -> Option<&ReplicatedSettings>;
+}
+
+/// Returns the settings change held by `entry`, if any.
+pub(crate) fn extract<T: SettingsEntry>(entry: &T) -> Option<ReplicatedSettings> {
+    entry.as_settings().cloned()
+}
@@ -0,0 +1,195 @@
+use crate::{
+    storage::{Entry, PinGuard},
+    util::{defaults::DECIDE_HOOK_BATCH_SIZE, LogEntry},
+};
+use std::fmt;
+
+/// A contiguous range of the decided log, starting at index `from_idx`.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct DecidedRange<T> {
+    /// The index of the first entry.
+    pub from_idx: usize,
+    /// The decided entries, in the order of the log.
+    pub entries: Vec<T>,
+}
+
+impl<T> DecidedRange<T> {
+    /// The index after the last entry.
+    pub fn to_idx(&self) -> usize {
+        self.from_idx + self.entries.len()
+    }
+}
+
+/// How much of a [`DecidedRange`] a [`DecideHook`] has taken.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum ShipStatus {
+    /// The hook took all entries of the range.
+    Shipped,
+    /// The hook took the given number of entries from the start of the range, e.g., because its
+    /// buffer is full. The rest of the range is offered again on the next tick.
+    Partial(usize),
+    /// The hook took no entries, e.g., because the external system is unavailable. The range is
+    /// offered again on the next tick.
+    Busy,
+}
+
+/// A hook that is called with every newly decided range of the log, e.g., to stream the decided
+/// entries to Kafka or object storage for change data capture. Set on a server with
+/// [`OmniPaxos::set_decide_hook`](crate::OmniPaxos::set_decide_hook).
+///
+/// The hook is called on the thread that runs the server, so it should only hand the entries over,
+/// e.g., to a channel, and push back with [`ShipStatus::Partial`] or [`ShipStatus::Busy`] when it
+/// can't keep up instead of blocking. While entries are not shipped, the server holds back the
+/// compaction of its log, see [`PinGuard`]. Every decided entry is offered exactly once per server
+/// and in the order of the log, unless the hook is set again or the server is restarted, in which
+/// case shipping continues from the `from_idx` of the [`DecideHookConfig`].
+pub trait DecideHook<T: Entry>: Send + Sync {
+    /// Called with the next decided range of the log. Returns how much of the range was taken.
+    fn on_decided(&mut self, range: &DecidedRange<T>) -> ShipStatus;
+
+    /// Called if the entries `from_idx..to_idx` were compacted before they were shipped, e.g.,
+    /// because this server was synchronized with a snapshot of the leader. The hook should then
+    /// restore the external system from a snapshot. Shipping continues at `to_idx`.
+    fn on_skipped(&mut self, _from_idx: usize, _to_idx: usize) {}
+}
+
+/// Configuration for [`OmniPaxos::set_decide_hook`](crate::OmniPaxos::set_decide_hook).
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct DecideHookConfig {
+    /// The index to start shipping at, e.g., the index up to which the external system already
+    /// holds the log.
+    pub from_idx: usize,
+    /// The maximum number of entries in a [`DecidedRange`].
+    pub max_batch_size: usize,
+}
+
+impl Default for DecideHookConfig {
+    fn default() -> Self {
+        Self {
+            from_idx: 0,
+            max_batch_size: DECIDE_HOOK_BATCH_SIZE,
+        }
+    }
+}
+
+/// Offers the decided log to a [`DecideHook`] and keeps track of how far it was shipped.
+pub(crate) struct DecideShipper<T: Entry> {
+    hook: Box<dyn DecideHook<T>>,
+    // The index up to which (exclusive) the log was shipped
+    shipped_idx: usize,
+    max_batch_size: usize,
+    // Whether the hook pushed back, until the next tick
+    busy: bool,
+    // Holds back the compaction of the entries that were not shipped yet
+    pin: PinGuard,
+}
+
+impl<T: Entry> DecideShipper<T> {
+    pub(crate) fn with(
+        hook: Box<dyn DecideHook<T>>,
+        config: DecideHookConfig,
+        pin: PinGuard,
+    ) -> Self {
+        Self {
+            hook,
+            shipped_idx: config.from_idx,
+            max_batch_size: config.max_batch_size.max(1),
+            busy: false,
+            pin,
+        }
+    }
+
+    pub(crate) fn into_hook(self) -> Box<dyn DecideHook<T>> {
+        self.hook
+    }
+
+    pub(crate) fn shipped_idx(&self) -> usize {
+        self.shipped_idx
+    }
+
+    /// Returns the range of the log to ship next, if the hook is not busy and the log is decided
+    /// beyond the shipped index.
+    pub(crate) fn next_range(&self, decided_idx: usize) -> Option<(usize, usize)> {
+        (!self.busy && self.shipped_idx < decided_idx).then(|| {
+            let to_idx = decided_idx.min(self.shipped_idx + self.max_batch_size);
+            (self.shipped_idx, to_idx)
+        })
+    }
+
+    /// Offers `entries`, read from the log at the shipped index, to the hook. A compacted entry
+    /// stands for the log up to `compacted_idx`. Returns whether all of them were shipped.
+    pub(crate) fn ship(&mut self, entries: Vec<LogEntry<T>>, compacted_idx: usize) -> bool {
+        let mut range = DecidedRange {
+            from_idx: self.shipped_idx,
+            entries: Vec::with_capacity(entries.len()),
+        };
+        for entry in entries {
+            match entry {
+                LogEntry::Decided(e) => range.entries.push(e),
+                LogEntry::Trimmed(_) | LogEntry::Snapshotted(_) => {
+                    if !self.offer(&mut range) {
+                        return false;
+                    }
+                    if self.shipped_idx < compacted_idx {
+                        self.hook.on_skipped(self.shipped_idx, compacted_idx);
+                        self.shipped_idx = compacted_idx;
+                    }
+                    range.from_idx = self.shipped_idx;
+                }
+                // The StopSign is not an entry of the application
+                LogEntry::StopSign(..) => {
+                    if !self.offer(&mut range) {
+                        return false;
+                    }
+                    self.shipped_idx += 1;
+                    range.from_idx = self.shipped_idx;
+                }
+                LogEntry::Undecided(_) => break,
+            }
+        }
+        self.offer(&mut range)
+    }
+
+    /// Offers `range` to the hook unless it is empty. Returns whether the whole range was shipped.
+    fn offer(&mut self, range: &mut DecidedRange<T>) -> bool {
+        if range.entries.is_empty() {
+            return true;
+        }
+        let len = range.entries.len();
+        let shipped = match self.hook.on_decided(range) {
+            ShipStatus::Shipped => len,
+            ShipStatus::Partial(n) => n.min(len),
+            ShipStatus::Busy => 0,
+        };
+        self.shipped_idx += shipped;
+        range.entries.clear();
+        range.from_idx = self.shipped_idx;
+        self.busy = shipped < len;
+        !self.busy
+    }
+
+    /// Moves the retention hold to the shipped index if it is behind.
+    pub(crate) fn repin<F>(&mut self, pin_log: F)
+    where
+        F: FnOnce(usize) -> PinGuard,
+    {
+        if self.pin.pinned_idx() < self.shipped_idx {
+            self.pin = pin_log(self.shipped_idx);
+        }
+    }
+
+    /// Lets the hook be offered entries again after it pushed back.
+    pub(crate) fn tick(&mut self) {
+        self.busy = false;
+    }
+}
+
+impl<T: Entry> fmt::Debug for DecideShipper<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("DecideShipper")
+            .field("shipped_idx", &self.shipped_idx)
+            .field("max_batch_size", &self.max_batch_size)
+            .field("busy", &self.busy)
+            .finish()
+    }
+}
@@ -0,0 +1,160 @@
+use crate::{
+    messages::sequence_paxos::{PaxosMessage, PaxosMsg},
+    storage::Entry,
+};
+use serde::{Deserialize, Serialize};
+use std::{
+    fs::{self, File, OpenOptions},
+    io::{self, Read, Seek, SeekFrom, Write},
+    path::{Path, PathBuf},
+    sync::atomic::{AtomicU64, Ordering},
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+/// Used to give every spill file of this process a unique name.
+static SPILL_FILE_COUNTER: AtomicU64 = AtomicU64::new(0);
+
+/// Functions to size, encode and decode the outgoing messages of type `M` that may be spilled.
+pub(crate) struct SpillCodec<M> {
+    /// Returns the size of `M` if it may be spilled, otherwise `None`.
+    pub(crate) spill_size: fn(&M) -> Option<u64>,
+    pub(crate) encode: fn(&M) -> bincode::Result<Vec<u8>>,
+    pub(crate) decode: fn(&[u8]) -> bincode::Result<M>,
+}
+
+/// Encodes `msg` for a [`SpillCodec`].
+pub(crate) fn encode<M: Serialize>(msg: &M) -> bincode::Result<Vec<u8>> {
+    bincode::serialize(msg)
+}
+
+/// Decodes `bytes` for a [`SpillCodec`].
+pub(crate) fn decode<M: for<'a> Deserialize<'a>>(bytes: &[u8]) -> bincode::Result<M> {
+    bincode::deserialize(bytes)
+}
+
+/// The size of `msg` if it is a message used to synchronize the log of a follower, which may
+/// carry arbitrarily many entries or a whole snapshot.
+pub(crate) fn sync_msg_size<T: Entry + Serialize>(msg: &PaxosMessage<T>) -> Option<u64> {
+    match msg.msg {
+        PaxosMsg::AcceptSync(_)
+        | PaxosMsg::Takeover(_)
+        | PaxosMsg::SyncPortion(_)
+        | PaxosMsg::PullResponse(_)
+        | PaxosMsg::BootstrapResponse(_)
+        | PaxosMsg::BackupSnapshot(_) => bincode::serialized_size(msg).ok(),
+        _ => None,
+    }
+}
+
+/// The location of a spilled message in the spill file.
+#[derive(Copy, Clone, Debug)]
+pub(crate) struct SpilledMsg {
+    offset: u64,
+    len: usize,
+}
+
+/// A temporary file that large outgoing messages are written to once the spillable messages held
+/// in memory exceed `memory_budget` bytes. The file is emptied whenever all spilled messages have
+/// been taken and removed when dropped.
+pub(crate) struct SpillFile<M> {
+    codec: SpillCodec<M>,
+    path: PathBuf,
+    file: File,
+    end: u64,
+    num_spilled: usize,
+    memory_budget: u64,
+    memory_used: u64,
+}
+
+impl<M> SpillFile<M> {
+    /// Creates a new spill file in the directory `dir`.
+    pub(crate) fn create(dir: &Path, memory_budget: u64, codec: SpillCodec<M>) -> io::Result<Self> {
+        let nanos = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|d| d.as_nanos())
+            .unwrap_or_default();
+        let name = format!(
+            "omnipaxos-spill-{}-{}-{}",
+            std::process::id(),
+            nanos,
+            SPILL_FILE_COUNTER.fetch_add(1, Ordering::Relaxed)
+        );
+        let path = dir.join(name);
+        let file = OpenOptions::new()
+            .read(true)
+            .write(true)
+            .create_new(true)
+            .open(&path)?;
+        Ok(Self {
+            codec,
+            path,
+            file,
+            end: 0,
+            num_spilled: 0,
+            memory_budget,
+            memory_used: 0,
+        })
+    }
+
+    /// Returns `Err(msg)` if `msg` should be kept in memory, together with the size it is accounted
+    /// for in the memory budget. Otherwise, writes `msg` to the file and returns its location.
+    pub(crate) fn spill(&mut self, msg: M) -> Result<SpilledMsg, (M, u64)> {
+        let size = match (self.codec.spill_size)(&msg) {
+            Some(size) => size,
+            None => return Err((msg, 0)),
+        };
+        if self.memory_used + size <= self.memory_budget {
+            self.memory_used += size;
+            return Err((msg, size));
+        }
+        match self.write(&msg) {
+            Ok(spilled) => Ok(spilled),
+            // Keep the message in memory rather than dropping it
+            Err(_) => {
+                self.memory_used += size;
+                Err((msg, size))
+            }
+        }
+    }
+
+    fn write(&mut self, msg: &M) -> io::Result<SpilledMsg> {
+        let bytes = (self.codec.encode)(msg).map_err(io::Error::other)?;
+        self.file.seek(SeekFrom::Start(self.end))?;
+        self.file.write_all(&bytes)?;
+        let spilled = SpilledMsg {
+            offset: self.end,
+            len: bytes.len(),
+        };
+        self.end += bytes.len() as u64;
+        self.num_spilled += 1;
+        Ok(spilled)
+    }
+
+    /// Reads a spilled message back from the file.
+    pub(crate) fn load(&mut self, spilled: SpilledMsg) -> M {
+        let mut bytes = vec![0; spilled.len];
+        self.file
+            .seek(SeekFrom::Start(spilled.offset))
+            .and_then(|_| self.file.read_exact(&mut bytes))
+            .expect("Failed to read spilled message");
+        let msg = (self.codec.decode)(&bytes).expect("Failed to decode spilled message");
+        self.num_spilled -= 1;
+        if self.num_spilled == 0 {
+            // Reclaim the disk space once all spilled messages have been taken
+            self.end = 0;
+            let _ = self.file.set_len(0);
+        }
+        msg
+    }
+
+    /// Releases the memory accounted for a message of `size` that was kept in memory.
+    pub(crate) fn release(&mut self, size: u64) {
+        self.memory_used -= size;
+    }
+}
+
+impl<M> Drop for SpillFile<M> {
+    fn drop(&mut self) {
+        let _ = fs::remove_file(&self.path);
+    }
+}
@@ -0,0 +1,329 @@
+mod reader;
+
+use super::{Entry, StopSign, Storage, StorageOp};
+use crate::{
+    ballot_leader_election::Ballot,
+    errors::{ArchiveErr, StorageErr},
+    util::{FlexibleQuorum, Region},
+    ClusterConfig,
+};
+pub(crate) use reader::{read_exact, Decoder};
+pub use reader::{ArchiveReader, ArchiveRecord};
+use std::{
+    error::Error,
+    io::{Read, Write},
+};
+
+/// The first bytes of every archive.
+pub const MAGIC: [u8; 4] = *b"OPXA";
+/// The version of the archive format written by this crate. Readers reject archives with a
+/// higher version, while records of unknown kinds are skipped.
+pub const FORMAT_VERSION: u16 = 1;
+
+pub(crate) const METADATA_RECORD: u8 = 1;
+pub(crate) const SNAPSHOT_RECORD: u8 = 2;
+pub(crate) const ENTRY_RECORD: u8 = 3;
+pub(crate) const STOPSIGN_RECORD: u8 = 4;
+pub(crate) const END_RECORD: u8 = 0xFF;
+
+/// Encodes the entries and snapshots of a log into bytes and back, e.g., with the serialization
+/// format the application already uses. The archive stores the bytes as they are, so tools that
+/// read an archive only need to understand this encoding to inspect the entries.
+pub trait ArchiveCodec<T: Entry> {
+    /// Encodes `entry` into bytes.
+    fn encode_entry(&self, entry: &T) -> Result<Vec<u8>, Box<dyn Error>>;
+
+    /// Decodes an entry encoded by [`encode_entry`](Self::encode_entry).
+    fn decode_entry(&self, bytes: &[u8]) -> Result<T, Box<dyn Error>>;
+
+    /// Encodes `snapshot` into bytes. Must be implemented if the log can be compacted with
+    /// snapshots.
+    fn encode_snapshot(&self, _snapshot: &T::Snapshot) -> Result<Vec<u8>, Box<dyn Error>> {
+        Err("snapshots are not supported by this codec".into())
+    }
+
+    /// Decodes a snapshot encoded by [`encode_snapshot`](Self::encode_snapshot).
+    fn decode_snapshot(&self, _bytes: &[u8]) -> Result<T::Snapshot, Box<dyn Error>> {
+        Err("snapshots are not supported by this codec".into())
+    }
+}
+
+/// The state of a server that is not part of the log itself.
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
+pub struct ArchiveMetadata {
+    /// The promised ballot.
+    pub promise: Option<Ballot>,
+    /// The ballot in which the log was last accepted.
+    pub accepted_round: Option<Ballot>,
+    /// The highest ballot used in the leader election.
+    pub ble_ballot: Option<Ballot>,
+    /// The decided index.
+    pub decided_idx: usize,
+    /// The compacted index. The entries of the archive start at this index.
+    pub compacted_idx: usize,
+}
+
+/// Writes the state of `storage` to `writer` in the archive format: its metadata, snapshot,
+/// entries after the compacted index, and StopSign. The entries and the snapshot are encoded with
+/// `codec`. See `docs/omnipaxos/archive.md` for the specification of the format.
+pub fn write_archive<T, S, C, W>(storage: &S, codec: &C, mut writer: W) -> Result<(), ArchiveErr>
+where
+    T: Entry,
+    S: Storage<T>,
+    C: ArchiveCodec<T>,
+    W: Write,
+{
+    let storage_err = |op| move |e| StorageErr::with(op, e);
+    let compacted_idx = storage
+        .get_compacted_idx()
+        .map_err(storage_err("read the compacted index"))?;
+    let metadata = ArchiveMetadata {
+        promise: storage
+            .get_promise()
+            .map_err(storage_err("read the promise"))?,
+        accepted_round: storage
+            .get_accepted_round()
+            .map_err(storage_err("read the accepted round"))?,
+        ble_ballot: storage
+            .get_ble_ballot()
+            .map_err(storage_err("read the leader election ballot"))?,
+        decided_idx: storage
+            .get_decided_idx()
+            .map_err(storage_err("read the decided index"))?,
+        compacted_idx,
+    };
+    writer.write_all(&MAGIC)?;
+    writer.write_all(&FORMAT_VERSION.to_le_bytes())?;
+    let mut record = Encoder::default();
+    record.metadata(&metadata);
+    record.write_to(&mut writer, METADATA_RECORD)?;
+    let snapshot = storage
+        .get_snapshot()
+        .map_err(storage_err("read the snapshot"))?;
+    if let Some(snapshot) = snapshot {
+        let bytes = codec
+            .encode_snapshot(&snapshot)
+            .map_err(|source| ArchiveErr::Codec {
+                what: "snapshot",
+                source,
+            })?;
+        record.usize(compacted_idx);
+        record.raw(&bytes);
+        record.write_to(&mut writer, SNAPSHOT_RECORD)?;
+    }
+    let entries = storage
+        .get_suffix(compacted_idx)
+        .map_err(storage_err("read log entries"))?;
+    for (idx, entry) in (compacted_idx..).zip(entries.iter()) {
+        let bytes = codec
+            .encode_entry(entry)
+            .map_err(|source| ArchiveErr::Codec {
+                what: "entry",
+                source,
+            })?;
+        record.usize(idx);
+        record.raw(&bytes);
+        record.write_to(&mut writer, ENTRY_RECORD)?;
+    }
+    let stopsign = storage
+        .get_stopsign()
+        .map_err(storage_err("read the StopSign"))?;
+    if let Some(ss) = stopsign {
+        record.stopsign(&ss);
+        record.write_to(&mut writer, STOPSIGN_RECORD)?;
+    }
+    record.write_to(&mut writer, END_RECORD)?;
+    writer.flush()?;
+    Ok(())
+}
+
+/// Restores the state written by [`write_archive`] from `reader` into `storage`, which should be
+/// empty. The entries and the snapshot are decoded with `codec`. Nothing is written to `storage`
+/// unless the whole archive could be read.
+pub fn restore_archive<T, S, C, R>(reader: R, codec: &C, storage: &mut S) -> Result<(), ArchiveErr>
+where
+    T: Entry,
+    S: Storage<T>,
+    C: ArchiveCodec<T>,
+    R: Read,
+{
+    let mut metadata = None;
+    let mut snapshot = None;
+    let mut entries = vec![];
+    let mut stopsign = None;
+    for record in ArchiveReader::new(reader)? {
+        match record? {
+            ArchiveRecord::Metadata(m) => metadata = Some(m),
+            ArchiveRecord::Snapshot { data, .. } => {
+                let s = codec
+                    .decode_snapshot(&data)
+                    .map_err(|source| ArchiveErr::Codec {
+                        what: "snapshot",
+                        source,
+                    })?;
+                snapshot = Some(s);
+            }
+            ArchiveRecord::Entry { idx, data } => {
+                let compacted_idx = metadata.map_or(0, |m: ArchiveMetadata| m.compacted_idx);
+                if idx != compacted_idx + entries.len() {
+                    return Err(ArchiveErr::Corrupt(format!(
+                        "expected the entry at index {}, found {}",
+                        compacted_idx + entries.len(),
+                        idx
+                    )));
+                }
+                let entry = codec
+                    .decode_entry(&data)
+                    .map_err(|source| ArchiveErr::Codec {
+                        what: "entry",
+                        source,
+                    })?;
+                entries.push(entry);
+            }
+            ArchiveRecord::StopSign(ss) => stopsign = Some(ss),
+        }
+    }
+    let metadata =
+        metadata.ok_or_else(|| ArchiveErr::Corrupt("missing metadata record".to_string()))?;
+    let compacted_idx = metadata.compacted_idx;
+    let mut ops = vec![];
+    if compacted_idx > 0 {
+        ops.push(StorageOp::Trim(compacted_idx));
+        ops.push(StorageOp::SetCompactedIdx(compacted_idx));
+    }
+    if snapshot.is_some() {
+        ops.push(StorageOp::SetSnapshot(snapshot));
+    }
+    ops.push(StorageOp::AppendOnPrefix(compacted_idx, entries));
+    if stopsign.is_some() {
+        ops.push(StorageOp::SetStopsign(stopsign));
+    }
+    if let Some(promise) = metadata.promise {
+        ops.push(StorageOp::SetPromise(promise));
+    }
+    if let Some(accepted_round) = metadata.accepted_round {
+        ops.push(StorageOp::SetAcceptedRound(accepted_round));
+    }
+    ops.push(StorageOp::SetDecidedIndex(metadata.decided_idx));
+    storage
+        .write_atomically(ops)
+        .map_err(|e| StorageErr::with("restore the archive", e))?;
+    if let Some(ballot) = metadata.ble_ballot {
+        storage
+            .set_ble_ballot(ballot)
+            .map_err(|e| StorageErr::with("restore the leader election ballot", e))?;
+    }
+    Ok(())
+}
+
+/// Builds the payload of a record. All integers are little-endian, and indexes and node ids are
+/// written as 64-bit integers.
+#[derive(Default)]
+pub(crate) struct Encoder(pub(crate) Vec<u8>);
+
+impl Encoder {
+    pub(crate) fn u8(&mut self, v: u8) {
+        self.0.push(v);
+    }
+
+    pub(crate) fn u32(&mut self, v: u32) {
+        self.0.extend_from_slice(&v.to_le_bytes());
+    }
+
+    pub(crate) fn u64(&mut self, v: u64) {
+        self.0.extend_from_slice(&v.to_le_bytes());
+    }
+
+    pub(crate) fn usize(&mut self, v: usize) {
+        self.u64(v as u64);
+    }
+
+    pub(crate) fn bool(&mut self, v: bool) {
+        self.u8(u8::from(v));
+    }
+
+    pub(crate) fn raw(&mut self, bytes: &[u8]) {
+        self.0.extend_from_slice(bytes);
+    }
+
+    pub(crate) fn bytes(&mut self, bytes: &[u8]) {
+        self.u32(bytes.len() as u32);
+        self.raw(bytes);
+    }
+
+    pub(crate) fn node_ids(&mut self, pids: &[u64]) {
+        self.u32(pids.len() as u32);
+        for pid in pids {
+            self.u64(*pid);
+        }
+    }
+
+    pub(crate) fn ballot(&mut self, ballot: Option<Ballot>) {
+        self.bool(ballot.is_some());
+        let b = ballot.unwrap_or_default();
+        self.u32(b.config_id);
+        self.u32(b.n);
+        self.u32(b.priority);
+        self.u64(b.pid);
+    }
+
+    pub(crate) fn metadata(&mut self, metadata: &ArchiveMetadata) {
+        self.ballot(metadata.promise);
+        self.ballot(metadata.accepted_round);
+        self.ballot(metadata.ble_ballot);
+        self.usize(metadata.decided_idx);
+        self.usize(metadata.compacted_idx);
+    }
+
+    pub(crate) fn stopsign(&mut self, ss: &StopSign) {
+        let ClusterConfig {
+            configuration_id,
+            nodes,
+            flexible_quorum,
+            regions,
+            hierarchical_quorum,
+            backup_nodes,
+            namespace,
+        } = &ss.next_config;
+        self.u32(*configuration_id);
+        self.node_ids(nodes);
+        self.node_ids(backup_nodes);
+        self.bool(flexible_quorum.is_some());
+        let FlexibleQuorum {
+            read_quorum_size,
+            write_quorum_size,
+        } = flexible_quorum.unwrap_or(FlexibleQuorum {
+            read_quorum_size: 0,
+            write_quorum_size: 0,
+        });
+        self.usize(read_quorum_size);
+        self.usize(write_quorum_size);
+        self.bool(*hierarchical_quorum);
+        self.u32(regions.len() as u32);
+        for Region { name, nodes, relay } in regions {
+            self.bytes(name.as_bytes());
+            self.node_ids(nodes);
+            self.bool(relay.is_some());
+            self.u64(relay.unwrap_or_default());
+        }
+        self.bool(ss.metadata.is_some());
+        self.bytes(ss.metadata.as_deref().unwrap_or_default());
+        self.bool(namespace.is_some());
+        self.u64(namespace.unwrap_or_default());
+    }
+
+    /// Writes the record of `kind` with the encoded payload to `writer` and clears the payload.
+    pub(crate) fn write_to<W: Write>(
+        &mut self,
+        writer: &mut W,
+        kind: u8,
+    ) -> Result<(), ArchiveErr> {
+        let len = u32::try_from(self.0.len())
+            .map_err(|_| ArchiveErr::Corrupt("record larger than 4 GiB".to_string()))?;
+        writer.write_all(&[kind])?;
+        writer.write_all(&len.to_le_bytes())?;
+        writer.write_all(&self.0)?;
+        self.0.clear();
+        Ok(())
+    }
+}
@@ -0,0 +1,263 @@
+use super::{
+    ArchiveMetadata, END_RECORD, ENTRY_RECORD, FORMAT_VERSION, MAGIC, METADATA_RECORD,
+    SNAPSHOT_RECORD, STOPSIGN_RECORD,
+};
+use crate::{
+    ballot_leader_election::Ballot,
+    errors::ArchiveErr,
+    storage::StopSign,
+    util::{FlexibleQuorum, NodeId, Region},
+    ClusterConfig,
+};
+use std::io::{ErrorKind, Read};
+
+/// A record of an archive. The entries and the snapshot are returned as the bytes written by the
+/// [`ArchiveCodec`](super::ArchiveCodec), so that an archive can be inspected without decoding
+/// them.
+#[derive(Clone, Debug, PartialEq)]
+pub enum ArchiveRecord {
+    /// The state of the server that is not part of the log.
+    Metadata(ArchiveMetadata),
+    /// The snapshot of the log up to `compacted_idx`.
+    Snapshot {
+        /// The index the snapshot was taken at.
+        compacted_idx: usize,
+        /// The encoded snapshot.
+        data: Vec<u8>,
+    },
+    /// A log entry.
+    Entry {
+        /// The index of the entry in the log.
+        idx: usize,
+        /// The encoded entry.
+        data: Vec<u8>,
+    },
+    /// The StopSign at the end of the log.
+    StopSign(StopSign),
+}
+
+/// Reads the records of an archive written by [`write_archive`](super::write_archive). It only
+/// depends on the format, not on the type of the entries, so it can be used to inspect and
+/// validate archives of any application.
+pub struct ArchiveReader<R: Read> {
+    reader: R,
+    version: u16,
+    done: bool,
+}
+
+impl<R: Read> ArchiveReader<R> {
+    /// Reads the header of the archive from `reader`. Fails if `reader` does not contain an
+    /// archive or if the archive was written with a newer version of the format.
+    pub fn new(mut reader: R) -> Result<Self, ArchiveErr> {
+        let mut magic = [0; 4];
+        read_exact(&mut reader, &mut magic)?;
+        if magic != MAGIC {
+            return Err(ArchiveErr::InvalidMagic);
+        }
+        let mut version = [0; 2];
+        read_exact(&mut reader, &mut version)?;
+        let version = u16::from_le_bytes(version);
+        if version == 0 || version > FORMAT_VERSION {
+            return Err(ArchiveErr::UnsupportedVersion(version));
+        }
+        Ok(Self {
+            reader,
+            version,
+            done: false,
+        })
+    }
+
+    /// The version of the format the archive was written with.
+    pub fn version(&self) -> u16 {
+        self.version
+    }
+
+    /// Reads the next record. Returns `None` after the end of the archive. Records of kinds that
+    /// are unknown to this version are skipped.
+    pub fn next_record(&mut self) -> Result<Option<ArchiveRecord>, ArchiveErr> {
+        while !self.done {
+            let mut header = [0; 5];
+            read_exact(&mut self.reader, &mut header)?;
+            let kind = header[0];
+            let len = u32::from_le_bytes([header[1], header[2], header[3], header[4]]) as usize;
+            let mut payload = vec![0; len];
+            read_exact(&mut self.reader, &mut payload)?;
+            let mut decoder = Decoder(&payload);
+            let record = match kind {
+                METADATA_RECORD => ArchiveRecord::Metadata(decoder.metadata()?),
+                SNAPSHOT_RECORD => ArchiveRecord::Snapshot {
+                    compacted_idx: decoder.usize()?,
+                    data: decoder.rest(),
+                },
+                ENTRY_RECORD => ArchiveRecord::Entry {
+                    idx: decoder.usize()?,
+                    data: decoder.rest(),
+                },
+                STOPSIGN_RECORD => ArchiveRecord::StopSign(decoder.stopsign()?),
+                END_RECORD => {
+                    self.done = true;
+                    continue;
+                }
+                _ => continue,
+            };
+            return Ok(Some(record));
+        }
+        Ok(None)
+    }
+}
+
+impl<R: Read> Iterator for ArchiveReader<R> {
+    type Item = Result<ArchiveRecord, ArchiveErr>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        match self.next_record() {
+            Ok(record) => record.map(Ok),
+            Err(e) => {
+                self.done = true;
+                Some(Err(e))
+            }
+        }
+    }
+}
+
+/// Like `Read::read_exact`, but reports a truncated archive as corrupt.
+pub(crate) fn read_exact<R: Read>(reader: &mut R, buf: &mut [u8]) -> Result<(), ArchiveErr> {
+    reader.read_exact(buf).map_err(|e| match e.kind() {
+        ErrorKind::UnexpectedEof => ArchiveErr::Corrupt("the archive is truncated".to_string()),
+        _ => ArchiveErr::Io(e),
+    })
+}
+
+/// Reads the fields of a record payload. See `Encoder` for the encoding.
+pub(crate) struct Decoder<'a>(pub(crate) &'a [u8]);
+
+impl Decoder<'_> {
+    pub(crate) fn take<const N: usize>(&mut self) -> Result<[u8; N], ArchiveErr> {
+        if self.0.len() < N {
+            return Err(ArchiveErr::Corrupt(
+                "record shorter than its fields".to_string(),
+            ));
+        }
+        let (field, rest) = self.0.split_at(N);
+        self.0 = rest;
+        Ok(field.try_into().unwrap())
+    }
+
+    pub(crate) fn u8(&mut self) -> Result<u8, ArchiveErr> {
+        Ok(self.take::<1>()?[0])
+    }
+
+    pub(crate) fn u32(&mut self) -> Result<u32, ArchiveErr> {
+        Ok(u32::from_le_bytes(self.take()?))
+    }
+
+    pub(crate) fn u64(&mut self) -> Result<u64, ArchiveErr> {
+        Ok(u64::from_le_bytes(self.take()?))
+    }
+
+    pub(crate) fn usize(&mut self) -> Result<usize, ArchiveErr> {
+        let v = self.u64()?;
+        usize::try_from(v).map_err(|_| ArchiveErr::Corrupt(format!("index {} is too large", v)))
+    }
+
+    pub(crate) fn bool(&mut self) -> Result<bool, ArchiveErr> {
+        match self.u8()? {
+            0 => Ok(false),
+            1 => Ok(true),
+            b => Err(ArchiveErr::Corrupt(format!("invalid flag {}", b))),
+        }
+    }
+
+    pub(crate) fn rest(&mut self) -> Vec<u8> {
+        std::mem::take(&mut self.0).to_vec()
+    }
+
+    pub(crate) fn bytes(&mut self) -> Result<Vec<u8>, ArchiveErr> {
+        let len = self.u32()? as usize;
+        if self.0.len() < len {
+            return Err(ArchiveErr::Corrupt(
+                "record shorter than its fields".to_string(),
+            ));
+        }
+        let (bytes, rest) = self.0.split_at(len);
+        self.0 = rest;
+        Ok(bytes.to_vec())
+    }
+
+    pub(crate) fn node_ids(&mut self) -> Result<Vec<NodeId>, ArchiveErr> {
+        let len = self.u32()?;
+        (0..len).map(|_| self.u64()).collect()
+    }
+
+    pub(crate) fn ballot(&mut self) -> Result<Option<Ballot>, ArchiveErr> {
+        let is_some = self.bool()?;
+        let ballot = Ballot {
+            config_id: self.u32()?,
+            n: self.u32()?,
+            priority: self.u32()?,
+            pid: self.u64()?,
+        };
+        Ok(is_some.then_some(ballot))
+    }
+
+    pub(crate) fn metadata(&mut self) -> Result<ArchiveMetadata, ArchiveErr> {
+        Ok(ArchiveMetadata {
+            promise: self.ballot()?,
+            accepted_round: self.ballot()?,
+            ble_ballot: self.ballot()?,
+            decided_idx: self.usize()?,
+            compacted_idx: self.usize()?,
+        })
+    }
+
+    pub(crate) fn stopsign(&mut self) -> Result<StopSign, ArchiveErr> {
+        let configuration_id = self.u32()?;
+        let nodes = self.node_ids()?;
+        let backup_nodes = self.node_ids()?;
+        let is_flexible = self.bool()?;
+        let flexible_quorum = FlexibleQuorum {
+            read_quorum_size: self.usize()?,
+            write_quorum_size: self.usize()?,
+        };
+        let hierarchical_quorum = self.bool()?;
+        let num_regions = self.u32()?;
+        let regions = (0..num_regions)
+            .map(|_| {
+                let name = String::from_utf8(self.bytes()?)
+                    .map_err(|_| ArchiveErr::Corrupt("region name is not UTF-8".to_string()))?;
+                let nodes = self.node_ids()?;
+                let has_relay = self.bool()?;
+                let relay = self.u64()?;
+                Ok(Region {
+                    name,
+                    nodes,
+                    relay: has_relay.then_some(relay),
+                })
+            })
+            .collect::<Result<_, ArchiveErr>>()?;
+        let has_metadata = self.bool()?;
+        let metadata = self.bytes()?;
+        // Archives written before namespaces were introduced end here
+        let namespace = match self.0.is_empty() {
+            true => None,
+            false => {
+                let has_namespace = self.bool()?;
+                let namespace = self.u64()?;
+                has_namespace.then_some(namespace)
+            }
+        };
+        let next_config = ClusterConfig {
+            configuration_id,
+            nodes,
+            flexible_quorum: is_flexible.then_some(flexible_quorum),
+            regions,
+            hierarchical_quorum,
+            backup_nodes,
+            namespace,
+        };
+        Ok(StopSign::with(
+            next_config,
+            has_metadata.then_some(metadata),
+        ))
+    }
+}
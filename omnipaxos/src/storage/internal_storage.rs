@@ -1,31 +1,152 @@
-use super::state_cache::StateCache;
+#[cfg(not(feature = "no_snapshots"))]
+use super::snapshot_check::check_snapshot_contract;
+use super::{
+    log_tail_cache::LogTailCache,
+    snapshot_check::SnapshotViolation,
+    state_cache::StateCache,
+    verification::{StorageVerifier, VerificationFailure},
+};
+#[cfg(feature = "pipeline_events")]
+use crate::pipeline::{PipelineStage, PipelineTracker};
 use crate::{
     ballot_leader_election::Ballot,
-    storage::{Entry, Snapshot, SnapshotType, StopSign, Storage, StorageOp, StorageResult},
-    util::{AcceptedMetaData, IndexEntry, LogEntry, LogSync, SnapshottedEntry},
-    CompactionErr,
+    errors::{FatalErr, StorageErr, StorageMigrationErr},
+    storage::{
+        log_pins::{LogPins, PinGuard},
+        migration::{copy_storage, verify_storage_copy},
+        EntriesHint, Entry, StopSign, Storage, StorageOp, StorageResult,
+    },
+    util::{
+        AcceptedMetaData, IndexEntry, LogEntry, LogEntryRef, LogSync, SnapshottedEntry,
+        LOCK_POISONED_MSG,
+    },
+    CompactionErr, TrimMode,
+};
+#[cfg(not(feature = "no_snapshots"))]
+use crate::{
+    storage::{use_snapshots, Snapshot, SnapshotType},
+    util::defaults::SNAPSHOT_VIOLATIONS,
 };
 #[cfg(feature = "unicache")]
 use crate::{unicache::*, util::NodeId};
 use std::{
     cmp::Ordering,
+    collections::{BTreeMap, VecDeque},
     marker::PhantomData,
     ops::{Bound, RangeBounds},
+    sync::{
+        atomic::{AtomicUsize, Ordering as AtomicOrdering},
+        Arc, Mutex, MutexGuard,
+    },
+    time::{Duration, Instant},
 };
 
 pub(crate) struct InternalStorageConfig {
     pub(crate) batch_size: usize,
+    pub(crate) batch_byte_size: usize,
+    pub(crate) decided_cache_size: usize,
+    pub(crate) trim_mode: TrimMode,
+    #[cfg(not(feature = "no_snapshots"))]
+    pub(crate) check_snapshots: bool,
+    #[cfg(not(feature = "no_snapshots"))]
+    pub(crate) snapshot_batch_size: usize,
+    pub(crate) track_entry_ballots: bool,
+}
+
+/// The decided, accepted, and compacted index of the log, published by Sequence Paxos whenever
+/// they change so that other threads can read them without locking the storage.
+#[derive(Debug, Default)]
+pub(crate) struct PublishedIndexes {
+    decided_idx: AtomicUsize,
+    accepted_idx: AtomicUsize,
+    compacted_idx: AtomicUsize,
+}
+
+impl PublishedIndexes {
+    pub(crate) fn get_decided_idx(&self) -> usize {
+        self.decided_idx.load(AtomicOrdering::Acquire)
+    }
+
+    pub(crate) fn get_accepted_idx(&self) -> usize {
+        self.accepted_idx.load(AtomicOrdering::Acquire)
+    }
+
+    pub(crate) fn get_compacted_idx(&self) -> usize {
+        self.compacted_idx.load(AtomicOrdering::Acquire)
+    }
+}
+
+/// The removal of compacted entries from the storage that was deferred by the [`TrimMode`], shared
+/// with the [`crate::storage::TrimWorker`]. Both indexes are only changed while holding the
+/// storage lock.
+#[derive(Debug, Default)]
+pub(crate) struct DeferredTrim {
+    /// The index up to which the entries should be removed.
+    target_idx: AtomicUsize,
+    /// The index up to which the entries have been removed.
+    trimmed_idx: AtomicUsize,
+}
+
+impl DeferredTrim {
+    pub(crate) fn is_pending(&self) -> bool {
+        self.target_idx.load(AtomicOrdering::Acquire)
+            > self.trimmed_idx.load(AtomicOrdering::Acquire)
+    }
+
+    /// Removes the entries up to the target index from `storage` if they have not been removed
+    /// yet. Returns the index up to which the entries were removed, if any.
+    pub(crate) fn run<I, T>(&self, storage: &Mutex<I>) -> StorageResult<Option<usize>>
+    where
+        I: Storage<T>,
+        T: Entry,
+    {
+        if !self.is_pending() {
+            return Ok(None);
+        }
+        let mut storage = storage.lock().expect(LOCK_POISONED_MSG);
+        let target_idx = self.target_idx.load(AtomicOrdering::Acquire);
+        if target_idx <= self.trimmed_idx.load(AtomicOrdering::Acquire) {
+            return Ok(None);
+        }
+        storage.trim(target_idx)?;
+        self.trimmed_idx.store(target_idx, AtomicOrdering::Release);
+        Ok(Some(target_idx))
+    }
 }
 
 /// Internal representation of storage. Serves as the interface between Sequence Paxos and the
-/// storage back-end.
+/// storage back-end. The storage back-end is behind a lock so that it can safely be shared with
+/// readers outside of Sequence Paxos.
 pub(crate) struct InternalStorage<I, T>
 where
     I: Storage<T>,
     T: Entry,
 {
-    storage: I,
+    storage: Arc<Mutex<I>>,
     state_cache: StateCache<T>,
+    tail_cache: LogTailCache<T>,
+    indexes: Arc<PublishedIndexes>,
+    // The maximum number of entries in the log of the storage, see `LogStorage::get_capacity`
+    capacity: Option<usize>,
+    trim_mode: TrimMode,
+    deferred_trim: Arc<DeferredTrim>,
+    // The indexes that the log must not be compacted past, see `OmniPaxos::pin_log`
+    log_pins: Arc<LogPins>,
+    #[cfg(not(feature = "no_snapshots"))]
+    check_snapshots: bool,
+    #[cfg(not(feature = "no_snapshots"))]
+    snapshot_batch_size: usize,
+    snapshot_violations: Mutex<VecDeque<SnapshotViolation>>,
+    // The ballot in which each run of entries was first appended to the log, keyed by the index of
+    // its first entry, or `None` if the ballot is unknown. Only kept if `track_entry_ballots` is
+    // enabled.
+    entry_ballots: Option<BTreeMap<usize, Option<Ballot>>>,
+    // The total duration and number of the appends to the storage since the latency was last taken
+    append_latency: (Duration, u32),
+    #[cfg(feature = "pipeline_events")]
+    pipeline: PipelineTracker,
+    // The first fatal error after which the application halted the server
+    fatal_error: Mutex<Option<FatalErr>>,
     _t: PhantomData<T>,
 }
 
@@ -38,9 +159,25 @@ where
         storage: I,
         config: InternalStorageConfig,
         #[cfg(feature = "unicache")] pid: NodeId,
-    ) -> Self {
+    ) -> StorageResult<Self> {
         let mut internal_store = InternalStorage {
-            storage,
+            storage: Arc::new(Mutex::new(storage)),
+            tail_cache: LogTailCache::with(config.decided_cache_size, 0),
+            indexes: Arc::default(),
+            capacity: None,
+            trim_mode: config.trim_mode,
+            deferred_trim: Arc::default(),
+            log_pins: Arc::default(),
+            #[cfg(not(feature = "no_snapshots"))]
+            check_snapshots: config.check_snapshots,
+            #[cfg(not(feature = "no_snapshots"))]
+            snapshot_batch_size: config.snapshot_batch_size,
+            snapshot_violations: Mutex::default(),
+            entry_ballots: config.track_entry_ballots.then(BTreeMap::new),
+            append_latency: (Duration::ZERO, 0),
+            #[cfg(feature = "pipeline_events")]
+            pipeline: PipelineTracker::default(),
+            fatal_error: Mutex::default(),
             state_cache: StateCache::new(
                 config,
                 #[cfg(feature = "unicache")]
@@ -48,29 +185,99 @@ where
             ),
             _t: Default::default(),
         };
-        internal_store.load_cache();
-        internal_store
-    }
-
-    fn load_cache(&mut self) {
-        self.state_cache.promise = self
-            .storage
-            .get_promise()
-            .expect("Failed to load cache from storage.")
-            .unwrap_or_default();
-        self.state_cache.decided_idx = self.storage.get_decided_idx().unwrap();
-        self.state_cache.accepted_round = self
-            .storage
-            .get_accepted_round()
-            .unwrap()
-            .unwrap_or_default();
-        self.state_cache.compacted_idx = self.storage.get_compacted_idx().unwrap();
-        self.state_cache.stopsign = self.storage.get_stopsign().unwrap();
-        self.state_cache.accepted_idx =
-            self.storage.get_log_len().unwrap() + self.state_cache.compacted_idx;
+        internal_store.load_cache()?;
+        Ok(internal_store)
+    }
+
+    fn storage(&self) -> MutexGuard<'_, I> {
+        self.storage.lock().expect(LOCK_POISONED_MSG)
+    }
+
+    /// Halts the server after `err`. Only the first error is kept.
+    pub(crate) fn halt(&self, err: FatalErr) {
+        let mut fatal_error = self.fatal_error.lock().expect(LOCK_POISONED_MSG);
+        if fatal_error.is_none() {
+            *fatal_error = Some(err);
+        }
+    }
+
+    /// Returns the error after which the server halted, if it did.
+    pub(crate) fn get_fatal_error(&self) -> Option<FatalErr> {
+        self.fatal_error.lock().expect(LOCK_POISONED_MSG).clone()
+    }
+
+    /// Fails once the server has halted, so that no write reaches the storage after a fatal error.
+    fn check_halted(&self) -> StorageResult<()> {
+        match self.get_fatal_error() {
+            Some(err) => Err(err.into()),
+            None => Ok(()),
+        }
+    }
+
+    /// Publishes the indexes of the state cache. Must be called after every change of them has
+    /// been written to the storage back-end.
+    fn publish_indexes(&mut self) {
+        #[cfg(feature = "pipeline_events")]
+        {
+            let (accepted_idx, decided_idx) =
+                (self.state_cache.accepted_idx, self.state_cache.decided_idx);
+            self.pipeline.reached(PipelineStage::Appended, accepted_idx);
+            self.pipeline.reached(PipelineStage::Decided, decided_idx);
+            self.pipeline.reached(PipelineStage::Readable, decided_idx);
+        }
+        let indexes = &self.indexes;
+        indexes
+            .decided_idx
+            .store(self.state_cache.decided_idx, AtomicOrdering::Release);
+        indexes
+            .accepted_idx
+            .store(self.state_cache.accepted_idx, AtomicOrdering::Release);
+        indexes
+            .compacted_idx
+            .store(self.state_cache.compacted_idx, AtomicOrdering::Release);
+    }
+
+    /// Runs the next batch of `verifier` directly on the storage back-end, bypassing the caches.
+    pub(crate) fn verify(&self, verifier: &mut StorageVerifier) -> Vec<VerificationFailure> {
+        verifier.run(&*self.storage())
+    }
+
+    fn load_cache(&mut self) -> StorageResult<()> {
+        let mut storage = self.storage.lock().expect(LOCK_POISONED_MSG);
+        self.state_cache.promise = storage.get_promise()?.unwrap_or_default();
+        self.state_cache.decided_idx = storage.get_decided_idx()?;
+        self.state_cache.accepted_round = storage.get_accepted_round()?.unwrap_or_default();
+        self.state_cache.ble_ballot = storage.get_ble_ballot()?.unwrap_or_default();
+        self.state_cache.standby_promise = storage.get_standby_promise()?;
+        self.state_cache.compacted_idx = storage.get_compacted_idx()?;
+        if self.state_cache.compacted_idx > 0 {
+            // Completes a deferred trim that was interrupted by a crash, so that the length of
+            // the log only counts the entries after the compacted index.
+            storage.trim(self.state_cache.compacted_idx)?;
+        }
+        self.deferred_trim
+            .target_idx
+            .store(self.state_cache.compacted_idx, AtomicOrdering::Release);
+        self.deferred_trim
+            .trimmed_idx
+            .store(self.state_cache.compacted_idx, AtomicOrdering::Release);
+        self.capacity = storage.get_capacity()?;
+        self.state_cache.stopsign = storage.get_stopsign()?;
+        self.state_cache.accepted_idx = storage.get_log_len()? + self.state_cache.compacted_idx;
+        drop(storage);
+        self.tail_cache.trim(self.state_cache.accepted_idx);
         if self.state_cache.stopsign.is_some() {
             self.state_cache.accepted_idx += 1;
         }
+        #[cfg(feature = "pipeline_events")]
+        {
+            self.pipeline = PipelineTracker::starting_at(
+                self.state_cache.accepted_idx,
+                self.state_cache.decided_idx,
+            );
+        }
+        self.publish_indexes();
+        Ok(())
     }
 
     /// Read all decided entries from `from_idx` in the log. Returns `None` if `from_idx` is out of bounds.
@@ -150,10 +357,33 @@ where
                     self.stopsign_is_decided(),
                 )]))
             }
-            e => {
-                unimplemented!("{}", format!("Unexpected read combination: {:?}", e))
+            e => Err(format!("Unexpected read combination: {:?}", e).into()),
+        }
+    }
+
+    /// Calls `f` with the entry at `idx` in the log. Regular entries are borrowed from the decided
+    /// cache or from the storage back-end if possible, all other entries are read with `read()`.
+    /// Returns `None` if `idx` is out of bounds.
+    pub(crate) fn read_ref<F, R>(&self, idx: usize, f: F) -> StorageResult<Option<R>>
+    where
+        F: FnOnce(LogEntryRef<'_, T>) -> R,
+    {
+        let compacted_idx = self.get_compacted_idx();
+        let accepted_idx = self.get_accepted_idx();
+        if let Some(IndexEntry::Entry) = self.get_entry_type(idx, compacted_idx, accepted_idx)? {
+            let decided = idx < self.get_decided_idx();
+            if let Some(entry) = self.tail_cache.get_ref(idx) {
+                return Ok(Some(f(LogEntryRef::with(entry, decided))));
+            }
+            let storage = self.storage();
+            if let Some(entry) = storage.get_entry_ref(idx)? {
+                return Ok(Some(f(LogEntryRef::with(entry, decided))));
             }
         }
+        let entry = self
+            .read(idx..idx + 1)?
+            .and_then(|mut entries| entries.pop());
+        Ok(entry.map(|e| f(e.as_entry_ref())))
     }
 
     fn get_entry_type(
@@ -195,7 +425,7 @@ where
     }
 
     fn create_compacted_entry(&self, compacted_idx: usize) -> StorageResult<LogEntry<T>> {
-        self.storage.get_snapshot().map(|snap| match snap {
+        self.storage().get_snapshot().map(|snap| match snap {
             Some(s) => LogEntry::Snapshotted(SnapshottedEntry::with(compacted_idx, s)),
             None => LogEntry::Trimmed(compacted_idx),
         })
@@ -208,7 +438,7 @@ where
         entry: T,
     ) -> StorageResult<Option<AcceptedMetaData<T>>> {
         let append_res = self.state_cache.append_entry(entry);
-        self.flush_if_full_batch(append_res)
+        self.flush_if_full_batch(append_res, 1)
     }
 
     // Append entries in batch, if the batch size is reached, flush the batch and return the
@@ -217,29 +447,54 @@ where
         &mut self,
         entries: Vec<T>,
     ) -> StorageResult<Option<AcceptedMetaData<T>>> {
+        let num_new_entries = entries.len();
         let append_res = self.state_cache.append_entries(entries);
-        self.flush_if_full_batch(append_res)
+        self.flush_if_full_batch(append_res, num_new_entries)
     }
 
-    // Flushes batched entries and appends a stopsign to the log. Returns the AcceptedMetaData
-    // associated with any flushed entries if there were any.
+    // Flushes batched entries and appends a stopsign to the log in a single write. Returns the
+    // AcceptedMetaData associated with any flushed entries if there were any.
     pub(crate) fn append_stopsign(
         &mut self,
         ss: StopSign,
     ) -> StorageResult<Option<AcceptedMetaData<T>>> {
+        self.check_halted()?;
+        let prev_stopsign = self.state_cache.stopsign.clone();
         let append_res = self.state_cache.append_stopsign(ss.clone());
-        let accepted_entries_metadata = self.flush_if_full_batch(append_res)?;
-        self.storage.set_stopsign(Some(ss))?;
+        let then = vec![StorageOp::SetStopsign(Some(ss))];
+        let accepted_entries_metadata = match self.flush_if_full_batch_with(append_res, 0, then) {
+            Ok(metadata) => metadata,
+            Err(e) => {
+                self.state_cache.stopsign = prev_stopsign;
+                return Err(e);
+            }
+        };
         self.state_cache.accepted_idx += 1;
+        self.publish_indexes();
         Ok(accepted_entries_metadata)
     }
 
+    // Flushes the entries of a full batch, of which the last `num_new_entries` were just
+    // appended. If the write fails, only those are dropped and the rest stay batched.
     fn flush_if_full_batch(
         &mut self,
         append_res: Option<Vec<T>>,
+        num_new_entries: usize,
+    ) -> StorageResult<Option<AcceptedMetaData<T>>> {
+        self.flush_if_full_batch_with(append_res, num_new_entries, vec![])
+    }
+
+    // Like `flush_if_full_batch()`, but commits the writes of `then` together with the flushed
+    // entries, or on their own if nothing is flushed.
+    fn flush_if_full_batch_with(
+        &mut self,
+        append_res: Option<Vec<T>>,
+        num_new_entries: usize,
+        then: Vec<StorageOp<T>>,
     ) -> StorageResult<Option<AcceptedMetaData<T>>> {
         if let Some(flushed_entries) = append_res {
-            let accepted_idx = self.append_entries_without_batching(flushed_entries.clone())?;
+            let accepted_idx =
+                self.append_flushed_entries(&flushed_entries, num_new_entries, then)?;
             Ok(Some(AcceptedMetaData {
                 accepted_idx,
                 #[cfg(not(feature = "unicache"))]
@@ -248,6 +503,7 @@ where
                 entries: self.state_cache.take_batched_processed(),
             }))
         } else {
+            self.write_ops(then)?;
             Ok(None)
         }
     }
@@ -258,9 +514,11 @@ where
         &mut self,
         entries: Vec<T>,
     ) -> StorageResult<Option<usize>> {
+        let num_new_entries = entries.len();
         let append_res = self.state_cache.append_entries(entries);
         if let Some(flushed_entries) = append_res {
-            let accepted_idx = self.append_entries_without_batching(flushed_entries)?;
+            let accepted_idx =
+                self.append_flushed_entries(&flushed_entries, num_new_entries, vec![])?;
             Ok(Some(accepted_idx))
         } else {
             Ok(None)
@@ -279,13 +537,79 @@ where
     }
 
     pub(crate) fn flush_batch(&mut self) -> StorageResult<usize> {
+        self.flush_batch_with(vec![])
+    }
+
+    // Flushes the batched entries and commits the writes of `then` in the same write. Returns
+    // the accepted index.
+    fn flush_batch_with(&mut self, then: Vec<StorageOp<T>>) -> StorageResult<usize> {
+        let flushed_entries = self.state_cache.take_batched_entries();
+        let accepted_idx = self.append_flushed_entries(&flushed_entries, 0, then)?;
         #[cfg(feature = "unicache")]
         {
             // clear the processed batch
             self.state_cache.batched_processed_by_leader.clear();
         }
-        let flushed_entries = self.state_cache.take_batched_entries();
-        self.append_entries_without_batching(flushed_entries)
+        Ok(accepted_idx)
+    }
+
+    // Appends the `flushed_entries` of the batch and commits the writes of `then` in the same
+    // write. If the write fails, the flushed entries are put back in the batch, except for the
+    // last `num_new_entries`, which the caller has just appended and reports as failed. Then the
+    // batch is the same as before the failed call.
+    fn append_flushed_entries(
+        &mut self,
+        flushed_entries: &[T],
+        num_new_entries: usize,
+        then: Vec<StorageOp<T>>,
+    ) -> StorageResult<usize> {
+        let res = self.append_entries_with(flushed_entries.to_vec(), then);
+        if res.is_err() {
+            let num_batched = flushed_entries.len() - num_new_entries;
+            self.state_cache
+                .restore_batched_entries(flushed_entries[..num_batched].to_vec());
+            #[cfg(feature = "unicache")]
+            {
+                let processed = &mut self.state_cache.batched_processed_by_leader;
+                processed.truncate(processed.len().saturating_sub(num_new_entries));
+            }
+        }
+        res
+    }
+
+    /// Flushes the batched entries and promises `n_prom` in a single write.
+    pub(crate) fn flush_batch_and_set_promise(&mut self, n_prom: Ballot) -> StorageResult<usize> {
+        self.check_halted()?;
+        let accepted_idx = self.flush_batch_with(vec![StorageOp::SetPromise(n_prom)])?;
+        self.state_cache.promise = n_prom;
+        Ok(accepted_idx)
+    }
+
+    /// Flushes the batched entries and sets the decided index to `idx`, or to the new accepted
+    /// index if it is smaller, in a single write. Returns the accepted index.
+    pub(crate) fn flush_batch_and_set_decided_idx(&mut self, idx: usize) -> StorageResult<usize> {
+        self.check_halted()?;
+        let batched = self.state_cache.batched_entries.len();
+        let decided_idx = idx.min(self.state_cache.accepted_idx + batched);
+        let accepted_idx = self.flush_batch_with(vec![StorageOp::SetDecidedIndex(decided_idx)])?;
+        #[cfg(feature = "pipeline_events")]
+        self.pipeline.reached(PipelineStage::Decided, decided_idx);
+        self.state_cache.decided_idx = decided_idx;
+        self.publish_indexes();
+        Ok(accepted_idx)
+    }
+
+    /// Flushes the batched entries and appends `ss` to the log in a single write. Returns the
+    /// accepted index, which includes the StopSign.
+    pub(crate) fn flush_batch_and_set_stopsign(&mut self, ss: StopSign) -> StorageResult<usize> {
+        self.check_halted()?;
+        self.flush_batch_with(vec![StorageOp::SetStopsign(Some(ss.clone()))])?;
+        if self.state_cache.stopsign.is_none() {
+            self.state_cache.accepted_idx += 1;
+        }
+        self.state_cache.stopsign = Some(ss);
+        self.publish_indexes();
+        Ok(self.state_cache.accepted_idx)
     }
 
     pub(crate) fn flush_batch_and_get_entries(
@@ -296,7 +620,7 @@ where
         } else {
             None
         };
-        self.flush_if_full_batch(flushed_entries)
+        self.flush_if_full_batch(flushed_entries, 0)
     }
 
     // Append entries without batching, return the accepted index
@@ -304,76 +628,302 @@ where
         &mut self,
         entries: Vec<T>,
     ) -> StorageResult<usize> {
+        self.append_entries_with(entries, vec![])
+    }
+
+    // Appends `entries` and commits the writes of `then` after them in a single write. Returns
+    // the accepted index, not including any StopSign set by `then`.
+    fn append_entries_with(
+        &mut self,
+        entries: Vec<T>,
+        then: Vec<StorageOp<T>>,
+    ) -> StorageResult<usize> {
+        self.check_halted()?;
+        if entries.is_empty() {
+            self.write_ops(then)?;
+            return Ok(self.state_cache.accepted_idx);
+        }
         let num_new_entries = entries.len();
-        self.storage.append_entries(entries)?;
+        let from_idx = self.state_cache.accepted_idx;
+        self.tail_cache.append(from_idx, &entries);
+        let start = Instant::now();
+        // A plain append doesn't need a batch
+        let res = match then.is_empty() {
+            true => self.storage().append_entries(entries),
+            false => {
+                let mut ops = vec![StorageOp::AppendEntries(entries)];
+                ops.extend(then);
+                self.storage().write_atomically(ops)
+            }
+        };
+        self.append_latency.0 += start.elapsed();
+        self.append_latency.1 += 1;
+        if let Err(e) = res {
+            self.tail_cache.truncate(from_idx);
+            return Err(e);
+        }
+        // Entries are only appended in the accept phase, where the promise is the leader's ballot
+        let ballot = self.state_cache.promise;
+        self.record_entry_ballots(from_idx, vec![(from_idx, Some(ballot))]);
         self.state_cache.accepted_idx += num_new_entries;
+        self.publish_indexes();
         Ok(self.state_cache.accepted_idx)
     }
 
+    // Commits the writes of `ops` atomically, if there are any.
+    fn write_ops(&self, ops: Vec<StorageOp<T>>) -> StorageResult<()> {
+        match ops.is_empty() {
+            true => Ok(()),
+            false => self.storage().write_atomically(ops),
+        }
+    }
+
     pub(crate) fn sync_log(
         &mut self,
         accepted_round: Ballot,
         decided_idx: usize,
         log_sync: Option<LogSync<T>>,
     ) -> StorageResult<usize> {
-        self.state_cache.accepted_round = accepted_round;
-        self.state_cache.decided_idx = decided_idx;
-        let mut sync_txn: Vec<StorageOp<T>> = vec![
+        self.check_halted()?;
+        // A delta snapshot is created from the log decided before the synchronization
+        #[cfg(not(feature = "no_snapshots"))]
+        let prev_log_decided_idx = self.get_decided_idx_without_stopsign();
+        // The accepted round, decided index and log are written atomically, and the cache is
+        // only updated once the write is committed.
+        let mut ops = vec![
             StorageOp::SetAcceptedRound(accepted_round),
             StorageOp::SetDecidedIndex(decided_idx),
         ];
-        if let Some(sync) = log_sync {
-            match sync.decided_snapshot {
-                Some(SnapshotType::Complete(c)) => {
-                    self.state_cache.compacted_idx = sync.sync_idx;
-                    sync_txn.push(StorageOp::Trim(sync.sync_idx));
-                    sync_txn.push(StorageOp::SetCompactedIdx(sync.sync_idx));
-                    sync_txn.push(StorageOp::SetSnapshot(Some(c)));
-                }
-                Some(SnapshotType::Delta(d)) => {
-                    let mut snapshot = self.create_decided_snapshot()?;
-                    snapshot.merge(d);
-                    self.state_cache.compacted_idx = sync.sync_idx;
-                    sync_txn.push(StorageOp::Trim(sync.sync_idx));
-                    sync_txn.push(StorageOp::SetCompactedIdx(sync.sync_idx));
-                    sync_txn.push(StorageOp::SetSnapshot(Some(snapshot)));
-                }
-                None => (),
+        let Some(sync) = log_sync else {
+            self.storage().write_atomically(ops)?;
+            self.state_cache.accepted_round = accepted_round;
+            self.state_cache.decided_idx = decided_idx;
+            self.publish_indexes();
+            return Ok(self.state_cache.accepted_idx);
+        };
+        let trimmed = sync.decided_snapshot.is_some();
+        #[cfg(feature = "no_snapshots")]
+        if trimmed {
+            Err(CompactionErr::SnapshotsUnsupported)?
+        }
+        #[cfg(not(feature = "no_snapshots"))]
+        let snapshot = match sync.decided_snapshot {
+            Some(SnapshotType::Complete(c)) => Some(c),
+            Some(SnapshotType::Delta(d)) => {
+                let mut snapshot = self.create_compacting_snapshot(prev_log_decided_idx)?;
+                snapshot.merge(d);
+                Some(snapshot)
             }
-            self.state_cache.accepted_idx = sync.sync_idx + sync.suffix.len();
-            sync_txn.push(StorageOp::AppendOnPrefix(sync.sync_idx, sync.suffix));
-            match sync.stopsign {
-                Some(ss) => {
-                    self.state_cache.stopsign = Some(ss.clone());
-                    self.state_cache.accepted_idx += 1;
-                    sync_txn.push(StorageOp::SetStopsign(Some(ss)));
-                }
-                None if self.state_cache.stopsign.is_some() => {
-                    self.state_cache.stopsign = None;
-                    sync_txn.push(StorageOp::SetStopsign(None));
-                }
-                None => (),
+            #[cfg(feature = "snapshot_sealing")]
+            Some(SnapshotType::Sealed(_)) => {
+                Err("a sealed snapshot must be opened before it is installed")?
             }
+            None => None,
+        };
+        // The ballots of the entries that a snapshot replaces are synced along with the suffix
+        #[cfg(not(feature = "no_snapshots"))]
+        let ballots_from = match trimmed {
+            true => prev_log_decided_idx.min(sync.sync_idx),
+            false => sync.sync_idx,
+        };
+        #[cfg(feature = "no_snapshots")]
+        let ballots_from = sync.sync_idx;
+        #[cfg(not(feature = "no_snapshots"))]
+        if let Some(snapshot) = snapshot {
+            ops.push(StorageOp::Trim(sync.sync_idx));
+            ops.push(StorageOp::SetCompactedIdx(sync.sync_idx));
+            ops.push(StorageOp::SetSnapshot(Some(snapshot)));
+        }
+        let sync_end_idx = sync.sync_idx + sync.suffix.len();
+        ops.push(StorageOp::AppendOnPrefix(sync.sync_idx, sync.suffix));
+        let stopsign_changed = sync.stopsign.is_some() || self.state_cache.stopsign.is_some();
+        if stopsign_changed {
+            ops.push(StorageOp::SetStopsign(sync.stopsign.clone()));
+        }
+        let mut storage = self.storage();
+        storage.write_atomically(ops)?;
+        if trimmed {
+            self.deferred_trim
+                .trimmed_idx
+                .fetch_max(sync.sync_idx, AtomicOrdering::AcqRel);
+        }
+        drop(storage);
+        self.state_cache.accepted_round = accepted_round;
+        self.state_cache.decided_idx = decided_idx;
+        if trimmed {
+            self.state_cache.compacted_idx = sync.sync_idx;
         }
-        self.storage.write_atomically(sync_txn)?;
+        let synced_ballots = sync
+            .suffix_ballots
+            .into_iter()
+            .filter(|(idx, _)| *idx < sync_end_idx)
+            .map(|(idx, ballot)| (idx.max(ballots_from), ballot));
+        let mut ballots = vec![(ballots_from, None)];
+        ballots.extend(synced_ballots);
+        self.record_entry_ballots(ballots_from, ballots);
+        self.state_cache.accepted_idx = sync_end_idx;
+        self.tail_cache.truncate(sync.sync_idx);
+        self.tail_cache.trim(self.state_cache.compacted_idx);
+        if stopsign_changed {
+            if sync.stopsign.is_some() {
+                self.state_cache.accepted_idx += 1;
+            }
+            self.state_cache.stopsign = sync.stopsign;
+        }
+        self.publish_indexes();
         Ok(self.state_cache.accepted_idx)
     }
 
-    fn create_decided_snapshot(&mut self) -> StorageResult<T::Snapshot> {
-        let log_decided_idx = self.get_decided_idx_without_stopsign();
-        self.create_snapshot(log_decided_idx)
+    /// Discards the entries and the StopSign from `idx` (inclusive) onwards. Returns the new
+    /// accepted index.
+    #[cfg(feature = "operator_tools")]
+    pub(crate) fn truncate_after(&mut self, idx: usize) -> StorageResult<usize> {
+        self.check_halted()?;
+        let accepted_idx = self.state_cache.accepted_idx;
+        // The StopSign is at the last index of the log
+        let log_len = match self.state_cache.stopsign {
+            Some(_) => accepted_idx - 1,
+            None => accepted_idx,
+        };
+        let mut truncate_txn = vec![];
+        if idx < log_len {
+            truncate_txn.push(StorageOp::AppendOnPrefix(idx, vec![]));
+        }
+        if idx < accepted_idx && self.state_cache.stopsign.is_some() {
+            truncate_txn.push(StorageOp::SetStopsign(None));
+        }
+        if !truncate_txn.is_empty() {
+            self.storage().write_atomically(truncate_txn)?;
+            self.state_cache.stopsign = None;
+            self.state_cache.accepted_idx = idx;
+            self.tail_cache.truncate(idx);
+            self.record_entry_ballots(idx, vec![]);
+            self.publish_indexes();
+        }
+        Ok(self.state_cache.accepted_idx)
     }
 
+    /// Replaces the ballots of the entries from `from_idx` on with `ballots`, given as the index
+    /// of the first entry of each ballot in increasing order.
+    fn record_entry_ballots(&mut self, from_idx: usize, ballots: Vec<(usize, Option<Ballot>)>) {
+        let Some(entry_ballots) = self.entry_ballots.as_mut() else {
+            return;
+        };
+        entry_ballots.split_off(&from_idx);
+        for (idx, ballot) in ballots {
+            // A later ballot for the same index replaces the earlier one
+            if entry_ballots
+                .last_key_value()
+                .is_some_and(|(last, _)| *last == idx)
+            {
+                entry_ballots.remove(&idx);
+            }
+            // Keeps one run per ballot
+            if entry_ballots.last_key_value().map(|(_, b)| *b) != Some(ballot) {
+                entry_ballots.insert(idx, ballot);
+            }
+        }
+    }
+
+    /// Returns the ballot in which the entry at `idx` was first appended to the log, or `None` if
+    /// it is unknown or not tracked.
+    pub(crate) fn get_entry_ballot(&self, idx: usize) -> Option<Ballot> {
+        if idx >= self.state_cache.accepted_idx {
+            return None;
+        }
+        let (_, ballot) = self.entry_ballots.as_ref()?.range(..=idx).next_back()?;
+        *ballot
+    }
+
+    /// Returns the ballots of the entries from `from_idx` on as the index of the first entry of
+    /// each ballot. Empty if the ballots are not tracked.
+    pub(crate) fn get_entry_ballots(&self, from_idx: usize) -> Vec<(usize, Option<Ballot>)> {
+        let Some(entry_ballots) = self.entry_ballots.as_ref() else {
+            return vec![];
+        };
+        let accepted_idx = self.state_cache.accepted_idx;
+        if from_idx >= accepted_idx {
+            return vec![];
+        }
+        let first = entry_ballots
+            .range(..=from_idx)
+            .next_back()
+            .and_then(|(_, ballot)| *ballot);
+        let rest = entry_ballots
+            .range(from_idx + 1..accepted_idx)
+            .map(|(idx, ballot)| (*idx, *ballot));
+        std::iter::once((from_idx, first)).chain(rest).collect()
+    }
+
+    #[cfg(feature = "no_snapshots")]
+    pub(crate) fn create_snapshot(&self, _compact_idx: usize) -> StorageResult<T::Snapshot> {
+        Err(CompactionErr::SnapshotsUnsupported.into())
+    }
+
+    #[cfg(not(feature = "no_snapshots"))]
     pub(crate) fn create_snapshot(&self, compact_idx: usize) -> StorageResult<T::Snapshot> {
         let current_compacted_idx = self.get_compacted_idx();
         if compact_idx < current_compacted_idx {
             Err(CompactionErr::TrimmedIndex(current_compacted_idx))?
         }
-        let entries = self
-            .storage
-            .get_entries(current_compacted_idx, compact_idx)?;
+        let snapshot = self.storage().get_snapshot()?;
+        self.merge_entries(snapshot, current_compacted_idx, compact_idx)
+    }
+
+    /// Creates the snapshot of the log up to `compact_idx` like `create_snapshot()`, but with a
+    /// `snapshot_batch_size`, the log is compacted and the intermediate snapshot is persisted
+    /// after every batch of entries. The caller is expected to compact the log up to
+    /// `compact_idx` with the returned snapshot.
+    #[cfg(not(feature = "no_snapshots"))]
+    fn create_compacting_snapshot(&mut self, compact_idx: usize) -> StorageResult<T::Snapshot> {
+        let mut from_idx = self.get_compacted_idx();
+        if compact_idx < from_idx {
+            Err(CompactionErr::TrimmedIndex(from_idx))?
+        }
+        let mut snapshot = self.storage().get_snapshot()?;
+        loop {
+            let to_idx = self.snapshot_batch_end(from_idx, compact_idx);
+            let merged = self.merge_batch(snapshot, from_idx, to_idx)?;
+            if to_idx == compact_idx {
+                return Ok(merged);
+            }
+            self.compact(to_idx, Some(merged.clone()))?;
+            snapshot = Some(merged);
+            from_idx = to_idx;
+        }
+    }
+
+    /// Merges the snapshot of the entries in [from_idx, to_idx) into `snapshot`, or returns it if
+    /// there is no `snapshot`. At most `snapshot_batch_size` entries are read at once.
+    #[cfg(not(feature = "no_snapshots"))]
+    fn merge_entries(
+        &self,
+        mut snapshot: Option<T::Snapshot>,
+        mut from_idx: usize,
+        to_idx: usize,
+    ) -> StorageResult<T::Snapshot> {
+        loop {
+            let batch_end = self.snapshot_batch_end(from_idx, to_idx);
+            let merged = self.merge_batch(snapshot, from_idx, batch_end)?;
+            if batch_end == to_idx {
+                return Ok(merged);
+            }
+            snapshot = Some(merged);
+            from_idx = batch_end;
+        }
+    }
+
+    #[cfg(not(feature = "no_snapshots"))]
+    fn merge_batch(
+        &self,
+        snapshot: Option<T::Snapshot>,
+        from_idx: usize,
+        to_idx: usize,
+    ) -> StorageResult<T::Snapshot> {
+        let entries = self.get_entries(from_idx, to_idx)?;
+        self.check_snapshot(&entries, from_idx, from_idx + entries.len() / 2);
         let delta = T::Snapshot::create(entries.as_slice());
-        match self.storage.get_snapshot()? {
+        match snapshot {
             Some(mut s) => {
                 s.merge(delta);
                 Ok(s)
@@ -382,10 +932,21 @@ where
         }
     }
 
+    /// The end of the batch of entries from `from_idx` that is snapshotted at once when
+    /// snapshotting up to `to_idx`.
+    #[cfg(not(feature = "no_snapshots"))]
+    fn snapshot_batch_end(&self, from_idx: usize, to_idx: usize) -> usize {
+        match self.snapshot_batch_size {
+            0 => to_idx,
+            batch_size => (from_idx + batch_size).min(to_idx),
+        }
+    }
+
     // Creates a Delta snapshot of entries from `from_idx` to the end of the decided log and also
     // returns the compacted idx of the created snapshot. If the range of entries contains entries
     // which have already been compacted a valid delta cannot be created, so creates a Complete
     // snapshot of the entire decided log instead.
+    #[cfg(not(feature = "no_snapshots"))]
     pub(crate) fn create_diff_snapshot(
         &self,
         from_idx: usize,
@@ -403,15 +964,52 @@ where
                 self.get_snapshot()?.map(|s| SnapshotType::Complete(s))
             }
         } else {
-            let diff_entries = self.get_entries(from_idx, log_decided_idx)?;
-            Some(SnapshotType::Delta(T::Snapshot::create(
-                diff_entries.as_slice(),
-            )))
+            if self.check_snapshots {
+                // The leader still has the entries that the receiver of the delta snapshotted.
+                // Only a batch of entries around `from_idx` is checked with a `snapshot_batch_size`.
+                let (check_from, check_to) = match self.snapshot_batch_size {
+                    0 => (compacted_idx, log_decided_idx),
+                    batch_size => (
+                        from_idx.saturating_sub(batch_size / 2).max(compacted_idx),
+                        (from_idx + batch_size / 2).min(log_decided_idx),
+                    ),
+                };
+                let entries = self.get_entries(check_from, check_to)?;
+                self.check_snapshot(&entries, check_from, from_idx);
+            }
+            Some(SnapshotType::Delta(self.merge_entries(
+                None,
+                from_idx,
+                log_decided_idx,
+            )?))
         };
         Ok((snapshot, log_decided_idx))
     }
 
+    /// Checks the `Snapshot` contract on the `entries` starting at `from_idx`, split into a prefix
+    /// and a delta at `split_idx`, if `check_snapshots` is enabled and neither part is empty.
+    #[cfg(not(feature = "no_snapshots"))]
+    fn check_snapshot(&self, entries: &[T], from_idx: usize, split_idx: usize) {
+        let to_idx = from_idx + entries.len();
+        if !self.check_snapshots || split_idx <= from_idx || split_idx >= to_idx {
+            return;
+        }
+        if let Some(violation) = check_snapshot_contract(entries, from_idx, split_idx) {
+            let mut violations = self.snapshot_violations.lock().expect(LOCK_POISONED_MSG);
+            if violations.len() == SNAPSHOT_VIOLATIONS {
+                violations.pop_front();
+            }
+            violations.push_back(violation);
+        }
+    }
+
+    pub(crate) fn take_snapshot_violations(&mut self) -> Vec<SnapshotViolation> {
+        let violations = self.snapshot_violations.get_mut().expect(LOCK_POISONED_MSG);
+        violations.drain(..).collect()
+    }
+
     pub(crate) fn try_trim(&mut self, idx: usize) -> StorageResult<()> {
+        self.check_halted()?;
         let decided_idx = self.get_decided_idx();
         let log_decided_idx = self.get_decided_idx_without_stopsign();
         let new_compacted_idx = match idx.cmp(&decided_idx) {
@@ -420,16 +1018,18 @@ where
             Ordering::Greater => Err(CompactionErr::UndecidedIndex(decided_idx))?,
         };
         if new_compacted_idx > self.get_compacted_idx() {
-            self.storage.write_atomically(vec![
-                StorageOp::Trim(new_compacted_idx),
-                StorageOp::SetCompactedIdx(new_compacted_idx),
-            ])?;
-            self.state_cache.compacted_idx = new_compacted_idx;
+            self.check_pins(new_compacted_idx)?;
+            self.compact(new_compacted_idx, None)?;
         }
         Ok(())
     }
 
+    #[cfg(not(feature = "no_snapshots"))]
     pub(crate) fn try_snapshot(&mut self, snapshot_idx: Option<usize>) -> StorageResult<()> {
+        self.check_halted()?;
+        if !use_snapshots::<T>() {
+            Err(CompactionErr::SnapshotsUnsupported)?
+        }
         let decided_idx = self.get_decided_idx();
         let log_decided_idx = self.get_decided_idx_without_stopsign();
         let new_compacted_idx = match snapshot_idx {
@@ -441,32 +1041,108 @@ where
             None => log_decided_idx,
         };
         if new_compacted_idx > self.get_compacted_idx() {
-            let snapshot = self.create_snapshot(new_compacted_idx)?;
-            self.storage.write_atomically(vec![
-                StorageOp::Trim(new_compacted_idx),
-                StorageOp::SetCompactedIdx(new_compacted_idx),
-                StorageOp::SetSnapshot(Some(snapshot)),
-            ])?;
-            self.state_cache.compacted_idx = new_compacted_idx;
+            self.check_pins(new_compacted_idx)?;
+            let snapshot = self.create_compacting_snapshot(new_compacted_idx)?;
+            self.compact(new_compacted_idx, Some(snapshot))?;
         }
         Ok(())
     }
 
+    #[cfg(feature = "no_snapshots")]
+    pub(crate) fn try_snapshot(&mut self, _snapshot_idx: Option<usize>) -> StorageResult<()> {
+        Err(CompactionErr::SnapshotsUnsupported.into())
+    }
+
+    /// Holds back the compaction of the log past `idx` while the returned guard is alive.
+    pub(crate) fn pin_log(&self, idx: usize) -> PinGuard {
+        self.log_pins.pin(idx)
+    }
+
+    /// Fails if compacting the log up to `compacted_idx` would remove pinned entries.
+    fn check_pins(&self, compacted_idx: usize) -> StorageResult<()> {
+        match self.log_pins.min_pinned_idx() {
+            Some(pinned_idx) if pinned_idx < compacted_idx => {
+                Err(CompactionErr::Pinned(pinned_idx))?
+            }
+            _ => Ok(()),
+        }
+    }
+
+    /// Compacts the log up to `compacted_idx` and stores `snapshot` if there is one. Unless the
+    /// `trim_mode` is `Immediate`, the compacted entries are only removed from the storage by
+    /// `run_deferred_trim()` or a `TrimWorker`.
+    fn compact(
+        &mut self,
+        compacted_idx: usize,
+        snapshot: Option<T::Snapshot>,
+    ) -> StorageResult<()> {
+        let immediate = self.trim_mode == TrimMode::Immediate;
+        let mut ops = vec![];
+        if immediate {
+            ops.push(StorageOp::Trim(compacted_idx));
+        }
+        ops.push(StorageOp::SetCompactedIdx(compacted_idx));
+        if let Some(snapshot) = snapshot {
+            ops.push(StorageOp::SetSnapshot(Some(snapshot)));
+        }
+        let mut storage = self.storage();
+        storage.write_atomically(ops)?;
+        let deferred_trim = &self.deferred_trim;
+        let idx = match immediate {
+            true => &deferred_trim.trimmed_idx,
+            false => &deferred_trim.target_idx,
+        };
+        idx.fetch_max(compacted_idx, AtomicOrdering::AcqRel);
+        drop(storage);
+        self.state_cache.compacted_idx = compacted_idx;
+        self.tail_cache.trim(compacted_idx);
+        self.publish_indexes();
+        Ok(())
+    }
+
+    pub(crate) fn get_trim_mode(&self) -> TrimMode {
+        self.trim_mode
+    }
+
+    /// Removes the compacted entries from the storage if their removal was deferred.
+    pub(crate) fn run_deferred_trim(&self) -> StorageResult<Option<usize>> {
+        self.check_halted()?;
+        self.deferred_trim.run(&self.storage)
+    }
+
+    /// Returns the deferred trim shared with trim workers.
+    pub(crate) fn get_deferred_trim(&self) -> Arc<DeferredTrim> {
+        Arc::clone(&self.deferred_trim)
+    }
+
+    /// Returns the average duration of the appends to the storage since the last call, or `None`
+    /// if there were none.
+    pub(crate) fn take_append_latency(&mut self) -> Option<Duration> {
+        let (total, appends) = std::mem::replace(&mut self.append_latency, (Duration::ZERO, 0));
+        (appends > 0).then(|| total / appends)
+    }
+
     pub(crate) fn set_promise(&mut self, n_prom: Ballot) -> StorageResult<()> {
+        self.check_halted()?;
         self.state_cache.promise = n_prom;
-        self.storage.set_promise(n_prom)
+        self.storage().set_promise(n_prom)
     }
 
     pub(crate) fn set_decided_idx(&mut self, idx: usize) -> StorageResult<()> {
+        self.check_halted()?;
+        #[cfg(feature = "pipeline_events")]
+        self.pipeline.reached(PipelineStage::Decided, idx);
         self.state_cache.decided_idx = idx;
-        self.storage.set_decided_idx(idx)
+        self.storage().set_decided_idx(idx)?;
+        self.publish_indexes();
+        Ok(())
     }
 
     pub(crate) fn get_decided_idx(&self) -> usize {
         self.state_cache.decided_idx
     }
 
-    fn get_decided_idx_without_stopsign(&self) -> usize {
+    pub(crate) fn get_decided_idx_without_stopsign(&self) -> usize {
         match self.stopsign_is_decided() {
             true => self.get_decided_idx() - 1,
             false => self.get_decided_idx(),
@@ -477,8 +1153,61 @@ where
         self.state_cache.accepted_round
     }
 
+    /// Returns the entries in the interval [from, to). Recently appended entries are served from
+    /// the log tail cache without reading from the storage back-end.
     pub(crate) fn get_entries(&self, from: usize, to: usize) -> StorageResult<Vec<T>> {
-        self.storage.get_entries(from, to)
+        match self.tail_cache.get(from, to) {
+            Some(entries) => Ok(entries),
+            None => self.storage().get_entries(from, to),
+        }
+    }
+
+    /// Returns the entries from `from` that fit in about `max_bytes` bytes, but at most up to
+    /// `to`, and the index to continue reading from. See
+    /// [`LogStorage::get_entries_hint`](crate::storage::LogStorage::get_entries_hint).
+    pub(crate) fn get_entries_hint(
+        &self,
+        from: usize,
+        to: usize,
+        max_bytes: usize,
+    ) -> StorageResult<EntriesHint<T>> {
+        match self.tail_cache.get(from, to) {
+            Some(entries) => Ok(EntriesHint::with(entries, from, to)),
+            None => self.storage().get_entries_hint(from, to, max_bytes),
+        }
+    }
+
+    /// Returns the entries in the interval [from, to), read in portions of about `max_bytes`
+    /// bytes instead of a single range read.
+    pub(crate) fn get_entries_in_portions(
+        &self,
+        from: usize,
+        to: usize,
+        max_bytes: usize,
+    ) -> StorageResult<Vec<T>> {
+        let mut entries = vec![];
+        let mut idx = from;
+        while idx < to {
+            let hint = self.get_entries_hint(idx, to, max_bytes)?;
+            if hint.entries.is_empty() {
+                // Like `get_entries()`, an incomplete interval is returned as empty
+                return Ok(vec![]);
+            }
+            entries.extend(hint.entries);
+            match hint.continuation {
+                Some(next) if next > idx => idx = next,
+                _ => break,
+            }
+        }
+        Ok(entries)
+    }
+
+    /// The length of the log in the storage, i.e., the accepted index without any StopSign.
+    pub(crate) fn get_log_end(&self) -> usize {
+        match self.state_cache.stopsign {
+            Some(_) => self.state_cache.accepted_idx - 1,
+            None => self.state_cache.accepted_idx,
+        }
     }
 
     /// The length of the replicated log, as if log was never compacted.
@@ -486,23 +1215,110 @@ where
         self.state_cache.accepted_idx
     }
 
+    /// Returns the accepted index, limited to the entries that the storage has persisted.
+    pub(crate) fn get_durable_accepted_idx(&self) -> StorageResult<usize> {
+        let accepted_idx = self.get_accepted_idx();
+        Ok(match self.storage().get_durable_idx()? {
+            Some(durable_idx) => durable_idx.min(accepted_idx),
+            None => accepted_idx,
+        })
+    }
+
+    /// Blocks until the storage has persisted all writes so far.
+    pub(crate) fn flush(&self) -> StorageResult<()> {
+        self.storage().flush()
+    }
+
+    pub(crate) fn approximate_size(&self) -> StorageResult<Option<u64>> {
+        self.storage().approximate_size()
+    }
+
+    /// Copies the state of the storage into `storage`, verifies the copy, and replaces the storage
+    /// with it. The storage stays locked throughout, so that neither readers nor the trim worker
+    /// can access it in between. Returns the replaced storage. Batched entries must be flushed
+    /// before.
+    pub(crate) fn swap_storage(&mut self, mut storage: I) -> Result<I, StorageMigrationErr>
+    where
+        T: PartialEq,
+    {
+        let mut current = self.storage.lock().expect(LOCK_POISONED_MSG);
+        copy_storage(&*current, &mut storage)?;
+        verify_storage_copy(&*current, &storage)?;
+        let capacity = storage
+            .get_capacity()
+            .map_err(|e| StorageErr::with("read the capacity", e))?;
+        let previous = std::mem::replace(&mut *current, storage);
+        // The compacted entries were not copied, so a deferred trim is completed
+        self.deferred_trim
+            .trimmed_idx
+            .fetch_max(self.state_cache.compacted_idx, AtomicOrdering::AcqRel);
+        self.capacity = capacity;
+        Ok(previous)
+    }
+
+    /// The length of the log including the entries that are batched but not appended yet.
+    pub(crate) fn get_appended_idx(&self) -> usize {
+        self.state_cache.accepted_idx + self.state_cache.batched_entries.len()
+    }
+
+    pub(crate) fn get_capacity(&self) -> Option<usize> {
+        self.capacity
+    }
+
+    /// The number of entries after the compacted index, including the batched entries.
+    pub(crate) fn get_uncompacted_len(&self) -> usize {
+        self.get_appended_idx()
+            .saturating_sub(self.get_compacted_idx())
+    }
+
+    /// The approximate size in bytes of the entries batched before they are flushed to storage.
+    pub(crate) fn get_batch_memory_usage(&self) -> usize {
+        self.state_cache.batched_bytes
+    }
+
+    /// The approximate size in bytes of the entries in the log tail cache.
+    pub(crate) fn get_cache_memory_usage(&self) -> usize {
+        self.tail_cache.size_hint()
+    }
+
+    /// Drops the entries in the log tail cache to free memory. Reads are served from the storage
+    /// until the cache has been refilled by the following appends.
+    pub(crate) fn clear_tail_cache(&mut self) {
+        self.tail_cache.clear();
+    }
+
     pub(crate) fn get_suffix(&self, from: usize) -> StorageResult<Vec<T>> {
-        self.storage.get_suffix(from)
+        self.storage().get_suffix(from)
     }
 
     pub(crate) fn get_promise(&self) -> Ballot {
         self.state_cache.promise
     }
 
-    pub(crate) fn set_stopsign(&mut self, ss: Option<StopSign>) -> StorageResult<usize> {
-        if ss.is_some() && self.state_cache.stopsign.is_none() {
-            self.state_cache.accepted_idx += 1;
-        } else if ss.is_none() && self.state_cache.stopsign.is_some() {
-            self.state_cache.accepted_idx -= 1;
+    /// Stores `ballot` as the highest ballot used in the leader election if it is higher than the
+    /// stored one.
+    pub(crate) fn set_ble_ballot(&mut self, ballot: Ballot) -> StorageResult<()> {
+        self.check_halted()?;
+        if ballot > self.state_cache.ble_ballot {
+            self.state_cache.ble_ballot = ballot;
+            self.storage().set_ble_ballot(ballot)?;
         }
-        self.state_cache.stopsign.clone_from(&ss);
-        self.storage.set_stopsign(ss)?;
-        Ok(self.state_cache.accepted_idx)
+        Ok(())
+    }
+
+    /// Durably stores the standby promise before it is cached, so that a cached standby promise
+    /// is never lost in a crash.
+    pub(crate) fn set_standby_promise(&mut self, standby: Option<Ballot>) -> StorageResult<()> {
+        self.check_halted()?;
+        if standby != self.state_cache.standby_promise {
+            self.storage().set_standby_promise(standby)?;
+            self.state_cache.standby_promise = standby;
+        }
+        Ok(())
+    }
+
+    pub(crate) fn get_standby_promise(&self) -> Option<Ballot> {
+        self.state_cache.standby_promise
     }
 
     pub(crate) fn get_stopsign(&self) -> Option<StopSign> {
@@ -514,14 +1330,34 @@ where
         self.state_cache.stopsign_is_decided()
     }
 
+    /// Returns the storage back-end shared with readers.
+    pub(crate) fn get_shared_storage(&self) -> Arc<Mutex<I>> {
+        Arc::clone(&self.storage)
+    }
+
+    #[cfg(feature = "pipeline_events")]
+    pub(crate) fn pipeline(&mut self) -> &mut PipelineTracker {
+        &mut self.pipeline
+    }
+
+    /// Returns the published indexes shared with readers.
+    pub(crate) fn get_published_indexes(&self) -> Arc<PublishedIndexes> {
+        Arc::clone(&self.indexes)
+    }
+
+    #[cfg(not(feature = "no_snapshots"))]
     pub(crate) fn get_snapshot(&self) -> StorageResult<Option<T::Snapshot>> {
-        self.storage.get_snapshot()
+        self.storage().get_snapshot()
     }
 
     pub(crate) fn get_compacted_idx(&self) -> usize {
         self.state_cache.compacted_idx
     }
 
+    pub(crate) fn set_batch_size(&mut self, batch_size: usize) {
+        self.state_cache.batch_size = batch_size;
+    }
+
     #[cfg(feature = "unicache")]
     pub(crate) fn get_unicache(&self) -> T::UniCache {
         self.state_cache.unicache.clone()
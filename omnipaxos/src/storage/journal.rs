@@ -0,0 +1,1218 @@
+use super::{
+    archive::{read_exact, ArchiveCodec, Decoder, Encoder},
+    EntriesHint, Entry, LogStorage, StateStorage, StopSign, StorageOp, StorageResult,
+};
+use crate::{ballot_leader_election::Ballot, errors::ArchiveErr, util::LOCK_POISONED_MSG};
+use std::{
+    io::{ErrorKind, Read, Write},
+    marker::PhantomData,
+    sync::Mutex,
+};
+
+/// The first bytes of every journal.
+pub const JOURNAL_MAGIC: [u8; 4] = *b"OPXJ";
+/// The version of the journal format written by this crate. Readers reject journals with a
+/// higher version, while records of unknown kinds are skipped.
+pub const JOURNAL_FORMAT_VERSION: u16 = 1;
+
+const CALL_RECORD: u8 = 1;
+
+const WRITE_ATOMICALLY: u8 = 0;
+const WRITE: u8 = 1;
+const SET_BLE_BALLOT: u8 = 2;
+const GET_ENTRIES: u8 = 3;
+const GET_ENTRIES_HINT: u8 = 4;
+const GET_ENTRY_REF: u8 = 5;
+const GET_LOG_LEN: u8 = 6;
+const GET_SUFFIX: u8 = 7;
+const APPROXIMATE_SIZE: u8 = 8;
+const GET_CAPACITY: u8 = 9;
+const GET_DURABLE_IDX: u8 = 10;
+const FLUSH: u8 = 11;
+const VERIFY_ENTRIES: u8 = 12;
+const GET_DECIDED_IDX: u8 = 13;
+const GET_ACCEPTED_ROUND: u8 = 14;
+const GET_PROMISE: u8 = 15;
+const GET_BLE_BALLOT: u8 = 16;
+const GET_STOPSIGN: u8 = 17;
+const GET_COMPACTED_IDX: u8 = 18;
+const GET_SNAPSHOT: u8 = 19;
+const VERIFY_SNAPSHOT: u8 = 20;
+const SET_STANDBY_PROMISE: u8 = 21;
+const GET_STANDBY_PROMISE: u8 = 22;
+
+const APPEND_ENTRY: u8 = 1;
+const APPEND_ENTRIES: u8 = 2;
+const APPEND_ON_PREFIX: u8 = 3;
+const SET_PROMISE: u8 = 4;
+const SET_DECIDED_INDEX: u8 = 5;
+const SET_ACCEPTED_ROUND: u8 = 6;
+const SET_COMPACTED_IDX: u8 = 7;
+const TRIM: u8 = 8;
+const SET_STOPSIGN: u8 = 9;
+const SET_SNAPSHOT: u8 = 10;
+
+const INLINE_PAYLOAD: u8 = 0;
+const HASHED_PAYLOAD: u8 = 1;
+
+/// How the entries and snapshots that are passed to the storage are written to a journal.
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
+pub enum JournalPayloads {
+    /// Encoded with the [`ArchiveCodec`], so that the journal can be replayed.
+    #[default]
+    Inline,
+    /// Only their length and hash, e.g., if the entries are confidential. The journal then shows
+    /// the sequence of calls, but can only be replayed up to the first call that writes entries or
+    /// a snapshot.
+    Hashed,
+}
+
+/// An entry or a snapshot passed to the storage, as written to a journal.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum JournalPayload {
+    /// The bytes encoded by the [`ArchiveCodec`].
+    Inline(Vec<u8>),
+    /// The length and the FNV-1a hash of the bytes encoded by the [`ArchiveCodec`].
+    Hashed {
+        /// The length of the encoded bytes.
+        len: usize,
+        /// The hash of the encoded bytes.
+        digest: u64,
+    },
+}
+
+/// A write of the storage, as written to a journal. Corresponds to a [`StorageOp`] and to the
+/// method of the storage with the same name.
+#[derive(Clone, Debug, PartialEq)]
+#[allow(missing_docs)]
+pub enum JournalOp {
+    AppendEntry(JournalPayload),
+    AppendEntries(Vec<JournalPayload>),
+    AppendOnPrefix(usize, Vec<JournalPayload>),
+    SetPromise(Ballot),
+    SetDecidedIndex(usize),
+    SetAcceptedRound(Ballot),
+    SetCompactedIdx(usize),
+    Trim(usize),
+    SetStopsign(Option<StopSign>),
+    SetSnapshot(Option<JournalPayload>),
+}
+
+/// A call of the storage API, as written to a journal. Corresponds to the method of
+/// [`LogStorage`] or [`StateStorage`] with the same name.
+#[derive(Clone, Debug, PartialEq)]
+#[allow(missing_docs)]
+pub enum JournalCall {
+    WriteAtomically(Vec<JournalOp>),
+    /// One of the writes that are also part of [`StorageOp`], e.g., `append_entries()`.
+    Write(JournalOp),
+    SetBleBallot(Ballot),
+    SetStandbyPromise(Option<Ballot>),
+    GetEntries {
+        from: usize,
+        to: usize,
+    },
+    GetEntriesHint {
+        from: usize,
+        to: usize,
+        max_bytes: usize,
+    },
+    GetEntryRef(usize),
+    GetLogLen,
+    GetSuffix(usize),
+    ApproximateSize,
+    GetCapacity,
+    GetDurableIdx,
+    Flush,
+    VerifyEntries {
+        from: usize,
+        to: usize,
+    },
+    GetDecidedIdx,
+    GetAcceptedRound,
+    GetPromise,
+    GetBleBallot,
+    GetStandbyPromise,
+    GetStopsign,
+    GetCompactedIdx,
+    GetSnapshot,
+    VerifySnapshot,
+}
+
+impl JournalCall {
+    /// Whether the result of the call may legitimately differ between storage implementations,
+    /// e.g., the approximate size. Only the errors of such calls are compared when a journal is
+    /// replayed.
+    pub fn is_backend_specific(&self) -> bool {
+        matches!(
+            self,
+            JournalCall::GetEntriesHint { .. }
+                | JournalCall::GetEntryRef(_)
+                | JournalCall::ApproximateSize
+                | JournalCall::GetCapacity
+                | JournalCall::GetDurableIdx
+        )
+    }
+}
+
+/// What a call of the storage API returned.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct JournalOutcome {
+    /// The error the call failed with, or `None` if it succeeded.
+    pub error: Option<String>,
+    /// The FNV-1a hash of the returned value, encoded as in an archive. 0 if the call failed.
+    pub digest: u64,
+}
+
+/// A call of the storage API and its outcome, as written to a journal.
+#[derive(Clone, Debug, PartialEq)]
+pub struct JournalRecord {
+    /// The position of the call in the journal, starting at 0.
+    pub seq: u64,
+    /// The call with its arguments.
+    pub call: JournalCall,
+    /// What the call returned.
+    pub outcome: JournalOutcome,
+}
+
+/// A call whose outcome differed when the journal was replayed, see [`replay_journal`].
+#[derive(Clone, Debug, PartialEq)]
+pub struct JournalDivergence {
+    /// The journaled call.
+    pub record: JournalRecord,
+    /// What the call returned when it was replayed.
+    pub replayed: JournalOutcome,
+}
+
+/// The result of replaying a journal with [`replay_journal`].
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct JournalReplay {
+    /// The number of replayed calls.
+    pub calls: u64,
+    /// The calls whose outcome differed from the journaled one.
+    pub divergences: Vec<JournalDivergence>,
+}
+
+/// The journal written by a [`JournalingStorage`].
+struct Journal<W> {
+    writer: W,
+    seq: u64,
+    error: Option<ArchiveErr>,
+}
+
+/// A storage that records every call of the storage API on the wrapped `storage`, with its
+/// arguments and outcome, to a journal, e.g., to attach to a bug report of a storage
+/// implementation. The journal can be replayed against a fresh storage with [`replay_journal`],
+/// which reissues the calls and reports every call whose outcome differs.
+///
+/// Journaling never fails the calls of the storage: if the journal can't be written, it stops
+/// and the error is returned by [`journal_error`](Self::journal_error).
+pub struct JournalingStorage<T, S, C, W>
+where
+    T: Entry,
+{
+    storage: S,
+    codec: C,
+    payloads: JournalPayloads,
+    journal: Mutex<Journal<W>>,
+    _t: PhantomData<T>,
+}
+
+impl<T, S, C, W> JournalingStorage<T, S, C, W>
+where
+    T: Entry,
+    S: LogStorage<T> + StateStorage<T>,
+    C: ArchiveCodec<T>,
+    W: Write,
+{
+    /// Wraps `storage` and writes the header of the journal to `writer`. The entries and the
+    /// snapshots are encoded with `codec` and written to the journal as set by `payloads`.
+    pub fn with(
+        storage: S,
+        codec: C,
+        payloads: JournalPayloads,
+        mut writer: W,
+    ) -> Result<Self, ArchiveErr> {
+        writer.write_all(&JOURNAL_MAGIC)?;
+        writer.write_all(&JOURNAL_FORMAT_VERSION.to_le_bytes())?;
+        Ok(Self {
+            storage,
+            codec,
+            payloads,
+            journal: Mutex::new(Journal {
+                writer,
+                seq: 0,
+                error: None,
+            }),
+            _t: PhantomData,
+        })
+    }
+
+    /// Returns the wrapped storage.
+    pub fn get_ref(&self) -> &S {
+        &self.storage
+    }
+
+    /// Returns the error after which the journal stopped, if any.
+    pub fn journal_error(&self) -> Option<String> {
+        let journal = self.journal.lock().expect(LOCK_POISONED_MSG);
+        journal.error.as_ref().map(|e| e.to_string())
+    }
+
+    /// Flushes the journal and returns the wrapped storage and the writer of the journal.
+    pub fn into_inner(self) -> Result<(S, W), ArchiveErr> {
+        let mut journal = self.journal.into_inner().expect(LOCK_POISONED_MSG);
+        journal.writer.flush()?;
+        Ok((self.storage, journal.writer))
+    }
+
+    fn payload(&self, bytes: Vec<u8>) -> JournalPayload {
+        match self.payloads {
+            JournalPayloads::Inline => JournalPayload::Inline(bytes),
+            JournalPayloads::Hashed => JournalPayload::Hashed {
+                len: bytes.len(),
+                digest: digest(&bytes),
+            },
+        }
+    }
+
+    fn entry(&self, entry: &T) -> Result<JournalPayload, ArchiveErr> {
+        Ok(self.payload(encode_entry(&self.codec, entry)?))
+    }
+
+    fn entries(&self, entries: &[T]) -> Result<Vec<JournalPayload>, ArchiveErr> {
+        entries.iter().map(|e| self.entry(e)).collect()
+    }
+
+    fn snapshot(
+        &self,
+        snapshot: &Option<T::Snapshot>,
+    ) -> Result<Option<JournalPayload>, ArchiveErr> {
+        snapshot
+            .as_ref()
+            .map(|s| Ok(self.payload(encode_snapshot(&self.codec, s)?)))
+            .transpose()
+    }
+
+    fn op(&self, op: &StorageOp<T>) -> Result<JournalOp, ArchiveErr> {
+        Ok(match op {
+            StorageOp::AppendEntry(entry) => JournalOp::AppendEntry(self.entry(entry)?),
+            StorageOp::AppendEntries(entries) => JournalOp::AppendEntries(self.entries(entries)?),
+            StorageOp::AppendOnPrefix(from_idx, entries) => {
+                JournalOp::AppendOnPrefix(*from_idx, self.entries(entries)?)
+            }
+            StorageOp::SetPromise(b) => JournalOp::SetPromise(*b),
+            StorageOp::SetDecidedIndex(idx) => JournalOp::SetDecidedIndex(*idx),
+            StorageOp::SetAcceptedRound(b) => JournalOp::SetAcceptedRound(*b),
+            StorageOp::SetCompactedIdx(idx) => JournalOp::SetCompactedIdx(*idx),
+            StorageOp::Trim(idx) => JournalOp::Trim(*idx),
+            StorageOp::SetStopsign(ss) => JournalOp::SetStopsign(ss.clone()),
+            StorageOp::SetSnapshot(s) => JournalOp::SetSnapshot(self.snapshot(s)?),
+        })
+    }
+
+    /// Appends `call` with the outcome `result` to the journal, unless it has stopped.
+    fn record<R, F>(
+        &self,
+        call: Result<JournalCall, ArchiveErr>,
+        result: &StorageResult<R>,
+        value: F,
+    ) where
+        F: FnOnce(&C, &R) -> Result<Encoder, ArchiveErr>,
+    {
+        let mut journal = self.journal.lock().expect(LOCK_POISONED_MSG);
+        if journal.error.is_some() {
+            return;
+        }
+        let written = call.and_then(|call| {
+            let recorded = outcome(&self.codec, result, value)?;
+            let mut record = Encoder::default();
+            record.u64(journal.seq);
+            encode_call(&mut record, &call);
+            encode_outcome(&mut record, &recorded);
+            record.write_to(&mut journal.writer, CALL_RECORD)
+        });
+        match written {
+            Ok(()) => journal.seq += 1,
+            Err(e) => journal.error = Some(e),
+        }
+    }
+}
+
+impl<T, S, C, W> LogStorage<T> for JournalingStorage<T, S, C, W>
+where
+    T: Entry,
+    S: LogStorage<T> + StateStorage<T>,
+    C: ArchiveCodec<T>,
+    W: Write,
+{
+    fn write_atomically(&mut self, ops: Vec<StorageOp<T>>) -> StorageResult<()> {
+        let call = ops
+            .iter()
+            .map(|op| self.op(op))
+            .collect::<Result<_, _>>()
+            .map(JournalCall::WriteAtomically);
+        let result = self.storage.write_atomically(ops);
+        self.record(call, &result, unit);
+        result
+    }
+
+    fn append_entry(&mut self, entry: T) -> StorageResult<()> {
+        let call = self
+            .entry(&entry)
+            .map(|p| JournalCall::Write(JournalOp::AppendEntry(p)));
+        let result = self.storage.append_entry(entry);
+        self.record(call, &result, unit);
+        result
+    }
+
+    fn append_entries(&mut self, entries: Vec<T>) -> StorageResult<()> {
+        let call = self
+            .entries(&entries)
+            .map(|p| JournalCall::Write(JournalOp::AppendEntries(p)));
+        let result = self.storage.append_entries(entries);
+        self.record(call, &result, unit);
+        result
+    }
+
+    fn append_on_prefix(&mut self, from_idx: usize, entries: Vec<T>) -> StorageResult<()> {
+        let call = self
+            .entries(&entries)
+            .map(|p| JournalCall::Write(JournalOp::AppendOnPrefix(from_idx, p)));
+        let result = self.storage.append_on_prefix(from_idx, entries);
+        self.record(call, &result, unit);
+        result
+    }
+
+    fn get_entries(&self, from: usize, to: usize) -> StorageResult<Vec<T>> {
+        let result = self.storage.get_entries(from, to);
+        self.record(Ok(JournalCall::GetEntries { from, to }), &result, entries);
+        result
+    }
+
+    fn get_entries_hint(
+        &self,
+        from: usize,
+        to: usize,
+        max_bytes: usize,
+    ) -> StorageResult<EntriesHint<T>> {
+        let result = self.storage.get_entries_hint(from, to, max_bytes);
+        let call = JournalCall::GetEntriesHint {
+            from,
+            to,
+            max_bytes,
+        };
+        self.record(Ok(call), &result, entries_hint);
+        result
+    }
+
+    fn get_entry_ref(&self, idx: usize) -> StorageResult<Option<&T>> {
+        let result = self.storage.get_entry_ref(idx);
+        self.record(
+            Ok(JournalCall::GetEntryRef(idx)),
+            &result,
+            |codec, entry| optional_entry(codec, *entry),
+        );
+        result
+    }
+
+    fn get_log_len(&self) -> StorageResult<usize> {
+        let result = self.storage.get_log_len();
+        self.record(Ok(JournalCall::GetLogLen), &result, index);
+        result
+    }
+
+    fn get_suffix(&self, from: usize) -> StorageResult<Vec<T>> {
+        let result = self.storage.get_suffix(from);
+        self.record(Ok(JournalCall::GetSuffix(from)), &result, entries);
+        result
+    }
+
+    fn trim(&mut self, idx: usize) -> StorageResult<()> {
+        let result = self.storage.trim(idx);
+        self.record(Ok(JournalCall::Write(JournalOp::Trim(idx))), &result, unit);
+        result
+    }
+
+    fn approximate_size(&self) -> StorageResult<Option<u64>> {
+        let result = self.storage.approximate_size();
+        self.record(Ok(JournalCall::ApproximateSize), &result, |_, size| {
+            Ok(optional_u64(size.as_ref().copied()))
+        });
+        result
+    }
+
+    fn get_capacity(&self) -> StorageResult<Option<usize>> {
+        let result = self.storage.get_capacity();
+        self.record(Ok(JournalCall::GetCapacity), &result, optional_index);
+        result
+    }
+
+    fn get_durable_idx(&self) -> StorageResult<Option<usize>> {
+        let result = self.storage.get_durable_idx();
+        self.record(Ok(JournalCall::GetDurableIdx), &result, optional_index);
+        result
+    }
+
+    fn flush(&self) -> StorageResult<()> {
+        let result = self.storage.flush();
+        self.record(Ok(JournalCall::Flush), &result, unit);
+        result
+    }
+
+    fn verify_entries(&self, from: usize, to: usize) -> StorageResult<()> {
+        let result = self.storage.verify_entries(from, to);
+        self.record(Ok(JournalCall::VerifyEntries { from, to }), &result, unit);
+        result
+    }
+}
+
+impl<T, S, C, W> StateStorage<T> for JournalingStorage<T, S, C, W>
+where
+    T: Entry,
+    S: LogStorage<T> + StateStorage<T>,
+    C: ArchiveCodec<T>,
+    W: Write,
+{
+    fn set_promise(&mut self, n_prom: Ballot) -> StorageResult<()> {
+        let result = self.storage.set_promise(n_prom);
+        let call = JournalCall::Write(JournalOp::SetPromise(n_prom));
+        self.record(Ok(call), &result, unit);
+        result
+    }
+
+    fn set_decided_idx(&mut self, ld: usize) -> StorageResult<()> {
+        let result = self.storage.set_decided_idx(ld);
+        let call = JournalCall::Write(JournalOp::SetDecidedIndex(ld));
+        self.record(Ok(call), &result, unit);
+        result
+    }
+
+    fn get_decided_idx(&self) -> StorageResult<usize> {
+        let result = self.storage.get_decided_idx();
+        self.record(Ok(JournalCall::GetDecidedIdx), &result, index);
+        result
+    }
+
+    fn set_accepted_round(&mut self, na: Ballot) -> StorageResult<()> {
+        let result = self.storage.set_accepted_round(na);
+        let call = JournalCall::Write(JournalOp::SetAcceptedRound(na));
+        self.record(Ok(call), &result, unit);
+        result
+    }
+
+    fn get_accepted_round(&self) -> StorageResult<Option<Ballot>> {
+        let result = self.storage.get_accepted_round();
+        self.record(Ok(JournalCall::GetAcceptedRound), &result, ballot);
+        result
+    }
+
+    fn get_promise(&self) -> StorageResult<Option<Ballot>> {
+        let result = self.storage.get_promise();
+        self.record(Ok(JournalCall::GetPromise), &result, ballot);
+        result
+    }
+
+    fn set_ble_ballot(&mut self, b: Ballot) -> StorageResult<()> {
+        let result = self.storage.set_ble_ballot(b);
+        self.record(Ok(JournalCall::SetBleBallot(b)), &result, unit);
+        result
+    }
+
+    fn get_ble_ballot(&self) -> StorageResult<Option<Ballot>> {
+        let result = self.storage.get_ble_ballot();
+        self.record(Ok(JournalCall::GetBleBallot), &result, ballot);
+        result
+    }
+
+    fn set_standby_promise(&mut self, standby: Option<Ballot>) -> StorageResult<()> {
+        let result = self.storage.set_standby_promise(standby);
+        self.record(Ok(JournalCall::SetStandbyPromise(standby)), &result, unit);
+        result
+    }
+
+    fn get_standby_promise(&self) -> StorageResult<Option<Ballot>> {
+        let result = self.storage.get_standby_promise();
+        self.record(Ok(JournalCall::GetStandbyPromise), &result, ballot);
+        result
+    }
+
+    fn set_stopsign(&mut self, s: Option<StopSign>) -> StorageResult<()> {
+        let call = JournalCall::Write(JournalOp::SetStopsign(s.clone()));
+        let result = self.storage.set_stopsign(s);
+        self.record(Ok(call), &result, unit);
+        result
+    }
+
+    fn get_stopsign(&self) -> StorageResult<Option<StopSign>> {
+        let result = self.storage.get_stopsign();
+        self.record(Ok(JournalCall::GetStopsign), &result, |_, ss| {
+            Ok(optional_stopsign(ss))
+        });
+        result
+    }
+
+    fn set_compacted_idx(&mut self, idx: usize) -> StorageResult<()> {
+        let result = self.storage.set_compacted_idx(idx);
+        let call = JournalCall::Write(JournalOp::SetCompactedIdx(idx));
+        self.record(Ok(call), &result, unit);
+        result
+    }
+
+    fn get_compacted_idx(&self) -> StorageResult<usize> {
+        let result = self.storage.get_compacted_idx();
+        self.record(Ok(JournalCall::GetCompactedIdx), &result, index);
+        result
+    }
+
+    fn set_snapshot(&mut self, snapshot: Option<T::Snapshot>) -> StorageResult<()> {
+        let call = self
+            .snapshot(&snapshot)
+            .map(|p| JournalCall::Write(JournalOp::SetSnapshot(p)));
+        let result = self.storage.set_snapshot(snapshot);
+        self.record(call, &result, unit);
+        result
+    }
+
+    fn get_snapshot(&self) -> StorageResult<Option<T::Snapshot>> {
+        let result = self.storage.get_snapshot();
+        self.record(
+            Ok(JournalCall::GetSnapshot),
+            &result,
+            optional_snapshot::<T, C>,
+        );
+        result
+    }
+
+    fn verify_snapshot(&self) -> StorageResult<()> {
+        let result = self.storage.verify_snapshot();
+        self.record(Ok(JournalCall::VerifySnapshot), &result, unit);
+        result
+    }
+}
+
+/// Reads the records of a journal written by a [`JournalingStorage`]. It only depends on the
+/// format, not on the type of the entries. A journal that ends after a complete record is read
+/// until there, since the journal of a crashed process has no end marker.
+pub struct JournalReader<R: Read> {
+    reader: R,
+    done: bool,
+}
+
+impl<R: Read> JournalReader<R> {
+    /// Reads the header of the journal from `reader`. Fails if `reader` does not contain a journal
+    /// or if the journal was written with a newer version of the format.
+    pub fn new(mut reader: R) -> Result<Self, ArchiveErr> {
+        let mut magic = [0; 4];
+        read_exact(&mut reader, &mut magic)?;
+        if magic != JOURNAL_MAGIC {
+            return Err(ArchiveErr::InvalidMagic);
+        }
+        let mut version = [0; 2];
+        read_exact(&mut reader, &mut version)?;
+        let version = u16::from_le_bytes(version);
+        if version == 0 || version > JOURNAL_FORMAT_VERSION {
+            return Err(ArchiveErr::UnsupportedVersion(version));
+        }
+        Ok(Self {
+            reader,
+            done: false,
+        })
+    }
+
+    /// Reads the next record. Returns `None` at the end of the journal. Records of kinds that are
+    /// unknown to this version are skipped.
+    pub fn next_record(&mut self) -> Result<Option<JournalRecord>, ArchiveErr> {
+        while !self.done {
+            let mut kind = [0; 1];
+            match self.reader.read(&mut kind) {
+                Ok(0) => {
+                    self.done = true;
+                    continue;
+                }
+                Ok(_) => {}
+                Err(e) if e.kind() == ErrorKind::Interrupted => continue,
+                Err(e) => return Err(ArchiveErr::Io(e)),
+            }
+            let mut len = [0; 4];
+            read_exact(&mut self.reader, &mut len)?;
+            let mut payload = vec![0; u32::from_le_bytes(len) as usize];
+            read_exact(&mut self.reader, &mut payload)?;
+            if kind[0] != CALL_RECORD {
+                continue;
+            }
+            let mut decoder = Decoder(&payload);
+            let seq = decoder.u64()?;
+            let call = decode_call(&mut decoder)?;
+            let outcome = decode_outcome(&mut decoder)?;
+            return Ok(Some(JournalRecord { seq, call, outcome }));
+        }
+        Ok(None)
+    }
+}
+
+impl<R: Read> Iterator for JournalReader<R> {
+    type Item = Result<JournalRecord, ArchiveErr>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        match self.next_record() {
+            Ok(record) => record.map(Ok),
+            Err(e) => {
+                self.done = true;
+                Some(Err(e))
+            }
+        }
+    }
+}
+
+/// Reissues the calls of the journal read from `reader` on `storage`, which should be in the same
+/// state as the journaled storage was when the journal started, e.g., empty. The entries and the
+/// snapshots are decoded with `codec`. Returns every call whose outcome differs from the journaled
+/// one: a call that fails on only one of the storages, or returns a different value. Only the
+/// errors are compared for calls that are [backend specific](JournalCall::is_backend_specific).
+pub fn replay_journal<T, S, C, R>(
+    reader: R,
+    codec: &C,
+    storage: &mut S,
+) -> Result<JournalReplay, ArchiveErr>
+where
+    T: Entry,
+    S: LogStorage<T> + StateStorage<T>,
+    C: ArchiveCodec<T>,
+    R: Read,
+{
+    let mut replay = JournalReplay::default();
+    for record in JournalReader::new(reader)? {
+        let record = record?;
+        let replayed = replay_call(&record, codec, storage)?;
+        let diverged = match (&record.outcome.error, &replayed.error) {
+            (None, None) => {
+                !record.call.is_backend_specific() && record.outcome.digest != replayed.digest
+            }
+            (journaled, replayed) => journaled.is_some() != replayed.is_some(),
+        };
+        if diverged {
+            replay
+                .divergences
+                .push(JournalDivergence { record, replayed });
+        }
+        replay.calls += 1;
+    }
+    Ok(replay)
+}
+
+fn replay_call<T, S, C>(
+    record: &JournalRecord,
+    codec: &C,
+    storage: &mut S,
+) -> Result<JournalOutcome, ArchiveErr>
+where
+    T: Entry,
+    S: LogStorage<T> + StateStorage<T>,
+    C: ArchiveCodec<T>,
+{
+    let seq = record.seq;
+    match &record.call {
+        JournalCall::WriteAtomically(ops) => {
+            let ops = ops
+                .iter()
+                .map(|op| storage_op(codec, op, seq))
+                .collect::<Result<_, _>>()?;
+            outcome(codec, &storage.write_atomically(ops), unit)
+        }
+        JournalCall::Write(op) => {
+            let result = match storage_op(codec, op, seq)? {
+                StorageOp::AppendEntry(entry) => storage.append_entry(entry),
+                StorageOp::AppendEntries(entries) => storage.append_entries(entries),
+                StorageOp::AppendOnPrefix(from_idx, entries) => {
+                    storage.append_on_prefix(from_idx, entries)
+                }
+                StorageOp::SetPromise(b) => storage.set_promise(b),
+                StorageOp::SetDecidedIndex(idx) => storage.set_decided_idx(idx),
+                StorageOp::SetAcceptedRound(b) => storage.set_accepted_round(b),
+                StorageOp::SetCompactedIdx(idx) => storage.set_compacted_idx(idx),
+                StorageOp::Trim(idx) => storage.trim(idx),
+                StorageOp::SetStopsign(ss) => storage.set_stopsign(ss),
+                StorageOp::SetSnapshot(s) => storage.set_snapshot(s),
+            };
+            outcome(codec, &result, unit)
+        }
+        JournalCall::SetBleBallot(b) => outcome(codec, &storage.set_ble_ballot(*b), unit),
+        JournalCall::SetStandbyPromise(b) => outcome(codec, &storage.set_standby_promise(*b), unit),
+        JournalCall::GetEntries { from, to } => {
+            outcome(codec, &storage.get_entries(*from, *to), entries)
+        }
+        JournalCall::GetEntriesHint {
+            from,
+            to,
+            max_bytes,
+        } => outcome(
+            codec,
+            &storage.get_entries_hint(*from, *to, *max_bytes),
+            entries_hint,
+        ),
+        JournalCall::GetEntryRef(idx) => {
+            outcome(codec, &storage.get_entry_ref(*idx), |codec, entry| {
+                optional_entry(codec, *entry)
+            })
+        }
+        JournalCall::GetLogLen => outcome(codec, &storage.get_log_len(), index),
+        JournalCall::GetSuffix(from) => outcome(codec, &storage.get_suffix(*from), entries),
+        JournalCall::ApproximateSize => outcome(codec, &storage.approximate_size(), |_, size| {
+            Ok(optional_u64(size.as_ref().copied()))
+        }),
+        JournalCall::GetCapacity => outcome(codec, &storage.get_capacity(), optional_index),
+        JournalCall::GetDurableIdx => outcome(codec, &storage.get_durable_idx(), optional_index),
+        JournalCall::Flush => outcome(codec, &storage.flush(), unit),
+        JournalCall::VerifyEntries { from, to } => {
+            outcome(codec, &storage.verify_entries(*from, *to), unit)
+        }
+        JournalCall::GetDecidedIdx => outcome(codec, &storage.get_decided_idx(), index),
+        JournalCall::GetAcceptedRound => outcome(codec, &storage.get_accepted_round(), ballot),
+        JournalCall::GetPromise => outcome(codec, &storage.get_promise(), ballot),
+        JournalCall::GetBleBallot => outcome(codec, &storage.get_ble_ballot(), ballot),
+        JournalCall::GetStandbyPromise => outcome(codec, &storage.get_standby_promise(), ballot),
+        JournalCall::GetStopsign => outcome(codec, &storage.get_stopsign(), |_, ss| {
+            Ok(optional_stopsign(ss))
+        }),
+        JournalCall::GetCompactedIdx => outcome(codec, &storage.get_compacted_idx(), index),
+        JournalCall::GetSnapshot => {
+            outcome(codec, &storage.get_snapshot(), optional_snapshot::<T, C>)
+        }
+        JournalCall::VerifySnapshot => outcome(codec, &storage.verify_snapshot(), unit),
+    }
+}
+
+/// Decodes a journaled write into the `StorageOp` to reissue. Fails if its payloads are hashed.
+fn storage_op<T: Entry, C: ArchiveCodec<T>>(
+    codec: &C,
+    op: &JournalOp,
+    seq: u64,
+) -> Result<StorageOp<T>, ArchiveErr> {
+    let entry = |p: &JournalPayload| match p {
+        JournalPayload::Inline(bytes) => {
+            codec
+                .decode_entry(bytes)
+                .map_err(|source| ArchiveErr::Codec {
+                    what: "entry",
+                    source,
+                })
+        }
+        JournalPayload::Hashed { .. } => Err(ArchiveErr::HashedPayload(seq)),
+    };
+    let entries = |ps: &[JournalPayload]| ps.iter().map(entry).collect::<Result<Vec<_>, _>>();
+    Ok(match op {
+        JournalOp::AppendEntry(p) => StorageOp::AppendEntry(entry(p)?),
+        JournalOp::AppendEntries(ps) => StorageOp::AppendEntries(entries(ps)?),
+        JournalOp::AppendOnPrefix(from_idx, ps) => {
+            StorageOp::AppendOnPrefix(*from_idx, entries(ps)?)
+        }
+        JournalOp::SetPromise(b) => StorageOp::SetPromise(*b),
+        JournalOp::SetDecidedIndex(idx) => StorageOp::SetDecidedIndex(*idx),
+        JournalOp::SetAcceptedRound(b) => StorageOp::SetAcceptedRound(*b),
+        JournalOp::SetCompactedIdx(idx) => StorageOp::SetCompactedIdx(*idx),
+        JournalOp::Trim(idx) => StorageOp::Trim(*idx),
+        JournalOp::SetStopsign(ss) => StorageOp::SetStopsign(ss.clone()),
+        JournalOp::SetSnapshot(None) => StorageOp::SetSnapshot(None),
+        JournalOp::SetSnapshot(Some(JournalPayload::Inline(bytes))) => {
+            let snapshot = codec
+                .decode_snapshot(bytes)
+                .map_err(|source| ArchiveErr::Codec {
+                    what: "snapshot",
+                    source,
+                })?;
+            StorageOp::SetSnapshot(Some(snapshot))
+        }
+        JournalOp::SetSnapshot(Some(JournalPayload::Hashed { .. })) => {
+            return Err(ArchiveErr::HashedPayload(seq))
+        }
+    })
+}
+
+/// The outcome of a call that returned `result`, with the returned value encoded by `value`.
+fn outcome<C, R, F>(
+    codec: &C,
+    result: &StorageResult<R>,
+    value: F,
+) -> Result<JournalOutcome, ArchiveErr>
+where
+    F: FnOnce(&C, &R) -> Result<Encoder, ArchiveErr>,
+{
+    Ok(match result {
+        Ok(v) => JournalOutcome {
+            error: None,
+            digest: digest(&value(codec, v)?.0),
+        },
+        Err(e) => JournalOutcome {
+            error: Some(e.to_string()),
+            digest: 0,
+        },
+    })
+}
+
+fn unit<C>(_: &C, _: &()) -> Result<Encoder, ArchiveErr> {
+    Ok(Encoder::default())
+}
+
+fn index<C>(_: &C, idx: &usize) -> Result<Encoder, ArchiveErr> {
+    let mut e = Encoder::default();
+    e.usize(*idx);
+    Ok(e)
+}
+
+fn optional_index<C>(_: &C, idx: &Option<usize>) -> Result<Encoder, ArchiveErr> {
+    Ok(optional_u64(idx.map(|i| i as u64)))
+}
+
+fn optional_u64(v: Option<u64>) -> Encoder {
+    let mut e = Encoder::default();
+    e.bool(v.is_some());
+    e.u64(v.unwrap_or_default());
+    e
+}
+
+fn ballot<C>(_: &C, b: &Option<Ballot>) -> Result<Encoder, ArchiveErr> {
+    let mut e = Encoder::default();
+    e.ballot(*b);
+    Ok(e)
+}
+
+fn optional_stopsign(ss: &Option<StopSign>) -> Encoder {
+    let mut e = Encoder::default();
+    encode_stopsign(&mut e, ss);
+    e
+}
+
+#[allow(clippy::ptr_arg)]
+fn entries<T: Entry, C: ArchiveCodec<T>>(
+    codec: &C,
+    entries: &Vec<T>,
+) -> Result<Encoder, ArchiveErr> {
+    let mut e = Encoder::default();
+    e.u32(entries.len() as u32);
+    for entry in entries {
+        e.bytes(&encode_entry(codec, entry)?);
+    }
+    Ok(e)
+}
+
+fn entries_hint<T: Entry, C: ArchiveCodec<T>>(
+    codec: &C,
+    hint: &EntriesHint<T>,
+) -> Result<Encoder, ArchiveErr> {
+    let mut e = entries(codec, &hint.entries)?;
+    e.bool(hint.continuation.is_some());
+    e.usize(hint.continuation.unwrap_or_default());
+    Ok(e)
+}
+
+fn optional_entry<T: Entry, C: ArchiveCodec<T>>(
+    codec: &C,
+    entry: Option<&T>,
+) -> Result<Encoder, ArchiveErr> {
+    let mut e = Encoder::default();
+    e.bool(entry.is_some());
+    if let Some(entry) = entry {
+        e.bytes(&encode_entry(codec, entry)?);
+    }
+    Ok(e)
+}
+
+fn optional_snapshot<T: Entry, C: ArchiveCodec<T>>(
+    codec: &C,
+    snapshot: &Option<T::Snapshot>,
+) -> Result<Encoder, ArchiveErr> {
+    let mut e = Encoder::default();
+    e.bool(snapshot.is_some());
+    if let Some(s) = snapshot {
+        e.bytes(&encode_snapshot(codec, s)?);
+    }
+    Ok(e)
+}
+
+fn encode_entry<T: Entry, C: ArchiveCodec<T>>(codec: &C, entry: &T) -> Result<Vec<u8>, ArchiveErr> {
+    codec
+        .encode_entry(entry)
+        .map_err(|source| ArchiveErr::Codec {
+            what: "entry",
+            source,
+        })
+}
+
+fn encode_snapshot<T: Entry, C: ArchiveCodec<T>>(
+    codec: &C,
+    snapshot: &T::Snapshot,
+) -> Result<Vec<u8>, ArchiveErr> {
+    codec
+        .encode_snapshot(snapshot)
+        .map_err(|source| ArchiveErr::Codec {
+            what: "snapshot",
+            source,
+        })
+}
+
+fn encode_call(e: &mut Encoder, call: &JournalCall) {
+    match call {
+        JournalCall::WriteAtomically(ops) => {
+            e.u8(WRITE_ATOMICALLY);
+            e.u32(ops.len() as u32);
+            for op in ops {
+                encode_op(e, op);
+            }
+        }
+        JournalCall::Write(op) => {
+            e.u8(WRITE);
+            encode_op(e, op);
+        }
+        JournalCall::SetBleBallot(b) => {
+            e.u8(SET_BLE_BALLOT);
+            e.ballot(Some(*b));
+        }
+        JournalCall::SetStandbyPromise(b) => {
+            e.u8(SET_STANDBY_PROMISE);
+            e.ballot(*b);
+        }
+        JournalCall::GetEntries { from, to } => {
+            e.u8(GET_ENTRIES);
+            e.usize(*from);
+            e.usize(*to);
+        }
+        JournalCall::GetEntriesHint {
+            from,
+            to,
+            max_bytes,
+        } => {
+            e.u8(GET_ENTRIES_HINT);
+            e.usize(*from);
+            e.usize(*to);
+            e.usize(*max_bytes);
+        }
+        JournalCall::GetEntryRef(idx) => {
+            e.u8(GET_ENTRY_REF);
+            e.usize(*idx);
+        }
+        JournalCall::GetLogLen => e.u8(GET_LOG_LEN),
+        JournalCall::GetSuffix(from) => {
+            e.u8(GET_SUFFIX);
+            e.usize(*from);
+        }
+        JournalCall::ApproximateSize => e.u8(APPROXIMATE_SIZE),
+        JournalCall::GetCapacity => e.u8(GET_CAPACITY),
+        JournalCall::GetDurableIdx => e.u8(GET_DURABLE_IDX),
+        JournalCall::Flush => e.u8(FLUSH),
+        JournalCall::VerifyEntries { from, to } => {
+            e.u8(VERIFY_ENTRIES);
+            e.usize(*from);
+            e.usize(*to);
+        }
+        JournalCall::GetDecidedIdx => e.u8(GET_DECIDED_IDX),
+        JournalCall::GetAcceptedRound => e.u8(GET_ACCEPTED_ROUND),
+        JournalCall::GetPromise => e.u8(GET_PROMISE),
+        JournalCall::GetBleBallot => e.u8(GET_BLE_BALLOT),
+        JournalCall::GetStandbyPromise => e.u8(GET_STANDBY_PROMISE),
+        JournalCall::GetStopsign => e.u8(GET_STOPSIGN),
+        JournalCall::GetCompactedIdx => e.u8(GET_COMPACTED_IDX),
+        JournalCall::GetSnapshot => e.u8(GET_SNAPSHOT),
+        JournalCall::VerifySnapshot => e.u8(VERIFY_SNAPSHOT),
+    }
+}
+
+fn encode_op(e: &mut Encoder, op: &JournalOp) {
+    match op {
+        JournalOp::AppendEntry(p) => {
+            e.u8(APPEND_ENTRY);
+            encode_payload(e, p);
+        }
+        JournalOp::AppendEntries(ps) => {
+            e.u8(APPEND_ENTRIES);
+            encode_payloads(e, ps);
+        }
+        JournalOp::AppendOnPrefix(from_idx, ps) => {
+            e.u8(APPEND_ON_PREFIX);
+            e.usize(*from_idx);
+            encode_payloads(e, ps);
+        }
+        JournalOp::SetPromise(b) => {
+            e.u8(SET_PROMISE);
+            e.ballot(Some(*b));
+        }
+        JournalOp::SetDecidedIndex(idx) => {
+            e.u8(SET_DECIDED_INDEX);
+            e.usize(*idx);
+        }
+        JournalOp::SetAcceptedRound(b) => {
+            e.u8(SET_ACCEPTED_ROUND);
+            e.ballot(Some(*b));
+        }
+        JournalOp::SetCompactedIdx(idx) => {
+            e.u8(SET_COMPACTED_IDX);
+            e.usize(*idx);
+        }
+        JournalOp::Trim(idx) => {
+            e.u8(TRIM);
+            e.usize(*idx);
+        }
+        JournalOp::SetStopsign(ss) => {
+            e.u8(SET_STOPSIGN);
+            encode_stopsign(e, ss);
+        }
+        JournalOp::SetSnapshot(s) => {
+            e.u8(SET_SNAPSHOT);
+            e.bool(s.is_some());
+            if let Some(p) = s {
+                encode_payload(e, p);
+            }
+        }
+    }
+}
+
+fn encode_payloads(e: &mut Encoder, ps: &[JournalPayload]) {
+    e.u32(ps.len() as u32);
+    for p in ps {
+        encode_payload(e, p);
+    }
+}
+
+fn encode_payload(e: &mut Encoder, p: &JournalPayload) {
+    match p {
+        JournalPayload::Inline(bytes) => {
+            e.u8(INLINE_PAYLOAD);
+            e.bytes(bytes);
+        }
+        JournalPayload::Hashed { len, digest } => {
+            e.u8(HASHED_PAYLOAD);
+            e.usize(*len);
+            e.u64(*digest);
+        }
+    }
+}
+
+/// Writes an optional StopSign as bytes, as its encoding in an archive extends to the end of the
+/// record.
+fn encode_stopsign(e: &mut Encoder, ss: &Option<StopSign>) {
+    e.bool(ss.is_some());
+    let mut stopsign = Encoder::default();
+    if let Some(ss) = ss {
+        stopsign.stopsign(ss);
+    }
+    e.bytes(&stopsign.0);
+}
+
+fn encode_outcome(e: &mut Encoder, outcome: &JournalOutcome) {
+    e.bool(outcome.error.is_some());
+    e.bytes(outcome.error.as_deref().unwrap_or_default().as_bytes());
+    e.u64(outcome.digest);
+}
+
+fn decode_call(d: &mut Decoder) -> Result<JournalCall, ArchiveErr> {
+    Ok(match d.u8()? {
+        WRITE_ATOMICALLY => {
+            let len = d.u32()?;
+            JournalCall::WriteAtomically((0..len).map(|_| decode_op(d)).collect::<Result<_, _>>()?)
+        }
+        WRITE => JournalCall::Write(decode_op(d)?),
+        SET_BLE_BALLOT => JournalCall::SetBleBallot(decode_ballot(d)?),
+        SET_STANDBY_PROMISE => JournalCall::SetStandbyPromise(d.ballot()?),
+        GET_ENTRIES => JournalCall::GetEntries {
+            from: d.usize()?,
+            to: d.usize()?,
+        },
+        GET_ENTRIES_HINT => JournalCall::GetEntriesHint {
+            from: d.usize()?,
+            to: d.usize()?,
+            max_bytes: d.usize()?,
+        },
+        GET_ENTRY_REF => JournalCall::GetEntryRef(d.usize()?),
+        GET_LOG_LEN => JournalCall::GetLogLen,
+        GET_SUFFIX => JournalCall::GetSuffix(d.usize()?),
+        APPROXIMATE_SIZE => JournalCall::ApproximateSize,
+        GET_CAPACITY => JournalCall::GetCapacity,
+        GET_DURABLE_IDX => JournalCall::GetDurableIdx,
+        FLUSH => JournalCall::Flush,
+        VERIFY_ENTRIES => JournalCall::VerifyEntries {
+            from: d.usize()?,
+            to: d.usize()?,
+        },
+        GET_DECIDED_IDX => JournalCall::GetDecidedIdx,
+        GET_ACCEPTED_ROUND => JournalCall::GetAcceptedRound,
+        GET_PROMISE => JournalCall::GetPromise,
+        GET_BLE_BALLOT => JournalCall::GetBleBallot,
+        GET_STANDBY_PROMISE => JournalCall::GetStandbyPromise,
+        GET_STOPSIGN => JournalCall::GetStopsign,
+        GET_COMPACTED_IDX => JournalCall::GetCompactedIdx,
+        GET_SNAPSHOT => JournalCall::GetSnapshot,
+        VERIFY_SNAPSHOT => JournalCall::VerifySnapshot,
+        code => {
+            return Err(ArchiveErr::Corrupt(format!(
+                "unknown storage call {}",
+                code
+            )))
+        }
+    })
+}
+
+fn decode_op(d: &mut Decoder) -> Result<JournalOp, ArchiveErr> {
+    Ok(match d.u8()? {
+        APPEND_ENTRY => JournalOp::AppendEntry(decode_payload(d)?),
+        APPEND_ENTRIES => JournalOp::AppendEntries(decode_payloads(d)?),
+        APPEND_ON_PREFIX => JournalOp::AppendOnPrefix(d.usize()?, decode_payloads(d)?),
+        SET_PROMISE => JournalOp::SetPromise(decode_ballot(d)?),
+        SET_DECIDED_INDEX => JournalOp::SetDecidedIndex(d.usize()?),
+        SET_ACCEPTED_ROUND => JournalOp::SetAcceptedRound(decode_ballot(d)?),
+        SET_COMPACTED_IDX => JournalOp::SetCompactedIdx(d.usize()?),
+        TRIM => JournalOp::Trim(d.usize()?),
+        SET_STOPSIGN => JournalOp::SetStopsign(decode_stopsign(d)?),
+        SET_SNAPSHOT => match d.bool()? {
+            true => JournalOp::SetSnapshot(Some(decode_payload(d)?)),
+            false => JournalOp::SetSnapshot(None),
+        },
+        code => return Err(ArchiveErr::Corrupt(format!("unknown storage op {}", code))),
+    })
+}
+
+fn decode_payloads(d: &mut Decoder) -> Result<Vec<JournalPayload>, ArchiveErr> {
+    let len = d.u32()?;
+    (0..len).map(|_| decode_payload(d)).collect()
+}
+
+fn decode_payload(d: &mut Decoder) -> Result<JournalPayload, ArchiveErr> {
+    match d.u8()? {
+        INLINE_PAYLOAD => Ok(JournalPayload::Inline(d.bytes()?)),
+        HASHED_PAYLOAD => Ok(JournalPayload::Hashed {
+            len: d.usize()?,
+            digest: d.u64()?,
+        }),
+        tag => Err(ArchiveErr::Corrupt(format!("unknown payload {}", tag))),
+    }
+}
+
+fn decode_ballot(d: &mut Decoder) -> Result<Ballot, ArchiveErr> {
+    d.ballot()?
+        .ok_or_else(|| ArchiveErr::Corrupt("missing ballot".to_string()))
+}
+
+fn decode_stopsign(d: &mut Decoder) -> Result<Option<StopSign>, ArchiveErr> {
+    let is_some = d.bool()?;
+    let bytes = d.bytes()?;
+    match is_some {
+        true => Ok(Some(Decoder(&bytes).stopsign()?)),
+        false => Ok(None),
+    }
+}
+
+fn decode_outcome(d: &mut Decoder) -> Result<JournalOutcome, ArchiveErr> {
+    let failed = d.bool()?;
+    let error = String::from_utf8(d.bytes()?)
+        .map_err(|_| ArchiveErr::Corrupt("error message is not UTF-8".to_string()))?;
+    Ok(JournalOutcome {
+        error: failed.then_some(error),
+        digest: d.u64()?,
+    })
+}
+
+/// FNV-1a, as the hashes must be the same regardless of the Rust version.
+fn digest(bytes: &[u8]) -> u64 {
+    bytes.iter().fold(0xcbf29ce484222325, |hash, b| {
+        (hash ^ *b as u64).wrapping_mul(0x100000001b3)
+    })
+}
@@ -0,0 +1,73 @@
+use crate::util::LOCK_POISONED_MSG;
+use std::{
+    collections::BTreeMap,
+    fmt,
+    sync::{Arc, Mutex},
+};
+
+/// The indexes of the log that are pinned by the alive [`PinGuard`]s, with the number of guards
+/// per index.
+#[derive(Debug, Default)]
+pub(crate) struct LogPins {
+    pins: Mutex<BTreeMap<usize, usize>>,
+}
+
+impl LogPins {
+    pub(crate) fn pin(self: &Arc<Self>, idx: usize) -> PinGuard {
+        let mut pins = self.pins.lock().expect(LOCK_POISONED_MSG);
+        *pins.entry(idx).or_insert(0) += 1;
+        PinGuard {
+            pins: Arc::clone(self),
+            idx,
+        }
+    }
+
+    /// Returns the lowest pinned index, if any.
+    pub(crate) fn min_pinned_idx(&self) -> Option<usize> {
+        let pins = self.pins.lock().expect(LOCK_POISONED_MSG);
+        pins.keys().next().copied()
+    }
+
+    fn unpin(&self, idx: usize) {
+        let mut pins = self.pins.lock().expect(LOCK_POISONED_MSG);
+        if let Some(count) = pins.get_mut(&idx) {
+            *count -= 1;
+            if *count == 0 {
+                pins.remove(&idx);
+            }
+        }
+    }
+}
+
+/// A retention hold on the log of an [`OmniPaxos`](crate::OmniPaxos) server, created with
+/// [`OmniPaxos::pin_log`](crate::OmniPaxos::pin_log). While the guard is alive, the server
+/// neither trims nor snapshots its log past the pinned index, so that, e.g., a backup job or a
+/// slow consumer can read the log from the pinned index on. Compacting past it fails with
+/// [`CompactionErr::Pinned`](crate::CompactionErr::Pinned). The hold is released when the guard
+/// is dropped. The guard can be moved to other threads.
+///
+/// Pins only hold back the compaction of this server. A follower that is synchronized with a
+/// snapshot of the leader, because it is too far behind, still replaces its log with it.
+pub struct PinGuard {
+    pins: Arc<LogPins>,
+    idx: usize,
+}
+
+impl PinGuard {
+    /// Returns the pinned index.
+    pub fn pinned_idx(&self) -> usize {
+        self.idx
+    }
+}
+
+impl Drop for PinGuard {
+    fn drop(&mut self) {
+        self.pins.unpin(self.idx);
+    }
+}
+
+impl fmt::Debug for PinGuard {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("PinGuard").field("idx", &self.idx).finish()
+    }
+}
@@ -0,0 +1,110 @@
+use super::Entry;
+use std::collections::VecDeque;
+
+/// A bounded in-memory copy of the tail of the log. It is filled as entries are appended, so it
+/// also holds accepted entries that are not decided yet, and it is truncated together with the log
+/// when a new leader overwrites them. Since applications typically poll the decided suffix on every
+/// tick, recently decided entries are served from here instead of re-reading the same entries from
+/// the storage back-end.
+pub(super) struct LogTailCache<T>
+where
+    T: Entry,
+{
+    /// The maximum number of entries to keep. A capacity of 0 disables the cache.
+    capacity: usize,
+    /// The log index of the first entry in `entries`.
+    start_idx: usize,
+    /// The cached entries, contiguous in the log starting from `start_idx`.
+    entries: VecDeque<T>,
+}
+
+impl<T> LogTailCache<T>
+where
+    T: Entry,
+{
+    pub(super) fn with(capacity: usize, end_idx: usize) -> Self {
+        LogTailCache {
+            capacity,
+            start_idx: end_idx,
+            entries: VecDeque::with_capacity(capacity),
+        }
+    }
+
+    /// The log index after the last cached entry.
+    fn end_idx(&self) -> usize {
+        self.start_idx + self.entries.len()
+    }
+
+    // Adds entries that were appended to the end of the log at `from_idx`. If the entries are not
+    // contiguous with the cached ones, the cache is restarted from `from_idx`.
+    pub(super) fn append(&mut self, from_idx: usize, entries: &[T]) {
+        if self.capacity == 0 {
+            return;
+        }
+        if from_idx != self.end_idx() || entries.len() >= self.capacity {
+            self.entries.clear();
+            self.start_idx = from_idx;
+        }
+        // Only the last `capacity` entries can be kept.
+        let skip = entries.len().saturating_sub(self.capacity);
+        self.start_idx += skip;
+        for entry in &entries[skip..] {
+            if self.entries.len() == self.capacity {
+                self.entries.pop_front();
+                self.start_idx += 1;
+            }
+            self.entries.push_back(entry.clone());
+        }
+    }
+
+    // Drops all cached entries at index `idx` and after. Used when the log is overwritten from `idx`.
+    pub(super) fn truncate(&mut self, idx: usize) {
+        if idx <= self.start_idx {
+            self.entries.clear();
+            self.start_idx = idx;
+        } else if idx < self.end_idx() {
+            self.entries.truncate(idx - self.start_idx);
+        }
+    }
+
+    // Drops all cached entries before index `idx`. Used when the log is compacted.
+    pub(super) fn trim(&mut self, idx: usize) {
+        if idx >= self.end_idx() {
+            self.entries.clear();
+            self.start_idx = idx;
+        } else if idx > self.start_idx {
+            self.entries.drain(..idx - self.start_idx);
+            self.start_idx = idx;
+        }
+    }
+
+    // Drops all cached entries, e.g., to free memory. The cache is refilled by the following appends.
+    pub(super) fn clear(&mut self) {
+        self.start_idx = self.end_idx();
+        self.entries.clear();
+    }
+
+    /// The approximate size in bytes of the cached entries.
+    pub(super) fn size_hint(&self) -> usize {
+        self.entries.iter().map(|e| e.size_hint()).sum()
+    }
+
+    /// Returns the entries in the interval [from, to) if all of them are cached.
+    pub(super) fn get(&self, from: usize, to: usize) -> Option<Vec<T>> {
+        if from < self.start_idx || to > self.end_idx() || from > to {
+            return None;
+        }
+        Some(
+            self.entries
+                .range(from - self.start_idx..to - self.start_idx)
+                .cloned()
+                .collect(),
+        )
+    }
+
+    /// Returns a reference to the entry at `idx` if it is cached.
+    pub(super) fn get_ref(&self, idx: usize) -> Option<&T> {
+        idx.checked_sub(self.start_idx)
+            .and_then(|i| self.entries.get(i))
+    }
+}
@@ -0,0 +1,322 @@
+use super::{Entry, Snapshot, Storage, StorageOp};
+use crate::{
+    ballot_leader_election::Ballot,
+    errors::{ImportErr, StorageErr, StorageMigrationErr},
+    util::ConfigurationId,
+};
+use std::error::Error;
+
+/// An entry of a Raft log.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct RaftEntry<E> {
+    /// The index of the entry. Raft logs start at index 1.
+    pub index: u64,
+    /// The term in which the entry was appended.
+    pub term: u64,
+    /// The payload of the entry.
+    pub data: E,
+}
+
+/// A Raft snapshot of the entries up to and including `index`.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct RaftSnapshot<S> {
+    /// The index of the last entry included in the snapshot.
+    pub index: u64,
+    /// The term of the last entry included in the snapshot.
+    pub term: u64,
+    /// The snapshot.
+    pub data: S,
+}
+
+/// The persistent state of a Raft server, e.g., as read from the storage of raft-rs or openraft.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct RaftState<E, S> {
+    /// The current term of the server.
+    pub term: u64,
+    /// The index of the last entry the server knows to be committed.
+    pub commit: u64,
+    /// The latest snapshot, if the log has been compacted.
+    pub snapshot: Option<RaftSnapshot<S>>,
+    /// The entries after the snapshot, ordered by index.
+    pub entries: Vec<RaftEntry<E>>,
+}
+
+/// Converts the entries and the snapshot of a Raft log into the ones of the application's
+/// OmniPaxos log. Every Raft entry must be mapped to exactly one OmniPaxos entry, e.g., Raft
+/// no-ops to an application no-op, so that the Raft entry at index `i` is at index `i - 1` in
+/// OmniPaxos.
+pub trait RaftEntryMapping<T: Entry> {
+    /// The payload of the Raft entries.
+    type RaftEntry;
+    /// The Raft snapshot.
+    type RaftSnapshot;
+
+    /// Maps a Raft entry to an OmniPaxos entry.
+    fn map_entry(&mut self, entry: RaftEntry<Self::RaftEntry>) -> Result<T, Box<dyn Error>>;
+
+    /// Maps a Raft snapshot to an OmniPaxos snapshot. Must be implemented if the Raft log was
+    /// compacted.
+    fn map_snapshot(
+        &mut self,
+        _snapshot: RaftSnapshot<Self::RaftSnapshot>,
+    ) -> Result<T::Snapshot, Box<dyn Error>> {
+        Err("snapshots are not supported by this mapping".into())
+    }
+}
+
+/// Imports the state of a Raft server into `storage`, which should be empty, so that the server
+/// can continue as an OmniPaxos server of the configuration `configuration_id`. All servers of the
+/// Raft cluster must be imported with the same `configuration_id` before any of them is started.
+///
+/// The Raft entry at index `i` becomes the entry at index `i - 1`, and the committed entries are
+/// decided. The Raft terms are mapped to ballots such that the leader elected in OmniPaxos
+/// recovers the log of the most up-to-date server as Raft would, so entries that were committed
+/// by Raft are never lost, while uncommitted entries might be.
+pub fn import_raft_state<T, S, M>(
+    state: RaftState<M::RaftEntry, M::RaftSnapshot>,
+    mapping: &mut M,
+    configuration_id: ConfigurationId,
+    storage: &mut S,
+) -> Result<(), ImportErr>
+where
+    T: Entry,
+    S: Storage<T>,
+    M: RaftEntryMapping<T>,
+{
+    let RaftState {
+        term,
+        commit,
+        snapshot,
+        entries,
+    } = state;
+    let (snapshot_idx, snapshot_term) = snapshot.as_ref().map_or((0, 0), |s| (s.index, s.term));
+    // Entries that are also included in the snapshot are skipped
+    let entries: Vec<RaftEntry<M::RaftEntry>> = entries
+        .into_iter()
+        .filter(|e| e.index > snapshot_idx)
+        .collect();
+    let mut last_idx = snapshot_idx;
+    let mut last_term = snapshot_term;
+    for entry in &entries {
+        if entry.index != last_idx + 1 {
+            return Err(ImportErr::InvalidLog(format!(
+                "expected the entry at index {}, found {}",
+                last_idx + 1,
+                entry.index
+            )));
+        }
+        if entry.term < last_term {
+            return Err(ImportErr::InvalidLog(format!(
+                "the term of the entry at index {} decreases to {}",
+                entry.index, entry.term
+            )));
+        }
+        last_idx = entry.index;
+        last_term = entry.term;
+    }
+    if commit > last_idx {
+        return Err(ImportErr::InvalidLog(format!(
+            "the commit index {} is after the last entry {}",
+            commit, last_idx
+        )));
+    }
+    if term < last_term {
+        return Err(ImportErr::InvalidLog(format!(
+            "the current term {} is before the term of the last entry {}",
+            term, last_term
+        )));
+    }
+    // The ballots don't belong to any server, so that servers with the same last term compare
+    // their logs by length only, like in Raft.
+    let ballot = |term: u64| {
+        let n = u32::try_from(term)
+            .map_err(|_| ImportErr::InvalidLog(format!("term {} is too large", term)))?;
+        Ok::<_, ImportErr>(Ballot::with(configuration_id, n, 0, 0))
+    };
+    let accepted_round = ballot(last_term)?;
+    // Nothing is promised, but the first leader is elected with a ballot of a later term
+    let ble_ballot = ballot(term)?;
+    let to_usize = |idx: u64| {
+        usize::try_from(idx)
+            .map_err(|_| ImportErr::InvalidLog(format!("index {} is too large", idx)))
+    };
+    let compacted_idx = to_usize(snapshot_idx)?;
+    // A snapshot only contains committed entries
+    let decided_idx = to_usize(commit.max(snapshot_idx))?;
+
+    let mut ops = vec![];
+    if let Some(s) = snapshot {
+        let index = s.index;
+        let snapshot = mapping
+            .map_snapshot(s)
+            .map_err(|source| ImportErr::Mapping { index, source })?;
+        ops.push(StorageOp::Trim(compacted_idx));
+        ops.push(StorageOp::SetCompactedIdx(compacted_idx));
+        ops.push(StorageOp::SetSnapshot(Some(snapshot)));
+    }
+    let entries = entries
+        .into_iter()
+        .map(|e| {
+            let index = e.index;
+            mapping
+                .map_entry(e)
+                .map_err(|source| ImportErr::Mapping { index, source })
+        })
+        .collect::<Result<Vec<T>, ImportErr>>()?;
+    ops.push(StorageOp::AppendOnPrefix(compacted_idx, entries));
+    ops.push(StorageOp::SetAcceptedRound(accepted_round));
+    ops.push(StorageOp::SetDecidedIndex(decided_idx));
+    storage
+        .write_atomically(ops)
+        .map_err(|e| StorageErr::with("import the Raft state", e))?;
+    storage
+        .set_ble_ballot(ble_ballot)
+        .map_err(|e| StorageErr::with("import the Raft term", e))?;
+    Ok(())
+}
+
+/// Copies the state of `from` into `to`, which should be empty: its promise, accepted round,
+/// leader election ballot, standby promise, decided and compacted index, snapshot, entries after the compacted
+/// index, and StopSign. Entries before the compacted index that have not been trimmed from `from`
+/// yet are not copied.
+pub fn copy_storage<T, S1, S2>(from: &S1, to: &mut S2) -> Result<(), StorageErr>
+where
+    T: Entry,
+    S1: Storage<T>,
+    S2: Storage<T>,
+{
+    let storage_err = |op| move |e| StorageErr::with(op, e);
+    let compacted_idx = from
+        .get_compacted_idx()
+        .map_err(storage_err("read the compacted index"))?;
+    let mut ops = vec![];
+    if compacted_idx > 0 {
+        ops.push(StorageOp::Trim(compacted_idx));
+        ops.push(StorageOp::SetCompactedIdx(compacted_idx));
+    }
+    let snapshot = from
+        .get_snapshot()
+        .map_err(storage_err("read the snapshot"))?;
+    if snapshot.is_some() {
+        ops.push(StorageOp::SetSnapshot(snapshot));
+    }
+    let entries = from
+        .get_suffix(compacted_idx)
+        .map_err(storage_err("read log entries"))?;
+    ops.push(StorageOp::AppendOnPrefix(compacted_idx, entries));
+    let stopsign = from
+        .get_stopsign()
+        .map_err(storage_err("read the StopSign"))?;
+    if stopsign.is_some() {
+        ops.push(StorageOp::SetStopsign(stopsign));
+    }
+    if let Some(promise) = from
+        .get_promise()
+        .map_err(storage_err("read the promise"))?
+    {
+        ops.push(StorageOp::SetPromise(promise));
+    }
+    let accepted_round = from
+        .get_accepted_round()
+        .map_err(storage_err("read the accepted round"))?;
+    if let Some(accepted_round) = accepted_round {
+        ops.push(StorageOp::SetAcceptedRound(accepted_round));
+    }
+    let decided_idx = from
+        .get_decided_idx()
+        .map_err(storage_err("read the decided index"))?;
+    ops.push(StorageOp::SetDecidedIndex(decided_idx));
+    to.write_atomically(ops)
+        .map_err(storage_err("copy the state"))?;
+    let ble_ballot = from
+        .get_ble_ballot()
+        .map_err(storage_err("read the leader election ballot"))?;
+    if let Some(ballot) = ble_ballot {
+        to.set_ble_ballot(ballot)
+            .map_err(storage_err("copy the leader election ballot"))?;
+    }
+    let standby_promise = from
+        .get_standby_promise()
+        .map_err(storage_err("read the standby promise"))?;
+    if standby_promise.is_some() {
+        to.set_standby_promise(standby_promise)
+            .map_err(storage_err("copy the standby promise"))?;
+    }
+    Ok(())
+}
+
+/// Checks that `copy` holds the same state as `original`, e.g., after [`copy_storage`], and that
+/// the copied entries are durable. Returns the first difference found. Snapshots are only
+/// compared if [`Snapshot::is_equivalent`] is implemented.
+pub fn verify_storage_copy<T, S1, S2>(original: &S1, copy: &S2) -> Result<(), StorageMigrationErr>
+where
+    T: Entry + PartialEq,
+    S1: Storage<T>,
+    S2: Storage<T>,
+{
+    let storage_err = |op| move |e| StorageErr::with(op, e);
+    macro_rules! compare {
+        ($what:expr, $get:ident) => {
+            let expected = original
+                .$get()
+                .map_err(storage_err("read the original state"))?;
+            let found = copy.$get().map_err(storage_err("read the copied state"))?;
+            if expected != found {
+                return Err(StorageMigrationErr::Mismatch(format!(
+                    "expected the {} {:?}, found {:?}",
+                    $what, expected, found
+                )));
+            }
+        };
+    }
+    compare!("promise", get_promise);
+    compare!("accepted round", get_accepted_round);
+    compare!("leader election ballot", get_ble_ballot);
+    compare!("standby promise", get_standby_promise);
+    compare!("decided index", get_decided_idx);
+    compare!("compacted index", get_compacted_idx);
+    compare!("StopSign", get_stopsign);
+    let compacted_idx = original
+        .get_compacted_idx()
+        .map_err(storage_err("read the original state"))?;
+    let entries = original
+        .get_suffix(compacted_idx)
+        .map_err(storage_err("read the original state"))?;
+    let copied_entries = copy
+        .get_suffix(compacted_idx)
+        .map_err(storage_err("read the copied state"))?;
+    if let Some(i) = (0..entries.len().max(copied_entries.len()))
+        .find(|i| entries.get(*i) != copied_entries.get(*i))
+    {
+        return Err(StorageMigrationErr::Mismatch(format!(
+            "the entries differ at index {}",
+            compacted_idx + i
+        )));
+    }
+    let snapshot = original
+        .get_snapshot()
+        .map_err(storage_err("read the original state"))?;
+    let copied_snapshot = copy
+        .get_snapshot()
+        .map_err(storage_err("read the copied state"))?;
+    let snapshots_match = match (&snapshot, &copied_snapshot) {
+        (Some(s), Some(copied)) => s.is_equivalent(copied) != Some(false),
+        (s, copied) => s.is_none() && copied.is_none(),
+    };
+    if !snapshots_match {
+        return Err(StorageMigrationErr::Mismatch(
+            "the snapshots differ".to_string(),
+        ));
+    }
+    let log_len = compacted_idx + entries.len();
+    match copy
+        .get_durable_idx()
+        .map_err(storage_err("read the copied state"))?
+    {
+        Some(durable_idx) if durable_idx < log_len => Err(StorageMigrationErr::NotDurable {
+            durable_idx,
+            log_len,
+        }),
+        _ => Ok(()),
+    }
+}
@@ -1,13 +1,32 @@
+/// A versioned archive format for the persisted state of a server and a reader for it.
+pub mod archive;
 pub(crate) mod internal_storage;
+/// A journal of the calls made to a storage, and a replayer that reissues them against another
+/// storage to reproduce storage bugs.
+pub mod journal;
+mod log_pins;
+mod log_tail_cache;
+/// Utilities for migrating the state of a server to another storage, or of a Raft server to
+/// OmniPaxos.
+pub mod migration;
+/// Utilities for replaying the decided log into a state machine of the application.
+pub mod replay;
+mod snapshot_check;
 mod state_cache;
+mod trim_worker;
+pub(crate) mod verification;
 
 use super::ballot_leader_election::Ballot;
 #[cfg(feature = "unicache")]
 use crate::unicache::*;
 use crate::ClusterConfig;
+pub use log_pins::PinGuard;
 #[cfg(feature = "serde")]
 use serde::{Deserialize, Serialize};
+pub use snapshot_check::SnapshotViolation;
 use std::{error::Error, fmt::Debug};
+pub use trim_worker::TrimWorker;
+pub use verification::{VerificationFailure, VerificationReport};
 
 /// Type of the entries stored in the log.
 pub trait Entry: Clone + Debug {
@@ -43,6 +62,31 @@ pub trait Entry: Clone + Debug {
     #[cfg(all(feature = "unicache", feature = "serde"))]
     /// The unicache type for caching popular/re-occurring fields of an entry.
     type UniCache: UniCache<T = Self> + Serialize + for<'a> Deserialize<'a>;
+
+    /// The approximate size of the entry in bytes when it is serialized. It is used to limit the
+    /// size of the storage batches (`batch_byte_size`) and of the messages that synchronize a
+    /// follower (`sync_batch_byte_size`). The default only counts the inline size of the entry, so
+    /// entries that hold heap data, e.g., a `Vec` or `String`, should override it.
+    fn size_hint(&self) -> usize {
+        std::mem::size_of_val(self)
+    }
+
+    /// A hash of the entry that must be the same on all servers, regardless of their platform or
+    /// Rust version. It is used to detect servers whose decided logs diverged, see
+    /// `fingerprint_tick_timeout` in [`crate::ServerConfig`]. Returns `None` by default, which
+    /// skips the check for the entry.
+    fn fingerprint(&self) -> Option<u64> {
+        None
+    }
+
+    /// The index of an earlier log entry that this entry depends on, e.g., the previous entry of
+    /// the same stream when several independent streams share one log. The leader only appends
+    /// the entry once the entry at that index is decided, so it is never placed ahead of its
+    /// dependency, while entries without a pending dependency can overtake it. Returns `None` by
+    /// default, i.e., the entry is appended in the order it is proposed.
+    fn dependency(&self) -> Option<usize> {
+        None
+    }
 }
 
 /// A StopSign entry that marks the end of a configuration. Used for reconfiguration.
@@ -75,6 +119,11 @@ where
 {
     Complete(T::Snapshot),
     Delta(T::Snapshot),
+    /// A snapshot that is encrypted and signed while it is sent to another server, see
+    /// [`crate::sealing::SnapshotSealer`]. It is opened before it is installed. Boxed, so that it
+    /// doesn't inflate the size of every message.
+    #[cfg(feature = "snapshot_sealing")]
+    Sealed(Box<crate::sealing::SealedSnapshot>),
 }
 
 /// Trait for implementing snapshot operations for log entries of type `T` in OmniPaxos.
@@ -89,16 +138,62 @@ where
     fn merge(&mut self, delta: Self);
 
     /// Whether `T` is snapshottable. If not, simply return `false` and leave the other functions `unimplemented!()`.
+    /// With the `no_snapshots` feature, this is ignored and the other functions are never called.
     fn use_snapshots() -> bool;
 
+    /// Whether this snapshot represents the same state as `other`. Only used to check that
+    /// `create()` and `merge()` are consistent with each other, see `check_snapshots` in
+    /// [`crate::ServerConfig`]. Returns `None` by default, which skips the check.
+    fn is_equivalent(&self, _other: &Self) -> Option<bool> {
+        None
+    }
+
     //fn size_hint() -> usize;  // TODO: To let the system know trade-off of using entries vs snapshot?
 }
 
+/// Whether the log entries of type `T` are snapshotted. Always `false` with the `no_snapshots`
+/// feature, so that the snapshot code paths are removed at compile time.
+#[cfg(not(feature = "no_snapshots"))]
+pub(crate) fn use_snapshots<T: Entry>() -> bool {
+    T::Snapshot::use_snapshots()
+}
+
+#[cfg(feature = "no_snapshots")]
+#[allow(clippy::extra_unused_type_parameters)]
+pub(crate) fn use_snapshots<T: Entry>() -> bool {
+    false
+}
+
 /// The Result type returned by the storage API.
 pub type StorageResult<T> = Result<T, Box<dyn Error>>;
 
+/// A prefix of the requested entries, as returned by [`LogStorage::get_entries_hint`].
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct EntriesHint<T> {
+    /// The entries from the requested start index.
+    pub entries: Vec<T>,
+    /// The index to continue reading from, i.e., the start index plus the number of `entries`, or
+    /// `None` if the entries reach the requested end index.
+    pub continuation: Option<usize>,
+}
+
+impl<T> EntriesHint<T> {
+    /// Returns the `entries` read from index `from` for a request that ends at index `to`.
+    pub fn with(entries: Vec<T>, from: usize, to: usize) -> Self {
+        let end = from + entries.len();
+        let continuation = match end < to && !entries.is_empty() {
+            true => Some(end),
+            false => None,
+        };
+        Self {
+            entries,
+            continuation,
+        }
+    }
+}
+
 /// The write operations of the storge implementation.
-#[derive(Debug)]
+#[derive(Clone, Debug)]
 pub enum StorageOp<T: Entry> {
     /// Appends an entry to the end of the log.
     AppendEntry(T),
@@ -122,16 +217,22 @@ pub enum StorageOp<T: Entry> {
     SetSnapshot(Option<T::Snapshot>),
 }
 
-/// Trait for implementing the storage backend of Sequence Paxos.
-pub trait Storage<T>
+/// The log part of the storage backend of Sequence Paxos: the log entries and the writes that
+/// change them. See [`Storage`].
+pub trait LogStorage<T>
 where
     T: Entry,
 {
-    /// **Atomically** perform all storage operations in order.
+    /// **Atomically** perform all storage operations in order, including the ones that change the
+    /// [`StateStorage`] of the same server.
     /// For correctness, the operations must be atomic i.e., either all operations are performed
     /// successfully or all get rolled back. If the `StorageResult` returns as `Err`, the
     /// operations are assumed to have been rolled back to the previous state before this function
-    /// call.
+    /// call. A log backend that is paired with a separate store for the state can, e.g., record the
+    /// operations in its write-ahead log and apply the state operations to the other store
+    /// afterwards, replaying them from the log after a crash. The server groups related writes,
+    /// e.g., the entries and the accepted round and decided index of a synchronization, into one
+    /// call, so that a failed write never leaves the storage with only some of them.
     fn write_atomically(&mut self, ops: Vec<StorageOp<T>>) -> StorageResult<()>;
 
     /// Appends an entry to the end of the log.
@@ -143,6 +244,93 @@ where
     /// Appends the entries of `entries` to the prefix from index `from_index` (inclusive) in the log.
     fn append_on_prefix(&mut self, from_idx: usize, entries: Vec<T>) -> StorageResult<()>;
 
+    /// Returns the entries in the log in the index interval of [from, to).
+    /// If entries **do not exist for the complete interval**, an empty Vector should be returned.
+    fn get_entries(&self, from: usize, to: usize) -> StorageResult<Vec<T>>;
+
+    /// Returns the entries in the log in the index interval of [from, to), or only a prefix of
+    /// them, e.g., the entries that fit in `max_bytes` bytes or that are stored in the same block,
+    /// together with the index to continue reading from. The server uses it to read long suffixes
+    /// in several smaller reads, e.g., when synchronizing a follower, instead of a single huge
+    /// range read. At least one entry should be returned for a non-empty interval, and an empty
+    /// Vector if the first entry doesn't exist. The default implementation reads the whole
+    /// interval with `get_entries()`.
+    fn get_entries_hint(
+        &self,
+        from: usize,
+        to: usize,
+        max_bytes: usize,
+    ) -> StorageResult<EntriesHint<T>> {
+        let _ = max_bytes;
+        let entries = self.get_entries(from, to)?;
+        Ok(EntriesHint::with(entries, from, to))
+    }
+
+    /// Returns a reference to the entry at index `idx` if the storage keeps it in memory, so that it
+    /// can be read without cloning it. The default implementation returns `None`, in which case
+    /// the entry is read with `get_entries()` instead.
+    fn get_entry_ref(&self, idx: usize) -> StorageResult<Option<&T>> {
+        let _ = idx;
+        Ok(None)
+    }
+
+    /// Returns the current length of the log (without the trimmed/snapshotted entries).
+    fn get_log_len(&self) -> StorageResult<usize>;
+
+    /// Returns the suffix of entries in the log from index `from` (inclusive).
+    /// If entries **do not exist for the complete interval**, an empty Vector should be returned.
+    fn get_suffix(&self, from: usize) -> StorageResult<Vec<T>>;
+
+    /// Removes elements up to the given [`idx`] from storage.
+    fn trim(&mut self, idx: usize) -> StorageResult<()>;
+
+    /// Returns the approximate number of bytes occupied by the storage, e.g., on disk, or `None` if
+    /// it is unknown. It is queried on every tick if `storage_size_limit` is set in the
+    /// `ServerConfig`, so it should be cheap. The default implementation returns `None`.
+    fn approximate_size(&self) -> StorageResult<Option<u64>> {
+        Ok(None)
+    }
+
+    /// Returns the maximum number of log entries the storage can hold, i.e., the entries that are
+    /// appended and not trimmed yet, or `None` if the log can grow without bounds, which is what
+    /// the default implementation returns. It is queried once when the server is created. If set,
+    /// the server compacts its decided log once it fills half of the capacity, and refuses new
+    /// proposals with `ProposeErr::Full` while its log fills the whole capacity.
+    fn get_capacity(&self) -> StorageResult<Option<usize>> {
+        Ok(None)
+    }
+
+    /// Returns the index up to which (exclusive) the log is durable if the storage acknowledges
+    /// writes before they are durable, e.g., because it persists them in the background. The
+    /// server only reports entries below this index as accepted to the leader, and checks for
+    /// newly persisted entries on every tick. Returns `None` if all written entries are durable,
+    /// which is what the default implementation does.
+    fn get_durable_idx(&self) -> StorageResult<Option<usize>> {
+        Ok(None)
+    }
+
+    /// Blocks until all writes so far are durable, e.g., by syncing them to disk or waiting for
+    /// the writes that are persisted in the background. Afterwards, the durable index covers all
+    /// entries written before. The default implementation does nothing, as it assumes that writes
+    /// are durable once they return.
+    fn flush(&self) -> StorageResult<()> {
+        Ok(())
+    }
+
+    /// Verifies the integrity of the stored entries in the index interval of [from, to), e.g., by
+    /// checking their checksums. The default implementation re-reads the entries, which detects
+    /// corruption if the storage verifies or decodes the data on reads.
+    fn verify_entries(&self, from: usize, to: usize) -> StorageResult<()> {
+        self.get_entries(from, to).map(|_| ())
+    }
+}
+
+/// The state part of the storage backend of Sequence Paxos: the small metadata of a server, i.e.,
+/// its rounds, decided and compacted index, snapshot and StopSign. See [`Storage`].
+pub trait StateStorage<T>
+where
+    T: Entry,
+{
     /// Sets the round that has been promised.
     fn set_promise(&mut self, n_prom: Ballot) -> StorageResult<()>;
 
@@ -159,19 +347,39 @@ where
     /// entries have been accepted.
     fn get_accepted_round(&self) -> StorageResult<Option<Ballot>>;
 
-    /// Returns the entries in the log in the index interval of [from, to).
-    /// If entries **do not exist for the complete interval**, an empty Vector should be returned.
-    fn get_entries(&self, from: usize, to: usize) -> StorageResult<Vec<T>>;
+    /// Returns the round that has been promised.
+    fn get_promise(&self) -> StorageResult<Option<Ballot>>;
 
-    /// Returns the current length of the log (without the trimmed/snapshotted entries).
-    fn get_log_len(&self) -> StorageResult<usize>;
+    /// Sets the highest ballot this server has used in the leader election. It is used together
+    /// with the promise to ensure that a recovered server never reuses or regresses its ballots, so
+    /// it should not be rolled back together with the rest of the state, e.g., when restoring the
+    /// state from a backup. The default implementation does not store the ballot, in which case a
+    /// recovered server only restarts its ballots from the promise.
+    fn set_ble_ballot(&mut self, _ballot: Ballot) -> StorageResult<()> {
+        Ok(())
+    }
 
-    /// Returns the suffix of entries in the log from index `from` (inclusive).
-    /// If entries **do not exist for the complete interval**, an empty Vector should be returned.
-    fn get_suffix(&self, from: usize) -> StorageResult<Vec<T>>;
+    /// Returns the highest ballot this server has used in the leader election, returns `None` if no
+    /// ballot has been stored.
+    fn get_ble_ballot(&self) -> StorageResult<Option<Ballot>> {
+        Ok(None)
+    }
 
-    /// Returns the round that has been promised.
-    fn get_promise(&self) -> StorageResult<Option<Ballot>>;
+    /// Sets the standby promise of this server: it does not promise any ballot of round
+    /// `standby.n` or lower to another server than the standby `standby.pid`, so that the standby
+    /// can take over without a prepare phase, see [`OmniPaxos::set_standby`](crate::OmniPaxos::set_standby).
+    /// Like the promise, it must be durable once this returns. The default implementation does not
+    /// support standbys and returns an error, which makes the leader wait for the standby
+    /// promises of other servers instead.
+    fn set_standby_promise(&mut self, _standby: Option<Ballot>) -> StorageResult<()> {
+        Err("standby promises are not supported by this storage".into())
+    }
+
+    /// Returns the standby promise of this server, returns `None` if no standby promise has been
+    /// stored.
+    fn get_standby_promise(&self) -> StorageResult<Option<Ballot>> {
+        Ok(None)
+    }
 
     /// Sets the StopSign used for reconfiguration.
     fn set_stopsign(&mut self, s: Option<StopSign>) -> StorageResult<()>;
@@ -179,9 +387,6 @@ where
     /// Returns the stored StopSign, returns `None` if no StopSign has been stored.
     fn get_stopsign(&self) -> StorageResult<Option<StopSign>>;
 
-    /// Removes elements up to the given [`idx`] from storage.
-    fn trim(&mut self, idx: usize) -> StorageResult<()>;
-
     /// Sets the compacted (i.e. trimmed or snapshotted) index.
     fn set_compacted_idx(&mut self, idx: usize) -> StorageResult<()>;
 
@@ -193,6 +398,177 @@ where
 
     /// Returns the stored snapshot.
     fn get_snapshot(&self) -> StorageResult<Option<T::Snapshot>>;
+
+    /// Verifies the integrity of the stored snapshot, e.g., by checking its checksum. The default
+    /// implementation re-reads the snapshot.
+    fn verify_snapshot(&self) -> StorageResult<()> {
+        self.get_snapshot().map(|_| ())
+    }
+}
+
+/// Trait for implementing the storage backend of Sequence Paxos. It is implemented for every type
+/// that implements both [`LogStorage`] and [`StateStorage`], so that the log entries and the state
+/// of a server can be kept in different backends, e.g., a fast write-ahead log for the entries and
+/// a small key-value store for the state, by implementing the two traits for a type that holds
+/// both.
+pub trait Storage<T>: LogStorage<T> + StateStorage<T>
+where
+    T: Entry,
+{
+}
+
+impl<T, S> Storage<T> for S
+where
+    T: Entry,
+    S: LogStorage<T> + StateStorage<T> + ?Sized,
+{
+}
+
+/// Forwards to the boxed storage, so that the storage of a server can be chosen at runtime, e.g.,
+/// with `Box<dyn Storage<T>>`.
+impl<T, S> LogStorage<T> for Box<S>
+where
+    T: Entry,
+    S: LogStorage<T> + ?Sized,
+{
+    fn write_atomically(&mut self, ops: Vec<StorageOp<T>>) -> StorageResult<()> {
+        (**self).write_atomically(ops)
+    }
+
+    fn append_entry(&mut self, entry: T) -> StorageResult<()> {
+        (**self).append_entry(entry)
+    }
+
+    fn append_entries(&mut self, entries: Vec<T>) -> StorageResult<()> {
+        (**self).append_entries(entries)
+    }
+
+    fn append_on_prefix(&mut self, from_idx: usize, entries: Vec<T>) -> StorageResult<()> {
+        (**self).append_on_prefix(from_idx, entries)
+    }
+
+    fn get_entries(&self, from: usize, to: usize) -> StorageResult<Vec<T>> {
+        (**self).get_entries(from, to)
+    }
+
+    fn get_entries_hint(
+        &self,
+        from: usize,
+        to: usize,
+        max_bytes: usize,
+    ) -> StorageResult<EntriesHint<T>> {
+        (**self).get_entries_hint(from, to, max_bytes)
+    }
+
+    fn get_entry_ref(&self, idx: usize) -> StorageResult<Option<&T>> {
+        (**self).get_entry_ref(idx)
+    }
+
+    fn get_log_len(&self) -> StorageResult<usize> {
+        (**self).get_log_len()
+    }
+
+    fn get_suffix(&self, from: usize) -> StorageResult<Vec<T>> {
+        (**self).get_suffix(from)
+    }
+
+    fn trim(&mut self, idx: usize) -> StorageResult<()> {
+        (**self).trim(idx)
+    }
+
+    fn approximate_size(&self) -> StorageResult<Option<u64>> {
+        (**self).approximate_size()
+    }
+
+    fn get_capacity(&self) -> StorageResult<Option<usize>> {
+        (**self).get_capacity()
+    }
+
+    fn get_durable_idx(&self) -> StorageResult<Option<usize>> {
+        (**self).get_durable_idx()
+    }
+
+    fn flush(&self) -> StorageResult<()> {
+        (**self).flush()
+    }
+
+    fn verify_entries(&self, from: usize, to: usize) -> StorageResult<()> {
+        (**self).verify_entries(from, to)
+    }
+}
+
+impl<T, S> StateStorage<T> for Box<S>
+where
+    T: Entry,
+    S: StateStorage<T> + ?Sized,
+{
+    fn set_promise(&mut self, n_prom: Ballot) -> StorageResult<()> {
+        (**self).set_promise(n_prom)
+    }
+
+    fn set_decided_idx(&mut self, ld: usize) -> StorageResult<()> {
+        (**self).set_decided_idx(ld)
+    }
+
+    fn get_decided_idx(&self) -> StorageResult<usize> {
+        (**self).get_decided_idx()
+    }
+
+    fn set_accepted_round(&mut self, na: Ballot) -> StorageResult<()> {
+        (**self).set_accepted_round(na)
+    }
+
+    fn get_accepted_round(&self) -> StorageResult<Option<Ballot>> {
+        (**self).get_accepted_round()
+    }
+
+    fn get_promise(&self) -> StorageResult<Option<Ballot>> {
+        (**self).get_promise()
+    }
+
+    fn set_ble_ballot(&mut self, ballot: Ballot) -> StorageResult<()> {
+        (**self).set_ble_ballot(ballot)
+    }
+
+    fn get_ble_ballot(&self) -> StorageResult<Option<Ballot>> {
+        (**self).get_ble_ballot()
+    }
+
+    fn set_standby_promise(&mut self, standby: Option<Ballot>) -> StorageResult<()> {
+        (**self).set_standby_promise(standby)
+    }
+
+    fn get_standby_promise(&self) -> StorageResult<Option<Ballot>> {
+        (**self).get_standby_promise()
+    }
+
+    fn set_stopsign(&mut self, s: Option<StopSign>) -> StorageResult<()> {
+        (**self).set_stopsign(s)
+    }
+
+    fn get_stopsign(&self) -> StorageResult<Option<StopSign>> {
+        (**self).get_stopsign()
+    }
+
+    fn set_compacted_idx(&mut self, idx: usize) -> StorageResult<()> {
+        (**self).set_compacted_idx(idx)
+    }
+
+    fn get_compacted_idx(&self) -> StorageResult<usize> {
+        (**self).get_compacted_idx()
+    }
+
+    fn set_snapshot(&mut self, snapshot: Option<T::Snapshot>) -> StorageResult<()> {
+        (**self).set_snapshot(snapshot)
+    }
+
+    fn get_snapshot(&self) -> StorageResult<Option<T::Snapshot>> {
+        (**self).get_snapshot()
+    }
+
+    fn verify_snapshot(&self) -> StorageResult<()> {
+        (**self).verify_snapshot()
+    }
 }
 
 /// A place holder type for when not using snapshots. You should not use this type, it is only internally when deriving the Entry implementation.
@@ -0,0 +1,150 @@
+use super::{Entry, Storage};
+use crate::errors::{ReplayErr, StorageErr};
+use std::{
+    error::Error,
+    thread,
+    time::{Duration, Instant},
+};
+
+/// An application state machine that the decided log can be replayed into with
+/// [`replay_decided_log`].
+pub trait Apply<T: Entry> {
+    /// Applies the decided entry at index `idx` of the log. If the entry declares the index of an
+    /// earlier entry it depends on with [`Entry::dependency`], the entries of independent streams
+    /// can be applied concurrently as long as each is applied after its dependency.
+    fn apply(&mut self, idx: usize, entry: T) -> Result<(), Box<dyn Error>>;
+
+    /// Restores the state from `snapshot`, which replaces the entries before `compacted_idx`.
+    /// Must be implemented if the log was compacted with snapshots.
+    fn apply_snapshot(
+        &mut self,
+        _snapshot: T::Snapshot,
+        _compacted_idx: usize,
+    ) -> Result<(), Box<dyn Error>> {
+        Err("snapshots are not supported by this state machine".into())
+    }
+}
+
+/// Configuration for [`replay_decided_log`].
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct ReplayConfig {
+    /// The index to start the replay at, e.g., the index up to which the state machine was
+    /// already restored from a checkpoint of the application. If it is before the compacted index,
+    /// the replay starts with the snapshot.
+    pub from_idx: usize,
+    /// The number of entries that are read from the storage at a time. The progress is reported
+    /// after every batch.
+    pub batch_size: usize,
+    /// The maximum number of entries that are applied per second, or `None` to replay as fast as
+    /// possible. Limits the load on a storage that is shared with a running server.
+    pub max_entries_per_sec: Option<u64>,
+}
+
+impl Default for ReplayConfig {
+    fn default() -> Self {
+        Self {
+            from_idx: 0,
+            batch_size: 1000,
+            max_entries_per_sec: None,
+        }
+    }
+}
+
+/// The progress of [`replay_decided_log`].
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct ReplayProgress {
+    /// The index up to which (exclusive) the log has been applied.
+    pub applied_idx: usize,
+    /// The index up to which (exclusive) the log is replayed, i.e., the decided index of the
+    /// storage when the replay started, without a decided StopSign.
+    pub end_idx: usize,
+    /// The time since the replay started.
+    pub elapsed: Duration,
+}
+
+/// Replays the decided log of `storage` into `state_machine`, e.g., to rebuild the state of the
+/// application after a bug corrupted it. The storage is only read, so a copy of the storage of a
+/// live server can be replayed without involving the server itself. `on_progress` is called after
+/// the snapshot and every batch of entries has been applied. Returns the final progress.
+///
+/// The decided index is read once at the start, so entries that are decided while the replay is
+/// running are not applied. Fails if the log was trimmed without a snapshot before the index to
+/// start at, since the state machine can't be rebuilt from it.
+pub fn replay_decided_log<T, S, A, F>(
+    storage: &S,
+    state_machine: &mut A,
+    config: &ReplayConfig,
+    mut on_progress: F,
+) -> Result<ReplayProgress, ReplayErr>
+where
+    T: Entry,
+    S: Storage<T>,
+    A: Apply<T>,
+    F: FnMut(&ReplayProgress),
+{
+    let storage_err = |op| move |e| StorageErr::with(op, e);
+    let start = Instant::now();
+    let compacted_idx = storage
+        .get_compacted_idx()
+        .map_err(storage_err("read the compacted index"))?;
+    let log_len = storage
+        .get_log_len()
+        .map_err(storage_err("read the log length"))?;
+    let decided_idx = storage
+        .get_decided_idx()
+        .map_err(storage_err("read the decided index"))?;
+    // A decided StopSign is not part of the entries
+    let end_idx = decided_idx.min(compacted_idx + log_len);
+    let mut progress = ReplayProgress {
+        applied_idx: config.from_idx,
+        end_idx,
+        elapsed: Duration::ZERO,
+    };
+    if config.from_idx < compacted_idx {
+        let snapshot = storage
+            .get_snapshot()
+            .map_err(storage_err("read the snapshot"))?
+            .ok_or(ReplayErr::Trimmed(compacted_idx))?;
+        state_machine
+            .apply_snapshot(snapshot, compacted_idx)
+            .map_err(|source| ReplayErr::Apply {
+                idx: compacted_idx,
+                source,
+            })?;
+        progress.applied_idx = compacted_idx;
+        progress.elapsed = start.elapsed();
+        on_progress(&progress);
+    }
+    let batch_size = config.batch_size.max(1);
+    let mut num_applied: u64 = 0;
+    while progress.applied_idx < end_idx {
+        let from_idx = progress.applied_idx;
+        let to_idx = end_idx.min(from_idx + batch_size);
+        let entries = storage
+            .get_entries(from_idx, to_idx)
+            .map_err(storage_err("read log entries"))?;
+        if entries.len() != to_idx - from_idx {
+            return Err(StorageErr::with(
+                "read log entries",
+                format!("missing decided entries in [{}, {})", from_idx, to_idx).into(),
+            )
+            .into());
+        }
+        for (idx, entry) in (from_idx..).zip(entries) {
+            state_machine
+                .apply(idx, entry)
+                .map_err(|source| ReplayErr::Apply { idx, source })?;
+        }
+        num_applied += (to_idx - from_idx) as u64;
+        if let Some(max_entries_per_sec) = config.max_entries_per_sec.filter(|r| *r > 0) {
+            let target = Duration::from_secs_f64(num_applied as f64 / max_entries_per_sec as f64);
+            if let Some(ahead) = target.checked_sub(start.elapsed()) {
+                thread::sleep(ahead);
+            }
+        }
+        progress.applied_idx = to_idx;
+        progress.elapsed = start.elapsed();
+        on_progress(&progress);
+    }
+    Ok(progress)
+}
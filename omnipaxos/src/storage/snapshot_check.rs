@@ -0,0 +1,41 @@
+#[cfg(not(feature = "no_snapshots"))]
+use crate::storage::{Entry, Snapshot};
+
+/// A violation of the [`Snapshot`] contract found by the snapshot check: merging the snapshot of
+/// the entries in [from_idx, split_idx) with the snapshot of the entries in [split_idx, to_idx)
+/// did not result in the same state as the snapshot of all entries in [from_idx, to_idx).
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct SnapshotViolation {
+    /// The first index of the checked entries.
+    pub from_idx: usize,
+    /// The index where the checked entries were split into the prefix and the delta.
+    pub split_idx: usize,
+    /// The end (exclusive) of the checked entries.
+    pub to_idx: usize,
+}
+
+/// Checks that `merge(create(prefix), create(delta)) == create(whole)`, where `entries` start at
+/// log index `from_idx` and are split into the prefix and the delta at `split_idx`. Returns the
+/// violation if the snapshots differ, and `None` if they are equivalent or cannot be compared.
+#[cfg(not(feature = "no_snapshots"))]
+pub(crate) fn check_snapshot_contract<T>(
+    entries: &[T],
+    from_idx: usize,
+    split_idx: usize,
+) -> Option<SnapshotViolation>
+where
+    T: Entry,
+{
+    let (prefix, delta) = entries.split_at(split_idx - from_idx);
+    let mut merged = T::Snapshot::create(prefix);
+    merged.merge(T::Snapshot::create(delta));
+    let whole = T::Snapshot::create(entries);
+    match merged.is_equivalent(&whole) {
+        Some(false) => Some(SnapshotViolation {
+            from_idx,
+            split_idx,
+            to_idx: from_idx + entries.len(),
+        }),
+        _ => None,
+    }
+}
@@ -13,12 +13,20 @@ where
     pub pid: NodeId,
     /// The maximum number of entries to batch.
     pub batch_size: usize,
+    /// The maximum approximate size in bytes of the batched entries. 0 means no limit.
+    pub batch_byte_size: usize,
     /// Vector which contains all the logged entries in-memory.
     pub batched_entries: Vec<T>,
+    /// The approximate size in bytes of the batched entries.
+    pub batched_bytes: usize,
     /// Last promised round.
     pub promise: Ballot,
     /// Last accepted round.
     pub accepted_round: Ballot,
+    /// Highest ballot used in the leader election.
+    pub ble_ballot: Ballot,
+    /// The standby promise, see [`StateStorage::set_standby_promise`](super::StateStorage::set_standby_promise).
+    pub standby_promise: Option<Ballot>,
     /// Length of the decided log.
     pub decided_idx: usize,
     /// Length of the accepted log.
@@ -46,9 +54,13 @@ where
             #[cfg(feature = "unicache")]
             pid,
             batch_size: config.batch_size,
+            batch_byte_size: config.batch_byte_size,
             batched_entries: Vec::with_capacity(config.batch_size),
+            batched_bytes: 0,
             promise: Ballot::default(),
             accepted_round: Ballot::default(),
+            ble_ballot: Ballot::default(),
+            standby_promise: None,
             decided_idx: 0,
             accepted_idx: 0,
             compacted_idx: 0,
@@ -68,6 +80,7 @@ where
             let processed = self.unicache.try_encode(&entry);
             self.batched_processed_by_leader.push(processed);
         }
+        self.batched_bytes += entry.size_hint();
         self.batched_entries.push(entry);
         self.take_entries_if_batch_is_full()
     }
@@ -85,6 +98,7 @@ where
                 }
             }
         }
+        self.batched_bytes += entries.iter().map(T::size_hint).sum::<usize>();
         self.batched_entries.extend(entries);
         self.take_entries_if_batch_is_full()
     }
@@ -100,9 +114,12 @@ where
         }
     }
 
-    // Return batched entries if the batch is full that need to be flushed in to storage.
+    // Return batched entries if the batch is full that need to be flushed in to storage. The
+    // batch is full when it either has `batch_size` entries or `batch_byte_size` bytes.
     fn take_entries_if_batch_is_full(&mut self) -> Option<Vec<T>> {
-        if self.batched_entries.len() >= self.batch_size {
+        if self.batched_entries.len() >= self.batch_size
+            || (self.batch_byte_size > 0 && self.batched_bytes >= self.batch_byte_size)
+        {
             Some(self.take_batched_entries())
         } else {
             None
@@ -112,9 +129,18 @@ where
     // Clears the batched entries and returns the cleared entries. If the batch is empty,
     // return an empty vector.
     pub(super) fn take_batched_entries(&mut self) -> Vec<T> {
+        self.batched_bytes = 0;
         std::mem::take(&mut self.batched_entries)
     }
 
+    // Puts entries that were taken from the batch for a write that failed back in front of the
+    // batch.
+    pub(super) fn restore_batched_entries(&mut self, mut entries: Vec<T>) {
+        self.batched_bytes += entries.iter().map(T::size_hint).sum::<usize>();
+        entries.append(&mut self.batched_entries);
+        self.batched_entries = entries;
+    }
+
     #[cfg(feature = "unicache")]
     pub(super) fn take_batched_processed(&mut self) -> Vec<T::EncodeResult> {
         std::mem::take(&mut self.batched_processed_by_leader)
@@ -0,0 +1,63 @@
+use super::{internal_storage::DeferredTrim, Entry, Storage, StorageResult};
+use std::{
+    marker::PhantomData,
+    sync::{Arc, Mutex},
+};
+
+/// A handle that removes the compacted entries of an [`OmniPaxos`](crate::OmniPaxos) server from
+/// its storage, created with [`OmniPaxos::trim_worker`](crate::OmniPaxos::trim_worker). When the
+/// `trim_mode` is [`TrimMode::Worker`](crate::TrimMode::Worker), `trim()` and `snapshot()` only
+/// record up to which index the log was compacted, and the application calls [`TrimWorker::run`],
+/// e.g., periodically on a background thread or after each compaction on its executor.
+///
+/// The worker locks the storage while it trims, so the server waits for a running trim before it
+/// can access its storage again. The worker can be cloned cheaply and moved to other threads.
+pub struct TrimWorker<T, B>
+where
+    T: Entry,
+    B: Storage<T>,
+{
+    storage: Arc<Mutex<B>>,
+    deferred_trim: Arc<DeferredTrim>,
+    _t: PhantomData<T>,
+}
+
+impl<T, B> Clone for TrimWorker<T, B>
+where
+    T: Entry,
+    B: Storage<T>,
+{
+    fn clone(&self) -> Self {
+        Self {
+            storage: Arc::clone(&self.storage),
+            deferred_trim: Arc::clone(&self.deferred_trim),
+            _t: PhantomData,
+        }
+    }
+}
+
+impl<T, B> TrimWorker<T, B>
+where
+    T: Entry,
+    B: Storage<T>,
+{
+    pub(crate) fn with(storage: Arc<Mutex<B>>, deferred_trim: Arc<DeferredTrim>) -> Self {
+        Self {
+            storage,
+            deferred_trim,
+            _t: PhantomData,
+        }
+    }
+
+    /// Returns whether there are compacted entries that have not been removed from the storage.
+    pub fn is_pending(&self) -> bool {
+        self.deferred_trim.is_pending()
+    }
+
+    /// Removes the compacted entries from the storage. Returns the index up to which the entries
+    /// were removed, or `None` if there was nothing to remove. If the trim fails, it is retried
+    /// on the next call.
+    pub fn run(&self) -> StorageResult<Option<usize>> {
+        self.deferred_trim.run(&self.storage)
+    }
+}
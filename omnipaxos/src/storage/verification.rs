@@ -0,0 +1,114 @@
+use crate::storage::{Entry, Storage};
+
+/// A corruption or read failure found while verifying the stored log.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum VerificationFailure {
+    /// The stored snapshot could not be verified. Contains the storage error.
+    Snapshot(String),
+    /// The stored log entries in the index interval of [from, to) could not be verified.
+    Entries {
+        /// The first index of the verified interval.
+        from: usize,
+        /// The end (exclusive) of the verified interval.
+        to: usize,
+        /// The storage error.
+        error: String,
+    },
+}
+
+/// The results of the background verification of the stored snapshot and log entries.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct VerificationReport {
+    /// The number of completed passes over the whole stored log.
+    pub num_passes: u64,
+    /// The total number of log entries that have been verified.
+    pub num_verified_entries: u64,
+    /// The total number of failed verifications.
+    pub num_failures: u64,
+    /// The most recent failed verification.
+    pub last_failure: Option<VerificationFailure>,
+}
+
+/// Verifies the stored log in rolling batches: every run verifies the next `batch_size` entries
+/// and the snapshot is verified at the start of every pass over the log.
+pub(crate) struct StorageVerifier {
+    batch_size: usize,
+    next_idx: usize,
+    report: VerificationReport,
+}
+
+impl StorageVerifier {
+    pub(crate) fn with(batch_size: usize) -> Self {
+        Self {
+            batch_size,
+            next_idx: 0,
+            report: VerificationReport::default(),
+        }
+    }
+
+    pub(crate) fn get_report(&self) -> &VerificationReport {
+        &self.report
+    }
+
+    /// Verifies the next batch of `storage`. Returns the failures found in this run.
+    pub(crate) fn run<T, B>(&mut self, storage: &B) -> Vec<VerificationFailure>
+    where
+        T: Entry,
+        B: Storage<T>,
+    {
+        let mut failures = vec![];
+        let log_bounds = storage.get_compacted_idx().and_then(|compacted_idx| {
+            storage
+                .get_log_len()
+                .map(|log_len| (compacted_idx, compacted_idx + log_len))
+        });
+        let (compacted_idx, log_end) = match log_bounds {
+            Ok(bounds) => bounds,
+            Err(e) => {
+                failures.push(VerificationFailure::Entries {
+                    from: self.next_idx,
+                    to: self.next_idx,
+                    error: e.to_string(),
+                });
+                self.record(&failures);
+                return failures;
+            }
+        };
+        if self.next_idx <= compacted_idx {
+            if compacted_idx > 0 {
+                if let Err(e) = storage.verify_snapshot() {
+                    failures.push(VerificationFailure::Snapshot(e.to_string()));
+                }
+            }
+            self.next_idx = compacted_idx;
+        }
+        let from = self.next_idx.min(log_end);
+        let to = (from + self.batch_size).min(log_end);
+        if from < to {
+            match storage.verify_entries(from, to) {
+                Ok(()) => self.report.num_verified_entries += (to - from) as u64,
+                Err(e) => failures.push(VerificationFailure::Entries {
+                    from,
+                    to,
+                    error: e.to_string(),
+                }),
+            }
+        }
+        if to >= log_end {
+            // Start the next pass from the beginning of the log
+            self.report.num_passes += 1;
+            self.next_idx = 0;
+        } else {
+            self.next_idx = to;
+        }
+        self.record(&failures);
+        failures
+    }
+
+    fn record(&mut self, failures: &[VerificationFailure]) {
+        if let Some(last) = failures.last() {
+            self.report.num_failures += failures.len() as u64;
+            self.report.last_failure = Some(last.clone());
+        }
+    }
+}
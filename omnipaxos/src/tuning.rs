@@ -0,0 +1,221 @@
+use crate::{FollowerAppendPolicy, ServerConfig};
+use std::fmt;
+
+/// How much attention a [`TuningNote`] deserves.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub enum TuningSeverity {
+    /// A trade-off that the configuration makes deliberately.
+    Info,
+    /// A combination of settings that is likely unintended, e.g., because a setting has no effect
+    /// or works against another one.
+    Warning,
+}
+
+/// An explanation of a trade-off made by a [`ServerConfig`].
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct TuningNote {
+    /// How much attention the note deserves.
+    pub severity: TuningSeverity,
+    /// The fields of the `ServerConfig` that the note is about.
+    pub fields: &'static [&'static str],
+    /// The explanation of the trade-off.
+    pub message: String,
+}
+
+impl fmt::Display for TuningNote {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{:?} [{}]: {}",
+            self.severity,
+            self.fields.join(", "),
+            self.message
+        )
+    }
+}
+
+/// The trade-offs made by a [`ServerConfig`], see [`ServerConfig::tuning_report`].
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct TuningReport {
+    /// The notes on the configuration, with the warnings first.
+    pub notes: Vec<TuningNote>,
+}
+
+impl TuningReport {
+    /// Returns the notes that point out likely unintended settings.
+    pub fn warnings(&self) -> impl Iterator<Item = &TuningNote> {
+        self.notes
+            .iter()
+            .filter(|n| n.severity == TuningSeverity::Warning)
+    }
+
+    fn note(&mut self, severity: TuningSeverity, fields: &'static [&'static str], message: String) {
+        self.notes.push(TuningNote {
+            severity,
+            fields,
+            message,
+        });
+    }
+}
+
+impl fmt::Display for TuningReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for note in &self.notes {
+            writeln!(f, "{}", note)?;
+        }
+        Ok(())
+    }
+}
+
+/// An election timeout below this many ticks reacts to short hiccups of the network or of the
+/// leader's process as if the leader had failed.
+const SHORT_ELECTION_TIMEOUT: u64 = 5;
+
+pub(crate) fn report(config: &ServerConfig) -> TuningReport {
+    use TuningSeverity::*;
+    let mut report = TuningReport::default();
+    if config.batch_size > 1 {
+        report.note(
+            Info,
+            &["batch_size", "batch_byte_size", "flush_batch_tick_timeout"],
+            format!(
+                "Entries are appended in batches of {} to raise the throughput. Under low load, \
+                 an entry waits up to {} ticks for its batch to fill before it is replicated.",
+                config.batch_size, config.flush_batch_tick_timeout
+            ),
+        );
+        if config.flush_batch_tick_timeout > config.election_tick_timeout {
+            report.note(
+                Warning,
+                &["flush_batch_tick_timeout", "election_tick_timeout"],
+                format!(
+                    "A partially filled batch waits {} ticks, longer than the election timeout of \
+                     {} ticks. Lower flush_batch_tick_timeout to bound the latency under low load.",
+                    config.flush_batch_tick_timeout, config.election_tick_timeout
+                ),
+            );
+        }
+    } else if config.batch_byte_size > 0 {
+        report.note(
+            Warning,
+            &["batch_byte_size", "batch_size"],
+            "batch_byte_size has no effect since batch_size is 1, i.e., every entry is appended \
+             on its own."
+                .to_string(),
+        );
+    }
+    if config.forward_batch_size > 1 {
+        report.note(
+            Info,
+            &[
+                "forward_batch_size",
+                "forward_batch_byte_size",
+                "forward_batch_tick_timeout",
+            ],
+            format!(
+                "Proposals appended on a follower are forwarded to the leader in batches of {} to \
+                 save the leader messages. Under low load, a proposal waits up to {} ticks before \
+                 it is forwarded.",
+                config.forward_batch_size, config.forward_batch_tick_timeout
+            ),
+        );
+        if config.forward_batch_tick_timeout > config.election_tick_timeout {
+            report.note(
+                Warning,
+                &["forward_batch_tick_timeout", "election_tick_timeout"],
+                format!(
+                    "A partially filled forward batch waits {} ticks, longer than the election \
+                     timeout of {} ticks. Lower forward_batch_tick_timeout to bound the latency \
+                     under low load.",
+                    config.forward_batch_tick_timeout, config.election_tick_timeout
+                ),
+            );
+        }
+    } else if config.forward_batch_byte_size > 0 {
+        report.note(
+            Warning,
+            &["forward_batch_byte_size", "forward_batch_size"],
+            "forward_batch_byte_size has no effect since forward_batch_size is 1, i.e., every \
+             proposal is forwarded on its own."
+                .to_string(),
+        );
+    }
+    if config.election_tick_timeout < SHORT_ELECTION_TIMEOUT {
+        report.note(
+            Info,
+            &["election_tick_timeout"],
+            format!(
+                "A failed leader is replaced within {} ticks, but delays of the network or of the \
+                 leader longer than that cause unnecessary leader changes.",
+                config.election_tick_timeout
+            ),
+        );
+    }
+    if config.resend_message_tick_timeout < config.election_tick_timeout {
+        report.note(
+            Warning,
+            &["resend_message_tick_timeout", "election_tick_timeout"],
+            format!(
+                "Messages are resent after {} ticks, before the election timeout of {} ticks, \
+                 which adds traffic on a slow network without speeding up the recovery.",
+                config.resend_message_tick_timeout, config.election_tick_timeout
+            ),
+        );
+    }
+    if config.reorder_window > 0 {
+        report.note(
+            Info,
+            &["reorder_window", "resend_message_tick_timeout"],
+            format!(
+                "Up to {} reordered messages are tolerated without resynchronizing, but a lost \
+                 message is only detected after up to {} ticks.",
+                config.reorder_window,
+                2 * config.resend_message_tick_timeout
+            ),
+        );
+    }
+    if config.sync_batch_size == 0 && config.sync_batch_byte_size == 0 {
+        report.note(
+            Info,
+            &["sync_batch_size", "sync_batch_byte_size"],
+            "A follower that is behind is synchronized with a single message, which holds its \
+             whole missing log in memory."
+                .to_string(),
+        );
+    }
+    if config.decided_cache_size == 0 {
+        report.note(
+            Info,
+            &["decided_cache_size"],
+            "Every read of recent entries and every synchronization of a follower reads from \
+             the storage."
+                .to_string(),
+        );
+    }
+    if let Some(pull_tick_timeout) = config.pull_tick_timeout {
+        report.note(
+            Info,
+            &["pull_tick_timeout"],
+            format!(
+                "New entries reach this server up to {} ticks later than with the leader pushing \
+                 them, which delays the decisions if this server is needed for a quorum.",
+                pull_tick_timeout
+            ),
+        );
+    }
+    if let FollowerAppendPolicy::Buffer { tick_timeout } = config.follower_append_policy {
+        if tick_timeout < config.election_tick_timeout {
+            report.note(
+                Warning,
+                &["follower_append_policy", "election_tick_timeout"],
+                format!(
+                    "Entries buffered without a leader are dropped after {} ticks, before a new \
+                     leader can be elected after {} ticks.",
+                    tick_timeout, config.election_tick_timeout
+                ),
+            );
+        }
+    }
+    report.notes.sort_by_key(|n| std::cmp::Reverse(n.severity));
+    report
+}
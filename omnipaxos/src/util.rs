@@ -1,11 +1,27 @@
 use super::{
     ballot_leader_election::Ballot,
-    messages::sequence_paxos::Promise,
+    messages::{
+        ballot_leader_election::BLEMessage,
+        sequence_paxos::{PaxosMessage, Promise, ProposalOrigin},
+    },
     storage::{Entry, SnapshotType, StopSign},
 };
+#[cfg(feature = "spill")]
+use crate::spill::{SpillFile, SpilledMsg};
+use crate::{
+    messages::sequence_paxos::DecidedFingerprint, metrics::Metrics, ClockJump, ClusterConfig,
+    CommitLatency, DivergenceAlarm, MisbehavingPeer, ProposalAffinity, RejectedMessageCause,
+    SequenceRecoveryStats, SlowFollowerCause, SlowFollowerEvent, SlowStorageEvent,
+};
 #[cfg(feature = "serde")]
 use serde::{Deserialize, Serialize};
-use std::{cmp::Ordering, fmt::Debug, marker::PhantomData};
+use std::{
+    cmp::Ordering,
+    collections::{BTreeMap, HashMap, VecDeque},
+    fmt::Debug,
+    marker::PhantomData,
+    time::{Duration, Instant, SystemTime},
+};
 
 /// Struct used to help another server synchronize their log with the current state of our own log.
 #[derive(Clone, Debug)]
@@ -22,6 +38,29 @@ where
     pub sync_idx: usize,
     /// The accepted StopSign.
     pub stopsign: Option<StopSign>,
+    /// The ballots in which the entries of `suffix`, and of `decided_snapshot` if it exists, were
+    /// first appended, as the log index of the first entry of each ballot, or `None` if the
+    /// ballot is unknown. Empty if the sender does not track the ballots, see
+    /// `track_entry_ballots` in [`ServerConfig`](crate::ServerConfig).
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub suffix_ballots: Vec<(usize, Option<Ballot>)>,
+}
+
+impl<T> LogSync<T>
+where
+    T: Entry,
+{
+    /// The approximate size in bytes of the heap data of the log sync. The snapshot only counts
+    /// with its inline size.
+    pub(crate) fn size_hint(&self) -> usize {
+        let suffix: usize = self.suffix.iter().map(|e| e.size_hint()).sum();
+        suffix
+            + self
+                .decided_snapshot
+                .as_ref()
+                .map_or(0, std::mem::size_of_val)
+            + self.suffix_ballots.len() * std::mem::size_of::<(usize, Option<Ballot>)>()
+    }
 }
 
 #[derive(Debug, Clone, Default)]
@@ -80,13 +119,27 @@ where
     // the sequence number of accepts for each follower where AcceptSync has sequence number = 1
     follower_seq_nums: Vec<SequenceNumber>,
     pub accepted_indexes: Vec<usize>,
+    // the decided indexes last reported by each follower
+    pub decided_indexes: Vec<usize>,
     max_promise_meta: PromiseMetaData,
     max_promise_sync: Option<LogSync<T>>,
-    batch_accept_meta: Vec<Option<(Ballot, usize)>>, //  index in outgoing
+    batch_accept_meta: Vec<Option<(Ballot, OutgoingIdx)>>, //  position in outgoing
+    // followers that are synchronized in portions and have not received their final AcceptSync yet
+    partially_synced: Vec<bool>,
+    // followers that pull new entries instead of having them pushed
+    pull_followers: Vec<bool>,
     pub max_pid: usize,
     // The number of promises needed in the prepare phase to become synced and
     // the number of accepteds needed in the accept phase to decide an entry.
     pub quorum: Quorum,
+    // the designated hot-standby leader
+    pub standby: Option<NodeId>,
+    // the servers that durably promised not to prepare the next round for another server than the standby
+    standby_promised: Vec<bool>,
+    // whether the standby can take over without a prepare phase, after which every decided entry must be accepted by it
+    pub standby_ready: bool,
+    // the round of the previous leader while this server takes over from it as the hot-standby leader
+    pub takeover_from: Option<Ballot>,
 }
 
 impl<T> LeaderState<T>
@@ -99,11 +152,18 @@ where
             promises_meta: vec![PromiseState::NotPromised; max_pid],
             follower_seq_nums: vec![SequenceNumber::default(); max_pid],
             accepted_indexes: vec![0; max_pid],
+            decided_indexes: vec![0; max_pid],
             max_promise_meta: PromiseMetaData::default(),
             max_promise_sync: None,
             batch_accept_meta: vec![None; max_pid],
+            partially_synced: vec![false; max_pid],
+            pull_followers: vec![false; max_pid],
             max_pid,
             quorum,
+            standby: None,
+            standby_promised: vec![false; max_pid],
+            standby_ready: false,
+            takeover_from: None,
         }
     }
 
@@ -139,17 +199,18 @@ where
             self.max_promise_meta = promise_meta.clone();
             self.max_promise_sync = prom.log_sync;
         }
+        self.decided_indexes[Self::pid_to_idx(from)] = promise_meta.decided_idx;
         self.promises_meta[Self::pid_to_idx(from)] = PromiseState::Promised(promise_meta);
-        let num_promised = self
-            .promises_meta
-            .iter()
-            .filter(|p| matches!(p, PromiseState::Promised(_)))
-            .count();
-        self.quorum.is_prepare_quorum(num_promised)
+        let promised = self.promises_meta.iter().filter_map(|p| match p {
+            PromiseState::Promised(meta) => Some(meta.pid),
+            _ => None,
+        });
+        self.quorum.is_prepare_quorum(promised)
     }
 
     pub fn reset_promise(&mut self, pid: NodeId) {
         self.promises_meta[Self::pid_to_idx(pid)] = PromiseState::NotPromised;
+        self.pull_followers[Self::pid_to_idx(pid)] = false;
     }
 
     /// Node `pid` seen with ballot greater than my ballot
@@ -157,6 +218,37 @@ where
         self.promises_meta[Self::pid_to_idx(pid)] = PromiseState::PromisedHigher;
     }
 
+    /// Whether the servers that have not promised a higher ballot can still form a prepare quorum.
+    pub fn can_form_prepare_quorum(&self) -> bool {
+        let preparable = self
+            .promises_meta
+            .iter()
+            .enumerate()
+            .filter(|(_, p)| !matches!(p, PromiseState::PromisedHigher))
+            .map(|(idx, _)| (idx + 1) as NodeId);
+        self.quorum.is_prepare_quorum(preparable)
+    }
+
+    pub fn set_standby_promised(&mut self, pid: NodeId) {
+        self.standby_promised[Self::pid_to_idx(pid)] = true;
+    }
+
+    /// Whether the servers that promised the standby's round to it form an accept quorum, so that
+    /// no prepare quorum of that round can be formed by another server.
+    pub fn is_standby_promise_quorum(&self) -> bool {
+        let promised = self
+            .standby_promised
+            .iter()
+            .enumerate()
+            .filter(|(_, promised)| **promised)
+            .map(|(idx, _)| (idx + 1) as NodeId);
+        self.quorum.is_accept_quorum(promised)
+    }
+
+    pub fn is_standby_promised(&self, pid: NodeId) -> bool {
+        self.standby_promised[Self::pid_to_idx(pid)]
+    }
+
     pub fn take_max_promise_sync(&mut self) -> Option<LogSync<T>> {
         std::mem::take(&mut self.max_promise_sync)
     }
@@ -176,10 +268,10 @@ where
             .unwrap_or_default()
     }
 
-    pub fn get_promise_meta(&self, pid: NodeId) -> &PromiseMetaData {
+    pub fn get_promise_meta(&self, pid: NodeId) -> Option<&PromiseMetaData> {
         match &self.promises_meta[Self::pid_to_idx(pid)] {
-            PromiseState::Promised(metadata) => metadata,
-            _ => panic!("No Metadata found for promised follower"),
+            PromiseState::Promised(metadata) => Some(metadata),
+            _ => None,
         }
     }
 
@@ -194,6 +286,15 @@ where
         self.batch_accept_meta = vec![None; self.max_pid];
     }
 
+    /// Forgets the cached messages that are queued to `to`.
+    pub fn reset_batch_accept_meta_to(&mut self, to: NodeId) {
+        for meta in self.batch_accept_meta.iter_mut() {
+            if matches!(meta, Some((_, (receiver, _))) if *receiver == to) {
+                *meta = None;
+            }
+        }
+    }
+
     pub fn get_promised_followers(&self) -> Vec<NodeId> {
         self.promises_meta
             .iter()
@@ -207,6 +308,18 @@ where
             .collect()
     }
 
+    /// The promised followers that have new entries pushed to them, i.e., not in pull mode.
+    pub fn get_push_followers(&self) -> Vec<NodeId> {
+        self.get_promised_followers()
+            .into_iter()
+            .filter(|pid| !self.pull_followers[Self::pid_to_idx(*pid)])
+            .collect()
+    }
+
+    pub fn set_pull_follower(&mut self, pid: NodeId) {
+        self.pull_followers[Self::pid_to_idx(pid)] = true;
+    }
+
     /// The pids of peers which have not promised a higher ballot than mine.
     pub fn get_preparable_peers(&self) -> Vec<NodeId> {
         self.promises_meta
@@ -219,7 +332,7 @@ where
             .collect()
     }
 
-    pub fn set_batch_accept_meta(&mut self, pid: NodeId, idx: Option<usize>) {
+    pub fn set_batch_accept_meta(&mut self, pid: NodeId, idx: Option<OutgoingIdx>) {
         let meta = idx.map(|x| (self.n_leader, x));
         self.batch_accept_meta[Self::pid_to_idx(pid)] = meta;
     }
@@ -228,7 +341,11 @@ where
         self.accepted_indexes[Self::pid_to_idx(pid)] = idx;
     }
 
-    pub fn get_batch_accept_meta(&self, pid: NodeId) -> Option<(Ballot, usize)> {
+    pub fn set_follower_decided_idx(&mut self, pid: NodeId, idx: usize) {
+        self.decided_indexes[Self::pid_to_idx(pid)] = idx;
+    }
+
+    pub fn get_batch_accept_meta(&self, pid: NodeId) -> Option<(Ballot, OutgoingIdx)> {
         self.batch_accept_meta
             .get(Self::pid_to_idx(pid))
             .unwrap()
@@ -236,6 +353,14 @@ where
             .copied()
     }
 
+    pub fn set_partially_synced(&mut self, pid: NodeId, partially_synced: bool) {
+        self.partially_synced[Self::pid_to_idx(pid)] = partially_synced;
+    }
+
+    pub fn is_partially_synced(&self, pid: NodeId) -> bool {
+        self.partially_synced[Self::pid_to_idx(pid)]
+    }
+
     pub fn get_decided_idx(&self, pid: NodeId) -> Option<usize> {
         match self.promises_meta.get(Self::pid_to_idx(pid)).unwrap() {
             PromiseState::Promised(metadata) => Some(metadata.decided_idx),
@@ -248,12 +373,18 @@ where
     }
 
     pub fn is_chosen(&self, idx: usize) -> bool {
-        let num_accepted = self
+        let accepted = self
             .accepted_indexes
             .iter()
-            .filter(|la| **la >= idx)
-            .count();
-        self.quorum.is_accept_quorum(num_accepted)
+            .enumerate()
+            .filter(|(_, la)| **la >= idx)
+            .map(|(pid_idx, _)| (pid_idx + 1) as NodeId);
+        // A ready standby takes over with its own log, so it must have accepted every decided entry
+        let standby_accepted = match self.standby {
+            Some(standby) if self.standby_ready => self.get_accepted_idx(standby) >= idx,
+            _ => true,
+        };
+        standby_accepted && self.quorum.is_accept_quorum(accepted)
     }
 }
 
@@ -292,6 +423,121 @@ where
     }
 }
 
+impl<T> LogEntry<T>
+where
+    T: Entry,
+{
+    /// Borrows the content of this entry as a [`LogEntryRef`].
+    pub fn as_entry_ref(&self) -> LogEntryRef<'_, T> {
+        match self {
+            LogEntry::Decided(e) => LogEntryRef::Decided(e),
+            LogEntry::Undecided(e) => LogEntryRef::Undecided(e),
+            LogEntry::Trimmed(idx) => LogEntryRef::Trimmed(*idx),
+            LogEntry::Snapshotted(s) => LogEntryRef::Snapshotted(s),
+            LogEntry::StopSign(ss, decided) => LogEntryRef::StopSign(ss, *decided),
+        }
+    }
+}
+
+/// The entry read in the log, borrowed instead of cloned. See [`LogEntry`] for the variants.
+#[derive(Debug, Clone, Copy)]
+pub enum LogEntryRef<'a, T>
+where
+    T: Entry,
+{
+    /// The entry is decided.
+    Decided(&'a T),
+    /// The entry is NOT decided. Might be removed from the log at a later time.
+    Undecided(&'a T),
+    /// The entry has been trimmed.
+    Trimmed(TrimmedIndex),
+    /// The entry has been snapshotted.
+    Snapshotted(&'a SnapshottedEntry<T>),
+    /// This Sequence Paxos instance has been stopped for reconfiguration. The accompanying bool
+    /// indicates whether the reconfiguration has been decided or not.
+    StopSign(&'a StopSign, bool),
+}
+
+impl<'a, T> LogEntryRef<'a, T>
+where
+    T: Entry,
+{
+    pub(crate) fn with(entry: &'a T, decided: bool) -> Self {
+        match decided {
+            true => LogEntryRef::Decided(entry),
+            false => LogEntryRef::Undecided(entry),
+        }
+    }
+
+    /// Clones the borrowed entry into an owned [`LogEntry`].
+    pub fn to_log_entry(self) -> LogEntry<T> {
+        match self {
+            LogEntryRef::Decided(e) => LogEntry::Decided(e.clone()),
+            LogEntryRef::Undecided(e) => LogEntry::Undecided(e.clone()),
+            LogEntryRef::Trimmed(idx) => LogEntry::Trimmed(idx),
+            LogEntryRef::Snapshotted(s) => LogEntry::Snapshotted(s.clone()),
+            LogEntryRef::StopSign(ss, decided) => LogEntry::StopSign(ss.clone(), decided),
+        }
+    }
+}
+
+/// An entry read in the log together with its absolute index in the log.
+#[derive(Debug, Clone)]
+pub struct IndexedLogEntry<T>
+where
+    T: Entry,
+{
+    /// The index of the entry in the log. A `Trimmed` or `Snapshotted` entry covers all indexes
+    /// from `idx` up to its compacted index, and the entry read after it is at the compacted index.
+    pub idx: usize,
+    /// The entry read at `idx`.
+    pub entry: LogEntry<T>,
+}
+
+impl<T> IndexedLogEntry<T>
+where
+    T: Entry,
+{
+    /// The index of the earlier entry that this entry depends on, see [`Entry::dependency`], e.g.,
+    /// to apply the entries of independent streams concurrently. `None` if the entry declares no
+    /// dependency or is not a `Decided` or `Undecided` entry.
+    pub fn dependency(&self) -> Option<usize> {
+        match &self.entry {
+            LogEntry::Decided(e) | LogEntry::Undecided(e) => e.dependency(),
+            _ => None,
+        }
+    }
+}
+
+impl<T: PartialEq + Entry> PartialEq for IndexedLogEntry<T>
+where
+    <T as Entry>::Snapshot: PartialEq,
+{
+    fn eq(&self, other: &Self) -> bool {
+        self.idx == other.idx && self.entry == other.entry
+    }
+}
+
+/// Attaches the log indexes to `entries` that were read starting at `from_idx`.
+pub(crate) fn with_indexes<T: Entry>(
+    from_idx: usize,
+    entries: Vec<LogEntry<T>>,
+) -> Vec<IndexedLogEntry<T>> {
+    let mut next_idx = from_idx;
+    entries
+        .into_iter()
+        .map(|entry| {
+            let idx = next_idx;
+            next_idx = match &entry {
+                LogEntry::Trimmed(compacted_idx) => *compacted_idx,
+                LogEntry::Snapshotted(s) => s.trimmed_idx,
+                _ => idx + 1,
+            };
+            IndexedLogEntry { idx, entry }
+        })
+        .collect()
+}
+
 /// Convenience struct for checking if a certain index exists, is compacted or is a StopSign.
 #[derive(Debug, Clone)]
 pub(crate) enum IndexEntry {
@@ -333,12 +579,758 @@ where
     }
 }
 
+/// Tracks the time from when the leader appends entries until they are decided.
+#[derive(Debug, Default)]
+pub(crate) struct CommitLatencyTracker {
+    /// The length of the log after each sampled append and the time of the append.
+    pending: VecDeque<(usize, Instant)>,
+    /// The most recent commit latencies.
+    samples: VecDeque<Duration>,
+}
+
+impl CommitLatencyTracker {
+    /// Samples an append after which the length of the log is `log_len`. Appends are not sampled
+    /// while too many sampled appends are pending.
+    pub(crate) fn appended(&mut self, log_len: usize) {
+        if self.pending.len() < defaults::COMMIT_LATENCY_SAMPLES {
+            self.pending.push_back((log_len, Instant::now()));
+        }
+    }
+
+    /// Records the latencies of the sampled appends that are decided with `decided_idx` and
+    /// reports them to `metrics` as the histogram `metric`.
+    pub(crate) fn decided(&mut self, decided_idx: usize, metric: &'static str, metrics: &Metrics) {
+        let now = Instant::now();
+        while let Some(&(log_len, appended)) = self.pending.front() {
+            if log_len > decided_idx {
+                break;
+            }
+            self.pending.pop_front();
+            if self.samples.len() == defaults::COMMIT_LATENCY_SAMPLES {
+                self.samples.pop_front();
+            }
+            let latency = now.duration_since(appended);
+            metrics.histogram(metric, latency.as_secs_f64());
+            self.samples.push_back(latency);
+        }
+    }
+
+    /// Discards the sampled appends that are not decided, e.g., when losing the leadership.
+    pub(crate) fn clear_pending(&mut self) {
+        self.pending.clear();
+    }
+
+    pub(crate) fn get_commit_latency(&self) -> Option<CommitLatency> {
+        if self.samples.is_empty() {
+            return None;
+        }
+        let mut sorted: Vec<Duration> = self.samples.iter().copied().collect();
+        sorted.sort_unstable();
+        let percentile = |p: usize| sorted[(sorted.len() * p).div_ceil(100) - 1];
+        Some(CommitLatency {
+            p50: percentile(50),
+            p99: percentile(99),
+            samples: sorted.len(),
+        })
+    }
+}
+
+/// Counts the proposals received from each server and keeps the latest latency each reported.
+#[derive(Debug, Default)]
+pub(crate) struct ProposalAffinityTracker(HashMap<NodeId, ProposalAffinity>);
+
+impl ProposalAffinityTracker {
+    /// Records `num_proposals` proposals from `pid`, together with the latency it reported.
+    pub(crate) fn proposed(
+        &mut self,
+        pid: NodeId,
+        num_proposals: usize,
+        latency: Option<CommitLatency>,
+    ) {
+        let affinity = self.0.entry(pid).or_default();
+        affinity.proposals += num_proposals as u64;
+        if latency.is_some() {
+            affinity.latency = latency;
+        }
+    }
+
+    pub(crate) fn get(&self) -> HashMap<NodeId, ProposalAffinity> {
+        self.0.clone()
+    }
+}
+
+/// How far a follower is behind the leader.
+#[derive(Debug, Default)]
+struct FollowerLag {
+    /// The leader's accepted index when the follower fell behind, and the tick at which it did.
+    behind_since: Option<(usize, u64)>,
+    /// Whether the follower rejected a message since it fell behind.
+    nacked: bool,
+    slow: bool,
+}
+
+/// Detects followers that are more entries or ticks behind the leader than configured.
+#[derive(Debug, Default)]
+pub(crate) struct SlowFollowerDetector {
+    max_lag: Option<usize>,
+    max_lagging_ticks: Option<u64>,
+    ticks: u64,
+    followers: HashMap<NodeId, FollowerLag>,
+    events: VecDeque<SlowFollowerEvent>,
+}
+
+impl SlowFollowerDetector {
+    pub(crate) fn with(max_lag: Option<usize>, max_lagging_ticks: Option<u64>) -> Self {
+        Self {
+            max_lag,
+            max_lagging_ticks,
+            ..Default::default()
+        }
+    }
+
+    pub(crate) fn is_enabled(&self) -> bool {
+        self.max_lag.is_some() || self.max_lagging_ticks.is_some()
+    }
+
+    pub(crate) fn tick(&mut self) {
+        self.ticks += 1;
+    }
+
+    /// Updates the lag of `pid` given its and the leader's accepted index.
+    pub(crate) fn update(
+        &mut self,
+        pid: NodeId,
+        follower_idx: usize,
+        leader_idx: usize,
+        connected: bool,
+    ) {
+        let ticks = self.ticks;
+        let follower = self.followers.entry(pid).or_default();
+        if follower
+            .behind_since
+            .is_some_and(|(idx, _)| follower_idx >= idx)
+        {
+            // The follower caught up to where it fell behind
+            follower.behind_since = None;
+            follower.nacked = false;
+        }
+        if follower.behind_since.is_none() && follower_idx < leader_idx {
+            follower.behind_since = Some((leader_idx, ticks));
+        }
+        let lag = leader_idx.saturating_sub(follower_idx);
+        let lagging_ticks = follower.behind_since.map_or(0, |(_, since)| ticks - since);
+        let slow = self.max_lag.is_some_and(|max| lag > max)
+            || self
+                .max_lagging_ticks
+                .is_some_and(|max| lagging_ticks >= max);
+        let event = match (follower.slow, slow) {
+            (false, true) => {
+                let cause = if !connected {
+                    SlowFollowerCause::Disconnected
+                } else if follower.nacked {
+                    SlowFollowerCause::Nacks
+                } else {
+                    SlowFollowerCause::NoAcks
+                };
+                SlowFollowerEvent::Slow {
+                    pid,
+                    lag,
+                    lagging_ticks,
+                    cause,
+                }
+            }
+            (true, false) => SlowFollowerEvent::CaughtUp(pid),
+            _ => return,
+        };
+        follower.slow = slow;
+        if self.events.len() == defaults::SLOW_FOLLOWER_EVENTS {
+            self.events.pop_front();
+        }
+        self.events.push_back(event);
+    }
+
+    /// Records that `pid` rejected a message of the leader.
+    pub(crate) fn nacked(&mut self, pid: NodeId) {
+        if let Some(follower) = self.followers.get_mut(&pid) {
+            follower.nacked = true;
+        }
+    }
+
+    /// Forgets the lag of all followers, e.g., when the leadership changes.
+    pub(crate) fn reset(&mut self) {
+        self.followers.clear();
+    }
+
+    pub(crate) fn take_events(&mut self) -> Vec<SlowFollowerEvent> {
+        self.events.drain(..).collect()
+    }
+}
+
+/// Detects the gaps between the calls to `tick()` and the jumps of the wall clock that are larger
+/// than a threshold, e.g., after the process or the VM was paused or the machine hibernated.
+#[derive(Debug, Default)]
+pub(crate) struct ClockJumpDetector {
+    threshold: Option<Duration>,
+    // The time of the monotonic and the wall clock at the previous tick
+    last_tick: Option<(Instant, SystemTime)>,
+    jumps: VecDeque<ClockJump>,
+}
+
+impl ClockJumpDetector {
+    pub(crate) fn with(threshold: Option<Duration>) -> Self {
+        Self {
+            threshold,
+            ..Default::default()
+        }
+    }
+
+    /// Compares the clocks with the previous tick. Returns the jump if there was one.
+    pub(crate) fn tick(&mut self) -> Option<ClockJump> {
+        let threshold = self.threshold?;
+        let now = (Instant::now(), SystemTime::now());
+        let (last_instant, last_time) = self.last_tick.replace(now)?;
+        let elapsed = now.0.duration_since(last_instant);
+        // The monotonic clock does not advance while the machine is suspended, so a hibernation
+        // only shows in the wall clock
+        let jump = match now.1.duration_since(last_time) {
+            _ if elapsed > threshold => ClockJump::Suspended { gap: elapsed },
+            Ok(wall) if wall > elapsed + threshold => ClockJump::WallClockForward {
+                offset: wall - elapsed,
+            },
+            Ok(wall) if wall + threshold < elapsed => ClockJump::WallClockBackward {
+                offset: elapsed - wall,
+            },
+            Err(e) if e.duration() + elapsed > threshold => ClockJump::WallClockBackward {
+                offset: e.duration() + elapsed,
+            },
+            _ => return None,
+        };
+        if self.jumps.len() == defaults::CLOCK_JUMPS {
+            self.jumps.pop_front();
+        }
+        self.jumps.push_back(jump);
+        Some(jump)
+    }
+
+    pub(crate) fn take_jumps(&mut self) -> Vec<ClockJump> {
+        self.jumps.drain(..).collect()
+    }
+}
+
+/// Detects that the storage of the leader has been slower than configured for a sustained number
+/// of ticks.
+#[derive(Debug, Default)]
+pub(crate) struct SlowStorageDetector {
+    max_latency: Option<Duration>,
+    max_slow_ticks: u64,
+    step_down: bool,
+    // The number of consecutive ticks as the leader in which the appends were slow
+    slow_ticks: u64,
+    // Whether the storage was reported as slow and has not been fast since
+    reported: bool,
+    events: VecDeque<SlowStorageEvent>,
+}
+
+impl SlowStorageDetector {
+    pub(crate) fn with(
+        max_latency: Option<Duration>,
+        max_slow_ticks: u64,
+        step_down: bool,
+    ) -> Self {
+        Self {
+            max_latency,
+            max_slow_ticks,
+            step_down,
+            ..Default::default()
+        }
+    }
+
+    /// Updates the detector with the average latency of the appends since the last tick, if there
+    /// were any. Returns whether the leader should step down.
+    pub(crate) fn tick(&mut self, latency: Option<Duration>, is_leader: bool) -> bool {
+        let (Some(max_latency), Some(latency)) = (self.max_latency, latency) else {
+            return false;
+        };
+        if latency <= max_latency {
+            self.slow_ticks = 0;
+            if self.reported {
+                self.reported = false;
+                self.push_event(SlowStorageEvent::Recovered);
+            }
+            return false;
+        }
+        if !is_leader {
+            self.slow_ticks = 0;
+            return false;
+        }
+        self.slow_ticks += 1;
+        // Only step down once while the storage is slow, so that a server that is elected again
+        // does not keep giving up its leadership
+        if self.reported || self.slow_ticks < self.max_slow_ticks {
+            return false;
+        }
+        self.reported = true;
+        self.push_event(SlowStorageEvent::Slow {
+            latency,
+            stepped_down: self.step_down,
+        });
+        self.step_down
+    }
+
+    fn push_event(&mut self, event: SlowStorageEvent) {
+        if self.events.len() == defaults::SLOW_STORAGE_EVENTS {
+            self.events.pop_front();
+        }
+        self.events.push_back(event);
+    }
+
+    pub(crate) fn take_events(&mut self) -> Vec<SlowStorageEvent> {
+        self.events.drain(..).collect()
+    }
+}
+
+/// Counts the messages rejected from each peer and reports the peers whose rejected messages reach
+/// a multiple of the `threshold`.
+#[derive(Debug, Default)]
+pub(crate) struct RejectedMessageTracker {
+    threshold: u64,
+    rejected: HashMap<NodeId, u64>,
+    events: VecDeque<MisbehavingPeer>,
+}
+
+impl RejectedMessageTracker {
+    pub(crate) fn with(threshold: u64) -> Self {
+        Self {
+            threshold,
+            ..Default::default()
+        }
+    }
+
+    /// Records that a message from `pid` was rejected because of `cause`.
+    pub(crate) fn reject(&mut self, pid: NodeId, cause: RejectedMessageCause) {
+        let rejected = self.rejected.entry(pid).or_default();
+        *rejected += 1;
+        if rejected.is_multiple_of(self.threshold) {
+            if self.events.len() == defaults::MISBEHAVING_PEER_EVENTS {
+                self.events.pop_front();
+            }
+            self.events.push_back(MisbehavingPeer {
+                pid,
+                rejected: *rejected,
+                cause,
+            });
+        }
+    }
+
+    pub(crate) fn get(&self, pid: NodeId) -> u64 {
+        self.rejected.get(&pid).copied().unwrap_or_default()
+    }
+
+    pub(crate) fn take_events(&mut self) -> Vec<MisbehavingPeer> {
+        self.events.drain(..).collect()
+    }
+}
+
+/// Counts the sequence-number recovery events of the accept-phase messages exchanged with each
+/// peer.
+#[derive(Debug, Default)]
+pub(crate) struct SequenceRecoveryTracker(HashMap<NodeId, SequenceRecoveryStats>);
+
+impl SequenceRecoveryTracker {
+    /// Records that a message from the leader `pid` was dropped because it was from a previous
+    /// session.
+    pub(crate) fn outdated(&mut self, pid: NodeId) {
+        self.0.entry(pid).or_default().outdated_messages += 1;
+    }
+
+    /// Records that a message from the leader `pid` revealed that preceding messages were lost.
+    pub(crate) fn dropped_preceding(&mut self, pid: NodeId) {
+        self.0.entry(pid).or_default().dropped_preceding_messages += 1;
+    }
+
+    /// Records that the accept sequence with the follower `pid` was restarted.
+    pub(crate) fn session_reset(&mut self, pid: NodeId) {
+        self.0.entry(pid).or_default().session_resets += 1;
+    }
+
+    pub(crate) fn get_all(&self) -> HashMap<NodeId, SequenceRecoveryStats> {
+        self.0.clone()
+    }
+}
+
+/// Returns the fingerprint of `entries`, or `None` if one of them has no fingerprint.
+pub(crate) fn fingerprint_entries<T: Entry>(entries: &[T]) -> Option<u64> {
+    // FNV-1a, as the fingerprint must be the same on all servers regardless of their Rust version
+    let mut hash: u64 = 0xcbf29ce484222325;
+    for entry in entries {
+        for b in entry.fingerprint()?.to_le_bytes() {
+            hash ^= b as u64;
+            hash = hash.wrapping_mul(0x100000001b3);
+        }
+    }
+    Some(hash)
+}
+
+/// Keeps track of the decided entries fingerprinted as the leader and of the fingerprint from the
+/// leader that this server has not decided the entries of yet, and reports the fingerprints that
+/// differ from the local log.
+#[derive(Debug, Default)]
+pub(crate) struct DivergenceDetector {
+    // The index from which the next fingerprint is taken as the leader
+    next_idx: usize,
+    pending: Option<(NodeId, DecidedFingerprint)>,
+    alarms: VecDeque<DivergenceAlarm>,
+}
+
+impl DivergenceDetector {
+    /// Starts fingerprinting the entries decided after `decided_idx`, e.g., when becoming the leader.
+    pub(crate) fn reset(&mut self, decided_idx: usize) {
+        self.next_idx = decided_idx;
+        self.pending = None;
+    }
+
+    pub(crate) fn get_next_idx(&self) -> usize {
+        self.next_idx
+    }
+
+    pub(crate) fn set_next_idx(&mut self, idx: usize) {
+        self.next_idx = idx;
+    }
+
+    pub(crate) fn set_pending(&mut self, leader: NodeId, fp: DecidedFingerprint) {
+        self.pending = Some((leader, fp));
+    }
+
+    pub(crate) fn take_pending(&mut self) -> Option<(NodeId, DecidedFingerprint)> {
+        self.pending.take()
+    }
+
+    pub(crate) fn alarm(&mut self, alarm: DivergenceAlarm) {
+        if self.alarms.len() == defaults::DIVERGENCE_ALARMS {
+            self.alarms.pop_front();
+        }
+        self.alarms.push_back(alarm);
+    }
+
+    pub(crate) fn take_alarms(&mut self) -> Vec<DivergenceAlarm> {
+        self.alarms.drain(..).collect()
+    }
+}
+
+/// Batches the proposals that a follower forwards to the leader, so that the leader handles one
+/// message for many proposals instead of one per `append()`.
+#[derive(Debug)]
+pub(crate) struct ForwardBatch<T> {
+    entries: Vec<T>,
+    // The origins of the batched proposals that were appended with a token or a deadline
+    origins: Vec<ProposalOrigin>,
+    // The approximate size in bytes of the batched proposals
+    bytes: usize,
+    // The number of proposals at which the batch is forwarded
+    max_size: usize,
+    // If not 0, the approximate size in bytes that a batch does not grow beyond
+    max_bytes: usize,
+}
+
+impl<T> ForwardBatch<T>
+where
+    T: Entry,
+{
+    pub(crate) fn with(max_size: usize, max_bytes: usize) -> Self {
+        Self {
+            entries: vec![],
+            origins: vec![],
+            bytes: 0,
+            max_size,
+            max_bytes,
+        }
+    }
+
+    /// Adds the proposals `entries`, some of which were appended with a token as described by
+    /// `origins`, and returns the batches that are ready to be forwarded. The batch is forwarded
+    /// before `entries` if they would make it larger than `max_bytes`, and after them if it is
+    /// full. The `entries` are never split, so that they are decided contiguously.
+    pub(crate) fn push(
+        &mut self,
+        mut entries: Vec<T>,
+        origins: Vec<ProposalOrigin>,
+    ) -> Vec<(Vec<T>, Vec<ProposalOrigin>)> {
+        let mut ready = vec![];
+        let bytes: usize = entries.iter().map(|e| e.size_hint()).sum();
+        if self.max_bytes != 0 && !self.is_empty() && self.bytes + bytes > self.max_bytes {
+            ready.push(self.take());
+        }
+        let offset = self.entries.len();
+        self.origins
+            .extend(origins.into_iter().map(|o| ProposalOrigin {
+                idx: offset + o.idx,
+                ..o
+            }));
+        self.entries.append(&mut entries);
+        self.bytes += bytes;
+        if self.entries.len() >= self.max_size
+            || (self.max_bytes != 0 && self.bytes >= self.max_bytes)
+        {
+            ready.push(self.take());
+        }
+        ready
+    }
+
+    pub(crate) fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    /// The approximate size in bytes of the batched proposals.
+    pub(crate) fn size_hint(&self) -> usize {
+        self.bytes
+    }
+
+    /// Takes the batched proposals and their origins.
+    pub(crate) fn take(&mut self) -> (Vec<T>, Vec<ProposalOrigin>) {
+        self.bytes = 0;
+        (
+            std::mem::take(&mut self.entries),
+            std::mem::take(&mut self.origins),
+        )
+    }
+
+    /// Removes and returns the batched proposals whose deadline has passed at `now`.
+    pub(crate) fn take_expired(&mut self, now: SystemTime) -> (Vec<T>, Vec<ProposalOrigin>) {
+        if !self.origins.iter().any(|o| o.is_expired(now)) {
+            return (vec![], vec![]);
+        }
+        let (entries, origins) = self.take();
+        let (kept, expired) =
+            split_proposals(entries, origins, |o| o.is_some_and(|o| o.is_expired(now)));
+        self.bytes = kept.0.iter().map(|e| e.size_hint()).sum();
+        (self.entries, self.origins) = kept;
+        expired
+    }
+}
+
+/// Splits `entries`, some of which have an origin as described by `origins`, into the entries
+/// whose origin does not match `matches` and those whose origin does, each with their origins
+/// re-indexed.
+#[allow(clippy::type_complexity)]
+pub(crate) fn split_proposals<T, F>(
+    entries: Vec<T>,
+    origins: Vec<ProposalOrigin>,
+    mut matches: F,
+) -> ((Vec<T>, Vec<ProposalOrigin>), (Vec<T>, Vec<ProposalOrigin>))
+where
+    F: FnMut(Option<&ProposalOrigin>) -> bool,
+{
+    let mut entry_origins: Vec<Option<ProposalOrigin>> = vec![None; entries.len()];
+    for o in origins {
+        if let Some(origin) = entry_origins.get_mut(o.idx) {
+            *origin = Some(o);
+        }
+    }
+    let mut kept = (vec![], vec![]);
+    let mut matched = (vec![], vec![]);
+    for (entry, origin) in entries.into_iter().zip(entry_origins) {
+        let (entries, origins) = match matches(origin.as_ref()) {
+            true => &mut matched,
+            false => &mut kept,
+        };
+        if let Some(origin) = origin {
+            origins.push(ProposalOrigin {
+                idx: entries.len(),
+                ..origin
+            });
+        }
+        entries.push(entry);
+    }
+    (kept, matched)
+}
+
+/// Holds the proposals that the leader can't append yet because the entry they depend on, see
+/// [`Entry::dependency`], is not decided.
+#[derive(Debug)]
+pub(crate) struct DependentProposals<T> {
+    // The groups of held proposals in the order they were proposed, each with the highest index
+    // its entries depend on and the origins of the entries that were appended with a token
+    held: Vec<(usize, Vec<T>, Vec<ProposalOrigin>)>,
+}
+
+impl<T> Default for DependentProposals<T> {
+    fn default() -> Self {
+        Self { held: vec![] }
+    }
+}
+
+impl<T> DependentProposals<T>
+where
+    T: Entry,
+{
+    /// Holds `entries` until the entry at `dependency` is decided. The entries are appended
+    /// together, so that they stay contiguous in the log.
+    pub(crate) fn hold(
+        &mut self,
+        dependency: usize,
+        entries: Vec<T>,
+        origins: Vec<ProposalOrigin>,
+    ) {
+        self.held.push((dependency, entries, origins));
+    }
+
+    /// Takes the held proposals whose dependencies are decided at `decided_idx`, in the order they
+    /// were proposed.
+    pub(crate) fn take_ready(&mut self, decided_idx: usize) -> Vec<(Vec<T>, Vec<ProposalOrigin>)> {
+        let (ready, held) = std::mem::take(&mut self.held)
+            .into_iter()
+            .partition(|(dependency, _, _)| *dependency < decided_idx);
+        self.held = held;
+        ready
+            .into_iter()
+            .map(|(_, entries, origins)| (entries, origins))
+            .collect()
+    }
+
+    /// Takes all held proposals and their origins, e.g., when this server is no longer the leader.
+    pub(crate) fn take_all(&mut self) -> (Vec<T>, Vec<ProposalOrigin>) {
+        let mut all = (vec![], vec![]);
+        for (_, mut entries, origins) in self.held.drain(..) {
+            let offset = all.0.len();
+            all.1.extend(origins.into_iter().map(|o| ProposalOrigin {
+                idx: offset + o.idx,
+                ..o
+            }));
+            all.0.append(&mut entries);
+        }
+        all
+    }
+
+    /// Removes and returns the held proposals whose deadline has passed at `now`.
+    pub(crate) fn take_expired(&mut self, now: SystemTime) -> (Vec<T>, Vec<ProposalOrigin>) {
+        let mut expired = (vec![], vec![]);
+        for (_, entries, origins) in self.held.iter_mut() {
+            if !origins.iter().any(|o| o.is_expired(now)) {
+                continue;
+            }
+            let (kept, (mut dropped, dropped_origins)) =
+                split_proposals(std::mem::take(entries), std::mem::take(origins), |o| {
+                    o.is_some_and(|o| o.is_expired(now))
+                });
+            let offset = expired.0.len();
+            expired
+                .1
+                .extend(dropped_origins.into_iter().map(|o| ProposalOrigin {
+                    idx: offset + o.idx,
+                    ..o
+                }));
+            expired.0.append(&mut dropped);
+            (*entries, *origins) = kept;
+        }
+        self.held.retain(|(_, entries, _)| !entries.is_empty());
+        expired
+    }
+
+    /// The number of held proposals.
+    pub(crate) fn num_held(&self) -> usize {
+        self.held.iter().map(|(_, entries, _)| entries.len()).sum()
+    }
+
+    /// The approximate size in bytes of the held proposals.
+    pub(crate) fn size_hint(&self) -> usize {
+        self.held
+            .iter()
+            .flat_map(|(_, entries, _)| entries)
+            .map(|e| e.size_hint())
+            .sum()
+    }
+}
+
+/// Tracks the proposals that were appended with an `OriginToken` until they are decided.
+#[derive(Debug, Default)]
+pub(crate) struct OriginTracker {
+    // The origins of the proposals this server appended to the log as the leader, by log index
+    placed: BTreeMap<usize, (NodeId, OriginToken)>,
+    // The origins of the proposals this server appended as a former leader that are not known to
+    // be decided, by log index
+    superseded: BTreeMap<usize, (NodeId, OriginToken)>,
+    // The tokens of the decided proposals that were appended on this server, by log index
+    decided: BTreeMap<usize, OriginToken>,
+}
+
+impl OriginTracker {
+    /// Records that the leader appended the proposals of `origins` starting at log index `start_idx`.
+    pub(crate) fn placed(&mut self, start_idx: usize, origins: Vec<ProposalOrigin>) {
+        for o in origins {
+            if let Some(token) = o.token {
+                self.placed.insert(start_idx + o.idx, (o.pid, token));
+            }
+        }
+    }
+
+    /// Removes and returns the appended proposals that are decided at `decided_idx`.
+    pub(crate) fn take_placed_decided(
+        &mut self,
+        decided_idx: usize,
+    ) -> Vec<(usize, (NodeId, OriginToken))> {
+        let undecided = self.placed.split_off(&decided_idx);
+        std::mem::replace(&mut self.placed, undecided)
+            .into_iter()
+            .collect()
+    }
+
+    /// Sets aside the proposals appended as the leader, as they might not be decided at the same
+    /// index after a leader change.
+    pub(crate) fn supersede_placed(&mut self) {
+        self.superseded.append(&mut self.placed);
+    }
+
+    /// Removes and returns the proposals appended as a former leader from `from_idx` on, and
+    /// forgets the others.
+    pub(crate) fn take_superseded(
+        &mut self,
+        from_idx: usize,
+    ) -> Vec<(usize, (NodeId, OriginToken))> {
+        let superseded = self.superseded.split_off(&from_idx);
+        self.superseded.clear();
+        superseded.into_iter().collect()
+    }
+
+    pub(crate) fn decided(&mut self, tokens: Vec<(usize, OriginToken)>) {
+        self.decided.extend(tokens);
+    }
+
+    /// Removes and returns the tokens of the decided proposals that are also decided in the log of
+    /// this server, i.e., that are at an index below `decided_idx`.
+    pub(crate) fn take_decided(&mut self, decided_idx: usize) -> Vec<(usize, OriginToken)> {
+        let undecided = self.decided.split_off(&decided_idx);
+        std::mem::replace(&mut self.decided, undecided)
+            .into_iter()
+            .collect()
+    }
+}
+
 pub(crate) mod defaults {
     pub(crate) const BUFFER_SIZE: usize = 100000;
     pub(crate) const BLE_BUFFER_SIZE: usize = 100;
     pub(crate) const ELECTION_TIMEOUT: u64 = 10;
     pub(crate) const RESEND_MESSAGE_TIMEOUT: u64 = 1000;
     pub(crate) const FLUSH_BATCH_TIMEOUT: u64 = 2000;
+    pub(crate) const FORWARD_BATCH_TIMEOUT: u64 = 1;
+    pub(crate) const COMPACTION_TIMEOUT: u64 = 1;
+    pub(crate) const DECIDED_CACHE_SIZE: usize = 1000;
+    pub(crate) const SYNC_PREFETCH: usize = 4;
+    pub(crate) const VERIFICATION_BATCH_SIZE: usize = 100;
+    pub(crate) const COMMIT_LATENCY_SAMPLES: usize = 1000;
+    pub(crate) const SLOW_FOLLOWER_EVENTS: usize = 1000;
+    pub(crate) const SLOW_STORAGE_TIMEOUT: u64 = 100;
+    pub(crate) const SLOW_STORAGE_EVENTS: usize = 1000;
+    pub(crate) const CLOCK_JUMPS: usize = 1000;
+    pub(crate) const CONFIG_MISMATCH_EVENTS: usize = 1000;
+    pub(crate) const MISBEHAVING_PEER_THRESHOLD: u64 = 10;
+    pub(crate) const MISBEHAVING_PEER_EVENTS: usize = 1000;
+    pub(crate) const AUDIT_RECORDS: usize = 1000;
+    pub(crate) const DIVERGENCE_ALARMS: usize = 1000;
+    #[cfg(not(feature = "no_snapshots"))]
+    pub(crate) const SNAPSHOT_VIOLATIONS: usize = 1000;
+    #[cfg(feature = "pipeline_events")]
+    pub(crate) const PIPELINE_EVENTS: usize = 10000;
+    pub(crate) const DECIDE_HOOK_BATCH_SIZE: usize = 1000;
 }
 
 #[allow(missing_docs)]
@@ -348,11 +1340,204 @@ pub type TrimmedIndex = usize;
 pub type NodeId = u64;
 /// ID for an OmniPaxos configuration (i.e., the set of servers in an OmniPaxos cluster)
 pub type ConfigurationId = u32;
+/// ID for the tenant that an OmniPaxos cluster belongs to, so that the clusters of several tenants
+/// can share the same processes and connections.
+pub type Namespace = u64;
+/// An opaque token attached to a proposal with `append_with_token()`, e.g., to identify the client
+/// request that the proposal answers.
+pub type OriginToken = u64;
 
 /// Error message to display when there was an error reading to the storage implementation.
 pub const READ_ERROR_MSG: &str = "Error reading from storage.";
 /// Error message to display when there was an error writing to the storage implementation.
 pub const WRITE_ERROR_MSG: &str = "Error writing to storage.";
+/// Error message to display when a snapshot could not be sealed with the `snapshot_sealer`.
+#[cfg(all(feature = "snapshot_sealing", not(feature = "no_snapshots")))]
+pub(crate) const SEAL_ERROR_MSG: &str = "Error sealing the snapshot.";
+/// Error message to display when the lock around the storage implementation was poisoned.
+pub(crate) const LOCK_POISONED_MSG: &str = "Storage lock was poisoned.";
+
+/// A message with a single receiver.
+pub(crate) trait Addressed {
+    fn receiver(&self) -> NodeId;
+}
+
+impl<T: Entry> Addressed for PaxosMessage<T> {
+    fn receiver(&self) -> NodeId {
+        self.to
+    }
+}
+
+impl Addressed for BLEMessage {
+    fn receiver(&self) -> NodeId {
+        self.to
+    }
+}
+
+/// The position of a queued outgoing message: its receiver and its index in the receiver's queue.
+pub(crate) type OutgoingIdx = (NodeId, usize);
+
+/// An outgoing message in its queue.
+enum Queued<M> {
+    InMemory(M),
+    /// A spillable message kept in memory and the size it accounts for in the spill budget.
+    #[cfg(feature = "spill")]
+    Accounted(M, u64),
+    #[cfg(feature = "spill")]
+    Spilled(SpilledMsg),
+}
+
+/// Outgoing messages queued separately per receiver, so that the messages to each peer can be
+/// taken independently of the others.
+pub(crate) struct OutgoingQueues<M: Addressed> {
+    queues: BTreeMap<NodeId, Vec<Queued<M>>>,
+    capacity: usize,
+    #[cfg(feature = "spill")]
+    spill: Option<SpillFile<M>>,
+}
+
+impl<M: Addressed> OutgoingQueues<M> {
+    /// Creates empty queues that each reserve space for `capacity` messages when used.
+    pub(crate) fn with(capacity: usize) -> Self {
+        Self {
+            queues: BTreeMap::new(),
+            capacity,
+            #[cfg(feature = "spill")]
+            spill: None,
+        }
+    }
+
+    /// Spill messages queued from now on to `spill` once they exceed its memory budget.
+    #[cfg(feature = "spill")]
+    pub(crate) fn enable_spill(&mut self, spill: SpillFile<M>) {
+        self.spill = Some(spill);
+    }
+
+    fn queued(&mut self, msg: M) -> Queued<M> {
+        #[cfg(feature = "spill")]
+        if let Some(spill) = self.spill.as_mut() {
+            return match spill.spill(msg) {
+                Ok(spilled) => Queued::Spilled(spilled),
+                Err((msg, 0)) => Queued::InMemory(msg),
+                Err((msg, size)) => Queued::Accounted(msg, size),
+            };
+        }
+        Queued::InMemory(msg)
+    }
+
+    fn unqueued(&mut self, queued: Queued<M>) -> M {
+        match queued {
+            Queued::InMemory(msg) => msg,
+            #[cfg(feature = "spill")]
+            Queued::Accounted(msg, size) => {
+                if let Some(spill) = self.spill.as_mut() {
+                    spill.release(size);
+                }
+                msg
+            }
+            #[cfg(feature = "spill")]
+            Queued::Spilled(spilled) => self
+                .spill
+                .as_mut()
+                .expect("Spilled message without a spill file")
+                .load(spilled),
+        }
+    }
+
+    /// Queues `msg` and returns its position.
+    pub(crate) fn push(&mut self, msg: M) -> OutgoingIdx {
+        let to = msg.receiver();
+        let queued = self.queued(msg);
+        let capacity = self.capacity;
+        let queue = self
+            .queues
+            .entry(to)
+            .or_insert_with(|| Vec::with_capacity(capacity));
+        queue.push(queued);
+        (to, queue.len() - 1)
+    }
+
+    /// The position the next message queued to `to` will get.
+    pub(crate) fn next_idx(&self, to: NodeId) -> OutgoingIdx {
+        (to, self.queues.get(&to).map_or(0, |q| q.len()))
+    }
+
+    /// The number of messages queued to all receivers.
+    pub(crate) fn num_queued(&self) -> usize {
+        self.queues.values().map(Vec::len).sum()
+    }
+
+    /// The approximate size in bytes of the queued messages held in memory, with the size of
+    /// each message given by `size`. Spilled messages don't count.
+    pub(crate) fn memory_usage<F>(&self, size: F) -> usize
+    where
+        F: Fn(&M) -> usize,
+    {
+        self.queues
+            .values()
+            .flatten()
+            .map(|queued| match queued {
+                Queued::InMemory(msg) => size(msg),
+                #[cfg(feature = "spill")]
+                Queued::Accounted(msg, _) => size(msg),
+                #[cfg(feature = "spill")]
+                Queued::Spilled(_) => 0,
+            })
+            .sum()
+    }
+
+    /// Returns the message at `idx` if it is held in memory.
+    pub(crate) fn get_mut(&mut self, (to, idx): OutgoingIdx) -> Option<&mut M> {
+        match self.queues.get_mut(&to).and_then(|q| q.get_mut(idx))? {
+            Queued::InMemory(msg) => Some(msg),
+            #[cfg(feature = "spill")]
+            Queued::Accounted(msg, _) => Some(msg),
+            #[cfg(feature = "spill")]
+            Queued::Spilled(_) => None,
+        }
+    }
+
+    /// Returns the last message queued to `to` if it is held in memory.
+    pub(crate) fn last_mut(&mut self, to: NodeId) -> Option<&mut M> {
+        let idx = self.queues.get(&to)?.len().checked_sub(1)?;
+        self.get_mut((to, idx))
+    }
+
+    /// Takes the queued messages to all receivers.
+    pub(crate) fn take_all(&mut self) -> Vec<M> {
+        let queues = std::mem::take(&mut self.queues);
+        queues
+            .into_values()
+            .flatten()
+            .map(|queued| self.unqueued(queued))
+            .collect()
+    }
+
+    /// Takes the queued messages to `to`.
+    pub(crate) fn take_for(&mut self, to: NodeId) -> Vec<M> {
+        let queue = self.queues.remove(&to).unwrap_or_default();
+        queue
+            .into_iter()
+            .map(|queued| self.unqueued(queued))
+            .collect()
+    }
+
+    /// Puts `msgs` back in front of the queues of their receivers.
+    pub(crate) fn requeue(&mut self, msgs: Vec<M>) {
+        let mut requeued: BTreeMap<NodeId, Vec<Queued<M>>> = BTreeMap::new();
+        for msg in msgs {
+            let to = msg.receiver();
+            let queued = self.queued(msg);
+            requeued.entry(to).or_default().push(queued);
+        }
+        for (to, mut msgs) in requeued {
+            if let Some(mut queued) = self.queues.remove(&to) {
+                msgs.append(&mut queued);
+            }
+            self.queues.insert(to, msgs);
+        }
+    }
+}
 
 /// Used for checking the ordering of message sequences in the accept phase
 #[derive(PartialEq, Eq)]
@@ -363,6 +1548,10 @@ pub(crate) enum MessageStatus {
     DroppedPreceding,
     /// An already identified message sequence break
     Outdated,
+    /// A message of the current session that was already received
+    Duplicate,
+    /// A message of the current session that overtook at most `reorder_window` preceding messages
+    Reordered,
 }
 
 /// Keeps track of the ordering of messages in the accept phase
@@ -376,18 +1565,102 @@ pub struct SequenceNumber {
 }
 
 impl SequenceNumber {
-    /// Compares this sequence number with the sequence number of an incoming message.
-    pub(crate) fn check_msg_status(&self, msg_seq_num: SequenceNumber) -> MessageStatus {
-        if msg_seq_num.session == self.session && msg_seq_num.counter == self.counter + 1 {
+    /// Compares this sequence number with the sequence number of an incoming message. Messages
+    /// that overtook at most `window` preceding messages of the same session are `Reordered`
+    /// instead of revealing a sequence break.
+    pub(crate) fn check_msg_status(
+        &self,
+        msg_seq_num: SequenceNumber,
+        window: u64,
+    ) -> MessageStatus {
+        if msg_seq_num.session != self.session {
+            if msg_seq_num < *self {
+                MessageStatus::Outdated
+            } else {
+                MessageStatus::DroppedPreceding
+            }
+        } else if msg_seq_num.counter == self.counter + 1 {
             MessageStatus::Expected
-        } else if msg_seq_num <= *self {
-            MessageStatus::Outdated
+        } else if msg_seq_num.counter <= self.counter {
+            MessageStatus::Duplicate
+        } else if msg_seq_num.counter <= self.counter + 1 + window {
+            MessageStatus::Reordered
         } else {
             MessageStatus::DroppedPreceding
         }
     }
 }
 
+/// Holds the accept-phase messages that arrived ahead of their predecessors until the gap before
+/// them is filled.
+#[derive(Debug)]
+pub(crate) struct ReorderBuffer<M> {
+    window: u64,
+    held: BTreeMap<u64, M>,
+    // The counter of the current sequence number when the buffer was last checked for a stall
+    checked_counter: Option<u64>,
+}
+
+impl<M> ReorderBuffer<M> {
+    pub(crate) fn with(window: u64) -> Self {
+        Self {
+            window,
+            held: BTreeMap::new(),
+            checked_counter: None,
+        }
+    }
+
+    pub(crate) fn window(&self) -> u64 {
+        self.window
+    }
+
+    /// Holds `msg` until its predecessors have arrived. Returns false if a message with the same
+    /// sequence number is already held, i.e., `msg` is a duplicate.
+    pub(crate) fn hold(&mut self, seq_num: SequenceNumber, msg: M) -> bool {
+        if self.held.contains_key(&seq_num.counter) {
+            return false;
+        }
+        self.held.insert(seq_num.counter, msg);
+        true
+    }
+
+    /// Returns the held message that directly follows `current`, if any.
+    pub(crate) fn pop_next(&mut self, current: SequenceNumber) -> Option<M> {
+        let next = self.held.first_key_value().map(|(counter, _)| *counter)?;
+        if next == current.counter + 1 {
+            self.held.remove(&next)
+        } else {
+            None
+        }
+    }
+
+    /// Returns true if messages have been held since the last check without the gap before them
+    /// being filled, i.e., the preceding messages were most likely lost.
+    pub(crate) fn stalled(&mut self, current: SequenceNumber) -> bool {
+        if self.held.is_empty() {
+            self.checked_counter = None;
+            return false;
+        }
+        let stalled = self.checked_counter == Some(current.counter);
+        self.checked_counter = Some(current.counter);
+        stalled
+    }
+
+    /// The approximate size in bytes of the held messages, with the size of each message given
+    /// by `size`.
+    pub(crate) fn memory_usage<F>(&self, size: F) -> usize
+    where
+        F: Fn(&M) -> usize,
+    {
+        self.held.values().map(size).sum()
+    }
+
+    pub(crate) fn clear(&mut self) {
+        self.held.clear();
+        self.checked_counter = None;
+    }
+}
+
 pub(crate) struct LogicalClock {
     time: u64,
     timeout: u64,
@@ -398,6 +1671,10 @@ impl LogicalClock {
         Self { time: 0, timeout }
     }
 
+    pub fn reset(&mut self) {
+        self.time = 0;
+    }
+
     pub fn tick_and_check_timeout(&mut self) -> bool {
         self.time += 1;
         if self.time == self.timeout {
@@ -421,17 +1698,100 @@ pub struct FlexibleQuorum {
     pub write_quorum_size: usize,
 }
 
+/// A group of nodes that are located close to each other, e.g., in the same data center.
+/// If the region has a `relay`, a leader outside of the region sends a single copy of new
+/// entries to the relay, which forwards them to the other nodes of the region.
+#[derive(Clone, Debug, PartialEq, Eq)]
+#[cfg_attr(any(feature = "serde", feature = "toml_config"), derive(Deserialize))]
+#[cfg_attr(feature = "serde", derive(Serialize))]
+pub struct Region {
+    /// The name of the region.
+    pub name: String,
+    /// The nodes located in the region.
+    pub nodes: Vec<NodeId>,
+    /// The node that forwards entries from a leader in another region to the rest of the region.
+    pub relay: Option<NodeId>,
+}
+
+/// A summary of the cluster configuration that a server runs with. It is sent to the peers with
+/// every heartbeat request so that a server that is pointed at the wrong cluster, or runs with a
+/// different configuration than its peers, is detected instead of taking part in the election.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct ConfigFingerprint {
+    /// The identifier of the configuration.
+    pub configuration_id: ConfigurationId,
+    /// A hash of the nodes, backup nodes, regions and quorum settings of the configuration.
+    pub membership_hash: u64,
+    /// The features this server was compiled with that change the exchanged messages, see
+    /// [`ConfigFingerprint::UNICACHE`].
+    pub features: u32,
+}
+
+impl ConfigFingerprint {
+    /// Set in `features` if the server was compiled with the `unicache` feature.
+    pub const UNICACHE: u32 = 1;
+
+    pub(crate) fn of(config: &ClusterConfig) -> Self {
+        // FNV-1a, as the hash must be the same on all servers regardless of their Rust version
+        let mut hash: u64 = 0xcbf29ce484222325;
+        let mut write = |bytes: &[u8]| {
+            for b in bytes {
+                hash ^= *b as u64;
+                hash = hash.wrapping_mul(0x100000001b3);
+            }
+        };
+        fn write_nodes(write: &mut impl FnMut(&[u8]), nodes: &[NodeId]) {
+            let mut nodes = nodes.to_vec();
+            nodes.sort_unstable();
+            write(&(nodes.len() as u64).to_le_bytes());
+            for pid in nodes {
+                write(&pid.to_le_bytes());
+            }
+        }
+        write_nodes(&mut write, &config.nodes);
+        write_nodes(&mut write, &config.backup_nodes);
+        for region in &config.regions {
+            write(region.name.as_bytes());
+            write_nodes(&mut write, &region.nodes);
+            write(&region.relay.unwrap_or_default().to_le_bytes());
+        }
+        if let Some(q) = config.flexible_quorum {
+            write(&(q.read_quorum_size as u64).to_le_bytes());
+            write(&(q.write_quorum_size as u64).to_le_bytes());
+        }
+        write(&[config.hierarchical_quorum as u8]);
+        let mut features = 0;
+        if cfg!(feature = "unicache") {
+            features |= Self::UNICACHE;
+        }
+        Self {
+            configuration_id: config.configuration_id,
+            membership_hash: hash,
+            features,
+        }
+    }
+}
+
 /// The type of quorum used by the OmniPaxos cluster.
-#[derive(Copy, Clone, Debug)]
+#[derive(Clone, Debug)]
 pub(crate) enum Quorum {
     /// Both the read quorum and the write quorums are a majority of nodes
     Majority(usize),
     /// The read and write quorum sizes are defined by a `FlexibleQuorum`
     Flexible(FlexibleQuorum),
+    /// Both the read quorum and the write quorums are a majority of the nodes in each of a
+    /// majority of the regions
+    Hierarchical(Vec<Vec<NodeId>>),
 }
 
 impl Quorum {
-    pub(crate) fn with(flexible_quorum_config: Option<FlexibleQuorum>, num_nodes: usize) -> Self {
+    pub(crate) fn with(
+        flexible_quorum_config: Option<FlexibleQuorum>,
+        hierarchical_quorum: bool,
+        regions: &[Region],
+        num_nodes: usize,
+    ) -> Self {
         match flexible_quorum_config {
             Some(FlexibleQuorum {
                 read_quorum_size,
@@ -440,23 +1800,55 @@ impl Quorum {
                 read_quorum_size,
                 write_quorum_size,
             }),
+            None if hierarchical_quorum => {
+                Quorum::Hierarchical(regions.iter().map(|r| r.nodes.clone()).collect())
+            }
             None => Quorum::Majority(num_nodes / 2 + 1),
         }
     }
 
-    pub(crate) fn is_prepare_quorum(&self, num_nodes: usize) -> bool {
+    /// Returns whether `nodes` form a quorum in the prepare phase.
+    pub(crate) fn is_prepare_quorum<I>(&self, nodes: I) -> bool
+    where
+        I: IntoIterator<Item = NodeId>,
+    {
         match self {
-            Quorum::Majority(majority) => num_nodes >= *majority,
-            Quorum::Flexible(flex_quorum) => num_nodes >= flex_quorum.read_quorum_size,
+            Quorum::Majority(majority) => nodes.into_iter().count() >= *majority,
+            Quorum::Flexible(flex_quorum) => {
+                nodes.into_iter().count() >= flex_quorum.read_quorum_size
+            }
+            Quorum::Hierarchical(regions) => Self::is_hierarchical_quorum(regions, nodes),
         }
     }
 
-    pub(crate) fn is_accept_quorum(&self, num_nodes: usize) -> bool {
+    /// Returns whether `nodes` form a quorum in the accept phase.
+    pub(crate) fn is_accept_quorum<I>(&self, nodes: I) -> bool
+    where
+        I: IntoIterator<Item = NodeId>,
+    {
         match self {
-            Quorum::Majority(majority) => num_nodes >= *majority,
-            Quorum::Flexible(flex_quorum) => num_nodes >= flex_quorum.write_quorum_size,
+            Quorum::Majority(majority) => nodes.into_iter().count() >= *majority,
+            Quorum::Flexible(flex_quorum) => {
+                nodes.into_iter().count() >= flex_quorum.write_quorum_size
+            }
+            Quorum::Hierarchical(regions) => Self::is_hierarchical_quorum(regions, nodes),
         }
     }
+
+    fn is_hierarchical_quorum<I>(regions: &[Vec<NodeId>], nodes: I) -> bool
+    where
+        I: IntoIterator<Item = NodeId>,
+    {
+        let nodes: Vec<NodeId> = nodes.into_iter().collect();
+        let num_region_quorums = regions
+            .iter()
+            .filter(|region| {
+                let num_in_region = region.iter().filter(|pid| nodes.contains(pid)).count();
+                num_in_region > region.len() / 2
+            })
+            .count();
+        num_region_quorums > regions.len() / 2
+    }
 }
 
 /// The entries flushed due to an append operation
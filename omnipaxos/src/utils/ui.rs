@@ -3,13 +3,19 @@ use crate::{
     messages::ballot_leader_election::HeartbeatReply,
     storage::Entry,
     util::{LeaderState, NodeId},
+    CommitLatency, ProposalAffinity,
 };
+use std::collections::HashMap;
 
 /// The states of all the nodes in the cluster.
 #[derive(Debug, Clone, Default)]
 pub struct ClusterState {
     /// The accepted indexes of all the nodes in the cluster. The index of the vector is the node id.
     pub accepted_indexes: Vec<usize>,
+    /// The decided indexes of all the nodes in the cluster as last reported to the leader. The index
+    /// of the vector is the node id. Compared to `accepted_indexes`, they show how far behind a node
+    /// is in learning which entries are decided, e.g., to only route reads to nodes that are not.
+    pub decided_indexes: Vec<usize>,
     /// All the received heartbeats from the previous heartbeat round, including the current node.
     /// Represents nodes that are currently alive from the view of the current node.
     pub heartbeats: Vec<HeartbeatReply>,
@@ -23,8 +29,11 @@ where
         let mut accepted_indexes = leader_state.accepted_indexes.clone();
         // Add one empty entry at the beginning to make the index of the vector sync with node id.
         accepted_indexes.insert(0, 0);
+        let mut decided_indexes = leader_state.decided_indexes.clone();
+        decided_indexes.insert(0, 0);
         Self {
             accepted_indexes,
+            decided_indexes,
             heartbeats: vec![],
         }
     }
@@ -43,4 +52,8 @@ pub struct OmniPaxosStates {
     pub heartbeats: Vec<HeartbeatReply>,
     /// The states of all the nodes in the cluster.
     pub cluster_state: ClusterState,
+    /// The commit latency of the entries appended while this node was the leader.
+    pub commit_latency: Option<CommitLatency>,
+    /// The proposals received from each node and the latency each node observed for them.
+    pub proposal_affinity: HashMap<NodeId, ProposalAffinity>,
 }
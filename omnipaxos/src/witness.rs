@@ -0,0 +1,151 @@
+use crate::{ballot_leader_election::Ballot, storage::Entry, util::NodeId};
+#[cfg(feature = "serde")]
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, VecDeque};
+
+/// The maximum number of witness reads that are collected at the same time.
+const MAX_PENDING_READS: usize = 100;
+
+/// The log of a server at the index of a witness read, see
+/// [`OmniPaxos::start_witness_read`](crate::OmniPaxos::start_witness_read).
+#[derive(Clone, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct Witness<T>
+where
+    T: Entry,
+{
+    /// The entry at the index, or `None` if it is not in the log of the server, i.e., it was
+    /// compacted or has not been accepted.
+    pub entry: Option<T>,
+    /// Whether the entry is decided.
+    pub decided: bool,
+    /// The ballot of the leader that first appended the entry, if `track_entry_ballots` is
+    /// enabled on the server and the ballot is known.
+    pub entry_ballot: Option<Ballot>,
+    /// The round in which the server last accepted entries.
+    pub accepted_round: Ballot,
+    /// The accepted index of the server.
+    pub accepted_idx: usize,
+    /// The decided index of the server.
+    pub decided_idx: usize,
+    /// The compacted index of the server.
+    pub compacted_idx: usize,
+    /// The error if the server failed to read the entry from its storage.
+    pub error: Option<String>,
+}
+
+/// The logs of a quorum of servers at the index of a witness read.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct WitnessReport<T>
+where
+    T: Entry,
+{
+    /// The id of the witness read.
+    pub id: u64,
+    /// The read log index.
+    pub idx: usize,
+    /// The log of every server that replied, ordered by the server's id.
+    pub witnesses: Vec<(NodeId, Witness<T>)>,
+}
+
+impl<T> WitnessReport<T>
+where
+    T: Entry + PartialEq,
+{
+    /// Returns whether the servers have diverged at the index, i.e., two servers decided
+    /// different entries or decided the entry in different ballots. Undecided entries may
+    /// legitimately differ and are not compared.
+    pub fn diverged(&self) -> bool {
+        let mut decided = self
+            .witnesses
+            .iter()
+            .filter_map(|(_, w)| w.decided.then_some(w))
+            .filter(|w| w.entry.is_some());
+        let Some(first) = decided.next() else {
+            return false;
+        };
+        decided.any(|w| {
+            w.entry != first.entry
+                || matches!((w.entry_ballot, first.entry_ballot), (Some(b1), Some(b2)) if b1 != b2)
+        })
+    }
+
+    /// Returns the servers that failed to read the index from their storage.
+    pub fn failed(&self) -> Vec<NodeId> {
+        self.witnesses
+            .iter()
+            .filter_map(|(pid, w)| w.error.as_ref().map(|_| *pid))
+            .collect()
+    }
+}
+
+/// Collects the witnesses of the ongoing witness reads of this server.
+#[derive(Debug)]
+pub(crate) struct WitnessReads<T>
+where
+    T: Entry,
+{
+    next_id: u64,
+    // The ongoing reads by their id, with the read index and the witnesses received so far
+    pending: VecDeque<(u64, usize, BTreeMap<NodeId, Witness<T>>)>,
+}
+
+impl<T> Default for WitnessReads<T>
+where
+    T: Entry,
+{
+    fn default() -> Self {
+        Self {
+            next_id: 1,
+            pending: VecDeque::new(),
+        }
+    }
+}
+
+impl<T> WitnessReads<T>
+where
+    T: Entry,
+{
+    /// Starts a new read of `idx` with the witness of this server. Returns the id of the read.
+    /// The oldest read is abandoned if too many are ongoing.
+    pub(crate) fn start(&mut self, idx: usize, pid: NodeId, witness: Witness<T>) -> u64 {
+        let id = self.next_id;
+        self.next_id += 1;
+        if self.pending.len() == MAX_PENDING_READS {
+            self.pending.pop_front();
+        }
+        self.pending
+            .push_back((id, idx, BTreeMap::from([(pid, witness)])));
+        id
+    }
+
+    /// Adds the witness of `from` to the read `id`. Returns false if the read is unknown.
+    pub(crate) fn add(&mut self, id: u64, from: NodeId, witness: Witness<T>) -> bool {
+        match self.pending.iter_mut().find(|(i, _, _)| *i == id) {
+            Some((_, _, witnesses)) => {
+                witnesses.insert(from, witness);
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// Returns the servers that have replied to the read `id`, or `None` if the read is unknown.
+    pub(crate) fn witnesses(&self, id: u64) -> Option<impl Iterator<Item = NodeId> + '_> {
+        self.pending
+            .iter()
+            .find(|(i, _, _)| *i == id)
+            .map(|(_, _, witnesses)| witnesses.keys().copied())
+    }
+
+    /// Finishes the read `id` and returns its report.
+    pub(crate) fn take(&mut self, id: u64) -> Option<WitnessReport<T>> {
+        let pos = self.pending.iter().position(|(i, _, _)| *i == id)?;
+        let (id, idx, witnesses) = self.pending.remove(pos)?;
+        Some(WitnessReport {
+            id,
+            idx,
+            witnesses: witnesses.into_iter().collect(),
+        })
+    }
+}
@@ -1,10 +1,16 @@
 #!/bin/bash
 
-# Tests all possible feature combinations.
+# Tests all possible combinations of the core features, and each of the other features on its own.
 # Usage: ./test_all_features.sh [-c], -c runs cargo check instead of cargo test
 
 # Define an array of feature names
-features=("macros" "logging" "toml_config" "unicache")
+features=("macros" "logging" "toml_config" "unicache" "tokio" "spill" "operator_tools")
+
+# The features that are tested on their own, as testing all their combinations would take too long
+single_features=("prometheus" "statsd" "pipeline_events" "no_snapshots" "serde" "no_panic" "snapshot_sealing" "framing" "admin")
+
+# Other combinations of features that interact with each other
+extra_combinations=("no_snapshots,snapshot_sealing")
 
 # Initialize a variable to track whether to run the tests or not
 check_only=false
@@ -32,16 +38,9 @@ total_combinations=$((1 << num_features))
 # Initialize a variable to store the failing combination
 failing_combinations=""
 
-# Loop through all possible feature combinations
-for ((i = 1; i < total_combinations; i++)); do
-    feature_flags="--features "
-
-    # Generate feature flags for the current combination
-    for ((j = 0; j < num_features; j++)); do
-        if (( (i >> j) & 1 )); then
-            feature_flags+="${features[j]},"
-        fi
-    done
+# Runs cargo check or cargo test with the given feature flags and records them if it fails
+run_cargo() {
+    local feature_flags=$1
 
     if [ "$check_only" = true ]; then
         # Run cargo check with the current feature combination
@@ -58,6 +57,25 @@ for ((i = 1; i < total_combinations; i++)); do
         echo "Cargo failed with combination ${feature_flags}"
         failing_combinations+="\n${feature_flags}"
     fi
+}
+
+# Loop through all possible feature combinations
+for ((i = 1; i < total_combinations; i++)); do
+    feature_flags="--features "
+
+    # Generate feature flags for the current combination
+    for ((j = 0; j < num_features; j++)); do
+        if (( (i >> j) & 1 )); then
+            feature_flags+="${features[j]},"
+        fi
+    done
+
+    run_cargo "$feature_flags"
+done
+
+# Test the other features on their own and in the combinations that interact
+for feature in "${single_features[@]}" "${extra_combinations[@]}"; do
+    run_cargo "--features ${feature}"
 done
 
 # Check if any combination failed and print the failing combinations
@@ -0,0 +1,218 @@
+#![cfg(feature = "admin")]
+
+pub mod utils;
+
+use kompact::prelude::Component;
+use omnipaxos::admin::{handle_admin_request, AdminRequest, AdminServer};
+use omnipaxos_storage::memory_storage::MemoryStorage;
+use serial_test::serial;
+use std::{
+    collections::HashMap,
+    io::{Read, Write},
+    net::TcpStream,
+    sync::Arc,
+    thread,
+    time::{Duration, Instant},
+};
+use utils::{omnireplica::OmniPaxosComponent, TestConfig, TestSystem, Value};
+
+/// Sends `request` to `admin`, lets it answer with the server of `node`, and returns the
+/// response.
+fn send(
+    admin: &mut AdminServer,
+    node: &Arc<Component<OmniPaxosComponent>>,
+    request: &str,
+) -> String {
+    let mut stream = TcpStream::connect(admin.local_addr().unwrap()).unwrap();
+    stream.write_all(request.as_bytes()).unwrap();
+    assert_eq!(node.on_definition(|x| admin.poll(&mut x.paxos)).unwrap(), 1);
+    let mut response = String::new();
+    stream.read_to_string(&mut response).unwrap();
+    response
+}
+
+/// Verifies that the admin server answers the status, refuses invalid and unauthorized
+/// requests, and translates the operations into calls of the server.
+#[test]
+#[serial]
+fn admin_server_test() {
+    let cfg = TestConfig::load("admin_test").expect("Test config loaded");
+    let mut sys = TestSystem::with(cfg);
+    sys.start_all_nodes();
+
+    let leader = sys.get_elected_leader(1, cfg.wait_timeout);
+    sys.make_proposals(
+        leader,
+        utils::create_proposals(1, cfg.num_proposals),
+        cfg.wait_timeout,
+    );
+
+    let mut admin = AdminServer::bind("127.0.0.1:0")
+        .unwrap()
+        .with_token("secret".to_string());
+    let op = sys.nodes.get(&leader).unwrap();
+    let auth = "Authorization: Bearer secret\r\n";
+    let status = send(
+        &mut admin,
+        op,
+        &format!("GET /status HTTP/1.1\r\n{}\r\n", auth),
+    );
+    assert!(status.starts_with("HTTP/1.1 200 OK"), "{}", status);
+    let pids = format!("\"pid\":{},\"leader\":{}", leader, leader);
+    assert!(status.contains(&pids), "{}", status);
+    assert!(status.contains("\"decided_idx\":3"), "{}", status);
+    assert!(
+        status.contains("\"memory_usage\":{\"outgoing\":"),
+        "{}",
+        status
+    );
+
+    let unauthorized = send(&mut admin, op, "GET /status HTTP/1.1\r\n\r\n");
+    assert!(unauthorized.starts_with("HTTP/1.1 401"), "{}", unauthorized);
+    let wrong_method = send(
+        &mut admin,
+        op,
+        &format!("GET /trim HTTP/1.1\r\n{}\r\n", auth),
+    );
+    assert!(wrong_method.starts_with("HTTP/1.1 405"), "{}", wrong_method);
+    let refused = send(
+        &mut admin,
+        op,
+        &format!("POST /trim?idx=10 HTTP/1.1\r\n{}\r\n", auth),
+    );
+    assert!(refused.starts_with("HTTP/1.1 409"), "{}", refused);
+    let invalid_encoding = send(
+        &mut admin,
+        op,
+        &format!("POST /trim?idx=%zz HTTP/1.1\r\n{}\r\n", auth),
+    );
+    assert!(
+        invalid_encoding.starts_with("HTTP/1.1 400"),
+        "{}",
+        invalid_encoding
+    );
+    let encoded = send(
+        &mut admin,
+        op,
+        &format!("POST /priority?value=%31%30 HTTP/1.1\r\n{}\r\n", auth),
+    );
+    assert!(encoded.starts_with("HTTP/1.1 200 OK"), "{}", encoded);
+
+    let snapshot = send(
+        &mut admin,
+        op,
+        &format!("POST /snapshot?idx=2&local=true HTTP/1.1\r\n{}\r\n", auth),
+    );
+    assert!(snapshot.starts_with("HTTP/1.1 200 OK"), "{}", snapshot);
+    assert_eq!(op.on_definition(|x| x.paxos.get_compacted_idx()), 2);
+    let metrics = send(
+        &mut admin,
+        op,
+        &format!("GET /metrics HTTP/1.1\r\n{}\r\n", auth),
+    );
+    let compacted_idx = format!("omnipaxos_compacted_idx{{pid=\"{}\"}} 2", leader);
+    assert!(metrics.contains(&compacted_idx), "{}", metrics);
+
+    let kompact_system =
+        std::mem::take(&mut sys.kompact_system).expect("No KompactSystem in memory");
+    match kompact_system.shutdown() {
+        Ok(_) => {}
+        Err(e) => panic!("Error on kompact shutdown: {}", e),
+    };
+}
+
+/// Verifies the parsing of the requests independently of the HTTP server.
+#[test]
+#[serial]
+fn parse_request_test() {
+    let params = |pairs: &[(&str, &str)]| -> HashMap<String, String> {
+        pairs
+            .iter()
+            .map(|(k, v)| (k.to_string(), v.to_string()))
+            .collect()
+    };
+    assert_eq!(
+        AdminRequest::parse(
+            "POST",
+            "/reconfigure",
+            &params(&[("configuration_id", "2"), ("nodes", "1,2,4")])
+        ),
+        Ok(AdminRequest::Reconfigure {
+            configuration_id: 2,
+            nodes: vec![1, 2, 4]
+        })
+    );
+    assert_eq!(
+        AdminRequest::parse("POST", "/trim", &params(&[])),
+        Ok(AdminRequest::Trim { trim_idx: None })
+    );
+    let missing = AdminRequest::parse("POST", "/priority", &params(&[])).unwrap_err();
+    assert_eq!(missing.status, 400);
+    let invalid = AdminRequest::parse("POST", "/trim", &params(&[("idx", "x")])).unwrap_err();
+    assert_eq!(invalid.status, 400);
+    assert_eq!(
+        AdminRequest::parse("GET", "/unknown", &params(&[]))
+            .unwrap_err()
+            .status,
+        404
+    );
+
+    // A server that is not the leader can't transfer the leadership
+    let cfg = TestConfig::load("admin_test").expect("Test config loaded");
+    let mut sys = TestSystem::with(cfg);
+    let response =
+        sys.nodes.get(&2).unwrap().on_definition(|x| {
+            handle_admin_request(&mut x.paxos, &AdminRequest::TransferLeadership)
+        });
+    assert_eq!(response.status, 409);
+
+    let kompact_system =
+        std::mem::take(&mut sys.kompact_system).expect("No KompactSystem in memory");
+    match kompact_system.shutdown() {
+        Ok(_) => {}
+        Err(e) => panic!("Error on kompact shutdown: {}", e),
+    };
+}
+
+/// Verifies that a client that sends its request slowly can't block `poll()` past the deadline
+/// of the request, and that a single call of `poll()` only serves a few connections.
+#[test]
+#[serial]
+fn slow_client_test() {
+    let cfg = TestConfig::load("admin_test").expect("Test config loaded");
+    let mut op = cfg
+        .into_omnipaxos_config(1)
+        .build(MemoryStorage::<Value>::default())
+        .expect("Failed to build OmniPaxos");
+    let mut admin = AdminServer::bind("127.0.0.1:0").unwrap();
+    let addr = admin.local_addr().unwrap();
+
+    let mut stream = TcpStream::connect(addr).unwrap();
+    let slow_client = thread::spawn(move || {
+        for b in b"GET /status HTTP/1.1\r\n\r\n" {
+            if stream.write_all(&[*b]).is_err() {
+                return;
+            }
+            thread::sleep(Duration::from_millis(20));
+        }
+    });
+    let start = Instant::now();
+    assert_eq!(admin.poll(&mut op).unwrap(), 0);
+    assert!(start.elapsed() < Duration::from_millis(300));
+    slow_client.join().unwrap();
+
+    let streams: Vec<_> = (0..6)
+        .map(|_| {
+            let mut stream = TcpStream::connect(addr).unwrap();
+            stream.write_all(b"GET /status HTTP/1.1\r\n\r\n").unwrap();
+            stream
+        })
+        .collect();
+    assert_eq!(admin.poll(&mut op).unwrap(), 4);
+    assert_eq!(admin.poll(&mut op).unwrap(), 2);
+    for mut stream in streams {
+        let mut response = String::new();
+        stream.read_to_string(&mut response).unwrap();
+        assert!(response.starts_with("HTTP/1.1 200 OK"), "{}", response);
+    }
+}
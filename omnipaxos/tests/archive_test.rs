@@ -0,0 +1,185 @@
+pub mod utils;
+
+use omnipaxos::{
+    ballot_leader_election::Ballot,
+    errors::ArchiveErr,
+    storage::{
+        archive::{
+            restore_archive, write_archive, ArchiveMetadata, ArchiveReader, ArchiveRecord,
+            FORMAT_VERSION,
+        },
+        LogStorage, Snapshot, StateStorage, StopSign, StorageOp,
+    },
+    util::{FlexibleQuorum, Region},
+    ClusterConfig,
+};
+use omnipaxos_storage::memory_storage::MemoryStorage;
+use utils::{JsonCodec, Value, ValueSnapshot};
+
+const COMPACTED_IDX: usize = 4;
+const LOG_LEN: usize = 10;
+
+fn ballot(n: u32, pid: u64) -> Ballot {
+    Ballot {
+        config_id: 1,
+        n,
+        priority: 0,
+        pid,
+    }
+}
+
+fn stopsign() -> StopSign {
+    let next_config = ClusterConfig {
+        configuration_id: 2,
+        nodes: vec![1, 2, 3, 4],
+        flexible_quorum: Some(FlexibleQuorum {
+            read_quorum_size: 3,
+            write_quorum_size: 2,
+        }),
+        regions: vec![Region {
+            name: "eu-north".to_string(),
+            nodes: vec![1, 2],
+            relay: Some(2),
+        }],
+        hierarchical_quorum: false,
+        backup_nodes: vec![5],
+        namespace: Some(7),
+    };
+    StopSign::with(next_config, Some(vec![1, 2, 3]))
+}
+
+fn values() -> Vec<Value> {
+    (0..LOG_LEN as u64).map(Value::with_id).collect()
+}
+
+fn snapshot() -> ValueSnapshot {
+    ValueSnapshot::create(&values()[..COMPACTED_IDX])
+}
+
+/// A storage with a snapshot of the first `COMPACTED_IDX` values, the rest of the log, and a
+/// StopSign.
+fn storage() -> MemoryStorage<Value> {
+    let mut storage = MemoryStorage::default();
+    storage
+        .write_atomically(vec![
+            StorageOp::AppendEntries(values()),
+            StorageOp::SetSnapshot(Some(snapshot())),
+            StorageOp::Trim(COMPACTED_IDX),
+            StorageOp::SetCompactedIdx(COMPACTED_IDX),
+            StorageOp::SetStopsign(Some(stopsign())),
+            StorageOp::SetPromise(ballot(3, 2)),
+            StorageOp::SetAcceptedRound(ballot(2, 1)),
+            StorageOp::SetDecidedIndex(LOG_LEN),
+        ])
+        .unwrap();
+    storage.set_ble_ballot(ballot(3, 2)).unwrap();
+    storage
+}
+
+fn archive() -> Vec<u8> {
+    let mut bytes = vec![];
+    write_archive(&storage(), &JsonCodec, &mut bytes).expect("Failed to write archive");
+    bytes
+}
+
+/// Verifies that the reader returns the state of the storage without knowing the entry type.
+#[test]
+fn read_archive_test() {
+    let bytes = archive();
+    let reader = ArchiveReader::new(bytes.as_slice()).expect("Failed to read header");
+    assert_eq!(reader.version(), FORMAT_VERSION);
+    let records: Vec<ArchiveRecord> = reader.map(|r| r.unwrap()).collect();
+    let mut expected = vec![
+        ArchiveRecord::Metadata(ArchiveMetadata {
+            promise: Some(ballot(3, 2)),
+            accepted_round: Some(ballot(2, 1)),
+            ble_ballot: Some(ballot(3, 2)),
+            decided_idx: LOG_LEN,
+            compacted_idx: COMPACTED_IDX,
+        }),
+        ArchiveRecord::Snapshot {
+            compacted_idx: COMPACTED_IDX,
+            data: serde_json::to_vec(&snapshot()).unwrap(),
+        },
+    ];
+    let values = values();
+    expected.extend((COMPACTED_IDX..LOG_LEN).map(|idx| ArchiveRecord::Entry {
+        idx,
+        data: serde_json::to_vec(&values[idx]).unwrap(),
+    }));
+    expected.push(ArchiveRecord::StopSign(stopsign()));
+    assert_eq!(records, expected);
+}
+
+/// Verifies that restoring an archive into an empty storage recreates the archived state.
+#[test]
+fn restore_archive_test() {
+    let bytes = archive();
+    let mut restored = MemoryStorage::default();
+    restore_archive(bytes.as_slice(), &JsonCodec, &mut restored)
+        .expect("Failed to restore archive");
+    let original = storage();
+    assert_eq!(
+        restored.get_compacted_idx().unwrap(),
+        original.get_compacted_idx().unwrap()
+    );
+    assert_eq!(
+        restored.get_snapshot().unwrap(),
+        original.get_snapshot().unwrap()
+    );
+    assert_eq!(
+        restored.get_suffix(COMPACTED_IDX).unwrap(),
+        original.get_suffix(COMPACTED_IDX).unwrap()
+    );
+    assert_eq!(
+        restored.get_stopsign().unwrap(),
+        original.get_stopsign().unwrap()
+    );
+    assert_eq!(
+        restored.get_promise().unwrap(),
+        original.get_promise().unwrap()
+    );
+    assert_eq!(
+        restored.get_accepted_round().unwrap(),
+        original.get_accepted_round().unwrap()
+    );
+    assert_eq!(
+        restored.get_ble_ballot().unwrap(),
+        original.get_ble_ballot().unwrap()
+    );
+    assert_eq!(restored.get_decided_idx().unwrap(), LOG_LEN);
+}
+
+/// Verifies that malformed archives are rejected and that unknown records are skipped.
+#[test]
+fn invalid_archive_test() {
+    let bytes = archive();
+    assert!(matches!(
+        ArchiveReader::new(&b"not an archive"[..]),
+        Err(ArchiveErr::InvalidMagic)
+    ));
+
+    let mut newer = bytes.clone();
+    newer[4..6].copy_from_slice(&(FORMAT_VERSION + 1).to_le_bytes());
+    assert!(matches!(
+        ArchiveReader::new(newer.as_slice()),
+        Err(ArchiveErr::UnsupportedVersion(v)) if v == FORMAT_VERSION + 1
+    ));
+
+    let truncated = &bytes[..bytes.len() - 1];
+    let mut restored = MemoryStorage::default();
+    assert!(matches!(
+        restore_archive(truncated, &JsonCodec, &mut restored),
+        Err(ArchiveErr::Corrupt(_))
+    ));
+    assert_eq!(restored.get_decided_idx().unwrap(), 0);
+
+    // Inserts a record of an unknown kind after the header
+    let mut extended = bytes[..6].to_vec();
+    extended.push(0x7F);
+    extended.extend_from_slice(&3u32.to_le_bytes());
+    extended.extend_from_slice(&[1, 2, 3]);
+    extended.extend_from_slice(&bytes[6..]);
+    let records = ArchiveReader::new(extended.as_slice()).unwrap().count();
+    assert_eq!(records, 2 + LOG_LEN - COMPACTED_IDX + 1);
+}
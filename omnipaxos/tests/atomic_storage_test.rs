@@ -1,15 +1,18 @@
 /// This file contains unit-style tests that check the atomicity of storage operations during
 /// handling of different messages, while injecting storage errors.
-/// We verify this way, that OmniPaxos successfully rolls back changes to a consistent state,
-/// when interrupted by a single storage error, before panicking.
+/// We verify this way, that OmniPaxos leaves the storage in a consistent state,
+/// when interrupted by a single storage error, before returning the error.
 ///
 /// Each test here follows the structure:
 ///     1. Set up a new OmniPaxos instance.
 ///     2. Give it any prerequisite messages that are needed to create the state required for the
 ///        test.
 ///     3. Schedule a failure in the mock-broken storage.
-///     4. Give it the test message and catch the ensuing panic.
+///     4. Give it the test message, which fails with the storage error.
 ///     5. Check if the storage is in a consistent state.
+///
+/// The last tests check that the storage error reaches the caller of the handler without
+/// halting the server, unless the application halts it or the `no_panic` feature is enabled.
 pub mod utils;
 
 use crate::utils::StorageType;
@@ -20,21 +23,21 @@ use omnipaxos::storage::Entry;
 #[cfg(feature = "unicache")]
 use omnipaxos::unicache::UniCache;
 use omnipaxos::{
+    errors::{FatalErr, ProtocolError},
     messages::{
         ballot_leader_election::{BLEMessage, HeartbeatMsg, HeartbeatReply},
         sequence_paxos::{AcceptSync, PaxosMessage, PaxosMsg, Prepare, Promise},
         Message,
     },
-    storage::{Snapshot, SnapshotType, Storage},
+    storage::{LogStorage, Snapshot, SnapshotType, StateStorage},
     util::{LogSync, NodeId, SequenceNumber},
-    OmniPaxos, OmniPaxosConfig,
+    OmniPaxos, OmniPaxosConfig, ProposeErr,
 };
 use omnipaxos_storage::memory_storage::MemoryStorage;
 use serial_test::serial;
-use std::{
-    panic::{catch_unwind, AssertUnwindSafe},
-    sync::{Arc, Mutex},
-};
+#[cfg(not(any(feature = "unicache", feature = "no_panic")))]
+use std::ops::Range;
+use std::sync::{Arc, Mutex};
 use utils::{BrokenStorageConfig, TestConfig, Value, ValueSnapshot};
 
 type MemoryStore = Arc<Mutex<MemoryStorage<Value>>>;
@@ -47,6 +50,17 @@ fn basic_setup() -> (
     MemoryStore,
     BrokenStore,
     OmniPaxos<Value, StorageType<Value>>,
+) {
+    basic_setup_with_batch_size(1)
+}
+
+/// Like `basic_setup()`, but batches up to `batch_size` entries before writing them.
+fn basic_setup_with_batch_size(
+    batch_size: usize,
+) -> (
+    MemoryStore,
+    BrokenStore,
+    OmniPaxos<Value, StorageType<Value>>,
 ) {
     let cfg = TestConfig::load("atomic_storage_test").expect("Test config loaded");
     let storage = StorageType::with(cfg.storage_type, "");
@@ -60,6 +74,7 @@ fn basic_setup() -> (
     op_config.cluster_config.nodes = (1..=cfg.num_nodes as NodeId).collect();
     op_config.cluster_config.configuration_id = 1;
     op_config.server_config.election_tick_timeout = 1; // set tick timeout to 1 as we need to trigger leader change when we call tick() in the tests.
+    op_config.server_config.batch_size = batch_size;
     let op = op_config.build(storage).unwrap();
     (mem_storage, storage_conf, op)
 }
@@ -78,39 +93,57 @@ fn _setup_leader() -> (
     let setup_msg = Message::<Value>::BLE(BLEMessage {
         from: 2,
         to: 1,
+        namespace: None,
         msg: HeartbeatMsg::Reply(HeartbeatReply {
             round: 1,
             ballot: n_old,
             leader: n_old,
             happy: true,
+            connectivity: 2,
+            quorum_connected: true,
+            payload: None,
+            compression: None,
         }),
     });
-    op.handle_incoming(setup_msg);
-    op.tick(); // trigger leader change
+    op.handle_incoming(setup_msg)
+        .expect("Failed to handle message");
+    op.tick().expect("Failed to tick"); // trigger leader change
     let setup_msg = Message::<Value>::BLE(BLEMessage {
         from: 2,
         to: 1,
+        namespace: None,
         msg: HeartbeatMsg::Reply(HeartbeatReply {
             round: 2,
             ballot: n_old,
             leader: n_old,
             happy: false,
+            connectivity: 2,
+            quorum_connected: true,
+            payload: None,
+            compression: None,
         }),
     });
-    op.handle_incoming(setup_msg);
-    op.tick(); // trigger leader change
+    op.handle_incoming(setup_msg)
+        .expect("Failed to handle message");
+    op.tick().expect("Failed to tick"); // trigger leader change
     let setup_msg = Message::<Value>::BLE(BLEMessage {
         from: 2,
         to: 1,
+        namespace: None,
         msg: HeartbeatMsg::Reply(HeartbeatReply {
             round: 3,
             ballot: n_old,
             leader: n_old,
             happy: false,
+            connectivity: 2,
+            quorum_connected: true,
+            payload: None,
+            compression: None,
         }),
     });
-    op.handle_incoming(setup_msg);
-    op.tick(); // trigger leader change
+    op.handle_incoming(setup_msg)
+        .expect("Failed to handle message");
+    op.tick().expect("Failed to tick"); // trigger leader change
     let msgs = op.outgoing_messages();
     for msg in msgs {
         if let Message::SequencePaxos(ref px_msg) = msg {
@@ -122,6 +155,7 @@ fn _setup_leader() -> (
     let setup_msg = Message::<Value>::SequencePaxos(PaxosMessage {
         from: 2,
         to: 1,
+        namespace: None,
         msg: PaxosMsg::Promise(Promise {
             n,
             decided_idx: 0,
@@ -130,7 +164,8 @@ fn _setup_leader() -> (
             log_sync: None,
         }),
     });
-    op.handle_incoming(setup_msg);
+    op.handle_incoming(setup_msg)
+        .expect("Failed to handle message");
     assert!(
         op.get_current_leader().expect("should have leader") == 1,
         "should be leader"
@@ -147,7 +182,18 @@ fn setup_follower() -> (
     BrokenStore,
     OmniPaxos<Value, StorageType<Value>>,
 ) {
-    let (mem_storage, storage_conf, mut op) = basic_setup();
+    setup_follower_with_batch_size(1)
+}
+
+/// Like `setup_follower()`, but batches up to `batch_size` entries before writing them.
+fn setup_follower_with_batch_size(
+    batch_size: usize,
+) -> (
+    MemoryStore,
+    BrokenStore,
+    OmniPaxos<Value, StorageType<Value>>,
+) {
+    let (mem_storage, storage_conf, mut op) = basic_setup_with_batch_size(batch_size);
     let mut n = mem_storage.lock().unwrap().get_promise().unwrap().unwrap();
     n.config_id = 1;
     n.n += 1;
@@ -155,6 +201,7 @@ fn setup_follower() -> (
     let setup_msg = Message::<Value>::SequencePaxos(PaxosMessage {
         from: 2,
         to: 1,
+        namespace: None,
         msg: PaxosMsg::Prepare(Prepare {
             decided_idx: 0,
             accepted_idx: 0,
@@ -167,7 +214,8 @@ fn setup_follower() -> (
             n,
         }),
     });
-    op.handle_incoming(setup_msg);
+    op.handle_incoming(setup_msg)
+        .expect("Failed to handle message");
 
     let seq = SequenceNumber {
         session: 1,
@@ -176,6 +224,7 @@ fn setup_follower() -> (
     let setup_msg = Message::<Value>::SequencePaxos(PaxosMessage {
         from: 2,
         to: 1,
+        namespace: None,
         msg: PaxosMsg::AcceptSync(AcceptSync {
             n,
             seq_num: seq,
@@ -185,12 +234,14 @@ fn setup_follower() -> (
                 suffix: vec![],
                 sync_idx: 0,
                 stopsign: None,
+                suffix_ballots: vec![],
             },
             #[cfg(feature = "unicache")]
             unicache: <Value as Entry>::UniCache::new(),
         }),
     });
-    op.handle_incoming(setup_msg);
+    op.handle_incoming(setup_msg)
+        .expect("Failed to handle message");
     op.outgoing_messages();
     assert!(
         op.get_current_leader().expect("should have leader") == 2,
@@ -210,6 +261,7 @@ fn atomic_storage_acceptsync_test() {
         let setup_msg = Message::<Value>::SequencePaxos(PaxosMessage {
             from: 2,
             to: 1,
+            namespace: None,
             msg: PaxosMsg::Prepare(Prepare {
                 decided_idx: 0,
                 accepted_idx: 0,
@@ -217,7 +269,8 @@ fn atomic_storage_acceptsync_test() {
                 n,
             }),
         });
-        op.handle_incoming(setup_msg);
+        op.handle_incoming(setup_msg)
+            .expect("Failed to handle message");
 
         let seq = SequenceNumber {
             session: 1,
@@ -233,6 +286,7 @@ fn atomic_storage_acceptsync_test() {
         let msg = Message::<Value>::SequencePaxos(PaxosMessage {
             from: 2,
             to: 1,
+            namespace: None,
             msg: PaxosMsg::AcceptSync(AcceptSync {
                 n,
                 seq_num: seq,
@@ -242,12 +296,13 @@ fn atomic_storage_acceptsync_test() {
                     suffix: vec![Value::with_id(1), Value::with_id(2), Value::with_id(3)],
                     sync_idx: 0,
                     stopsign: None,
+                    suffix_ballots: vec![],
                 },
                 #[cfg(feature = "unicache")]
                 unicache: <Value as Entry>::UniCache::new(),
             }),
         });
-        let _res = catch_unwind(AssertUnwindSafe(|| op.handle_incoming(msg.clone())));
+        let _res = op.handle_incoming(msg);
 
         // check consistency
         let s = mem_storage.lock().unwrap();
@@ -275,6 +330,7 @@ fn atomic_storage_trim_test() {
         let setup_msg = Message::<Value>::SequencePaxos(PaxosMessage {
             from: 2,
             to: 1,
+            namespace: None,
             msg: PaxosMsg::AcceptDecide(AcceptDecide {
                 n: mem_storage.lock().unwrap().get_promise().unwrap().unwrap(),
                 seq_num: SequenceNumber {
@@ -292,7 +348,8 @@ fn atomic_storage_trim_test() {
                 ],
             }),
         });
-        op.handle_incoming(setup_msg);
+        op.handle_incoming(setup_msg)
+            .expect("Failed to handle message");
 
         let old_compacted_idx = mem_storage.lock().unwrap().get_compacted_idx().unwrap();
         let old_log_len = mem_storage.lock().unwrap().get_log_len().unwrap();
@@ -305,9 +362,10 @@ fn atomic_storage_trim_test() {
         let msg = Message::<Value>::SequencePaxos(PaxosMessage {
             from: 2,
             to: 1,
+            namespace: None,
             msg: PaxosMsg::Compaction(Compaction::Trim(4)),
         });
-        let _res = catch_unwind(AssertUnwindSafe(|| op.handle_incoming(msg.clone())));
+        let _res = op.handle_incoming(msg);
 
         // check consistency
         let s = mem_storage.lock().unwrap();
@@ -339,6 +397,7 @@ fn atomic_storage_snapshot_test() {
         let setup_msg = Message::<Value>::SequencePaxos(PaxosMessage {
             from: 2,
             to: 1,
+            namespace: None,
             msg: PaxosMsg::AcceptDecide(AcceptDecide {
                 n: mem_storage.lock().unwrap().get_promise().unwrap().unwrap(),
                 seq_num: SequenceNumber {
@@ -356,7 +415,8 @@ fn atomic_storage_snapshot_test() {
                 ],
             }),
         });
-        op.handle_incoming(setup_msg);
+        op.handle_incoming(setup_msg)
+            .expect("Failed to handle message");
 
         let old_compacted_idx = mem_storage.lock().unwrap().get_compacted_idx().unwrap();
         let old_log_len = mem_storage.lock().unwrap().get_log_len().unwrap();
@@ -369,9 +429,10 @@ fn atomic_storage_snapshot_test() {
         let msg = Message::<Value>::SequencePaxos(PaxosMessage {
             from: 2,
             to: 1,
+            namespace: None,
             msg: PaxosMsg::Compaction(Compaction::Snapshot(Some(4))),
         });
-        let _res = catch_unwind(AssertUnwindSafe(|| op.handle_incoming(msg.clone())));
+        let _res = op.handle_incoming(msg);
 
         // check consistency
         let s = mem_storage.lock().unwrap();
@@ -417,6 +478,7 @@ fn atomic_storage_accept_decide_test() {
         let msg = Message::<Value>::SequencePaxos(PaxosMessage {
             from: 2,
             to: 1,
+            namespace: None,
             msg: PaxosMsg::AcceptDecide(AcceptDecide {
                 n: mem_storage.lock().unwrap().get_promise().unwrap().unwrap(),
                 seq_num: SequenceNumber {
@@ -434,7 +496,7 @@ fn atomic_storage_accept_decide_test() {
                 ],
             }),
         });
-        let _res = catch_unwind(AssertUnwindSafe(|| op.handle_incoming(msg.clone())));
+        let _res = op.handle_incoming(msg);
 
         // check consistency
         let s = mem_storage.lock().unwrap();
@@ -453,6 +515,71 @@ fn atomic_storage_accept_decide_test() {
     }
 }
 
+/// An `AcceptDecide` from node 2 that carries the entries with the ids in `ids`.
+#[cfg(not(any(feature = "unicache", feature = "no_panic")))]
+fn accept_decide(mem_storage: &MemoryStore, counter: u64, ids: Range<u64>) -> Message<Value> {
+    Message::SequencePaxos(PaxosMessage {
+        from: 2,
+        to: 1,
+        namespace: None,
+        msg: PaxosMsg::AcceptDecide(AcceptDecide {
+            n: mem_storage.lock().unwrap().get_promise().unwrap().unwrap(),
+            seq_num: SequenceNumber {
+                session: 1,
+                counter,
+            },
+            decided_idx: 0,
+            entries: ids.map(Value::with_id).collect(),
+        }),
+    })
+}
+
+/// Checks that a follower whose write of an `AcceptDecide` failed ends up with the same log as
+/// a follower without failures once the leader resends the message, i.e., that neither the
+/// batched entries nor the entries of the message are lost or appended twice.
+#[cfg(not(any(feature = "unicache", feature = "no_panic")))]
+#[test]
+#[serial]
+fn retried_accept_decide_test() {
+    fn run_single_test(fail_after_n_ops: usize) {
+        let (expected_storage, _, mut expected_op) = setup_follower_with_batch_size(4);
+        let (mem_storage, storage_conf, mut op) = setup_follower_with_batch_size(4);
+        // the first message stays batched and the second one fills the batch
+        let batched_msg = accept_decide(&mem_storage, 2, 1..3);
+        let flushing_msg = accept_decide(&mem_storage, 3, 3..7);
+        for msg in [batched_msg.clone(), flushing_msg.clone()] {
+            expected_op
+                .handle_incoming(msg)
+                .expect("Failed to handle message");
+        }
+
+        op.handle_incoming(batched_msg)
+            .expect("Failed to handle message");
+        storage_conf
+            .lock()
+            .unwrap()
+            .schedule_failure_in(fail_after_n_ops);
+        if op.handle_incoming(flushing_msg.clone()).is_err() {
+            op.handle_incoming(flushing_msg)
+                .expect("Failed to handle the resent message");
+        }
+
+        let expected = expected_storage.lock().unwrap();
+        let s = mem_storage.lock().unwrap();
+        let log_len = s.get_log_len().unwrap();
+        assert_eq!(log_len, expected.get_log_len().unwrap());
+        assert_eq!(
+            s.get_entries(0, log_len).unwrap(),
+            expected.get_entries(0, log_len).unwrap(),
+            "the logs diverged after a failed write"
+        );
+    }
+    // run the test with injected failures at different points in time
+    for i in 1..10 {
+        run_single_test(i);
+    }
+}
+
 #[test]
 #[serial]
 fn atomic_storage_majority_promises_test() {
@@ -464,38 +591,56 @@ fn atomic_storage_majority_promises_test() {
         let setup_msg = Message::<Value>::BLE(BLEMessage {
             from: 2,
             to: 1,
+            namespace: None,
             msg: HeartbeatMsg::Reply(HeartbeatReply {
                 round: 1,
                 ballot: n_old,
                 leader: n_old,
                 happy: true,
+                connectivity: 2,
+                quorum_connected: true,
+                payload: None,
+                compression: None,
             }),
         });
-        op.handle_incoming(setup_msg);
-        op.tick();
+        op.handle_incoming(setup_msg)
+            .expect("Failed to handle message");
+        op.tick().expect("Failed to tick");
         let setup_msg = Message::<Value>::BLE(BLEMessage {
             from: 2,
             to: 1,
+            namespace: None,
             msg: HeartbeatMsg::Reply(HeartbeatReply {
                 round: 2,
                 ballot: n_old,
                 leader: n_old,
                 happy: false,
+                connectivity: 2,
+                quorum_connected: true,
+                payload: None,
+                compression: None,
             }),
         });
-        op.handle_incoming(setup_msg);
+        op.handle_incoming(setup_msg)
+            .expect("Failed to handle message");
         let setup_msg = Message::<Value>::BLE(BLEMessage {
             from: 3,
             to: 1,
+            namespace: None,
             msg: HeartbeatMsg::Reply(HeartbeatReply {
                 round: 2,
                 ballot: n_old,
                 leader: n_old,
                 happy: false,
+                connectivity: 2,
+                quorum_connected: true,
+                payload: None,
+                compression: None,
             }),
         });
-        op.handle_incoming(setup_msg);
-        op.tick();
+        op.handle_incoming(setup_msg)
+            .expect("Failed to handle message");
+        op.tick().expect("Failed to tick");
         // Send messages to 1 so it sees it has gained leadership and notifies paxos
         let mut n_new = n_old;
         n_new.n += 1;
@@ -503,26 +648,38 @@ fn atomic_storage_majority_promises_test() {
         let setup_msg = Message::<Value>::BLE(BLEMessage {
             from: 2,
             to: 1,
+            namespace: None,
             msg: HeartbeatMsg::Reply(HeartbeatReply {
                 round: 3,
                 ballot: n_new,
                 leader: n_new,
                 happy: true,
+                connectivity: 2,
+                quorum_connected: true,
+                payload: None,
+                compression: None,
             }),
         });
-        op.handle_incoming(setup_msg);
+        op.handle_incoming(setup_msg)
+            .expect("Failed to handle message");
         let setup_msg = Message::<Value>::BLE(BLEMessage {
             from: 3,
             to: 1,
+            namespace: None,
             msg: HeartbeatMsg::Reply(HeartbeatReply {
                 round: 3,
                 ballot: n_new,
                 leader: n_new,
                 happy: true,
+                connectivity: 2,
+                quorum_connected: true,
+                payload: None,
+                compression: None,
             }),
         });
-        op.handle_incoming(setup_msg);
-        op.tick(); // 1 gains leadership here
+        op.handle_incoming(setup_msg)
+            .expect("Failed to handle message");
+        op.tick().expect("Failed to tick"); // 1 gains leadership here
         let msgs = op.outgoing_messages();
         for msg in msgs {
             if let Message::SequencePaxos(px_msg) = msg {
@@ -544,6 +701,7 @@ fn atomic_storage_majority_promises_test() {
         let msg = Message::<Value>::SequencePaxos(PaxosMessage {
             from: 2,
             to: 1,
+            namespace: None,
             msg: PaxosMsg::Promise(Promise {
                 n,
                 decided_idx: 2,
@@ -557,10 +715,11 @@ fn atomic_storage_majority_promises_test() {
                     suffix: vec![Value::with_id(3)],
                     sync_idx: 2,
                     stopsign: None,
+                    suffix_ballots: vec![],
                 }),
             }),
         });
-        let _res = catch_unwind(AssertUnwindSafe(|| op.handle_incoming(msg.clone())));
+        let _res = op.handle_incoming(msg);
 
         // check consistency
         let s = mem_storage.lock().unwrap();
@@ -592,3 +751,103 @@ fn atomic_storage_majority_promises_test() {
         run_single_test(i);
     }
 }
+
+/// A `Prepare` from node 2 with a higher ballot than the promise of the follower, which makes
+/// the follower persist a new promise.
+fn higher_prepare(mem_storage: &MemoryStore) -> Message<Value> {
+    let s = mem_storage.lock().unwrap();
+    let mut n = s.get_promise().unwrap().unwrap();
+    n.n += 1;
+    Message::SequencePaxos(PaxosMessage {
+        from: 2,
+        to: 1,
+        namespace: None,
+        msg: PaxosMsg::Prepare(Prepare {
+            decided_idx: 0,
+            accepted_idx: 0,
+            n_accepted: s.get_accepted_round().unwrap().unwrap_or_default(),
+            n,
+        }),
+    })
+}
+
+#[cfg(not(feature = "no_panic"))]
+#[test]
+#[serial]
+fn storage_err_returned_test() {
+    let (mem_storage, storage_conf, mut op) = setup_follower();
+    let old_promise = mem_storage.lock().unwrap().get_promise().unwrap();
+    let msg = higher_prepare(&mem_storage);
+
+    storage_conf.lock().unwrap().schedule_failure_in(1);
+    let err = op
+        .handle_incoming(msg.clone())
+        .expect_err("The storage error was not returned");
+    assert!(err.is_storage_failure());
+    match err {
+        ProtocolError::Failed(FatalErr::Storage { error, .. }) => {
+            assert_eq!(error, "test error from mocked broken storage")
+        }
+        err => panic!("Unexpected error: {:?}", err),
+    }
+    assert_eq!(op.get_fatal_error(), None, "the server halted");
+
+    // the server keeps running, so the message can be handled once the storage works again
+    op.handle_incoming(msg).expect("Failed to handle message");
+    assert!(mem_storage.lock().unwrap().get_promise().unwrap() > old_promise);
+    op.tick().expect("Failed to tick");
+}
+
+#[test]
+#[serial]
+fn halt_after_storage_err_test() {
+    let (mem_storage, storage_conf, mut op) = setup_follower();
+    let msg = higher_prepare(&mem_storage);
+
+    storage_conf.lock().unwrap().schedule_failure_in(1);
+    let err = op
+        .handle_incoming(msg.clone())
+        .expect_err("The storage error was not returned");
+    op.outgoing_messages();
+    op.halt(err.fatal_error().clone());
+    assert_eq!(op.get_fatal_error().as_ref(), Some(err.fatal_error()));
+
+    let halted = Err(ProtocolError::Halted(err.fatal_error().clone()));
+    assert_eq!(op.handle_incoming(msg), halted);
+    assert_eq!(op.tick(), halted);
+    assert!(matches!(
+        op.append(Value::with_id(1)),
+        Err(ProposeErr::Halted(_))
+    ));
+    assert!(op.outgoing_messages().is_empty());
+}
+
+#[cfg(feature = "no_panic")]
+#[test]
+#[serial]
+fn no_panic_halts_after_storage_err_test() {
+    let (mem_storage, storage_conf, mut op) = setup_follower();
+    let msg = higher_prepare(&mem_storage);
+
+    storage_conf.lock().unwrap().schedule_failure_in(1);
+    let err = op
+        .handle_incoming(msg.clone())
+        .expect_err("The storage error was not returned");
+    assert!(matches!(
+        err,
+        ProtocolError::Failed(FatalErr::Storage { .. })
+    ));
+    assert_eq!(
+        op.get_fatal_error().as_ref(),
+        Some(err.fatal_error()),
+        "the server didn't halt"
+    );
+
+    let halted = Err(ProtocolError::Halted(err.fatal_error().clone()));
+    assert_eq!(op.handle_incoming(msg), halted);
+    assert_eq!(op.tick(), halted);
+    assert!(matches!(
+        op.append(Value::with_id(1)),
+        Err(ProposeErr::Halted(_))
+    ));
+}
@@ -0,0 +1,140 @@
+pub mod utils;
+
+use omnipaxos::{
+    messages::{
+        sequence_paxos::{Accepted, PaxosMessage, PaxosMsg},
+        Message,
+    },
+    storage::{Entry, NoSnapshot},
+    util::{LogEntry, NodeId},
+    AppendOutcome, MessageOutcome, OmniPaxos, ProposeErr, RejectedMessageCause,
+};
+use omnipaxos_storage::memory_storage::MemoryStorage;
+use serial_test::serial;
+use std::collections::HashMap;
+use utils::{elect_leader, no_unicache, tick_until, TestConfig};
+
+const MAX_ENTRY_SIZE: usize = 4;
+
+#[derive(Clone, Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+struct Value(String);
+
+impl Entry for Value {
+    type Snapshot = NoSnapshot;
+    no_unicache!();
+
+    fn size_hint(&self) -> usize {
+        self.0.len()
+    }
+}
+
+type OmniPaxosValue = OmniPaxos<Value, MemoryStorage<Value>>;
+
+fn cluster(cfg: &TestConfig) -> HashMap<NodeId, OmniPaxosValue> {
+    utils::create_cluster(cfg, |server_cfg| {
+        server_cfg.max_entry_size = Some(MAX_ENTRY_SIZE)
+    })
+}
+
+fn value(s: &str) -> Value {
+    Value(s.to_string())
+}
+
+/// Verifies that the entries of a batch are accepted, deferred, or rejected individually, and
+/// that the rejected entries don't prevent the rest of the batch from being decided in order.
+#[test]
+#[serial]
+fn append_batch_test() {
+    let cfg = TestConfig::load("batch_outcome_test").expect("Test config loaded");
+    let mut nodes = cluster(&cfg);
+    let leader = elect_leader(&mut nodes);
+    let follower = *nodes.keys().find(|pid| **pid != leader).unwrap();
+    // the leader only appends to its log once it has synchronized it with the followers
+    let result = nodes
+        .get_mut(&leader)
+        .unwrap()
+        .append_batch(vec![value("a")]);
+    assert!(result.is_ok());
+    tick_until(&mut nodes, |nodes| {
+        nodes.values().all(|op| op.get_decided_idx() == 1)
+    });
+    let result = nodes.get_mut(&leader).unwrap().append_batch(vec![
+        value("b"),
+        value("too large"),
+        value("c"),
+    ]);
+    assert_eq!(result.num_accepted(), 2);
+    assert!(!result.is_ok());
+    assert!(matches!(result.outcomes[0], AppendOutcome::Accepted));
+    match result.into_rejected().as_slice() {
+        [(1, ProposeErr::EntryTooLarge { entry, .. })] => {
+            assert_eq!(*entry, value("too large"))
+        }
+        rejected => panic!("Unexpected rejections: {:?}", rejected),
+    }
+
+    let result = nodes
+        .get_mut(&follower)
+        .unwrap()
+        .append_batch(vec![value("d"), value("e")]);
+    assert!(result.is_ok());
+    assert_eq!(result.num_deferred(), 2);
+    tick_until(&mut nodes, |nodes| {
+        nodes.values().all(|op| op.get_decided_idx() == 5)
+    });
+    let decided: Vec<_> = nodes[&follower]
+        .read_decided_suffix(0)
+        .unwrap()
+        .into_iter()
+        .map(|e| match e {
+            LogEntry::Decided(v) => v,
+            e => panic!("Unexpected entry: {:?}", e),
+        })
+        .collect();
+    assert_eq!(
+        decided,
+        vec![value("a"), value("b"), value("c"), value("d"), value("e")]
+    );
+}
+
+/// Verifies that the messages of a batch are handled individually and that a rejected message
+/// is reported at its position without the other messages being dropped.
+#[test]
+#[serial]
+fn handle_incoming_batch_test() {
+    let cfg = TestConfig::load("batch_outcome_test").expect("Test config loaded");
+    let mut nodes = cluster(&cfg);
+    let leader = elect_leader(&mut nodes);
+    let follower = *nodes.keys().find(|pid| **pid != leader).unwrap();
+    let op = nodes.get_mut(&leader).unwrap();
+    let n = op.get_promise();
+    let accepted = Accepted {
+        n,
+        accepted_idx: 0,
+        decided_idx: 0,
+    };
+    let msg = |from| {
+        Message::SequencePaxos(PaxosMessage {
+            from,
+            to: leader,
+            namespace: None,
+            msg: PaxosMsg::Accepted(accepted),
+        })
+    };
+    let result = op.handle_incoming_batch(vec![msg(follower), msg(9), msg(follower)]);
+    assert_eq!(
+        result.outcomes,
+        vec![
+            MessageOutcome::Handled,
+            MessageOutcome::Rejected(RejectedMessageCause::NonMember),
+            MessageOutcome::Handled,
+        ]
+    );
+    assert_eq!(result.num_handled(), 2);
+    assert_eq!(
+        result.rejected(),
+        vec![(1, RejectedMessageCause::NonMember)]
+    );
+    assert_eq!(op.get_rejected_message_count(9), 1);
+}
@@ -1,10 +1,22 @@
 pub mod utils;
 
 use kompact::prelude::{promise, Ask, FutureCollection};
-use omnipaxos::ballot_leader_election::Ballot;
+use omnipaxos::{
+    ballot_leader_election::Ballot,
+    messages::{sequence_paxos::PaxosMsg, Message},
+    storage::{Entry, NoSnapshot},
+    util::{LogEntry, NodeId},
+    OmniPaxos,
+};
+use omnipaxos_storage::memory_storage::MemoryStorage;
 use serial_test::serial;
-use std::{thread, time::Duration};
-use utils::{TestConfig, TestSystem};
+use std::{
+    cell::Cell,
+    collections::{HashMap, HashSet},
+    thread,
+    time::Duration,
+};
+use utils::{no_unicache, tick_until, tick_until_filtered, TestConfig, TestSystem};
 
 /// Test case for batching.
 #[test]
@@ -63,3 +75,163 @@ fn check_batching(decided_idx: usize, last_decided_idx: usize, batch_size: usize
         assert!(decided_idx - last_decided_idx >= batch_size);
     }
 }
+
+/// Larger than the `sync_batch_byte_size` of the test config.
+const LARGE_VALUE_SIZE: usize = 600;
+
+#[derive(Clone, Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+struct Value(Vec<u8>);
+
+impl Entry for Value {
+    type Snapshot = NoSnapshot;
+    no_unicache!();
+
+    fn size_hint(&self) -> usize {
+        self.0.len()
+    }
+}
+
+type OmniPaxosValue = OmniPaxos<Value, MemoryStorage<Value>>;
+
+/// Entries of varying sizes, every tenth one of `LARGE_VALUE_SIZE` bytes.
+fn value(id: usize) -> Value {
+    let len = match id % 10 {
+        9 => LARGE_VALUE_SIZE,
+        i => 20 + 30 * i,
+    };
+    Value(vec![id as u8; len])
+}
+
+fn cluster(cfg: &TestConfig) -> HashMap<NodeId, OmniPaxosValue> {
+    utils::create_cluster(cfg, |_| ())
+}
+
+/// Ticks the cluster without the `disconnected` servers until `done` holds.
+fn tick_until_connected<F>(
+    nodes: &mut HashMap<NodeId, OmniPaxosValue>,
+    disconnected: &HashSet<NodeId>,
+    done: F,
+) where
+    F: Fn(&HashMap<NodeId, OmniPaxosValue>) -> bool,
+{
+    tick_until_filtered(
+        nodes,
+        |msg| {
+            !disconnected.contains(&msg.get_sender()) && !disconnected.contains(&msg.get_receiver())
+        },
+        done,
+    );
+}
+
+/// Verifies that a follower that is behind is synchronized with messages of at most
+/// `sync_batch_byte_size` bytes, or a single larger entry, and ends up with the same log as
+/// the leader.
+#[test]
+#[serial]
+fn sync_batch_byte_size_test() {
+    let cfg = TestConfig::load("sync_batch_byte_size_test").expect("Test config loaded");
+    let sync_batch_byte_size = cfg.sync_batch_byte_size.unwrap();
+    let num_proposals = cfg.num_proposals as usize;
+    let mut nodes = cluster(&cfg);
+    let mut disconnected = HashSet::new();
+    tick_until_connected(&mut nodes, &disconnected, |nodes| {
+        nodes.values().all(|op| op.get_current_leader().is_some())
+    });
+    let leader = nodes[&1].get_current_leader().unwrap();
+    let follower = (1..=cfg.num_nodes as NodeId)
+        .find(|pid| *pid != leader)
+        .unwrap();
+
+    disconnected.insert(follower);
+    for id in 0..num_proposals {
+        nodes
+            .get_mut(&leader)
+            .unwrap()
+            .append(value(id))
+            .expect("Failed to append");
+    }
+    tick_until_connected(&mut nodes, &disconnected, |nodes| {
+        nodes[&leader].get_decided_idx() == num_proposals
+    });
+
+    for peer in (1..=cfg.num_nodes as NodeId).filter(|pid| *pid != follower) {
+        nodes.get_mut(&peer).unwrap().reconnected(follower);
+        nodes.get_mut(&follower).unwrap().reconnected(peer);
+    }
+    let mut num_sync_msgs = 0;
+    tick_until_filtered(
+        &mut nodes,
+        |msg| {
+            let Message::SequencePaxos(m) = msg else {
+                return true;
+            };
+            let suffix = match &m.msg {
+                PaxosMsg::AcceptSync(acc_sync) if m.to == follower => &acc_sync.log_sync.suffix,
+                PaxosMsg::SyncPortion(portion) => &portion.log_sync.suffix,
+                _ => return true,
+            };
+            num_sync_msgs += 1;
+            let bytes: usize = suffix.iter().map(Entry::size_hint).sum();
+            assert!(
+                bytes <= sync_batch_byte_size || suffix.len() == 1,
+                "A sync message of {} entries has {} bytes",
+                suffix.len(),
+                bytes
+            );
+            true
+        },
+        |nodes| nodes[&follower].get_decided_idx() == num_proposals,
+    );
+    assert!(
+        num_sync_msgs > 1,
+        "The follower was not synced with several messages"
+    );
+    let expected: Vec<LogEntry<Value>> = (0..num_proposals)
+        .map(|id| LogEntry::Decided(value(id)))
+        .collect();
+    assert_eq!(nodes[&follower].read_decided_suffix(0), Some(expected));
+}
+
+/// Verifies that the batched entries are flushed once they reach `batch_byte_size` bytes,
+/// even though the batch has fewer than `batch_size` entries.
+#[test]
+#[serial]
+fn batch_byte_size_test() {
+    let cfg = TestConfig::load("batch_byte_size_test").expect("Test config loaded");
+    let entry_size = cfg.batch_byte_size.unwrap() * 2 / 5;
+    let mut nodes = cluster(&cfg);
+    tick_until(&mut nodes, |nodes| {
+        nodes.values().all(|op| op.get_current_leader().is_some())
+    });
+    let leader = nodes[&1].get_current_leader().unwrap();
+    let append = |nodes: &mut HashMap<NodeId, OmniPaxosValue>, id: u8| {
+        nodes
+            .get_mut(&leader)
+            .unwrap()
+            .append(Value(vec![id; entry_size]))
+            .expect("Failed to append");
+    };
+    // The entries appended before the leader is in the accept phase are not batched
+    for id in 0..3 {
+        append(&mut nodes, id);
+    }
+    tick_until(&mut nodes, |nodes| {
+        nodes.values().all(|op| op.get_decided_idx() == 3)
+    });
+
+    append(&mut nodes, 3);
+    append(&mut nodes, 4);
+    let ticks = Cell::new(0);
+    tick_until(&mut nodes, |_| {
+        ticks.set(ticks.get() + 1);
+        ticks.get() > 10
+    });
+    assert_eq!(nodes[&leader].get_decided_idx(), 3);
+
+    // The third entry fills the batch to more than `batch_byte_size` bytes
+    append(&mut nodes, 5);
+    tick_until(&mut nodes, |nodes| {
+        nodes.values().all(|op| op.get_decided_idx() == 6)
+    });
+}
@@ -1,8 +1,12 @@
 pub mod utils;
 
+use omnipaxos::{ballot_leader_election::Ballot, storage::StateStorage, util::NodeId};
+use omnipaxos_storage::memory_storage::MemoryStorage;
 use serial_test::serial;
 use std::thread;
-use utils::{TestConfig, TestSystem};
+use utils::{wait_until, StorageType, StorageTypeSelector, TestConfig, TestSystem};
+
+const USED_ROUND: u32 = 5;
 
 /// Test Ballot Election Leader module.
 /// The test waits for [`num_elections`] elections.
@@ -45,3 +49,240 @@ fn ble_test() {
         Err(e) => panic!("Error on kompact shutdown: {}", e),
     };
 }
+
+/// Verifies that a server that recovers from a state that is older than the ballots it has
+/// used in the leader election never reuses any of those ballots.
+#[test]
+#[serial]
+fn recovered_ballot_not_reused_test() {
+    let cfg = TestConfig::load("ble_ballot_test").expect("Test config loaded");
+    let mut sys = TestSystem::with(cfg);
+    // Server 1 has used the ballot of `USED_ROUND`, but its promise was lost
+    let mut storage = MemoryStorage::default();
+    storage
+        .set_ble_ballot(Ballot::with(1, USED_ROUND, 0, 1))
+        .unwrap();
+    sys.kill_node(1);
+    sys.create_node(1, &cfg, StorageType::with_memory(storage));
+    sys.start_all_nodes();
+
+    // Wait to ensure stabilized leader
+    thread::sleep(8 * cfg.election_timeout);
+    for node in sys.nodes.values() {
+        assert_eq!(
+            node.on_definition(|x| x.paxos.get_current_leader()),
+            Some(1)
+        );
+    }
+    let promise = sys
+        .nodes
+        .get(&1)
+        .unwrap()
+        .on_definition(|x| x.paxos.get_promise());
+    assert!(
+        promise.n > USED_ROUND,
+        "server 1 reused round {} after recovery",
+        promise.n
+    );
+
+    let kompact_system =
+        std::mem::take(&mut sys.kompact_system).expect("No KompactSystem in memory");
+    match kompact_system.shutdown() {
+        Ok(_) => {}
+        Err(e) => panic!("Error on kompact shutdown: {}", e),
+    };
+}
+
+/// Verifies that ballots are ordered by their configuration first, so that servers that
+/// recover a promise of a previous configuration from storage do not treat it as newer than
+/// the ballots of the current configuration.
+#[test]
+#[serial]
+fn previous_configuration_ballot_test() {
+    let cfg = TestConfig {
+        configuration_id: 2,
+        ..TestConfig::load("ble_ballot_test").expect("Test config loaded")
+    };
+    let old_ballot = Ballot::with(1, USED_ROUND, 10, cfg.num_nodes as NodeId);
+    assert!(Ballot::with(2, 1, 0, 1) > old_ballot);
+    assert!(Ballot::with(2, 1, 0, 1) < Ballot::with(2, 1, 0, 2));
+
+    let mut sys = TestSystem::with(cfg);
+    for pid in 1..=cfg.num_nodes as NodeId {
+        let mut storage = MemoryStorage::default();
+        storage.set_promise(old_ballot).unwrap();
+        sys.kill_node(pid);
+        sys.create_node(pid, &cfg, StorageType::with_memory(storage));
+    }
+    sys.start_all_nodes();
+
+    wait_until(
+        cfg.wait_timeout,
+        "No ballot of the current configuration was promised",
+        || {
+            let promises: Vec<Ballot> = sys
+                .nodes
+                .values()
+                .map(|node| node.on_definition(|x| x.paxos.get_promise()))
+                .collect();
+            promises[0].config_id == cfg.configuration_id
+                && promises.iter().all(|p| *p == promises[0])
+        },
+    );
+    for node in sys.nodes.values() {
+        let promise = node.on_definition(|x| x.paxos.get_promise());
+        assert!(
+            promise.n <= USED_ROUND,
+            "round {} was raised above the ballot of the previous configuration",
+            promise.n
+        );
+    }
+
+    let kompact_system =
+        std::mem::take(&mut sys.kompact_system).expect("No KompactSystem in memory");
+    match kompact_system.shutdown() {
+        Ok(_) => {}
+        Err(e) => panic!("Error on kompact shutdown: {}", e),
+    };
+}
+
+/// A server with the highest priority that only reaches one other server is not
+/// quorum-connected. Verifies that the other servers do not follow its ballot, so that the
+/// leader of the majority keeps the server it shares with it in its quorum and remains the
+/// leader when another server fails.
+#[test]
+#[serial]
+fn minority_connected_server_not_followed_test() {
+    let cfg = TestConfig::load("quorum_connected_test").expect("Test config loaded");
+    let mut sys = TestSystem::with(cfg);
+    let minority = cfg.num_nodes as NodeId;
+    let majority: Vec<NodeId> = (1..minority).collect();
+    let prioritized_cfg = TestConfig {
+        leader_priority: 10,
+        ..cfg
+    };
+    sys.kill_node(minority);
+    sys.create_node(
+        minority,
+        &prioritized_cfg,
+        StorageType::with(StorageTypeSelector::Memory, ""),
+    );
+    // The prioritized server only reaches server 1
+    for pid in &majority[1..] {
+        sys.nodes
+            .get(&minority)
+            .unwrap()
+            .on_definition(|x| x.set_connection(*pid, false));
+        sys.nodes
+            .get(pid)
+            .unwrap()
+            .on_definition(|x| x.set_connection(minority, false));
+    }
+    sys.start_all_nodes();
+
+    // Server 1 learns from the heartbeats of the prioritized server that it is not
+    // quorum-connected
+    let first_node = sys.nodes.get(&1).unwrap();
+    wait_until(
+        cfg.wait_timeout,
+        "No heartbeat with the connectivity of the prioritized server",
+        || {
+            first_node.on_definition(|x| {
+                x.paxos
+                    .get_ui_states()
+                    .heartbeats
+                    .iter()
+                    .any(|reply| reply.ballot.pid == minority && reply.connectivity == 2)
+            })
+        },
+    );
+    let heartbeats = first_node.on_definition(|x| x.paxos.get_ui_states().heartbeats);
+    assert!(heartbeats
+        .iter()
+        .all(|reply| reply.quorum_connected != (reply.ballot.pid == minority)));
+    let promise_of = |pid: &NodeId| {
+        sys.nodes
+            .get(pid)
+            .unwrap()
+            .on_definition(|x| x.paxos.get_promise())
+    };
+    wait_until(
+        cfg.wait_timeout,
+        "The majority did not elect a leader",
+        || {
+            let promise = promise_of(&1);
+            promise.pid != 0 && majority.iter().all(|pid| promise_of(pid) == promise)
+        },
+    );
+    let promise = promise_of(&1);
+    assert_ne!(promise.pid, minority);
+
+    // The leader, server 1 and one more server still form a quorum without the failed server
+    let failed = *majority[1..]
+        .iter()
+        .find(|pid| **pid != promise.pid)
+        .unwrap();
+    sys.set_node_connections(failed, false);
+    thread::sleep(8 * cfg.election_timeout);
+    for pid in majority.iter().filter(|pid| **pid != failed) {
+        assert_eq!(promise_of(pid), promise);
+    }
+
+    let kompact_system =
+        std::mem::take(&mut sys.kompact_system).expect("No KompactSystem in memory");
+    match kompact_system.shutdown() {
+        Ok(_) => {}
+        Err(e) => panic!("Error on kompact shutdown: {}", e),
+    };
+}
+
+fn load(pid: NodeId) -> Vec<u8> {
+    (pid * 10).to_le_bytes().to_vec()
+}
+
+/// Verifies that every server receives the payloads the other servers attach to their
+/// heartbeats, and that a removed payload is no longer reported.
+#[test]
+#[serial]
+fn heartbeat_payload_test() {
+    let cfg = TestConfig::load("heartbeat_payload_test").expect("Test config loaded");
+    let mut sys = TestSystem::with(cfg);
+    for (pid, node) in &sys.nodes {
+        node.on_definition(|x| x.paxos.set_heartbeat_payload(Some(load(*pid))));
+    }
+    sys.start_all_nodes();
+
+    let peer_payload = |pid: NodeId, peer: NodeId| {
+        sys.nodes
+            .get(&pid)
+            .unwrap()
+            .on_definition(|x| x.paxos.get_peer_payload(peer).map(|p| p.to_vec()))
+    };
+    let pids: Vec<NodeId> = (1..=cfg.num_nodes as NodeId).collect();
+    wait_until(cfg.wait_timeout, "The payloads were not received", || {
+        pids.iter().all(|pid| {
+            pids.iter()
+                .filter(|peer| *peer != pid)
+                .all(|peer| peer_payload(*pid, *peer) == Some(load(*peer)))
+        })
+    });
+    assert_eq!(peer_payload(1, 1), None);
+
+    sys.nodes
+        .get(&2)
+        .unwrap()
+        .on_definition(|x| x.paxos.set_heartbeat_payload(None));
+    wait_until(
+        cfg.wait_timeout,
+        "The removed payload is still reported",
+        || peer_payload(1, 2).is_none() && peer_payload(3, 2).is_none(),
+    );
+    assert_eq!(peer_payload(1, 3), Some(load(3)));
+
+    let kompact_system =
+        std::mem::take(&mut sys.kompact_system).expect("No KompactSystem in memory");
+    match kompact_system.shutdown() {
+        Ok(_) => {}
+        Err(e) => panic!("Error on kompact shutdown: {}", e),
+    };
+}
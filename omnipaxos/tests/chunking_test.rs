@@ -0,0 +1,236 @@
+#![cfg(not(feature = "unicache"))]
+/// This file contains unit-style tests of entries that are split into chunks with
+/// `append_chunked()`. The chunks need an entry type that implements `Chunkable`, so the tests
+/// drive a single follower with the messages of its leader instead of running a `TestSystem`.
+pub mod utils;
+
+use omnipaxos::{
+    ballot_leader_election::Ballot,
+    chunking::{ChunkInfo, Chunkable},
+    messages::{
+        sequence_paxos::{AcceptDecide, AcceptSync, Decide, PaxosMessage, PaxosMsg, Prepare},
+        Message,
+    },
+    storage::{Entry, NoSnapshot},
+    util::{LogEntry, LogSync, SequenceNumber},
+    OmniPaxos, ProposeErr,
+};
+use omnipaxos_storage::memory_storage::MemoryStorage;
+use serial_test::serial;
+use utils::TestConfig;
+
+const MAX_CHUNK_SIZE: usize = 16;
+const LEADER: u64 = 2;
+
+#[derive(Clone, Debug, PartialEq)]
+enum Blob {
+    Data(Vec<u8>),
+    Chunk(ChunkInfo, Vec<u8>),
+}
+
+impl Entry for Blob {
+    type Snapshot = NoSnapshot;
+}
+
+impl Chunkable for Blob {
+    fn to_bytes(&self) -> Vec<u8> {
+        match self {
+            Blob::Data(d) => d.clone(),
+            Blob::Chunk(_, d) => d.clone(),
+        }
+    }
+
+    fn from_bytes(bytes: Vec<u8>) -> Self {
+        Blob::Data(bytes)
+    }
+
+    fn chunk(info: ChunkInfo, data: Vec<u8>) -> Self {
+        Blob::Chunk(info, data)
+    }
+
+    fn as_chunk(&self) -> Option<(ChunkInfo, &[u8])> {
+        match self {
+            Blob::Chunk(info, d) => Some((*info, d)),
+            Blob::Data(_) => None,
+        }
+    }
+}
+
+/// Creates server 1 from what is persisted in `storage` and makes it a follower of server 2 in
+/// round `n`.
+fn setup_follower(storage: MemoryStorage<Blob>, n: u32) -> OmniPaxos<Blob, MemoryStorage<Blob>> {
+    let cfg = TestConfig::load("sync_test").expect("Test config loaded");
+    let mut op = cfg
+        .into_omnipaxos_config(1)
+        .build(storage)
+        .expect("failed to build OmniPaxos");
+    let log_len = op.get_decided_idx();
+    let n = Ballot {
+        config_id: 1,
+        n,
+        priority: 0,
+        pid: LEADER,
+    };
+    let prepare = PaxosMsg::Prepare(Prepare {
+        n,
+        decided_idx: log_len,
+        n_accepted: Ballot::default(),
+        accepted_idx: log_len,
+    });
+    op.handle_incoming(paxos_message(prepare))
+        .expect("Failed to handle message");
+    let accept_sync = PaxosMsg::AcceptSync(AcceptSync {
+        n,
+        seq_num: seq_num(1),
+        decided_idx: log_len,
+        log_sync: LogSync {
+            decided_snapshot: None,
+            suffix: vec![],
+            sync_idx: log_len,
+            stopsign: None,
+            suffix_ballots: vec![],
+        },
+    });
+    op.handle_incoming(paxos_message(accept_sync))
+        .expect("Failed to handle message");
+    op.outgoing_messages();
+    assert_eq!(op.get_current_leader(), Some(LEADER));
+    op
+}
+
+fn paxos_message(msg: PaxosMsg<Blob>) -> Message<Blob> {
+    Message::SequencePaxos(PaxosMessage {
+        from: LEADER,
+        to: 1,
+        namespace: None,
+        msg,
+    })
+}
+
+fn seq_num(counter: u64) -> SequenceNumber {
+    SequenceNumber {
+        session: 1,
+        counter,
+    }
+}
+
+/// Returns the proposals that `op` forwarded to the leader.
+fn forwarded_proposals(op: &mut OmniPaxos<Blob, MemoryStorage<Blob>>) -> Vec<Blob> {
+    op.outgoing_messages()
+        .into_iter()
+        .flat_map(|msg| match msg {
+            Message::SequencePaxos(PaxosMessage {
+                msg: PaxosMsg::ProposalForward(entries),
+                ..
+            }) => entries,
+            _ => vec![],
+        })
+        .collect()
+}
+
+/// Makes the follower `op` accept and decide `entries` as the leader would, starting with the
+/// message with sequence number `counter`.
+fn decide(op: &mut OmniPaxos<Blob, MemoryStorage<Blob>>, entries: Vec<Blob>, counter: u64) {
+    let n = op.get_promise();
+    let decided_idx = op.get_decided_idx() + entries.len();
+    let accept_decide = PaxosMsg::AcceptDecide(AcceptDecide {
+        n,
+        seq_num: seq_num(counter),
+        decided_idx: op.get_decided_idx(),
+        entries,
+    });
+    op.handle_incoming(paxos_message(accept_decide))
+        .expect("Failed to handle message");
+    let decide = PaxosMsg::Decide(Decide {
+        n,
+        seq_num: seq_num(counter + 1),
+        decided_idx,
+    });
+    op.handle_incoming(paxos_message(decide))
+        .expect("Failed to handle message");
+    assert_eq!(op.get_decided_idx(), decided_idx);
+}
+
+/// Verifies that an entry larger than the chunk size is proposed as contiguous chunks and is
+/// read back reassembled, while small entries are appended unchanged.
+#[test]
+#[serial]
+fn chunked_entry_test() {
+    let mut op = setup_follower(MemoryStorage::default(), 1);
+    let small = Blob::Data(vec![1; MAX_CHUNK_SIZE]);
+    let large = Blob::Data((0..100).collect());
+    op.append_chunked(small.clone(), MAX_CHUNK_SIZE)
+        .expect("Failed to append");
+    op.append_chunked(large.clone(), MAX_CHUNK_SIZE)
+        .expect("Failed to append");
+
+    let proposals = forwarded_proposals(&mut op);
+    let num_chunks = 100usize.div_ceil(MAX_CHUNK_SIZE);
+    assert_eq!(proposals.len(), 1 + num_chunks);
+    assert_eq!(proposals[0], small);
+    for (i, chunk) in proposals[1..].iter().enumerate() {
+        let (info, _) = chunk.as_chunk().expect("Large entry was not chunked");
+        assert_eq!(info.pid, 1);
+        assert_eq!(info.index as usize, i);
+        assert_eq!(info.count as usize, num_chunks);
+    }
+
+    decide(&mut op, proposals, 2);
+    let (entries, next_idx) = op
+        .read_decided_suffix_reassembled(0)
+        .expect("No decided entries");
+    assert_eq!(next_idx, 1 + num_chunks);
+    assert_eq!(
+        entries,
+        vec![
+            LogEntry::Decided(small.clone()),
+            LogEntry::Decided(large.clone())
+        ]
+    );
+    // A read from the middle of the chunked entry returns it whole
+    let (entries, next_idx) = op
+        .read_decided_suffix_reassembled(3)
+        .expect("No decided entries");
+    assert_eq!(next_idx, 1 + num_chunks);
+    assert_eq!(entries, vec![LogEntry::Decided(large.clone())]);
+
+    assert!(matches!(
+        op.append_chunked(large.clone(), 0),
+        Err(ProposeErr::InvalidChunkSize(entry)) if entry == large
+    ));
+}
+
+/// Verifies that the entries split by a restarted server are not in the same group as the
+/// entries it split before the restart.
+#[test]
+#[serial]
+fn chunk_group_after_restart_test() {
+    let mut op = setup_follower(MemoryStorage::default(), 1);
+    let large = Blob::Data((0..100).collect());
+    op.append_chunked(large.clone(), MAX_CHUNK_SIZE)
+        .expect("Failed to append");
+    let chunks = forwarded_proposals(&mut op);
+    decide(&mut op, chunks.clone(), 2);
+
+    let storage = op
+        .swap_storage(MemoryStorage::default())
+        .expect("Failed to take the storage");
+    let mut op = setup_follower(storage, 2);
+    op.append_chunked(large.clone(), MAX_CHUNK_SIZE)
+        .expect("Failed to append");
+    let chunks_after_restart = forwarded_proposals(&mut op);
+    assert_eq!(chunks_after_restart.len(), chunks.len());
+    let (info, _) = chunks[0].as_chunk().unwrap();
+    let (info_after_restart, _) = chunks_after_restart[0].as_chunk().unwrap();
+    assert_eq!(info_after_restart.pid, info.pid);
+    assert_ne!(info_after_restart.group, info.group);
+
+    decide(&mut op, chunks_after_restart, 2);
+    let (entries, _) = op
+        .read_decided_suffix_reassembled(0)
+        .expect("No decided entries");
+    assert_eq!(
+        entries,
+        vec![LogEntry::Decided(large.clone()), LogEntry::Decided(large)]
+    );
+}
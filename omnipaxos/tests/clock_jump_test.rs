@@ -0,0 +1,60 @@
+pub mod utils;
+
+use omnipaxos::{util::NodeId, ClockJump, OmniPaxos};
+use omnipaxos_storage::memory_storage::MemoryStorage;
+use serial_test::serial;
+use std::{collections::HashMap, thread, time::Duration};
+use utils::{elect_leader, tick_until, TestConfig, Value};
+
+const THRESHOLD: Duration = Duration::from_millis(50);
+
+type OmniPaxosValue = OmniPaxos<Value, MemoryStorage<Value>>;
+
+/// Builds a cluster that detects clock jumps.
+fn cluster(cfg: &TestConfig) -> HashMap<NodeId, OmniPaxosValue> {
+    utils::create_cluster(cfg, |server_cfg| {
+        server_cfg.clock_jump_threshold = Some(THRESHOLD)
+    })
+}
+
+/// Verifies that a gap between the calls to `tick()` is detected as a clock jump, and that the
+/// leadership is confirmed with fresh heartbeats afterwards instead of being taken over.
+#[test]
+#[serial]
+fn suspended_test() {
+    let cfg = TestConfig::load("clock_jump_test").expect("Test config loaded");
+    let mut nodes = cluster(&cfg);
+    let leader = elect_leader(&mut nodes);
+    for op in nodes.values_mut() {
+        op.take_clock_jumps();
+    }
+
+    thread::sleep(THRESHOLD * 2);
+    for op in nodes.values_mut() {
+        op.tick().expect("Failed to tick");
+        match op.take_clock_jumps().as_slice() {
+            [ClockJump::Suspended { gap }] => assert!(*gap >= THRESHOLD * 2),
+            jumps => panic!("Unexpected clock jumps: {:?}", jumps),
+        }
+    }
+    let ballot = nodes[&leader].get_promise();
+    let op = nodes.get_mut(&leader).unwrap();
+    op.append(Value::with_id(1)).expect("Failed to append");
+    tick_until(&mut nodes, |nodes| {
+        nodes.values().all(|op| op.get_decided_idx() == 1)
+    });
+    for op in nodes.values() {
+        assert_eq!(op.get_current_leader(), Some(leader));
+        assert_eq!(op.get_promise(), ballot);
+    }
+}
+
+/// Verifies that a zero threshold is refused.
+#[test]
+#[serial]
+fn zero_threshold_test() {
+    let cfg = TestConfig::load("clock_jump_test").expect("Test config loaded");
+    let mut op_config = cfg.into_omnipaxos_config(1);
+    op_config.server_config.clock_jump_threshold = Some(Duration::ZERO);
+    assert!(op_config.build(MemoryStorage::<Value>::default()).is_err());
+}
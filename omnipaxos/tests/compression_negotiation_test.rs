@@ -0,0 +1,42 @@
+pub mod utils;
+
+use omnipaxos::{util::NodeId, OmniPaxos};
+use omnipaxos_storage::memory_storage::MemoryStorage;
+use serial_test::serial;
+use std::collections::HashMap;
+use utils::{tick_until, TestConfig, Value};
+
+type OmniPaxosValue = OmniPaxos<Value, MemoryStorage<Value>>;
+
+/// Builds a cluster whose servers support the given compressions.
+fn cluster(cfg: &TestConfig, compressions: &[Option<&str>]) -> HashMap<NodeId, OmniPaxosValue> {
+    utils::create_cluster(cfg, |server_cfg| {
+        server_cfg.compression = compressions[server_cfg.pid as usize - 1].map(str::to_string)
+    })
+}
+
+/// Verifies that messages may only be compressed between servers that advertised the same
+/// compression, and that a reconnected server has to advertise it again.
+#[test]
+#[serial]
+fn compression_negotiation_test() {
+    let cfg = TestConfig::load("compression_negotiation_test").expect("Test config loaded");
+    let mut nodes = cluster(&cfg, &[Some("rle"), Some("rle"), None, Some("lz4")]);
+    assert!(!nodes[&1].peer_accepts_compression(2));
+    tick_until(&mut nodes, |nodes| {
+        nodes[&1].peer_accepts_compression(2) && nodes[&2].peer_accepts_compression(1)
+    });
+    for (pid, peer) in [(1, 3), (3, 1), (1, 4), (4, 1), (3, 4), (1, 1)] {
+        assert!(
+            !nodes[&pid].peer_accepts_compression(peer),
+            "{} may compress the messages to {}",
+            pid,
+            peer
+        );
+    }
+
+    nodes.get_mut(&1).unwrap().reconnected(2);
+    assert!(!nodes[&1].peer_accepts_compression(2));
+    assert!(nodes[&2].peer_accepts_compression(1));
+    tick_until(&mut nodes, |nodes| nodes[&1].peer_accepts_compression(2));
+}
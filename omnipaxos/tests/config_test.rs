@@ -30,6 +30,7 @@ fn config_all_fields_test() {
             assert_eq!(config.server_config.flush_batch_tick_timeout, 200);
             assert_eq!(config.server_config.buffer_size, 10000);
             assert_eq!(config.server_config.batch_size, 2);
+            assert_eq!(config.server_config.decided_cache_size, 500);
             #[cfg(feature = "logging")]
             assert_eq!(
                 config.server_config.logger_file_path,
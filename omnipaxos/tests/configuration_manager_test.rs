@@ -0,0 +1,127 @@
+pub mod utils;
+
+use omnipaxos::{
+    errors::TransitionErr,
+    reconfiguration::{ConfigurationManager, ConfiguredEntry},
+    util::{LogEntry, NodeId},
+    ClusterConfig, ServerConfig,
+};
+use omnipaxos_storage::memory_storage::MemoryStorage;
+use serial_test::serial;
+use std::collections::HashMap;
+use utils::{tick_until, TestConfig, Value};
+
+type Manager = ConfigurationManager<Value, MemoryStorage<Value>>;
+
+fn server_config(cfg: &TestConfig, pid: NodeId) -> ServerConfig {
+    cfg.into_omnipaxos_config(pid).server_config
+}
+
+fn storage() -> MemoryStorage<Value> {
+    MemoryStorage::default()
+}
+
+fn cluster(cfg: &TestConfig) -> HashMap<NodeId, Manager> {
+    utils::create_cluster(cfg, |_| ())
+        .into_iter()
+        .map(|(pid, op)| (pid, ConfigurationManager::with(op, 0)))
+        .collect()
+}
+
+fn describe(entries: &[ConfiguredEntry<Value>]) -> Vec<(u32, usize, Option<Value>)> {
+    entries
+        .iter()
+        .map(|e| {
+            let value = match &e.entry {
+                LogEntry::Decided(v) => Some(v.clone()),
+                LogEntry::StopSign(_, true) => None,
+                e => panic!("Unexpected entry: {:?}", e),
+            };
+            (e.configuration_id, e.idx, value)
+        })
+        .collect()
+}
+
+/// Verifies that the manager delivers the entries of the old configuration, then its StopSign,
+/// and only after the next instance is started, the entries of the new configuration.
+#[test]
+#[serial]
+fn configuration_manager_test() {
+    let cfg = TestConfig::load("configuration_manager_test").expect("Test config loaded");
+    let mut nodes = cluster(&cfg);
+    tick_until(&mut nodes, |nodes| {
+        nodes
+            .values()
+            .all(|m| m.current().get_current_leader().is_some())
+    });
+    let leader = nodes[&1].current().get_current_leader().unwrap();
+    let next_config = ClusterConfig {
+        configuration_id: 2,
+        nodes: (1..=cfg.num_nodes as NodeId).collect(),
+        ..Default::default()
+    };
+    let op = nodes.get_mut(&leader).unwrap().current_mut();
+    op.append(Value::with_id(1)).expect("Failed to append");
+    op.append(Value::with_id(2)).expect("Failed to append");
+    op.reconfigure(next_config, None)
+        .expect("Failed to reconfigure");
+    let m = nodes.get_mut(&leader).unwrap();
+    assert!(matches!(
+        m.start_next(server_config(&cfg, leader), storage()),
+        Err(TransitionErr::NotStopped)
+    ));
+    tick_until(&mut nodes, |nodes| {
+        nodes
+            .values()
+            .all(|m| m.current().is_reconfigured().is_some())
+    });
+
+    for (pid, m) in nodes.iter_mut() {
+        let delivered = m.poll_decided();
+        assert_eq!(
+            describe(&delivered),
+            vec![
+                (1, 0, Some(Value::with_id(1))),
+                (1, 1, Some(Value::with_id(2))),
+                (1, 2, None)
+            ]
+        );
+        assert!(m.poll_decided().is_empty());
+        assert_eq!(
+            m.get_delivered_stopsign()
+                .unwrap()
+                .next_config
+                .configuration_id,
+            2
+        );
+        let old = m
+            .start_next(server_config(&cfg, *pid), storage())
+            .expect("Failed to start the next configuration");
+        assert_eq!(old.get_cluster_config().configuration_id, 1);
+        assert_eq!(m.get_configuration_id(), 2);
+        assert!(m.get_delivered_stopsign().is_none());
+    }
+
+    tick_until(&mut nodes, |nodes| {
+        nodes
+            .values()
+            .all(|m| m.current().get_current_leader().is_some())
+    });
+    let leader = nodes[&1].current().get_current_leader().unwrap();
+    nodes
+        .get_mut(&leader)
+        .unwrap()
+        .current_mut()
+        .append(Value::with_id(3))
+        .expect("Failed to append");
+    tick_until(&mut nodes, |nodes| {
+        nodes.values().all(|m| m.current().get_decided_idx() == 1)
+    });
+    for m in nodes.values_mut() {
+        assert_eq!(
+            describe(&m.poll_decided()),
+            vec![(2, 0, Some(Value::with_id(3)))]
+        );
+        assert_eq!(m.get_delivered_idx(), 1);
+    }
+}
@@ -1,13 +1,44 @@
 pub mod utils;
 
-use kompact::prelude::{promise, Ask, FutureCollection};
+use kompact::prelude::{promise, Ask, Component, FutureCollection};
+#[cfg(feature = "pipeline_events")]
+use omnipaxos::pipeline::{PipelineEvent, PipelineStage};
 use omnipaxos::{
-    storage::{Snapshot, StopSign, Storage},
-    ClusterConfig, OmniPaxosConfig,
+    audit::AuditAction,
+    ballot_leader_election::Ballot,
+    messages::{
+        ballot_leader_election::{BLEMessage, HeartbeatMsg, HeartbeatReply},
+        sequence_paxos::{Accepted, PaxosMessage, PaxosMsg, Prepare, Promise},
+        Message,
+    },
+    shipping::{DecideHook, DecideHookConfig, DecidedRange, ShipStatus},
+    storage::SnapshotType,
+    util::{IndexedLogEntry, LogEntry, LogSync, NodeId},
+    witness::{Witness, WitnessReport},
+    ClusterConfig, MisbehavingPeer, OmniPaxos, ProposalRejectionCause, ProposeErr, ReadConsistency,
+    ReadErr, ReadToken, RejectedMessageCause, SlowFollowerCause, SlowFollowerEvent,
+    SlowStorageEvent,
+};
+#[cfg(not(feature = "no_snapshots"))]
+use omnipaxos::{
+    storage::{LogStorage, Snapshot, StateStorage, StopSign},
+    OmniPaxosConfig, Timer,
 };
 use serial_test::serial;
+#[cfg(feature = "pipeline_events")]
+use std::time::Instant;
+use std::{
+    collections::HashMap,
+    error::Error,
+    sync::{mpsc, Arc, Mutex, RwLock},
+    thread,
+    time::{Duration, SystemTime},
+};
+#[cfg(not(feature = "no_snapshots"))]
+use utils::{create_temp_dir, ValueSnapshot};
 use utils::{
-    create_temp_dir, verification::*, StorageType, TestConfig, TestSystem, Value, ValueSnapshot,
+    omnireplica::OmniPaxosComponent, verification::*, wait_until, StorageType, TestConfig,
+    TestSystem, Value,
 };
 
 /// Verifies the 3 properties that the Paxos algorithm offers
@@ -60,6 +91,7 @@ fn consensus_test() {
 
 #[test]
 #[serial]
+#[cfg(not(feature = "no_snapshots"))]
 fn read_test() {
     let cfg = TestConfig::load("consensus_test").expect("Test config loaded");
 
@@ -150,6 +182,7 @@ fn read_test() {
 
 #[test]
 #[serial]
+#[cfg(not(feature = "no_snapshots"))]
 fn read_entries_test() {
     let cfg = TestConfig::load("consensus_test").expect("Test config loaded");
 
@@ -277,3 +310,2070 @@ fn read_entries_test() {
     verify_snapshot(snapshot, snapshotted_idx, &ValueSnapshot::create(&log));
     verify_stopsign(stopsign, &ss);
 }
+
+/// Verifies that followers in pull mode replicate and decide the entries of the leader by
+/// requesting them, even though the leader can't push entries to them.
+#[test]
+#[serial]
+fn pull_replication_test() {
+    let cfg = TestConfig::load("pull_replication_test").expect("Test config loaded");
+    let mut sys = TestSystem::with(cfg);
+    sys.start_all_nodes();
+
+    let leader = sys.get_elected_leader(1, cfg.wait_timeout);
+    let vec_proposals = utils::create_proposals(1, cfg.num_proposals);
+    let mut futures = vec![];
+    for node in sys.nodes.values() {
+        node.on_definition(|x| {
+            for v in &vec_proposals {
+                let (kprom, kfuture) = promise::<()>();
+                x.insert_decided_future(Ask::new(kprom, v.clone()));
+                futures.push(kfuture);
+            }
+        });
+    }
+    sys.nodes.get(&leader).unwrap().on_definition(|x| {
+        // The leader can't push entries or decisions to the followers
+        x.drop_outgoing = Some(|msg| {
+            matches!(msg, Message::SequencePaxos(m)
+                if matches!(m.msg, PaxosMsg::AcceptDecide(_) | PaxosMsg::Decide(_)))
+        });
+        for v in &vec_proposals {
+            x.paxos.append(v.clone()).expect("Failed to append");
+        }
+    });
+
+    match FutureCollection::collect_with_timeout::<Vec<_>>(futures, cfg.wait_timeout) {
+        Ok(_) => {}
+        Err(e) => panic!("Error on collecting futures of decided proposals: {}", e),
+    }
+    for node in sys.nodes.values() {
+        verify_log(
+            node.on_definition(|x| x.read_decided_log()),
+            vec_proposals.clone(),
+        );
+    }
+
+    let kompact_system =
+        std::mem::take(&mut sys.kompact_system).expect("No KompactSystem in memory");
+    match kompact_system.shutdown() {
+        Ok(_) => {}
+        Err(e) => panic!("Error on kompact shutdown: {}", e),
+    };
+}
+
+/// Verifies that a leader replicates new entries to a remote region through the relay of that
+/// region, by dropping all the entries that the leader sends directly to a follower.
+#[test]
+#[serial]
+fn region_relay_test() {
+    let cfg = TestConfig::load("region_relay_test").expect("Test config loaded");
+    let mut sys = TestSystem::with(cfg);
+    sys.start_all_nodes();
+
+    // Regions are [1, 2, 3] relayed by 1 and [4, 5] relayed by 4
+    let leader = sys.get_elected_leader(1, cfg.wait_timeout);
+    let remote_region: Vec<NodeId> = match leader {
+        1..=3 => vec![4, 5],
+        _ => vec![1, 2, 3],
+    };
+    // Make sure that all servers follow the leader before dropping its messages
+    let vec_proposals = utils::create_proposals(1, cfg.num_proposals);
+    let mut futures = vec![];
+    for (_, node) in sys.nodes.iter().filter(|(pid, _)| **pid != leader) {
+        let (kprom, kfuture) = promise::<()>();
+        node.on_definition(|x| x.insert_decided_future(Ask::new(kprom, vec_proposals[0].clone())));
+        futures.push(kfuture);
+    }
+    sys.make_proposals(leader, vec_proposals[..1].to_vec(), cfg.wait_timeout);
+    match FutureCollection::collect_with_timeout::<Vec<_>>(futures, cfg.wait_timeout) {
+        Ok(_) => {}
+        Err(e) => panic!("Error on collecting futures of decided proposals: {}", e),
+    }
+
+    sys.nodes.get(&leader).unwrap().on_definition(|x| {
+        x.drop_outgoing = Some(|msg| {
+            matches!(msg, Message::SequencePaxos(m) if matches!(m.msg, PaxosMsg::AcceptDecide(_)))
+        })
+    });
+    let mut futures = vec![];
+    for pid in &remote_region {
+        sys.nodes.get(pid).unwrap().on_definition(|x| {
+            for v in &vec_proposals[1..] {
+                let (kprom, kfuture) = promise::<()>();
+                x.insert_decided_future(Ask::new(kprom, v.clone()));
+                futures.push(kfuture);
+            }
+        });
+    }
+    sys.make_proposals(leader, vec_proposals[1..].to_vec(), cfg.wait_timeout);
+    match FutureCollection::collect_with_timeout::<Vec<_>>(futures, cfg.wait_timeout) {
+        Ok(_) => {}
+        Err(e) => panic!(
+            "The remote region did not decide the relayed entries: {}",
+            e
+        ),
+    }
+    for pid in &remote_region {
+        let log = sys
+            .nodes
+            .get(pid)
+            .unwrap()
+            .on_definition(|x| x.read_decided_log());
+        verify_log(log, vec_proposals.clone());
+    }
+
+    let kompact_system =
+        std::mem::take(&mut sys.kompact_system).expect("No KompactSystem in memory");
+    match kompact_system.shutdown() {
+        Ok(_) => {}
+        Err(e) => panic!("Error on kompact shutdown: {}", e),
+    };
+}
+
+fn assert_send_sync<S: Send + Sync>() {}
+
+/// Verifies that `OmniPaxos` can be shared between threads, where several threads read the
+/// log concurrently while another one appends to it.
+#[test]
+#[serial]
+fn concurrent_read_test() {
+    assert_send_sync::<OmniPaxos<Value, StorageType<Value>>>();
+
+    let cfg = TestConfig::load("consensus_test").expect("Test config loaded");
+    let num_readers = cfg.num_threads;
+    let storage = StorageType::<Value>::with(cfg.storage_type, "");
+    let omni_paxos = cfg
+        .into_omnipaxos_config(1)
+        .build(storage)
+        .expect("failed to build OmniPaxos");
+    let omni_paxos = Arc::new(RwLock::new(omni_paxos));
+
+    thread::scope(|s| {
+        for _ in 0..num_readers {
+            let omni_paxos = &omni_paxos;
+            s.spawn(move || {
+                for _ in 0..cfg.num_proposals {
+                    let op = omni_paxos.read().unwrap();
+                    let decided_idx = op.get_decided_idx();
+                    let decided = op.read_decided_suffix(0).unwrap_or_default();
+                    assert_eq!(decided.len(), decided_idx);
+                    assert!(decided.iter().all(|e| matches!(e, LogEntry::Decided(_))));
+                }
+            });
+        }
+        s.spawn(|| {
+            for v in utils::create_proposals(1, cfg.num_proposals) {
+                let mut op = omni_paxos.write().unwrap();
+                op.append(v).expect("Failed to append");
+                op.tick().expect("Failed to tick");
+            }
+        });
+    });
+}
+
+/// Verifies that a reader on another thread observes a consistent, growing decided log while
+/// the server keeps handling messages.
+#[test]
+#[serial]
+fn reader_test() {
+    let cfg = TestConfig::load("consensus_test").expect("Test config loaded");
+    let mut sys = TestSystem::with(cfg);
+    sys.start_all_nodes();
+
+    let leader = sys.get_elected_leader(1, cfg.wait_timeout);
+    let follower = (1..=cfg.num_nodes as NodeId)
+        .find(|pid| *pid != leader)
+        .unwrap();
+    let follower_node = sys.nodes.get(&follower).unwrap();
+    let reader = follower_node.on_definition(|x| x.paxos.reader());
+    let vec_proposals = utils::create_proposals(1, cfg.num_proposals);
+    let mut futures = vec![];
+    follower_node.on_definition(|x| {
+        for v in &vec_proposals {
+            let (kprom, kfuture) = promise::<()>();
+            x.insert_decided_future(Ask::new(kprom, v.clone()));
+            futures.push(kfuture);
+        }
+    });
+
+    let reader_thread = thread::spawn({
+        let reader = reader.clone();
+        let vec_proposals = vec_proposals.clone();
+        move || {
+            let mut prev_decided_idx = 0;
+            while prev_decided_idx < vec_proposals.len() {
+                let decided = reader.read_decided_suffix(0).unwrap_or_default();
+                assert!(decided.len() >= prev_decided_idx, "Decided log shrunk");
+                for (entry, v) in decided.iter().zip(&vec_proposals) {
+                    assert_eq!(*entry, LogEntry::Decided(v.clone()));
+                }
+                prev_decided_idx = decided.len();
+                thread::yield_now();
+            }
+        }
+    });
+    sys.make_proposals(leader, vec_proposals.clone(), cfg.wait_timeout);
+    match FutureCollection::collect_with_timeout::<Vec<_>>(futures, cfg.wait_timeout) {
+        Ok(_) => {}
+        Err(e) => panic!("Error on collecting futures of decided proposals: {}", e),
+    }
+    reader_thread.join().expect("Reader thread panicked");
+
+    let num_proposals = vec_proposals.len();
+    assert_eq!(reader.get_current_leader(), Some(leader));
+    assert_eq!(reader.get_decided_idx(), num_proposals);
+    assert_eq!(
+        reader.read(3),
+        Some(LogEntry::Decided(vec_proposals[3].clone()))
+    );
+    assert_eq!(reader.read(num_proposals), None);
+    assert_eq!(
+        reader.read_decided_suffix(10),
+        follower_node.on_definition(|x| x.paxos.read_decided_suffix(10))
+    );
+
+    let kompact_system =
+        std::mem::take(&mut sys.kompact_system).expect("No KompactSystem in memory");
+    match kompact_system.shutdown() {
+        Ok(_) => {}
+        Err(e) => panic!("Error on kompact shutdown: {}", e),
+    };
+}
+
+const READER_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// Verifies that the indexes of a reader can be read while the server is blocked in a write
+/// to the storage, and that they are updated once the write completes.
+#[test]
+#[serial]
+fn reader_indexes_without_lock_test() {
+    let cfg = TestConfig::load("consensus_test").expect("Test config loaded");
+    let mut sys = TestSystem::with(cfg);
+    sys.start_all_nodes();
+
+    let leader = sys.get_elected_leader(1, cfg.wait_timeout);
+    let follower = (1..=cfg.num_nodes as NodeId)
+        .find(|pid| *pid != leader)
+        .unwrap();
+    let follower_node = sys.nodes.get(&follower).unwrap();
+    let vec_proposals = utils::create_proposals(1, cfg.num_proposals);
+    let num_proposals = vec_proposals.len();
+    let last = vec_proposals.last().unwrap();
+    let (kprom, kfuture) = promise::<()>();
+    follower_node.on_definition(|x| x.insert_decided_future(Ask::new(kprom, last.clone())));
+    sys.make_proposals(leader, vec_proposals.clone(), cfg.wait_timeout);
+    kfuture
+        .wait_timeout(cfg.wait_timeout)
+        .expect("The follower did not decide the proposals");
+    let reader = follower_node.on_definition(|x| x.paxos.reader());
+    assert_eq!(reader.get_decided_idx(), num_proposals);
+    assert_eq!(reader.get_accepted_idx(), num_proposals);
+    assert_eq!(reader.get_compacted_idx(), 0);
+
+    // A read batch holds the lock of the storage, so the follower is blocked in the write of
+    // the new entry until the batch is dropped
+    let batch = reader.batch();
+    sys.nodes.get(&leader).unwrap().on_definition(|x| {
+        x.paxos
+            .append(Value::with_id(num_proposals as u64 + 1))
+            .expect("Failed to append")
+    });
+    thread::sleep(cfg.election_timeout);
+    let (indexes_tx, indexes) = mpsc::channel();
+    thread::spawn({
+        let reader = reader.clone();
+        move || {
+            let _ = indexes_tx.send((
+                reader.get_decided_idx(),
+                reader.get_accepted_idx(),
+                reader.get_compacted_idx(),
+            ));
+        }
+    });
+    let read = indexes
+        .recv_timeout(READER_TIMEOUT)
+        .expect("Reader blocked while the server writes to the storage");
+    assert_eq!(read, (num_proposals, num_proposals, 0));
+
+    drop(batch);
+    wait_until(
+        cfg.wait_timeout,
+        "The follower did not accept the entry after the write",
+        || reader.get_accepted_idx() == num_proposals + 1,
+    );
+    assert_eq!(
+        reader.get_decided_idx(),
+        follower_node.on_definition(|x| x.paxos.get_decided_idx())
+    );
+
+    let kompact_system =
+        std::mem::take(&mut sys.kompact_system).expect("No KompactSystem in memory");
+    match kompact_system.shutdown() {
+        Ok(_) => {}
+        Err(e) => panic!("Error on kompact shutdown: {}", e),
+    };
+}
+
+/// Verifies that all reads of a read batch observe the same log while the server waits to
+/// write to the storage, and that the server continues once the batch is dropped.
+#[test]
+#[serial]
+fn read_batch_test() {
+    let cfg = TestConfig::load("consensus_test").expect("Test config loaded");
+    let mut sys = TestSystem::with(cfg);
+    sys.start_all_nodes();
+
+    let leader = sys.get_elected_leader(1, cfg.wait_timeout);
+    let follower = (1..=cfg.num_nodes as NodeId)
+        .find(|pid| *pid != leader)
+        .unwrap();
+    let follower_node = sys.nodes.get(&follower).unwrap();
+    let vec_proposals = utils::create_proposals(1, cfg.num_proposals);
+    let num_proposals = vec_proposals.len();
+    let last = vec_proposals.last().unwrap();
+    let (kprom, kfuture) = promise::<()>();
+    follower_node.on_definition(|x| x.insert_decided_future(Ask::new(kprom, last.clone())));
+    sys.make_proposals(leader, vec_proposals.clone(), cfg.wait_timeout);
+    kfuture
+        .wait_timeout(cfg.wait_timeout)
+        .expect("The follower did not decide the proposals");
+    let reader = follower_node.on_definition(|x| x.paxos.reader());
+    let batch = reader.batch();
+    let decided_idx = batch.get_decided_idx();
+    assert_eq!(decided_idx, num_proposals);
+
+    // The follower accepts a new entry while the batch is read
+    sys.make_proposals(
+        leader,
+        vec![Value::with_id(num_proposals as u64 + 1)],
+        cfg.wait_timeout,
+    );
+    thread::sleep(cfg.election_timeout);
+    assert_eq!(reader.get_accepted_idx(), num_proposals);
+    assert_eq!(batch.get_decided_idx(), decided_idx);
+    assert_eq!(batch.read(decided_idx), None);
+    let entries = batch
+        .read_decided_range(10, decided_idx + 1)
+        .expect("Failed to read");
+    assert_eq!(entries.len(), decided_idx - 10);
+    assert_eq!(entries[0], LogEntry::Decided(vec_proposals[10].clone()));
+    assert_eq!(batch.read_decided_suffix(10), Some(entries));
+
+    drop(batch);
+    wait_until(
+        READER_TIMEOUT,
+        "The follower did not continue after the batch was dropped",
+        || reader.get_accepted_idx() == num_proposals + 1,
+    );
+
+    let kompact_system =
+        std::mem::take(&mut sys.kompact_system).expect("No KompactSystem in memory");
+    match kompact_system.shutdown() {
+        Ok(_) => {}
+        Err(e) => panic!("Error on kompact shutdown: {}", e),
+    };
+}
+
+const MAX_MESSAGES: usize = 2;
+const MAX_BYTES: usize = 10;
+
+/// A simple size estimate: one byte per message plus one per replicated entry.
+fn message_size(msg: &Message<Value>) -> usize {
+    match msg {
+        Message::SequencePaxos(p) => match &p.msg {
+            PaxosMsg::AcceptDecide(acc) => 1 + acc.entries.len(),
+            PaxosMsg::ProposalForward(entries) => 1 + entries.len(),
+            _ => 1,
+        },
+        Message::BLE(_) => 1,
+    }
+}
+
+/// Verifies that `outgoing_messages_within()` respects the budget and that the messages left
+/// queued are sent by later calls, so that all proposals still get decided.
+#[test]
+#[serial]
+fn outgoing_budget_test() {
+    let cfg = TestConfig::load("consensus_test").expect("Test config loaded");
+    let mut sys = TestSystem::with(cfg);
+    sys.start_all_nodes();
+
+    let leader = sys.get_elected_leader(1, cfg.wait_timeout);
+    let vec_proposals = utils::create_proposals(1, cfg.num_proposals);
+    let mut futures = vec![];
+    for node in sys.nodes.values() {
+        node.on_definition(|x| {
+            for v in &vec_proposals {
+                let (kprom, kfuture) = promise::<()>();
+                x.insert_decided_future(Ask::new(kprom, v.clone()));
+                futures.push(kfuture);
+            }
+        });
+    }
+    sys.nodes.get(&leader).unwrap().on_definition(|x| {
+        for v in &vec_proposals {
+            x.paxos.append(v.clone()).expect("Failed to append");
+        }
+        loop {
+            let sent = x
+                .paxos
+                .outgoing_messages_within(MAX_MESSAGES, MAX_BYTES, message_size);
+            if sent.is_empty() {
+                break;
+            }
+            assert!(sent.len() <= MAX_MESSAGES, "Too many messages returned");
+            let num_bytes: usize = sent.iter().map(message_size).sum();
+            assert!(
+                sent.len() == 1 || num_bytes <= MAX_BYTES,
+                "Byte budget was exceeded"
+            );
+            x.send(sent);
+        }
+    });
+
+    match FutureCollection::collect_with_timeout::<Vec<_>>(futures, cfg.wait_timeout) {
+        Ok(_) => {}
+        Err(e) => panic!("Error on collecting futures of decided proposals: {}", e),
+    }
+    for node in sys.nodes.values() {
+        verify_log(
+            node.on_definition(|x| x.read_decided_log()),
+            vec_proposals.clone(),
+        );
+    }
+
+    let kompact_system =
+        std::mem::take(&mut sys.kompact_system).expect("No KompactSystem in memory");
+    match kompact_system.shutdown() {
+        Ok(_) => {}
+        Err(e) => panic!("Error on kompact shutdown: {}", e),
+    };
+}
+
+/// Verifies that a blocked connection does not delay the messages to other servers, and that
+/// the messages queued for the blocked connection are delivered once it is drained again.
+#[test]
+#[serial]
+fn per_peer_outgoing_test() {
+    let cfg = TestConfig::load("consensus_test").expect("Test config loaded");
+    let mut sys = TestSystem::with(cfg);
+    sys.start_all_nodes();
+
+    let leader = sys.get_elected_leader(1, cfg.wait_timeout);
+    let slow = (1..=cfg.num_nodes as NodeId)
+        .find(|pid| *pid != leader)
+        .unwrap();
+    let leader_node = sys.nodes.get(&leader).unwrap();
+    leader_node.on_definition(|x| x.blocked_peers.insert(slow));
+    let vec_proposals = utils::create_proposals(1, cfg.num_proposals);
+    let mut futures = vec![];
+    for (_, node) in sys
+        .nodes
+        .iter()
+        .filter(|(pid, _)| **pid != leader && **pid != slow)
+    {
+        node.on_definition(|x| {
+            for v in &vec_proposals {
+                let (kprom, kfuture) = promise::<()>();
+                x.insert_decided_future(Ask::new(kprom, v.clone()));
+                futures.push(kfuture);
+            }
+        });
+    }
+    sys.make_proposals(leader, vec_proposals.clone(), cfg.wait_timeout);
+    match FutureCollection::collect_with_timeout::<Vec<_>>(futures, cfg.wait_timeout) {
+        Ok(_) => {}
+        Err(e) => panic!("Error on collecting futures of decided proposals: {}", e),
+    }
+    let slow_node = sys.nodes.get(&slow).unwrap();
+    assert_eq!(slow_node.on_definition(|x| x.paxos.get_decided_idx()), 0);
+
+    let mut futures = vec![];
+    slow_node.on_definition(|x| {
+        for v in &vec_proposals {
+            let (kprom, kfuture) = promise::<()>();
+            x.insert_decided_future(Ask::new(kprom, v.clone()));
+            futures.push(kfuture);
+        }
+    });
+    leader_node.on_definition(|x| x.blocked_peers.remove(&slow));
+    match FutureCollection::collect_with_timeout::<Vec<_>>(futures, cfg.wait_timeout) {
+        Ok(_) => {}
+        Err(e) => panic!("The queued messages were not delivered: {}", e),
+    }
+    for node in sys.nodes.values() {
+        verify_log(
+            node.on_definition(|x| x.read_decided_log()),
+            vec_proposals.clone(),
+        );
+    }
+
+    let kompact_system =
+        std::mem::take(&mut sys.kompact_system).expect("No KompactSystem in memory");
+    match kompact_system.shutdown() {
+        Ok(_) => {}
+        Err(e) => panic!("Error on kompact shutdown: {}", e),
+    };
+}
+
+/// Verifies that fenced reads succeed while the observed ballot is promised and fail once a
+/// new leader has been elected.
+#[test]
+#[serial]
+fn read_fenced_test() {
+    let cfg = TestConfig::load("consensus_test").expect("Test config loaded");
+    let mut sys = TestSystem::with(cfg);
+    sys.start_all_nodes();
+
+    let leader = sys.get_elected_leader(1, cfg.wait_timeout);
+    let follower = (1..=cfg.num_nodes as NodeId)
+        .find(|pid| *pid != leader)
+        .unwrap();
+    let follower_node = sys.nodes.get(&follower).unwrap();
+    let v = Value::with_id(1);
+    let mut futures = vec![];
+    for node in sys.nodes.values() {
+        let (kprom, kfuture) = promise::<()>();
+        node.on_definition(|x| x.insert_decided_future(Ask::new(kprom, v.clone())));
+        futures.push(kfuture);
+    }
+    sys.nodes
+        .get(&leader)
+        .unwrap()
+        .on_definition(|x| x.paxos.append(v.clone()).expect("Failed to append"));
+    match FutureCollection::collect_with_timeout::<Vec<_>>(futures, cfg.wait_timeout) {
+        Ok(_) => {}
+        Err(e) => panic!("Error on collecting futures of decided proposals: {}", e),
+    }
+
+    let ballot = follower_node.on_definition(|x| x.paxos.get_promise());
+    for node in sys.nodes.values() {
+        node.on_definition(|x| {
+            assert_eq!(
+                x.paxos.read_fenced(0, ballot).ok(),
+                Some(Some(LogEntry::Decided(v.clone())))
+            );
+            assert_eq!(x.paxos.read_fenced(1, ballot).ok(), Some(None));
+        });
+    }
+
+    sys.set_node_connections(leader, false);
+    wait_until(cfg.wait_timeout, "No new leader was elected", || {
+        follower_node.on_definition(|x| x.paxos.get_promise()) > ballot
+    });
+    follower_node.on_definition(|x| {
+        let current = x.paxos.get_promise();
+        assert!(matches!(
+            x.paxos.read_fenced(0, ballot),
+            Err(ReadErr::StaleBallot { current: c }) if c == current
+        ));
+        assert_eq!(
+            x.paxos.read_fenced(0, current).ok(),
+            Some(Some(LogEntry::Decided(v.clone())))
+        );
+    });
+
+    let kompact_system =
+        std::mem::take(&mut sys.kompact_system).expect("No KompactSystem in memory");
+    match kompact_system.shutdown() {
+        Ok(_) => {}
+        Err(e) => panic!("Error on kompact shutdown: {}", e),
+    };
+}
+
+/// Verifies that local reads with an explicit consistency level read the local log and that
+/// the levels that are not supported yet are rejected.
+#[test]
+#[serial]
+fn read_consistency_test() {
+    let cfg = TestConfig::load("consensus_test").expect("Test config loaded");
+    let mut sys = TestSystem::with(cfg);
+    sys.start_all_nodes();
+
+    let leader = sys.get_elected_leader(1, cfg.wait_timeout);
+    let v = Value::with_id(1);
+    let mut futures = vec![];
+    for node in sys.nodes.values() {
+        let (kprom, kfuture) = promise::<()>();
+        node.on_definition(|x| x.insert_decided_future(Ask::new(kprom, v.clone())));
+        futures.push(kfuture);
+    }
+    sys.nodes
+        .get(&leader)
+        .unwrap()
+        .on_definition(|x| x.paxos.append(v.clone()).expect("Failed to append"));
+    match FutureCollection::collect_with_timeout::<Vec<_>>(futures, cfg.wait_timeout) {
+        Ok(_) => {}
+        Err(e) => panic!("Error on collecting futures of decided proposals: {}", e),
+    }
+
+    for node in sys.nodes.values() {
+        node.on_definition(|x| {
+            assert_eq!(
+                x.paxos.read_with(0, ReadConsistency::Local).ok(),
+                Some(Some(LogEntry::Decided(v.clone())))
+            );
+            assert_eq!(
+                x.paxos.read_with(1, ReadConsistency::Local).ok(),
+                Some(None)
+            );
+            assert_eq!(
+                x.paxos
+                    .read_entries_with(.., ReadConsistency::default())
+                    .ok(),
+                Some(x.paxos.read_entries(..))
+            );
+            for consistency in [ReadConsistency::LeaderLease, ReadConsistency::Linearizable] {
+                assert!(matches!(
+                    x.paxos.read_with(0, consistency),
+                    Err(ReadErr::UnsupportedConsistency(c)) if c == consistency
+                ));
+            }
+        });
+    }
+
+    let kompact_system =
+        std::mem::take(&mut sys.kompact_system).expect("No KompactSystem in memory");
+    match kompact_system.shutdown() {
+        Ok(_) => {}
+        Err(e) => panic!("Error on kompact shutdown: {}", e),
+    };
+}
+
+/// Verifies that a server that is behind the log observed by a client refuses its reads and
+/// appends with the token, and serves them once it has caught up.
+#[test]
+#[serial]
+fn read_token_test() {
+    let cfg = TestConfig::load("read_token_test").expect("Test config loaded");
+    let mut sys = TestSystem::with(cfg);
+    sys.start_all_nodes();
+
+    let leader = sys.get_elected_leader(1, cfg.wait_timeout);
+    let lagging = (1..=cfg.num_nodes as NodeId)
+        .find(|pid| *pid != leader)
+        .unwrap();
+    let leader_px = sys.nodes.get(&leader).unwrap();
+    let lagging_px = sys.nodes.get(&lagging).unwrap();
+    let proposals = utils::create_proposals(1, 2 * cfg.num_proposals);
+    let (first_proposals, last_proposals) = proposals.split_at(cfg.num_proposals as usize);
+    sys.make_proposals(leader, first_proposals.to_vec(), cfg.wait_timeout);
+    wait_until(cfg.wait_timeout, "The proposals were not decided", || {
+        lagging_px.on_definition(|x| x.paxos.get_decided_idx()) == cfg.num_proposals as usize
+    });
+    let (entry, old_token) = lagging_px
+        .on_definition(|x| x.paxos.read_monotonic(0, ReadToken::default()))
+        .expect("Failed to read");
+    assert_eq!(entry, Some(LogEntry::Decided(proposals[0].clone())));
+
+    // The client reads from the majority while the lagging server is disconnected
+    sys.set_node_connections(lagging, false);
+    sys.make_proposals(leader, last_proposals.to_vec(), cfg.wait_timeout);
+    let last_idx = 2 * cfg.num_proposals as usize - 1;
+    let token = leader_px.on_definition(|x| {
+        let (entry, token) = x
+            .paxos
+            .read_monotonic(last_idx, old_token)
+            .expect("Failed to read");
+        assert_eq!(entry, Some(LogEntry::Decided(proposals[last_idx].clone())));
+        assert_eq!(token, x.paxos.get_read_token());
+        token
+    });
+
+    // The client is switched to the lagging server
+    lagging_px.on_definition(|x| {
+        assert!(matches!(
+            x.paxos.read_monotonic(last_idx, token),
+            Err(ReadErr::BehindToken { current, .. }) if current == x.paxos.get_read_token()
+        ));
+        let v = Value::with_id(0);
+        match x.paxos.append_monotonic(v.clone(), token) {
+            Err(ProposeErr::BehindToken(refused, _)) => assert_eq!(refused, v),
+            res => panic!("Append was not refused: {:?}", res),
+        }
+        x.paxos
+            .read_monotonic(0, old_token)
+            .expect("Failed to read with the old token");
+    });
+
+    sys.set_node_connections(lagging, true);
+    lagging_px.on_definition(|x| x.paxos.reconnected(leader));
+    wait_until(
+        cfg.wait_timeout,
+        "The lagging server did not catch up",
+        || lagging_px.on_definition(|x| x.paxos.get_decided_idx()) == last_idx + 1,
+    );
+    lagging_px.on_definition(|x| {
+        // The server might have caught up with a snapshot of the entries
+        let (entry, _) = x
+            .paxos
+            .read_monotonic(last_idx, token)
+            .expect("Failed to read after catching up");
+        assert!(entry.is_some());
+        x.paxos
+            .append_monotonic(Value::with_id(0), token)
+            .expect("Failed to append after catching up");
+    });
+
+    let kompact_system =
+        std::mem::take(&mut sys.kompact_system).expect("No KompactSystem in memory");
+    match kompact_system.shutdown() {
+        Ok(_) => {}
+        Err(e) => panic!("Error on kompact shutdown: {}", e),
+    };
+}
+
+/// Decides the proposals of `cfg` on all servers of a new `TestSystem` and returns it with the
+/// leader.
+fn decided_system(cfg: &TestConfig) -> (TestSystem, NodeId, Vec<Value>) {
+    let sys = TestSystem::with(*cfg);
+    sys.start_all_nodes();
+    let leader = sys.get_elected_leader(1, cfg.wait_timeout);
+    let vec_proposals = utils::create_proposals(1, cfg.num_proposals);
+    sys.make_proposals(leader, vec_proposals.clone(), cfg.wait_timeout);
+    wait_until(cfg.wait_timeout, "The proposals were not decided", || {
+        sys.nodes
+            .values()
+            .all(|node| node.on_definition(|x| x.paxos.get_decided_idx()) == vec_proposals.len())
+    });
+    (sys, leader, vec_proposals)
+}
+
+/// Waits until the witness read `id` of `node` is reported and returns the report.
+fn witness_report(
+    node: &Arc<Component<OmniPaxosComponent>>,
+    id: u64,
+    cfg: &TestConfig,
+) -> WitnessReport<Value> {
+    let mut report = None;
+    wait_until(
+        cfg.wait_timeout,
+        "The witness read was not reported",
+        || {
+            report = node.on_definition(|x| x.paxos.take_witness_report(id));
+            report.is_some()
+        },
+    );
+    report.unwrap()
+}
+
+/// Verifies that a witness read collects the entry and ballots of the other servers once a
+/// quorum has replied.
+#[test]
+#[serial]
+fn witness_read_test() {
+    let cfg = TestConfig::load("witness_read_test").expect("Test config loaded");
+    let (mut sys, leader, vec_proposals) = decided_system(&cfg);
+    let follower = (1..=cfg.num_nodes as NodeId)
+        .find(|pid| *pid != leader)
+        .unwrap();
+    let follower_node = sys.nodes.get(&follower).unwrap();
+    let idx = 2;
+    let id = follower_node.on_definition(|x| x.paxos.start_witness_read(idx));
+    let report = witness_report(follower_node, id, &cfg);
+    assert_eq!((report.id, report.idx), (id, idx));
+    // The replies that arrive after the report is taken are not part of it
+    let witnesses: Vec<NodeId> = report.witnesses.iter().map(|(pid, _)| *pid).collect();
+    assert!(witnesses.len() >= 2 && witnesses.contains(&follower));
+    let ballot = sys
+        .nodes
+        .get(&leader)
+        .unwrap()
+        .on_definition(|x| x.paxos.get_entry_ballot(idx));
+    assert!(ballot.is_some());
+    for (_, witness) in &report.witnesses {
+        assert_eq!(witness.entry, Some(vec_proposals[idx].clone()));
+        assert!(witness.decided);
+        assert_eq!(witness.entry_ballot, ballot);
+        assert_eq!(witness.decided_idx, vec_proposals.len());
+    }
+    assert!(!report.diverged());
+    assert!(report.failed().is_empty());
+    // The report is only returned once
+    assert!(follower_node
+        .on_definition(|x| x.paxos.take_witness_report(id))
+        .is_none());
+
+    // An index beyond the log is reported as missing
+    let id = follower_node.on_definition(|x| x.paxos.start_witness_read(vec_proposals.len()));
+    let report = witness_report(follower_node, id, &cfg);
+    assert!(report
+        .witnesses
+        .iter()
+        .all(|(_, w)| w.entry.is_none() && !w.decided));
+    assert!(!report.diverged());
+
+    let kompact_system =
+        std::mem::take(&mut sys.kompact_system).expect("No KompactSystem in memory");
+    match kompact_system.shutdown() {
+        Ok(_) => {}
+        Err(e) => panic!("Error on kompact shutdown: {}", e),
+    };
+}
+
+/// Verifies that a witness read is only reported once a quorum has replied.
+#[test]
+#[serial]
+fn witness_read_quorum_test() {
+    let cfg = TestConfig::load("witness_read_test").expect("Test config loaded");
+    let (mut sys, leader, _) = decided_system(&cfg);
+    let mut followers = (1..=cfg.num_nodes as NodeId).filter(|pid| *pid != leader);
+    let (connected, disconnected) = (followers.next().unwrap(), followers.next().unwrap());
+    let leader_node = sys.nodes.get(&leader).unwrap();
+
+    // Only one of the two other servers is connected
+    sys.set_node_connections(disconnected, false);
+    let id = leader_node.on_definition(|x| x.paxos.start_witness_read(0));
+    let report = witness_report(leader_node, id, &cfg);
+    assert_eq!(report.witnesses.len(), 2);
+
+    sys.set_node_connections(connected, false);
+    let id = leader_node.on_definition(|x| x.paxos.start_witness_read(0));
+    thread::sleep(cfg.election_timeout);
+    assert!(leader_node
+        .on_definition(|x| x.paxos.take_witness_report(id))
+        .is_none());
+
+    let kompact_system =
+        std::mem::take(&mut sys.kompact_system).expect("No KompactSystem in memory");
+    match kompact_system.shutdown() {
+        Ok(_) => {}
+        Err(e) => panic!("Error on kompact shutdown: {}", e),
+    };
+}
+
+/// Verifies that decided entries that differ are reported as divergent.
+#[test]
+fn witness_diverged_test() {
+    let witness = |v, decided| Witness {
+        entry: Some(Value::with_id(v)),
+        decided,
+        entry_ballot: None,
+        accepted_round: Default::default(),
+        accepted_idx: 1,
+        decided_idx: decided as usize,
+        compacted_idx: 0,
+        error: None,
+    };
+    let report = |witnesses| WitnessReport {
+        id: 1,
+        idx: 0,
+        witnesses,
+    };
+    assert!(!report(vec![(1, witness(1, true)), (2, witness(2, false))]).diverged());
+    assert!(report(vec![(1, witness(1, true)), (2, witness(2, true))]).diverged());
+    assert!(!report(vec![(1, witness(1, true)), (2, witness(1, true))]).diverged());
+}
+
+/// Verifies that a fenced read whose storage fails returns the storage error, and that the
+/// entry is read once the storage works again.
+#[test]
+#[serial]
+fn read_fenced_storage_err_test() {
+    let cfg = TestConfig::load("read_storage_err_test").expect("Test config loaded");
+    let mut sys = TestSystem::with(cfg);
+    let storage_confs = sys.use_broken_storages(&cfg);
+    sys.start_all_nodes();
+
+    let leader = sys.get_elected_leader(1, cfg.wait_timeout);
+    sys.make_proposals(
+        leader,
+        utils::create_proposals(1, cfg.num_proposals),
+        cfg.wait_timeout,
+    );
+    sys.nodes.get(&leader).unwrap().on_definition(|x| {
+        let ballot = x.paxos.get_promise();
+        storage_confs[&leader]
+            .lock()
+            .unwrap()
+            .schedule_failure_in(1);
+        match x.paxos.read_fenced(0, ballot) {
+            Err(ReadErr::Storage(err)) => {
+                assert_eq!(err.op, "read log entries");
+                assert_eq!(
+                    err.source().unwrap().to_string(),
+                    "test error from mocked broken storage"
+                );
+                assert!(err
+                    .to_string()
+                    .starts_with("storage error while trying to read log entries"));
+            }
+            res => panic!("Unexpected read result: {:?}", res),
+        }
+        assert_eq!(
+            x.paxos.read_fenced(0, ballot).ok(),
+            Some(Some(LogEntry::Decided(Value::with_id(1))))
+        );
+    });
+
+    let kompact_system =
+        std::mem::take(&mut sys.kompact_system).expect("No KompactSystem in memory");
+    match kompact_system.shutdown() {
+        Ok(_) => {}
+        Err(e) => panic!("Error on kompact shutdown: {}", e),
+    };
+}
+
+const COMMIT_DELAY: Duration = Duration::from_millis(20);
+
+/// Verifies that the leader reports the latency of its appends once they are decided, and that
+/// a delayed quorum shows up in the reported latency.
+#[test]
+#[serial]
+fn commit_latency_test() {
+    let cfg = TestConfig::load("commit_latency_test").expect("Test config loaded");
+    let mut sys = TestSystem::with(cfg);
+    sys.start_all_nodes();
+
+    let leader = sys.get_elected_leader(1, cfg.wait_timeout);
+    let leader_node = sys.nodes.get(&leader).unwrap();
+    assert_eq!(
+        leader_node.on_definition(|x| x.paxos.get_commit_latency()),
+        None
+    );
+    let vec_proposals = utils::create_proposals(1, cfg.num_proposals);
+    sys.make_proposals(leader, vec_proposals[..1].to_vec(), cfg.wait_timeout);
+    assert_eq!(
+        leader_node.on_definition(|x| x.paxos.get_commit_latency().map(|l| l.samples)),
+        Some(1)
+    );
+
+    // The followers only receive the entries after the delay
+    let followers: Vec<NodeId> = (1..=cfg.num_nodes as NodeId)
+        .filter(|pid| *pid != leader)
+        .collect();
+    let mut futures = vec![];
+    leader_node.on_definition(|x| {
+        x.blocked_peers.extend(&followers);
+        for v in &vec_proposals[1..] {
+            let (kprom, kfuture) = promise::<()>();
+            x.paxos.append(v.clone()).expect("Failed to append");
+            x.insert_decided_future(Ask::new(kprom, v.clone()));
+            futures.push(kfuture);
+        }
+    });
+    thread::sleep(COMMIT_DELAY);
+    leader_node.on_definition(|x| x.blocked_peers.clear());
+    match FutureCollection::collect_with_timeout::<Vec<_>>(futures, cfg.wait_timeout) {
+        Ok(_) => {}
+        Err(e) => panic!("Error on collecting futures of decided proposals: {}", e),
+    }
+
+    leader_node.on_definition(|x| {
+        let latency = x
+            .paxos
+            .get_commit_latency()
+            .expect("no commit latency reported");
+        assert_eq!(latency.samples, vec_proposals.len());
+        assert!(latency.p50 >= COMMIT_DELAY);
+        assert!(latency.p99 >= latency.p50);
+        assert_eq!(x.paxos.get_ui_states().commit_latency, Some(latency));
+    });
+    for pid in &followers {
+        let node = sys.nodes.get(pid).unwrap();
+        assert_eq!(node.on_definition(|x| x.paxos.get_commit_latency()), None);
+    }
+
+    let kompact_system =
+        std::mem::take(&mut sys.kompact_system).expect("No KompactSystem in memory");
+    match kompact_system.shutdown() {
+        Ok(_) => {}
+        Err(e) => panic!("Error on kompact shutdown: {}", e),
+    };
+}
+
+/// Verifies that the leader reports a follower that does not acknowledge its entries and a
+/// disconnected follower as slow with the likely cause, and reports when a follower caught up.
+#[test]
+#[serial]
+fn slow_follower_test() {
+    let cfg = TestConfig::load("slow_follower_test").expect("Test config loaded");
+    let mut sys = TestSystem::with(cfg);
+    sys.start_all_nodes();
+
+    let leader = sys.get_elected_leader(1, cfg.wait_timeout);
+    let mut followers = (1..=cfg.num_nodes as NodeId).filter(|pid| *pid != leader);
+    let (slow, disconnected) = (followers.next().unwrap(), followers.next().unwrap());
+    let vec_proposals = utils::create_proposals(1, cfg.num_proposals);
+    sys.make_proposals(leader, vec_proposals[..1].to_vec(), cfg.wait_timeout);
+    let leader_node = sys.nodes.get(&leader).unwrap();
+    assert_eq!(
+        leader_node.on_definition(|x| x.paxos.take_slow_follower_events()),
+        vec![]
+    );
+    let mut events = vec![];
+    let mut wait_for_event = |msg: &str, event: SlowFollowerEvent| {
+        wait_until(cfg.wait_timeout, msg, || {
+            events.append(&mut leader_node.on_definition(|x| x.paxos.take_slow_follower_events()));
+            events.contains(&event)
+        });
+    };
+
+    // A follower that is behind for too long is reported even if its lag is small
+    sys.nodes.get(&slow).unwrap().on_definition(|x| {
+        x.drop_outgoing = Some(|msg| matches!(msg, Message::SequencePaxos(_)));
+    });
+    leader_node.on_definition(|x| {
+        x.paxos
+            .append(vec_proposals[1].clone())
+            .expect("Failed to append")
+    });
+    wait_for_event(
+        "The follower without acks was not reported",
+        SlowFollowerEvent::Slow {
+            pid: slow,
+            lag: 1,
+            lagging_ticks: cfg.slow_follower_tick_timeout.unwrap(),
+            cause: SlowFollowerCause::NoAcks,
+        },
+    );
+    sys.nodes
+        .get(&slow)
+        .unwrap()
+        .on_definition(|x| x.drop_outgoing = None);
+    leader_node.on_definition(|x| {
+        x.paxos
+            .append(vec_proposals[2].clone())
+            .expect("Failed to append")
+    });
+    wait_for_event(
+        "The follower that caught up was not reported",
+        SlowFollowerEvent::CaughtUp(slow),
+    );
+
+    // A disconnected follower is reported as soon as it exceeds the lag
+    sys.set_node_connections(disconnected, false);
+    wait_until(
+        cfg.wait_timeout,
+        "The follower was not disconnected",
+        || {
+            leader_node.on_definition(|x| {
+                x.paxos
+                    .get_ui_states()
+                    .heartbeats
+                    .iter()
+                    .all(|hb| hb.ballot.pid != disconnected)
+            })
+        },
+    );
+    let max_lag = cfg.slow_follower_lag.unwrap();
+    leader_node.on_definition(|x| {
+        for v in &vec_proposals[3..=max_lag + 3] {
+            x.paxos.append(v.clone()).expect("Failed to append");
+        }
+    });
+    wait_for_event(
+        "The disconnected follower was not reported",
+        SlowFollowerEvent::Slow {
+            pid: disconnected,
+            lag: max_lag + 1,
+            lagging_ticks: 0,
+            cause: SlowFollowerCause::Disconnected,
+        },
+    );
+
+    let kompact_system =
+        std::mem::take(&mut sys.kompact_system).expect("No KompactSystem in memory");
+    match kompact_system.shutdown() {
+        Ok(_) => {}
+        Err(e) => panic!("Error on kompact shutdown: {}", e),
+    };
+}
+
+/// Waits until `done` returns true while every server that considers itself the leader appends
+/// entries, so that the leader appends in every tick.
+fn append_until<F>(sys: &TestSystem, timeout: Duration, msg: &str, mut done: F)
+where
+    F: FnMut() -> bool,
+{
+    let mut id = 0;
+    wait_until(timeout, msg, || {
+        for (pid, node) in &sys.nodes {
+            node.on_definition(|x| {
+                if x.paxos.get_current_leader() == Some(*pid) {
+                    id += 1;
+                    x.paxos
+                        .append(Value::with_id(id))
+                        .expect("Failed to append");
+                }
+            });
+        }
+        done()
+    });
+}
+
+/// Verifies that a leader with a slow storage steps down so that another server takes over,
+/// and reports when its storage is fast again.
+#[test]
+#[serial]
+fn slow_storage_step_down_test() {
+    let cfg = TestConfig {
+        step_down_on_slow_storage: true,
+        ..TestConfig::load("slow_storage_test").expect("Test config loaded")
+    };
+    let max_latency = cfg.slow_storage_latency.unwrap();
+    let mut sys = TestSystem::with(cfg);
+    let storages = sys.use_broken_storages(&cfg);
+    sys.start_all_nodes();
+
+    let leader = sys.get_elected_leader(1, cfg.wait_timeout);
+    let leader_node = sys.nodes.get(&leader).unwrap();
+    storages[&leader]
+        .lock()
+        .unwrap()
+        .set_delay(Some(2 * max_latency));
+    append_until(
+        &sys,
+        cfg.wait_timeout,
+        "The leader with the slow storage did not step down",
+        || {
+            sys.nodes.values().all(|node| {
+                node.on_definition(|x| x.paxos.get_current_leader())
+                    .is_some_and(|l| l != leader)
+            })
+        },
+    );
+    let events = leader_node.on_definition(|x| x.paxos.take_slow_storage_events());
+    assert!(matches!(
+        events[..],
+        [SlowStorageEvent::Slow { latency, stepped_down: true }] if latency > max_latency
+    ));
+
+    // The former leader reports its storage as fast again once it appends as a follower
+    storages[&leader].lock().unwrap().set_delay(None);
+    let mut events = vec![];
+    append_until(
+        &sys,
+        cfg.wait_timeout,
+        "The fast storage was not reported",
+        || {
+            events.append(&mut leader_node.on_definition(|x| x.paxos.take_slow_storage_events()));
+            !events.is_empty()
+        },
+    );
+    assert_eq!(events, vec![SlowStorageEvent::Recovered]);
+    for (_, node) in sys.nodes.iter().filter(|(pid, _)| **pid != leader) {
+        assert_eq!(
+            node.on_definition(|x| x.paxos.take_slow_storage_events()),
+            vec![]
+        );
+    }
+
+    let kompact_system =
+        std::mem::take(&mut sys.kompact_system).expect("No KompactSystem in memory");
+    match kompact_system.shutdown() {
+        Ok(_) => {}
+        Err(e) => panic!("Error on kompact shutdown: {}", e),
+    };
+}
+
+/// Verifies that a leader with a slow storage only reports it if it is not configured to step
+/// down.
+#[test]
+#[serial]
+fn slow_storage_event_test() {
+    let cfg = TestConfig::load("slow_storage_test").expect("Test config loaded");
+    let mut sys = TestSystem::with(cfg);
+    let storages = sys.use_broken_storages(&cfg);
+    sys.start_all_nodes();
+
+    let leader = sys.get_elected_leader(1, cfg.wait_timeout);
+    let leader_node = sys.nodes.get(&leader).unwrap();
+    storages[&leader]
+        .lock()
+        .unwrap()
+        .set_delay(Some(2 * cfg.slow_storage_latency.unwrap()));
+    let mut events = vec![];
+    append_until(
+        &sys,
+        cfg.wait_timeout,
+        "The slow storage was not reported",
+        || {
+            events.append(&mut leader_node.on_definition(|x| x.paxos.take_slow_storage_events()));
+            !events.is_empty()
+        },
+    );
+    assert!(matches!(
+        events[..],
+        [SlowStorageEvent::Slow {
+            stepped_down: false,
+            ..
+        }]
+    ));
+    for node in sys.nodes.values() {
+        assert_eq!(
+            node.on_definition(|x| x.paxos.get_current_leader()),
+            Some(leader)
+        );
+    }
+
+    let kompact_system =
+        std::mem::take(&mut sys.kompact_system).expect("No KompactSystem in memory");
+    match kompact_system.shutdown() {
+        Ok(_) => {}
+        Err(e) => panic!("Error on kompact shutdown: {}", e),
+    };
+}
+
+/// Verifies that the servers elect a leader and decide entries when the timers are driven
+/// individually instead of by `tick()`, and that the compaction only runs when its timer does.
+#[test]
+#[serial]
+#[cfg(not(feature = "no_snapshots"))]
+fn handle_timer_test() {
+    let cfg = TestConfig::load("timer_test").expect("Test config loaded");
+    let mut sys = TestSystem::with(cfg);
+    for node in sys.nodes.values() {
+        node.on_definition(|x| x.timers = Some(vec![Timer::Election]));
+    }
+    sys.start_all_nodes();
+
+    let leader = sys.get_elected_leader(1, cfg.wait_timeout);
+    let vec_proposals = utils::create_proposals(1, cfg.num_proposals);
+    let last = vec_proposals.last().unwrap();
+    let mut futures = vec![];
+    for (_, node) in sys.nodes.iter().filter(|(pid, _)| **pid != leader) {
+        let (kprom, kfuture) = promise::<()>();
+        node.on_definition(|x| x.insert_decided_future(Ask::new(kprom, last.clone())));
+        futures.push(kfuture);
+    }
+    sys.make_proposals(leader, vec_proposals, cfg.wait_timeout);
+    match FutureCollection::collect_with_timeout::<Vec<_>>(futures, cfg.wait_timeout) {
+        Ok(_) => {}
+        Err(e) => panic!("Error on collecting futures of decided proposals: {}", e),
+    }
+
+    // the compaction timer does not time out by ticking
+    for node in sys.nodes.values() {
+        node.on_definition(|x| x.timers = None);
+    }
+    thread::sleep(2 * cfg.election_timeout);
+    // all servers are scheduled to snapshot before `snapshot_interval` and do so once their
+    // compaction timer is run
+    for node in sys.nodes.values() {
+        node.on_definition(|x| {
+            assert_eq!(x.paxos.get_compacted_idx(), 0);
+            x.paxos
+                .handle_timer(Timer::Compaction)
+                .expect("Failed to handle timer");
+            assert!(x.paxos.get_compacted_idx() > 0);
+        });
+    }
+
+    let kompact_system =
+        std::mem::take(&mut sys.kompact_system).expect("No KompactSystem in memory");
+    match kompact_system.shutdown() {
+        Ok(_) => {}
+        Err(e) => panic!("Error on kompact shutdown: {}", e),
+    };
+}
+
+fn paxos_msg(from: NodeId, to: NodeId, msg: PaxosMsg<Value>) -> Message<Value> {
+    Message::SequencePaxos(PaxosMessage {
+        from,
+        to,
+        namespace: None,
+        msg,
+    })
+}
+
+/// Injects implausible messages into a running cluster and verifies that they are rejected and
+/// counted per sender, that repeated offenders are reported, and that the cluster keeps
+/// working correctly.
+#[test]
+#[serial]
+fn message_validation_test() {
+    let cfg = TestConfig::load("message_validation_test").expect("Test config loaded");
+    let threshold = cfg.misbehaving_peer_threshold.unwrap();
+    let mut sys = TestSystem::with(cfg);
+    sys.start_all_nodes();
+
+    let leader = sys.get_elected_leader(1, cfg.wait_timeout);
+    let follower = (1..=cfg.num_nodes as NodeId)
+        .find(|pid| *pid != leader)
+        .unwrap();
+    let leader_node = sys.nodes.get(&leader).unwrap();
+    let follower_node = sys.nodes.get(&follower).unwrap();
+    let n = leader_node.on_definition(|x| x.paxos.get_promise());
+    leader_node.on_definition(|x| x.paxos.append(Value::with_id(1)).expect("Failed to append"));
+    wait_until(cfg.wait_timeout, "The entry was not decided", || {
+        sys.nodes
+            .values()
+            .all(|node| node.on_definition(|x| x.paxos.get_decided_idx()) == 1)
+    });
+
+    // an Accepted beyond the end of the leader's log would decide entries that don't exist
+    let accepted = Accepted {
+        n,
+        accepted_idx: 100,
+        decided_idx: 1,
+    };
+    leader_node.on_definition(|x| {
+        x.paxos
+            .handle_incoming(paxos_msg(follower, leader, PaxosMsg::Accepted(accepted)))
+            .expect("Failed to handle message");
+        assert_eq!(x.paxos.get_decided_idx(), 1);
+        assert_eq!(x.paxos.get_rejected_message_count(follower), 1);
+        assert!(x.paxos.take_misbehaving_peers().is_empty());
+        // a message from a server that is not part of the cluster
+        let non_member = cfg.num_nodes as NodeId + 1;
+        x.paxos
+            .handle_incoming(paxos_msg(non_member, leader, PaxosMsg::Accepted(accepted)))
+            .expect("Failed to handle message");
+        assert_eq!(x.paxos.get_rejected_message_count(non_member), 1);
+    });
+
+    // a Prepare that claims to have decided more than it accepted
+    let prepare = Prepare {
+        n: Ballot { n: n.n + 1, ..n },
+        decided_idx: 10,
+        n_accepted: n,
+        accepted_idx: 1,
+    };
+    // a heartbeat reply with a ballot of a server that is not part of the cluster
+    let reply = HeartbeatReply {
+        round: 1,
+        ballot: Ballot {
+            pid: cfg.num_nodes as NodeId + 1,
+            priority: u32::MAX,
+            ..n
+        },
+        leader: n,
+        happy: false,
+        connectivity: 2,
+        quorum_connected: true,
+        payload: None,
+        compression: None,
+    };
+    follower_node.on_definition(|x| {
+        x.paxos
+            .handle_incoming(paxos_msg(leader, follower, PaxosMsg::Prepare(prepare)))
+            .expect("Failed to handle message");
+        assert_eq!(x.paxos.get_promise(), n);
+        x.paxos
+            .handle_incoming(Message::BLE(BLEMessage {
+                from: leader,
+                to: follower,
+                namespace: None,
+                msg: HeartbeatMsg::Reply(reply),
+            }))
+            .expect("Failed to handle message");
+        assert_eq!(x.paxos.get_rejected_message_count(leader), threshold);
+        assert_eq!(
+            x.paxos.take_misbehaving_peers(),
+            vec![MisbehavingPeer {
+                pid: leader,
+                rejected: threshold,
+                cause: RejectedMessageCause::NonMemberBallot,
+            }]
+        );
+    });
+
+    // the cluster is unaffected
+    sys.make_proposals(follower, vec![Value::with_id(2)], cfg.wait_timeout);
+    for node in sys.nodes.values() {
+        assert_eq!(node.on_definition(|x| x.paxos.get_promise()), n);
+    }
+
+    let kompact_system =
+        std::mem::take(&mut sys.kompact_system).expect("No KompactSystem in memory");
+    match kompact_system.shutdown() {
+        Ok(_) => {}
+        Err(e) => panic!("Error on kompact shutdown: {}", e),
+    };
+}
+
+fn indexed(idx: usize, entry: LogEntry<Value>) -> IndexedLogEntry<Value> {
+    IndexedLogEntry { idx, entry }
+}
+
+/// Reads entries across a trimmed prefix and verifies that every entry is returned with its
+/// index in the log, both by the server and by a reader.
+#[test]
+#[serial]
+fn indexed_read_test() {
+    let cfg = TestConfig::load("consensus_test").expect("Test config loaded");
+    let mut sys = TestSystem::with(cfg);
+    sys.start_all_nodes();
+
+    let leader = sys.get_elected_leader(1, cfg.wait_timeout);
+    let vec_proposals = utils::create_proposals(1, 10);
+    let last = vec_proposals.last().unwrap();
+    let mut futures = vec![];
+    for (_, node) in sys.nodes.iter().filter(|(pid, _)| **pid != leader) {
+        let (kprom, kfuture) = promise::<()>();
+        node.on_definition(|x| x.insert_decided_future(Ask::new(kprom, last.clone())));
+        futures.push(kfuture);
+    }
+    sys.make_proposals(leader, vec_proposals.clone(), cfg.wait_timeout);
+    match FutureCollection::collect_with_timeout::<Vec<_>>(futures, cfg.wait_timeout) {
+        Ok(_) => {}
+        Err(e) => panic!("Error on collecting futures of decided proposals: {}", e),
+    }
+    sys.nodes
+        .get(&leader)
+        .unwrap()
+        .on_definition(|x| x.paxos.trim(Some(4)).expect("Failed to trim"));
+    wait_until(cfg.wait_timeout, "The log was not trimmed", || {
+        sys.nodes
+            .values()
+            .all(|node| node.on_definition(|x| x.paxos.get_compacted_idx()) == 4)
+    });
+
+    let expected: Vec<_> = [indexed(2, LogEntry::Trimmed(4))]
+        .into_iter()
+        .chain((4..10).map(|idx| indexed(idx, LogEntry::Decided(vec_proposals[idx].clone()))))
+        .collect();
+    for node in sys.nodes.values() {
+        node.on_definition(|x| {
+            assert_eq!(
+                x.paxos.read_decided_suffix_indexed(2),
+                Some(expected.clone())
+            );
+            assert_eq!(
+                x.paxos.reader().read_decided_suffix_indexed(2),
+                Some(expected.clone())
+            );
+            assert_eq!(
+                x.paxos.read_entries_indexed(6..8),
+                Some(expected[3..5].to_vec())
+            );
+            assert_eq!(
+                x.paxos.read_entries_indexed(..=1),
+                Some(vec![indexed(0, LogEntry::Trimmed(4))])
+            );
+            assert_eq!(x.paxos.read_decided_suffix_indexed(10), None);
+        });
+    }
+
+    let kompact_system =
+        std::mem::take(&mut sys.kompact_system).expect("No KompactSystem in memory");
+    match kompact_system.shutdown() {
+        Ok(_) => {}
+        Err(e) => panic!("Error on kompact shutdown: {}", e),
+    };
+}
+
+/// The leader decides twice before its outgoing messages are taken. Verifies that each
+/// follower gets a single Decide with the latest decided index and that the followers keep
+/// accepting entries in the same sequence afterwards.
+#[test]
+#[serial]
+fn coalesced_decide_test() {
+    let cfg = TestConfig::load("consensus_test").expect("Test config loaded");
+    let mut sys = TestSystem::with(cfg);
+    sys.start_all_nodes();
+
+    let leader = sys.get_elected_leader(1, cfg.wait_timeout);
+    let followers: Vec<NodeId> = (1..=cfg.num_nodes as NodeId)
+        .filter(|pid| *pid != leader)
+        .collect();
+    let leader_node = sys.nodes.get(&leader).unwrap();
+    let vec_proposals = utils::create_proposals(1, 4);
+    let decided_on_all = |decided_idx: usize| {
+        wait_until(cfg.wait_timeout, "The entries were not decided", || {
+            sys.nodes
+                .values()
+                .all(|node| node.on_definition(|x| x.paxos.get_decided_idx()) == decided_idx)
+        });
+    };
+    sys.make_proposals(leader, vec_proposals[..1].to_vec(), cfg.wait_timeout);
+    decided_on_all(1);
+    let leader_ballot = leader_node.on_definition(|x| x.paxos.get_promise());
+
+    // The messages of the leader are only sent by hand, and only its accepts are sent before
+    // the followers have accepted both entries
+    leader_node.on_definition(|x| {
+        x.blocked_peers.extend(&followers);
+        for v in &vec_proposals[1..3] {
+            x.paxos.append(v.clone()).expect("Failed to append");
+            let msgs = followers
+                .iter()
+                .flat_map(|pid| x.paxos.outgoing_for(*pid))
+                .collect();
+            x.send(msgs);
+        }
+    });
+    wait_until(
+        cfg.wait_timeout,
+        "The followers did not accept the entries",
+        || {
+            leader_node.on_definition(|x| {
+                let accepted_indexes = x.paxos.get_ui_states().cluster_state.accepted_indexes;
+                followers
+                    .iter()
+                    .all(|pid| accepted_indexes[*pid as usize] == 3)
+            })
+        },
+    );
+    leader_node.on_definition(|x| {
+        let msgs: Vec<_> = followers
+            .iter()
+            .flat_map(|pid| x.paxos.outgoing_for(*pid))
+            .collect();
+        let mut decided_idxs: HashMap<NodeId, Vec<usize>> = HashMap::new();
+        for msg in &msgs {
+            if let Message::SequencePaxos(m) = msg {
+                if let PaxosMsg::Decide(d) = &m.msg {
+                    decided_idxs.entry(m.to).or_default().push(d.decided_idx);
+                }
+            }
+        }
+        for pid in &followers {
+            assert_eq!(decided_idxs.get(pid), Some(&vec![3]));
+        }
+        x.send(msgs);
+        x.blocked_peers.clear();
+    });
+    decided_on_all(3);
+
+    sys.make_proposals(leader, vec_proposals[3..].to_vec(), cfg.wait_timeout);
+    decided_on_all(4);
+    for node in sys.nodes.values() {
+        assert_eq!(node.on_definition(|x| x.paxos.get_promise()), leader_ballot);
+    }
+
+    let kompact_system =
+        std::mem::take(&mut sys.kompact_system).expect("No KompactSystem in memory");
+    match kompact_system.shutdown() {
+        Ok(_) => {}
+        Err(e) => panic!("Error on kompact shutdown: {}", e),
+    };
+}
+
+/// Verifies that the followers report their decided index to the leader with the entries they
+/// accept, so that the cluster state of the leader shows how far each server has decided.
+#[test]
+#[serial]
+fn decided_index_report_test() {
+    let cfg = TestConfig::load("consensus_test").expect("Test config loaded");
+    let mut sys = TestSystem::with(cfg);
+    sys.start_all_nodes();
+
+    let leader = sys.get_elected_leader(1, cfg.wait_timeout);
+    let vec_proposals = utils::create_proposals(1, 6);
+    let decided_on_all = |decided_idx: usize| {
+        wait_until(cfg.wait_timeout, "The entries were not decided", || {
+            sys.nodes
+                .values()
+                .all(|node| node.on_definition(|x| x.paxos.get_decided_idx()) == decided_idx)
+        });
+    };
+    sys.make_proposals(leader, vec_proposals[..5].to_vec(), cfg.wait_timeout);
+    decided_on_all(5);
+    sys.make_proposals(leader, vec_proposals[5..].to_vec(), cfg.wait_timeout);
+    decided_on_all(6);
+
+    // The followers accepted the last entry before they learned that it is decided
+    let leader_node = sys.nodes.get(&leader).unwrap();
+    wait_until(
+        cfg.wait_timeout,
+        "The leader did not receive all Accepted messages",
+        || {
+            leader_node.on_definition(|x| {
+                let accepted_indexes = x.paxos.get_ui_states().cluster_state.accepted_indexes;
+                (1..=cfg.num_nodes).all(|pid| accepted_indexes[pid] == 6)
+            })
+        },
+    );
+    let cluster_state = leader_node.on_definition(|x| x.paxos.get_ui_states().cluster_state);
+    for pid in 1..=cfg.num_nodes as NodeId {
+        let expected_decided_idx = if pid == leader { 6 } else { 5 };
+        assert_eq!(
+            cluster_state.decided_indexes[pid as usize],
+            expected_decided_idx
+        );
+    }
+
+    let kompact_system =
+        std::mem::take(&mut sys.kompact_system).expect("No KompactSystem in memory");
+    match kompact_system.shutdown() {
+        Ok(_) => {}
+        Err(e) => panic!("Error on kompact shutdown: {}", e),
+    };
+}
+
+/// Returns the range of entries that reached `stage` in `events`, which must be contiguous,
+/// and the time the last of them did.
+#[cfg(feature = "pipeline_events")]
+fn reached(events: &[PipelineEvent], stage: PipelineStage) -> Option<(usize, usize, Instant)> {
+    let mut stage_events = events.iter().filter(|e| e.stage == stage);
+    let first = stage_events.next()?;
+    let (from_idx, mut to_idx, mut time) = (first.from_idx, first.to_idx, first.time);
+    for e in stage_events {
+        assert_eq!(e.from_idx, to_idx, "gap in the events of {:?}", stage);
+        to_idx = e.to_idx;
+        time = e.time;
+    }
+    Some((from_idx, to_idx, time))
+}
+
+/// Verifies that the entries pass the stages of the pipeline in order, that the leader observes
+/// all stages, and that the followers observe the stages of their own log.
+#[test]
+#[serial]
+#[cfg(feature = "pipeline_events")]
+fn pipeline_events_test() {
+    let cfg = TestConfig::load("consensus_test").expect("Test config loaded");
+    let mut sys = TestSystem::with(cfg);
+    sys.start_all_nodes();
+
+    let leader = sys.get_elected_leader(1, cfg.wait_timeout);
+    let start_idx = sys
+        .nodes
+        .get(&leader)
+        .unwrap()
+        .on_definition(|x| x.paxos.get_decided_idx());
+    for node in sys.nodes.values() {
+        node.on_definition(|x| x.paxos.take_pipeline_events());
+    }
+    let proposals = utils::create_proposals(1, cfg.num_proposals);
+    let end_idx = start_idx + proposals.len();
+    sys.make_proposals(leader, proposals, cfg.wait_timeout);
+    wait_until(
+        cfg.wait_timeout,
+        "Not all servers decided the proposals",
+        || {
+            sys.nodes
+                .values()
+                .all(|node| node.on_definition(|x| x.paxos.get_decided_idx()) == end_idx)
+        },
+    );
+
+    for (pid, node) in &sys.nodes {
+        let events = node.on_definition(|x| x.paxos.take_pipeline_events());
+        assert!(events.windows(2).all(|w| w[0].time <= w[1].time));
+        let stages: &[PipelineStage] = if *pid == leader {
+            &[
+                PipelineStage::Proposed,
+                PipelineStage::Appended,
+                PipelineStage::Replicated,
+                PipelineStage::Decided,
+                PipelineStage::Readable,
+            ]
+        } else {
+            assert_eq!(reached(&events, PipelineStage::Proposed), None);
+            assert_eq!(reached(&events, PipelineStage::Replicated), None);
+            &[
+                PipelineStage::Appended,
+                PipelineStage::Decided,
+                PipelineStage::Readable,
+            ]
+        };
+        let mut previous = None;
+        for stage in stages {
+            let (from_idx, to_idx, time) = reached(&events, *stage)
+                .unwrap_or_else(|| panic!("no {:?} events at {}", stage, pid));
+            assert_eq!((from_idx, to_idx), (start_idx, end_idx), "{:?}", stage);
+            assert!(
+                previous <= Some(time),
+                "{:?} before the previous stage",
+                stage
+            );
+            previous = Some(time);
+        }
+        assert!(node.on_definition(|x| x.paxos.take_pipeline_events().is_empty()));
+    }
+
+    let kompact_system =
+        std::mem::take(&mut sys.kompact_system).expect("No KompactSystem in memory");
+    match kompact_system.shutdown() {
+        Ok(_) => {}
+        Err(e) => panic!("Error on kompact shutdown: {}", e),
+    };
+}
+
+/// Verifies that the administrative operations are recorded in order with their initiator and
+/// outcome, and that other calls are not recorded.
+#[test]
+#[serial]
+fn audit_log_test() {
+    let cfg = TestConfig::load("audit_test").expect("Test config loaded");
+    let mut sys = TestSystem::with(cfg);
+    sys.start_all_nodes();
+
+    let leader = sys.get_elected_leader(1, cfg.wait_timeout);
+    let follower = (1..=cfg.num_nodes as NodeId)
+        .find(|pid| *pid != leader)
+        .unwrap();
+    let log = sys.nodes.get(&follower).unwrap().on_definition(|x| {
+        let op = &mut x.paxos;
+        op.append(Value::with_id(1)).expect("Failed to append");
+        assert!(op.get_audit_log().is_empty());
+
+        op.set_audit_initiator(Some("alice".to_string()));
+        assert!(op.trim(None).is_err());
+        op.set_priority(3);
+        op.set_audit_initiator(None);
+        op.step_down();
+        let invalid_config = ClusterConfig {
+            configuration_id: 2,
+            nodes: vec![],
+            ..Default::default()
+        };
+        assert!(op.reconfigure(invalid_config, None).is_err());
+        op.get_audit_log()
+    });
+    let actions: Vec<AuditAction> = log.iter().map(|r| r.action.clone()).collect();
+    assert_eq!(
+        actions,
+        vec![
+            AuditAction::Trim { trim_idx: None },
+            AuditAction::SetPriority(3),
+            AuditAction::StepDown,
+            AuditAction::Reconfigure {
+                configuration_id: 2,
+                nodes: vec![]
+            },
+        ]
+    );
+    let initiators: Vec<Option<&str>> = log.iter().map(|r| r.initiator.as_deref()).collect();
+    assert_eq!(initiators, vec![Some("alice"), Some("alice"), None, None]);
+    let failed: Vec<bool> = log.iter().map(|r| r.error.is_some()).collect();
+    assert_eq!(failed, vec![true, false, false, true]);
+    let leader_log = sys
+        .nodes
+        .get(&leader)
+        .unwrap()
+        .on_definition(|x| x.paxos.get_audit_log());
+    assert!(leader_log.is_empty());
+
+    let kompact_system =
+        std::mem::take(&mut sys.kompact_system).expect("No KompactSystem in memory");
+    match kompact_system.shutdown() {
+        Ok(_) => {}
+        Err(e) => panic!("Error on kompact shutdown: {}", e),
+    };
+}
+
+/// Verifies that an entry whose deadline has already passed is refused by
+/// `append_with_deadline()`.
+#[test]
+#[serial]
+fn passed_deadline_test() {
+    let cfg = TestConfig::load("proposal_deadline_test").expect("Test config loaded");
+    let mut sys = TestSystem::with(cfg);
+    let deadline = SystemTime::now() - Duration::from_secs(1);
+    sys.nodes.get(&2).unwrap().on_definition(|x| {
+        match x.paxos.append_with_deadline(Value::with_id(1), deadline) {
+            Err(ProposeErr::DeadlineExceeded(entry)) => assert_eq!(entry, Value::with_id(1)),
+            res => panic!("Unexpected result: {:?}", res),
+        }
+    });
+
+    let kompact_system =
+        std::mem::take(&mut sys.kompact_system).expect("No KompactSystem in memory");
+    match kompact_system.shutdown() {
+        Ok(_) => {}
+        Err(e) => panic!("Error on kompact shutdown: {}", e),
+    };
+}
+
+/// Verifies that an entry that is buffered without a leader until its deadline passes is
+/// dropped, returned by `take_rejected_proposals()`, and never decided, while an entry with a
+/// later deadline is decided once there is a leader.
+#[test]
+#[serial]
+fn missed_deadline_test() {
+    let cfg = TestConfig::load("proposal_deadline_test").expect("Test config loaded");
+    let mut sys = TestSystem::with(cfg);
+    let follower = sys.nodes.get(&2).unwrap();
+    let now = SystemTime::now();
+    follower.on_definition(|x| {
+        x.paxos
+            .append_with_deadline(Value::with_id(1), now + Duration::from_millis(20))
+            .expect("Failed to append");
+        x.paxos
+            .append_with_deadline(Value::with_id(2), now + Duration::from_secs(60))
+            .expect("Failed to append");
+        x.paxos.append(Value::with_id(3)).expect("Failed to append");
+    });
+    thread::sleep(Duration::from_millis(30));
+    follower.on_definition(|x| {
+        x.paxos.tick().expect("Failed to tick");
+        let rejected = x.paxos.take_rejected_proposals();
+        assert_eq!(rejected.len(), 1);
+        assert_eq!(rejected[0].entry, Value::with_id(1));
+        assert_eq!(rejected[0].token, None);
+        assert_eq!(rejected[0].cause, ProposalRejectionCause::DeadlineExceeded);
+    });
+
+    sys.start_all_nodes();
+    wait_until(cfg.wait_timeout, "The proposals were not decided", || {
+        sys.nodes
+            .values()
+            .all(|node| node.on_definition(|x| x.paxos.get_decided_idx()) == 2)
+    });
+    for node in sys.nodes.values() {
+        verify_log(
+            node.on_definition(|x| x.read_decided_log()),
+            vec![Value::with_id(2), Value::with_id(3)],
+        );
+    }
+    assert!(sys
+        .nodes
+        .get(&2)
+        .unwrap()
+        .on_definition(|x| x.paxos.take_rejected_proposals())
+        .is_empty());
+
+    let kompact_system =
+        std::mem::take(&mut sys.kompact_system).expect("No KompactSystem in memory");
+    match kompact_system.shutdown() {
+        Ok(_) => {}
+        Err(e) => panic!("Error on kompact shutdown: {}", e),
+    };
+}
+
+/// The ranges a hook shipped, each with the index of its first entry.
+type ShippedRanges = Arc<Mutex<Vec<(usize, Vec<Value>)>>>;
+
+/// Records the shipped ranges, and takes at most `capacity` entries per call, or none while
+/// `busy` is set.
+#[derive(Clone, Default)]
+struct RecordingHook {
+    ranges: ShippedRanges,
+    busy: Arc<Mutex<bool>>,
+    capacity: usize,
+}
+
+impl DecideHook<Value> for RecordingHook {
+    fn on_decided(&mut self, range: &DecidedRange<Value>) -> ShipStatus {
+        if *self.busy.lock().unwrap() {
+            return ShipStatus::Busy;
+        }
+        let n = range.entries.len().min(self.capacity);
+        let entries = range.entries[..n].to_vec();
+        self.ranges.lock().unwrap().push((range.from_idx, entries));
+        match n == range.entries.len() {
+            true => ShipStatus::Shipped,
+            false => ShipStatus::Partial(n),
+        }
+    }
+}
+
+/// Verifies that the hook is offered the decided log in contiguous ranges of at most
+/// `max_batch_size` entries, and that the entries it did not take are offered again.
+#[test]
+#[serial]
+fn ship_decided_test() {
+    let cfg = TestConfig::load("decide_hook_test").expect("Test config loaded");
+    let mut sys = TestSystem::with(cfg);
+    sys.start_all_nodes();
+
+    let leader = sys.get_elected_leader(1, cfg.wait_timeout);
+    let follower = (1..=cfg.num_nodes as NodeId)
+        .find(|pid| *pid != leader)
+        .unwrap();
+    let hook = RecordingHook {
+        capacity: 2,
+        ..Default::default()
+    };
+    let config = DecideHookConfig {
+        from_idx: 0,
+        max_batch_size: 3,
+    };
+    let follower_node = sys.nodes.get(&follower).unwrap();
+    follower_node.on_definition(|x| {
+        x.paxos.set_decide_hook(hook.clone(), config);
+        assert_eq!(x.paxos.get_shipped_idx(), Some(0));
+    });
+    let proposals = utils::create_proposals(1, cfg.num_proposals);
+    sys.make_proposals(follower, proposals.clone(), cfg.wait_timeout);
+    wait_until(cfg.wait_timeout, "The decided log was not shipped", || {
+        follower_node.on_definition(|x| x.paxos.get_shipped_idx())
+            == Some(cfg.num_proposals as usize)
+    });
+
+    let ranges = hook.ranges.lock().unwrap().clone();
+    let mut next_idx = 0;
+    let mut shipped = vec![];
+    for (from_idx, mut entries) in ranges {
+        assert_eq!(from_idx, next_idx);
+        assert!(entries.len() <= 2);
+        next_idx += entries.len();
+        shipped.append(&mut entries);
+    }
+    assert_eq!(shipped, proposals);
+    follower_node.on_definition(|x| {
+        assert!(x.paxos.remove_decide_hook().is_some());
+        assert_eq!(x.paxos.get_shipped_idx(), None);
+    });
+
+    let kompact_system =
+        std::mem::take(&mut sys.kompact_system).expect("No KompactSystem in memory");
+    match kompact_system.shutdown() {
+        Ok(_) => {}
+        Err(e) => panic!("Error on kompact shutdown: {}", e),
+    };
+}
+
+/// Verifies that a busy hook holds back shipping without losing entries, and that shipping
+/// starts at the configured index.
+#[test]
+#[serial]
+fn busy_hook_test() {
+    let cfg = TestConfig::load("decide_hook_test").expect("Test config loaded");
+    let mut sys = TestSystem::with(cfg);
+    sys.start_all_nodes();
+
+    let leader = sys.get_elected_leader(1, cfg.wait_timeout);
+    let num_proposals = cfg.num_proposals as usize;
+    let proposals = utils::create_proposals(1, cfg.num_proposals + 1);
+    sys.make_proposals(
+        leader,
+        proposals[..num_proposals].to_vec(),
+        cfg.wait_timeout,
+    );
+
+    let hook = RecordingHook {
+        capacity: usize::MAX,
+        ..Default::default()
+    };
+    *hook.busy.lock().unwrap() = true;
+    let config = DecideHookConfig {
+        from_idx: 2,
+        ..Default::default()
+    };
+    let leader_node = sys.nodes.get(&leader).unwrap();
+    leader_node.on_definition(|x| x.paxos.set_decide_hook(hook.clone(), config));
+    sys.make_proposals(
+        leader,
+        proposals[num_proposals..].to_vec(),
+        cfg.wait_timeout,
+    );
+    assert_eq!(
+        leader_node.on_definition(|x| x.paxos.get_shipped_idx()),
+        Some(2)
+    );
+    assert!(hook.ranges.lock().unwrap().is_empty());
+
+    *hook.busy.lock().unwrap() = false;
+    wait_until(cfg.wait_timeout, "The decided log was not shipped", || {
+        leader_node.on_definition(|x| x.paxos.get_shipped_idx()) == Some(proposals.len())
+    });
+    let shipped: Vec<_> = hook
+        .ranges
+        .lock()
+        .unwrap()
+        .iter()
+        .flat_map(|(_, entries)| entries.clone())
+        .collect();
+    assert_eq!(shipped, proposals[2..]);
+    assert_eq!(hook.ranges.lock().unwrap()[0].0, 2);
+
+    let kompact_system =
+        std::mem::take(&mut sys.kompact_system).expect("No KompactSystem in memory");
+    match kompact_system.shutdown() {
+        Ok(_) => {}
+        Err(e) => panic!("Error on kompact shutdown: {}", e),
+    };
+}
+
+/// Handles `prepare` from `candidate` at the server `pid` of `sys` and returns the Promise it
+/// replies with, if any.
+fn promise_for(
+    sys: &TestSystem,
+    pid: NodeId,
+    candidate: NodeId,
+    prepare: Prepare,
+) -> Option<Promise<Value>> {
+    sys.nodes.get(&pid).unwrap().on_definition(|x| {
+        x.paxos
+            .handle_incoming(Message::SequencePaxos(PaxosMessage {
+                from: candidate,
+                to: pid,
+                namespace: None,
+                msg: PaxosMsg::Prepare(prepare),
+            }))
+            .expect("Failed to handle message");
+        x.paxos
+            .outgoing_messages()
+            .into_iter()
+            .find_map(|msg| match msg {
+                Message::SequencePaxos(PaxosMessage {
+                    to,
+                    msg: PaxosMsg::Promise(promise),
+                    ..
+                }) if to == candidate => Some(promise),
+                _ => None,
+            })
+    })
+}
+
+/// The entries that `log_sync` synchronizes, including the snapshotted ones.
+fn synced_entries(log_sync: &LogSync<Value>) -> Vec<Value> {
+    let mut entries = match &log_sync.decided_snapshot {
+        Some(SnapshotType::Complete(s)) => s.snapshotted.clone(),
+        None => vec![],
+        s => panic!("Unexpected snapshot: {:?}", s),
+    };
+    entries.extend(log_sync.suffix.iter().cloned());
+    entries
+}
+
+/// Verifies that a follower answers a Prepare that the leader resent, e.g., because the
+/// Promise was lost, with the same Promise, including the log sync built for the first one.
+#[test]
+#[serial]
+fn duplicate_prepare_test() {
+    let cfg = TestConfig::load("promise_cache_test").expect("Test config loaded");
+    let mut sys = TestSystem::with(cfg);
+    sys.start_all_nodes();
+
+    let leader = sys.get_elected_leader(1, cfg.wait_timeout);
+    let mut others = (1..=cfg.num_nodes as NodeId).filter(|pid| *pid != leader);
+    let (follower, candidate) = (others.next().unwrap(), others.next().unwrap());
+    let proposals = utils::create_proposals(1, cfg.num_proposals);
+    sys.make_proposals(leader, proposals.clone(), cfg.wait_timeout);
+    wait_until(cfg.wait_timeout, "The proposals were not decided", || {
+        sys.nodes
+            .get(&follower)
+            .unwrap()
+            .on_definition(|x| x.paxos.get_decided_idx())
+            == proposals.len()
+    });
+
+    let promised = sys
+        .nodes
+        .get(&follower)
+        .unwrap()
+        .on_definition(|x| x.paxos.get_promise());
+    let prepare = Prepare {
+        n: Ballot {
+            n: promised.n + 1,
+            pid: candidate,
+            ..promised
+        },
+        decided_idx: 0,
+        n_accepted: Ballot::default(),
+        accepted_idx: 0,
+    };
+    let first =
+        promise_for(&sys, follower, candidate, prepare).expect("No Promise for the Prepare");
+    let synced = synced_entries(first.log_sync.as_ref().expect("No log sync"));
+    assert_eq!(synced, proposals);
+
+    let resent =
+        promise_for(&sys, follower, candidate, prepare).expect("No Promise for the resent Prepare");
+    assert_eq!(resent.n, first.n);
+    assert_eq!(resent.n_accepted, first.n_accepted);
+    assert_eq!(resent.decided_idx, first.decided_idx);
+    assert_eq!(resent.accepted_idx, first.accepted_idx);
+    assert_eq!(resent.log_sync.as_ref().map(synced_entries), Some(synced));
+
+    // a Prepare of an older ballot is still ignored
+    let outdated = Prepare {
+        n: promised,
+        ..prepare
+    };
+    assert!(promise_for(&sys, follower, candidate, outdated).is_none());
+
+    let kompact_system =
+        std::mem::take(&mut sys.kompact_system).expect("No KompactSystem in memory");
+    match kompact_system.shutdown() {
+        Ok(_) => {}
+        Err(e) => panic!("Error on kompact shutdown: {}", e),
+    };
+}
@@ -169,7 +169,9 @@ mod docs_integration_test {
 
         // handle incoming message from network layer
         let msg: Message<KeyValue> = in_msg; // message to this node e.g. `msg.get_receiver() == 2`
-        omni_paxos.handle_incoming(msg);
+        omni_paxos
+            .handle_incoming(msg)
+            .expect("Failed to handle message");
         // END_CODE_EXAMPLE
     }
 
@@ -217,7 +219,7 @@ mod docs_integration_test {
 
         // CODE_EXAMPLE
         // Call this periodically
-        omni_paxos.tick();
+        omni_paxos.tick().expect("Failed to tick");
         // END_CODE_EXAMPLE
     }
 
@@ -357,6 +359,7 @@ mod docs_integration_test {
             configuration_id: 1,
             nodes: vec![1, 2, 3, 4, 5, 6, 7],
             flexible_quorum: Some(flex_quorum),
+            ..Default::default()
         };
         let server_config = ServerConfig {
             pid: 1,
@@ -0,0 +1,281 @@
+pub mod utils;
+
+use omnipaxos::{
+    ballot_leader_election::Ballot,
+    messages::{sequence_paxos::PaxosMsg, Message},
+    storage::{EntriesHint, Entry, LogStorage, StateStorage, StopSign, StorageOp, StorageResult},
+    util::NodeId,
+    OmniPaxos,
+};
+use omnipaxos_storage::memory_storage::MemoryStorage;
+use serial_test::serial;
+use std::{
+    collections::{HashMap, HashSet},
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        Arc,
+    },
+};
+use utils::{tick_until_filtered, verification::verify_log, TestConfig, Value, ValueSnapshot};
+
+/// Shorter than the `sync_batch_size` of the test config.
+const HINT_LEN: usize = 2;
+
+/// A memory storage that returns at most `HINT_LEN` entries per hinted read and counts them.
+struct HintedStorage {
+    storage: MemoryStorage<Value>,
+    hinted_reads: Arc<AtomicUsize>,
+}
+
+impl LogStorage<Value> for HintedStorage {
+    fn write_atomically(&mut self, ops: Vec<StorageOp<Value>>) -> StorageResult<()> {
+        self.storage.write_atomically(ops)
+    }
+
+    fn append_entry(&mut self, entry: Value) -> StorageResult<()> {
+        self.storage.append_entry(entry)
+    }
+
+    fn append_entries(&mut self, entries: Vec<Value>) -> StorageResult<()> {
+        self.storage.append_entries(entries)
+    }
+
+    fn append_on_prefix(&mut self, from_idx: usize, entries: Vec<Value>) -> StorageResult<()> {
+        self.storage.append_on_prefix(from_idx, entries)
+    }
+
+    fn get_entries(&self, from: usize, to: usize) -> StorageResult<Vec<Value>> {
+        self.storage.get_entries(from, to)
+    }
+
+    fn get_entries_hint(
+        &self,
+        from: usize,
+        to: usize,
+        max_bytes: usize,
+    ) -> StorageResult<EntriesHint<Value>> {
+        self.hinted_reads.fetch_add(1, Ordering::Relaxed);
+        let hint = self
+            .storage
+            .get_entries_hint(from, to.min(from + HINT_LEN), max_bytes)?;
+        Ok(EntriesHint::with(hint.entries, from, to))
+    }
+
+    fn get_log_len(&self) -> StorageResult<usize> {
+        self.storage.get_log_len()
+    }
+
+    fn get_suffix(&self, from: usize) -> StorageResult<Vec<Value>> {
+        self.storage.get_suffix(from)
+    }
+
+    fn trim(&mut self, idx: usize) -> StorageResult<()> {
+        self.storage.trim(idx)
+    }
+}
+
+impl StateStorage<Value> for HintedStorage {
+    fn set_promise(&mut self, n_prom: Ballot) -> StorageResult<()> {
+        self.storage.set_promise(n_prom)
+    }
+
+    fn set_decided_idx(&mut self, ld: usize) -> StorageResult<()> {
+        self.storage.set_decided_idx(ld)
+    }
+
+    fn get_decided_idx(&self) -> StorageResult<usize> {
+        self.storage.get_decided_idx()
+    }
+
+    fn set_accepted_round(&mut self, na: Ballot) -> StorageResult<()> {
+        self.storage.set_accepted_round(na)
+    }
+
+    fn get_accepted_round(&self) -> StorageResult<Option<Ballot>> {
+        self.storage.get_accepted_round()
+    }
+
+    fn get_promise(&self) -> StorageResult<Option<Ballot>> {
+        self.storage.get_promise()
+    }
+
+    fn set_ble_ballot(&mut self, ballot: Ballot) -> StorageResult<()> {
+        self.storage.set_ble_ballot(ballot)
+    }
+
+    fn get_ble_ballot(&self) -> StorageResult<Option<Ballot>> {
+        self.storage.get_ble_ballot()
+    }
+
+    fn set_stopsign(&mut self, s: Option<StopSign>) -> StorageResult<()> {
+        self.storage.set_stopsign(s)
+    }
+
+    fn get_stopsign(&self) -> StorageResult<Option<StopSign>> {
+        self.storage.get_stopsign()
+    }
+
+    fn set_compacted_idx(&mut self, idx: usize) -> StorageResult<()> {
+        self.storage.set_compacted_idx(idx)
+    }
+
+    fn get_compacted_idx(&self) -> StorageResult<usize> {
+        self.storage.get_compacted_idx()
+    }
+
+    fn set_snapshot(&mut self, snapshot: Option<ValueSnapshot>) -> StorageResult<()> {
+        self.storage.set_snapshot(snapshot)
+    }
+
+    fn get_snapshot(&self) -> StorageResult<Option<ValueSnapshot>> {
+        self.storage.get_snapshot()
+    }
+}
+
+type OmniPaxosValue = OmniPaxos<Value, HintedStorage>;
+
+struct Cluster {
+    nodes: HashMap<NodeId, OmniPaxosValue>,
+    hinted_reads: HashMap<NodeId, Arc<AtomicUsize>>,
+    disconnected: HashSet<NodeId>,
+    /// The largest number of entries sent in a message that synchronizes a follower.
+    max_sync_entries: usize,
+}
+
+impl Cluster {
+    fn with(cfg: &TestConfig) -> Self {
+        let mut hinted_reads = HashMap::new();
+        let nodes = (1..=cfg.num_nodes as NodeId)
+            .map(|pid| {
+                let reads = Arc::new(AtomicUsize::new(0));
+                hinted_reads.insert(pid, reads.clone());
+                let storage = HintedStorage {
+                    storage: MemoryStorage::default(),
+                    hinted_reads: reads,
+                };
+                let op = cfg
+                    .into_omnipaxos_config(pid)
+                    .build(storage)
+                    .expect("Failed to build OmniPaxos");
+                (pid, op)
+            })
+            .collect();
+        Self {
+            nodes,
+            hinted_reads,
+            disconnected: HashSet::new(),
+            max_sync_entries: 0,
+        }
+    }
+
+    /// Like `utils::tick_until`, but drops the messages of disconnected servers and records the
+    /// largest sync it saw.
+    fn tick_until<F>(&mut self, done: F)
+    where
+        F: Fn(&HashMap<NodeId, OmniPaxosValue>) -> bool,
+    {
+        let disconnected = &self.disconnected;
+        let max_sync_entries = &mut self.max_sync_entries;
+        tick_until_filtered(
+            &mut self.nodes,
+            |msg| {
+                if disconnected.contains(&msg.get_sender())
+                    || disconnected.contains(&msg.get_receiver())
+                {
+                    return false;
+                }
+                if let Message::SequencePaxos(m) = msg {
+                    let sync_entries = match &m.msg {
+                        PaxosMsg::AcceptSync(acc_sync) => acc_sync.log_sync.suffix.len(),
+                        PaxosMsg::SyncPortion(portion) => portion.log_sync.suffix.len(),
+                        _ => 0,
+                    };
+                    *max_sync_entries = (*max_sync_entries).max(sync_entries);
+                }
+                true
+            },
+            done,
+        );
+    }
+
+    fn reconnect(&mut self, pid: NodeId) {
+        self.disconnected.remove(&pid);
+        let peers: Vec<NodeId> = self.nodes.keys().copied().filter(|p| *p != pid).collect();
+        for peer in peers {
+            self.nodes.get_mut(&peer).unwrap().reconnected(pid);
+            self.nodes.get_mut(&pid).unwrap().reconnected(peer);
+        }
+    }
+}
+
+/// Verifies that the memory storage returns the entries that fit in `max_bytes`, but at least
+/// one, together with the index to continue reading from.
+#[test]
+fn memory_storage_hint_test() {
+    let mut storage = MemoryStorage::default();
+    storage
+        .append_entries((0..10).map(Value::with_id).collect())
+        .expect("Failed to append");
+    let entry_size = Value::with_id(0).size_hint();
+    let hint = storage
+        .get_entries_hint(2, 8, 3 * entry_size)
+        .expect("Failed to read");
+    assert_eq!(
+        hint,
+        EntriesHint::with(
+            vec![Value::with_id(2), Value::with_id(3), Value::with_id(4)],
+            2,
+            8
+        )
+    );
+    assert_eq!(hint.continuation, Some(5));
+    let hint = storage.get_entries_hint(5, 8, 0).expect("Failed to read");
+    assert_eq!(hint.entries, vec![Value::with_id(5)]);
+    let hint = storage
+        .get_entries_hint(5, 8, usize::MAX)
+        .expect("Failed to read");
+    assert_eq!(
+        hint.entries,
+        vec![Value::with_id(5), Value::with_id(6), Value::with_id(7)]
+    );
+    assert_eq!(hint.continuation, None);
+}
+
+/// Verifies that a follower that missed many entries is synchronized from the portions that
+/// the storage of the leader returns for hinted reads, even if they are shorter than
+/// `sync_batch_size`.
+#[test]
+#[serial]
+fn sync_with_hinted_reads_test() {
+    let cfg = TestConfig::load("entries_hint_test").expect("Test config loaded");
+    let num_proposals = cfg.num_proposals as usize;
+    let mut cluster = Cluster::with(&cfg);
+    cluster.tick_until(|nodes| nodes.values().all(|op| op.get_current_leader().is_some()));
+    let leader = cluster.nodes[&1].get_current_leader().unwrap();
+    let follower = *cluster.nodes.keys().find(|pid| **pid != leader).unwrap();
+
+    cluster.disconnected.insert(follower);
+    let proposals = utils::create_proposals(1, cfg.num_proposals);
+    for v in proposals.clone() {
+        cluster
+            .nodes
+            .get_mut(&leader)
+            .unwrap()
+            .append(v)
+            .expect("Failed to append");
+    }
+    cluster.tick_until(|nodes| nodes[&leader].get_decided_idx() == num_proposals);
+
+    cluster.reconnect(follower);
+    cluster.tick_until(|nodes| nodes[&follower].get_decided_idx() == num_proposals);
+    assert!(cluster.hinted_reads[&leader].load(Ordering::Relaxed) > 0);
+    assert!(
+        cluster.max_sync_entries <= HINT_LEN,
+        "{}",
+        cluster.max_sync_entries
+    );
+    let log = cluster.nodes[&follower]
+        .read_decided_suffix(0)
+        .expect("Failed to read the log");
+    verify_log(log, proposals);
+}
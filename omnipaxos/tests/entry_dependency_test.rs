@@ -0,0 +1,126 @@
+pub mod utils;
+
+use omnipaxos::{
+    storage::{Entry, NoSnapshot},
+    util::{LogEntry, NodeId},
+    OmniPaxos,
+};
+use omnipaxos_storage::memory_storage::MemoryStorage;
+use serial_test::serial;
+use std::collections::HashMap;
+use utils::{elect_leader, no_unicache, tick_until, TestConfig};
+
+#[derive(Clone, Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+struct Value {
+    id: u64,
+    dependency: Option<usize>,
+}
+
+impl Entry for Value {
+    type Snapshot = NoSnapshot;
+    no_unicache!();
+
+    fn dependency(&self) -> Option<usize> {
+        self.dependency
+    }
+}
+
+type OmniPaxosValue = OmniPaxos<Value, MemoryStorage<Value>>;
+
+fn value(id: u64) -> Value {
+    Value {
+        id,
+        dependency: None,
+    }
+}
+
+fn dependent(id: u64, dependency: usize) -> Value {
+    Value {
+        id,
+        dependency: Some(dependency),
+    }
+}
+
+fn cluster(cfg: &TestConfig) -> HashMap<NodeId, OmniPaxosValue> {
+    utils::create_cluster(cfg, |_| ())
+}
+
+fn decided_ids(op: &OmniPaxosValue) -> Vec<u64> {
+    op.read_decided_suffix(0)
+        .unwrap()
+        .into_iter()
+        .map(|e| match e {
+            LogEntry::Decided(v) => v.id,
+            e => panic!("Unexpected entry: {:?}", e),
+        })
+        .collect()
+}
+
+/// Verifies that the leader holds an entry until the entry it depends on is decided, while an
+/// independent entry proposed after it is appended right away, and that the dependency can be
+/// read together with the decided entry.
+#[test]
+#[serial]
+fn dependency_not_decided_test() {
+    let cfg = TestConfig::load("entry_dependency_test").expect("Test config loaded");
+    let mut nodes = cluster(&cfg);
+    let leader = elect_leader(&mut nodes);
+    let op = nodes.get_mut(&leader).unwrap();
+    op.append(value(0)).expect("Failed to append");
+    op.append(dependent(1, 0)).expect("Failed to append");
+    op.append(value(2)).expect("Failed to append");
+    assert_eq!(op.get_outgoing_stats().dependent_proposals, 1);
+    tick_until(&mut nodes, |nodes| {
+        nodes.values().all(|op| op.get_decided_idx() == 3)
+    });
+    for op in nodes.values() {
+        assert_eq!(decided_ids(op), vec![0, 2, 1]);
+        let dependencies: Vec<_> = op
+            .read_decided_suffix_indexed(0)
+            .unwrap()
+            .iter()
+            .map(|e| e.dependency())
+            .collect();
+        assert_eq!(dependencies, vec![None, None, Some(0)]);
+    }
+    assert_eq!(nodes[&leader].get_outgoing_stats().dependent_proposals, 0);
+}
+
+/// Verifies that a forwarded entry whose dependency is already decided is appended in the
+/// order it was proposed, and that a forwarded entry that depends on a later index is held
+/// until that index is decided.
+#[test]
+#[serial]
+fn forwarded_dependency_test() {
+    let cfg = TestConfig::load("entry_dependency_test").expect("Test config loaded");
+    let mut nodes = cluster(&cfg);
+    let leader = elect_leader(&mut nodes);
+    nodes
+        .get_mut(&leader)
+        .unwrap()
+        .append(value(0))
+        .expect("Failed to append");
+    tick_until(&mut nodes, |nodes| {
+        nodes.values().all(|op| op.get_decided_idx() == 1)
+    });
+    let follower = *nodes.keys().find(|pid| **pid != leader).unwrap();
+    let op = nodes.get_mut(&follower).unwrap();
+    op.append(dependent(1, 2)).expect("Failed to append");
+    op.append(dependent(2, 0)).expect("Failed to append");
+    tick_until(&mut nodes, |nodes| {
+        nodes[&leader].get_outgoing_stats().dependent_proposals == 1
+            && nodes.values().all(|op| op.get_decided_idx() == 2)
+    });
+    nodes
+        .get_mut(&leader)
+        .unwrap()
+        .append(value(3))
+        .expect("Failed to append");
+    tick_until(&mut nodes, |nodes| {
+        nodes.values().all(|op| op.get_decided_idx() == 4)
+    });
+    for op in nodes.values() {
+        assert_eq!(decided_ids(op), vec![0, 2, 3, 1]);
+    }
+}
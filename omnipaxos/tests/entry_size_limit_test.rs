@@ -0,0 +1,111 @@
+pub mod utils;
+
+use omnipaxos::{
+    storage::{Entry, NoSnapshot},
+    util::{LogEntry, NodeId},
+    OmniPaxos, ProposalRejectionCause, ProposeErr,
+};
+use omnipaxos_storage::memory_storage::MemoryStorage;
+use serial_test::serial;
+use std::collections::HashMap;
+use utils::{elect_leader, no_unicache, tick_until, tick_until_filtered, TestConfig};
+
+const UNLIMITED: NodeId = 3;
+const MAX_ENTRY_SIZE: usize = 16;
+
+#[derive(Clone, Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+struct Value(String);
+
+impl Entry for Value {
+    type Snapshot = NoSnapshot;
+    no_unicache!();
+
+    fn size_hint(&self) -> usize {
+        self.0.len()
+    }
+}
+
+type OmniPaxosValue = OmniPaxos<Value, MemoryStorage<Value>>;
+
+/// Builds a cluster in which all servers but `UNLIMITED` have a `max_entry_size`.
+fn cluster(cfg: &TestConfig) -> HashMap<NodeId, OmniPaxosValue> {
+    utils::create_cluster(cfg, |server_cfg| {
+        server_cfg.max_entry_size = (server_cfg.pid != UNLIMITED).then_some(MAX_ENTRY_SIZE)
+    })
+}
+
+/// Elects a leader with a `max_entry_size` and returns it.
+fn elect_limited_leader(nodes: &mut HashMap<NodeId, OmniPaxosValue>) -> NodeId {
+    tick_until_filtered(
+        nodes,
+        |msg| msg.get_sender() != UNLIMITED && msg.get_receiver() != UNLIMITED,
+        |nodes| nodes.values().any(|op| op.get_current_leader().is_some()),
+    );
+    elect_leader(nodes)
+}
+
+fn value(size: usize) -> Value {
+    Value("x".repeat(size))
+}
+
+/// Verifies that an entry larger than the `max_entry_size` is refused by `append()` with its
+/// size and the limit, and that an entry of exactly the limit is decided.
+#[test]
+#[serial]
+fn append_too_large_test() {
+    let cfg = TestConfig::load("entry_size_limit_test").expect("Test config loaded");
+    let mut nodes = cluster(&cfg);
+    let leader = elect_limited_leader(&mut nodes);
+    let op = nodes.get_mut(&leader).unwrap();
+    match op.append(value(MAX_ENTRY_SIZE + 1)) {
+        Err(ProposeErr::EntryTooLarge { entry, size, limit }) => {
+            assert_eq!(entry, value(MAX_ENTRY_SIZE + 1));
+            assert_eq!(size, MAX_ENTRY_SIZE + 1);
+            assert_eq!(limit, MAX_ENTRY_SIZE);
+        }
+        res => panic!("Unexpected result: {:?}", res),
+    }
+    op.append(value(MAX_ENTRY_SIZE)).expect("Failed to append");
+    tick_until(&mut nodes, |nodes| {
+        nodes.values().all(|op| op.get_decided_idx() == 1)
+    });
+    assert_eq!(
+        nodes[&leader].read(0),
+        Some(LogEntry::Decided(value(MAX_ENTRY_SIZE)))
+    );
+}
+
+/// Verifies that the leader drops the forwarded entries that are larger than its
+/// `max_entry_size` and returns them to the forwarding server, while the other entries of the
+/// same forward are decided in order.
+#[test]
+#[serial]
+fn forwarded_too_large_test() {
+    let cfg = TestConfig::load("entry_size_limit_test").expect("Test config loaded");
+    let mut nodes = cluster(&cfg);
+    let leader = elect_limited_leader(&mut nodes);
+    let op = nodes.get_mut(&UNLIMITED).unwrap();
+    op.append(value(1)).expect("Failed to append");
+    op.append_with_token(value(MAX_ENTRY_SIZE * 2), 7)
+        .expect("Failed to append");
+    op.append(value(2)).expect("Failed to append");
+    tick_until(&mut nodes, |nodes| {
+        nodes.values().all(|op| op.get_decided_idx() == 2)
+    });
+    let decided: Vec<_> = nodes[&leader]
+        .read_decided_suffix(0)
+        .unwrap()
+        .into_iter()
+        .map(|e| match e {
+            LogEntry::Decided(v) => v,
+            e => panic!("Unexpected entry: {:?}", e),
+        })
+        .collect();
+    assert_eq!(decided, vec![value(1), value(2)]);
+    let rejected = nodes.get_mut(&UNLIMITED).unwrap().take_rejected_proposals();
+    assert_eq!(rejected.len(), 1);
+    assert_eq!(rejected[0].entry, value(MAX_ENTRY_SIZE * 2));
+    assert_eq!(rejected[0].token, Some(7));
+    assert_eq!(rejected[0].cause, ProposalRejectionCause::EntryTooLarge);
+}
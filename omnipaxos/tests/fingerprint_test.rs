@@ -0,0 +1,127 @@
+pub mod utils;
+
+use omnipaxos::{
+    messages::{sequence_paxos::PaxosMsg, Message},
+    storage::{Entry, NoSnapshot},
+    util::NodeId,
+    OmniPaxos,
+};
+use omnipaxos_storage::memory_storage::MemoryStorage;
+use serial_test::serial;
+use std::{cell::Cell, collections::HashMap};
+use utils::{elect_leader, no_unicache, tick_until, tick_until_filtered, TestConfig};
+
+const FINGERPRINT_TIMEOUT: u64 = 5;
+
+#[derive(Clone, Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+struct Value(u64);
+
+impl Entry for Value {
+    type Snapshot = NoSnapshot;
+    no_unicache!();
+
+    fn fingerprint(&self) -> Option<u64> {
+        Some(self.0)
+    }
+}
+
+type OmniPaxosValue = OmniPaxos<Value, MemoryStorage<Value>>;
+
+fn cluster(cfg: &TestConfig) -> HashMap<NodeId, OmniPaxosValue> {
+    utils::create_cluster(cfg, |server_cfg| {
+        server_cfg.fingerprint_tick_timeout = Some(FINGERPRINT_TIMEOUT)
+    })
+}
+
+/// Changes the entries of `msg` if they are replicated to `corrupted`.
+fn corrupt(msg: &mut Message<Value>, corrupted: Option<NodeId>) {
+    if let Message::SequencePaxos(m) = msg {
+        if let PaxosMsg::AcceptDecide(acc) = &mut m.msg {
+            if Some(m.to) == corrupted {
+                acc.entries.iter_mut().for_each(|v| v.0 += 1000);
+            }
+        }
+    }
+}
+
+/// Ticks all `nodes` `ticks` times and passes their messages, including all replies. The
+/// entries replicated to `corrupted` are changed on the way.
+fn tick_corrupting(
+    nodes: &mut HashMap<NodeId, OmniPaxosValue>,
+    corrupted: Option<NodeId>,
+    ticks: u64,
+) {
+    let ticked = Cell::new(0);
+    tick_until_filtered(
+        nodes,
+        |msg| {
+            corrupt(msg, corrupted);
+            true
+        },
+        |_| {
+            ticked.set(ticked.get() + 1);
+            ticked.get() > ticks
+        },
+    );
+}
+
+fn propose(
+    cfg: &TestConfig,
+    nodes: &mut HashMap<NodeId, OmniPaxosValue>,
+    leader: NodeId,
+    from: u64,
+) {
+    let op = nodes.get_mut(&leader).unwrap();
+    for v in from..from + cfg.num_proposals {
+        op.append(Value(v)).expect("Failed to append");
+    }
+}
+
+/// Verifies that followers with the same decided log as the leader raise no alarm, and that a
+/// follower whose decided entries differ raises alarms for exactly the diverged entries.
+#[test]
+#[serial]
+fn divergence_alarm_test() {
+    let cfg = TestConfig::load("fingerprint_test").expect("Test config loaded");
+    let num_proposals = cfg.num_proposals as usize;
+    let mut nodes = cluster(&cfg);
+    let leader = elect_leader(&mut nodes);
+    let corrupted = *nodes.keys().find(|pid| **pid != leader).unwrap();
+    propose(&cfg, &mut nodes, leader, 0);
+    tick_until(&mut nodes, |nodes| {
+        nodes
+            .values()
+            .all(|op| op.get_decided_idx() == num_proposals)
+    });
+    // Let the leader send the fingerprints of all decided entries
+    tick_corrupting(&mut nodes, None, 2 * FINGERPRINT_TIMEOUT);
+    for op in nodes.values_mut() {
+        assert!(op.take_divergence_alarms().is_empty());
+    }
+
+    propose(&cfg, &mut nodes, leader, cfg.num_proposals);
+    let decided_idx = 2 * num_proposals;
+    tick_until_filtered(
+        &mut nodes,
+        |msg| {
+            corrupt(msg, Some(corrupted));
+            true
+        },
+        |nodes| nodes.values().all(|op| op.get_decided_idx() == decided_idx),
+    );
+    tick_corrupting(&mut nodes, Some(corrupted), 2 * FINGERPRINT_TIMEOUT);
+    for (pid, op) in nodes.iter_mut() {
+        if *pid != corrupted {
+            assert!(op.take_divergence_alarms().is_empty());
+        }
+    }
+    let alarms = nodes.get_mut(&corrupted).unwrap().take_divergence_alarms();
+    assert!(!alarms.is_empty());
+    for alarm in &alarms {
+        assert_eq!(alarm.leader, leader);
+        assert!(alarm.from_idx >= num_proposals && alarm.to_idx <= decided_idx);
+        assert_ne!(alarm.leader_fingerprint, alarm.local_fingerprint);
+    }
+    assert_eq!(alarms.last().unwrap().to_idx, decided_idx);
+}
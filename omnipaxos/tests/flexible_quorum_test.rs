@@ -87,3 +87,56 @@ fn flexible_quorum_accept_phase_test() {
     let leaders_log = leader.on_definition(|x| x.read_decided_log());
     verify_log(leaders_log, expected_log);
 }
+
+/// Verifies that with hierarchical quorums, a majority of nodes in a majority of regions can
+/// elect a leader and decide entries even though they are not a majority of all nodes, e.g., if
+/// a full region has failed.
+#[test]
+#[serial]
+fn hierarchical_quorum_decides_test() {
+    let cfg = TestConfig::load("hierarchical_quorum_test").expect("Test config couldn't be loaded");
+    let mut sys = TestSystem::with(cfg);
+    sys.start_all_nodes();
+
+    // Regions are [1, 2, 3], [4, 5, 6] and [7, 8, 9]
+    for node_id in [3, 6, 7, 8, 9] {
+        sys.kill_node(node_id);
+    }
+    let proposals: Vec<Value> = (0..cfg.num_proposals).map(Value::with_id).collect();
+    let leader_id = sys.get_elected_leader(1, cfg.wait_timeout);
+    sys.make_proposals(leader_id, proposals.clone(), cfg.wait_timeout);
+
+    let leader = sys.nodes.get(&leader_id).unwrap();
+    let leaders_log = leader.on_definition(|x| x.read_decided_log());
+    verify_log(leaders_log, proposals);
+}
+
+/// Verifies that with hierarchical quorums, a majority of all nodes can't decide entries if it
+/// only contains a majority of the nodes in a minority of the regions.
+#[test]
+#[serial]
+fn hierarchical_quorum_majority_not_enough_test() {
+    let cfg = TestConfig::load("hierarchical_quorum_test").expect("Test config couldn't be loaded");
+    let mut sys = TestSystem::with(cfg);
+    sys.start_all_nodes();
+
+    // Regions are [1, 2, 3], [4, 5, 6] and [7, 8, 9]
+    for node_id in [5, 6, 8, 9] {
+        sys.kill_node(node_id);
+    }
+    let node = sys.nodes.get(&1).unwrap();
+    node.on_definition(|x| {
+        for v in (0..cfg.num_proposals).map(Value::with_id) {
+            x.paxos.append(v).expect("Failed to append");
+        }
+    });
+    thread::sleep(cfg.wait_timeout);
+
+    for node in sys.nodes.values() {
+        let decided_idx = node.on_definition(|x| x.paxos.get_decided_idx());
+        assert_eq!(
+            decided_idx, 0,
+            "Entries were decided without a hierarchical quorum"
+        );
+    }
+}
@@ -0,0 +1,149 @@
+pub mod utils;
+
+use omnipaxos::{
+    messages::{sequence_paxos::PaxosMsg, Message},
+    storage::{Entry, NoSnapshot},
+    util::{LogEntry, NodeId},
+    OmniPaxos,
+};
+use omnipaxos_storage::memory_storage::MemoryStorage;
+use serial_test::serial;
+use std::collections::HashMap;
+use utils::{deliver, elect_leader, no_unicache, tick_until_filtered, TestConfig};
+
+const FORWARD_BATCH_TIMEOUT: u64 = 3;
+const VALUE_SIZE: usize = 30;
+
+#[derive(Clone, Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+struct Value(u64);
+
+impl Entry for Value {
+    type Snapshot = NoSnapshot;
+    no_unicache!();
+
+    fn size_hint(&self) -> usize {
+        VALUE_SIZE
+    }
+}
+
+type OmniPaxosValue = OmniPaxos<Value, MemoryStorage<Value>>;
+
+fn cluster(
+    cfg: &TestConfig,
+    forward_batch_size: usize,
+    forward_batch_byte_size: usize,
+) -> HashMap<NodeId, OmniPaxosValue> {
+    utils::create_cluster(cfg, |server_cfg| {
+        server_cfg.forward_batch_size = forward_batch_size;
+        server_cfg.forward_batch_byte_size = forward_batch_byte_size;
+        server_cfg.forward_batch_tick_timeout = FORWARD_BATCH_TIMEOUT;
+    })
+}
+
+/// Elects a leader and returns one of its followers.
+fn elect_follower(nodes: &mut HashMap<NodeId, OmniPaxosValue>) -> NodeId {
+    let leader = elect_leader(nodes);
+    *nodes.keys().find(|pid| **pid != leader).unwrap()
+}
+
+/// Records the number of proposals in `msg` if it is a forward from `follower`.
+fn record_forward(msg: &Message<Value>, follower: NodeId, forwards: &mut Vec<usize>) {
+    if let Message::SequencePaxos(m) = msg {
+        if m.from == follower {
+            match &m.msg {
+                PaxosMsg::ProposalForward(entries) => forwards.push(entries.len()),
+                PaxosMsg::TokenProposalForward(tpf) => forwards.push(tpf.entries.len()),
+                _ => {}
+            }
+        }
+    }
+}
+
+/// Delivers the outgoing messages of all servers, including all replies, without ticking and
+/// returns the number of proposals in each forward from `follower` meanwhile.
+fn deliver_all(nodes: &mut HashMap<NodeId, OmniPaxosValue>, follower: NodeId) -> Vec<usize> {
+    let mut forwards = vec![];
+    let pids: Vec<NodeId> = nodes.keys().copied().collect();
+    deliver(nodes, &pids, |msg| {
+        record_forward(msg, follower, &mut forwards);
+        true
+    });
+    forwards
+}
+
+/// Ticks until all servers decided `num_proposals` and returns the number of proposals in each
+/// forward from `follower` meanwhile.
+fn tick_until_decided(
+    nodes: &mut HashMap<NodeId, OmniPaxosValue>,
+    follower: NodeId,
+    num_proposals: u64,
+) -> Vec<usize> {
+    let mut forwards = vec![];
+    tick_until_filtered(
+        nodes,
+        |msg| {
+            record_forward(msg, follower, &mut forwards);
+            true
+        },
+        |nodes| {
+            nodes
+                .values()
+                .all(|op| op.get_decided_idx() == num_proposals as usize)
+        },
+    );
+    forwards
+}
+
+fn assert_decided_in_order(nodes: &HashMap<NodeId, OmniPaxosValue>, num_proposals: u64) {
+    for op in nodes.values() {
+        let decided: Vec<_> = op
+            .read_decided_suffix(0)
+            .unwrap()
+            .into_iter()
+            .map(|e| match e {
+                LogEntry::Decided(v) => v,
+                e => panic!("Unexpected entry: {:?}", e),
+            })
+            .collect();
+        assert_eq!(decided, (0..num_proposals).map(Value).collect::<Vec<_>>());
+    }
+}
+
+/// Verifies that a follower forwards its proposals in batches of `forward_batch_size` as soon
+/// as a batch is full, and the remaining proposals once the `forward_batch_tick_timeout`
+/// expires.
+#[test]
+#[serial]
+fn forward_batch_size_test() {
+    let cfg = TestConfig::load("forward_batching_test").expect("Test config loaded");
+    let num_proposals = 12;
+    let mut nodes = cluster(&cfg, 5, 0);
+    let follower = elect_follower(&mut nodes);
+    let op = nodes.get_mut(&follower).unwrap();
+    for v in 0..num_proposals {
+        op.append(Value(v)).expect("Failed to append");
+    }
+    assert_eq!(deliver_all(&mut nodes, follower), vec![5, 5]);
+    let forwards = tick_until_decided(&mut nodes, follower, num_proposals);
+    assert_eq!(forwards, vec![2]);
+    assert_decided_in_order(&nodes, num_proposals);
+}
+
+/// Verifies that a forward batch is sent before it grows beyond the `forward_batch_byte_size`.
+#[test]
+#[serial]
+fn forward_batch_byte_size_test() {
+    let cfg = TestConfig::load("forward_batching_test").expect("Test config loaded");
+    let num_proposals = 10;
+    let mut nodes = cluster(&cfg, 100, 3 * VALUE_SIZE + 1);
+    let follower = elect_follower(&mut nodes);
+    let op = nodes.get_mut(&follower).unwrap();
+    for v in 0..num_proposals {
+        op.append(Value(v)).expect("Failed to append");
+    }
+    assert_eq!(deliver_all(&mut nodes, follower), vec![3, 3, 3]);
+    let forwards = tick_until_decided(&mut nodes, follower, num_proposals);
+    assert_eq!(forwards, vec![1]);
+    assert_decided_in_order(&nodes, num_proposals);
+}
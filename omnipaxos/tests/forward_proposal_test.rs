@@ -1,10 +1,14 @@
 pub mod utils;
 
 use kompact::prelude::{promise, Ask};
-use omnipaxos::{ballot_leader_election::Ballot, util::NodeId};
+use omnipaxos::{
+    ballot_leader_election::Ballot,
+    util::{LogEntry, NodeId},
+    FollowerAppendPolicy, ProposalAffinity, ProposeErr,
+};
 use rand::Rng;
 use serial_test::serial;
-use utils::{TestConfig, TestSystem, Value};
+use utils::{wait_until, TestConfig, TestSystem, Value};
 
 /// Verifies if the follower nodes forwards the proposal message to a leader
 /// so it can get decided.
@@ -55,3 +59,291 @@ fn forward_proposal_test() {
         Err(e) => panic!("Error on kompact shutdown: {}", e),
     };
 }
+
+const PROPOSALS_PER_NODE: u64 = 5;
+
+/// Appends entries with tokens on every server, so that they are forwarded, buffered, or
+/// appended by the leader, and verifies that each server gets back exactly the tokens of its own
+/// entries together with the index the entry was decided at.
+#[test]
+#[serial]
+fn origin_token_test() {
+    let cfg = TestConfig::load("proposal_test").expect("Test config loaded");
+    let mut sys = TestSystem::with(cfg);
+    // the token of an entry is its id, which is unique across the cluster
+    let id = |pid: NodeId, i: u64| pid * 100 + i;
+    let propose = |sys: &TestSystem, range: std::ops::Range<u64>| {
+        for (pid, node) in &sys.nodes {
+            node.on_definition(|x| {
+                for i in range.clone() {
+                    x.paxos
+                        .append_with_token(Value::with_id(id(*pid, i)), id(*pid, i))
+                        .expect("Failed to append");
+                }
+                // entries without a token don't have one returned
+                x.paxos.append(Value::with_id(0)).expect("Failed to append");
+            });
+        }
+    };
+    // buffered until a leader is elected
+    propose(&sys, 0..PROPOSALS_PER_NODE);
+    sys.start_all_nodes();
+    sys.get_elected_leader(1, cfg.wait_timeout);
+    // forwarded to or appended by the elected leader
+    propose(&sys, PROPOSALS_PER_NODE..2 * PROPOSALS_PER_NODE);
+    let num_entries = cfg.num_nodes * (2 * PROPOSALS_PER_NODE as usize + 2);
+    wait_until(cfg.wait_timeout, "The entries were not decided", || {
+        sys.nodes
+            .values()
+            .all(|node| node.on_definition(|x| x.paxos.get_decided_idx()) == num_entries)
+    });
+
+    for (pid, node) in &sys.nodes {
+        node.on_definition(|x| {
+            let tokens = x.paxos.take_decided_tokens();
+            let mut returned: Vec<u64> = tokens.iter().map(|(_, token)| *token).collect();
+            returned.sort();
+            let expected: Vec<u64> = (0..2 * PROPOSALS_PER_NODE).map(|i| id(*pid, i)).collect();
+            assert_eq!(returned, expected, "Server {} got wrong tokens", pid);
+            for (idx, token) in tokens {
+                match x.paxos.read(idx) {
+                    Some(LogEntry::Decided(v)) => assert_eq!(v, Value::with_id(token)),
+                    e => panic!("Entry {} of server {} is not decided: {:?}", idx, pid, e),
+                }
+            }
+            assert!(x.paxos.take_decided_tokens().is_empty());
+        });
+    }
+
+    let kompact_system =
+        std::mem::take(&mut sys.kompact_system).expect("No KompactSystem in memory");
+    match kompact_system.shutdown() {
+        Ok(_) => {}
+        Err(e) => panic!("Error on kompact shutdown: {}", e),
+    };
+}
+
+/// Verifies that the leader counts the proposals forwarded by a follower and receives the
+/// latency the follower observed for them, next to the proposals appended at the leader.
+#[test]
+#[serial]
+fn proposal_affinity_test() {
+    let cfg = TestConfig::load("proposal_test").expect("Test config loaded");
+    let mut sys = TestSystem::with(cfg);
+    sys.start_all_nodes();
+    let leader = sys.get_elected_leader(1, cfg.wait_timeout);
+    let follower = (1..=cfg.num_nodes as NodeId)
+        .find(|pid| *pid != leader)
+        .unwrap();
+
+    sys.make_proposals(leader, vec![Value::with_id(0)], cfg.wait_timeout);
+    // Each proposal is decided before the next one is forwarded
+    for id in 1..=PROPOSALS_PER_NODE {
+        sys.make_proposals(follower, vec![Value::with_id(id)], cfg.wait_timeout);
+    }
+
+    // The follower observed a latency for each forward and reported it with the later ones
+    let follower_node = sys.nodes.get(&follower).unwrap();
+    let own = follower_node.on_definition(|x| x.paxos.get_proposal_affinity()[&follower]);
+    assert_eq!(own.proposals, PROPOSALS_PER_NODE);
+    assert_eq!(own.latency.unwrap().samples, PROPOSALS_PER_NODE as usize);
+
+    sys.nodes.get(&leader).unwrap().on_definition(|x| {
+        let affinity = x.paxos.get_proposal_affinity();
+        assert_eq!(affinity.len(), 2);
+        let forwarded = affinity[&follower];
+        assert_eq!(forwarded.proposals, PROPOSALS_PER_NODE);
+        assert_eq!(
+            forwarded.latency.unwrap().samples,
+            PROPOSALS_PER_NODE as usize - 1
+        );
+        assert_eq!(
+            affinity[&leader],
+            ProposalAffinity {
+                proposals: 1,
+                latency: x.paxos.get_commit_latency(),
+            }
+        );
+        assert_eq!(x.paxos.get_ui_states().proposal_affinity, affinity);
+    });
+
+    // The servers that did not propose anything have not received any proposals
+    for (pid, node) in &sys.nodes {
+        if *pid != leader && *pid != follower {
+            assert!(node.on_definition(|x| x.paxos.get_proposal_affinity().is_empty()));
+        }
+    }
+
+    let kompact_system =
+        std::mem::take(&mut sys.kompact_system).expect("No KompactSystem in memory");
+    match kompact_system.shutdown() {
+        Ok(_) => {}
+        Err(e) => panic!("Error on kompact shutdown: {}", e),
+    };
+}
+
+const FOLLOWER_APPEND_TICK_TIMEOUT: u64 = 100;
+
+/// Returns a server of `sys` other than `leader` once it knows the leader.
+fn follower_of(sys: &TestSystem, leader: NodeId, cfg: &TestConfig) -> NodeId {
+    let follower = (1..=cfg.num_nodes as NodeId)
+        .find(|pid| *pid != leader)
+        .unwrap();
+    let follower_px = sys.nodes.get(&follower).unwrap();
+    wait_until(
+        cfg.wait_timeout,
+        "The follower did not learn the leader",
+        || follower_px.on_definition(|x| x.paxos.get_current_leader()) == Some(leader),
+    );
+    follower
+}
+
+/// Verifies that a follower with the `Reject` policy refuses appends and names the leader,
+/// while the leader accepts them.
+#[test]
+#[serial]
+fn follower_append_reject_test() {
+    let cfg = TestConfig {
+        follower_append_policy: FollowerAppendPolicy::Reject,
+        ..TestConfig::load("follower_append_test").expect("Test config loaded")
+    };
+    let mut sys = TestSystem::with(cfg);
+    sys.start_all_nodes();
+    let leader = sys.get_elected_leader(1, cfg.wait_timeout);
+    let follower = follower_of(&sys, leader, &cfg);
+
+    sys.nodes.get(&follower).unwrap().on_definition(|x| {
+        let v = Value::with_id(1);
+        match x.paxos.append(v.clone()) {
+            Err(ProposeErr::NotLeader(refused, l)) => {
+                assert_eq!(refused, v);
+                assert_eq!(l, Some(leader));
+            }
+            res => panic!("Append was not refused: {:?}", res),
+        }
+        let v = Value::with_id(2);
+        match x.paxos.append_with_token(v.clone(), 2) {
+            Err(ProposeErr::NotLeader(refused, l)) => {
+                assert_eq!(refused, v);
+                assert_eq!(l, Some(leader));
+            }
+            res => panic!("Append was not refused: {:?}", res),
+        }
+    });
+    sys.make_proposals(leader, vec![Value::with_id(3)], cfg.wait_timeout);
+
+    let kompact_system =
+        std::mem::take(&mut sys.kompact_system).expect("No KompactSystem found in memory");
+    match kompact_system.shutdown() {
+        Ok(_) => {}
+        Err(e) => panic!("Error on kompact shutdown: {}", e),
+    };
+}
+
+/// Verifies that a follower with the `Buffer` policy drops the entries that were buffered for
+/// longer than the timeout without a known leader, and forwards the others once a leader is
+/// elected.
+#[test]
+#[serial]
+fn follower_append_buffer_timeout_test() {
+    let cfg = TestConfig {
+        follower_append_policy: FollowerAppendPolicy::Buffer {
+            tick_timeout: FOLLOWER_APPEND_TICK_TIMEOUT,
+        },
+        ..TestConfig::load("follower_append_test").expect("Test config loaded")
+    };
+    let mut sys = TestSystem::with(cfg);
+    // The disconnected server doesn't learn the leader
+    sys.set_node_connections(1, false);
+    sys.start_all_nodes();
+    let leader = sys.get_elected_leader(2, cfg.wait_timeout);
+
+    let disconnected_px = sys.nodes.get(&1).unwrap();
+    disconnected_px.on_definition(|x| {
+        assert_eq!(x.paxos.get_current_leader(), None);
+        x.paxos
+            .append_with_token(Value::with_id(1), 1)
+            .expect("Failed to append");
+        x.paxos.append(Value::with_id(2)).expect("Failed to append");
+        for _ in 0..FOLLOWER_APPEND_TICK_TIMEOUT - 1 {
+            x.paxos.tick().expect("Failed to tick");
+        }
+        assert!(x.paxos.take_expired_proposals().is_empty());
+        x.paxos.append(Value::with_id(3)).expect("Failed to append");
+        x.paxos.tick().expect("Failed to tick");
+        assert_eq!(
+            x.paxos.take_expired_proposals(),
+            vec![(Value::with_id(1), Some(1)), (Value::with_id(2), None)]
+        );
+    });
+
+    sys.set_node_connections(1, true);
+    wait_until(
+        cfg.wait_timeout,
+        "The buffered entry was not decided",
+        || {
+            sys.nodes
+                .values()
+                .all(|node| node.on_definition(|x| x.paxos.get_decided_idx()) == 1)
+        },
+    );
+    disconnected_px.on_definition(|x| {
+        assert_eq!(x.paxos.get_current_leader(), Some(leader));
+        assert_eq!(
+            x.read_decided_log(),
+            vec![LogEntry::Decided(Value::with_id(3))]
+        );
+    });
+    assert!(sys
+        .nodes
+        .values()
+        .all(|node| node.on_definition(|x| x.paxos.take_expired_proposals().is_empty())));
+
+    let kompact_system =
+        std::mem::take(&mut sys.kompact_system).expect("No KompactSystem found in memory");
+    match kompact_system.shutdown() {
+        Ok(_) => {}
+        Err(e) => panic!("Error on kompact shutdown: {}", e),
+    };
+}
+
+/// Verifies that entries buffered with the default `Forward` policy are kept until a leader is
+/// elected.
+#[test]
+#[serial]
+fn follower_append_forward_test() {
+    let cfg = TestConfig::load("follower_append_test").expect("Test config loaded");
+    let mut sys = TestSystem::with(cfg);
+    sys.set_node_connections(1, false);
+    sys.start_all_nodes();
+    let leader = sys.get_elected_leader(2, cfg.wait_timeout);
+
+    sys.nodes.get(&1).unwrap().on_definition(|x| {
+        x.paxos.append(Value::with_id(1)).expect("Failed to append");
+        for _ in 0..10 * FOLLOWER_APPEND_TICK_TIMEOUT {
+            x.paxos.tick().expect("Failed to tick");
+        }
+        assert!(x.paxos.take_expired_proposals().is_empty());
+    });
+    sys.set_node_connections(1, true);
+    wait_until(
+        cfg.wait_timeout,
+        "The buffered entry was not decided",
+        || {
+            sys.nodes
+                .values()
+                .all(|node| node.on_definition(|x| x.paxos.get_decided_idx()) == 1)
+        },
+    );
+
+    // Appends on a follower are forwarded to the leader
+    let follower = follower_of(&sys, leader, &cfg);
+    sys.make_proposals(follower, vec![Value::with_id(2)], cfg.wait_timeout);
+
+    let kompact_system =
+        std::mem::take(&mut sys.kompact_system).expect("No KompactSystem found in memory");
+    match kompact_system.shutdown() {
+        Ok(_) => {}
+        Err(e) => panic!("Error on kompact shutdown: {}", e),
+    };
+}
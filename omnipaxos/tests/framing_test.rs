@@ -0,0 +1,154 @@
+#![cfg(feature = "framing")]
+
+pub mod utils;
+
+use crate::utils::StorageType;
+use omnipaxos::{
+    errors::FramingErr,
+    framing::{FrameCodec, FrameCompressor, HEADER_LEN},
+    messages::Message,
+};
+use omnipaxos_storage::memory_storage::MemoryStorage;
+use serial_test::serial;
+use std::{error::Error, io::Cursor, sync::Arc};
+use utils::{TestConfig, Value};
+
+/// A toy run-length compressor that encodes every run of equal bytes as its length and byte.
+#[derive(Debug)]
+struct RunLength;
+
+impl FrameCompressor for RunLength {
+    fn compress(&self, bytes: &[u8]) -> Result<Vec<u8>, Box<dyn Error>> {
+        let mut compressed: Vec<u8> = vec![];
+        for b in bytes {
+            match compressed.len() {
+                n if n > 0 && compressed[n - 1] == *b && compressed[n - 2] < u8::MAX => {
+                    compressed[n - 2] += 1
+                }
+                _ => compressed.extend_from_slice(&[1, *b]),
+            }
+        }
+        Ok(compressed)
+    }
+
+    fn decompress(&self, bytes: &[u8]) -> Result<Vec<u8>, Box<dyn Error>> {
+        if !bytes.len().is_multiple_of(2) {
+            return Err("truncated run".into());
+        }
+        Ok(bytes
+            .chunks(2)
+            .flat_map(|run| std::iter::repeat_n(run[1], run[0] as usize))
+            .collect())
+    }
+}
+
+fn outgoing_messages() -> Vec<Message<Value>> {
+    let cfg = TestConfig::load("framing_test").expect("Test config loaded");
+    let mut op = cfg
+        .into_omnipaxos_config(1)
+        .build(StorageType::with_memory(MemoryStorage::default()))
+        .expect("Failed to build OmniPaxos");
+    op.outgoing_messages()
+}
+
+/// Verifies that messages sent as a stream of frames are decoded once their frame has been
+/// received completely, and that they are decoded to the messages that were sent.
+#[test]
+#[serial]
+fn message_stream_test() {
+    let codec = FrameCodec::default();
+    let msgs = outgoing_messages();
+    assert!(!msgs.is_empty());
+    let frames: Vec<Vec<u8>> = msgs
+        .iter()
+        .map(|m| codec.encode_message(m).expect("Failed to encode"))
+        .collect();
+    let stream: Vec<u8> = frames.concat();
+    let mut received = vec![];
+    let mut decoded = vec![];
+    for byte in stream {
+        received.push(byte);
+        if let Some((msg, frame_len)) = codec
+            .decode_message::<Message<Value>>(&received)
+            .expect("Failed to decode")
+        {
+            assert_eq!(frame_len, received.len());
+            received.clear();
+            decoded.push(msg);
+        }
+    }
+    assert!(received.is_empty());
+    assert_eq!(decoded.len(), msgs.len());
+    for (msg, frame) in decoded.iter().zip(&frames) {
+        assert_eq!(&codec.encode_message(msg).unwrap(), frame);
+    }
+}
+
+/// Verifies that a frame that was corrupted on the wire is rejected.
+#[test]
+fn corrupted_frame_test() {
+    let codec = FrameCodec::default();
+    let mut frame = codec.encode(b"some payload").unwrap();
+    frame[HEADER_LEN + 3] ^= 0x10;
+    assert!(matches!(
+        codec.decode(&frame),
+        Err(FramingErr::ChecksumMismatch { .. })
+    ));
+    let mut frame = codec.encode(b"some payload").unwrap();
+    frame[4] = 0x80;
+    assert!(matches!(
+        codec.decode(&frame),
+        Err(FramingErr::UnknownFlags(0x80))
+    ));
+}
+
+/// Verifies that a frame larger than the maximum is neither written nor buffered by the
+/// receiver, which rejects it as soon as it has read the header.
+#[test]
+fn max_frame_size_test() {
+    let codec = FrameCodec {
+        max_frame_size: 16,
+        ..Default::default()
+    };
+    assert!(matches!(
+        codec.encode(&[0; 17]),
+        Err(FramingErr::TooLarge { size: 17, max: 16 })
+    ));
+    let frame = FrameCodec::default().encode(&[0; 17]).unwrap();
+    assert!(matches!(
+        codec.decode(&frame[..HEADER_LEN]),
+        Err(FramingErr::TooLarge { size: 17, max: 16 })
+    ));
+}
+
+/// Verifies that large payloads are compressed if that makes them smaller, and that they are
+/// only decompressed by a codec with a compressor.
+#[test]
+fn compression_test() {
+    let codec = FrameCodec {
+        compressor: Some(Arc::new(RunLength)),
+        compression_threshold: 64,
+        ..Default::default()
+    };
+    let payload = vec![7; 1000];
+    let mut stream = vec![];
+    codec.write_frame(&mut stream, &payload).unwrap();
+    assert!(stream.len() < payload.len());
+    assert_eq!(codec.read_frame(Cursor::new(&stream)).unwrap(), payload);
+    assert!(matches!(
+        FrameCodec::default().decode(&stream),
+        Err(FramingErr::NoCompressor)
+    ));
+
+    // Small payloads, payloads that don't compress, and payloads to receivers that don't
+    // accept compression are sent as they are.
+    let incompressible: Vec<u8> = (0..100).collect();
+    let frame = codec.encode_with(&payload, false).unwrap();
+    assert_eq!(frame.len(), HEADER_LEN + payload.len());
+    for payload in [vec![7; 10], incompressible] {
+        let frame = codec.encode(&payload).unwrap();
+        assert_eq!(frame.len(), HEADER_LEN + payload.len());
+        let (decoded, _) = FrameCodec::default().decode(&frame).unwrap().unwrap();
+        assert_eq!(decoded, payload);
+    }
+}
@@ -0,0 +1,103 @@
+#![cfg(feature = "tokio")]
+
+pub mod utils;
+
+use omnipaxos::{handle::OmniPaxosHandle, messages::Message, util::NodeId};
+use std::collections::HashMap;
+use tokio::{sync::mpsc, time};
+use utils::{verification::verify_log, StorageType, TestConfig, Value};
+
+const BUFFER_SIZE: usize = 1000;
+
+type Handle = OmniPaxosHandle<Value, StorageType<Value>>;
+
+/// Spawns an OmniPaxos task for every server of `cfg` and a task that routes the messages
+/// between them.
+fn spawn_cluster(cfg: &TestConfig) -> HashMap<NodeId, Handle> {
+    let (outgoing, mut network) = mpsc::channel::<Message<Value>>(BUFFER_SIZE);
+    let mut senders = HashMap::new();
+    let mut handles = HashMap::new();
+    for pid in 1..=cfg.num_nodes as NodeId {
+        let op = cfg
+            .into_omnipaxos_config(pid)
+            .build(StorageType::with(cfg.storage_type, ""))
+            .expect("failed to build OmniPaxos");
+        let (sender, incoming) = mpsc::channel(BUFFER_SIZE);
+        let (handle, _) =
+            OmniPaxosHandle::spawn(op, cfg.election_timeout, incoming, outgoing.clone());
+        senders.insert(pid, sender);
+        handles.insert(pid, handle);
+    }
+    tokio::spawn(async move {
+        while let Some(msg) = network.recv().await {
+            if let Some(sender) = senders.get(&msg.get_receiver()) {
+                let _ = sender.send(msg).await;
+            }
+        }
+    });
+    handles
+}
+
+/// Verifies that servers run through `OmniPaxosHandle`s elect a leader and decide the
+/// entries appended through a handle.
+#[tokio::test(flavor = "multi_thread")]
+async fn handle_test() {
+    let cfg = TestConfig::load("handle_test").expect("Test config loaded");
+    let handles = spawn_cluster(&cfg);
+    time::timeout(cfg.wait_timeout, async {
+        for handle in handles.values() {
+            while handle.get_current_leader().await.unwrap().is_none() {
+                time::sleep(cfg.election_timeout).await;
+            }
+        }
+    })
+    .await
+    .expect("No leader was elected");
+
+    let proposals = utils::create_proposals(1, cfg.num_proposals);
+    for v in &proposals {
+        handles[&2]
+            .append(v.clone())
+            .await
+            .expect("Failed to append");
+    }
+    time::timeout(cfg.wait_timeout, async {
+        for handle in handles.values() {
+            while handle.get_decided_idx().await.unwrap() < proposals.len() {
+                time::sleep(cfg.election_timeout).await;
+            }
+        }
+    })
+    .await
+    .expect("Entries were not decided");
+
+    for handle in handles.values() {
+        let log = handle
+            .read_decided_suffix(0)
+            .await
+            .unwrap()
+            .expect("No decided entries");
+        verify_log(log, proposals.clone());
+    }
+}
+
+/// Verifies that the OmniPaxos task stops once the receiver of its outgoing messages is closed,
+/// instead of ticking an instance whose messages can't be sent anymore.
+#[tokio::test(flavor = "multi_thread")]
+async fn closed_outgoing_test() {
+    let cfg = TestConfig::load("handle_test").expect("Test config loaded");
+    let op = cfg
+        .into_omnipaxos_config(1)
+        .build(StorageType::with(cfg.storage_type, ""))
+        .expect("failed to build OmniPaxos");
+    let (_sender, incoming) = mpsc::channel(BUFFER_SIZE);
+    let (outgoing, network) = mpsc::channel(BUFFER_SIZE);
+    let (_handle, task): (Handle, _) =
+        OmniPaxosHandle::spawn(op, cfg.election_timeout, incoming, outgoing);
+    drop(network);
+    time::timeout(cfg.wait_timeout, task)
+        .await
+        .expect("The task did not stop")
+        .expect("The task panicked")
+        .expect("The task failed");
+}
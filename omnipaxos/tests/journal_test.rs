@@ -0,0 +1,131 @@
+pub mod utils;
+
+use omnipaxos::{
+    ballot_leader_election::Ballot,
+    errors::ArchiveErr,
+    storage::{
+        archive::ArchiveCodec,
+        journal::{
+            replay_journal, JournalCall, JournalOp, JournalPayload, JournalPayloads, JournalReader,
+            JournalingStorage,
+        },
+        LogStorage, StateStorage, StorageOp,
+    },
+};
+use omnipaxos_storage::memory_storage::MemoryStorage;
+use utils::{JsonCodec, Value};
+
+type JournaledStorage = JournalingStorage<Value, MemoryStorage<Value>, JsonCodec, Vec<u8>>;
+
+fn ballot(n: u32) -> Ballot {
+    Ballot::with(1, n, 0, 1)
+}
+
+fn values(ids: &[u64]) -> Vec<Value> {
+    ids.iter().copied().map(Value::with_id).collect()
+}
+
+fn encoded(id: u64) -> Vec<u8> {
+    JsonCodec
+        .encode_entry(&Value::with_id(id))
+        .expect("Failed to encode")
+}
+
+/// Makes some calls on a journaled storage and returns the journal.
+fn journal(payloads: JournalPayloads) -> Vec<u8> {
+    let mut storage: JournaledStorage =
+        JournalingStorage::with(MemoryStorage::default(), JsonCodec, payloads, vec![])
+            .expect("Failed to start journal");
+    storage.set_promise(ballot(2)).unwrap();
+    storage.append_entries(values(&[1, 2])).unwrap();
+    storage
+        .write_atomically(vec![
+            StorageOp::AppendOnPrefix(1, values(&[3, 4])),
+            StorageOp::SetAcceptedRound(ballot(2)),
+            StorageOp::SetDecidedIndex(2),
+        ])
+        .unwrap();
+    assert_eq!(storage.get_log_len().unwrap(), 3);
+    assert_eq!(storage.get_entries(0, 3).unwrap(), values(&[1, 3, 4]));
+    assert_eq!(storage.get_decided_idx().unwrap(), 2);
+    storage.approximate_size().unwrap();
+    assert_eq!(storage.journal_error(), None);
+    let (_, journal) = storage.into_inner().expect("Failed to flush journal");
+    journal
+}
+
+/// Verifies that the journal records every call with its arguments, and that replaying it
+/// against a fresh storage reproduces every outcome.
+#[test]
+fn replay_journal_test() {
+    let journal = journal(JournalPayloads::Inline);
+    let records: Vec<_> = JournalReader::new(journal.as_slice())
+        .expect("Failed to read header")
+        .map(|r| r.expect("Failed to read record"))
+        .collect();
+    assert_eq!(records.len(), 7);
+    assert!(records.iter().enumerate().all(|(i, r)| r.seq == i as u64));
+    assert_eq!(
+        records[1].call,
+        JournalCall::Write(JournalOp::AppendEntries(vec![
+            JournalPayload::Inline(encoded(1)),
+            JournalPayload::Inline(encoded(2)),
+        ]))
+    );
+    assert!(records.iter().all(|r| r.outcome.error.is_none()));
+
+    let mut fresh = MemoryStorage::default();
+    let replay = replay_journal(journal.as_slice(), &JsonCodec, &mut fresh)
+        .expect("Failed to replay journal");
+    assert_eq!(replay.calls, 7);
+    assert!(replay.divergences.is_empty());
+    assert_eq!(fresh.get_suffix(0).unwrap(), values(&[1, 3, 4]));
+    assert_eq!(fresh.get_promise().unwrap(), Some(ballot(2)));
+}
+
+/// Verifies that a replay reports the calls that return something else on the other storage.
+#[test]
+fn divergence_test() {
+    let journal = journal(JournalPayloads::Inline);
+    let mut other = MemoryStorage::default();
+    other.append_entry(Value::with_id(0)).unwrap();
+    let replay = replay_journal(journal.as_slice(), &JsonCodec, &mut other)
+        .expect("Failed to replay journal");
+    assert_eq!(replay.calls, 7);
+    let diverged: Vec<_> = replay
+        .divergences
+        .iter()
+        .map(|d| d.record.call.clone())
+        .collect();
+    // The log has the same length, but starts with another entry.
+    assert_eq!(diverged, vec![JournalCall::GetEntries { from: 0, to: 3 }]);
+}
+
+/// Verifies that a journal with hashed entries can be read, but not replayed.
+#[test]
+fn hashed_payloads_test() {
+    let journal = journal(JournalPayloads::Hashed);
+    let records: Vec<_> = JournalReader::new(journal.as_slice())
+        .expect("Failed to read header")
+        .map(|r| r.expect("Failed to read record"))
+        .collect();
+    match &records[1].call {
+        JournalCall::Write(JournalOp::AppendEntries(payloads)) => {
+            let len = encoded(1).len();
+            assert!(payloads
+                .iter()
+                .all(|p| matches!(p, JournalPayload::Hashed { len: l, .. } if *l == len)));
+        }
+        call => panic!("Unexpected call {:?}", call),
+    }
+    let result = replay_journal(
+        journal.as_slice(),
+        &JsonCodec,
+        &mut MemoryStorage::default(),
+    );
+    assert!(matches!(result, Err(ArchiveErr::HashedPayload(1))));
+    assert!(matches!(
+        JournalReader::new(&b"OPXA\x01\x00"[..]),
+        Err(ArchiveErr::InvalidMagic)
+    ));
+}
@@ -0,0 +1,131 @@
+pub mod utils;
+
+use omnipaxos::{
+    storage::{Entry, NoSnapshot},
+    util::NodeId,
+    OmniPaxos, ProposalRejectionCause, ProposeErr,
+};
+use omnipaxos_storage::memory_storage::MemoryStorage;
+use serial_test::serial;
+use std::collections::HashMap;
+use utils::{deliver, elect_leader, no_unicache, tick_until, TestConfig};
+
+const MEMORY_LIMIT: usize = 8 * 1024;
+const ENTRY_SIZE: usize = 1024;
+
+#[derive(Clone, Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+struct Value(String);
+
+impl Entry for Value {
+    type Snapshot = NoSnapshot;
+    no_unicache!();
+
+    fn size_hint(&self) -> usize {
+        self.0.len()
+    }
+}
+
+type OmniPaxosValue = OmniPaxos<Value, MemoryStorage<Value>>;
+
+/// Builds a cluster in which all servers have a `memory_limit`.
+fn cluster(cfg: &TestConfig) -> HashMap<NodeId, OmniPaxosValue> {
+    utils::create_cluster(cfg, |server_cfg| {
+        server_cfg.memory_limit = Some(MEMORY_LIMIT)
+    })
+}
+
+fn value() -> Value {
+    Value("x".repeat(ENTRY_SIZE))
+}
+
+/// Verifies that the buffered proposals and the queued outgoing messages are accounted for.
+#[test]
+#[serial]
+fn memory_usage_test() {
+    let cfg = TestConfig::load("memory_limit_test").expect("Test config loaded");
+    let mut nodes = cluster(&cfg);
+    // without a leader, the proposal is buffered until it can be forwarded
+    let follower = nodes.get_mut(&1).unwrap();
+    follower.append(value()).expect("Failed to append");
+    let usage = follower.get_memory_usage();
+    assert!(usage.pending_proposals >= ENTRY_SIZE, "{:?}", usage);
+    assert_eq!(usage.total(), follower.get_memory_usage().total());
+
+    tick_until(&mut nodes, |nodes| {
+        nodes.values().all(|op| op.get_decided_idx() == 1)
+    });
+    let leader = nodes[&1].get_current_leader().unwrap();
+    let leader = nodes.get_mut(&leader).unwrap();
+    let before = leader.get_memory_usage();
+    leader.append(value()).expect("Failed to append");
+    let usage = leader.get_memory_usage();
+    // With UniCache, the outgoing entries are accounted for by the size of their encoding
+    let min_growth = if cfg!(feature = "unicache") {
+        1
+    } else {
+        ENTRY_SIZE
+    };
+    assert!(
+        usage.outgoing + usage.pending_proposals
+            >= before.outgoing + before.pending_proposals + min_growth,
+        "{:?}",
+        usage
+    );
+    leader.outgoing_messages();
+    assert!(leader.get_memory_usage().outgoing < usage.outgoing);
+}
+
+/// Verifies that a server over its `memory_limit` drops the cache of its log tail, refuses
+/// appends and forwarded proposals, and accepts them again once the usage is below the limit.
+#[test]
+#[serial]
+fn memory_limit_test() {
+    let cfg = TestConfig::load("memory_limit_test").expect("Test config loaded");
+    let mut nodes = cluster(&cfg);
+    let leader_pid = elect_leader(&mut nodes);
+    let follower = *nodes.keys().find(|pid| **pid != leader_pid).unwrap();
+    let leader = nodes.get_mut(&leader_pid).unwrap();
+    leader.append(value()).expect("Failed to append");
+    tick_until(&mut nodes, |nodes| {
+        nodes.values().all(|op| op.get_decided_idx() == 1)
+    });
+
+    // the messages to the followers are not taken, so the outgoing queues fill up beyond what
+    // dropping the cache of the log tail frees
+    let leader = nodes.get_mut(&leader_pid).unwrap();
+    assert!(leader.get_memory_usage().caches >= ENTRY_SIZE);
+    loop {
+        let usage = leader.get_memory_usage();
+        if usage.total() - usage.caches > MEMORY_LIMIT {
+            break;
+        }
+        leader.append(value()).expect("Failed to append");
+    }
+    leader.tick().expect("Failed to tick");
+    assert_eq!(leader.get_memory_usage().caches, 0);
+    match leader.append(value()) {
+        Err(ProposeErr::MemoryLimitExceeded(entry)) => assert_eq!(entry, value()),
+        res => panic!("Unexpected result: {:?}", res),
+    }
+
+    nodes
+        .get_mut(&follower)
+        .unwrap()
+        .append(value())
+        .expect("Failed to append");
+    deliver(&mut nodes, &[follower], |_| true);
+    deliver(&mut nodes, &[leader_pid], |_| true);
+    let rejected = nodes.get_mut(&follower).unwrap().take_rejected_proposals();
+    assert_eq!(rejected.len(), 1);
+    assert_eq!(
+        rejected[0].cause,
+        ProposalRejectionCause::MemoryLimitExceeded
+    );
+
+    // once the outgoing messages are taken, the next tick lifts the limit
+    let leader = nodes.get_mut(&leader_pid).unwrap();
+    assert!(leader.get_memory_usage().total() <= MEMORY_LIMIT);
+    leader.tick().expect("Failed to tick");
+    leader.append(value()).expect("Failed to append");
+}
@@ -0,0 +1,350 @@
+pub mod utils;
+
+#[cfg(feature = "prometheus")]
+use omnipaxos::metrics::PrometheusSink;
+#[cfg(feature = "statsd")]
+use omnipaxos::metrics::StatsdSink;
+use omnipaxos::{
+    messages::{sequence_paxos::PaxosMsg, Message},
+    metrics::{self, MetricsSink},
+    util::NodeId,
+    OmniPaxos,
+};
+use omnipaxos_storage::memory_storage::MemoryStorage;
+use serial_test::serial;
+use std::{
+    collections::HashMap,
+    sync::{Arc, Mutex},
+};
+use utils::{tick_until, TestConfig, Value};
+
+type OmniPaxosValue = OmniPaxos<Value, MemoryStorage<Value>>;
+
+/// Keeps the totals of the counters, the last values of the gauges, and all observations of
+/// the histograms.
+#[derive(Debug, Default)]
+struct RecordingSink {
+    counters: Mutex<HashMap<&'static str, u64>>,
+    gauges: Mutex<HashMap<&'static str, f64>>,
+    histograms: Mutex<HashMap<&'static str, Vec<f64>>>,
+}
+
+impl RecordingSink {
+    fn counter_value(&self, name: &str) -> u64 {
+        self.counters
+            .lock()
+            .unwrap()
+            .get(name)
+            .copied()
+            .unwrap_or(0)
+    }
+
+    fn gauge_value(&self, name: &str) -> Option<f64> {
+        self.gauges.lock().unwrap().get(name).copied()
+    }
+
+    fn num_observations(&self, name: &str) -> usize {
+        self.histograms
+            .lock()
+            .unwrap()
+            .get(name)
+            .map_or(0, |values| values.len())
+    }
+}
+
+impl MetricsSink for RecordingSink {
+    fn counter(&self, name: &'static str, value: u64) {
+        *self.counters.lock().unwrap().entry(name).or_default() += value;
+    }
+
+    fn gauge(&self, name: &'static str, value: f64) {
+        self.gauges.lock().unwrap().insert(name, value);
+    }
+
+    fn histogram(&self, name: &'static str, value: f64) {
+        self.histograms
+            .lock()
+            .unwrap()
+            .entry(name)
+            .or_default()
+            .push(value);
+    }
+}
+
+fn cluster<F>(cfg: &TestConfig, sink: F) -> HashMap<NodeId, OmniPaxosValue>
+where
+    F: Fn(NodeId) -> Arc<dyn MetricsSink>,
+{
+    utils::create_cluster(cfg, |server_cfg| {
+        server_cfg.metrics_sink = Some(sink(server_cfg.pid))
+    })
+}
+
+/// Elects a leader, decides the proposals of `cfg`, and ticks once more so that the gauges
+/// show the decided entries. Returns the pid of the leader.
+fn decide_entries(cfg: &TestConfig, nodes: &mut HashMap<NodeId, OmniPaxosValue>) -> NodeId {
+    let op = nodes.get_mut(&1).unwrap();
+    for v in utils::create_proposals(1, cfg.num_proposals) {
+        op.append(v).expect("Failed to append");
+    }
+    tick_until(nodes, |nodes| {
+        nodes
+            .values()
+            .all(|op| op.get_decided_idx() == cfg.num_proposals as usize)
+    });
+    for op in nodes.values_mut() {
+        op.tick().expect("Failed to tick");
+    }
+    nodes[&1].get_current_leader().expect("No leader elected")
+}
+
+/// Verifies that the servers report their messages, leader changes, log indexes, and commit
+/// latencies to their metrics sink.
+#[test]
+#[serial]
+fn metrics_sink_test() {
+    let cfg = TestConfig::load("metrics_test").expect("Test config loaded");
+    let sinks: HashMap<NodeId, Arc<RecordingSink>> = (1..=cfg.num_nodes as NodeId)
+        .map(|pid| (pid, Arc::default()))
+        .collect();
+    let mut nodes = cluster(&cfg, |pid| sinks[&pid].clone());
+    let leader = decide_entries(&cfg, &mut nodes);
+
+    for (pid, sink) in &sinks {
+        assert!(sink.counter_value(metrics::MESSAGES_SENT) > 0);
+        assert!(sink.counter_value(metrics::MESSAGES_RECEIVED) > 0);
+        assert_eq!(sink.counter_value(metrics::MESSAGES_REJECTED), 0);
+        assert!(sink.counter_value(metrics::LEADER_CHANGES) > 0);
+        let is_leader = if *pid == leader { 1.0 } else { 0.0 };
+        assert_eq!(sink.gauge_value(metrics::IS_LEADER), Some(is_leader));
+        assert_eq!(
+            sink.gauge_value(metrics::DECIDED_IDX),
+            Some(cfg.num_proposals as f64)
+        );
+        assert_eq!(
+            sink.gauge_value(metrics::ACCEPTED_IDX),
+            Some(cfg.num_proposals as f64)
+        );
+        assert_eq!(sink.gauge_value(metrics::COMPACTED_IDX), Some(0.0));
+    }
+    // Only the leader observes when entries are appended
+    for (pid, sink) in &sinks {
+        let num_latencies = sink.num_observations(metrics::COMMIT_LATENCY);
+        if *pid == leader {
+            assert!(
+                num_latencies > 0,
+                "The leader did not report commit latencies"
+            );
+        } else {
+            assert_eq!(num_latencies, 0);
+        }
+    }
+}
+
+/// Whether `msg` is a message of the leader to `to` that is ordered by a sequence number.
+fn is_sequenced_to(msg: &Message<Value>, to: NodeId) -> bool {
+    match msg {
+        Message::SequencePaxos(m) => {
+            m.to == to && matches!(m.msg, PaxosMsg::AcceptDecide(_) | PaxosMsg::Decide(_))
+        }
+        _ => false,
+    }
+}
+
+/// Verifies that the servers report the occupancy of their outgoing queues, proposal buffers,
+/// and in-flight windows, and count the messages they dropped because of their sequence
+/// numbers.
+#[test]
+#[serial]
+fn outgoing_stats_test() {
+    let cfg = TestConfig::load("metrics_test").expect("Test config loaded");
+    let num_proposals = cfg.num_proposals as usize;
+    let sinks: HashMap<NodeId, Arc<RecordingSink>> = (1..=cfg.num_nodes as NodeId)
+        .map(|pid| (pid, Arc::default()))
+        .collect();
+    let mut nodes = cluster(&cfg, |pid| sinks[&pid].clone());
+
+    // Without a leader, the proposals are buffered
+    let op = nodes.get_mut(&1).unwrap();
+    for v in utils::create_proposals(1, cfg.num_proposals) {
+        op.append(v).expect("Failed to append");
+    }
+    assert_eq!(op.get_outgoing_stats().pending_proposals, num_proposals);
+    op.tick().expect("Failed to tick");
+    assert_eq!(
+        sinks[&1].gauge_value(metrics::PENDING_PROPOSALS),
+        Some(cfg.num_proposals as f64)
+    );
+    assert!(sinks[&1].gauge_value(metrics::OUTGOING_MESSAGES).unwrap() > 0.0);
+    tick_until(&mut nodes, |nodes| {
+        nodes
+            .values()
+            .all(|op| op.get_decided_idx() == num_proposals)
+    });
+    let leader = nodes[&1].get_current_leader().expect("No leader elected");
+    let follower = (1..=cfg.num_nodes as NodeId)
+        .find(|pid| *pid != leader)
+        .unwrap();
+    for op in nodes.values() {
+        assert_eq!(op.get_outgoing_stats().pending_proposals, 0);
+    }
+
+    // The entries are in flight until the followers accept them
+    let op = nodes.get_mut(&leader).unwrap();
+    for v in utils::create_proposals(1, cfg.num_proposals) {
+        op.append(v).expect("Failed to append");
+    }
+    let stats = op.get_outgoing_stats();
+    assert!(stats.queued_messages > 0);
+    for pid in (1..=cfg.num_nodes as NodeId).filter(|pid| *pid != leader) {
+        assert_eq!(stats.in_flight.get(&pid), Some(&num_proposals));
+    }
+    op.tick().expect("Failed to tick");
+    assert_eq!(
+        sinks[&leader].gauge_value(metrics::MAX_IN_FLIGHT_ENTRIES),
+        Some(cfg.num_proposals as f64)
+    );
+
+    // Duplicated messages are outdated
+    let msgs: Vec<Message<Value>> = op
+        .outgoing_messages()
+        .into_iter()
+        .filter(|msg| is_sequenced_to(msg, follower))
+        .collect();
+    assert!(!msgs.is_empty());
+    for msg in msgs.iter().chain(msgs.iter()) {
+        nodes
+            .get_mut(&follower)
+            .unwrap()
+            .handle_incoming(msg.clone())
+            .expect("Failed to handle message");
+    }
+    let stats = nodes[&follower].get_outgoing_stats();
+    assert_eq!(stats.outdated_messages, msgs.len() as u64);
+    assert_eq!(stats.dropped_preceding_messages, 0);
+    assert_eq!(
+        sinks[&follower].counter_value(metrics::MESSAGES_OUTDATED),
+        msgs.len() as u64
+    );
+
+    // A message after a lost one reveals the gap
+    let mut lost = false;
+    for v in 0..2 {
+        let op = nodes.get_mut(&leader).unwrap();
+        op.append(Value::with_id(v)).expect("Failed to append");
+        let msgs: Vec<Message<Value>> = op
+            .outgoing_messages()
+            .into_iter()
+            .filter(|msg| is_sequenced_to(msg, follower))
+            .collect();
+        for msg in msgs {
+            if lost {
+                nodes
+                    .get_mut(&follower)
+                    .unwrap()
+                    .handle_incoming(msg)
+                    .expect("Failed to handle message");
+            }
+            lost = true;
+        }
+    }
+    let stats = nodes[&follower].get_outgoing_stats();
+    assert_eq!(stats.dropped_preceding_messages, 1);
+    assert_eq!(
+        sinks[&follower].counter_value(metrics::MESSAGES_DROPPED_PRECEDING),
+        1
+    );
+
+    // The follower resynchronizes and the in-flight windows drain
+    let decided_idx = 2 * num_proposals + 2;
+    tick_until(&mut nodes, |nodes| {
+        nodes.values().all(|op| op.get_decided_idx() == decided_idx)
+            && nodes[&leader]
+                .get_outgoing_stats()
+                .in_flight
+                .values()
+                .all(|n| *n == 0)
+    });
+    nodes
+        .get_mut(&leader)
+        .unwrap()
+        .tick()
+        .expect("Failed to tick");
+    assert_eq!(
+        sinks[&leader].gauge_value(metrics::MAX_IN_FLIGHT_ENTRIES),
+        Some(0.0)
+    );
+}
+
+/// Verifies that the Prometheus sink renders the reported metrics in the text exposition
+/// format with the labels of the server.
+#[cfg(feature = "prometheus")]
+#[test]
+#[serial]
+fn prometheus_sink_test() {
+    let cfg = TestConfig::load("metrics_test").expect("Test config loaded");
+    let sinks: HashMap<NodeId, Arc<PrometheusSink>> = (1..=cfg.num_nodes as NodeId)
+        .map(|pid| {
+            let sink = PrometheusSink::with(&[("pid", &pid.to_string())]);
+            (pid, Arc::new(sink))
+        })
+        .collect();
+    let mut nodes = cluster(&cfg, |pid| sinks[&pid].clone());
+    let leader = decide_entries(&cfg, &mut nodes);
+
+    let rendered = sinks[&leader].render();
+    let lines: Vec<&str> = rendered.lines().collect();
+    let pid_label = format!("{{pid=\"{}\"}}", leader);
+    for expected in [
+        "# TYPE omnipaxos_decided_idx gauge".to_string(),
+        format!("omnipaxos_decided_idx{} {}", pid_label, cfg.num_proposals),
+        format!("omnipaxos_is_leader{} 1", pid_label),
+        "# TYPE omnipaxos_messages_sent_total counter".to_string(),
+        "# TYPE omnipaxos_commit_latency_seconds histogram".to_string(),
+    ] {
+        assert!(lines.contains(&expected.as_str()), "missing `{}`", expected);
+    }
+    let count_prefix = format!("omnipaxos_commit_latency_seconds_count{} ", pid_label);
+    let inf_prefix = format!(
+        "omnipaxos_commit_latency_seconds_bucket{{pid=\"{}\",le=\"+Inf\"}} ",
+        leader
+    );
+    let value_of = |prefix: &str| -> u64 {
+        let line = lines
+            .iter()
+            .find(|line| line.starts_with(prefix))
+            .unwrap_or_else(|| panic!("missing `{}`", prefix));
+        line[prefix.len()..].parse().unwrap()
+    };
+    assert!(value_of(&count_prefix) > 0);
+    assert_eq!(value_of(&count_prefix), value_of(&inf_prefix));
+}
+
+/// Verifies that the StatsD sink sends every metric as a line of the StatsD protocol.
+#[cfg(feature = "statsd")]
+#[test]
+fn statsd_sink_test() {
+    let server = std::net::UdpSocket::bind("127.0.0.1:0").unwrap();
+    server
+        .set_read_timeout(Some(std::time::Duration::from_secs(5)))
+        .unwrap();
+    let sink = StatsdSink::with(server.local_addr().unwrap(), "node1.").unwrap();
+    sink.counter(metrics::MESSAGES_SENT, 3);
+    sink.gauge(metrics::DECIDED_IDX, 5.0);
+    sink.histogram(metrics::COMMIT_LATENCY, 0.25);
+
+    let mut buf = [0; 512];
+    let mut received = vec![];
+    for _ in 0..3 {
+        let len = server.recv(&mut buf).expect("No StatsD packet received");
+        received.push(String::from_utf8(buf[..len].to_vec()).unwrap());
+    }
+    assert_eq!(
+        received,
+        vec![
+            "node1.omnipaxos_messages_sent_total:3|c",
+            "node1.omnipaxos_decided_idx:5|g",
+            "node1.omnipaxos_commit_latency_seconds:0.25|h",
+        ]
+    );
+}
@@ -0,0 +1,101 @@
+pub mod utils;
+
+use crate::utils::StorageType;
+use omnipaxos::{
+    messages::Message,
+    util::{Namespace, NodeId},
+    OmniPaxos, RejectedMessageCause,
+};
+use omnipaxos_storage::memory_storage::MemoryStorage;
+use serial_test::serial;
+use std::collections::HashMap;
+use utils::{verification::verify_log, TestConfig, Value, MAX_TICKS};
+
+/// The servers of all tenants, keyed by their namespace and pid.
+type Tenants = HashMap<(Namespace, NodeId), OmniPaxos<Value, StorageType<Value>>>;
+
+fn tenants(cfg: &TestConfig, namespaces: &[Namespace]) -> Tenants {
+    let mut tenants = HashMap::new();
+    for namespace in namespaces {
+        for pid in 1..=cfg.num_nodes as NodeId {
+            let mut op_config = cfg.into_omnipaxos_config(pid);
+            op_config.cluster_config.namespace = Some(*namespace);
+            let op = op_config
+                .build(StorageType::with_memory(MemoryStorage::default()))
+                .expect("Failed to build OmniPaxos");
+            tenants.insert((*namespace, pid), op);
+        }
+    }
+    tenants
+}
+
+/// Like `utils::tick_until`, but delivers every message to the server of its receiver in every
+/// namespace, as a shared connection that does not route by namespace would.
+fn tick_until<F>(tenants: &mut Tenants, done: F)
+where
+    F: Fn(&Tenants) -> bool,
+{
+    for _ in 0..MAX_TICKS {
+        if done(tenants) {
+            return;
+        }
+        for op in tenants.values_mut() {
+            op.tick().expect("Failed to tick");
+        }
+        loop {
+            let msgs: Vec<Message<Value>> = tenants
+                .values_mut()
+                .flat_map(|op| op.outgoing_messages())
+                .collect();
+            if msgs.is_empty() {
+                break;
+            }
+            for msg in msgs {
+                for ((_, pid), op) in tenants.iter_mut() {
+                    if *pid == msg.get_receiver() {
+                        op.handle_incoming(msg.clone())
+                            .expect("Failed to handle message");
+                    }
+                }
+            }
+        }
+    }
+    panic!("tenants did not reach the expected state in time");
+}
+
+/// Verifies that clusters of different namespaces with the same pids and configuration id
+/// reject each other's messages and decide their logs independently.
+#[test]
+#[serial]
+fn namespace_test() {
+    let cfg = TestConfig::load("namespace_test").expect("Test config loaded");
+    let namespaces = [1, 2];
+    let mut tenants = tenants(&cfg, &namespaces);
+    tick_until(&mut tenants, |tenants| {
+        tenants.values().all(|op| op.get_current_leader().is_some())
+    });
+    let proposals = |namespace| utils::create_proposals(1, cfg.num_proposals * namespace);
+    for namespace in namespaces {
+        let leader = tenants[&(namespace, 1)].get_current_leader().unwrap();
+        let op = tenants.get_mut(&(namespace, leader)).unwrap();
+        for v in proposals(namespace) {
+            op.append(v).expect("Failed to append");
+        }
+    }
+    tick_until(&mut tenants, |tenants| {
+        tenants.iter().all(|((namespace, _), op)| {
+            op.get_decided_idx() == (cfg.num_proposals * namespace) as usize
+        })
+    });
+    for ((namespace, pid), op) in tenants.iter_mut() {
+        let log = op.read_decided_suffix(0).expect("Failed to read the log");
+        verify_log(log, proposals(*namespace));
+        // The messages of the other namespace were rejected
+        let peer = (1..=cfg.num_nodes as NodeId).find(|p| p != pid).unwrap();
+        assert!(op.get_rejected_message_count(peer) > 0);
+        assert!(op
+            .take_misbehaving_peers()
+            .iter()
+            .all(|p| p.cause == RejectedMessageCause::ForeignNamespace));
+    }
+}
@@ -0,0 +1,117 @@
+pub mod utils;
+
+use crate::utils::StorageType;
+use omnipaxos::{
+    errors::CompactionErr,
+    storage::{Entry, NoSnapshot},
+    util::NodeId,
+    OmniPaxos,
+};
+use omnipaxos_storage::memory_storage::MemoryStorage;
+use serde::{Deserialize, Serialize};
+use serial_test::serial;
+use std::collections::HashMap;
+use utils::{no_unicache, tick_until, TestConfig, TestSystem};
+
+/// An entry type that doesn't use snapshots.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+struct Unsnapshotted(u64);
+
+impl Entry for Unsnapshotted {
+    type Snapshot = NoSnapshot;
+    no_unicache!();
+}
+
+type OmniPaxosUnsnapshotted = OmniPaxos<Unsnapshotted, StorageType<Unsnapshotted>>;
+
+/// Verifies that snapshotting the log of entries that don't use snapshots fails instead of
+/// creating a `NoSnapshot`.
+#[test]
+#[serial]
+fn snapshot_unsupported_test() {
+    let cfg = TestConfig::load("no_snapshots_test").expect("Test config loaded");
+    let mut nodes: HashMap<NodeId, OmniPaxosUnsnapshotted> = (1..=cfg.num_nodes as NodeId)
+        .map(|pid| {
+            let op = cfg
+                .into_omnipaxos_config(pid)
+                .build(StorageType::with_memory(MemoryStorage::default()))
+                .expect("Failed to build OmniPaxos");
+            (pid, op)
+        })
+        .collect();
+    tick_until(&mut nodes, |nodes| {
+        nodes.values().all(|op| op.get_current_leader().is_some())
+    });
+    let leader = nodes[&1].get_current_leader().unwrap();
+    for v in 1..=cfg.num_proposals {
+        nodes
+            .get_mut(&leader)
+            .unwrap()
+            .append(Unsnapshotted(v))
+            .expect("Failed to append");
+    }
+    tick_until(&mut nodes, |nodes| {
+        nodes
+            .values()
+            .all(|op| op.get_decided_idx() == cfg.num_proposals as usize)
+    });
+
+    for op in nodes.values_mut() {
+        assert!(matches!(
+            op.snapshot(None, true),
+            Err(CompactionErr::SnapshotsUnsupported)
+        ));
+        assert_eq!(op.get_compacted_idx(), 0);
+    }
+}
+
+/// Verifies that the `no_snapshots` feature disables the snapshots of entry types that
+/// implement them.
+#[test]
+#[serial]
+fn snapshots_disabled_test() {
+    let cfg = TestConfig::load("no_snapshots_test").expect("Test config loaded");
+    let mut sys = TestSystem::with(cfg);
+    sys.start_all_nodes();
+    let leader = sys.get_elected_leader(1, cfg.wait_timeout);
+    sys.make_proposals(
+        leader,
+        utils::create_proposals(1, cfg.num_proposals),
+        cfg.wait_timeout,
+    );
+
+    sys.nodes.get(&leader).unwrap().on_definition(|x| {
+        let result = x.paxos.snapshot(None, true);
+        if cfg!(feature = "no_snapshots") {
+            assert!(matches!(result, Err(CompactionErr::SnapshotsUnsupported)));
+            assert_eq!(x.paxos.get_compacted_idx(), 0);
+        } else {
+            result.expect("Failed to snapshot");
+            assert_eq!(x.paxos.get_compacted_idx(), cfg.num_proposals as usize);
+        }
+    });
+
+    let kompact_system =
+        std::mem::take(&mut sys.kompact_system).expect("No KompactSystem found in memory");
+    match kompact_system.shutdown() {
+        Ok(_) => {}
+        Err(e) => panic!("Error on kompact shutdown: {}", e),
+    };
+}
+
+/// Verifies that backup servers, which only receive snapshots, can't be configured with the
+/// `no_snapshots` feature.
+#[test]
+#[cfg(feature = "no_snapshots")]
+fn no_backups_test() {
+    let cfg = TestConfig {
+        num_backup_nodes: 1,
+        ..TestConfig::load("no_snapshots_test").expect("Test config loaded")
+    };
+    assert!(cfg
+        .into_omnipaxos_config(1)
+        .build(StorageType::<utils::Value>::with_memory(
+            MemoryStorage::default()
+        ))
+        .is_err());
+}
@@ -0,0 +1,112 @@
+pub mod utils;
+
+use omnipaxos::{
+    storage::{Entry, NoSnapshot},
+    util::{LogEntry, LogEntryRef, NodeId},
+    OmniPaxos,
+};
+use omnipaxos_storage::memory_storage::MemoryStorage;
+use serial_test::serial;
+use std::{
+    collections::HashMap,
+    sync::atomic::{AtomicUsize, Ordering},
+};
+use utils::{no_unicache, tick_until, TestConfig};
+
+static CLONES: AtomicUsize = AtomicUsize::new(0);
+
+/// An entry that counts how often it is cloned.
+#[derive(Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+struct CountedValue(u64);
+
+impl Clone for CountedValue {
+    fn clone(&self) -> Self {
+        CLONES.fetch_add(1, Ordering::SeqCst);
+        CountedValue(self.0)
+    }
+}
+
+impl Entry for CountedValue {
+    type Snapshot = NoSnapshot;
+    no_unicache!();
+}
+
+type OmniPaxosCounted = OmniPaxos<CountedValue, MemoryStorage<CountedValue>>;
+
+/// Verifies that `read_ref` returns the same entries as `read` without cloning them, both when
+/// they are served from the cache of recent entries and from the memory storage.
+#[test]
+#[serial]
+fn read_ref_test() {
+    for decided_cache_size in [0, 100] {
+        let cfg = TestConfig {
+            decided_cache_size: Some(decided_cache_size),
+            ..TestConfig::load("read_ref_test").expect("Test config loaded")
+        };
+        let num_proposals = cfg.num_proposals as usize;
+        let mut nodes: HashMap<NodeId, OmniPaxosCounted> = (1..=cfg.num_nodes as NodeId)
+            .map(|pid| {
+                let op = cfg
+                    .into_omnipaxos_config(pid)
+                    .build(MemoryStorage::default())
+                    .expect("Failed to build OmniPaxos");
+                (pid, op)
+            })
+            .collect();
+        let op = nodes.get_mut(&1).unwrap();
+        for v in 0..cfg.num_proposals {
+            op.append(CountedValue(v)).expect("Failed to append");
+        }
+        tick_until(&mut nodes, |nodes| {
+            nodes
+                .values()
+                .all(|op| op.get_decided_idx() == num_proposals)
+        });
+        let leader = nodes[&1].get_current_leader().expect("No leader elected");
+        nodes
+            .get_mut(&leader)
+            .unwrap()
+            .trim(Some(cfg.trim_idx))
+            .expect("Failed to trim");
+        tick_until(&mut nodes, |nodes| {
+            nodes
+                .values()
+                .all(|op| op.get_compacted_idx() == cfg.trim_idx)
+        });
+
+        for op in nodes.values() {
+            let clones = CLONES.load(Ordering::SeqCst);
+            for idx in cfg.trim_idx..num_proposals {
+                let expected = op.read(idx);
+                let entry = op.read_ref(idx, |entry| entry.to_log_entry());
+                assert_eq!(entry.expect("Failed to read"), expected);
+            }
+            let trimmed = op.read_ref(cfg.trim_idx - 1, |entry| entry.to_log_entry());
+            assert_eq!(
+                trimmed.expect("Failed to read"),
+                Some(LogEntry::Trimmed(cfg.trim_idx))
+            );
+            let out_of_bounds = op.read_ref(num_proposals, |_| ());
+            assert_eq!(out_of_bounds.expect("Failed to read"), None);
+            // `read` and `to_log_entry` clone the decided entries once each
+            assert_eq!(
+                CLONES.load(Ordering::SeqCst),
+                clones + 2 * (num_proposals - cfg.trim_idx)
+            );
+
+            let clones = CLONES.load(Ordering::SeqCst);
+            let sum: u64 = (cfg.trim_idx..num_proposals)
+                .filter_map(|idx| {
+                    op.read_ref(idx, |entry| match entry {
+                        LogEntryRef::Decided(CountedValue(v)) => *v,
+                        e => panic!("Unexpected entry: {:?}", e),
+                    })
+                    .expect("Failed to read")
+                })
+                .sum();
+            assert_eq!(sum, (cfg.trim_idx as u64..cfg.num_proposals).sum::<u64>());
+            assert_eq!(CLONES.load(Ordering::SeqCst), clones);
+        }
+    }
+}
@@ -1,15 +1,29 @@
 pub mod utils;
 
 use crate::utils::STOPSIGN_ID;
-use kompact::prelude::{promise, Ask};
+use kompact::prelude::{promise, Ask, FutureCollection};
+#[cfg(not(feature = "no_snapshots"))]
 use omnipaxos::{
+    errors::HandoffErr,
+    handoff::{HandoffReceiver, HandoffRef},
+    storage::{archive::ArchiveCodec, Snapshot},
+    OmniPaxos,
+};
+use omnipaxos::{
+    storage::StopSign,
     util::{LogEntry, NodeId},
-    ClusterConfig,
+    ClusterConfig, ReconfigErr, ReconfigurationStatus,
 };
 use serial_test::serial;
-use utils::{TestConfig, TestSystem, Value};
+use std::thread;
+use utils::{wait_until, StorageType, StorageTypeSelector, TestConfig, TestSystem, Value};
+#[cfg(not(feature = "no_snapshots"))]
+use utils::{JsonCodec, ValueSnapshot};
 
 const SS_METADATA: u8 = 255;
+const SHRINK_REASON: &str = "reconfiguration would shrink the cluster";
+#[cfg(not(feature = "no_snapshots"))]
+const HANDOFF_CHUNK_SIZE: usize = 3;
 
 /// Verifies that the decided StopSign is correct and error is returned when trying to append after decided StopSign.
 #[test]
@@ -30,6 +44,7 @@ fn reconfig_test() {
         configuration_id: new_config_id,
         nodes: new_nodes,
         flexible_quorum: None,
+        ..Default::default()
     };
     let metadata = Some(vec![SS_METADATA]);
 
@@ -90,3 +105,583 @@ fn reconfig_test() {
         Err(e) => panic!("Error on kompact shutdown: {}", e),
     };
 }
+
+/// Verifies that proposing the same reconfiguration again returns its status instead of proposing
+/// a second StopSign, and that a different reconfiguration can't be proposed once one is decided.
+#[test]
+#[serial]
+fn reconfig_status_test() {
+    let cfg = TestConfig::load("consensus_test").expect("Test config loaded");
+    let mut sys = TestSystem::with(cfg);
+    sys.start_all_nodes();
+    sys.get_elected_leader(1, cfg.wait_timeout);
+
+    let new_config_id = 2;
+    let new_nodes: Vec<NodeId> = (cfg.num_nodes as NodeId..(cfg.num_nodes as NodeId + 3)).collect();
+    let new_config = ClusterConfig {
+        configuration_id: new_config_id,
+        nodes: new_nodes,
+        flexible_quorum: None,
+        ..Default::default()
+    };
+    let other_config = ClusterConfig {
+        configuration_id: new_config_id + 1,
+        ..new_config.clone()
+    };
+    let metadata = Some(vec![SS_METADATA]);
+
+    let first_node = sys.nodes.get(&1).unwrap();
+    let reconfig_f = first_node.on_definition(|x| {
+        let (kprom, kfuture) = promise::<()>();
+        assert_eq!(x.paxos.reconfiguration_status(), None);
+        let status = x
+            .paxos
+            .reconfigure(new_config.clone(), metadata.clone())
+            .expect("Failed to reconfigure");
+        assert_eq!(x.paxos.reconfiguration_status(), Some(status.clone()));
+        let repeated_status = x
+            .paxos
+            .reconfigure(new_config.clone(), metadata.clone())
+            .expect("Repeated reconfiguration failed");
+        assert_eq!(repeated_status, status);
+        x.insert_decided_future(Ask::new(kprom, Value::with_id(STOPSIGN_ID)));
+        kfuture
+    });
+
+    reconfig_f
+        .wait_timeout(cfg.wait_timeout)
+        .expect("Failed to collect reconfiguration future");
+
+    first_node.on_definition(|x| {
+        let decided = x
+            .paxos
+            .read_decided_suffix(0)
+            .expect("Failed to read decided suffix");
+        let num_stopsigns = decided
+            .iter()
+            .filter(|e| matches!(e, LogEntry::StopSign(..)))
+            .count();
+        assert_eq!(num_stopsigns, 1, "StopSign was proposed twice");
+        assert_eq!(
+            x.paxos.reconfiguration_status(),
+            Some(ReconfigurationStatus::Decided)
+        );
+        let repeated_status = x
+            .paxos
+            .reconfigure(new_config.clone(), metadata.clone())
+            .expect("Repeated reconfiguration failed");
+        assert_eq!(repeated_status, ReconfigurationStatus::Decided);
+        x.paxos
+            .reconfigure(other_config, None)
+            .expect_err("Should not be able to propose a different reconfiguration");
+    });
+
+    let kompact_system =
+        std::mem::take(&mut sys.kompact_system).expect("No KompactSystem in memory");
+    match kompact_system.shutdown() {
+        Ok(_) => {}
+        Err(e) => panic!("Error on kompact shutdown: {}", e),
+    };
+}
+
+fn refuse_shrinking(current: &ClusterConfig, ss: &StopSign) -> Result<(), String> {
+    if ss.next_config.nodes.len() < current.nodes.len() {
+        Err(SHRINK_REASON.to_string())
+    } else {
+        Ok(())
+    }
+}
+
+fn new_config(nodes: Vec<NodeId>) -> ClusterConfig {
+    ClusterConfig {
+        configuration_id: 2,
+        nodes,
+        ..Default::default()
+    }
+}
+
+/// Verifies that the proposer itself refuses a reconfiguration that fails its validator.
+#[test]
+#[serial]
+fn reconfig_rejected_by_proposer_test() {
+    let cfg = TestConfig {
+        reconfiguration_validator: Some(refuse_shrinking),
+        ..TestConfig::load("consensus_test").expect("Test config loaded")
+    };
+    let mut sys = TestSystem::with(cfg);
+    sys.start_all_nodes();
+    sys.get_elected_leader(1, cfg.wait_timeout);
+
+    let all_nodes: Vec<NodeId> = (1..=cfg.num_nodes as NodeId).collect();
+    let first_node = sys.nodes.get(&1).unwrap();
+    let reconfig_f = first_node.on_definition(|x| {
+        match x
+            .paxos
+            .reconfigure(new_config(all_nodes[1..].to_vec()), None)
+        {
+            Err(ReconfigErr::Rejected { reason, .. }) => assert_eq!(reason, SHRINK_REASON),
+            res => panic!("Unexpected reconfiguration result: {:?}", res),
+        }
+        assert_eq!(x.paxos.reconfiguration_status(), None);
+
+        let mut grown_nodes = all_nodes.clone();
+        grown_nodes.push(cfg.num_nodes as NodeId + 1);
+        let status = x
+            .paxos
+            .reconfigure(new_config(grown_nodes), None)
+            .expect("Valid reconfiguration was rejected");
+        assert_ne!(
+            status,
+            ReconfigurationStatus::Rejected(SHRINK_REASON.to_string())
+        );
+        let (kprom, kfuture) = promise::<()>();
+        x.insert_decided_future(Ask::new(kprom, Value::with_id(STOPSIGN_ID)));
+        kfuture
+    });
+    reconfig_f
+        .wait_timeout(cfg.wait_timeout)
+        .expect("Failed to collect reconfiguration future");
+
+    let kompact_system =
+        std::mem::take(&mut sys.kompact_system).expect("No KompactSystem in memory");
+    match kompact_system.shutdown() {
+        Ok(_) => {}
+        Err(e) => panic!("Error on kompact shutdown: {}", e),
+    };
+}
+
+/// Verifies that the leader refuses a forwarded reconfiguration that fails its validator and
+/// that the reason is propagated back to the proposer.
+#[test]
+#[serial]
+fn reconfig_rejected_by_leader_test() {
+    let cfg = TestConfig {
+        reconfiguration_validator: Some(refuse_shrinking),
+        ..TestConfig::load("consensus_test").expect("Test config loaded")
+    };
+    let mut sys = TestSystem::with(cfg);
+    sys.start_all_nodes();
+    let leader = sys.get_elected_leader(1, cfg.wait_timeout);
+
+    // Replace a follower with one without a validator so that it forwards the reconfiguration
+    let follower = (1..=cfg.num_nodes as NodeId)
+        .find(|pid| *pid != leader)
+        .unwrap();
+    let unvalidated_cfg = TestConfig {
+        reconfiguration_validator: None,
+        ..cfg
+    };
+    sys.kill_node(follower);
+    sys.create_node(
+        follower,
+        &unvalidated_cfg,
+        StorageType::with(StorageTypeSelector::Memory, ""),
+    );
+    sys.start_node(follower);
+    sys.nodes
+        .get(&follower)
+        .unwrap()
+        .on_definition(|x| x.paxos.reconnected(leader));
+    assert_eq!(sys.get_elected_leader(follower, cfg.wait_timeout), leader);
+
+    let all_nodes: Vec<NodeId> = (1..=cfg.num_nodes as NodeId).collect();
+    let follower_node = sys.nodes.get(&follower).unwrap();
+    let status = follower_node.on_definition(|x| {
+        x.paxos
+            .reconfigure(new_config(all_nodes[1..].to_vec()), None)
+            .expect("Failed to propose reconfiguration")
+    });
+    assert_eq!(status, ReconfigurationStatus::Proposed);
+    let rejected = ReconfigurationStatus::Rejected(SHRINK_REASON.to_string());
+    wait_until(
+        cfg.wait_timeout,
+        "The rejection was not propagated to the proposer",
+        || {
+            follower_node.on_definition(|x| x.paxos.reconfiguration_status())
+                == Some(rejected.clone())
+        },
+    );
+    for node in sys.nodes.values() {
+        assert!(
+            node.on_definition(|x| x.paxos.is_reconfigured()).is_none(),
+            "Rejected StopSign was decided"
+        );
+    }
+
+    let kompact_system =
+        std::mem::take(&mut sys.kompact_system).expect("No KompactSystem in memory");
+    match kompact_system.shutdown() {
+        Ok(_) => {}
+        Err(e) => panic!("Error on kompact shutdown: {}", e),
+    };
+}
+
+/// Verifies that a server that was configured with a different membership than its peers is
+/// reported on both sides and does not take part in the cluster.
+#[test]
+#[serial]
+fn config_mismatch_test() {
+    let cfg = TestConfig::load("config_mismatch_test").expect("Test config loaded");
+    let mut sys = TestSystem::with(cfg);
+    // the last server was accidentally configured for another cluster
+    let mismatched = cfg.num_nodes as NodeId;
+    let mismatched_cfg = TestConfig {
+        num_nodes: cfg.num_nodes + 1,
+        ..cfg
+    };
+    sys.kill_node(mismatched);
+    sys.create_node(
+        mismatched,
+        &mismatched_cfg,
+        StorageType::with(StorageTypeSelector::Memory, ""),
+    );
+    sys.start_all_nodes();
+
+    let leader = sys.get_elected_leader(1, cfg.wait_timeout);
+    assert_ne!(leader, mismatched);
+    let vec_proposals = utils::create_proposals(1, cfg.num_proposals);
+    let last = vec_proposals.last().unwrap();
+    let mut futures = vec![];
+    for (_, node) in sys
+        .nodes
+        .iter()
+        .filter(|(pid, _)| **pid != leader && **pid != mismatched)
+    {
+        let (kprom, kfuture) = promise::<()>();
+        node.on_definition(|x| x.insert_decided_future(Ask::new(kprom, last.clone())));
+        futures.push(kfuture);
+    }
+    sys.make_proposals(leader, vec_proposals, cfg.wait_timeout);
+    match FutureCollection::collect_with_timeout::<Vec<_>>(futures, cfg.wait_timeout) {
+        Ok(_) => {}
+        Err(e) => panic!("Error on collecting futures of decided proposals: {}", e),
+    }
+    let mismatched_node = sys.nodes.get(&mismatched).unwrap();
+    assert_eq!(
+        mismatched_node.on_definition(|x| x.paxos.get_decided_idx()),
+        0
+    );
+
+    for pid in (1..=cfg.num_nodes as NodeId).filter(|pid| *pid != mismatched) {
+        let mismatches = sys
+            .nodes
+            .get(&pid)
+            .unwrap()
+            .on_definition(|x| x.paxos.take_config_mismatches());
+        assert_eq!(mismatches.len(), 1, "{:?}", mismatches);
+        assert_eq!(mismatches[0].pid, mismatched);
+        assert_eq!(
+            mismatches[0].local.configuration_id,
+            mismatches[0].remote.configuration_id
+        );
+        assert_ne!(
+            mismatches[0].local.membership_hash,
+            mismatches[0].remote.membership_hash
+        );
+    }
+    let mut reported: Vec<NodeId> = mismatched_node.on_definition(|x| {
+        x.paxos
+            .take_config_mismatches()
+            .iter()
+            .map(|m| m.pid)
+            .collect()
+    });
+    reported.sort();
+    let expected: Vec<NodeId> = (1..mismatched).collect();
+    assert_eq!(reported, expected);
+
+    // a mismatch is only reported once
+    thread::sleep(4 * cfg.election_timeout);
+    for node in sys.nodes.values() {
+        assert!(node
+            .on_definition(|x| x.paxos.take_config_mismatches())
+            .is_empty());
+    }
+
+    let kompact_system =
+        std::mem::take(&mut sys.kompact_system).expect("No KompactSystem in memory");
+    match kompact_system.shutdown() {
+        Ok(_) => {}
+        Err(e) => panic!("Error on kompact shutdown: {}", e),
+    };
+}
+
+/// A follower accepts a StopSign that no other server but the crashed leader accepted, and
+/// the remaining servers move on without it. Verifies that the follower proposes the StopSign
+/// again when it restarts and learns that it was not chosen, so that the reconfiguration is
+/// decided instead of silently discarded.
+#[test]
+#[serial]
+fn stopsign_recovery_test() {
+    let cfg = TestConfig::load("stopsign_recovery_test").expect("Test config loaded");
+    let mut sys = TestSystem::with(cfg);
+    sys.start_all_nodes();
+
+    let leader = sys.get_elected_leader(1, cfg.wait_timeout);
+    let follower = (1..=cfg.num_nodes as NodeId)
+        .find(|pid| *pid != leader)
+        .unwrap();
+    let others: Vec<NodeId> = (1..=cfg.num_nodes as NodeId)
+        .filter(|pid| *pid != leader && *pid != follower)
+        .collect();
+    sys.make_proposals(leader, utils::create_proposals(1, 1), cfg.wait_timeout);
+
+    // Only the follower receives the StopSign before the leader crashes
+    let next_config = new_config(vec![1, 2, 3]);
+    sys.nodes.get(&leader).unwrap().on_definition(|x| {
+        x.blocked_peers.extend(&others);
+        x.blocked_peers.insert(follower);
+        x.paxos
+            .reconfigure(next_config.clone(), None)
+            .expect("Failed to reconfigure");
+        let msgs = x.paxos.outgoing_for(follower);
+        x.send(msgs);
+    });
+    let follower_px = sys.nodes.get(&follower).unwrap();
+    wait_until(cfg.wait_timeout, "The StopSign was not accepted", || {
+        follower_px.on_definition(|x| x.paxos.reconfiguration_status())
+            == Some(ReconfigurationStatus::Accepted)
+    });
+    sys.kill_node(leader);
+    sys.kill_node(follower);
+
+    // The remaining majority elects a new leader and decides an entry without the StopSign
+    let new_leader = sys.get_next_leader(others[0], cfg.wait_timeout);
+    sys.make_proposals(new_leader, utils::create_proposals(2, 2), cfg.wait_timeout);
+    wait_until(cfg.wait_timeout, "The entry was not decided", || {
+        sys.nodes
+            .values()
+            .all(|node| node.on_definition(|x| x.paxos.get_decided_idx()) == 2)
+    });
+
+    let storage_path = sys.temp_dir_path.clone();
+    sys.create_node(
+        follower,
+        &cfg,
+        StorageType::with(cfg.storage_type, &format!("{storage_path}{follower}")),
+    );
+    let follower_px = sys.nodes.get(&follower).unwrap();
+    assert_eq!(
+        follower_px.on_definition(|x| x.paxos.reconfiguration_status()),
+        Some(ReconfigurationStatus::Accepted)
+    );
+    sys.start_node(follower);
+    wait_until(cfg.wait_timeout, "The StopSign was not decided", || {
+        sys.nodes.values().all(|node| {
+            node.on_definition(|x| x.paxos.reconfiguration_status())
+                == Some(ReconfigurationStatus::Decided)
+        })
+    });
+    for node in sys.nodes.values() {
+        node.on_definition(|x| {
+            let ss = x.paxos.is_reconfigured().expect("StopSign is not decided");
+            assert_eq!(ss.next_config, next_config);
+            assert_eq!(x.paxos.get_decided_idx(), 3);
+        });
+    }
+
+    let kompact_system =
+        std::mem::take(&mut sys.kompact_system).expect("No KompactSystem in memory");
+    match kompact_system.shutdown() {
+        Ok(_) => {}
+        Err(e) => panic!("Error on kompact shutdown: {}", e),
+    };
+}
+
+/// Decides the proposals of `cfg`, compacts a part of the log at server 1, and reconfigures to a
+/// configuration with the new server 4 with `reconfigure`.
+#[cfg(not(feature = "no_snapshots"))]
+fn decide_and_reconfigure<F>(sys: &TestSystem, cfg: &TestConfig, reconfigure: F)
+where
+    F: FnOnce(&mut OmniPaxos<Value, StorageType<Value>>, ClusterConfig),
+{
+    let leader = sys.get_elected_leader(1, cfg.wait_timeout);
+    sys.make_proposals(
+        leader,
+        utils::create_proposals(1, cfg.num_proposals),
+        cfg.wait_timeout,
+    );
+    let num_proposals = cfg.num_proposals as usize;
+    wait_until(
+        cfg.wait_timeout,
+        "Not all servers decided the proposals",
+        || {
+            sys.nodes
+                .values()
+                .all(|node| node.on_definition(|x| x.paxos.get_decided_idx()) == num_proposals)
+        },
+    );
+    sys.nodes.get(&1).unwrap().on_definition(|x| {
+        x.paxos
+            .snapshot(Some(num_proposals / 2), true)
+            .expect("Failed to snapshot")
+    });
+    let new_config = ClusterConfig {
+        configuration_id: 2,
+        nodes: vec![1, 2, 4],
+        ..Default::default()
+    };
+    sys.nodes
+        .get(&leader)
+        .unwrap()
+        .on_definition(|x| reconfigure(&mut x.paxos, new_config));
+    wait_until(
+        cfg.wait_timeout,
+        "Not all servers were reconfigured",
+        || {
+            sys.nodes
+                .values()
+                .all(|node| node.on_definition(|x| x.paxos.is_reconfigured().is_some()))
+        },
+    );
+}
+
+/// Verifies that the final snapshot of a configuration is transferred in chunks to a server
+/// of the next configuration, and that the application's metadata is kept in the StopSign.
+#[test]
+#[serial]
+#[cfg(not(feature = "no_snapshots"))]
+fn handoff_test() {
+    let cfg = TestConfig::load("handoff_test").expect("Test config loaded");
+    let mut sys = TestSystem::with(cfg);
+    sys.start_all_nodes();
+    decide_and_reconfigure(&sys, &cfg, |op, new_config| {
+        op.reconfigure_with_handoff(new_config, Some(vec![SS_METADATA]), HANDOFF_CHUNK_SIZE)
+            .expect("Failed to reconfigure");
+    });
+    let ss = sys
+        .nodes
+        .get(&2)
+        .unwrap()
+        .on_definition(|x| x.paxos.is_reconfigured().unwrap());
+    let (handoff, metadata) = HandoffRef::decode(ss.metadata.as_deref().unwrap()).unwrap();
+    assert_eq!(handoff.chunk_size, HANDOFF_CHUNK_SIZE);
+    assert_eq!(metadata, Some(vec![SS_METADATA]));
+
+    // Servers with and without a compacted log create the same snapshot
+    let create_handoff = |pid| {
+        sys.nodes
+            .get(&pid)
+            .unwrap()
+            .on_definition(|x| x.paxos.create_handoff(&JsonCodec).unwrap())
+    };
+    let sender = create_handoff(1);
+    let manifest = sender.manifest();
+    assert_eq!(manifest, create_handoff(3).manifest());
+    assert_eq!(manifest.configuration_id, 2);
+    assert_eq!(manifest.decided_idx, cfg.num_proposals as usize);
+    let proposals = utils::create_proposals(1, cfg.num_proposals);
+    let snapshot_len = JsonCodec
+        .encode_snapshot(&ValueSnapshot::create(&proposals))
+        .unwrap()
+        .len();
+    assert_eq!(
+        manifest.num_chunks(),
+        snapshot_len.div_ceil(HANDOFF_CHUNK_SIZE) as u32
+    );
+
+    // Receives the chunks in reverse, with the first one missing and then resent
+    let mut receiver = HandoffReceiver::with(manifest);
+    for chunk in sender
+        .chunks()
+        .skip(1)
+        .collect::<Vec<_>>()
+        .into_iter()
+        .rev()
+    {
+        receiver.handle_chunk(chunk).unwrap();
+    }
+    assert_eq!(receiver.missing_chunks(), vec![0]);
+    assert!(matches!(
+        receiver.clone().finish(&JsonCodec),
+        Err(HandoffErr::Incomplete(1))
+    ));
+    receiver.handle_chunk(sender.chunk(0).unwrap()).unwrap();
+    receiver.handle_chunk(sender.chunk(1).unwrap()).unwrap();
+    assert!(receiver.is_complete());
+    let (decided_idx, snapshot) = receiver.finish(&JsonCodec).unwrap();
+    assert_eq!(decided_idx, cfg.num_proposals as usize);
+    assert_eq!(snapshot.snapshotted, proposals);
+
+    let kompact_system =
+        std::mem::take(&mut sys.kompact_system).expect("No KompactSystem in memory");
+    match kompact_system.shutdown() {
+        Ok(_) => {}
+        Err(e) => panic!("Error on kompact shutdown: {}", e),
+    };
+}
+
+/// Verifies that a handoff is refused before the reconfiguration, for a reconfiguration that
+/// did not request one, and that invalid or corrupted chunks are detected.
+#[test]
+#[serial]
+#[cfg(not(feature = "no_snapshots"))]
+fn invalid_handoff_test() {
+    let cfg = TestConfig::load("handoff_test").expect("Test config loaded");
+    let mut sys = TestSystem::with(cfg);
+    sys.start_all_nodes();
+    let first_node = sys.nodes.get(&1).unwrap();
+    assert!(matches!(
+        first_node.on_definition(|x| x.paxos.create_handoff(&JsonCodec)),
+        Err(HandoffErr::NotReconfigured)
+    ));
+    decide_and_reconfigure(&sys, &cfg, |op, new_config| {
+        op.reconfigure(new_config, None)
+            .expect("Failed to reconfigure");
+    });
+    assert!(matches!(
+        first_node.on_definition(|x| x.paxos.create_handoff(&JsonCodec)),
+        Err(HandoffErr::NotRequested)
+    ));
+    let kompact_system =
+        std::mem::take(&mut sys.kompact_system).expect("No KompactSystem in memory");
+    match kompact_system.shutdown() {
+        Ok(_) => {}
+        Err(e) => panic!("Error on kompact shutdown: {}", e),
+    };
+
+    let mut sys = TestSystem::with(cfg);
+    sys.start_all_nodes();
+    decide_and_reconfigure(&sys, &cfg, |op, new_config| {
+        op.reconfigure_with_handoff(new_config, None, HANDOFF_CHUNK_SIZE)
+            .expect("Failed to reconfigure");
+    });
+    let sender = sys
+        .nodes
+        .get(&1)
+        .unwrap()
+        .on_definition(|x| x.paxos.create_handoff(&JsonCodec).unwrap());
+    let mut receiver = HandoffReceiver::with(sender.manifest());
+    let mut chunk = sender.chunk(0).unwrap();
+    chunk.configuration_id = 3;
+    assert!(matches!(
+        receiver.handle_chunk(chunk),
+        Err(HandoffErr::WrongConfiguration {
+            expected: 2,
+            found: 3
+        })
+    ));
+    let mut chunk = sender.chunk(0).unwrap();
+    chunk.data.pop();
+    assert!(matches!(
+        receiver.handle_chunk(chunk),
+        Err(HandoffErr::InvalidChunk(0))
+    ));
+    for mut chunk in sender.chunks() {
+        if chunk.index == 0 {
+            chunk.data[0] ^= 1;
+        }
+        receiver.handle_chunk(chunk).unwrap();
+    }
+    assert!(matches!(
+        receiver.finish(&JsonCodec),
+        Err(HandoffErr::DigestMismatch)
+    ));
+
+    let kompact_system =
+        std::mem::take(&mut sys.kompact_system).expect("No KompactSystem in memory");
+    match kompact_system.shutdown() {
+        Ok(_) => {}
+        Err(e) => panic!("Error on kompact shutdown: {}", e),
+    };
+}
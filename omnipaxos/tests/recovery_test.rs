@@ -1,12 +1,31 @@
 pub mod utils;
 
 use kompact::prelude::{promise, Ask, FutureCollection, KFuture};
-use omnipaxos::util::{LogEntry, NodeId};
+use omnipaxos::{
+    ballot_leader_election::Ballot,
+    errors::{ImportErr, StorageMigrationErr},
+    storage::{
+        migration::{import_raft_state, RaftEntry, RaftEntryMapping, RaftSnapshot, RaftState},
+        LogStorage, Snapshot, StateStorage, StopSign,
+    },
+    util::{LogEntry, NodeId},
+    BootstrapErr, ClusterConfig, StartupReconciliation,
+};
+#[cfg(feature = "operator_tools")]
+use omnipaxos::{
+    messages::{sequence_paxos::PaxosMsg, Message},
+    TruncationErr,
+};
+use omnipaxos_storage::memory_storage::MemoryStorage;
 use serial_test::serial;
-use std::{thread, time::Duration};
-use utils::{verification::verify_log, StorageType, TestConfig, TestSystem, Value};
+use std::{collections::HashMap, error::Error, thread, time::Duration};
+use utils::{
+    create_temp_dir, verification::verify_log, wait_until, StorageType, StorageTypeSelector,
+    TestConfig, TestSystem, Value, ValueSnapshot,
+};
 
 const SLEEP_TIMEOUT: Duration = Duration::from_secs(1);
+const USED_ROUND: u32 = 5;
 
 #[test]
 #[serial]
@@ -208,3 +227,862 @@ pub fn kill_and_recover_node(sys: &mut TestSystem, cfg: &TestConfig, pid: NodeId
     sys.create_node(pid, cfg, storage);
     sys.start_node(pid);
 }
+
+/// Verifies that a follower that is asked to recover re-requests its state from the leader,
+/// reports its progress and signals the completion exactly once.
+#[test]
+#[serial]
+fn fail_recover_follower_test() {
+    let cfg = TestConfig::load("fail_recover_test").expect("Test config loaded");
+    let mut sys = TestSystem::with(cfg);
+    sys.start_all_nodes();
+
+    let leader = sys.get_elected_leader(1, cfg.wait_timeout);
+    let follower = (1..=cfg.num_nodes as NodeId)
+        .find(|pid| *pid != leader)
+        .unwrap();
+    let follower_px = sys.nodes.get(&follower).unwrap();
+    let proposals = utils::create_proposals(1, cfg.num_proposals);
+    let futures: Vec<KFuture<()>> = proposals
+        .iter()
+        .map(|v| {
+            let (kprom, kfuture) = promise::<()>();
+            follower_px.on_definition(|x| x.insert_decided_future(Ask::new(kprom, v.clone())));
+            kfuture
+        })
+        .collect();
+    sys.make_proposals(leader, proposals.clone(), cfg.wait_timeout);
+    match FutureCollection::collect_with_timeout::<Vec<_>>(futures, cfg.wait_timeout) {
+        Ok(_) => {}
+        Err(e) => panic!("Error on collecting futures of decided proposals: {}", e),
+    }
+
+    follower_px.on_definition(|x| {
+        assert_eq!(x.paxos.recovery_progress(), None);
+        x.paxos.fail_recover();
+        let progress = x.paxos.recovery_progress().expect("recovery did not start");
+        assert_eq!(progress.start_idx, proposals.len());
+        assert!(!progress.completed);
+        assert_eq!(x.paxos.take_completed_recovery(), None);
+    });
+    wait_until(cfg.wait_timeout, "recovery did not complete", || {
+        follower_px.on_definition(|x| x.paxos.recovery_progress().is_some_and(|p| p.completed))
+    });
+    follower_px.on_definition(|x| {
+        let completed = x
+            .paxos
+            .take_completed_recovery()
+            .expect("completion not reported");
+        assert_eq!(completed.source, Some(leader));
+        assert_eq!(x.paxos.take_completed_recovery(), None);
+        assert_eq!(x.paxos.recovery_progress(), None);
+    });
+    verify_log(
+        follower_px.on_definition(|x| x.read_decided_log()),
+        proposals,
+    );
+
+    // Failing to recover the leader has no effect
+    sys.nodes.get(&leader).unwrap().on_definition(|x| {
+        x.paxos.fail_recover();
+        assert_eq!(x.paxos.recovery_progress(), None);
+    });
+
+    let kompact_system =
+        std::mem::take(&mut sys.kompact_system).expect("No KompactSystem in memory");
+    match kompact_system.shutdown() {
+        Ok(_) => {}
+        Err(e) => panic!("Error on kompact shutdown: {}", e),
+    };
+}
+
+/// Verifies that a server restarting from its storage reports the entries it restores from the
+/// leader.
+#[test]
+#[serial]
+fn restart_recovery_progress_test() {
+    let cfg = TestConfig::load("fail_recover_test").expect("Test config loaded");
+    let mut sys = TestSystem::with(cfg);
+    sys.start_all_nodes();
+
+    let leader = sys.get_elected_leader(1, cfg.wait_timeout);
+    let lagging = (1..=cfg.num_nodes as NodeId)
+        .find(|pid| *pid != leader)
+        .unwrap();
+    let proposals = utils::create_proposals(1, cfg.num_proposals);
+    sys.set_node_connections(lagging, false);
+    sys.make_proposals(leader, proposals.clone(), cfg.wait_timeout);
+
+    // Restart the lagging server with a stored promise but an empty log
+    let mut storage = MemoryStorage::default();
+    storage
+        .set_promise(
+            sys.nodes
+                .get(&leader)
+                .unwrap()
+                .on_definition(|x| x.paxos.get_promise()),
+        )
+        .unwrap();
+    sys.kill_node(lagging);
+    sys.create_node(lagging, &cfg, StorageType::with_memory(storage));
+    sys.set_node_connections(lagging, true);
+    let recover_px = sys.nodes.get(&lagging).unwrap();
+    let progress = recover_px
+        .on_definition(|x| x.paxos.recovery_progress())
+        .expect("recovery did not start");
+    assert_eq!(progress.start_idx, 0);
+    sys.start_node(lagging);
+
+    wait_until(cfg.wait_timeout, "recovery did not complete", || {
+        recover_px.on_definition(|x| x.paxos.recovery_progress().is_some_and(|p| p.completed))
+    });
+    let completed = recover_px
+        .on_definition(|x| x.paxos.take_completed_recovery())
+        .expect("completion not reported");
+    assert_eq!(completed.source, Some(leader));
+    assert_eq!(completed.entries_restored, proposals.len());
+    wait_until(cfg.wait_timeout, "recovered log was not decided", || {
+        recover_px.on_definition(|x| x.paxos.get_decided_idx()) == proposals.len()
+    });
+    verify_log(
+        recover_px.on_definition(|x| x.read_decided_log()),
+        proposals,
+    );
+
+    let kompact_system =
+        std::mem::take(&mut sys.kompact_system).expect("No KompactSystem in memory");
+    match kompact_system.shutdown() {
+        Ok(_) => {}
+        Err(e) => panic!("Error on kompact shutdown: {}", e),
+    };
+}
+
+/// Verifies that a follower can discard its undecided suffix and recovers it from the leader,
+/// and that the truncation is refused where it would discard decided entries.
+#[cfg(feature = "operator_tools")]
+#[test]
+#[serial]
+fn unsafe_truncate_after_test() {
+    let cfg = TestConfig::load("fail_recover_test").expect("Test config loaded");
+    let mut sys = TestSystem::with(cfg);
+    sys.start_all_nodes();
+
+    let leader = sys.get_elected_leader(1, cfg.wait_timeout);
+    let follower = (1..=cfg.num_nodes as NodeId)
+        .find(|pid| *pid != leader)
+        .unwrap();
+    let leader_px = sys.nodes.get(&leader).unwrap();
+    let follower_px = sys.nodes.get(&follower).unwrap();
+    let proposals = utils::create_proposals(1, cfg.num_proposals);
+    let num_decided = proposals.len() / 2;
+    let (decided, undecided) = proposals.split_at(num_decided);
+    let futures: Vec<KFuture<()>> = decided
+        .iter()
+        .map(|v| {
+            let (kprom, kfuture) = promise::<()>();
+            follower_px.on_definition(|x| x.insert_decided_future(Ask::new(kprom, v.clone())));
+            kfuture
+        })
+        .collect();
+    sys.make_proposals(leader, decided.to_vec(), cfg.wait_timeout);
+    match FutureCollection::collect_with_timeout::<Vec<_>>(futures, cfg.wait_timeout) {
+        Ok(_) => {}
+        Err(e) => panic!("Error on collecting futures of decided proposals: {}", e),
+    }
+
+    leader_px.on_definition(|x| {
+        assert_eq!(
+            x.paxos.unsafe_truncate_after(num_decided),
+            Err(TruncationErr::CurrentLeader(leader))
+        )
+    });
+    follower_px.on_definition(|x| {
+        assert_eq!(
+            x.paxos.unsafe_truncate_after(num_decided - 1),
+            Err(TruncationErr::DecidedIndex(num_decided))
+        );
+        assert_eq!(
+            x.paxos.unsafe_truncate_after(num_decided + 1),
+            Err(TruncationErr::OutOfBounds(num_decided))
+        );
+    });
+
+    // The follower accepts the new entries but does not learn that they are decided yet
+    leader_px.on_definition(|x| {
+        x.drop_outgoing = Some(
+            |msg| matches!(msg, Message::SequencePaxos(m) if matches!(m.msg, PaxosMsg::Decide(_))),
+        )
+    });
+    sys.make_proposals(leader, undecided.to_vec(), cfg.wait_timeout);
+    wait_until(
+        cfg.wait_timeout,
+        "follower did not accept the entries",
+        || follower_px.on_definition(|x| x.paxos.read(proposals.len() - 1).is_some()),
+    );
+    let futures: Vec<KFuture<()>> = undecided
+        .iter()
+        .map(|v| {
+            let (kprom, kfuture) = promise::<()>();
+            follower_px.on_definition(|x| x.insert_decided_future(Ask::new(kprom, v.clone())));
+            kfuture
+        })
+        .collect();
+    follower_px.on_definition(|x| {
+        assert_eq!(x.paxos.get_decided_idx(), num_decided);
+        assert_eq!(
+            x.paxos.read(num_decided),
+            Some(LogEntry::Undecided(undecided[0].clone()))
+        );
+        x.paxos
+            .unsafe_truncate_after(num_decided)
+            .expect("failed to truncate");
+        assert_eq!(x.paxos.read(num_decided), None);
+        assert!(x.paxos.recovery_progress().is_some());
+    });
+    leader_px.on_definition(|x| x.drop_outgoing = None);
+
+    match FutureCollection::collect_with_timeout::<Vec<_>>(futures, cfg.wait_timeout) {
+        Ok(_) => {}
+        Err(e) => panic!("The follower did not recover the truncated entries: {}", e),
+    }
+    verify_log(
+        follower_px.on_definition(|x| x.read_decided_log()),
+        proposals,
+    );
+
+    let kompact_system =
+        std::mem::take(&mut sys.kompact_system).expect("No KompactSystem in memory");
+    match kompact_system.shutdown() {
+        Ok(_) => {}
+        Err(e) => panic!("Error on kompact shutdown: {}", e),
+    };
+}
+
+/// Verifies that a wiped server initializes its storage from a healthy follower, then recovers
+/// from the leader, and that bootstrapping is refused for servers that are not wiped.
+#[test]
+#[serial]
+fn bootstrap_from_peer_test() {
+    let cfg = TestConfig::load("fail_recover_test").expect("Test config loaded");
+    let mut sys = TestSystem::with(cfg);
+    sys.start_all_nodes();
+
+    let leader = sys.get_elected_leader(1, cfg.wait_timeout);
+    let mut followers = (1..=cfg.num_nodes as NodeId).filter(|pid| *pid != leader);
+    let (wiped, healthy) = (followers.next().unwrap(), followers.next().unwrap());
+    let proposals = utils::create_proposals(1, cfg.num_proposals);
+    let futures: Vec<KFuture<()>> = proposals
+        .iter()
+        .map(|v| {
+            let (kprom, kfuture) = promise::<()>();
+            sys.nodes
+                .get(&healthy)
+                .unwrap()
+                .on_definition(|x| x.insert_decided_future(Ask::new(kprom, v.clone())));
+            kfuture
+        })
+        .collect();
+    sys.make_proposals(leader, proposals.clone(), cfg.wait_timeout);
+    match FutureCollection::collect_with_timeout::<Vec<_>>(futures, cfg.wait_timeout) {
+        Ok(_) => {}
+        Err(e) => panic!("Error on collecting futures of decided proposals: {}", e),
+    }
+    sys.nodes.get(&healthy).unwrap().on_definition(|x| {
+        assert_eq!(
+            x.paxos.bootstrap_from_peer(wiped),
+            Err(BootstrapErr::NotWiped)
+        )
+    });
+
+    sys.kill_node(wiped);
+    sys.create_node(
+        wiped,
+        &cfg,
+        StorageType::with_memory(MemoryStorage::default()),
+    );
+    let unknown = cfg.num_nodes as NodeId + 1;
+    let wiped_px = sys.nodes.get(&wiped).unwrap();
+    wiped_px.on_definition(|x| {
+        assert_eq!(
+            x.paxos.bootstrap_from_peer(unknown),
+            Err(BootstrapErr::UnknownPeer(unknown))
+        );
+        x.paxos
+            .bootstrap_from_peer(healthy)
+            .expect("failed to start bootstrap");
+    });
+
+    // Only the healthy follower can reach the wiped server
+    sys.set_node_connections(leader, false);
+    sys.start_node(wiped);
+    wait_until(
+        cfg.wait_timeout,
+        "the wiped server was not bootstrapped",
+        || wiped_px.on_definition(|x| x.paxos.get_decided_idx()) == proposals.len(),
+    );
+    let progress = wiped_px
+        .on_definition(|x| x.paxos.recovery_progress())
+        .expect("recovery did not start");
+    assert_eq!(progress.source, Some(healthy));
+    assert_eq!(progress.entries_restored, proposals.len());
+
+    sys.set_node_connections(leader, true);
+    wait_until(cfg.wait_timeout, "recovery did not complete", || {
+        wiped_px.on_definition(|x| x.paxos.recovery_progress().is_some_and(|p| p.completed))
+    });
+    verify_log(wiped_px.on_definition(|x| x.read_decided_log()), proposals);
+    assert_eq!(
+        wiped_px.on_definition(|x| x.paxos.get_promise()),
+        sys.nodes
+            .get(&healthy)
+            .unwrap()
+            .on_definition(|x| x.paxos.get_promise())
+    );
+
+    let kompact_system =
+        std::mem::take(&mut sys.kompact_system).expect("No KompactSystem in memory");
+    match kompact_system.shutdown() {
+        Ok(_) => {}
+        Err(e) => panic!("Error on kompact shutdown: {}", e),
+    };
+}
+
+/// Maps Raft entries that contain the id of a value to `Value`, and Raft snapshots that contain
+/// the ids of the snapshotted values to `ValueSnapshot`.
+struct ValueMapping;
+
+impl RaftEntryMapping<Value> for ValueMapping {
+    type RaftEntry = u64;
+    type RaftSnapshot = Vec<u64>;
+
+    fn map_entry(&mut self, entry: RaftEntry<u64>) -> Result<Value, Box<dyn Error>> {
+        Ok(Value::with_id(entry.data))
+    }
+
+    fn map_snapshot(
+        &mut self,
+        snapshot: RaftSnapshot<Vec<u64>>,
+    ) -> Result<ValueSnapshot, Box<dyn Error>> {
+        let values: Vec<Value> = snapshot.data.into_iter().map(Value::with_id).collect();
+        Ok(ValueSnapshot::create(&values))
+    }
+}
+
+/// Raft entries with the given terms, starting at `from_index`. The id of each entry is its
+/// index, or its index plus 100 if it is `stale`.
+fn raft_entries(from_index: u64, terms: &[u64], stale: bool) -> Vec<RaftEntry<u64>> {
+    (from_index..)
+        .zip(terms)
+        .map(|(index, term)| RaftEntry {
+            index,
+            term: *term,
+            data: if stale { index + 100 } else { index },
+        })
+        .collect()
+}
+
+/// The states of a Raft cluster after the leader of term 2, server 1, crashed. Server 2
+/// compacted its log, and server 3 has an uncommitted entry of term 1 that was never
+/// replicated to the others.
+fn raft_states() -> HashMap<NodeId, RaftState<u64, Vec<u64>>> {
+    let leader = RaftState {
+        term: 2,
+        commit: 5,
+        snapshot: None,
+        entries: raft_entries(1, &[1, 1, 1, 2, 2, 2], false),
+    };
+    let compacted = RaftState {
+        term: 2,
+        commit: 4,
+        snapshot: Some(RaftSnapshot {
+            index: 3,
+            term: 1,
+            data: vec![1, 2, 3],
+        }),
+        entries: raft_entries(4, &[2, 2], false),
+    };
+    let mut entries = raft_entries(1, &[1, 1, 1], false);
+    entries.append(&mut raft_entries(4, &[1], true));
+    let lagging = RaftState {
+        term: 2,
+        commit: 3,
+        snapshot: None,
+        entries,
+    };
+    HashMap::from([(1, leader), (2, compacted), (3, lagging)])
+}
+
+/// Verifies that a cluster imported from Raft keeps every entry committed by Raft, drops the
+/// conflicting uncommitted entries, and continues to decide new entries.
+#[test]
+#[serial]
+fn raft_import_test() {
+    let cfg = TestConfig::load("raft_import_test").expect("Test config loaded");
+    let mut sys = TestSystem::with(cfg);
+    for (pid, state) in raft_states() {
+        let mut storage = MemoryStorage::default();
+        import_raft_state(state, &mut ValueMapping, cfg.configuration_id, &mut storage)
+            .expect("Failed to import the Raft state");
+        sys.kill_node(pid);
+        sys.create_node(pid, &cfg, StorageType::with_memory(storage));
+    }
+    let decided_idx = |pid| {
+        sys.nodes
+            .get(&pid)
+            .unwrap()
+            .on_definition(|x| x.paxos.get_decided_idx())
+    };
+    assert_eq!(decided_idx(1), 5);
+    assert_eq!(decided_idx(2), 4);
+    assert_eq!(decided_idx(3), 3);
+    sys.nodes
+        .get(&2)
+        .unwrap()
+        .on_definition(|x| match x.paxos.read(0) {
+            Some(LogEntry::Snapshotted(s)) => assert_eq!(
+                s.snapshot.snapshotted,
+                (1..=3).map(Value::with_id).collect::<Vec<_>>()
+            ),
+            e => panic!("Unexpected entry: {:?}", e),
+        });
+
+    sys.start_all_nodes();
+    wait_until(cfg.wait_timeout, "The imported log was not decided", || {
+        sys.nodes.values().all(|node| {
+            node.on_definition(|x| {
+                x.paxos.get_current_leader().is_some() && x.paxos.get_decided_idx() >= 5
+            })
+        })
+    });
+    let leader = sys.get_elected_leader(1, cfg.wait_timeout);
+    let new_value = Value::with_id(0);
+    sys.make_proposals(leader, vec![new_value.clone()], cfg.wait_timeout);
+    wait_until(cfg.wait_timeout, "The new value was not decided", || {
+        sys.nodes.values().all(|node| {
+            node.on_definition(|x| {
+                let last_idx = x.paxos.get_decided_idx() - 1;
+                x.paxos.read(last_idx) == Some(LogEntry::Decided(new_value.clone()))
+            })
+        })
+    });
+    // The uncommitted entry of term 2 is only kept if the leader recovered the log of server 1,
+    // while the one of server 3 is always replaced.
+    let decided_idx = decided_idx(1);
+    assert!(decided_idx == 6 || decided_idx == 7, "{}", decided_idx);
+    let mut expected: Vec<Value> = (1..decided_idx as u64).map(Value::with_id).collect();
+    expected.push(new_value);
+    for node in sys.nodes.values() {
+        verify_log(
+            node.on_definition(|x| x.read_decided_log()),
+            expected.clone(),
+        );
+    }
+
+    let kompact_system =
+        std::mem::take(&mut sys.kompact_system).expect("No KompactSystem in memory");
+    match kompact_system.shutdown() {
+        Ok(_) => {}
+        Err(e) => panic!("Error on kompact shutdown: {}", e),
+    };
+}
+
+/// Verifies that inconsistent Raft states are rejected before anything is written.
+#[test]
+#[serial]
+fn invalid_raft_state_test() {
+    let cfg = TestConfig::load("raft_import_test").expect("Test config loaded");
+    let mut gap = raft_entries(1, &[1, 1], false);
+    gap.append(&mut raft_entries(4, &[1], false));
+    let invalid = [
+        RaftState {
+            term: 1,
+            commit: 1,
+            snapshot: None,
+            entries: gap,
+        },
+        RaftState {
+            term: 2,
+            commit: 1,
+            snapshot: None,
+            entries: raft_entries(1, &[2, 1], false),
+        },
+        RaftState {
+            term: 1,
+            commit: 3,
+            snapshot: None,
+            entries: raft_entries(1, &[1, 1], false),
+        },
+        RaftState {
+            term: 1,
+            commit: 1,
+            snapshot: None,
+            entries: raft_entries(1, &[1, 2], false),
+        },
+    ];
+    for state in invalid {
+        let mut storage = MemoryStorage::default();
+        let result =
+            import_raft_state(state, &mut ValueMapping, cfg.configuration_id, &mut storage);
+        assert!(
+            matches!(result, Err(ImportErr::InvalidLog(_))),
+            "{:?}",
+            result
+        );
+        let op = cfg
+            .into_omnipaxos_config(1)
+            .build(StorageType::with_memory(storage))
+            .expect("Failed to build OmniPaxos");
+        assert_eq!(op.get_decided_idx(), 0);
+        assert_eq!(op.read(0), None);
+    }
+}
+
+/// Verifies that the leader and a follower can switch to a persistent storage while the
+/// cluster keeps deciding entries, and that the new storage holds the whole log.
+#[test]
+#[serial]
+fn swap_storage_test() {
+    let cfg = TestConfig::load("storage_migration_test").expect("Test config loaded");
+    let mut sys = TestSystem::with(cfg);
+    sys.start_all_nodes();
+
+    let leader = sys.get_elected_leader(1, cfg.wait_timeout);
+    let follower = (1..=cfg.num_nodes as NodeId)
+        .find(|pid| *pid != leader)
+        .unwrap();
+    let num_proposals = cfg.num_proposals as usize;
+    let vec_proposals = utils::create_proposals(1, 2 * cfg.num_proposals);
+    sys.make_proposals(
+        leader,
+        vec_proposals[..num_proposals].to_vec(),
+        cfg.wait_timeout,
+    );
+    wait_until(cfg.wait_timeout, "The proposals were not decided", || {
+        sys.nodes
+            .values()
+            .all(|node| node.on_definition(|x| x.paxos.get_decided_idx()) == num_proposals)
+    });
+    for pid in [follower, leader] {
+        sys.nodes.get(&pid).unwrap().on_definition(|x| {
+            // Entries that are not decided yet are also migrated
+            if pid == leader {
+                x.paxos
+                    .append(vec_proposals[num_proposals].clone())
+                    .expect("Failed to append");
+            }
+            let storage = StorageType::with(StorageTypeSelector::Persistent, &create_temp_dir());
+            let previous = x
+                .paxos
+                .swap_storage(storage)
+                .expect("Failed to swap the storage");
+            assert_eq!(previous.get_decided_idx().unwrap(), num_proposals);
+        });
+    }
+    sys.make_proposals(
+        leader,
+        vec_proposals[num_proposals + 1..].to_vec(),
+        cfg.wait_timeout,
+    );
+    let follower_node = sys.nodes.get(&follower).unwrap();
+    wait_until(
+        cfg.wait_timeout,
+        "The follower did not decide the log",
+        || follower_node.on_definition(|x| x.paxos.get_decided_idx()) == vec_proposals.len(),
+    );
+    for pid in [leader, follower] {
+        let log = sys
+            .nodes
+            .get(&pid)
+            .unwrap()
+            .on_definition(|x| x.read_decided_log());
+        verify_log(log, vec_proposals.clone());
+    }
+
+    let kompact_system =
+        std::mem::take(&mut sys.kompact_system).expect("No KompactSystem found in memory");
+    match kompact_system.shutdown() {
+        Ok(_) => {}
+        Err(e) => panic!("Error on kompact shutdown: {}", e),
+    };
+}
+
+/// Verifies that a server keeps its storage if the copy differs from it.
+#[test]
+#[serial]
+fn swap_storage_mismatch_test() {
+    let cfg = TestConfig::load("storage_migration_test").expect("Test config loaded");
+    let mut sys = TestSystem::with(cfg);
+    sys.start_all_nodes();
+
+    let leader = sys.get_elected_leader(1, cfg.wait_timeout);
+    let vec_proposals = utils::create_proposals(1, 2 * cfg.num_proposals);
+    let (first_proposals, last_proposals) = vec_proposals.split_at(cfg.num_proposals as usize);
+    sys.make_proposals(leader, first_proposals.to_vec(), cfg.wait_timeout);
+    // A storage that is not empty does not hold a copy of the state afterwards
+    let mut storage = MemoryStorage::default();
+    let ss = StopSign::with(ClusterConfig::default(), None);
+    storage.set_stopsign(Some(ss)).unwrap();
+    let leader_node = sys.nodes.get(&leader).unwrap();
+    leader_node.on_definition(|x| {
+        let result = x.paxos.swap_storage(StorageType::with_memory(storage));
+        assert!(matches!(result, Err(StorageMigrationErr::Mismatch(_))));
+    });
+    sys.make_proposals(leader, last_proposals.to_vec(), cfg.wait_timeout);
+    verify_log(
+        leader_node.on_definition(|x| x.read_decided_log()),
+        vec_proposals,
+    );
+
+    let kompact_system =
+        std::mem::take(&mut sys.kompact_system).expect("No KompactSystem found in memory");
+    match kompact_system.shutdown() {
+        Ok(_) => {}
+        Err(e) => panic!("Error on kompact shutdown: {}", e),
+    };
+}
+
+/// Replaces the server `pid` of `sys` with a new one that starts from `storage`.
+fn restart_with_storage(
+    sys: &mut TestSystem,
+    cfg: &TestConfig,
+    pid: NodeId,
+    storage: MemoryStorage<Value>,
+) {
+    sys.kill_node(pid);
+    sys.create_node(pid, cfg, StorageType::with_memory(storage));
+}
+
+/// Verifies that a server without any persisted state reports a fresh start, only once.
+#[test]
+#[serial]
+fn fresh_start_test() {
+    let cfg = TestConfig::load("startup_reconciliation_test").expect("Test config loaded");
+    let mut sys = TestSystem::with(cfg);
+    sys.nodes.get(&1).unwrap().on_definition(|x| {
+        assert_eq!(
+            x.paxos.take_startup_reconciliation(),
+            Some(StartupReconciliation::Fresh)
+        );
+        assert_eq!(x.paxos.take_startup_reconciliation(), None);
+        assert!(x.paxos.recovery_progress().is_none());
+    });
+
+    let kompact_system =
+        std::mem::take(&mut sys.kompact_system).expect("No KompactSystem in memory");
+    match kompact_system.shutdown() {
+        Ok(_) => {}
+        Err(e) => panic!("Error on kompact shutdown: {}", e),
+    };
+}
+
+/// Verifies that a server that recovers the promise of another server follows it as the
+/// leader and recovers its log.
+#[test]
+#[serial]
+fn recovered_promise_test() {
+    let cfg = TestConfig::load("startup_reconciliation_test").expect("Test config loaded");
+    let mut sys = TestSystem::with(cfg);
+    let promise = Ballot::with(cfg.configuration_id, USED_ROUND, 0, 2);
+    let mut storage = MemoryStorage::default();
+    storage.set_promise(promise).unwrap();
+    restart_with_storage(&mut sys, &cfg, 1, storage);
+    sys.nodes.get(&1).unwrap().on_definition(|x| {
+        assert_eq!(
+            x.paxos.take_startup_reconciliation(),
+            Some(StartupReconciliation::Recover { promise })
+        );
+        assert_eq!(x.paxos.get_current_leader(), Some(2));
+        assert!(x.paxos.recovery_progress().is_some());
+    });
+
+    let kompact_system =
+        std::mem::take(&mut sys.kompact_system).expect("No KompactSystem in memory");
+    match kompact_system.shutdown() {
+        Ok(_) => {}
+        Err(e) => panic!("Error on kompact shutdown: {}", e),
+    };
+}
+
+/// Verifies that a leader that restarts together with its followers gives up its old
+/// leadership, and that the cluster elects a leader in a higher round.
+#[test]
+#[serial]
+fn recovered_own_leadership_test() {
+    let cfg = TestConfig::load("startup_reconciliation_test").expect("Test config loaded");
+    let mut sys = TestSystem::with(cfg);
+    let promise = Ballot::with(cfg.configuration_id, USED_ROUND, 0, 1);
+    for pid in 1..=cfg.num_nodes as NodeId {
+        let mut storage = MemoryStorage::default();
+        storage.set_promise(promise).unwrap();
+        restart_with_storage(&mut sys, &cfg, pid, storage);
+        let expected = if pid == 1 {
+            StartupReconciliation::NewElection { promise }
+        } else {
+            StartupReconciliation::Recover { promise }
+        };
+        sys.nodes.get(&pid).unwrap().on_definition(|x| {
+            assert_eq!(x.paxos.take_startup_reconciliation(), Some(expected));
+        });
+    }
+
+    sys.start_all_nodes();
+    wait_until(
+        cfg.wait_timeout,
+        "No leader was elected in a new round",
+        || {
+            let promises: Vec<_> = sys
+                .nodes
+                .values()
+                .map(|node| node.on_definition(|x| x.paxos.get_promise()))
+                .collect();
+            promises[0] > promise && promises.iter().all(|p| *p == promises[0])
+        },
+    );
+    sys.get_elected_leader(1, cfg.wait_timeout);
+
+    let kompact_system =
+        std::mem::take(&mut sys.kompact_system).expect("No KompactSystem in memory");
+    match kompact_system.shutdown() {
+        Ok(_) => {}
+        Err(e) => panic!("Error on kompact shutdown: {}", e),
+    };
+}
+
+/// Verifies that a server whose promise is older than its accepted round raises the promise
+/// to the accepted round and is forced to recover, and that a server that has accepted entries
+/// but lost its promise is forced to recover instead of starting fresh.
+#[test]
+#[serial]
+fn forced_recovery_test() {
+    let cfg = TestConfig::load("startup_reconciliation_test").expect("Test config loaded");
+    let mut sys = TestSystem::with(cfg);
+    let recovered_promise = Ballot::with(cfg.configuration_id, 2, 0, 2);
+    let accepted_round = Ballot::with(cfg.configuration_id, USED_ROUND, 0, 3);
+    let mut storage = MemoryStorage::default();
+    storage.set_promise(recovered_promise).unwrap();
+    storage.set_accepted_round(accepted_round).unwrap();
+    storage.append_entry(Value::with_id(1)).unwrap();
+    restart_with_storage(&mut sys, &cfg, 1, storage);
+    sys.nodes.get(&1).unwrap().on_definition(|x| {
+        assert_eq!(
+            x.paxos.take_startup_reconciliation(),
+            Some(StartupReconciliation::ForcedRecovery {
+                recovered_promise: Some(recovered_promise),
+                accepted_round,
+                new_election: false,
+            })
+        );
+        assert_eq!(x.paxos.get_promise(), accepted_round);
+        assert_eq!(x.paxos.get_current_leader(), Some(3));
+        assert!(x.paxos.recovery_progress().is_some());
+    });
+
+    let mut storage = MemoryStorage::default();
+    storage.append_entry(Value::with_id(1)).unwrap();
+    restart_with_storage(&mut sys, &cfg, 2, storage);
+    sys.nodes.get(&2).unwrap().on_definition(|x| {
+        assert_eq!(
+            x.paxos.take_startup_reconciliation(),
+            Some(StartupReconciliation::ForcedRecovery {
+                recovered_promise: None,
+                accepted_round: Ballot::default(),
+                new_election: false,
+            })
+        );
+        assert!(x.paxos.recovery_progress().is_some());
+    });
+
+    let kompact_system =
+        std::mem::take(&mut sys.kompact_system).expect("No KompactSystem in memory");
+    match kompact_system.shutdown() {
+        Ok(_) => {}
+        Err(e) => panic!("Error on kompact shutdown: {}", e),
+    };
+}
+
+/// Verifies that a wiped server neither promises nor accepts anything until a quorum of the
+/// other servers has reported its state, and then recovers the log and the promise.
+#[test]
+#[serial]
+fn recover_seamlessly_test() {
+    let cfg = TestConfig::load("recover_seamlessly_test").expect("Test config loaded");
+    // The only other server of a pair is not a majority on its own
+    let pair_cfg = TestConfig {
+        num_nodes: 2,
+        ..cfg
+    };
+    let mut op = pair_cfg
+        .into_omnipaxos_config(1)
+        .build(StorageType::<Value>::with_memory(MemoryStorage::default()))
+        .expect("Failed to build OmniPaxos");
+    assert_eq!(op.recover_seamlessly(), Err(BootstrapErr::NoQuorum));
+
+    let mut sys = TestSystem::with(cfg);
+    sys.start_all_nodes();
+    let leader = sys.get_elected_leader(1, cfg.wait_timeout);
+    let proposals = utils::create_proposals(1, cfg.num_proposals);
+    sys.make_proposals(leader, proposals.clone(), cfg.wait_timeout);
+    wait_until(cfg.wait_timeout, "The proposals were not decided", || {
+        sys.nodes
+            .values()
+            .all(|node| node.on_definition(|x| x.paxos.get_decided_idx()) == proposals.len())
+    });
+    let wiped = (1..=cfg.num_nodes as NodeId)
+        .find(|pid| *pid != leader)
+        .unwrap();
+    sys.nodes.get(&leader).unwrap().on_definition(|x| {
+        assert_eq!(x.paxos.recover_seamlessly(), Err(BootstrapErr::NotWiped));
+    });
+
+    sys.kill_node(wiped);
+    sys.create_node(
+        wiped,
+        &cfg,
+        StorageType::with_memory(MemoryStorage::default()),
+    );
+    // The leader doesn't reply, so the other follower alone is not a quorum
+    sys.set_node_connections(leader, false);
+    let wiped_node = sys.nodes.get(&wiped).unwrap();
+    wiped_node.on_definition(|x| {
+        x.paxos
+            .recover_seamlessly()
+            .expect("Failed to start recovery")
+    });
+    sys.start_node(wiped);
+    thread::sleep(SLEEP_TIMEOUT);
+    wiped_node.on_definition(|x| {
+        assert_eq!(x.paxos.get_promise(), Ballot::default());
+        assert_eq!(x.paxos.get_decided_idx(), 0);
+        assert!(x.paxos.recovery_progress().is_none());
+    });
+
+    sys.set_node_connections(leader, true);
+    wait_until(cfg.wait_timeout, "The wiped server did not recover", || {
+        wiped_node.on_definition(|x| x.paxos.recovery_progress().is_some_and(|p| p.completed))
+    });
+    verify_log(
+        wiped_node.on_definition(|x| x.read_decided_log()),
+        proposals,
+    );
+    // The leader might have changed while it was isolated
+    wait_until(
+        cfg.wait_timeout,
+        "The wiped server did not recover the promise",
+        || {
+            let promises: Vec<_> = sys
+                .nodes
+                .values()
+                .map(|node| node.on_definition(|x| x.paxos.get_promise()))
+                .collect();
+            let promise = wiped_node.on_definition(|x| x.paxos.get_promise());
+            promises.iter().max() == Some(&promise)
+        },
+    );
+
+    let kompact_system =
+        std::mem::take(&mut sys.kompact_system).expect("No KompactSystem in memory");
+    match kompact_system.shutdown() {
+        Ok(_) => {}
+        Err(e) => panic!("Error on kompact shutdown: {}", e),
+    };
+}
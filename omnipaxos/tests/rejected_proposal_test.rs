@@ -0,0 +1,142 @@
+pub mod utils;
+
+use omnipaxos::{util::NodeId, ClusterConfig, OmniPaxos, ProposalRejectionCause, RejectedProposal};
+use omnipaxos_storage::memory_storage::MemoryStorage;
+use serial_test::serial;
+use std::collections::HashMap;
+use utils::{deliver, elect_leader, tick_until, tick_until_filtered, TestConfig, Value};
+
+type OmniPaxosValue = OmniPaxos<Value, MemoryStorage<Value>>;
+
+fn cluster(cfg: &TestConfig) -> HashMap<NodeId, OmniPaxosValue> {
+    utils::create_cluster(cfg, |_| ())
+}
+
+/// Like `tick_until`, but only delivers the messages between the `connected` servers.
+fn tick_until_among<F>(nodes: &mut HashMap<NodeId, OmniPaxosValue>, connected: &[NodeId], done: F)
+where
+    F: Fn(&HashMap<NodeId, OmniPaxosValue>) -> bool,
+{
+    tick_until_filtered(
+        nodes,
+        |msg| connected.contains(&msg.get_sender()) && connected.contains(&msg.get_receiver()),
+        done,
+    );
+}
+
+/// Elects a leader among the `connected` servers and returns it.
+fn elect(nodes: &mut HashMap<NodeId, OmniPaxosValue>, connected: &[NodeId]) -> NodeId {
+    tick_until_among(nodes, connected, |nodes| {
+        let leader = nodes[&connected[0]].get_current_leader();
+        leader.is_some_and(|l| connected.contains(&l))
+            && connected
+                .iter()
+                .all(|pid| nodes[pid].get_current_leader() == leader)
+    });
+    nodes[&connected[0]].get_current_leader().unwrap()
+}
+
+fn rejected(entry: u64, token: u64, cause: ProposalRejectionCause) -> RejectedProposal<Value> {
+    RejectedProposal {
+        entry: Value::with_id(entry),
+        token: Some(token),
+        cause,
+    }
+}
+
+/// Verifies that a proposal forwarded to a leader that is being reconfigured is returned to
+/// the follower it was appended on.
+#[test]
+#[serial]
+fn reconfigured_test() {
+    let cfg = TestConfig::load("rejected_proposal_test").expect("Test config loaded");
+    let pids: Vec<NodeId> = (1..=cfg.num_nodes as NodeId).collect();
+    let mut nodes = cluster(&cfg);
+    let leader = elect_leader(&mut nodes);
+    let follower = *pids.iter().find(|pid| **pid != leader).unwrap();
+    let new_config = ClusterConfig {
+        configuration_id: 2,
+        nodes: pids.clone(),
+        ..Default::default()
+    };
+    nodes
+        .get_mut(&leader)
+        .unwrap()
+        .reconfigure(new_config, None)
+        .expect("Failed to reconfigure");
+    // The follower doesn't know about the reconfiguration yet
+    nodes
+        .get_mut(&follower)
+        .unwrap()
+        .append_with_token(Value::with_id(1), 1)
+        .expect("Failed to append");
+    deliver(&mut nodes, &[follower], |msg| msg.get_receiver() == leader);
+    tick_until(&mut nodes, |nodes| {
+        nodes.values().all(|op| op.is_reconfigured().is_some())
+    });
+    let op = nodes.get_mut(&follower).unwrap();
+    assert!(op.take_expired_proposals().is_empty());
+    assert_eq!(
+        op.take_rejected_proposals(),
+        vec![rejected(1, 1, ProposalRejectionCause::Reconfigured)]
+    );
+    assert!(op.take_rejected_proposals().is_empty());
+}
+
+/// Verifies that the proposals a leader appended but couldn't replicate before it was
+/// partitioned are returned to the servers they were appended on once the log of the former
+/// leader is overwritten by the new leader.
+#[test]
+#[serial]
+fn truncated_test() {
+    let cfg = TestConfig::load("rejected_proposal_test").expect("Test config loaded");
+    let pids: Vec<NodeId> = (1..=cfg.num_nodes as NodeId).collect();
+    let mut nodes = cluster(&cfg);
+    let old_leader = elect_leader(&mut nodes);
+    let others: Vec<NodeId> = pids.into_iter().filter(|pid| *pid != old_leader).collect();
+    let follower = others[0];
+    nodes
+        .get_mut(&old_leader)
+        .unwrap()
+        .append_with_token(Value::with_id(1), 1)
+        .expect("Failed to append");
+    nodes
+        .get_mut(&follower)
+        .unwrap()
+        .append_with_token(Value::with_id(2), 2)
+        .expect("Failed to append");
+    deliver(&mut nodes, &[follower], |msg| {
+        msg.get_receiver() == old_leader
+    });
+    // The old leader appended both entries, but is partitioned before replicating them
+    nodes.get_mut(&old_leader).unwrap().outgoing_messages();
+
+    let new_leader = elect(&mut nodes, &others);
+    nodes
+        .get_mut(&new_leader)
+        .unwrap()
+        .append(Value::with_id(3))
+        .expect("Failed to append");
+    tick_until_among(&mut nodes, &others, |nodes| {
+        others.iter().all(|pid| nodes[pid].get_decided_idx() == 1)
+    });
+    assert!(nodes
+        .values_mut()
+        .all(|op| op.take_rejected_proposals().is_empty()));
+
+    // The rejection sent by the old leader to the follower is delivered before the next tick
+    tick_until(&mut nodes, |nodes| {
+        nodes.values().all(|op| op.get_decided_idx() == 1)
+    });
+    assert_eq!(
+        nodes
+            .get_mut(&old_leader)
+            .unwrap()
+            .take_rejected_proposals(),
+        vec![rejected(1, 1, ProposalRejectionCause::Truncated)]
+    );
+    assert_eq!(
+        nodes.get_mut(&follower).unwrap().take_rejected_proposals(),
+        vec![rejected(2, 2, ProposalRejectionCause::Truncated)]
+    );
+}
@@ -0,0 +1,184 @@
+pub mod utils;
+
+use omnipaxos::{
+    messages::{sequence_paxos::PaxosMsg, Message},
+    util::NodeId,
+    OmniPaxos,
+};
+use omnipaxos_storage::memory_storage::MemoryStorage;
+use serial_test::serial;
+use std::collections::HashMap;
+use utils::{tick_until, TestConfig, Value};
+
+type OmniPaxosValue = OmniPaxos<Value, MemoryStorage<Value>>;
+
+fn cluster(cfg: &TestConfig, reorder_window: usize) -> HashMap<NodeId, OmniPaxosValue> {
+    utils::create_cluster(cfg, |server_cfg| server_cfg.reorder_window = reorder_window)
+}
+
+fn is_sequenced_to(msg: &Message<Value>, to: NodeId) -> bool {
+    match msg {
+        Message::SequencePaxos(m) => {
+            m.to == to && matches!(m.msg, PaxosMsg::AcceptDecide(_) | PaxosMsg::Decide(_))
+        }
+        _ => false,
+    }
+}
+
+/// Waits until all `nodes` decided the first entry and the proposals of `cfg`.
+fn tick_until_decided(cfg: &TestConfig, nodes: &mut HashMap<NodeId, OmniPaxosValue>) {
+    let decided_idx = cfg.num_proposals as usize + 1;
+    tick_until(nodes, |nodes| {
+        nodes.values().all(|op| op.get_decided_idx() == decided_idx)
+    });
+}
+
+/// Decides an entry and then appends the proposals of `cfg` at the leader one by one. Returns
+/// the cluster, a follower, and the sequenced messages from the leader to the follower in the
+/// order they were sent. The messages to the other servers are delivered.
+fn sequenced_messages(
+    cfg: &TestConfig,
+    reorder_window: usize,
+) -> (HashMap<NodeId, OmniPaxosValue>, NodeId, Vec<Message<Value>>) {
+    let mut nodes = cluster(cfg, reorder_window);
+    tick_until(&mut nodes, |nodes| {
+        nodes.values().all(|op| op.get_current_leader().is_some())
+    });
+    let leader = nodes[&1].get_current_leader().unwrap();
+    nodes
+        .get_mut(&leader)
+        .unwrap()
+        .append(Value::with_id(0))
+        .expect("Failed to append");
+    tick_until(&mut nodes, |nodes| {
+        nodes.values().all(|op| op.get_decided_idx() == 1)
+    });
+    let follower = *nodes.keys().find(|pid| **pid != leader).unwrap();
+    let mut sequenced = vec![];
+    for v in utils::create_proposals(1, cfg.num_proposals) {
+        let op = nodes.get_mut(&leader).unwrap();
+        op.append(v).expect("Failed to append");
+        for msg in op.outgoing_messages() {
+            if is_sequenced_to(&msg, follower) {
+                sequenced.push(msg);
+            } else if msg.get_receiver() != follower {
+                nodes
+                    .get_mut(&msg.get_receiver())
+                    .unwrap()
+                    .handle_incoming(msg)
+                    .expect("Failed to handle message");
+            }
+        }
+    }
+    assert_eq!(sequenced.len(), cfg.num_proposals as usize);
+    (nodes, follower, sequenced)
+}
+
+/// Verifies that a follower holds the messages that overtook preceding ones within the
+/// `reorder_window` and counts the duplicates instead of resynchronizing with the leader.
+#[test]
+#[serial]
+fn reorder_window_test() {
+    let cfg = TestConfig::load("reorder_window_test").expect("Test config loaded");
+    let num_proposals = cfg.num_proposals;
+    let (mut nodes, follower, sequenced) = sequenced_messages(&cfg, 2);
+    let op = nodes.get_mut(&follower).unwrap();
+    for msg in sequenced.iter().rev() {
+        op.handle_incoming(msg.clone())
+            .expect("Failed to handle message");
+    }
+    let stats = op.get_outgoing_stats();
+    assert_eq!(stats.dropped_preceding_messages, 0);
+    assert_eq!(stats.reordered_messages, num_proposals - 1);
+    assert!(op.read(num_proposals as usize).is_some());
+
+    // Redelivered messages are duplicates, also while they are held
+    op.handle_incoming(sequenced[0].clone())
+        .expect("Failed to handle message");
+    let stats = op.get_outgoing_stats();
+    assert_eq!(stats.duplicate_messages, 1);
+    assert_eq!(stats.outdated_messages, 1);
+
+    let (mut nodes, follower, sequenced) = sequenced_messages(&cfg, 2);
+    let op = nodes.get_mut(&follower).unwrap();
+    op.handle_incoming(sequenced[2].clone())
+        .expect("Failed to handle message");
+    op.handle_incoming(sequenced[2].clone())
+        .expect("Failed to handle message");
+    let stats = op.get_outgoing_stats();
+    assert_eq!(stats.duplicate_messages, 1);
+    assert_eq!(stats.dropped_preceding_messages, 0);
+    op.handle_incoming(sequenced[0].clone())
+        .expect("Failed to handle message");
+    op.handle_incoming(sequenced[1].clone())
+        .expect("Failed to handle message");
+    assert_eq!(nodes[&follower].get_outgoing_stats().reordered_messages, 1);
+    tick_until_decided(&cfg, &mut nodes);
+    assert_eq!(
+        nodes[&follower]
+            .get_outgoing_stats()
+            .dropped_preceding_messages,
+        0
+    );
+}
+
+/// Verifies that a message that overtook more messages than the `reorder_window` makes the
+/// follower resynchronize with the leader, which is the only behavior without a window, and
+/// that the recovery events are counted for the peers involved.
+#[test]
+#[serial]
+fn outside_reorder_window_test() {
+    let cfg = TestConfig::load("reorder_window_test").expect("Test config loaded");
+    for reorder_window in [0, 1] {
+        let (mut nodes, follower, sequenced) = sequenced_messages(&cfg, reorder_window);
+        let leader = nodes[&follower].get_current_leader().unwrap();
+        let op = nodes.get_mut(&follower).unwrap();
+        op.handle_incoming(sequenced[2].clone())
+            .expect("Failed to handle message");
+        let stats = op.get_outgoing_stats();
+        assert_eq!(stats.dropped_preceding_messages, 1);
+        assert_eq!(stats.reordered_messages, 0);
+        let recovery = op.get_sequence_recovery_stats();
+        assert_eq!(recovery[&leader].dropped_preceding_messages, 1);
+        tick_until_decided(&cfg, &mut nodes);
+        // the follower might request to be resynchronized more than once while it recovers
+        assert!(nodes[&leader].get_sequence_recovery_stats()[&follower].session_resets >= 1);
+
+        // the messages of the previous session are outdated after the resynchronization
+        let op = nodes.get_mut(&follower).unwrap();
+        op.handle_incoming(sequenced[0].clone())
+            .expect("Failed to handle message");
+        let recovery = op.get_sequence_recovery_stats();
+        assert_eq!(recovery[&leader].outdated_messages, 1);
+        assert_eq!(recovery[&leader].session_resets, 0);
+    }
+}
+
+/// Verifies that a follower resynchronizes with the leader if the messages that the held ones
+/// overtook don't arrive.
+#[test]
+#[serial]
+fn lost_message_test() {
+    let cfg = TestConfig::load("reorder_window_test").expect("Test config loaded");
+    let resend_timeout = cfg
+        .into_omnipaxos_config(1)
+        .server_config
+        .resend_message_tick_timeout;
+    let (mut nodes, follower, sequenced) = sequenced_messages(&cfg, 2);
+    let op = nodes.get_mut(&follower).unwrap();
+    op.handle_incoming(sequenced[1].clone())
+        .expect("Failed to handle message");
+    op.handle_incoming(sequenced[2].clone())
+        .expect("Failed to handle message");
+    for _ in 0..resend_timeout {
+        op.tick().expect("Failed to tick");
+    }
+    assert_eq!(op.get_outgoing_stats().dropped_preceding_messages, 0);
+    for _ in 0..resend_timeout {
+        op.tick().expect("Failed to tick");
+    }
+    let stats = op.get_outgoing_stats();
+    assert_eq!(stats.dropped_preceding_messages, 1);
+    assert_eq!(stats.reordered_messages, 0);
+    tick_until_decided(&cfg, &mut nodes);
+}
@@ -0,0 +1,129 @@
+pub mod utils;
+
+use crate::utils::{Value, ValueSnapshot};
+use omnipaxos::{
+    errors::ReplayErr,
+    storage::{
+        replay::{replay_decided_log, Apply, ReplayConfig, ReplayProgress},
+        LogStorage, Snapshot, StateStorage,
+    },
+};
+use omnipaxos_storage::memory_storage::MemoryStorage;
+use std::{error::Error, time::Duration};
+
+const NUM_ENTRIES: u64 = 20;
+const DECIDED_IDX: usize = 15;
+const COMPACTED_IDX: usize = 5;
+
+/// A state machine that records the applied entries.
+#[derive(Default)]
+struct Applied {
+    entries: Vec<Value>,
+}
+
+impl Apply<Value> for Applied {
+    fn apply(&mut self, idx: usize, entry: Value) -> Result<(), Box<dyn Error>> {
+        assert_eq!(idx, self.entries.len(), "Entry applied out of order");
+        self.entries.push(entry);
+        Ok(())
+    }
+
+    fn apply_snapshot(
+        &mut self,
+        snapshot: ValueSnapshot,
+        compacted_idx: usize,
+    ) -> Result<(), Box<dyn Error>> {
+        assert_eq!(snapshot.snapshotted.len(), compacted_idx);
+        self.entries = snapshot.snapshotted;
+        Ok(())
+    }
+}
+
+fn values(from: u64, to: u64) -> Vec<Value> {
+    (from..to).map(Value::with_id).collect()
+}
+
+fn storage() -> MemoryStorage<Value> {
+    let mut storage = MemoryStorage::default();
+    storage
+        .append_entries(values(0, NUM_ENTRIES))
+        .expect("Failed to append");
+    storage
+        .set_decided_idx(DECIDED_IDX)
+        .expect("Failed to decide");
+    storage
+}
+
+fn compact(storage: &mut MemoryStorage<Value>, snapshot: Option<ValueSnapshot>) {
+    storage.set_snapshot(snapshot).unwrap();
+    storage.trim(COMPACTED_IDX).unwrap();
+    storage.set_compacted_idx(COMPACTED_IDX).unwrap();
+}
+
+/// Verifies that exactly the decided entries are applied in order and that the progress is
+/// reported after every batch.
+#[test]
+fn replay_test() {
+    let storage = storage();
+    let mut applied = Applied::default();
+    let config = ReplayConfig {
+        batch_size: 4,
+        ..Default::default()
+    };
+    let mut reported = vec![];
+    let progress = replay_decided_log(&storage, &mut applied, &config, |p| {
+        reported.push(p.applied_idx)
+    })
+    .expect("Failed to replay");
+    assert_eq!(applied.entries, values(0, DECIDED_IDX as u64));
+    assert_eq!(reported, vec![4, 8, 12, 15]);
+    assert_eq!(progress.applied_idx, DECIDED_IDX);
+    assert_eq!(progress.end_idx, DECIDED_IDX);
+}
+
+/// Verifies that a compacted log is replayed from its snapshot, and that a log trimmed
+/// without a snapshot can only be replayed from the compacted index on.
+#[test]
+fn replay_compacted_test() {
+    let mut storage = storage();
+    compact(&mut storage, Some(ValueSnapshot::create(&values(0, 5))));
+    let mut applied = Applied::default();
+    replay_decided_log(&storage, &mut applied, &ReplayConfig::default(), |_| ())
+        .expect("Failed to replay");
+    assert_eq!(applied.entries, values(0, DECIDED_IDX as u64));
+
+    let mut storage = self::storage();
+    compact(&mut storage, None);
+    let res = replay_decided_log(
+        &storage,
+        &mut Applied::default(),
+        &ReplayConfig::default(),
+        |_| (),
+    );
+    assert!(matches!(res, Err(ReplayErr::Trimmed(COMPACTED_IDX))));
+    // A state machine restored up to the compacted index continues from there
+    let mut applied = Applied {
+        entries: values(0, COMPACTED_IDX as u64),
+    };
+    let config = ReplayConfig {
+        from_idx: COMPACTED_IDX,
+        ..Default::default()
+    };
+    replay_decided_log(&storage, &mut applied, &config, |_| ()).expect("Failed to replay");
+    assert_eq!(applied.entries, values(0, DECIDED_IDX as u64));
+}
+
+/// Verifies that the replay is slowed down to the configured rate.
+#[test]
+fn replay_rate_limit_test() {
+    let storage = storage();
+    let config = ReplayConfig {
+        batch_size: 1,
+        max_entries_per_sec: Some(100),
+        ..Default::default()
+    };
+    let ReplayProgress { elapsed, .. } =
+        replay_decided_log(&storage, &mut Applied::default(), &config, |_| ())
+            .expect("Failed to replay");
+    assert!(elapsed >= Duration::from_millis(140), "{:?}", elapsed);
+}
@@ -0,0 +1,139 @@
+pub mod utils;
+
+use omnipaxos::{
+    settings::{ReplicatedSettings, SettingsEntry},
+    storage::{Entry, NoSnapshot},
+    util::{LogEntry, NodeId},
+    OmniPaxos, ProposeErr,
+};
+use omnipaxos_storage::memory_storage::MemoryStorage;
+use serial_test::serial;
+use std::collections::HashMap;
+use utils::{no_unicache, tick_until, TestConfig};
+
+#[derive(Clone, Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+enum Command {
+    Value(u64),
+    Settings(ReplicatedSettings),
+}
+
+impl Entry for Command {
+    type Snapshot = NoSnapshot;
+    no_unicache!();
+}
+
+impl SettingsEntry for Command {
+    fn settings(settings: ReplicatedSettings) -> Self {
+        Command::Settings(settings)
+    }
+
+    fn as_settings(&self) -> Option<&ReplicatedSettings> {
+        match self {
+            Command::Settings(settings) => Some(settings),
+            _ => None,
+        }
+    }
+}
+
+type OmniPaxosCommand = OmniPaxos<Command, MemoryStorage<Command>>;
+
+/// Proposes settings changes in between regular entries and verifies that every server that
+/// enabled replicated settings applies them at the log index they were decided at, while
+/// invalid changes are rejected or ignored.
+#[test]
+#[serial]
+fn replicated_settings_test() {
+    let cfg = TestConfig::load("replicated_settings_test").expect("Test config loaded");
+    let mut nodes: HashMap<NodeId, OmniPaxosCommand> = (1..=cfg.num_nodes as NodeId)
+        .map(|pid| {
+            let op = cfg
+                .into_omnipaxos_config(pid)
+                .build(MemoryStorage::default())
+                .expect("Failed to build OmniPaxos");
+            (pid, op)
+        })
+        .collect();
+    // the last server keeps its local settings
+    let unreplicated = cfg.num_nodes as NodeId;
+    for pid in 1..unreplicated {
+        nodes
+            .get_mut(&pid)
+            .unwrap()
+            .enable_replicated_settings()
+            .expect("Failed to enable the replicated settings");
+    }
+    let invalid = ReplicatedSettings {
+        batch_size: Some(0),
+        ..Default::default()
+    };
+    match nodes.get_mut(&1).unwrap().propose_settings(invalid.clone()) {
+        Err(ProposeErr::InvalidSettings(_, settings)) => assert_eq!(settings, invalid),
+        res => panic!("Invalid settings were proposed: {:?}", res),
+    }
+
+    let first = ReplicatedSettings {
+        sync_batch_size: Some(10),
+        snapshot_interval: Some(Some(50)),
+        ..Default::default()
+    };
+    let second = ReplicatedSettings {
+        batch_size: Some(2),
+        snapshot_interval: Some(None),
+        ..Default::default()
+    };
+    let op = nodes.get_mut(&1).unwrap();
+    op.append(Command::Value(1)).expect("Failed to append");
+    op.propose_settings(first.clone())
+        .expect("Failed to propose settings");
+    op.append(Command::Value(2)).expect("Failed to append");
+    // appended directly so that it bypasses the validation and must be ignored when decided
+    op.append(Command::Settings(invalid))
+        .expect("Failed to append");
+    op.propose_settings(second.clone())
+        .expect("Failed to propose settings");
+    op.append(Command::Value(3)).expect("Failed to append");
+    tick_until(&mut nodes, |nodes| {
+        nodes.values().all(|op| op.get_decided_idx() == 6)
+    });
+
+    let op = nodes.get(&1).unwrap();
+    let settings_idx = |settings: &ReplicatedSettings| {
+        (0..6)
+            .find(|idx| match op.read(*idx) {
+                Some(LogEntry::Decided(Command::Settings(s))) => s == *settings,
+                _ => false,
+            })
+            .expect("settings entry not found")
+    };
+    let second_idx = settings_idx(&second);
+    assert!(settings_idx(&first) < second_idx);
+    let expected = ReplicatedSettings {
+        batch_size: Some(2),
+        sync_batch_size: Some(10),
+        snapshot_interval: Some(None),
+    };
+    for pid in 1..unreplicated {
+        assert_eq!(
+            nodes.get(&pid).unwrap().get_applied_settings(),
+            Some((second_idx, &expected)),
+            "Server {} applied wrong settings",
+            pid
+        );
+    }
+    assert_eq!(
+        nodes.get(&unreplicated).unwrap().get_applied_settings(),
+        None
+    );
+
+    // enabling later applies the changes that are already decided
+    nodes
+        .get_mut(&unreplicated)
+        .unwrap()
+        .enable_replicated_settings()
+        .expect("Failed to enable the replicated settings");
+    assert_eq!(
+        nodes.get(&unreplicated).unwrap().get_applied_settings(),
+        Some((second_idx, &expected))
+    );
+}
@@ -0,0 +1,246 @@
+#![cfg(all(feature = "snapshot_sealing", not(feature = "no_snapshots")))]
+pub mod utils;
+
+use crate::utils::StorageType;
+use omnipaxos::{
+    errors::SealingErr,
+    messages::{sequence_paxos::PaxosMsg, Message},
+    sealing::{self, SnapshotSealer},
+    storage::{Snapshot, SnapshotType},
+    util::{LogEntry, NodeId},
+    OmniPaxos, RejectedMessageCause,
+};
+use omnipaxos_storage::memory_storage::MemoryStorage;
+use serial_test::serial;
+use std::{collections::HashMap, error::Error, sync::Arc};
+use utils::{tick_until_filtered, verification::verify_log, TestConfig, Value, ValueSnapshot};
+
+type OmniPaxosValue = OmniPaxos<Value, StorageType<Value>>;
+
+/// A toy sealer that XORs the bytes with the key and signs them with a keyed checksum. It only
+/// knows its own key.
+#[derive(Debug)]
+struct XorSealer {
+    key: u64,
+}
+
+impl XorSealer {
+    fn xor(&self, key_id: u64, bytes: &[u8]) -> Result<Vec<u8>, Box<dyn Error>> {
+        if key_id != self.key {
+            return Err(format!("unknown key {}", key_id).into());
+        }
+        Ok(bytes.iter().map(|b| b ^ key_id as u8).collect())
+    }
+
+    fn checksum(key_id: u64, bytes: &[u8]) -> Vec<u8> {
+        let sum = bytes.iter().fold(key_id, |sum, b| {
+            sum.wrapping_mul(31).wrapping_add(*b as u64)
+        });
+        sum.to_be_bytes().to_vec()
+    }
+}
+
+impl SnapshotSealer for XorSealer {
+    fn current_key_id(&self) -> u64 {
+        self.key
+    }
+
+    fn encrypt(&self, key_id: u64, plaintext: &[u8]) -> Result<Vec<u8>, Box<dyn Error>> {
+        self.xor(key_id, plaintext)
+    }
+
+    fn decrypt(&self, key_id: u64, ciphertext: &[u8]) -> Result<Vec<u8>, Box<dyn Error>> {
+        self.xor(key_id, ciphertext)
+    }
+
+    fn sign(&self, key_id: u64, bytes: &[u8]) -> Result<Vec<u8>, Box<dyn Error>> {
+        self.xor(key_id, &Self::checksum(key_id, bytes))
+    }
+
+    fn verify(&self, key_id: u64, bytes: &[u8], signature: &[u8]) -> Result<(), Box<dyn Error>> {
+        match self.sign(key_id, bytes)? == signature {
+            true => Ok(()),
+            false => Err("signature mismatch".into()),
+        }
+    }
+}
+
+struct Cluster {
+    nodes: HashMap<NodeId, OmniPaxosValue>,
+    pids: Vec<NodeId>,
+    /// The number of sealed and unsealed snapshots sent between the servers.
+    sealed_snapshots: usize,
+    unsealed_snapshots: usize,
+}
+
+impl Cluster {
+    /// Creates a cluster in which each server seals its snapshots with its key in `keys`.
+    fn with(cfg: &TestConfig, keys: &[u64]) -> Self {
+        let pids: Vec<NodeId> = (1..=cfg.num_nodes as NodeId).collect();
+        let nodes = pids
+            .iter()
+            .zip(keys)
+            .map(|(pid, key)| {
+                let mut op_config = cfg.into_omnipaxos_config(*pid);
+                op_config.server_config.snapshot_sealer = Some(Arc::new(XorSealer { key: *key }));
+                let op = op_config
+                    .build(StorageType::with_memory(MemoryStorage::default()))
+                    .expect("Failed to build OmniPaxos");
+                (*pid, op)
+            })
+            .collect();
+        Self {
+            nodes,
+            pids,
+            sealed_snapshots: 0,
+            unsealed_snapshots: 0,
+        }
+    }
+
+    /// Like `utils::tick_until`, but only delivers the messages between the `connected` servers
+    /// and counts the snapshots they carry.
+    fn tick_until<F>(&mut self, connected: &[NodeId], done: F)
+    where
+        F: Fn(&HashMap<NodeId, OmniPaxosValue>) -> bool,
+    {
+        let sealed_snapshots = &mut self.sealed_snapshots;
+        let unsealed_snapshots = &mut self.unsealed_snapshots;
+        tick_until_filtered(
+            &mut self.nodes,
+            |msg| {
+                if !connected.contains(&msg.get_sender())
+                    || !connected.contains(&msg.get_receiver())
+                {
+                    return false;
+                }
+                if let Message::SequencePaxos(m) = msg {
+                    let snapshot = match &m.msg {
+                        PaxosMsg::Promise(prom) => prom
+                            .log_sync
+                            .as_ref()
+                            .and_then(|sync| sync.decided_snapshot.as_ref()),
+                        PaxosMsg::AcceptSync(acc_sync) => {
+                            acc_sync.log_sync.decided_snapshot.as_ref()
+                        }
+                        _ => None,
+                    };
+                    match snapshot {
+                        Some(SnapshotType::Sealed(_)) => *sealed_snapshots += 1,
+                        Some(_) => *unsealed_snapshots += 1,
+                        None => (),
+                    }
+                }
+                true
+            },
+            done,
+        );
+    }
+
+    /// Elects a leader and decides `proposals` while `follower` is partitioned. Returns the
+    /// leader.
+    fn decide_without(&mut self, follower: NodeId, proposals: Vec<Value>) -> NodeId {
+        let connected: Vec<NodeId> = self
+            .pids
+            .iter()
+            .copied()
+            .filter(|p| *p != follower)
+            .collect();
+        self.tick_until(&connected, |nodes| {
+            let leader = nodes[&connected[0]].get_current_leader();
+            leader.is_some() && nodes[&connected[1]].get_current_leader() == leader
+        });
+        let leader = self.nodes[&connected[0]].get_current_leader().unwrap();
+        let decided_idx = proposals.len();
+        for v in proposals {
+            self.nodes
+                .get_mut(&leader)
+                .unwrap()
+                .append(v)
+                .expect("Failed to append");
+        }
+        self.tick_until(&connected, |nodes| {
+            nodes[&leader].get_decided_idx() == decided_idx
+        });
+        leader
+    }
+}
+
+/// Verifies that a follower that missed many entries is synchronized with a sealed snapshot
+/// that it opens and installs.
+#[test]
+#[serial]
+fn sealed_snapshot_sync_test() {
+    let cfg = TestConfig::load("snapshot_sealing_test").expect("Test config loaded");
+    let mut cluster = Cluster::with(&cfg, &[7, 7, 7]);
+    let follower = 3;
+    let proposals = utils::create_proposals(1, cfg.num_proposals);
+    cluster.decide_without(follower, proposals.clone());
+    let pids = cluster.pids.clone();
+    cluster.tick_until(&pids, |nodes| {
+        nodes[&follower].get_decided_idx() == proposals.len()
+    });
+    assert!(cluster.sealed_snapshots > 0);
+    assert_eq!(cluster.unsealed_snapshots, 0);
+    assert!(cluster.nodes[&follower].get_compacted_idx() > 0);
+    let log = cluster.nodes[&follower]
+        .read_decided_suffix(0)
+        .expect("Failed to read the log");
+    assert!(matches!(log[0], LogEntry::Snapshotted(_)));
+    verify_log(log, proposals);
+}
+
+/// Verifies that a follower rejects the snapshots that it cannot verify and reports the
+/// sender as misbehaving.
+#[test]
+#[serial]
+fn unknown_key_test() {
+    let cfg = TestConfig::load("snapshot_sealing_test").expect("Test config loaded");
+    let mut cluster = Cluster::with(&cfg, &[7, 7, 9]);
+    let follower = 3;
+    let leader = cluster.decide_without(follower, utils::create_proposals(1, cfg.num_proposals));
+    let pids = cluster.pids.clone();
+    cluster.tick_until(&pids, |nodes| {
+        nodes[&follower].get_rejected_message_count(leader) > 0
+    });
+    assert!(cluster.sealed_snapshots > 0);
+    let op = cluster.nodes.get_mut(&follower).unwrap();
+    assert_eq!(op.get_compacted_idx(), 0);
+    assert!(op.get_decided_idx() < cfg.num_proposals as usize);
+    assert!(op
+        .take_misbehaving_peers()
+        .iter()
+        .any(|p| p.cause == RejectedMessageCause::InvalidSnapshot));
+}
+
+/// Verifies that a sealed snapshot only opens with a valid signature and at the index it was
+/// sealed for.
+#[test]
+fn seal_and_open_test() {
+    let sealer = XorSealer { key: 7 };
+    let sum = ValueSnapshot::create(&[Value::with_id(42)]);
+    let snapshot = SnapshotType::<Value>::Delta(sum.clone());
+    let sealed = sealing::seal(&sealer, &snapshot, 10).expect("Failed to seal");
+    assert_eq!(sealed.key_id, 7);
+    match sealing::open::<Value>(&sealer, &sealed, 10) {
+        Ok(SnapshotType::Delta(s)) => assert_eq!(s, sum),
+        other => panic!("Unexpected result {:?}", other),
+    }
+    assert!(matches!(
+        sealing::open::<Value>(&sealer, &sealed, 11),
+        Err(SealingErr::IndexMismatch {
+            sealed: 10,
+            expected: 11
+        })
+    ));
+    let mut tampered = sealed.clone();
+    tampered.ciphertext[0] ^= 1;
+    assert!(matches!(
+        sealing::open::<Value>(&sealer, &tampered, 10),
+        Err(SealingErr::InvalidSignature(_))
+    ));
+    let other = XorSealer { key: 9 };
+    assert!(matches!(
+        sealing::open::<Value>(&other, &sealed, 10),
+        Err(SealingErr::InvalidSignature(_))
+    ));
+}
@@ -1,13 +1,31 @@
+#![cfg(not(feature = "no_snapshots"))]
+
 pub mod utils;
 
-use crate::utils::{omnireplica::OmniPaxosComponent, ValueSnapshot};
+use crate::utils::{omnireplica::OmniPaxosComponent, StorageType, ValueSnapshot};
 use kompact::prelude::{promise, Ask, Component, FutureCollection};
-use omnipaxos::{storage::Snapshot, util::LogEntry};
+use omnipaxos::{
+    storage::{Entry, Snapshot, SnapshotViolation, StateStorage},
+    util::{LogEntry, NodeId},
+    OmniPaxos,
+};
+use omnipaxos_storage::memory_storage::MemoryStorage;
+use serde::{Deserialize, Serialize};
 use serial_test::serial;
-use std::{sync::Arc, thread};
-use utils::{TestConfig, TestSystem, Value};
+use std::{
+    cell::Cell,
+    collections::{HashMap, HashSet},
+    ops::RangeInclusive,
+    panic::{self, AssertUnwindSafe},
+    sync::{Arc, Mutex},
+    thread,
+};
+use utils::{
+    no_unicache, tick_until, tick_until_filtered, wait_until, TestConfig, TestSystem, Value,
+};
 
 const SNAPSHOT_INDEX_INCREMENT: usize = 10;
+const SNAPSHOT_BATCH_SIZE: usize = 4;
 
 /// Test trimming the log.
 /// At the end the log is retrieved from each replica and verified
@@ -158,3 +176,429 @@ fn check_snapshot(
         assert_eq!(decided_sfx.len(), num_proposals - snapshot_idx + 1); // +1 as all snapshotted entries are represented by LogEntry::Snapshotted
     });
 }
+
+fn backup_snapshot(node: &Arc<Component<OmniPaxosComponent>>) -> Option<ValueSnapshot> {
+    node.on_definition(|x| match x.paxos.read(0) {
+        Some(LogEntry::Snapshotted(s)) => Some(s.snapshot),
+        _ => None,
+    })
+}
+
+/// Verifies that a backup receives the decided state as snapshots, first completely and then
+/// incrementally, without taking part in the cluster.
+#[test]
+#[serial]
+fn backup_test() {
+    let cfg = TestConfig::load("backup_test").expect("Test config loaded");
+    let mut sys = TestSystem::with(cfg);
+    sys.start_all_nodes();
+
+    let leader = sys.get_elected_leader(1, cfg.wait_timeout);
+    let backup = sys
+        .nodes
+        .get(&(cfg.num_nodes as NodeId + 1))
+        .expect("No backup node found");
+    let vec_proposals = utils::create_proposals(1, 2 * cfg.num_proposals);
+    let (first, second) = vec_proposals.split_at(cfg.num_proposals as usize);
+    sys.make_proposals(leader, first.to_vec(), cfg.wait_timeout);
+    wait_until(cfg.wait_timeout, "The backup received no snapshot", || {
+        backup_snapshot(backup).is_some_and(|s| s.snapshotted == first)
+    });
+    assert_eq!(
+        backup.on_definition(|x| x.paxos.get_compacted_idx()),
+        first.len()
+    );
+
+    sys.make_proposals(leader, second.to_vec(), cfg.wait_timeout);
+    wait_until(
+        cfg.wait_timeout,
+        "The backup received no incremental snapshot",
+        || backup_snapshot(backup).is_some_and(|s| s.snapshotted == vec_proposals),
+    );
+
+    // The backup is neither elected nor does it know the leader
+    assert_eq!(backup.on_definition(|x| x.paxos.get_current_leader()), None);
+    for pid in 1..=cfg.num_nodes as NodeId {
+        let node = sys.nodes.get(&pid).unwrap();
+        assert_eq!(
+            node.on_definition(|x| x.paxos.get_current_leader()),
+            Some(leader)
+        );
+    }
+
+    let kompact_system =
+        std::mem::take(&mut sys.kompact_system).expect("No KompactSystem found in memory");
+    match kompact_system.shutdown() {
+        Ok(_) => {}
+        Err(e) => panic!("Error on kompact shutdown: {}", e),
+    };
+}
+
+/// Verifies that the servers snapshot their log automatically, each at a different index, so
+/// that no two servers snapshot at the same time.
+#[test]
+#[serial]
+fn staggered_snapshot_test() {
+    let cfg = TestConfig::load("snapshot_schedule_test").expect("Test config loaded");
+    let mut sys = TestSystem::with(cfg);
+    sys.start_all_nodes();
+
+    let leader = sys.get_elected_leader(1, cfg.wait_timeout);
+    let vec_proposals = utils::create_proposals(1, cfg.num_proposals);
+    sys.make_proposals(leader, vec_proposals.clone(), cfg.wait_timeout);
+    // the snapshot indexes of the three servers are spread 10 entries apart
+    let expected = [(1, 60), (2, 40), (3, 50)];
+    for (pid, idx) in expected {
+        let node = sys.nodes.get(&pid).unwrap();
+        wait_until(cfg.wait_timeout, "The log was not snapshotted", || {
+            node.on_definition(|x| x.paxos.get_compacted_idx()) == idx
+        });
+        node.on_definition(|x| match x.paxos.read(0) {
+            Some(LogEntry::Snapshotted(s)) => {
+                assert_eq!(s.snapshot.snapshotted, vec_proposals[..idx]);
+            }
+            e => panic!("Server {} has no snapshot: {:?}", pid, e),
+        });
+    }
+
+    let kompact_system =
+        std::mem::take(&mut sys.kompact_system).expect("No KompactSystem found in memory");
+    match kompact_system.shutdown() {
+        Ok(_) => {}
+        Err(e) => panic!("Error on kompact shutdown: {}", e),
+    };
+}
+
+thread_local! {
+    /// If set, creating a snapshot of this value panics, as if the server crashed.
+    static CRASH_ON: Cell<Option<u64>> = const { Cell::new(None) };
+}
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+struct BatchValue(u64);
+
+impl Entry for BatchValue {
+    type Snapshot = BatchSum;
+    no_unicache!();
+}
+
+/// The sum of all snapshotted values.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+struct BatchSum(u64);
+
+impl Snapshot<BatchValue> for BatchSum {
+    fn create(entries: &[BatchValue]) -> Self {
+        assert!(
+            entries.len() <= SNAPSHOT_BATCH_SIZE,
+            "{} entries were snapshotted at once",
+            entries.len()
+        );
+        let crash_on = CRASH_ON.with(|c| c.get());
+        if entries.iter().any(|v| Some(v.0) == crash_on) {
+            panic!("crashed while snapshotting");
+        }
+        BatchSum(entries.iter().map(|v| v.0).sum())
+    }
+
+    fn merge(&mut self, delta: Self) {
+        self.0 += delta.0;
+    }
+
+    fn use_snapshots() -> bool {
+        true
+    }
+
+    fn is_equivalent(&self, other: &Self) -> Option<bool> {
+        Some(self == other)
+    }
+}
+
+fn batch_sum(ids: RangeInclusive<u64>) -> BatchSum {
+    BatchSum(ids.sum())
+}
+
+type OmniPaxosBatch = OmniPaxos<BatchValue, StorageType<BatchValue>>;
+type Storages = HashMap<NodeId, Arc<Mutex<MemoryStorage<BatchValue>>>>;
+
+/// Servers whose storages outlive them, so that the persisted snapshots can be inspected.
+fn batch_cluster(cfg: &TestConfig) -> (HashMap<NodeId, OmniPaxosBatch>, Storages) {
+    let storages: Storages = (1..=cfg.num_nodes as NodeId)
+        .map(|pid| (pid, Arc::default()))
+        .collect();
+    let nodes = utils::create_cluster_with(
+        cfg,
+        |server_cfg| server_cfg.snapshot_batch_size = SNAPSHOT_BATCH_SIZE,
+        |pid| StorageType::Broken(storages[&pid].clone(), Arc::default()),
+    );
+    (nodes, storages)
+}
+
+/// Ticks the cluster without the `disconnected` servers until `done` holds.
+fn tick_until_connected<F>(
+    nodes: &mut HashMap<NodeId, OmniPaxosBatch>,
+    disconnected: &HashSet<NodeId>,
+    done: F,
+) where
+    F: Fn(&HashMap<NodeId, OmniPaxosBatch>) -> bool,
+{
+    tick_until_filtered(
+        nodes,
+        |msg| {
+            !disconnected.contains(&msg.get_sender()) && !disconnected.contains(&msg.get_receiver())
+        },
+        done,
+    );
+}
+
+/// Appends the values with `ids` at the leader and waits until the servers that are not
+/// `disconnected` decided them. Returns the pid of the leader.
+fn decide_batch(
+    nodes: &mut HashMap<NodeId, OmniPaxosBatch>,
+    disconnected: &HashSet<NodeId>,
+    ids: RangeInclusive<u64>,
+) -> NodeId {
+    tick_until_connected(nodes, disconnected, |nodes| {
+        nodes.values().all(|op| op.get_current_leader().is_some())
+    });
+    let leader = nodes[&1].get_current_leader().unwrap();
+    let decided_idx = *ids.end() as usize;
+    for id in ids {
+        nodes
+            .get_mut(&leader)
+            .unwrap()
+            .append(BatchValue(id))
+            .expect("Failed to append");
+    }
+    tick_until_connected(nodes, disconnected, |nodes| {
+        nodes
+            .iter()
+            .filter(|(pid, _)| !disconnected.contains(pid))
+            .all(|(_, op)| op.get_decided_idx() == decided_idx)
+    });
+    leader
+}
+
+/// Verifies that a long log is snapshotted in batches, and that the intermediate snapshots are
+/// persisted so that a server that crashes while snapshotting keeps the completed batches.
+#[test]
+#[serial]
+fn snapshot_in_batches_test() {
+    let cfg = TestConfig::load("snapshot_batch_test").expect("Test config loaded");
+    let num_proposals = cfg.num_proposals;
+    let (mut nodes, storages) = batch_cluster(&cfg);
+    let leader = decide_batch(&mut nodes, &HashSet::new(), 1..=num_proposals);
+    let follower = (1..=cfg.num_nodes as NodeId)
+        .find(|pid| *pid != leader)
+        .unwrap();
+
+    // The batches are [0, 4), [4, 8), and [8, 10)
+    CRASH_ON.with(|c| c.set(Some(num_proposals)));
+    let op = nodes.get_mut(&follower).unwrap();
+    let crashed = panic::catch_unwind(AssertUnwindSafe(|| op.snapshot(None, true)));
+    assert!(crashed.is_err(), "The snapshot did not crash");
+    CRASH_ON.with(|c| c.set(None));
+    let storage = storages[&follower].lock().unwrap();
+    assert_eq!(
+        storage.get_compacted_idx().unwrap(),
+        2 * SNAPSHOT_BATCH_SIZE
+    );
+    assert_eq!(
+        storage.get_snapshot().unwrap(),
+        Some(batch_sum(1..=2 * SNAPSHOT_BATCH_SIZE as u64))
+    );
+    drop(storage);
+
+    let op = nodes.get_mut(&leader).unwrap();
+    op.snapshot(None, true).expect("Failed to snapshot");
+    match op.read(0) {
+        Some(LogEntry::Snapshotted(s)) => {
+            assert_eq!(s.trimmed_idx, num_proposals as usize);
+            assert_eq!(s.snapshot, batch_sum(1..=num_proposals));
+        }
+        e => panic!("Unexpected entry: {:?}", e),
+    }
+}
+
+/// Verifies that a follower that catches up with a delta snapshot merges it with its own log
+/// in batches, and that the leader creates the delta in batches.
+#[test]
+#[serial]
+fn catch_up_in_batches_test() {
+    let cfg = TestConfig::load("snapshot_batch_test").expect("Test config loaded");
+    let num_proposals = cfg.num_proposals;
+    let (mut nodes, _) = batch_cluster(&cfg);
+    let mut disconnected = HashSet::new();
+    let leader = decide_batch(&mut nodes, &disconnected, 1..=num_proposals);
+    let follower = (1..=cfg.num_nodes as NodeId)
+        .find(|pid| *pid != leader)
+        .unwrap();
+
+    disconnected.insert(follower);
+    decide_batch(
+        &mut nodes,
+        &disconnected,
+        num_proposals + 1..=2 * num_proposals,
+    );
+    assert_eq!(nodes[&follower].get_decided_idx(), num_proposals as usize);
+    for peer in (1..=cfg.num_nodes as NodeId).filter(|pid| *pid != follower) {
+        nodes.get_mut(&peer).unwrap().reconnected(follower);
+        nodes.get_mut(&follower).unwrap().reconnected(peer);
+    }
+    tick_until_connected(&mut nodes, &HashSet::new(), |nodes| {
+        nodes[&follower].get_decided_idx() == 2 * num_proposals as usize
+    });
+    match nodes[&follower].read(0) {
+        Some(LogEntry::Snapshotted(s)) => {
+            assert_eq!(s.trimmed_idx, 2 * num_proposals as usize);
+            assert_eq!(s.snapshot, batch_sum(1..=2 * num_proposals));
+        }
+        e => panic!("Unexpected entry: {:?}", e),
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+struct ContractValue(u64);
+
+impl Entry for ContractValue {
+    type Snapshot = OverwritingSum;
+    no_unicache!();
+}
+
+/// Meant to be the sum of all snapshotted values, but `merge()` overwrites the sum with the
+/// one of the delta.
+#[derive(Clone, Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+struct OverwritingSum(u64);
+
+impl Snapshot<ContractValue> for OverwritingSum {
+    fn create(entries: &[ContractValue]) -> Self {
+        OverwritingSum(entries.iter().map(|v| v.0).sum())
+    }
+
+    fn merge(&mut self, delta: Self) {
+        self.0 = delta.0;
+    }
+
+    fn use_snapshots() -> bool {
+        true
+    }
+
+    fn is_equivalent(&self, other: &Self) -> Option<bool> {
+        Some(self == other)
+    }
+}
+
+/// The servers and the backup of `cfg`, which check their snapshots if `check_snapshots` is
+/// set.
+fn checking_cluster<T>(
+    cfg: &TestConfig,
+    check_snapshots: bool,
+) -> HashMap<NodeId, OmniPaxos<T, MemoryStorage<T>>>
+where
+    T: Entry,
+{
+    utils::create_cluster(cfg, |server_cfg| {
+        server_cfg.check_snapshots = check_snapshots
+    })
+}
+
+/// Appends the `entries` at server 1 and waits until they are decided by all servers and the
+/// backup. Returns the pid of the leader.
+fn append_and_decide<T>(
+    nodes: &mut HashMap<NodeId, OmniPaxos<T, MemoryStorage<T>>>,
+    entries: Vec<T>,
+) -> NodeId
+where
+    T: Entry,
+{
+    let decided_idx = nodes[&1].get_decided_idx() + entries.len();
+    let op = nodes.get_mut(&1).unwrap();
+    for entry in entries {
+        op.append(entry).expect("Failed to append");
+    }
+    tick_until(nodes, |nodes| {
+        nodes.values().all(|op| op.get_decided_idx() == decided_idx)
+    });
+    nodes[&1].get_current_leader().expect("No leader elected")
+}
+
+fn contract_values(from: u64, to: u64) -> Vec<ContractValue> {
+    (from..=to).map(ContractValue).collect()
+}
+
+/// Verifies that a `Snapshot` implementation whose `merge()` is inconsistent with `create()`
+/// is reported when a server snapshots its log.
+#[test]
+#[serial]
+fn local_snapshot_violation_test() {
+    let cfg = TestConfig::load("snapshot_contract_test").expect("Test config loaded");
+    let mut nodes = checking_cluster::<ContractValue>(&cfg, true);
+    let leader = append_and_decide(&mut nodes, contract_values(1, cfg.num_proposals));
+    let op = nodes.get_mut(&leader).unwrap();
+    // Ignores the violations found in the snapshots sent to the backup
+    op.take_snapshot_violations();
+    op.snapshot(None, true).expect("Failed to snapshot");
+    assert_eq!(
+        op.take_snapshot_violations(),
+        vec![SnapshotViolation {
+            from_idx: 0,
+            split_idx: cfg.num_proposals as usize / 2,
+            to_idx: cfg.num_proposals as usize,
+        }]
+    );
+    assert_eq!(op.take_snapshot_violations(), vec![]);
+}
+
+/// Verifies that the leader checks a delta snapshot against the entries it was created from
+/// before sending it, here to the backup.
+#[test]
+#[serial]
+fn delta_snapshot_violation_test() {
+    let cfg = TestConfig::load("snapshot_contract_test").expect("Test config loaded");
+    let num_proposals = cfg.num_proposals;
+    let num_delta = num_proposals / 2;
+    let mut nodes = checking_cluster::<ContractValue>(&cfg, true);
+    append_and_decide(&mut nodes, contract_values(1, num_proposals));
+    // Lets the leader learn that the backup has decided all entries
+    let ticks = Cell::new(0);
+    tick_until(&mut nodes, |_| {
+        ticks.set(ticks.get() + 1);
+        ticks.get() > 2 * cfg.backup_tick_timeout.unwrap()
+    });
+    let leader = append_and_decide(
+        &mut nodes,
+        contract_values(num_proposals + 1, num_proposals + num_delta),
+    );
+    let violations = nodes.get_mut(&leader).unwrap().take_snapshot_violations();
+    let expected = SnapshotViolation {
+        from_idx: 0,
+        split_idx: num_proposals as usize,
+        to_idx: (num_proposals + num_delta) as usize,
+    };
+    assert!(
+        violations.contains(&expected),
+        "{:?} was not reported: {:?}",
+        expected,
+        violations
+    );
+}
+
+/// Verifies that no violations are reported for a consistent `Snapshot` implementation or
+/// when `check_snapshots` is disabled.
+#[test]
+#[serial]
+fn no_snapshot_violation_test() {
+    let cfg = TestConfig::load("snapshot_contract_test").expect("Test config loaded");
+    let mut nodes = checking_cluster::<Value>(&cfg, true);
+    let leader = append_and_decide(&mut nodes, utils::create_proposals(1, cfg.num_proposals));
+    let op = nodes.get_mut(&leader).unwrap();
+    op.snapshot(None, true).expect("Failed to snapshot");
+    assert_eq!(op.take_snapshot_violations(), vec![]);
+
+    let mut nodes = checking_cluster::<ContractValue>(&cfg, false);
+    let leader = append_and_decide(&mut nodes, contract_values(1, cfg.num_proposals));
+    let op = nodes.get_mut(&leader).unwrap();
+    op.snapshot(None, true).expect("Failed to snapshot");
+    assert_eq!(op.take_snapshot_violations(), vec![]);
+}
@@ -0,0 +1,213 @@
+pub mod utils;
+
+use omnipaxos::{
+    ballot_leader_election::Ballot,
+    errors::StandbyErr,
+    messages::{
+        sequence_paxos::{PaxosMessage, PaxosMsg, Prepare},
+        Message,
+    },
+    util::NodeId,
+    OmniPaxos,
+};
+use omnipaxos_storage::memory_storage::MemoryStorage;
+use serial_test::serial;
+use std::collections::HashMap;
+use utils::{
+    elect_leader, tick_until, tick_until_filtered, verification::verify_log, TestConfig, Value,
+};
+
+type OmniPaxosValue = OmniPaxos<Value, MemoryStorage<Value>>;
+
+/// Elects a leader, decides the `proposals`, and returns the cluster and its leader.
+fn with_decided(
+    cfg: &TestConfig,
+    proposals: &[Value],
+) -> (HashMap<NodeId, OmniPaxosValue>, NodeId) {
+    let mut nodes = utils::create_cluster(cfg, |_| ());
+    let leader = elect_leader(&mut nodes);
+    let op = nodes.get_mut(&leader).unwrap();
+    for v in proposals {
+        op.append(v.clone()).expect("Failed to append");
+    }
+    tick_until(&mut nodes, |nodes| {
+        nodes
+            .values()
+            .all(|op| op.get_decided_idx() == proposals.len())
+    });
+    (nodes, leader)
+}
+
+/// Like `with_decided`, but also makes a follower the ready standby of the leader. Returns the
+/// cluster, its leader, and the standby.
+fn with_ready_standby(
+    cfg: &TestConfig,
+    proposals: &[Value],
+) -> (HashMap<NodeId, OmniPaxosValue>, NodeId, NodeId) {
+    let (mut nodes, leader) = with_decided(cfg, proposals);
+    let standby = *nodes.keys().find(|pid| **pid != leader).unwrap();
+    nodes
+        .get_mut(&leader)
+        .unwrap()
+        .set_standby(standby)
+        .expect("Failed to designate the standby");
+    tick_until(&mut nodes, |nodes| {
+        nodes[&leader].get_standby() == Some((standby, true))
+    });
+    (nodes, leader, standby)
+}
+
+/// Handles `prepare` at the server `pid` and returns its reply, if any.
+fn reply_to(op: &mut OmniPaxosValue, pid: NodeId, prepare: Prepare) -> Option<PaxosMsg<Value>> {
+    let from = prepare.n.pid;
+    op.handle_incoming(Message::SequencePaxos(PaxosMessage {
+        from,
+        to: pid,
+        namespace: None,
+        msg: PaxosMsg::Prepare(prepare),
+    }))
+    .expect("Failed to handle message");
+    op.outgoing_messages()
+        .into_iter()
+        .find_map(|msg| match msg {
+            Message::SequencePaxos(m) if m.to == from => Some(m.msg),
+            _ => None,
+        })
+}
+
+/// Verifies that the ready standby takes over from a crashed leader without a prepare phase
+/// and that the decided entries are preserved.
+#[test]
+#[serial]
+fn standby_takeover_test() {
+    let cfg = TestConfig::load("standby_test").expect("Test config loaded");
+    let num_proposals = cfg.num_proposals as usize;
+    let proposals = utils::create_proposals(1, 2 * cfg.num_proposals + 1);
+    let (mut nodes, leader, standby) = with_ready_standby(&cfg, &proposals[..num_proposals]);
+    let op = nodes.get_mut(&leader).unwrap();
+    for v in &proposals[num_proposals..2 * num_proposals] {
+        op.append(v.clone()).expect("Failed to append");
+    }
+    tick_until(&mut nodes, |nodes| {
+        nodes
+            .values()
+            .all(|op| op.get_decided_idx() == 2 * num_proposals)
+    });
+
+    // The leader crashes, i.e., its messages are dropped
+    let mut delivered = vec![];
+    tick_until_filtered(
+        &mut nodes,
+        |msg| {
+            let deliver = msg.get_sender() != leader && msg.get_receiver() != leader;
+            if let (true, Message::SequencePaxos(m)) = (deliver, msg) {
+                delivered.push(m.clone());
+            }
+            deliver
+        },
+        |nodes| {
+            nodes
+                .iter()
+                .filter(|(pid, _)| **pid != leader)
+                .all(|(_, op)| op.get_current_leader() == Some(standby))
+        },
+    );
+    assert!(
+        delivered
+            .iter()
+            .any(|m| m.from == standby && matches!(m.msg, PaxosMsg::Takeover(_))),
+        "The standby did not take over"
+    );
+    assert!(
+        !delivered
+            .iter()
+            .any(|m| matches!(m.msg, PaxosMsg::Prepare(_))),
+        "A prepare phase was run"
+    );
+
+    nodes
+        .get_mut(&standby)
+        .unwrap()
+        .append(proposals.last().unwrap().clone())
+        .expect("Failed to append");
+    tick_until_filtered(
+        &mut nodes,
+        |msg| msg.get_sender() != leader && msg.get_receiver() != leader,
+        |nodes| {
+            nodes
+                .iter()
+                .filter(|(pid, _)| **pid != leader)
+                .all(|(_, op)| op.get_decided_idx() == proposals.len())
+        },
+    );
+    for (_, op) in nodes.iter().filter(|(pid, _)| **pid != leader) {
+        verify_log(
+            op.read_decided_suffix(0).unwrap_or_default(),
+            proposals.clone(),
+        );
+    }
+}
+
+/// Verifies that a follower refuses a Prepare of another server than the standby in the
+/// standby's round.
+#[test]
+#[serial]
+fn standby_round_refused_test() {
+    let cfg = TestConfig::load("standby_test").expect("Test config loaded");
+    let proposals = utils::create_proposals(1, 2);
+    let (mut nodes, leader, standby) = with_ready_standby(&cfg, &proposals);
+    let mut others = nodes
+        .keys()
+        .copied()
+        .filter(|pid| *pid != leader && *pid != standby);
+    let (follower, other) = (others.next().unwrap(), others.next().unwrap());
+    let op = nodes.get_mut(&follower).unwrap();
+    let promised = op.get_promise();
+    let prepare = |pid, n| Prepare {
+        n: Ballot {
+            n,
+            priority: 100,
+            pid,
+            ..promised
+        },
+        decided_idx: 0,
+        n_accepted: Ballot::default(),
+        accepted_idx: 0,
+    };
+    match reply_to(op, follower, prepare(other, promised.n + 1)) {
+        Some(PaxosMsg::NotAccepted(not_acc)) => assert_eq!(not_acc.standby, Some(standby)),
+        reply => panic!("Expected NotAccepted, got {:?}", reply),
+    }
+    assert_eq!(op.get_promise(), promised);
+    // the round after the standby's is not refused
+    assert!(matches!(
+        reply_to(op, follower, prepare(other, promised.n + 2)),
+        Some(PaxosMsg::Promise(_))
+    ));
+}
+
+/// Verifies that only the leader can designate a standby, once per leadership.
+#[test]
+#[serial]
+fn set_standby_errors_test() {
+    let cfg = TestConfig::load("standby_test").expect("Test config loaded");
+    let (mut nodes, leader) = with_decided(&cfg, &utils::create_proposals(1, 1));
+    let mut followers = nodes.keys().copied().filter(|pid| *pid != leader);
+    let (standby, other) = (followers.next().unwrap(), followers.next().unwrap());
+    assert!(matches!(
+        nodes.get_mut(&standby).unwrap().set_standby(other),
+        Err(StandbyErr::NotLeader)
+    ));
+    let op = nodes.get_mut(&leader).unwrap();
+    assert!(matches!(
+        op.set_standby(leader),
+        Err(StandbyErr::NotPeer(pid)) if pid == leader
+    ));
+    assert!(matches!(op.set_standby(10), Err(StandbyErr::NotPeer(10))));
+    op.set_standby(standby)
+        .expect("Failed to designate the standby");
+    assert!(matches!(
+        op.set_standby(other),
+        Err(StandbyErr::AlreadyDesignated(pid)) if pid == standby
+    ));
+}
@@ -2,11 +2,18 @@ pub mod utils;
 
 use crate::utils::STOPSIGN_ID;
 use kompact::prelude::{promise, Ask, FutureCollection};
-use omnipaxos::{storage::StopSign, util::NodeId, ClusterConfig};
+use omnipaxos::{
+    storage::StopSign,
+    util::{LogEntry, NodeId},
+    ClusterConfig,
+};
 use serial_test::serial;
+use std::time::Duration;
+#[cfg(feature = "spill")]
+use std::{fs, thread, time::Instant};
 use utils::{
     verification::{verify_log, verify_stopsign},
-    TestConfig, TestSystem, Value,
+    wait_until, TestConfig, TestSystem, Value,
 };
 
 /// The state of the leader's and follower's log at the time of a sync
@@ -26,6 +33,7 @@ struct SyncTest {
 /// has invalid undecided entries.
 #[test]
 #[serial]
+#[cfg(not(feature = "no_snapshots"))]
 fn sync_full_test() {
     // Define leader's log
     let leaders_log = [1, 2, 3, 4, 5, 10, 11, 12]
@@ -113,6 +121,7 @@ fn sync_only_stopsign_test() {
 /// merges onto their empty log.
 #[test]
 #[serial]
+#[cfg(not(feature = "no_snapshots"))]
 fn sync_only_snapshot_test() {
     // Define leader's log
     let leaders_log: Vec<Value> = [1, 2, 3].into_iter().map(Value::with_id).collect();
@@ -136,6 +145,7 @@ fn sync_only_snapshot_test() {
 /// the partly-snapshotted decided entries of the follower.
 #[test]
 #[serial]
+#[cfg(not(feature = "no_snapshots"))]
 fn sync_follower_snapshot_test() {
     // Define leader's log
     let leaders_log = [1, 2, 3, 4, 5].into_iter().map(Value::with_id).collect();
@@ -275,3 +285,340 @@ fn sync_test(test: SyncTest) {
     }
     verify_log(followers_entries, test.leaders_log);
 }
+
+/// A leader keeps the entries it appends while it is disconnected in the cache of its log tail.
+/// Verifies that once a new leader decided other entries at the same indexes and synced the old
+/// leader, the old leader reads the new entries instead of the overwritten ones from its cache.
+#[test]
+#[serial]
+fn read_after_truncating_sync_test() {
+    let cfg = TestConfig::load("sync_test").expect("Test config couldn't be loaded");
+    let sys = TestSystem::with(cfg);
+    sys.start_all_nodes();
+
+    let decided = utils::create_proposals(1, 2);
+    let overwritten = utils::create_proposals(101, 103);
+    let new_entries = utils::create_proposals(201, 203);
+    let end_idx = decided.len() + overwritten.len();
+
+    let old_leader_id = sys.get_elected_leader(1, cfg.wait_timeout);
+    let old_leader = sys.nodes.get(&old_leader_id).unwrap();
+    sys.make_proposals(old_leader_id, decided.clone(), cfg.wait_timeout);
+    sys.set_node_connections(old_leader_id, false);
+    old_leader.on_definition(|x| {
+        for entry in &overwritten {
+            x.paxos.append(entry.clone()).expect("Couldn't append");
+        }
+        let expected: Vec<_> = overwritten
+            .iter()
+            .cloned()
+            .map(LogEntry::Undecided)
+            .collect();
+        assert_eq!(x.paxos.read_entries(decided.len()..end_idx), Some(expected));
+    });
+
+    // Wait a bit so next leader is stabilized (otherwise we can lose proposals)
+    std::thread::sleep(8 * cfg.election_timeout);
+    let node = sys.nodes.keys().find(|x| **x != old_leader_id).unwrap();
+    let new_leader_id = sys.get_elected_leader(*node, cfg.wait_timeout);
+    assert_ne!(old_leader_id, new_leader_id, "New leader must be chosen!");
+    sys.make_proposals(new_leader_id, new_entries.clone(), cfg.wait_timeout);
+
+    // Reconnect the old leader and wait until it decided the new entries
+    let mut proposal_futures = vec![];
+    old_leader.on_definition(|x| {
+        for v in &new_entries {
+            let (kprom, kfuture) = promise::<()>();
+            x.insert_decided_future(Ask::new(kprom, v.clone()));
+            proposal_futures.push(kfuture);
+        }
+    });
+    sys.set_node_connections(old_leader_id, true);
+    FutureCollection::collect_with_timeout::<Vec<_>>(proposal_futures, cfg.wait_timeout)
+        .expect("Old leader did not decide the new entries");
+
+    let expected: Vec<_> = decided.into_iter().chain(new_entries).collect();
+    old_leader.on_definition(|x| {
+        let decided_log = x
+            .paxos
+            .read_decided_suffix(0)
+            .expect("Cannot read decided log");
+        verify_log(decided_log, expected.clone());
+        let entries = x
+            .paxos
+            .read_entries(0..end_idx)
+            .expect("Cannot read entries");
+        verify_log(entries, expected);
+    });
+}
+
+/// Verifies that every server reports the ballot of the leader that appended each entry,
+/// including an old leader that missed a leader change and was synced by the new leader.
+#[test]
+#[serial]
+fn entry_ballot_test() {
+    let cfg = TestConfig::load("entry_ballot_test").expect("Test config couldn't be loaded");
+    let sys = TestSystem::with(cfg);
+    sys.start_all_nodes();
+
+    let split_idx = cfg.num_proposals as usize;
+    let old_leader_id = sys.get_elected_leader(1, cfg.wait_timeout);
+    let old_leader = sys.nodes.get(&old_leader_id).unwrap();
+    let first_ballot = old_leader.on_definition(|x| x.paxos.get_promise());
+    sys.make_proposals(
+        old_leader_id,
+        utils::create_proposals(1, cfg.num_proposals),
+        cfg.wait_timeout,
+    );
+    wait_until_decided(&sys, split_idx, cfg.wait_timeout);
+    for node in sys.nodes.values() {
+        node.on_definition(|x| {
+            assert_eq!(x.paxos.get_entry_ballot(0), Some(first_ballot));
+            assert_eq!(x.paxos.get_entry_ballot(split_idx), None);
+            assert_eq!(x.paxos.get_entry_ballots(0), vec![(0, Some(first_ballot))]);
+        });
+    }
+
+    sys.set_node_connections(old_leader_id, false);
+    // Wait a bit so next leader is stabilized (otherwise we can lose proposals)
+    std::thread::sleep(8 * cfg.election_timeout);
+    let node = sys.nodes.keys().find(|x| **x != old_leader_id).unwrap();
+    let new_leader_id = sys.get_elected_leader(*node, cfg.wait_timeout);
+    assert_ne!(old_leader_id, new_leader_id, "New leader must be chosen!");
+    let second_ballot = sys
+        .nodes
+        .get(&new_leader_id)
+        .unwrap()
+        .on_definition(|x| x.paxos.get_promise());
+    assert!(second_ballot > first_ballot);
+    sys.make_proposals(
+        new_leader_id,
+        utils::create_proposals(cfg.num_proposals + 1, 2 * cfg.num_proposals),
+        cfg.wait_timeout,
+    );
+    sys.set_node_connections(old_leader_id, true);
+    wait_until_decided(&sys, 2 * split_idx, cfg.wait_timeout);
+
+    for node in sys.nodes.values() {
+        node.on_definition(|x| {
+            assert_eq!(x.paxos.get_entry_ballot(split_idx - 1), Some(first_ballot));
+            assert_eq!(x.paxos.get_entry_ballot(split_idx), Some(second_ballot));
+            assert_eq!(
+                x.paxos.get_entry_ballots(0),
+                vec![(0, Some(first_ballot)), (split_idx, Some(second_ballot))]
+            );
+            assert_eq!(
+                x.paxos.get_entry_ballots(split_idx + 1),
+                vec![(split_idx + 1, Some(second_ballot))]
+            );
+        });
+    }
+}
+
+/// Verifies that no ballots are reported unless `track_entry_ballots` is enabled.
+#[test]
+#[serial]
+fn untracked_entry_ballot_test() {
+    let mut cfg = TestConfig::load("entry_ballot_test").expect("Test config couldn't be loaded");
+    cfg.track_entry_ballots = false;
+    let sys = TestSystem::with(cfg);
+    sys.start_all_nodes();
+
+    let leader_id = sys.get_elected_leader(1, cfg.wait_timeout);
+    sys.make_proposals(
+        leader_id,
+        utils::create_proposals(1, cfg.num_proposals),
+        cfg.wait_timeout,
+    );
+    wait_until_decided(&sys, cfg.num_proposals as usize, cfg.wait_timeout);
+    for node in sys.nodes.values() {
+        node.on_definition(|x| {
+            assert_eq!(x.paxos.get_entry_ballot(0), None);
+            assert_eq!(x.paxos.get_entry_ballots(0), vec![]);
+        });
+    }
+}
+
+/// Waits until all servers of `sys` decided the log up to `decided_idx`.
+fn wait_until_decided(sys: &TestSystem, decided_idx: usize, timeout: Duration) {
+    wait_until(timeout, "Not all servers decided the entries", || {
+        sys.nodes
+            .values()
+            .all(|node| node.on_definition(|x| x.paxos.get_decided_idx()) == decided_idx)
+    });
+}
+
+/// Verifies that a follower that missed more entries than `sync_batch_size` catches up by
+/// requesting the missing log in portions, and ends up with the same log as the leader.
+#[test]
+#[serial]
+fn sync_in_portions_test() {
+    let cfg = TestConfig::load("sync_portions_test").expect("Test config couldn't be loaded");
+    let sys = TestSystem::with(cfg);
+    sys.start_all_nodes();
+
+    let leader_id = sys.get_elected_leader(1, cfg.wait_timeout);
+    let follower_id = *sys.nodes.keys().find(|pid| **pid != leader_id).unwrap();
+    let follower = sys.nodes.get(&follower_id).unwrap();
+    let proposals = utils::create_proposals(1, cfg.num_proposals);
+
+    sys.set_node_connections(follower_id, false);
+    sys.make_proposals(leader_id, proposals.clone(), cfg.wait_timeout);
+    assert_eq!(follower.on_definition(|x| x.paxos.get_decided_idx()), 0);
+
+    let mut proposal_futures = vec![];
+    follower.on_definition(|x| {
+        for v in &proposals {
+            let (kprom, kfuture) = promise::<()>();
+            x.insert_decided_future(Ask::new(kprom, v.clone()));
+            proposal_futures.push(kfuture);
+        }
+    });
+    sys.set_node_connections(follower_id, true);
+    follower.on_definition(|x| x.paxos.reconnected(leader_id));
+    FutureCollection::collect_with_timeout::<Vec<_>>(proposal_futures, cfg.wait_timeout)
+        .expect("The follower did not catch up");
+
+    let follower_log = follower.on_definition(|x| x.read_decided_log());
+    verify_log(follower_log, proposals);
+}
+
+/// Decides `proposals` while `follower` is disconnected, then reconnects it and waits until it
+/// caught up with the leader.
+#[cfg(not(feature = "no_snapshots"))]
+fn decide_while_disconnected(
+    sys: &TestSystem,
+    cfg: &TestConfig,
+    leader_id: NodeId,
+    follower_id: NodeId,
+    proposals: Vec<Value>,
+) {
+    let leader = sys.nodes.get(&leader_id).unwrap();
+    let follower = sys.nodes.get(&follower_id).unwrap();
+    sys.set_node_connections(follower_id, false);
+    sys.make_proposals(leader_id, proposals, cfg.wait_timeout);
+    sys.set_node_connections(follower_id, true);
+    follower.on_definition(|x| x.paxos.reconnected(leader_id));
+    let decided_idx = leader.on_definition(|x| x.paxos.get_decided_idx());
+    wait_until(cfg.wait_timeout, "The follower did not catch up", || {
+        follower.on_definition(|x| x.paxos.get_decided_idx()) == decided_idx
+    });
+}
+
+/// Verifies that a follower that diverges from the leader by at most
+/// `max_sync_suffix_entries` entries is synchronized with the entries, and one that diverges
+/// by more with a snapshot.
+#[test]
+#[serial]
+#[cfg(not(feature = "no_snapshots"))]
+fn max_sync_suffix_entries_test() {
+    let cfg = TestConfig::load("max_sync_suffix_test").expect("Test config couldn't be loaded");
+    let max_sync_suffix_entries = cfg.max_sync_suffix_entries.unwrap() as u64;
+    let mut sys = TestSystem::with(cfg);
+    sys.start_all_nodes();
+
+    let leader_id = sys.get_elected_leader(1, cfg.wait_timeout);
+    let follower_id = *sys.nodes.keys().find(|pid| **pid != leader_id).unwrap();
+    let short = max_sync_suffix_entries / 2;
+    let long = short + 2 * max_sync_suffix_entries;
+    let proposals = utils::create_proposals(1, long);
+    let (short_proposals, long_proposals) = proposals.split_at(short as usize);
+
+    decide_while_disconnected(&sys, &cfg, leader_id, follower_id, short_proposals.to_vec());
+    let follower = sys.nodes.get(&follower_id).unwrap();
+    assert_eq!(follower.on_definition(|x| x.paxos.get_compacted_idx()), 0);
+
+    decide_while_disconnected(&sys, &cfg, leader_id, follower_id, long_proposals.to_vec());
+    let compacted_idx = follower.on_definition(|x| x.paxos.get_compacted_idx());
+    assert!(compacted_idx > short as usize, "{}", compacted_idx);
+    let follower_log = follower.on_definition(|x| x.read_decided_log());
+    assert!(matches!(follower_log[0], LogEntry::Snapshotted(_)));
+    verify_log(follower_log, proposals);
+    // The leader still has all the entries
+    let leader = sys.nodes.get(&leader_id).unwrap();
+    assert_eq!(leader.on_definition(|x| x.paxos.get_compacted_idx()), 0);
+
+    let kompact_system =
+        std::mem::take(&mut sys.kompact_system).expect("No KompactSystem found in memory");
+    match kompact_system.shutdown() {
+        Ok(_) => {}
+        Err(e) => panic!("Error on kompact shutdown: {}", e),
+    };
+}
+
+#[cfg(feature = "spill")]
+fn spilled_bytes(spill_dir: &std::path::Path) -> u64 {
+    fs::read_dir(spill_dir)
+        .expect("failed to read spill dir")
+        .map(|f| f.unwrap().metadata().unwrap().len())
+        .sum()
+}
+
+/// Verifies that a follower catching up on a large part of the log is synced through spilled
+/// messages, and that the spill files are emptied once drained and removed when dropped.
+#[cfg(feature = "spill")]
+#[test]
+#[serial]
+fn spill_test() {
+    let cfg = TestConfig::load("sync_test").expect("Test config couldn't be loaded");
+    let mut sys = TestSystem::with(cfg);
+    let spill_dir = tempfile::tempdir().expect("failed to create spill dir");
+    for node in sys.nodes.values() {
+        // Spill every sync message
+        node.on_definition(|x| x.paxos.spill_syncs_to_disk(spill_dir.path(), 0))
+            .expect("failed to create spill file");
+    }
+    assert_eq!(
+        fs::read_dir(spill_dir.path()).unwrap().count(),
+        cfg.num_nodes
+    );
+    sys.start_all_nodes();
+
+    let leader_id = sys.get_elected_leader(1, cfg.wait_timeout);
+    let lagging_id = *sys.nodes.keys().find(|pid| **pid != leader_id).unwrap();
+    let leader = sys.nodes.get(&leader_id).unwrap();
+    let lagging = sys.nodes.get(&lagging_id).unwrap();
+    let proposals = utils::create_proposals(1, cfg.num_proposals);
+    sys.set_node_connections(lagging_id, false);
+    sys.make_proposals(leader_id, proposals.clone(), cfg.wait_timeout);
+    assert_eq!(lagging.on_definition(|x| x.paxos.get_decided_idx()), 0);
+
+    // Take the messages to the lagging follower by hand, so that the spilled ones can be seen
+    leader.on_definition(|x| x.blocked_peers.insert(lagging_id));
+    sys.set_node_connections(lagging_id, true);
+    lagging.on_definition(|x| x.paxos.reconnected(leader_id));
+    let mut spilled = false;
+    let deadline = Instant::now() + cfg.wait_timeout;
+    while lagging.on_definition(|x| x.paxos.get_decided_idx()) < proposals.len() {
+        assert!(Instant::now() < deadline, "The follower did not catch up");
+        spilled |= spilled_bytes(spill_dir.path()) > 0;
+        leader.on_definition(|x| {
+            let msgs = x.paxos.outgoing_for(lagging_id);
+            x.send(msgs);
+        });
+        thread::sleep(Duration::from_millis(1));
+    }
+    assert!(spilled, "the sync to the lagging follower was not spilled");
+    leader.on_definition(|x| x.blocked_peers.remove(&lagging_id));
+    for node in sys.nodes.values() {
+        verify_log(
+            node.on_definition(|x| x.read_decided_log()),
+            proposals.clone(),
+        );
+    }
+
+    // Drain the remaining messages, after which the spill files are empty
+    sys.stop_all_nodes();
+    for node in sys.nodes.values() {
+        node.on_definition(|x| x.paxos.outgoing_messages());
+    }
+    assert_eq!(spilled_bytes(spill_dir.path()), 0);
+    let kompact_system =
+        std::mem::take(&mut sys.kompact_system).expect("No KompactSystem in memory");
+    match kompact_system.shutdown() {
+        Ok(_) => {}
+        Err(e) => panic!("Error on kompact shutdown: {}", e),
+    };
+    sys.nodes.clear();
+    assert_eq!(fs::read_dir(spill_dir.path()).unwrap().count(), 0);
+}
@@ -2,10 +2,22 @@ pub mod utils;
 
 use crate::utils::omnireplica::OmniPaxosComponent;
 use kompact::prelude::{promise, Ask, Component, FutureCollection};
-use omnipaxos::util::LogEntry;
+use omnipaxos::{
+    storage::{LogStorage, StateStorage, StorageOp},
+    util::{LogEntry, NodeId},
+    CompactionErr, LowSpacePolicy, ProposeErr, TrimMode,
+};
+use omnipaxos_storage::{memory_storage::MemoryStorage, ring_buffer_storage::RingBufferStorage};
 use serial_test::serial;
-use std::{sync::Arc, thread};
-use utils::{TestConfig, TestSystem, Value};
+use std::{
+    collections::HashMap,
+    mem::size_of,
+    sync::{Arc, Mutex},
+    thread,
+};
+#[cfg(not(feature = "no_snapshots"))]
+use utils::{verification::verify_log, StorageTypeSelector};
+use utils::{wait_until, StorageType, TestConfig, TestSystem, Value};
 
 const TRIM_INDEX_INCREMENT: usize = 10;
 
@@ -160,3 +172,518 @@ fn check_trim(vec_proposals: &[Value], trim_idx: usize, node: Arc<Component<Omni
         assert_eq!(decided_sfx.len(), num_proposals - trim_idx + 1); // +1 as all trimmed entries are represented by LogEntry::Trimmed
     });
 }
+
+const MAX_ENTRIES: u64 = 10;
+#[cfg(not(feature = "no_snapshots"))]
+const RING_BUFFER_BATCH: usize = 5;
+
+/// Decides one entry more than fit into the storage limit on all servers and returns the leader.
+fn exceed_storage_limit(sys: &TestSystem, cfg: &TestConfig) -> NodeId {
+    let leader = sys.get_elected_leader(1, cfg.wait_timeout);
+    let vec_proposals = utils::create_proposals(1, MAX_ENTRIES + 1);
+    let last = vec_proposals.last().unwrap();
+    let mut futures = vec![];
+    for node in sys.nodes.values() {
+        let (kprom, kfuture) = promise::<()>();
+        node.on_definition(|x| x.insert_decided_future(Ask::new(kprom, last.clone())));
+        futures.push(kfuture);
+    }
+    sys.nodes.get(&leader).unwrap().on_definition(|x| {
+        for v in &vec_proposals {
+            x.paxos.append(v.clone()).expect("Failed to append");
+        }
+    });
+    match FutureCollection::collect_with_timeout::<Vec<_>>(futures, cfg.wait_timeout) {
+        Ok(_) => {}
+        Err(e) => panic!("Error on collecting futures of decided proposals: {}", e),
+    }
+    // wait for the storage size to be checked on the next ticks
+    thread::sleep(2 * cfg.election_timeout);
+    leader
+}
+
+/// Verifies that all servers refuse new proposals once their storage exceeds the limit.
+#[test]
+#[serial]
+fn refuse_appends_test() {
+    let cfg = TestConfig {
+        // `MemoryStorage` counts the size of the stored entries
+        storage_size_limit: Some(MAX_ENTRIES * size_of::<Value>() as u64),
+        low_space_policy: LowSpacePolicy::RefuseAppends,
+        ..TestConfig::load("low_space_test").expect("Test config loaded")
+    };
+    let mut sys = TestSystem::with(cfg);
+    sys.start_all_nodes();
+
+    exceed_storage_limit(&sys, &cfg);
+    for node in sys.nodes.values() {
+        node.on_definition(|x| {
+            let v = Value::with_id(0);
+            match x.paxos.append(v.clone()) {
+                Err(ProposeErr::Full(refused)) => assert_eq!(refused, v),
+                res => panic!("Proposal was not refused: {:?}", res),
+            }
+            assert_eq!(x.paxos.get_compacted_idx(), 0);
+        });
+    }
+
+    let kompact_system =
+        std::mem::take(&mut sys.kompact_system).expect("No KompactSystem found in memory");
+    match kompact_system.shutdown() {
+        Ok(_) => {}
+        Err(e) => panic!("Error on kompact shutdown: {}", e),
+    };
+}
+
+/// Verifies that the servers compact the decided log to get below the limit instead of refusing
+/// new proposals.
+#[test]
+#[serial]
+fn compact_then_refuse_appends_test() {
+    let cfg = TestConfig {
+        storage_size_limit: Some(MAX_ENTRIES * size_of::<Value>() as u64),
+        low_space_policy: LowSpacePolicy::CompactThenRefuseAppends,
+        ..TestConfig::load("low_space_test").expect("Test config loaded")
+    };
+    let mut sys = TestSystem::with(cfg);
+    sys.start_all_nodes();
+
+    let leader = exceed_storage_limit(&sys, &cfg);
+    wait_until(
+        cfg.wait_timeout,
+        "The decided log was not compacted",
+        || {
+            sys.nodes.values().all(|node| {
+                node.on_definition(|x| x.paxos.get_compacted_idx()) == MAX_ENTRIES as usize + 1
+            })
+        },
+    );
+    for node in sys.nodes.values() {
+        node.on_definition(|x| {
+            x.paxos
+                .append(Value::with_id(0))
+                .expect("Proposal was refused after compacting")
+        });
+    }
+    sys.make_proposals(leader, vec![Value::with_id(0)], cfg.wait_timeout);
+
+    let kompact_system =
+        std::mem::take(&mut sys.kompact_system).expect("No KompactSystem found in memory");
+    match kompact_system.shutdown() {
+        Ok(_) => {}
+        Err(e) => panic!("Error on kompact shutdown: {}", e),
+    };
+}
+
+/// Verifies that a cluster decides many more entries than fit into a `RingBufferStorage` by
+/// compacting the log before it fills the capacity, and that proposals are refused while the
+/// log is full.
+#[cfg(not(feature = "no_snapshots"))]
+#[test]
+#[serial]
+fn ring_buffer_storage_test() {
+    let cfg = TestConfig::load("ring_buffer_storage_test").expect("Test config loaded");
+    let capacity = match cfg.storage_type {
+        StorageTypeSelector::RingBuffer { capacity } => capacity,
+        _ => panic!("ring_buffer_storage_test must use a RingBuffer storage"),
+    };
+    let mut sys = TestSystem::with(cfg);
+    sys.start_all_nodes();
+    let leader = sys.get_elected_leader(1, cfg.wait_timeout);
+
+    let vec_proposals = utils::create_proposals(1, cfg.num_proposals);
+    for batch in vec_proposals.chunks(RING_BUFFER_BATCH) {
+        sys.make_proposals(leader, batch.to_vec(), cfg.wait_timeout);
+    }
+    wait_until(cfg.wait_timeout, "The log was not decided", || {
+        sys.nodes.values().all(|node| {
+            node.on_definition(|x| x.paxos.get_decided_idx()) == cfg.num_proposals as usize
+        })
+    });
+    for node in sys.nodes.values() {
+        node.on_definition(|x| {
+            assert!(x.paxos.get_compacted_idx() > cfg.num_proposals as usize - capacity);
+            let log = x.paxos.read_decided_suffix(0).expect("Cannot read log");
+            verify_log(log, vec_proposals.clone());
+        });
+    }
+
+    // Entries that are not decided can't be compacted
+    let appended = sys.nodes.get(&leader).unwrap().on_definition(|x| {
+        let mut appended = 0;
+        loop {
+            let v = Value::with_id(0);
+            match x.paxos.append(v.clone()) {
+                Ok(()) => appended += 1,
+                Err(ProposeErr::Full(refused)) => {
+                    assert_eq!(refused, v);
+                    break appended;
+                }
+                Err(e) => panic!("Unexpected proposal error: {:?}", e),
+            }
+        }
+    });
+    assert!(appended <= capacity);
+    let decided_idx = cfg.num_proposals as usize + appended;
+    wait_until(cfg.wait_timeout, "The full log was not decided", || {
+        sys.nodes
+            .values()
+            .all(|node| node.on_definition(|x| x.paxos.get_decided_idx()) == decided_idx)
+    });
+    sys.make_proposals(leader, vec![Value::with_id(0)], cfg.wait_timeout);
+
+    let kompact_system =
+        std::mem::take(&mut sys.kompact_system).expect("No KompactSystem found in memory");
+    match kompact_system.shutdown() {
+        Ok(_) => {}
+        Err(e) => panic!("Error on kompact shutdown: {}", e),
+    };
+}
+
+/// Verifies that a `RingBufferStorage` refuses writes that exceed its capacity without changing.
+#[test]
+fn ring_buffer_capacity_test() {
+    let values: Vec<Value> = (1..=4).map(Value::with_id).collect();
+    let mut storage = RingBufferStorage::<Value>::with_capacity(3);
+    assert_eq!(storage.get_capacity().unwrap(), Some(3));
+    storage
+        .append_entries(values[..2].to_vec())
+        .expect("Failed to append");
+    assert!(storage.append_entries(values[2..].to_vec()).is_err());
+    assert!(storage
+        .write_atomically(vec![
+            StorageOp::AppendEntries(values[2..].to_vec()),
+            StorageOp::SetDecidedIndex(2),
+        ])
+        .is_err());
+    assert_eq!(storage.get_decided_idx().unwrap(), 0);
+    assert_eq!(storage.get_log_len().unwrap(), 2);
+
+    // The appended entries fit once the log is trimmed in the same write
+    storage
+        .write_atomically(vec![
+            StorageOp::AppendEntries(values[2..].to_vec()),
+            StorageOp::Trim(2),
+            StorageOp::SetCompactedIdx(2),
+        ])
+        .expect("Failed to write");
+    assert_eq!(storage.get_log_len().unwrap(), 2);
+    assert_eq!(storage.get_entries(2, 4).unwrap(), values[2..].to_vec());
+    assert_eq!(storage.get_suffix(3).unwrap(), values[3..].to_vec());
+}
+
+/// Recreates the servers of `sys` before they are started on storages that outlive them, so
+/// that the entries in the storages can be inspected and the servers restarted from them.
+fn use_shared_storages(
+    sys: &mut TestSystem,
+    cfg: &TestConfig,
+) -> HashMap<NodeId, Arc<Mutex<MemoryStorage<Value>>>> {
+    (1..=cfg.num_nodes as NodeId)
+        .map(|pid| {
+            let storage: Arc<Mutex<MemoryStorage<Value>>> = Arc::default();
+            sys.kill_node(pid);
+            sys.create_node(
+                pid,
+                cfg,
+                StorageType::Broken(storage.clone(), Arc::default()),
+            );
+            (pid, storage)
+        })
+        .collect()
+}
+
+/// The number of entries that are physically in `storage`.
+fn stored_entries(storage: &Mutex<MemoryStorage<Value>>) -> usize {
+    storage.lock().unwrap().get_log_len().unwrap()
+}
+
+/// Decides the proposals of `cfg` and trims the log up to its `trim_idx` on all servers.
+/// Returns the pid of the leader.
+fn decide_and_trim(sys: &TestSystem, cfg: &TestConfig) -> NodeId {
+    let leader = sys.get_elected_leader(1, cfg.wait_timeout);
+    sys.make_proposals(
+        leader,
+        utils::create_proposals(1, cfg.num_proposals),
+        cfg.wait_timeout,
+    );
+    wait_until(cfg.wait_timeout, "The entries were not decided", || {
+        sys.nodes.values().all(|node| {
+            node.on_definition(|x| x.paxos.get_decided_idx()) == cfg.num_proposals as usize
+        })
+    });
+    // The leader can only trim once it has learned that all servers decided the entries
+    let leader_node = sys.nodes.get(&leader).unwrap();
+    wait_until(
+        cfg.wait_timeout,
+        "The leader could not trim the log",
+        || leader_node.on_definition(|x| x.paxos.trim(Some(cfg.trim_idx)).is_ok()),
+    );
+    wait_until(cfg.wait_timeout, "The log was not trimmed", || {
+        sys.nodes
+            .values()
+            .all(|node| node.on_definition(|x| x.paxos.get_compacted_idx()) == cfg.trim_idx)
+    });
+    leader
+}
+
+/// The decided log after it was trimmed up to the `trim_idx` of `cfg`.
+fn trimmed_log(cfg: &TestConfig) -> Vec<LogEntry<Value>> {
+    let mut expected = vec![LogEntry::Trimmed(cfg.trim_idx)];
+    expected.extend(
+        utils::create_proposals(cfg.trim_idx as u64 + 1, cfg.num_proposals)
+            .into_iter()
+            .map(LogEntry::Decided),
+    );
+    expected
+}
+
+/// Verifies that a trim whose storage fails returns the storage error with the failed
+/// operation, and that the log can be trimmed once the storage works again.
+#[test]
+#[serial]
+fn trim_storage_err_test() {
+    let cfg = TestConfig::load("trim_worker_test").expect("Test config loaded");
+    let mut sys = TestSystem::with(cfg);
+    let storage_confs = sys.use_broken_storages(&cfg);
+    sys.start_all_nodes();
+
+    let leader = sys.get_elected_leader(1, cfg.wait_timeout);
+    sys.make_proposals(
+        leader,
+        utils::create_proposals(1, cfg.num_proposals),
+        cfg.wait_timeout,
+    );
+    let leader_node = sys.nodes.get(&leader).unwrap();
+    // The leader can only trim once it has learned that all servers accepted the entries
+    wait_until(cfg.wait_timeout, "The entries were not accepted", || {
+        leader_node.on_definition(|x| {
+            let accepted_indexes = x.paxos.get_ui_states().cluster_state.accepted_indexes;
+            (1..=cfg.num_nodes).all(|pid| accepted_indexes[pid] == cfg.num_proposals as usize)
+        })
+    });
+    leader_node.on_definition(|x| {
+        storage_confs[&leader]
+            .lock()
+            .unwrap()
+            .schedule_failure_in(1);
+        match x.paxos.trim(Some(cfg.trim_idx)) {
+            Err(CompactionErr::Storage(op)) => assert_eq!(op, "trim the log"),
+            res => panic!("Unexpected trim result: {:?}", res),
+        }
+        x.paxos.trim(Some(cfg.trim_idx)).expect("Failed to trim");
+    });
+    wait_until(cfg.wait_timeout, "The log was not trimmed", || {
+        sys.nodes
+            .values()
+            .all(|node| node.on_definition(|x| x.paxos.get_compacted_idx()) == cfg.trim_idx)
+    });
+
+    let kompact_system =
+        std::mem::take(&mut sys.kompact_system).expect("No KompactSystem found in memory");
+    match kompact_system.shutdown() {
+        Ok(_) => {}
+        Err(e) => panic!("Error on kompact shutdown: {}", e),
+    };
+}
+
+/// Verifies that with `TrimMode::Worker` the log is compacted by `trim()`, but the entries
+/// are only removed from the storage when the trim worker runs.
+#[test]
+#[serial]
+fn worker_trim_test() {
+    let cfg = TestConfig {
+        trim_mode: TrimMode::Worker,
+        ..TestConfig::load("trim_worker_test").expect("Test config loaded")
+    };
+    let num_proposals = cfg.num_proposals as usize;
+    let mut sys = TestSystem::with(cfg);
+    let storages = use_shared_storages(&mut sys, &cfg);
+    sys.start_all_nodes();
+    decide_and_trim(&sys, &cfg);
+
+    for (pid, node) in &sys.nodes {
+        let storage = &storages[pid];
+        node.on_definition(|x| {
+            assert_eq!(x.paxos.read_decided_suffix(0), Some(trimmed_log(&cfg)));
+            assert_eq!(stored_entries(storage), num_proposals);
+
+            let worker = x.paxos.trim_worker();
+            assert!(worker.is_pending());
+            assert_eq!(worker.run().expect("Failed to trim"), Some(cfg.trim_idx));
+            assert!(!worker.is_pending());
+            assert_eq!(worker.run().expect("Failed to trim"), None);
+            assert_eq!(stored_entries(storage), num_proposals - cfg.trim_idx);
+            assert_eq!(x.paxos.read_decided_suffix(0), Some(trimmed_log(&cfg)));
+        });
+    }
+
+    let kompact_system =
+        std::mem::take(&mut sys.kompact_system).expect("No KompactSystem found in memory");
+    match kompact_system.shutdown() {
+        Ok(_) => {}
+        Err(e) => panic!("Error on kompact shutdown: {}", e),
+    };
+}
+
+/// Verifies that with `TrimMode::Tick` the entries are removed from the storage by the
+/// compaction timer.
+#[test]
+#[serial]
+fn tick_trim_test() {
+    let cfg = TestConfig {
+        trim_mode: TrimMode::Tick,
+        ..TestConfig::load("trim_worker_test").expect("Test config loaded")
+    };
+    let mut sys = TestSystem::with(cfg);
+    let storages = use_shared_storages(&mut sys, &cfg);
+    sys.start_all_nodes();
+    decide_and_trim(&sys, &cfg);
+
+    wait_until(
+        cfg.wait_timeout,
+        "The entries were not removed from the storage",
+        || {
+            storages
+                .values()
+                .all(|storage| stored_entries(storage) == cfg.num_proposals as usize - cfg.trim_idx)
+        },
+    );
+    for node in sys.nodes.values() {
+        assert!(!node.on_definition(|x| x.paxos.trim_worker().is_pending()));
+    }
+
+    let kompact_system =
+        std::mem::take(&mut sys.kompact_system).expect("No KompactSystem found in memory");
+    match kompact_system.shutdown() {
+        Ok(_) => {}
+        Err(e) => panic!("Error on kompact shutdown: {}", e),
+    };
+}
+
+/// Verifies that a server that crashes before its deferred trim is run recovers its log
+/// correctly and removes the compacted entries when it restarts.
+#[test]
+#[serial]
+fn pending_trim_recovery_test() {
+    let cfg = TestConfig {
+        trim_mode: TrimMode::Worker,
+        ..TestConfig::load("trim_worker_test").expect("Test config loaded")
+    };
+    let mut sys = TestSystem::with(cfg);
+    let storages = use_shared_storages(&mut sys, &cfg);
+    sys.start_all_nodes();
+    let leader = decide_and_trim(&sys, &cfg);
+    let follower = (1..=cfg.num_nodes as NodeId)
+        .find(|pid| *pid != leader)
+        .unwrap();
+    assert!(sys
+        .nodes
+        .get(&follower)
+        .unwrap()
+        .on_definition(|x| x.paxos.trim_worker().is_pending()));
+
+    sys.kill_node(follower);
+    sys.create_node(
+        follower,
+        &cfg,
+        StorageType::Broken(storages[&follower].clone(), Arc::default()),
+    );
+    let follower_px = sys.nodes.get(&follower).unwrap();
+    follower_px.on_definition(|x| {
+        assert!(!x.paxos.trim_worker().is_pending());
+        assert_eq!(x.paxos.read_decided_suffix(0), Some(trimmed_log(&cfg)));
+    });
+    assert_eq!(
+        stored_entries(&storages[&follower]),
+        cfg.num_proposals as usize - cfg.trim_idx
+    );
+    sys.start_node(follower);
+
+    let last = Value::with_id(cfg.num_proposals + 1);
+    let (kprom, kfuture) = promise::<()>();
+    follower_px.on_definition(|x| x.insert_decided_future(Ask::new(kprom, last.clone())));
+    sys.make_proposals(leader, vec![last], cfg.wait_timeout);
+    kfuture
+        .wait_timeout(cfg.wait_timeout)
+        .expect("The restarted server did not decide the new entry");
+
+    let kompact_system =
+        std::mem::take(&mut sys.kompact_system).expect("No KompactSystem found in memory");
+    match kompact_system.shutdown() {
+        Ok(_) => {}
+        Err(e) => panic!("Error on kompact shutdown: {}", e),
+    };
+}
+
+/// Verifies that the log is not compacted past a pinned index while the guard is alive, on the
+/// leader as well as on a follower that is asked to trim by the leader.
+#[test]
+#[serial]
+fn pin_log_test() {
+    let cfg = TestConfig::load("pin_log_test").expect("Test config loaded");
+    let mut sys = TestSystem::with(cfg);
+    sys.start_all_nodes();
+
+    let leader = sys.get_elected_leader(1, cfg.wait_timeout);
+    let mut followers = (1..=cfg.num_nodes as NodeId).filter(|pid| *pid != leader);
+    let (pinned, unpinned) = (followers.next().unwrap(), followers.next().unwrap());
+    let vec_proposals = utils::create_proposals(1, cfg.num_proposals);
+    sys.make_proposals(leader, vec_proposals.clone(), cfg.wait_timeout);
+    wait_until(cfg.wait_timeout, "The proposals were not decided", || {
+        sys.nodes
+            .values()
+            .all(|node| node.on_definition(|x| x.paxos.get_decided_idx()) == vec_proposals.len())
+    });
+    let node = |pid: NodeId| sys.nodes.get(&pid).unwrap();
+    let compacted_idx = |pid: NodeId| node(pid).on_definition(|x| x.paxos.get_compacted_idx());
+
+    let leader_guard = node(leader).on_definition(|x| x.paxos.pin_log(5));
+    let second_leader_guard = node(leader).on_definition(|x| x.paxos.pin_log(5));
+    let follower_guard = node(pinned).on_definition(|x| x.paxos.pin_log(3));
+    assert_eq!(leader_guard.pinned_idx(), 5);
+    // The leader learns that the followers decided the entries from their replies
+    let mut result = Ok(());
+    wait_until(
+        cfg.wait_timeout,
+        "The leader did not learn that all servers decided",
+        || {
+            result = node(leader).on_definition(|x| x.paxos.trim(Some(8)));
+            !matches!(result, Err(CompactionErr::NotAllDecided(_)))
+        },
+    );
+    assert!(matches!(result, Err(CompactionErr::Pinned(5))));
+    assert_eq!(compacted_idx(leader), 0);
+    // The hold is only released once all guards of the index are dropped
+    drop(leader_guard);
+    node(leader).on_definition(|x| {
+        assert!(matches!(
+            x.paxos.trim(Some(8)),
+            Err(CompactionErr::Pinned(5))
+        ));
+        x.paxos.trim(Some(5)).expect("Failed to trim");
+    });
+    wait_until(
+        cfg.wait_timeout,
+        "The unpinned follower did not trim",
+        || compacted_idx(unpinned) == 5,
+    );
+    assert_eq!(compacted_idx(pinned), 0);
+    assert_eq!(
+        node(pinned).on_definition(|x| x.paxos.read(3)),
+        Some(LogEntry::Decided(vec_proposals[3].clone()))
+    );
+
+    drop(follower_guard);
+    drop(second_leader_guard);
+    node(leader).on_definition(|x| x.paxos.trim(Some(8)).expect("Failed to trim"));
+    wait_until(cfg.wait_timeout, "The servers did not trim", || {
+        (1..=cfg.num_nodes as NodeId).all(|pid| compacted_idx(pid) == 8)
+    });
+
+    let kompact_system =
+        std::mem::take(&mut sys.kompact_system).expect("No KompactSystem found in memory");
+    match kompact_system.shutdown() {
+        Ok(_) => {}
+        Err(e) => panic!("Error on kompact shutdown: {}", e),
+    };
+}
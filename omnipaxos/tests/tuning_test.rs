@@ -0,0 +1,87 @@
+pub mod utils;
+
+use omnipaxos::{tuning::TuningSeverity, util::NodeId, OmniPaxos, ServerConfig};
+use omnipaxos_storage::memory_storage::MemoryStorage;
+use serial_test::serial;
+use std::collections::HashMap;
+use utils::{tick_until, TestConfig, Value};
+
+fn presets() -> Vec<(&'static str, ServerConfig)> {
+    vec![
+        ("low_latency", ServerConfig::low_latency()),
+        ("high_throughput", ServerConfig::high_throughput()),
+        ("wan", ServerConfig::wan()),
+    ]
+}
+
+fn cluster(
+    cfg: &TestConfig,
+    preset: &ServerConfig,
+) -> HashMap<NodeId, OmniPaxos<Value, MemoryStorage<Value>>> {
+    utils::create_cluster(cfg, |server_cfg| {
+        *server_cfg = ServerConfig {
+            pid: server_cfg.pid,
+            ..preset.clone()
+        }
+    })
+}
+
+/// Verifies that the presets don't cause any warnings and that a cluster configured with each
+/// of them decides entries.
+#[test]
+#[serial]
+fn presets_test() {
+    let cfg = TestConfig::load("tuning_test").expect("Test config loaded");
+    for (name, preset) in presets() {
+        let report = preset.tuning_report();
+        assert_eq!(report.warnings().count(), 0, "{}:\n{}", name, report);
+        let mut nodes = cluster(&cfg, &preset);
+        tick_until(&mut nodes, |nodes| {
+            nodes.values().all(|op| op.get_current_leader().is_some())
+        });
+        let leader = nodes[&1].get_current_leader().unwrap();
+        let op = nodes.get_mut(&leader).unwrap();
+        for v in utils::create_proposals(1, cfg.num_proposals) {
+            op.append(v).expect("Failed to append");
+        }
+        tick_until(&mut nodes, |nodes| {
+            nodes
+                .values()
+                .all(|op| op.get_decided_idx() == cfg.num_proposals as usize)
+        });
+    }
+}
+
+/// Verifies that settings which work against each other are reported as warnings, before the
+/// notes on deliberate trade-offs.
+#[test]
+fn tuning_report_test() {
+    let config = ServerConfig {
+        batch_size: 100,
+        flush_batch_tick_timeout: 50,
+        election_tick_timeout: 10,
+        reorder_window: 4,
+        ..Default::default()
+    };
+    let report = config.tuning_report();
+    let warnings: Vec<_> = report.warnings().map(|n| n.fields).collect();
+    assert_eq!(
+        warnings,
+        vec![&["flush_batch_tick_timeout", "election_tick_timeout"]]
+    );
+    assert_eq!(report.notes[0].severity, TuningSeverity::Warning);
+    assert!(report
+        .notes
+        .iter()
+        .any(|n| n.fields.contains(&"reorder_window")));
+
+    let config = ServerConfig {
+        batch_byte_size: 1024,
+        ..Default::default()
+    };
+    let report = config.tuning_report();
+    assert_eq!(
+        report.warnings().map(|n| n.fields).collect::<Vec<_>>(),
+        vec![&["batch_byte_size", "batch_size"]]
+    );
+}
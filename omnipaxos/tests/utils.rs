@@ -4,13 +4,18 @@ use omnipaxos::{
     ballot_leader_election::Ballot,
     macros::*,
     messages::Message,
-    storage::{Entry, Snapshot, Storage, StorageResult},
-    util::{FlexibleQuorum, NodeId},
-    ClusterConfig, OmniPaxosConfig, ServerConfig,
+    reconfiguration::ConfigurationManager,
+    storage::{
+        archive::ArchiveCodec, Entry, LogStorage, Snapshot, StateStorage, Storage, StorageResult,
+    },
+    util::{FlexibleQuorum, NodeId, Region},
+    ClusterConfig, FollowerAppendPolicy, LowSpacePolicy, OmniPaxos, OmniPaxosConfig,
+    ReconfigurationValidator, ServerConfig, TrimMode,
 };
 use omnipaxos_storage::{
     memory_storage::MemoryStorage,
     persistent_storage::{PersistentStorage, PersistentStorageConfig},
+    ring_buffer_storage::RingBufferStorage,
 };
 use serde::{Deserialize, Deserializer, Serialize};
 use std::{
@@ -18,7 +23,8 @@ use std::{
     error::Error,
     fs, str,
     sync::{Arc, Mutex},
-    time::Duration,
+    thread,
+    time::{Duration, Instant},
 };
 use tempfile::TempDir;
 
@@ -40,6 +46,17 @@ where
     Ok(Duration::from_millis(val))
 }
 
+/// Like `deserialize_duration_millis`, for optional durations.
+fn deserialize_optional_duration_millis<'de, D>(
+    deserializer: D,
+) -> Result<Option<Duration>, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    let val: Option<u64> = Deserialize::deserialize(deserializer)?;
+    Ok(val.map(Duration::from_millis))
+}
+
 pub fn create_proposals(from: u64, to: u64) -> Vec<Value> {
     #[cfg(feature = "unicache")]
     {
@@ -66,6 +83,18 @@ pub fn create_proposals(from: u64, to: u64) -> Vec<Value> {
     }
 }
 
+/// Polls `condition` until it holds. Panics with `msg` if it does not hold within `timeout`.
+pub fn wait_until<F>(timeout: Duration, msg: &str, mut condition: F)
+where
+    F: FnMut() -> bool,
+{
+    let deadline = Instant::now() + timeout;
+    while !condition() {
+        assert!(Instant::now() < deadline, "{}", msg);
+        thread::sleep(CHECK_DECIDED_TIMEOUT);
+    }
+}
+
 /// Configuration for `TestSystem`. TestConfig loads the values from
 /// the configuration file `/tests/config/test.toml` using toml
 #[derive(Deserialize, Clone, Copy)]
@@ -73,6 +102,9 @@ pub fn create_proposals(from: u64, to: u64) -> Vec<Value> {
 pub struct TestConfig {
     pub num_threads: usize,
     pub num_nodes: usize,
+    /// The backup nodes get the pids after the `num_nodes` voting nodes.
+    pub num_backup_nodes: usize,
+    pub configuration_id: u32,
     #[serde(rename(deserialize = "wait_timeout_ms"))]
     #[serde(deserialize_with = "deserialize_duration_millis")]
     pub wait_timeout: Duration,
@@ -90,7 +122,42 @@ pub struct TestConfig {
     pub num_elections: u64,
     pub trim_idx: usize,
     pub flexible_quorum: Option<(usize, usize)>,
+    /// Splits the nodes into this many regions of consecutive pids, relayed by their first node.
+    pub num_regions: usize,
+    pub hierarchical_quorum: bool,
     pub batch_size: usize,
+    pub batch_byte_size: Option<usize>,
+    pub sync_batch_size: usize,
+    pub sync_batch_byte_size: Option<usize>,
+    pub max_sync_suffix_entries: Option<usize>,
+    pub pull_tick_timeout: Option<u64>,
+    pub slow_follower_lag: Option<usize>,
+    pub slow_follower_tick_timeout: Option<u64>,
+    #[serde(rename(deserialize = "slow_storage_latency_ms"))]
+    #[serde(deserialize_with = "deserialize_optional_duration_millis")]
+    pub slow_storage_latency: Option<Duration>,
+    pub slow_storage_tick_timeout: Option<u64>,
+    pub step_down_on_slow_storage: bool,
+    pub storage_size_limit: Option<u64>,
+    pub backup_tick_timeout: Option<u64>,
+    pub snapshot_interval: Option<usize>,
+    pub compaction_tick_timeout: Option<u64>,
+    pub misbehaving_peer_threshold: Option<u64>,
+    pub decided_cache_size: Option<usize>,
+    pub leader_priority: u32,
+    pub track_entry_ballots: bool,
+    /// Can't be loaded from the config file, set it on the loaded config instead.
+    #[serde(skip)]
+    pub low_space_policy: LowSpacePolicy,
+    /// Can't be loaded from the config file, set it on the loaded config instead.
+    #[serde(skip)]
+    pub trim_mode: TrimMode,
+    /// Can't be loaded from the config file, set it on the loaded config instead.
+    #[serde(skip)]
+    pub follower_append_policy: FollowerAppendPolicy,
+    /// Can't be loaded from the config file, set it on the loaded config instead.
+    #[serde(skip)]
+    pub reconfiguration_validator: Option<ReconfigurationValidator>,
     // #[cfg(feature = "unicache")]
     pub num_iterations: u64,
 }
@@ -114,12 +181,31 @@ impl TestConfig {
                 read_quorum_size,
                 write_quorum_size,
             });
+        let regions = match self.num_regions {
+            0 => vec![],
+            num_regions => all_pids
+                .chunks(self.num_nodes.div_ceil(num_regions))
+                .enumerate()
+                .map(|(i, nodes)| Region {
+                    name: format!("region{i}"),
+                    nodes: nodes.to_vec(),
+                    relay: nodes.first().copied(),
+                })
+                .collect(),
+        };
+        let backup_pids = (self.num_nodes + 1..=self.num_nodes + self.num_backup_nodes)
+            .map(|pid| pid as NodeId)
+            .collect();
         let cluster_config = ClusterConfig {
-            configuration_id: 1,
+            configuration_id: self.configuration_id,
             nodes: all_pids,
+            backup_nodes: backup_pids,
             flexible_quorum,
+            regions,
+            hierarchical_quorum: self.hierarchical_quorum,
+            ..Default::default()
         };
-        let server_config = ServerConfig {
+        let mut server_config = ServerConfig {
             pid,
             election_tick_timeout: 1,
             // Make tick timeouts relative to election timeout
@@ -128,8 +214,42 @@ impl TestConfig {
             flush_batch_tick_timeout: self.flush_batch_timeout.as_millis() as u64
                 / self.election_timeout.as_millis() as u64,
             batch_size: self.batch_size,
+            sync_batch_size: self.sync_batch_size,
+            max_sync_suffix_entries: self.max_sync_suffix_entries,
+            pull_tick_timeout: self.pull_tick_timeout,
+            slow_follower_lag: self.slow_follower_lag,
+            slow_follower_tick_timeout: self.slow_follower_tick_timeout,
+            slow_storage_latency: self.slow_storage_latency,
+            step_down_on_slow_storage: self.step_down_on_slow_storage,
+            storage_size_limit: self.storage_size_limit,
+            low_space_policy: self.low_space_policy,
+            trim_mode: self.trim_mode,
+            backup_tick_timeout: self.backup_tick_timeout,
+            snapshot_interval: self.snapshot_interval,
+            reconfiguration_validator: self.reconfiguration_validator,
+            leader_priority: self.leader_priority,
+            track_entry_ballots: self.track_entry_ballots,
+            follower_append_policy: self.follower_append_policy,
             ..Default::default()
         };
+        if let Some(slow_storage_tick_timeout) = self.slow_storage_tick_timeout {
+            server_config.slow_storage_tick_timeout = slow_storage_tick_timeout;
+        }
+        if let Some(compaction_tick_timeout) = self.compaction_tick_timeout {
+            server_config.compaction_tick_timeout = compaction_tick_timeout;
+        }
+        if let Some(threshold) = self.misbehaving_peer_threshold {
+            server_config.misbehaving_peer_threshold = threshold;
+        }
+        if let Some(decided_cache_size) = self.decided_cache_size {
+            server_config.decided_cache_size = decided_cache_size;
+        }
+        if let Some(batch_byte_size) = self.batch_byte_size {
+            server_config.batch_byte_size = batch_byte_size;
+        }
+        if let Some(sync_batch_byte_size) = self.sync_batch_byte_size {
+            server_config.sync_batch_byte_size = sync_batch_byte_size;
+        }
         OmniPaxosConfig {
             cluster_config,
             server_config,
@@ -142,6 +262,8 @@ impl Default for TestConfig {
         Self {
             num_threads: 3,
             num_nodes: 3,
+            num_backup_nodes: 0,
+            configuration_id: 1,
             wait_timeout: Duration::from_millis(5000),
             election_timeout: Duration::from_millis(200),
             resend_message_timeout: Duration::from_millis(500),
@@ -151,7 +273,31 @@ impl Default for TestConfig {
             num_elections: 0,
             trim_idx: 0,
             flexible_quorum: None,
+            num_regions: 0,
+            hierarchical_quorum: false,
             batch_size: 1,
+            batch_byte_size: None,
+            sync_batch_size: 0,
+            sync_batch_byte_size: None,
+            max_sync_suffix_entries: None,
+            pull_tick_timeout: None,
+            slow_follower_lag: None,
+            slow_follower_tick_timeout: None,
+            slow_storage_latency: None,
+            slow_storage_tick_timeout: None,
+            step_down_on_slow_storage: false,
+            storage_size_limit: None,
+            low_space_policy: LowSpacePolicy::default(),
+            trim_mode: TrimMode::default(),
+            backup_tick_timeout: None,
+            snapshot_interval: None,
+            compaction_tick_timeout: None,
+            misbehaving_peer_threshold: None,
+            decided_cache_size: None,
+            leader_priority: 0,
+            track_entry_ballots: false,
+            follower_append_policy: FollowerAppendPolicy::default(),
+            reconfiguration_validator: None,
             num_iterations: 0,
         }
     }
@@ -164,6 +310,7 @@ pub enum StorageTypeSelector {
     Persistent,
     Memory,
     Broken(BrokenStorageConfig),
+    RingBuffer { capacity: usize },
 }
 
 #[derive(Clone, Copy, Debug, Deserialize, Default)]
@@ -172,6 +319,9 @@ pub struct BrokenStorageConfig {
     /// Fail once after this many operations
     fail_in: usize,
     op_counter: usize,
+    /// Delay every operation by this long
+    #[serde(skip)]
+    delay: Option<Duration>,
 }
 
 impl BrokenStorageConfig {
@@ -181,6 +331,9 @@ impl BrokenStorageConfig {
     pub fn tick(&mut self) -> StorageResult<()> {
         let err = Err("test error from mocked broken storage".into());
         self.op_counter += 1;
+        if let Some(delay) = self.delay {
+            thread::sleep(delay);
+        }
         if self.fail_in > 0 {
             self.fail_in -= 1;
             if self.fail_in == 0 {
@@ -195,6 +348,12 @@ impl BrokenStorageConfig {
     pub fn schedule_failure_in(&mut self, n: usize) {
         self.fail_in = n;
     }
+
+    /// Delays every following operation by `delay` to mock a slow storage, or stops delaying
+    /// them if `None`.
+    pub fn set_delay(&mut self, delay: Option<Duration>) {
+        self.delay = delay;
+    }
 }
 
 /// An enum which can either be a 'PersistentStorage' or 'MemoryStorage', the type depends on the
@@ -206,6 +365,7 @@ where
 {
     Persistent(PersistentStorage<T>),
     Memory(MemoryStorage<T>),
+    RingBuffer(RingBufferStorage<T>),
     /// Mocks a storage that fails depending of the config.
     /// Arc<Mutex<_>> is needed since we need to mutate conf through immutable references.
     Broken(
@@ -230,6 +390,9 @@ where
                 Arc::new(Mutex::new(MemoryStorage::default())),
                 Arc::new(Mutex::new(config)),
             ),
+            StorageTypeSelector::RingBuffer { capacity } => {
+                StorageType::RingBuffer(RingBufferStorage::with_capacity(capacity))
+            }
         }
     }
 
@@ -238,7 +401,7 @@ where
     }
 }
 
-impl<T> Storage<T> for StorageType<T>
+impl<T> LogStorage<T> for StorageType<T>
 where
     T: Entry + Serialize + for<'a> Deserialize<'a>,
     T::Snapshot: Serialize + for<'a> Deserialize<'a>,
@@ -250,6 +413,7 @@ where
         match self {
             StorageType::Persistent(persist_s) => persist_s.write_atomically(ops),
             StorageType::Memory(mem_s) => mem_s.write_atomically(ops),
+            StorageType::RingBuffer(ring_s) => ring_s.write_atomically(ops),
             StorageType::Broken(mem_s, conf) => {
                 // NOTE: Can't properly test for atomicity since we can't tick between writes in batch.
                 conf.lock().unwrap().tick()?;
@@ -262,6 +426,7 @@ where
         match self {
             StorageType::Persistent(persist_s) => persist_s.append_entry(entry),
             StorageType::Memory(mem_s) => mem_s.append_entry(entry),
+            StorageType::RingBuffer(ring_s) => ring_s.append_entry(entry),
             StorageType::Broken(mem_s, conf) => {
                 conf.lock().unwrap().tick()?;
                 mem_s.lock().unwrap().append_entry(entry)
@@ -273,6 +438,7 @@ where
         match self {
             StorageType::Persistent(persist_s) => persist_s.append_entries(entries),
             StorageType::Memory(mem_s) => mem_s.append_entries(entries),
+            StorageType::RingBuffer(ring_s) => ring_s.append_entries(entries),
             StorageType::Broken(mem_s, conf) => {
                 conf.lock().unwrap().tick()?;
                 mem_s.lock().unwrap().append_entries(entries)
@@ -284,6 +450,7 @@ where
         match self {
             StorageType::Persistent(persist_s) => persist_s.append_on_prefix(from_idx, entries),
             StorageType::Memory(mem_s) => mem_s.append_on_prefix(from_idx, entries),
+            StorageType::RingBuffer(ring_s) => ring_s.append_on_prefix(from_idx, entries),
             StorageType::Broken(mem_s, conf) => {
                 conf.lock().unwrap().tick()?;
                 mem_s.lock().unwrap().append_on_prefix(from_idx, entries)
@@ -291,10 +458,89 @@ where
         }
     }
 
+    fn get_entries(&self, from: usize, to: usize) -> StorageResult<Vec<T>> {
+        match self {
+            StorageType::Persistent(persist_s) => persist_s.get_entries(from, to),
+            StorageType::Memory(mem_s) => mem_s.get_entries(from, to),
+            StorageType::RingBuffer(ring_s) => ring_s.get_entries(from, to),
+            StorageType::Broken(mem_s, conf) => {
+                conf.lock().unwrap().tick()?;
+                mem_s.lock().unwrap().get_entries(from, to)
+            }
+        }
+    }
+
+    fn get_log_len(&self) -> StorageResult<usize> {
+        match self {
+            StorageType::Persistent(persist_s) => persist_s.get_log_len(),
+            StorageType::Memory(mem_s) => mem_s.get_log_len(),
+            StorageType::RingBuffer(ring_s) => ring_s.get_log_len(),
+            StorageType::Broken(mem_s, conf) => {
+                conf.lock().unwrap().tick()?;
+                mem_s.lock().unwrap().get_log_len()
+            }
+        }
+    }
+
+    fn get_suffix(&self, from: usize) -> StorageResult<Vec<T>> {
+        match self {
+            StorageType::Persistent(persist_s) => persist_s.get_suffix(from),
+            StorageType::Memory(mem_s) => mem_s.get_suffix(from),
+            StorageType::RingBuffer(ring_s) => ring_s.get_suffix(from),
+            StorageType::Broken(mem_s, conf) => {
+                conf.lock().unwrap().tick()?;
+                mem_s.lock().unwrap().get_suffix(from)
+            }
+        }
+    }
+
+    fn trim(&mut self, idx: usize) -> StorageResult<()> {
+        match self {
+            StorageType::Persistent(persist_s) => persist_s.trim(idx),
+            StorageType::Memory(mem_s) => mem_s.trim(idx),
+            StorageType::RingBuffer(ring_s) => ring_s.trim(idx),
+            StorageType::Broken(mem_s, conf) => {
+                conf.lock().unwrap().tick()?;
+                mem_s.lock().unwrap().trim(idx)
+            }
+        }
+    }
+
+    fn approximate_size(&self) -> StorageResult<Option<u64>> {
+        match self {
+            StorageType::Persistent(persist_s) => persist_s.approximate_size(),
+            StorageType::Memory(mem_s) => mem_s.approximate_size(),
+            StorageType::RingBuffer(ring_s) => ring_s.approximate_size(),
+            StorageType::Broken(mem_s, conf) => {
+                conf.lock().unwrap().tick()?;
+                mem_s.lock().unwrap().approximate_size()
+            }
+        }
+    }
+
+    fn get_capacity(&self) -> StorageResult<Option<usize>> {
+        match self {
+            StorageType::Persistent(persist_s) => persist_s.get_capacity(),
+            StorageType::Memory(mem_s) => mem_s.get_capacity(),
+            StorageType::RingBuffer(ring_s) => ring_s.get_capacity(),
+            StorageType::Broken(mem_s, conf) => {
+                conf.lock().unwrap().tick()?;
+                mem_s.lock().unwrap().get_capacity()
+            }
+        }
+    }
+}
+
+impl<T> StateStorage<T> for StorageType<T>
+where
+    T: Entry + Serialize + for<'a> Deserialize<'a>,
+    T::Snapshot: Serialize + for<'a> Deserialize<'a>,
+{
     fn set_promise(&mut self, n_prom: Ballot) -> StorageResult<()> {
         match self {
             StorageType::Persistent(persist_s) => persist_s.set_promise(n_prom),
             StorageType::Memory(mem_s) => mem_s.set_promise(n_prom),
+            StorageType::RingBuffer(ring_s) => ring_s.set_promise(n_prom),
             StorageType::Broken(mem_s, conf) => {
                 conf.lock().unwrap().tick()?;
                 mem_s.lock().unwrap().set_promise(n_prom)
@@ -306,6 +552,7 @@ where
         match self {
             StorageType::Persistent(persist_s) => persist_s.set_decided_idx(ld),
             StorageType::Memory(mem_s) => mem_s.set_decided_idx(ld),
+            StorageType::RingBuffer(ring_s) => ring_s.set_decided_idx(ld),
             StorageType::Broken(mem_s, conf) => {
                 conf.lock().unwrap().tick()?;
                 mem_s.lock().unwrap().set_decided_idx(ld)
@@ -317,6 +564,7 @@ where
         match self {
             StorageType::Persistent(persist_s) => persist_s.get_decided_idx(),
             StorageType::Memory(mem_s) => mem_s.get_decided_idx(),
+            StorageType::RingBuffer(ring_s) => ring_s.get_decided_idx(),
             StorageType::Broken(mem_s, conf) => {
                 conf.lock().unwrap().tick()?;
                 mem_s.lock().unwrap().get_decided_idx()
@@ -328,6 +576,7 @@ where
         match self {
             StorageType::Persistent(persist_s) => persist_s.set_accepted_round(na),
             StorageType::Memory(mem_s) => mem_s.set_accepted_round(na),
+            StorageType::RingBuffer(ring_s) => ring_s.set_accepted_round(na),
             StorageType::Broken(mem_s, conf) => {
                 conf.lock().unwrap().tick()?;
                 mem_s.lock().unwrap().set_accepted_round(na)
@@ -339,6 +588,7 @@ where
         match self {
             StorageType::Persistent(persist_s) => persist_s.get_accepted_round(),
             StorageType::Memory(mem_s) => mem_s.get_accepted_round(),
+            StorageType::RingBuffer(ring_s) => ring_s.get_accepted_round(),
             StorageType::Broken(mem_s, conf) => {
                 conf.lock().unwrap().tick()?;
                 mem_s.lock().unwrap().get_accepted_round()
@@ -346,46 +596,62 @@ where
         }
     }
 
-    fn get_entries(&self, from: usize, to: usize) -> StorageResult<Vec<T>> {
+    fn get_promise(&self) -> StorageResult<Option<Ballot>> {
         match self {
-            StorageType::Persistent(persist_s) => persist_s.get_entries(from, to),
-            StorageType::Memory(mem_s) => mem_s.get_entries(from, to),
+            StorageType::Persistent(persist_s) => persist_s.get_promise(),
+            StorageType::Memory(mem_s) => mem_s.get_promise(),
+            StorageType::RingBuffer(ring_s) => ring_s.get_promise(),
             StorageType::Broken(mem_s, conf) => {
                 conf.lock().unwrap().tick()?;
-                mem_s.lock().unwrap().get_entries(from, to)
+                mem_s.lock().unwrap().get_promise()
             }
         }
     }
 
-    fn get_log_len(&self) -> StorageResult<usize> {
+    fn set_ble_ballot(&mut self, ballot: Ballot) -> StorageResult<()> {
         match self {
-            StorageType::Persistent(persist_s) => persist_s.get_log_len(),
-            StorageType::Memory(mem_s) => mem_s.get_log_len(),
+            StorageType::Persistent(persist_s) => persist_s.set_ble_ballot(ballot),
+            StorageType::Memory(mem_s) => mem_s.set_ble_ballot(ballot),
+            StorageType::RingBuffer(ring_s) => ring_s.set_ble_ballot(ballot),
             StorageType::Broken(mem_s, conf) => {
                 conf.lock().unwrap().tick()?;
-                mem_s.lock().unwrap().get_log_len()
+                mem_s.lock().unwrap().set_ble_ballot(ballot)
             }
         }
     }
 
-    fn get_suffix(&self, from: usize) -> StorageResult<Vec<T>> {
+    fn get_ble_ballot(&self) -> StorageResult<Option<Ballot>> {
         match self {
-            StorageType::Persistent(persist_s) => persist_s.get_suffix(from),
-            StorageType::Memory(mem_s) => mem_s.get_suffix(from),
+            StorageType::Persistent(persist_s) => persist_s.get_ble_ballot(),
+            StorageType::Memory(mem_s) => mem_s.get_ble_ballot(),
+            StorageType::RingBuffer(ring_s) => ring_s.get_ble_ballot(),
             StorageType::Broken(mem_s, conf) => {
                 conf.lock().unwrap().tick()?;
-                mem_s.lock().unwrap().get_suffix(from)
+                mem_s.lock().unwrap().get_ble_ballot()
             }
         }
     }
 
-    fn get_promise(&self) -> StorageResult<Option<Ballot>> {
+    fn set_standby_promise(&mut self, standby: Option<Ballot>) -> StorageResult<()> {
         match self {
-            StorageType::Persistent(persist_s) => persist_s.get_promise(),
-            StorageType::Memory(mem_s) => mem_s.get_promise(),
+            StorageType::Persistent(persist_s) => persist_s.set_standby_promise(standby),
+            StorageType::Memory(mem_s) => mem_s.set_standby_promise(standby),
+            StorageType::RingBuffer(ring_s) => ring_s.set_standby_promise(standby),
             StorageType::Broken(mem_s, conf) => {
                 conf.lock().unwrap().tick()?;
-                mem_s.lock().unwrap().get_promise()
+                mem_s.lock().unwrap().set_standby_promise(standby)
+            }
+        }
+    }
+
+    fn get_standby_promise(&self) -> StorageResult<Option<Ballot>> {
+        match self {
+            StorageType::Persistent(persist_s) => persist_s.get_standby_promise(),
+            StorageType::Memory(mem_s) => mem_s.get_standby_promise(),
+            StorageType::RingBuffer(ring_s) => ring_s.get_standby_promise(),
+            StorageType::Broken(mem_s, conf) => {
+                conf.lock().unwrap().tick()?;
+                mem_s.lock().unwrap().get_standby_promise()
             }
         }
     }
@@ -394,6 +660,7 @@ where
         match self {
             StorageType::Persistent(persist_s) => persist_s.set_stopsign(s),
             StorageType::Memory(mem_s) => mem_s.set_stopsign(s),
+            StorageType::RingBuffer(ring_s) => ring_s.set_stopsign(s),
             StorageType::Broken(mem_s, conf) => {
                 conf.lock().unwrap().tick()?;
                 mem_s.lock().unwrap().set_stopsign(s)
@@ -405,6 +672,7 @@ where
         match self {
             StorageType::Persistent(persist_s) => persist_s.get_stopsign(),
             StorageType::Memory(mem_s) => mem_s.get_stopsign(),
+            StorageType::RingBuffer(ring_s) => ring_s.get_stopsign(),
             StorageType::Broken(mem_s, conf) => {
                 conf.lock().unwrap().tick()?;
                 mem_s.lock().unwrap().get_stopsign()
@@ -412,21 +680,11 @@ where
         }
     }
 
-    fn trim(&mut self, idx: usize) -> StorageResult<()> {
-        match self {
-            StorageType::Persistent(persist_s) => persist_s.trim(idx),
-            StorageType::Memory(mem_s) => mem_s.trim(idx),
-            StorageType::Broken(mem_s, conf) => {
-                conf.lock().unwrap().tick()?;
-                mem_s.lock().unwrap().trim(idx)
-            }
-        }
-    }
-
     fn set_compacted_idx(&mut self, idx: usize) -> StorageResult<()> {
         match self {
             StorageType::Persistent(persist_s) => persist_s.set_compacted_idx(idx),
             StorageType::Memory(mem_s) => mem_s.set_compacted_idx(idx),
+            StorageType::RingBuffer(ring_s) => ring_s.set_compacted_idx(idx),
             StorageType::Broken(mem_s, conf) => {
                 conf.lock().unwrap().tick()?;
                 mem_s.lock().unwrap().set_compacted_idx(idx)
@@ -438,6 +696,7 @@ where
         match self {
             StorageType::Persistent(persist_s) => persist_s.get_compacted_idx(),
             StorageType::Memory(mem_s) => mem_s.get_compacted_idx(),
+            StorageType::RingBuffer(ring_s) => ring_s.get_compacted_idx(),
             StorageType::Broken(mem_s, conf) => {
                 conf.lock().unwrap().tick()?;
                 mem_s.lock().unwrap().get_compacted_idx()
@@ -449,6 +708,7 @@ where
         match self {
             StorageType::Persistent(persist_s) => persist_s.set_snapshot(snapshot),
             StorageType::Memory(mem_s) => mem_s.set_snapshot(snapshot),
+            StorageType::RingBuffer(ring_s) => ring_s.set_snapshot(snapshot),
             StorageType::Broken(mem_s, conf) => {
                 conf.lock().unwrap().tick()?;
                 mem_s.lock().unwrap().set_snapshot(snapshot)
@@ -460,6 +720,7 @@ where
         match self {
             StorageType::Persistent(persist_s) => persist_s.get_snapshot(),
             StorageType::Memory(mem_s) => mem_s.get_snapshot(),
+            StorageType::RingBuffer(ring_s) => ring_s.get_snapshot(),
             StorageType::Broken(mem_s, conf) => {
                 conf.lock().unwrap().tick()?;
                 mem_s.lock().unwrap().get_snapshot()
@@ -492,7 +753,7 @@ impl TestSystem {
         let mut nodes = HashMap::new();
         let mut omni_refs: HashMap<NodeId, ActorRef<Message<Value>>> = HashMap::new();
 
-        for pid in 1..=test_config.num_nodes as NodeId {
+        for pid in 1..=(test_config.num_nodes + test_config.num_backup_nodes) as NodeId {
             let op_config = test_config.into_omnipaxos_config(pid);
             let storage: StorageType<Value> =
                 StorageType::with(test_config.storage_type, &format!("{temp_dir_path}{pid}"));
@@ -589,6 +850,26 @@ impl TestSystem {
         self.nodes.insert(pid, omni_replica);
     }
 
+    /// Recreates the servers before they are started on storages whose failures can be
+    /// scheduled through the returned configs.
+    pub fn use_broken_storages(
+        &mut self,
+        test_config: &TestConfig,
+    ) -> HashMap<NodeId, Arc<Mutex<BrokenStorageConfig>>> {
+        (1..=test_config.num_nodes as NodeId)
+            .map(|pid| {
+                let storage_conf: Arc<Mutex<BrokenStorageConfig>> = Arc::default();
+                self.kill_node(pid);
+                self.create_node(
+                    pid,
+                    test_config,
+                    StorageType::Broken(Arc::default(), storage_conf.clone()),
+                );
+                (pid, storage_conf)
+            })
+            .collect()
+    }
+
     pub fn start_node(&self, pid: NodeId) {
         let node = self
             .nodes
@@ -741,7 +1022,7 @@ pub mod omnireplica {
         ballot_leader_election::Ballot,
         messages::Message,
         util::{LogEntry, NodeId},
-        OmniPaxos,
+        OmniPaxos, Timer,
     };
     use std::collections::{HashMap, HashSet};
 
@@ -752,6 +1033,13 @@ pub mod omnireplica {
         pid: NodeId,
         pub peers: HashMap<NodeId, ActorRef<Message<Value>>>,
         pub peer_disconnections: HashSet<NodeId>,
+        /// Used to simulate the loss of the outgoing messages for which it returns true.
+        pub drop_outgoing: Option<fn(&Message<Value>) -> bool>,
+        /// Used to simulate blocked connections. The outgoing messages to these peers are left
+        /// queued in OmniPaxos until the peer is removed again.
+        pub blocked_peers: HashSet<NodeId>,
+        /// Used to drive these timers individually with `handle_timer()` instead of `tick()`.
+        pub timers: Option<Vec<Timer>>,
         paxos_timer: Option<ScheduledTimer>,
         tick_timer: Option<ScheduledTimer>,
         tick_timeout: Duration,
@@ -776,7 +1064,16 @@ pub mod omnireplica {
             self.tick_timer =
                 Some(
                     self.schedule_periodic(self.tick_timeout, self.tick_timeout, move |c, _| {
-                        c.paxos.tick();
+                        match &c.timers {
+                            Some(timers) => {
+                                for timer in timers {
+                                    c.paxos
+                                        .handle_timer(*timer)
+                                        .expect("Failed to handle timer");
+                                }
+                            }
+                            None => c.paxos.tick().expect("Failed to tick"),
+                        }
                         let promise = c.paxos.get_promise();
                         if promise > c.current_leader_ballot {
                             c.current_leader_ballot = promise;
@@ -807,6 +1104,9 @@ pub mod omnireplica {
                 pid,
                 peers: HashMap::new(),
                 peer_disconnections: HashSet::new(),
+                drop_outgoing: None,
+                blocked_peers: HashSet::new(),
+                timers: None,
                 paxos_timer: None,
                 tick_timer: None,
                 tick_timeout,
@@ -823,8 +1123,29 @@ pub mod omnireplica {
         }
 
         fn send_outgoing_msgs(&mut self) {
-            let outgoing = self.paxos.outgoing_messages();
-            for out in outgoing {
+            let outgoing = if self.blocked_peers.is_empty() {
+                self.paxos.outgoing_messages()
+            } else {
+                let unblocked: Vec<NodeId> = self
+                    .peers
+                    .keys()
+                    .filter(|pid| !self.blocked_peers.contains(pid))
+                    .copied()
+                    .collect();
+                unblocked
+                    .into_iter()
+                    .flat_map(|pid| self.paxos.outgoing_for(pid))
+                    .collect()
+            };
+            self.send(outgoing);
+        }
+
+        /// Sends `msgs` to their receivers as if they were taken from the outgoing messages.
+        pub fn send(&mut self, msgs: Vec<Message<Value>>) {
+            for out in msgs {
+                if self.drop_outgoing.is_some_and(|drop| drop(&out)) {
+                    continue;
+                }
                 if self.is_connected_to(&out.get_receiver()) {
                     match self.peers.get(&out.get_receiver()) {
                         Some(receiver) => receiver.tell(out),
@@ -901,7 +1222,9 @@ pub mod omnireplica {
         type Message = Message<Value>;
 
         fn receive_local(&mut self, msg: Self::Message) -> Handled {
-            self.paxos.handle_incoming(msg);
+            self.paxos
+                .handle_incoming(msg)
+                .expect("Failed to handle message");
             Handled::Ok
         }
 
@@ -971,6 +1294,10 @@ impl Snapshot<Value> for ValueSnapshot {
     fn use_snapshots() -> bool {
         true
     }
+
+    fn is_equivalent(&self, other: &Self) -> Option<bool> {
+        Some(self.snapshotted == other.snapshotted)
+    }
 }
 
 impl ValueSnapshot {
@@ -987,6 +1314,225 @@ impl PartialEq<Self> for ValueSnapshot {
 
 impl Eq for ValueSnapshot {}
 
+/// Encodes values and snapshots as JSON, e.g., in archives.
+pub struct JsonCodec;
+
+impl ArchiveCodec<Value> for JsonCodec {
+    fn encode_entry(&self, entry: &Value) -> Result<Vec<u8>, Box<dyn Error>> {
+        Ok(serde_json::to_vec(entry)?)
+    }
+
+    fn decode_entry(&self, bytes: &[u8]) -> Result<Value, Box<dyn Error>> {
+        Ok(serde_json::from_slice(bytes)?)
+    }
+
+    fn encode_snapshot(&self, snapshot: &ValueSnapshot) -> Result<Vec<u8>, Box<dyn Error>> {
+        Ok(serde_json::to_vec(snapshot)?)
+    }
+
+    fn decode_snapshot(&self, bytes: &[u8]) -> Result<ValueSnapshot, Box<dyn Error>> {
+        Ok(serde_json::from_slice(bytes)?)
+    }
+}
+
+/// Declares the UniCache types of an `Entry` that is never encoded. Lets the entry types that the
+/// tests define themselves compile with the `unicache` feature.
+#[allow(unused_macros)]
+macro_rules! no_unicache {
+    () => {
+        #[cfg(feature = "unicache")]
+        type Encoded = ();
+        #[cfg(feature = "unicache")]
+        type Encodable = ();
+        #[cfg(feature = "unicache")]
+        type NotEncodable = ();
+        #[cfg(feature = "unicache")]
+        type EncodeResult = Self;
+        #[cfg(feature = "unicache")]
+        type UniCache = $crate::utils::NoUniCache<Self>;
+    };
+}
+#[allow(unused_imports)]
+pub(crate) use no_unicache;
+
+/// A UniCache that sends every entry as it is.
+#[cfg(feature = "unicache")]
+#[derive(Clone, Debug)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize), serde(bound = ""))]
+pub struct NoUniCache<T>(std::marker::PhantomData<T>);
+
+#[cfg(feature = "unicache")]
+impl<T: Entry<EncodeResult = T>> UniCache for NoUniCache<T> {
+    type T = T;
+
+    fn new() -> Self {
+        NoUniCache(std::marker::PhantomData)
+    }
+
+    fn try_encode(&mut self, entry: &T) -> T {
+        entry.clone()
+    }
+
+    fn decode(&mut self, processed: T) -> T {
+        processed
+    }
+}
+
+/// Builds a server with a `MemoryStorage` for each node of `cfg`, with its server config changed
+/// by `configure`. Used by the tests that drive the servers with `tick_until`.
+pub fn create_cluster<T, F>(
+    cfg: &TestConfig,
+    configure: F,
+) -> HashMap<NodeId, OmniPaxos<T, MemoryStorage<T>>>
+where
+    T: Entry,
+    F: FnMut(&mut ServerConfig),
+{
+    create_cluster_with(cfg, configure, |_| MemoryStorage::default())
+}
+
+/// Like `create_cluster`, but builds the storage of each server with `storage`.
+pub fn create_cluster_with<T, B, F, S>(
+    cfg: &TestConfig,
+    mut configure: F,
+    mut storage: S,
+) -> HashMap<NodeId, OmniPaxos<T, B>>
+where
+    T: Entry,
+    B: Storage<T>,
+    F: FnMut(&mut ServerConfig),
+    S: FnMut(NodeId) -> B,
+{
+    (1..=(cfg.num_nodes + cfg.num_backup_nodes) as NodeId)
+        .map(|pid| {
+            let mut op_config = cfg.into_omnipaxos_config(pid);
+            configure(&mut op_config.server_config);
+            let op = op_config
+                .build(storage(pid))
+                .expect("Failed to build OmniPaxos");
+            (pid, op)
+        })
+        .collect()
+}
+
+/// The number of ticks after which `tick_until` gives up.
+pub const MAX_TICKS: usize = 1000;
+
+/// A server that `tick_until` can drive.
+pub trait Server {
+    type Entry: Entry;
+    type Storage: Storage<Self::Entry>;
+
+    /// Returns the instance that is ticked and handles the messages.
+    fn omni_paxos(&mut self) -> &mut OmniPaxos<Self::Entry, Self::Storage>;
+}
+
+impl<T, B> Server for OmniPaxos<T, B>
+where
+    T: Entry,
+    B: Storage<T>,
+{
+    type Entry = T;
+    type Storage = B;
+
+    fn omni_paxos(&mut self) -> &mut OmniPaxos<T, B> {
+        self
+    }
+}
+
+impl<T, B> Server for ConfigurationManager<T, B>
+where
+    T: Entry,
+    B: Storage<T>,
+{
+    type Entry = T;
+    type Storage = B;
+
+    fn omni_paxos(&mut self) -> &mut OmniPaxos<T, B> {
+        self.current_mut()
+    }
+}
+
+/// Ticks all `nodes` and passes their messages by hand, including all replies before the next
+/// tick, until `done` holds. Used by the tests whose entry or storage type can't run in a
+/// `TestSystem`. Yields before every tick to let the threads of a storage catch up. Panics if
+/// that takes more than `MAX_TICKS` ticks.
+pub fn tick_until<N, F>(nodes: &mut HashMap<NodeId, N>, done: F)
+where
+    N: Server,
+    F: Fn(&HashMap<NodeId, N>) -> bool,
+{
+    tick_until_filtered(nodes, |_| true, done);
+}
+
+/// Ticks all `nodes` with `tick_until` until they agree on a leader and returns it.
+pub fn elect_leader<T, B>(nodes: &mut HashMap<NodeId, OmniPaxos<T, B>>) -> NodeId
+where
+    T: Entry,
+    B: Storage<T>,
+{
+    tick_until(nodes, |nodes| {
+        let leader = nodes.values().next().and_then(|op| op.get_current_leader());
+        leader.is_some() && nodes.values().all(|op| op.get_current_leader() == leader)
+    });
+    nodes
+        .values()
+        .find_map(|op| op.get_current_leader())
+        .unwrap()
+}
+
+/// Like `tick_until`, but only delivers the messages for which `filter` returns true. The
+/// other messages are dropped. `filter` may also change the messages on the way.
+pub fn tick_until_filtered<N, D, F>(nodes: &mut HashMap<NodeId, N>, mut filter: D, done: F)
+where
+    N: Server,
+    D: FnMut(&mut Message<N::Entry>) -> bool,
+    F: Fn(&HashMap<NodeId, N>) -> bool,
+{
+    for _ in 0..MAX_TICKS {
+        thread::yield_now();
+        if done(nodes) {
+            return;
+        }
+        for node in nodes.values_mut() {
+            node.omni_paxos().tick().expect("Failed to tick");
+        }
+        let pids: Vec<NodeId> = nodes.keys().copied().collect();
+        deliver(nodes, &pids, &mut filter);
+    }
+    panic!("cluster did not reach the expected state in time");
+}
+
+/// Passes the outgoing messages of the servers `from` by hand without ticking any server, until
+/// they have no messages left. Only delivers the messages for which `filter` returns true, like
+/// `tick_until_filtered`. The replies of the servers that are not in `from` are kept in their
+/// outgoing queues.
+pub fn deliver<N, D>(nodes: &mut HashMap<NodeId, N>, from: &[NodeId], mut filter: D)
+where
+    N: Server,
+    D: FnMut(&mut Message<N::Entry>) -> bool,
+{
+    loop {
+        let msgs: Vec<_> = from
+            .iter()
+            .flat_map(|pid| nodes.get_mut(pid).unwrap().omni_paxos().outgoing_messages())
+            .collect();
+        if msgs.is_empty() {
+            return;
+        }
+        for mut msg in msgs {
+            if !filter(&mut msg) {
+                continue;
+            }
+            if let Some(node) = nodes.get_mut(&msg.get_receiver()) {
+                node.omni_paxos()
+                    .handle_incoming(msg)
+                    .expect("Failed to handle message");
+            }
+        }
+    }
+}
+
 /// Create a temporary directory in /tmp/
 pub fn create_temp_dir() -> String {
     let dir = TempDir::new().expect("Failed to create temporary directory");
@@ -0,0 +1,231 @@
+/// This file contains unit-style tests of the background verification of the stored log. The
+/// verification is only observable through a storage whose entries can be corrupted, so the tests
+/// drive a single follower on such a storage with the messages of its leader instead of running a
+/// `TestSystem`.
+pub mod utils;
+
+use omnipaxos::{
+    ballot_leader_election::Ballot,
+    messages::{
+        sequence_paxos::{AcceptSync, PaxosMessage, PaxosMsg, Prepare},
+        Message,
+    },
+    storage::{LogStorage, StateStorage, StopSign, StorageOp, StorageResult, VerificationFailure},
+    util::{LogSync, SequenceNumber},
+    OmniPaxos,
+};
+#[cfg(feature = "unicache")]
+use omnipaxos::{storage::Entry, unicache::UniCache};
+use omnipaxos_storage::memory_storage::MemoryStorage;
+use serial_test::serial;
+use std::sync::{Arc, Mutex};
+use utils::{TestConfig, Value, ValueSnapshot};
+
+const VERIFICATION_TICK_TIMEOUT: u64 = 2;
+const VERIFICATION_BATCH_SIZE: usize = 10;
+const CORRUPTION_ERROR: &str = "checksum mismatch";
+const MAX_TICKS: usize = 1000;
+
+/// A memory storage where entries can be marked as corrupted. Corrupted entries fail their
+/// verification but can still be read.
+struct CorruptibleStorage {
+    storage: MemoryStorage<Value>,
+    corrupted_idx: Arc<Mutex<Option<usize>>>,
+}
+
+impl LogStorage<Value> for CorruptibleStorage {
+    fn write_atomically(&mut self, ops: Vec<StorageOp<Value>>) -> StorageResult<()> {
+        self.storage.write_atomically(ops)
+    }
+
+    fn append_entry(&mut self, entry: Value) -> StorageResult<()> {
+        self.storage.append_entry(entry)
+    }
+
+    fn append_entries(&mut self, entries: Vec<Value>) -> StorageResult<()> {
+        self.storage.append_entries(entries)
+    }
+
+    fn append_on_prefix(&mut self, from_idx: usize, entries: Vec<Value>) -> StorageResult<()> {
+        self.storage.append_on_prefix(from_idx, entries)
+    }
+
+    fn get_entries(&self, from: usize, to: usize) -> StorageResult<Vec<Value>> {
+        self.storage.get_entries(from, to)
+    }
+
+    fn get_log_len(&self) -> StorageResult<usize> {
+        self.storage.get_log_len()
+    }
+
+    fn get_suffix(&self, from: usize) -> StorageResult<Vec<Value>> {
+        self.storage.get_suffix(from)
+    }
+
+    fn trim(&mut self, idx: usize) -> StorageResult<()> {
+        self.storage.trim(idx)
+    }
+
+    fn verify_entries(&self, from: usize, to: usize) -> StorageResult<()> {
+        match *self.corrupted_idx.lock().unwrap() {
+            Some(idx) if (from..to).contains(&idx) => Err(CORRUPTION_ERROR.into()),
+            _ => self.storage.verify_entries(from, to),
+        }
+    }
+}
+
+impl StateStorage<Value> for CorruptibleStorage {
+    fn set_promise(&mut self, n_prom: Ballot) -> StorageResult<()> {
+        self.storage.set_promise(n_prom)
+    }
+
+    fn set_decided_idx(&mut self, ld: usize) -> StorageResult<()> {
+        self.storage.set_decided_idx(ld)
+    }
+
+    fn get_decided_idx(&self) -> StorageResult<usize> {
+        self.storage.get_decided_idx()
+    }
+
+    fn set_accepted_round(&mut self, na: Ballot) -> StorageResult<()> {
+        self.storage.set_accepted_round(na)
+    }
+
+    fn get_accepted_round(&self) -> StorageResult<Option<Ballot>> {
+        self.storage.get_accepted_round()
+    }
+
+    fn get_promise(&self) -> StorageResult<Option<Ballot>> {
+        self.storage.get_promise()
+    }
+
+    fn set_ble_ballot(&mut self, ballot: Ballot) -> StorageResult<()> {
+        self.storage.set_ble_ballot(ballot)
+    }
+
+    fn get_ble_ballot(&self) -> StorageResult<Option<Ballot>> {
+        self.storage.get_ble_ballot()
+    }
+
+    fn set_stopsign(&mut self, s: Option<StopSign>) -> StorageResult<()> {
+        self.storage.set_stopsign(s)
+    }
+
+    fn get_stopsign(&self) -> StorageResult<Option<StopSign>> {
+        self.storage.get_stopsign()
+    }
+
+    fn set_compacted_idx(&mut self, idx: usize) -> StorageResult<()> {
+        self.storage.set_compacted_idx(idx)
+    }
+
+    fn get_compacted_idx(&self) -> StorageResult<usize> {
+        self.storage.get_compacted_idx()
+    }
+
+    fn set_snapshot(&mut self, snapshot: Option<ValueSnapshot>) -> StorageResult<()> {
+        self.storage.set_snapshot(snapshot)
+    }
+
+    fn get_snapshot(&self) -> StorageResult<Option<ValueSnapshot>> {
+        self.storage.get_snapshot()
+    }
+}
+
+/// Makes the server `op` a follower of server 2 that has decided `entries`.
+fn sync_follower(op: &mut OmniPaxos<Value, CorruptibleStorage>, entries: Vec<Value>) {
+    let n = Ballot {
+        config_id: 1,
+        n: 1,
+        priority: 0,
+        pid: 2,
+    };
+    let prepare = PaxosMsg::Prepare(Prepare {
+        n,
+        decided_idx: 0,
+        n_accepted: Ballot::default(),
+        accepted_idx: 0,
+    });
+    let accept_sync = PaxosMsg::AcceptSync(AcceptSync {
+        n,
+        seq_num: SequenceNumber {
+            session: 1,
+            counter: 1,
+        },
+        decided_idx: entries.len(),
+        log_sync: LogSync {
+            decided_snapshot: None,
+            suffix: entries,
+            sync_idx: 0,
+            stopsign: None,
+            suffix_ballots: vec![],
+        },
+        #[cfg(feature = "unicache")]
+        unicache: <Value as Entry>::UniCache::new(),
+    });
+    for msg in [prepare, accept_sync] {
+        op.handle_incoming(Message::SequencePaxos(PaxosMessage {
+            from: 2,
+            to: 1,
+            namespace: None,
+            msg,
+        }))
+        .expect("Failed to handle message");
+    }
+    op.outgoing_messages();
+}
+
+/// Ticks `op` until `done` holds. Panics if that takes more than `MAX_TICKS` ticks.
+fn tick_until<F>(op: &mut OmniPaxos<Value, CorruptibleStorage>, done: F)
+where
+    F: Fn(&OmniPaxos<Value, CorruptibleStorage>) -> bool,
+{
+    for _ in 0..MAX_TICKS {
+        if done(op) {
+            return;
+        }
+        op.tick().expect("Failed to tick");
+    }
+    panic!("server did not reach the expected state in time");
+}
+
+/// Verifies that the background verification passes over the whole log and reports an
+/// entry that fails its verification.
+#[test]
+#[serial]
+fn verification_test() {
+    let cfg = TestConfig::load("verification_test").expect("Test config loaded");
+    let corrupted_idx = Arc::new(Mutex::new(None));
+    let storage = CorruptibleStorage {
+        storage: MemoryStorage::default(),
+        corrupted_idx: corrupted_idx.clone(),
+    };
+    let mut op_config = cfg.into_omnipaxos_config(1);
+    op_config.server_config.verification_tick_timeout = Some(VERIFICATION_TICK_TIMEOUT);
+    op_config.server_config.verification_batch_size = VERIFICATION_BATCH_SIZE;
+    let mut op = op_config.build(storage).expect("failed to build OmniPaxos");
+    sync_follower(&mut op, utils::create_proposals(1, cfg.num_proposals));
+    assert_eq!(op.get_decided_idx(), cfg.num_proposals as usize);
+
+    // The server eventually verifies its whole log without failures
+    let passes_before = op.get_verification_report().num_passes;
+    tick_until(&mut op, |op| {
+        op.get_verification_report().num_passes > passes_before + 1
+    });
+    let report = op.get_verification_report();
+    assert!(report.num_verified_entries >= cfg.num_proposals);
+    assert_eq!(report.num_failures, 0);
+    assert_eq!(report.last_failure, None);
+
+    // A corrupted entry is found on the next pass
+    let corrupted = cfg.num_proposals as usize / 2;
+    *corrupted_idx.lock().unwrap() = Some(corrupted);
+    tick_until(&mut op, |op| op.get_verification_report().num_failures > 0);
+    match op.get_verification_report().last_failure {
+        Some(VerificationFailure::Entries { from, to, error }) => {
+            assert!((from..to).contains(&corrupted));
+            assert_eq!(error, CORRUPTION_ERROR);
+        }
+        f => panic!("Unexpected verification failure: {:?}", f),
+    }
+}
@@ -0,0 +1,189 @@
+pub mod utils;
+
+use crate::utils::{StorageType, Value};
+use omnipaxos::{
+    storage::{LogStorage, StateStorage},
+    util::{LogEntry, NodeId},
+    OmniPaxos,
+};
+use omnipaxos_storage::{memory_storage::MemoryStorage, write_behind_storage::WriteBehindStorage};
+use serial_test::serial;
+use std::{
+    cell::Cell,
+    collections::HashMap,
+    sync::{Arc, Mutex},
+};
+use utils::{elect_leader, tick_until, TestConfig};
+
+/// The number of ticks the servers run while the backends are blocked.
+const BLOCKED_TICKS: usize = 100;
+
+type Backend = Arc<Mutex<MemoryStorage<Value>>>;
+type OmniPaxosValue = OmniPaxos<Value, WriteBehindStorage<Value, StorageType<Value>>>;
+
+/// A write-behind storage over what was persisted in `backend`.
+fn storage(backend: &Backend) -> WriteBehindStorage<Value, StorageType<Value>> {
+    WriteBehindStorage::with(StorageType::Broken(backend.clone(), Arc::default()))
+        .expect("failed to open storage")
+}
+
+/// Servers with write-behind storages whose backends outlive them, so that the backends can
+/// be blocked and the servers can be restarted from them.
+fn cluster(cfg: &TestConfig) -> (HashMap<NodeId, OmniPaxosValue>, HashMap<NodeId, Backend>) {
+    let backends: HashMap<NodeId, Backend> = (1..=cfg.num_nodes as NodeId)
+        .map(|pid| (pid, Arc::default()))
+        .collect();
+    let nodes = utils::create_cluster_with(cfg, |_| (), |pid| storage(&backends[&pid]));
+    (nodes, backends)
+}
+
+/// Elects a leader and ticks until every server has persisted its round as the accepted round.
+/// The accepted round is written through to the backend, so the backends must not be blocked
+/// before.
+fn elect_persisted_leader(
+    nodes: &mut HashMap<NodeId, OmniPaxosValue>,
+    backends: &HashMap<NodeId, Backend>,
+) -> NodeId {
+    let leader = elect_leader(nodes);
+    tick_until(nodes, |nodes| {
+        backends.iter().all(|(pid, backend)| {
+            let accepted_round = backend.lock().unwrap().get_accepted_round().unwrap();
+            accepted_round == Some(nodes[pid].get_promise())
+        })
+    });
+    leader
+}
+
+/// Ticks the servers `BLOCKED_TICKS` times.
+fn tick_blocked(nodes: &mut HashMap<NodeId, OmniPaxosValue>) {
+    let ticks = Cell::new(0);
+    tick_until(nodes, |_| {
+        ticks.set(ticks.get() + 1);
+        ticks.get() > BLOCKED_TICKS
+    });
+}
+
+fn expected_log(cfg: &TestConfig) -> Vec<LogEntry<Value>> {
+    utils::create_proposals(1, cfg.num_proposals)
+        .into_iter()
+        .map(LogEntry::Decided)
+        .collect()
+}
+
+/// Verifies that entries are only decided once a quorum has persisted them to their backends,
+/// and that a server restarted from its backend recovers the decided log.
+#[test]
+#[serial]
+fn write_behind_test() {
+    let cfg = TestConfig::load("write_behind_test").expect("Test config loaded");
+    let num_proposals = cfg.num_proposals as usize;
+    let (mut nodes, backends) = cluster(&cfg);
+    let leader = elect_persisted_leader(&mut nodes, &backends);
+
+    // Holding the locks of the backends blocks the persisting threads
+    let guards: Vec<_> = backends.values().map(|b| b.lock().unwrap()).collect();
+    let op = nodes.get_mut(&leader).unwrap();
+    for v in utils::create_proposals(1, cfg.num_proposals) {
+        op.append(v).expect("Failed to append");
+    }
+    tick_blocked(&mut nodes);
+    for op in nodes.values() {
+        assert_eq!(op.get_decided_idx(), 0);
+        assert_eq!(
+            op.read_entries(0..num_proposals).map(|e| e.len()),
+            Some(num_proposals),
+            "The entries are not served from memory"
+        );
+    }
+    drop(guards);
+    tick_until(&mut nodes, |nodes| {
+        nodes
+            .values()
+            .all(|op| op.get_decided_idx() == num_proposals)
+    });
+
+    let follower = (1..=cfg.num_nodes as NodeId)
+        .find(|pid| *pid != leader)
+        .unwrap();
+    nodes.remove(&follower);
+    let backend = backends[&follower].lock().unwrap();
+    assert_eq!(backend.get_log_len().unwrap(), num_proposals);
+    drop(backend);
+    let restarted = cfg
+        .into_omnipaxos_config(follower)
+        .build(storage(&backends[&follower]))
+        .expect("failed to build OmniPaxos");
+    nodes.insert(follower, restarted);
+    assert_eq!(
+        nodes[&follower].read_decided_suffix(0),
+        Some(expected_log(&cfg))
+    );
+}
+
+/// Verifies that `flush_and_get_durable_idx()` waits until the accepted entries are persisted
+/// to the backend and returns them as durable.
+#[test]
+#[serial]
+fn flush_and_get_durable_idx_test() {
+    let cfg = TestConfig::load("write_behind_test").expect("Test config loaded");
+    let num_proposals = cfg.num_proposals as usize;
+    let (mut nodes, backends) = cluster(&cfg);
+    let leader = elect_persisted_leader(&mut nodes, &backends);
+
+    let guards: Vec<_> = backends.values().map(|b| b.lock().unwrap()).collect();
+    let op = nodes.get_mut(&leader).unwrap();
+    for v in utils::create_proposals(1, cfg.num_proposals) {
+        op.append(v).expect("Failed to append");
+    }
+    tick_blocked(&mut nodes);
+    drop(guards);
+    for pid in 1..=cfg.num_nodes as NodeId {
+        let durable_idx = nodes
+            .get_mut(&pid)
+            .unwrap()
+            .flush_and_get_durable_idx()
+            .expect("Failed to flush");
+        assert_eq!(durable_idx, num_proposals);
+        assert_eq!(
+            backends[&pid].lock().unwrap().get_log_len().unwrap(),
+            num_proposals
+        );
+    }
+    tick_until(&mut nodes, |nodes| {
+        nodes
+            .values()
+            .all(|op| op.get_decided_idx() == num_proposals)
+    });
+}
+
+/// Verifies that a follower whose backend lags behind the decided log reports a decided index
+/// that the leader accepts, i.e., one that is not beyond the durable log of the follower.
+#[test]
+#[serial]
+fn decided_ahead_of_durable_test() {
+    let cfg = TestConfig::load("write_behind_test").expect("Test config loaded");
+    let num_proposals = cfg.num_proposals as usize;
+    let (mut nodes, backends) = cluster(&cfg);
+    let leader = elect_persisted_leader(&mut nodes, &backends);
+    let follower = (1..=cfg.num_nodes as NodeId)
+        .find(|pid| *pid != leader)
+        .unwrap();
+
+    let guard = backends[&follower].lock().unwrap();
+    let op = nodes.get_mut(&leader).unwrap();
+    for v in utils::create_proposals(1, cfg.num_proposals) {
+        op.append(v).expect("Failed to append");
+    }
+    tick_until(&mut nodes, |nodes| {
+        nodes[&follower].get_decided_idx() == num_proposals
+    });
+    tick_blocked(&mut nodes);
+    assert_eq!(nodes[&leader].get_rejected_message_count(follower), 0);
+    drop(guard);
+    tick_until(&mut nodes, |nodes| {
+        nodes
+            .values()
+            .all(|op| op.get_decided_idx() == num_proposals)
+    });
+    assert_eq!(nodes[&leader].get_rejected_message_count(follower), 0);
+}
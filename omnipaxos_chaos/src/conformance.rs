@@ -0,0 +1,497 @@
+use crate::{Transport, Value};
+use omnipaxos::{
+    messages::Message,
+    util::{LogEntry, NodeId},
+    ClusterConfig, OmniPaxos, ServerConfig,
+};
+use omnipaxos_storage::memory_storage::MemoryStorage;
+use std::{
+    collections::{BTreeMap, HashMap, VecDeque},
+    error::Error,
+    fmt,
+};
+
+/// The number of servers in the cluster of a conformance case.
+const NUM_NODES: u64 = 3;
+/// The number of entries that must be decided in the cases that expect progress.
+const NUM_DECIDED: usize = 50;
+/// The number of ticks a conformance case runs for at most.
+const MAX_TICKS: u64 = 3000;
+/// The number of calls to `receive()` after the last tick in which the transport must deliver the
+/// remaining messages.
+const MAX_DRAIN_ROUNDS: usize = 10;
+
+/// What the script of a [`ConformanceCase`] does with a message sent on a link, before the message
+/// is handed to the transport under test.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum LinkAction {
+    /// The message is sent.
+    Deliver,
+    /// The message is lost.
+    Drop,
+    /// The message is sent twice.
+    Duplicate,
+    /// The message is held back and sent after the next message on the same link.
+    Delay,
+}
+
+/// The outcome OmniPaxos is expected to reach in a [`ConformanceCase`]. The servers must never
+/// decide different entries in any case.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum ExpectedOutcome {
+    /// All servers decide new entries.
+    Decided,
+    /// The servers only have to agree on what they decided. Progress is not required, e.g.,
+    /// because the script loses too many messages.
+    Safe,
+}
+
+/// A scripted sequence of message faults and the protocol outcome expected over a transport that
+/// delivers the messages it is given in order.
+#[derive(Copy, Clone, Debug)]
+pub struct ConformanceCase {
+    /// The name of the case.
+    pub name: &'static str,
+    /// Returns the action for the `n`-th message sent on a link (starting at 0). Applied to every
+    /// link separately.
+    pub script: fn(usize) -> LinkAction,
+    /// Whether `reconnected()` is called on both servers of a link after one of its messages was
+    /// dropped, as a transport must do when it re-establishes a lost session.
+    pub reconnect: bool,
+    /// The expected outcome.
+    pub expected: ExpectedOutcome,
+}
+
+/// Returns the conformance cases that [`run_conformance`] runs.
+pub fn cases() -> Vec<ConformanceCase> {
+    vec![
+        ConformanceCase {
+            name: "in_order",
+            script: |_| LinkAction::Deliver,
+            reconnect: false,
+            expected: ExpectedOutcome::Decided,
+        },
+        ConformanceCase {
+            name: "duplicates",
+            script: |n| match n % 3 {
+                0 => LinkAction::Duplicate,
+                _ => LinkAction::Deliver,
+            },
+            reconnect: false,
+            expected: ExpectedOutcome::Decided,
+        },
+        ConformanceCase {
+            name: "burst_loss_with_reconnect",
+            script: |n| match n {
+                20..=29 => LinkAction::Drop,
+                _ => LinkAction::Deliver,
+            },
+            reconnect: true,
+            expected: ExpectedOutcome::Decided,
+        },
+        ConformanceCase {
+            name: "periodic_loss_with_reconnect",
+            script: |n| match n % 10 {
+                9 => LinkAction::Drop,
+                _ => LinkAction::Deliver,
+            },
+            reconnect: true,
+            expected: ExpectedOutcome::Decided,
+        },
+        ConformanceCase {
+            name: "reorders",
+            script: |n| match n % 5 {
+                4 => LinkAction::Delay,
+                _ => LinkAction::Deliver,
+            },
+            reconnect: false,
+            expected: ExpectedOutcome::Decided,
+        },
+        ConformanceCase {
+            name: "silent_loss",
+            script: |n| match n % 7 {
+                6 => LinkAction::Drop,
+                _ => LinkAction::Deliver,
+            },
+            reconnect: false,
+            expected: ExpectedOutcome::Decided,
+        },
+        ConformanceCase {
+            name: "heavy_silent_loss",
+            script: |n| match n % 2 {
+                1 => LinkAction::Drop,
+                _ => LinkAction::Deliver,
+            },
+            reconnect: false,
+            expected: ExpectedOutcome::Safe,
+        },
+    ]
+}
+
+/// A violation found by a conformance case.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum ConformanceViolation {
+    /// The transport delivered a message on a link that is not the next message sent on it, i.e.,
+    /// it reordered, duplicated, or altered the messages of the link.
+    OutOfOrder {
+        /// The sender of the message.
+        from: NodeId,
+        /// The receiver of the message.
+        to: NodeId,
+        /// The position of the message among the messages received on the link.
+        position: usize,
+    },
+    /// The transport did not deliver all messages sent on a link.
+    Lost {
+        /// The sender of the messages.
+        from: NodeId,
+        /// The receiver of the messages.
+        to: NodeId,
+        /// The number of messages sent on the link.
+        sent: usize,
+        /// The number of messages received on the link.
+        received: usize,
+    },
+    /// Two servers decided different entries at `idx`.
+    Disagreement {
+        /// The server that decided differently than the others.
+        pid: NodeId,
+        /// The log index of the entry.
+        idx: usize,
+    },
+    /// A server did not decide enough entries although the case expects progress.
+    NotDecided {
+        /// The server.
+        pid: NodeId,
+        /// The decided index of the server.
+        decided_idx: usize,
+        /// The number of entries that should have been decided.
+        expected: usize,
+    },
+}
+
+impl fmt::Display for ConformanceViolation {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ConformanceViolation::OutOfOrder { from, to, position } => write!(
+                f,
+                "message {} received from {} at {} is not the next message sent on the link",
+                position, from, to
+            ),
+            ConformanceViolation::Lost {
+                from,
+                to,
+                sent,
+                received,
+            } => write!(
+                f,
+                "only {} of the {} messages sent from {} to {} were received",
+                received, sent, from, to
+            ),
+            ConformanceViolation::Disagreement { pid, idx } => write!(
+                f,
+                "server {} decided a different entry at index {} than the others",
+                pid, idx
+            ),
+            ConformanceViolation::NotDecided {
+                pid,
+                decided_idx,
+                expected,
+            } => write!(
+                f,
+                "server {} only decided up to {} instead of {}",
+                pid, decided_idx, expected
+            ),
+        }
+    }
+}
+
+impl Error for ConformanceViolation {}
+
+/// A conformance case that failed.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct ConformanceFailure {
+    /// The name of the case.
+    pub case: &'static str,
+    /// The violation found.
+    pub violation: ConformanceViolation,
+}
+
+impl fmt::Display for ConformanceFailure {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "case {}: {}", self.case, self.violation)
+    }
+}
+
+impl Error for ConformanceFailure {}
+
+/// Runs all conformance [`cases`], each over a new transport created by `new_transport`. Returns
+/// the first case that failed.
+pub fn run_conformance<N, F>(mut new_transport: F) -> Result<(), ConformanceFailure>
+where
+    N: Transport,
+    F: FnMut() -> N,
+{
+    for case in cases() {
+        run_case(&case, new_transport()).map_err(|violation| ConformanceFailure {
+            case: case.name,
+            violation,
+        })?;
+    }
+    Ok(())
+}
+
+/// Runs a single conformance case over `transport`. A cluster decides entries while the script
+/// of the case is applied to the messages that are handed to the transport. The transport must
+/// deliver every message it is given exactly once and in order per link, and the cluster must
+/// reach the expected outcome.
+pub fn run_case<N>(case: &ConformanceCase, transport: N) -> Result<(), ConformanceViolation>
+where
+    N: Transport,
+{
+    let mut run = CaseRun::with(case, transport);
+    for _ in 0..MAX_TICKS {
+        run.tick()?;
+        if run.all_decided() {
+            break;
+        }
+    }
+    run.drain()?;
+    match case.expected {
+        ExpectedOutcome::Decided => run.check_decided(),
+        ExpectedOutcome::Safe => Ok(()),
+    }
+}
+
+struct CaseRun<N> {
+    case: ConformanceCase,
+    servers: BTreeMap<NodeId, OmniPaxos<Value, MemoryStorage<Value>>>,
+    transport: N,
+    // The number of messages the script has been applied to on each link
+    scripted: HashMap<(NodeId, NodeId), usize>,
+    // The message held back on each link by a `Delay`
+    delayed: HashMap<(NodeId, NodeId), Message<Value>>,
+    // The messages handed to the transport on each link that have not been received yet
+    in_flight: HashMap<(NodeId, NodeId), VecDeque<String>>,
+    // The number of messages received on each link
+    received: HashMap<(NodeId, NodeId), usize>,
+    // The links on which a message was dropped since the last tick
+    reconnects: Vec<(NodeId, NodeId)>,
+    decided_log: Vec<Value>,
+    next_value: u64,
+}
+
+impl<N> CaseRun<N>
+where
+    N: Transport,
+{
+    fn with(case: &ConformanceCase, transport: N) -> Self {
+        let cluster_config = ClusterConfig {
+            configuration_id: 1,
+            nodes: (1..=NUM_NODES).collect(),
+            ..Default::default()
+        };
+        let servers = cluster_config
+            .nodes
+            .iter()
+            .map(|pid| {
+                let server_config = ServerConfig {
+                    pid: *pid,
+                    resend_message_tick_timeout: 50,
+                    ..Default::default()
+                };
+                let server = cluster_config
+                    .clone()
+                    .build_for_server(server_config, MemoryStorage::default())
+                    .expect("failed to build OmniPaxos");
+                (*pid, server)
+            })
+            .collect();
+        Self {
+            case: *case,
+            servers,
+            transport,
+            scripted: HashMap::new(),
+            delayed: HashMap::new(),
+            in_flight: HashMap::new(),
+            received: HashMap::new(),
+            reconnects: vec![],
+            decided_log: vec![],
+            next_value: 0,
+        }
+    }
+
+    fn tick(&mut self) -> Result<(), ConformanceViolation> {
+        for (from, to) in std::mem::take(&mut self.reconnects) {
+            self.servers.get_mut(&from).unwrap().reconnected(to);
+            self.servers.get_mut(&to).unwrap().reconnected(from);
+        }
+        self.propose();
+        let mut outgoing = vec![];
+        for server in self.servers.values_mut() {
+            server.tick().expect("Failed to tick");
+            outgoing.append(&mut server.outgoing_messages());
+        }
+        for msg in outgoing {
+            self.apply_script(msg);
+        }
+        self.receive()?;
+        self.verify_safety()
+    }
+
+    /// Proposes a new entry on the server that considers itself the leader. Entries that are
+    /// appended by a leader that is replaced before it decides them may be lost, so entries are
+    /// proposed until enough are decided.
+    fn propose(&mut self) {
+        if self.decided_log.len() >= NUM_DECIDED {
+            return;
+        }
+        let leader = self
+            .servers
+            .iter_mut()
+            .find(|(pid, server)| server.get_current_leader() == Some(**pid));
+        if let Some((_, server)) = leader {
+            if server.append(Value(self.next_value)).is_ok() {
+                self.next_value += 1;
+            }
+        }
+    }
+
+    fn apply_script(&mut self, msg: Message<Value>) {
+        let link = (msg.get_sender(), msg.get_receiver());
+        let n = self.scripted.entry(link).or_default();
+        let action = (self.case.script)(*n);
+        *n += 1;
+        match action {
+            LinkAction::Deliver => self.send(msg),
+            LinkAction::Drop => {
+                if self.case.reconnect {
+                    self.reconnects.push(link);
+                }
+            }
+            LinkAction::Duplicate => {
+                self.send(msg.clone());
+                self.send(msg);
+            }
+            LinkAction::Delay => {
+                if let Some(delayed) = self.delayed.insert(link, msg) {
+                    self.send(delayed);
+                }
+                return;
+            }
+        }
+        if let Some(delayed) = self.delayed.remove(&link) {
+            self.send(delayed);
+        }
+    }
+
+    fn send(&mut self, msg: Message<Value>) {
+        let link = (msg.get_sender(), msg.get_receiver());
+        self.in_flight
+            .entry(link)
+            .or_default()
+            .push_back(format!("{:?}", msg));
+        self.transport.send(msg);
+    }
+
+    /// Delivers the messages that arrived and checks that each is the next one sent on its link.
+    fn receive(&mut self) -> Result<(), ConformanceViolation> {
+        for msg in self.transport.receive() {
+            let (from, to) = (msg.get_sender(), msg.get_receiver());
+            let position = self.received.entry((from, to)).or_default();
+            let expected = self
+                .in_flight
+                .get_mut(&(from, to))
+                .and_then(|in_flight| in_flight.pop_front());
+            if expected != Some(format!("{:?}", msg)) {
+                return Err(ConformanceViolation::OutOfOrder {
+                    from,
+                    to,
+                    position: *position,
+                });
+            }
+            *position += 1;
+            if let Some(server) = self.servers.get_mut(&to) {
+                server
+                    .handle_incoming(msg)
+                    .expect("Failed to handle message");
+            }
+        }
+        Ok(())
+    }
+
+    /// Waits for the transport to deliver the messages that are still in flight.
+    fn drain(&mut self) -> Result<(), ConformanceViolation> {
+        for _ in 0..MAX_DRAIN_ROUNDS {
+            if self
+                .in_flight
+                .values()
+                .all(|in_flight| in_flight.is_empty())
+            {
+                break;
+            }
+            self.receive()?;
+        }
+        self.verify_safety()?;
+        let mut lost = self
+            .in_flight
+            .iter()
+            .filter(|(_, in_flight)| !in_flight.is_empty())
+            .map(|(link, in_flight)| (*link, in_flight.len()))
+            .collect::<Vec<_>>();
+        lost.sort();
+        match lost.first() {
+            Some(((from, to), num_lost)) => {
+                let received = self.received.get(&(*from, *to)).copied().unwrap_or(0);
+                Err(ConformanceViolation::Lost {
+                    from: *from,
+                    to: *to,
+                    sent: received + num_lost,
+                    received,
+                })
+            }
+            None => Ok(()),
+        }
+    }
+
+    /// Checks the decided entries of every server against the decided log.
+    fn verify_safety(&mut self) -> Result<(), ConformanceViolation> {
+        for (pid, server) in &self.servers {
+            let entries = server.read_decided_suffix(0).unwrap_or_default();
+            for (idx, entry) in entries.into_iter().enumerate() {
+                let LogEntry::Decided(found) = entry else {
+                    continue;
+                };
+                match self.decided_log.get(idx) {
+                    Some(expected) if *expected != found => {
+                        return Err(ConformanceViolation::Disagreement { pid: *pid, idx })
+                    }
+                    Some(_) => (),
+                    None => self.decided_log.push(found),
+                }
+            }
+        }
+        Ok(())
+    }
+
+    fn all_decided(&self) -> bool {
+        self.servers
+            .values()
+            .all(|server| server.get_decided_idx() >= NUM_DECIDED)
+    }
+
+    fn check_decided(&self) -> Result<(), ConformanceViolation> {
+        match self
+            .servers
+            .iter()
+            .find(|(_, server)| server.get_decided_idx() < NUM_DECIDED)
+        {
+            Some((pid, server)) => Err(ConformanceViolation::NotDecided {
+                pid: *pid,
+                decided_idx: server.get_decided_idx(),
+                expected: NUM_DECIDED,
+            }),
+            None => Ok(()),
+        }
+    }
+}
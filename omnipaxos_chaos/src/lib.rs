@@ -0,0 +1,530 @@
+//! A soak and chaos test harness for [OmniPaxos](https://crates.io/crates/omnipaxos). It runs a
+//! cluster in a single process and alternates between epochs with injected faults (crashes,
+//! network partitions, message loss, and slow disks) and quiet epochs without faults. It
+//! continuously verifies that the servers agree on the decided prefix of the log, and at the end of
+//! every quiet epoch that the cluster made progress and that all servers caught up.
+//!
+//! Use [`run`] to test OmniPaxos itself, or [`run_with`] to validate your own [`Storage`] and
+//! [`Transport`] implementations under the same faults. The [`conformance`] kit runs scripted
+//! message sequences over a [`Transport`] and checks the expected protocol outcomes.
+
+#![deny(missing_docs)]
+/// Scripted conformance tests for transports.
+pub mod conformance;
+/// The transport between the servers of the cluster under test.
+pub mod network;
+/// A storage wrapper that injects faults.
+pub mod storage;
+
+pub use conformance::run_conformance;
+pub use network::{InMemoryTransport, Transport};
+use omnipaxos::{
+    messages::Message,
+    storage::{Entry, NoSnapshot, Storage},
+    util::{LogEntry, NodeId},
+    ClusterConfig, OmniPaxos, ServerConfig,
+};
+use omnipaxos_storage::memory_storage::MemoryStorage;
+use rand::{rngs::StdRng, seq::SliceRandom, Rng, SeedableRng};
+use std::{collections::BTreeMap, error::Error, fmt, time::Duration};
+pub use storage::FaultyStorage;
+
+/// The number of ticks between the message deliveries to a server with a slow disk.
+const SLOW_DISK_FACTOR: u64 = 4;
+
+/// The entry type replicated by the cluster under test. Every proposal has a unique value.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Value(pub u64);
+
+impl Entry for Value {
+    type Snapshot = NoSnapshot;
+}
+
+/// Configuration of a chaos test run.
+/// # Fields
+/// * `nodes`: The number of servers in the cluster.
+/// * `ticks`: The number of ticks to run for.
+/// * `seed`: The seed of the random fault schedule.
+/// * `epoch_ticks`: The number of ticks a fault is injected for.
+/// * `quiet_ticks`: The number of ticks without faults after every fault, at the end of which the cluster must have made progress.
+/// * `proposals_per_tick`: The number of entries proposed at random servers every tick.
+/// * `drop_rate`: The probability that a message is lost in epochs with message loss.
+/// * `slow_disk_delay`: The delay added to every write of a server with a slow disk.
+#[derive(Clone, Debug)]
+pub struct ChaosConfig {
+    /// The number of servers in the cluster.
+    pub nodes: u64,
+    /// The number of ticks to run for.
+    pub ticks: u64,
+    /// The seed of the random fault schedule. The schedule is reproducible with the same seed, but
+    /// the run as a whole is not exactly, as OmniPaxos itself is not deterministic.
+    pub seed: u64,
+    /// The number of ticks a fault is injected for.
+    pub epoch_ticks: u64,
+    /// The number of ticks without faults after every fault. At the end of them, the cluster must
+    /// have decided new entries and all servers must have caught up with the decided index from
+    /// before them.
+    pub quiet_ticks: u64,
+    /// The number of entries proposed at random servers every tick.
+    pub proposals_per_tick: usize,
+    /// The probability that a message is lost in epochs with message loss.
+    pub drop_rate: f64,
+    /// The delay added to every write of a server with a slow disk. Such a server also only
+    /// handles its messages every few ticks.
+    pub slow_disk_delay: Duration,
+}
+
+impl Default for ChaosConfig {
+    fn default() -> Self {
+        Self {
+            nodes: 5,
+            ticks: 20_000,
+            seed: 0,
+            epoch_ticks: 300,
+            quiet_ticks: 1000,
+            proposals_per_tick: 1,
+            drop_rate: 0.05,
+            slow_disk_delay: Duration::from_micros(50),
+        }
+    }
+}
+
+/// The statistics of a chaos test run that passed.
+#[derive(Clone, Debug, Default)]
+pub struct Report {
+    /// The number of ticks that were run.
+    pub ticks: u64,
+    /// The length of the decided log at the end of the run.
+    pub decided_idx: usize,
+    /// The number of epochs in which servers were crashed.
+    pub crashes: u64,
+    /// The number of epochs in which the network was partitioned.
+    pub partitions: u64,
+    /// The number of epochs in which messages were lost.
+    pub message_losses: u64,
+    /// The number of epochs in which a server had a slow disk.
+    pub slow_disks: u64,
+}
+
+impl fmt::Display for Report {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{} ticks, {} decided entries, {} crash, {} partition, {} message loss, and {} slow disk epochs",
+            self.ticks,
+            self.decided_idx,
+            self.crashes,
+            self.partitions,
+            self.message_losses,
+            self.slow_disks
+        )
+    }
+}
+
+/// A violation of the guarantees of OmniPaxos detected by a chaos test run.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Violation {
+    /// A server decided a different entry at `idx` than another server.
+    Disagreement {
+        /// The server that decided `found`.
+        pid: NodeId,
+        /// The log index of the entry.
+        idx: usize,
+        /// The entry decided by the other servers.
+        expected: Value,
+        /// The entry decided by `pid`.
+        found: Value,
+    },
+    /// A server decided an entry that was never proposed.
+    NotProposed {
+        /// The server that decided the entry.
+        pid: NodeId,
+        /// The log index of the entry.
+        idx: usize,
+        /// The entry.
+        found: Value,
+    },
+    /// A server returned something else than a decided entry for an index below its decided index.
+    NotDecided {
+        /// The server.
+        pid: NodeId,
+        /// The log index.
+        idx: usize,
+    },
+    /// The decided index of a server decreased, e.g., because it was not persisted.
+    DecidedIdxDecreased {
+        /// The server.
+        pid: NodeId,
+        /// The decided index before.
+        from: usize,
+        /// The decided index after.
+        to: usize,
+    },
+    /// The cluster did not decide any new entry during a quiet epoch.
+    NoProgress {
+        /// The tick at which the quiet epoch ended.
+        tick: u64,
+        /// The decided index of the cluster.
+        decided_idx: usize,
+    },
+    /// A server did not catch up with the rest of the cluster during a quiet epoch.
+    NotCaughtUp {
+        /// The tick at which the quiet epoch ended.
+        tick: u64,
+        /// The server.
+        pid: NodeId,
+        /// The decided index of the server.
+        decided_idx: usize,
+        /// The decided index of the cluster at the start of the quiet epoch.
+        expected: usize,
+    },
+}
+
+impl fmt::Display for Violation {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Violation::Disagreement {
+                pid,
+                idx,
+                expected,
+                found,
+            } => write!(
+                f,
+                "server {} decided {:?} at index {}, but others decided {:?}",
+                pid, found, idx, expected
+            ),
+            Violation::NotProposed { pid, idx, found } => write!(
+                f,
+                "server {} decided {:?} at index {}, which was never proposed",
+                pid, found, idx
+            ),
+            Violation::NotDecided { pid, idx } => write!(
+                f,
+                "server {} did not return a decided entry at index {} below its decided index",
+                pid, idx
+            ),
+            Violation::DecidedIdxDecreased { pid, from, to } => write!(
+                f,
+                "decided index of server {} decreased from {} to {}",
+                pid, from, to
+            ),
+            Violation::NoProgress { tick, decided_idx } => write!(
+                f,
+                "no entry was decided in the quiet epoch ending at tick {} (decided index {})",
+                tick, decided_idx
+            ),
+            Violation::NotCaughtUp {
+                tick,
+                pid,
+                decided_idx,
+                expected,
+            } => write!(
+                f,
+                "server {} only decided up to {} instead of {} in the quiet epoch ending at tick {}",
+                pid, decided_idx, expected, tick
+            ),
+        }
+    }
+}
+
+impl Error for Violation {}
+
+/// Runs a chaos test of OmniPaxos with in-memory storages and transport.
+pub fn run(config: &ChaosConfig) -> Result<Report, Violation> {
+    run_with(
+        config,
+        |_| MemoryStorage::default(),
+        InMemoryTransport::default(),
+    )
+}
+
+/// Runs a chaos test with the storages created by `make_storage` for each server and the
+/// messages sent over `transport`. A crashed server is recovered with the state of its storage, so
+/// the storage must not lose anything that it reported as written.
+/// # Panics
+/// Panics if `config.nodes` is 0.
+pub fn run_with<S, F, N>(
+    config: &ChaosConfig,
+    make_storage: F,
+    transport: N,
+) -> Result<Report, Violation>
+where
+    S: Storage<Value>,
+    F: FnMut(NodeId) -> S,
+    N: Transport,
+{
+    assert!(config.nodes > 0, "the cluster must have at least one node");
+    let mut chaos = Chaos::with(config.clone(), make_storage, transport);
+    while chaos.report.ticks < config.ticks {
+        chaos.inject_fault();
+        chaos.run_ticks(config.epoch_ticks)?;
+        chaos.heal();
+        let decided_idx = chaos.decided_log.len();
+        chaos.run_ticks(config.quiet_ticks)?;
+        chaos.check_liveness(decided_idx)?;
+    }
+    chaos.report.decided_idx = chaos.decided_log.len();
+    Ok(chaos.report)
+}
+
+struct Chaos<S, N>
+where
+    S: Storage<Value>,
+{
+    config: ChaosConfig,
+    rng: StdRng,
+    cluster_config: ClusterConfig,
+    storages: BTreeMap<NodeId, FaultyStorage<S>>,
+    // The servers that have not crashed
+    servers: BTreeMap<NodeId, OmniPaxos<Value, FaultyStorage<S>>>,
+    transport: N,
+    // The servers on one side of the partition
+    partition: Option<Vec<NodeId>>,
+    drop_rate: f64,
+    // The server with a slow disk and the messages it has not handled yet
+    slow_disk: Option<(NodeId, Vec<Message<Value>>)>,
+    // The decided log as agreed on by all servers so far
+    decided_log: Vec<Value>,
+    // The decided index of each server that has been verified
+    verified_idx: BTreeMap<NodeId, usize>,
+    next_value: u64,
+    report: Report,
+}
+
+impl<S, N> Chaos<S, N>
+where
+    S: Storage<Value>,
+    N: Transport,
+{
+    fn with<F>(config: ChaosConfig, mut make_storage: F, transport: N) -> Self
+    where
+        F: FnMut(NodeId) -> S,
+    {
+        let cluster_config = ClusterConfig {
+            configuration_id: 1,
+            nodes: (1..=config.nodes).collect(),
+            ..Default::default()
+        };
+        let storages = cluster_config
+            .nodes
+            .iter()
+            .map(|pid| (*pid, FaultyStorage::new(make_storage(*pid))))
+            .collect();
+        let mut chaos = Self {
+            rng: StdRng::seed_from_u64(config.seed),
+            config,
+            cluster_config,
+            storages,
+            servers: BTreeMap::new(),
+            transport,
+            partition: None,
+            drop_rate: 0.0,
+            slow_disk: None,
+            decided_log: vec![],
+            verified_idx: BTreeMap::new(),
+            next_value: 0,
+            report: Report::default(),
+        };
+        chaos.start_crashed_servers();
+        chaos
+    }
+
+    /// Starts the servers that are not running with the state in their storage.
+    fn start_crashed_servers(&mut self) {
+        for (pid, storage) in &self.storages {
+            if self.servers.contains_key(pid) {
+                continue;
+            }
+            let server_config = ServerConfig {
+                pid: *pid,
+                resend_message_tick_timeout: 50,
+                ..Default::default()
+            };
+            let server = self
+                .cluster_config
+                .clone()
+                .build_for_server(server_config, storage.clone())
+                .expect("failed to build OmniPaxos");
+            self.servers.insert(*pid, server);
+        }
+    }
+
+    fn inject_fault(&mut self) {
+        let pids = self.cluster_config.nodes.clone();
+        let max_crashed = (pids.len() - 1) / 2;
+        match self.rng.gen_range(0..4) {
+            0 if max_crashed > 0 => {
+                let num_crashed = self.rng.gen_range(1..=max_crashed);
+                for pid in pids.choose_multiple(&mut self.rng, num_crashed) {
+                    self.servers.remove(pid);
+                }
+                self.report.crashes += 1;
+            }
+            1 if pids.len() > 1 => {
+                let size = self.rng.gen_range(1..pids.len());
+                let side = pids.choose_multiple(&mut self.rng, size).copied().collect();
+                self.partition = Some(side);
+                self.report.partitions += 1;
+            }
+            2 => {
+                let pid = *pids.choose(&mut self.rng).unwrap();
+                self.storages[&pid].set_write_delay(self.config.slow_disk_delay);
+                self.slow_disk = Some((pid, vec![]));
+                self.report.slow_disks += 1;
+            }
+            _ => {
+                self.drop_rate = self.config.drop_rate;
+                self.report.message_losses += 1;
+            }
+        }
+    }
+
+    /// Removes all faults and restarts the crashed servers.
+    fn heal(&mut self) {
+        self.start_crashed_servers();
+        self.partition = None;
+        self.drop_rate = 0.0;
+        if let Some((pid, pending)) = self.slow_disk.take() {
+            self.storages[&pid].set_write_delay(Duration::ZERO);
+            for msg in pending {
+                self.deliver(msg);
+            }
+        }
+    }
+
+    fn run_ticks(&mut self, ticks: u64) -> Result<(), Violation> {
+        for _ in 0..ticks {
+            self.report.ticks += 1;
+            self.propose();
+            self.exchange_messages();
+            self.verify_safety()?;
+        }
+        Ok(())
+    }
+
+    fn propose(&mut self) {
+        for _ in 0..self.config.proposals_per_tick {
+            let pids: Vec<NodeId> = self.servers.keys().copied().collect();
+            if let Some(pid) = pids.choose(&mut self.rng) {
+                let value = Value(self.next_value);
+                self.next_value += 1;
+                // proposals may be refused, e.g., if the server is recovering
+                let _ = self.servers.get_mut(pid).unwrap().append(value);
+            }
+        }
+    }
+
+    fn exchange_messages(&mut self) {
+        let mut outgoing = vec![];
+        for server in self.servers.values_mut() {
+            server.tick().expect("Failed to tick");
+            outgoing.append(&mut server.outgoing_messages());
+        }
+        for msg in outgoing {
+            if self.is_reachable(&msg) && !self.rng.gen_bool(self.drop_rate) {
+                self.transport.send(msg);
+            }
+        }
+        for msg in self.transport.receive() {
+            match &mut self.slow_disk {
+                Some((pid, pending)) if *pid == msg.get_receiver() => pending.push(msg),
+                _ => self.deliver(msg),
+            }
+        }
+        let tick = self.report.ticks;
+        if let Some((_, pending)) = self
+            .slow_disk
+            .as_mut()
+            .filter(|_| tick.is_multiple_of(SLOW_DISK_FACTOR))
+        {
+            for msg in std::mem::take(pending) {
+                self.deliver(msg);
+            }
+        }
+    }
+
+    fn is_reachable(&self, msg: &Message<Value>) -> bool {
+        match &self.partition {
+            Some(side) => side.contains(&msg.get_sender()) == side.contains(&msg.get_receiver()),
+            None => true,
+        }
+    }
+
+    /// Delivers `msg` unless its receiver crashed or was partitioned from the sender.
+    fn deliver(&mut self, msg: Message<Value>) {
+        if !self.is_reachable(&msg) {
+            return;
+        }
+        if let Some(server) = self.servers.get_mut(&msg.get_receiver()) {
+            server
+                .handle_incoming(msg)
+                .expect("Failed to handle message");
+        }
+    }
+
+    /// Checks the newly decided entries of every server against the decided log.
+    fn verify_safety(&mut self) -> Result<(), Violation> {
+        for (pid, server) in &self.servers {
+            let pid = *pid;
+            let verified_idx = self.verified_idx.get(&pid).copied().unwrap_or_default();
+            let decided_idx = server.get_decided_idx();
+            if decided_idx < verified_idx {
+                return Err(Violation::DecidedIdxDecreased {
+                    pid,
+                    from: verified_idx,
+                    to: decided_idx,
+                });
+            }
+            if decided_idx == verified_idx {
+                continue;
+            }
+            let entries = server.read_decided_suffix(verified_idx).unwrap_or_default();
+            for idx in verified_idx..decided_idx {
+                let found = match entries.get(idx - verified_idx) {
+                    Some(LogEntry::Decided(value)) => value.clone(),
+                    _ => return Err(Violation::NotDecided { pid, idx }),
+                };
+                if found.0 >= self.next_value {
+                    return Err(Violation::NotProposed { pid, idx, found });
+                }
+                match self.decided_log.get(idx) {
+                    Some(expected) if *expected != found => {
+                        return Err(Violation::Disagreement {
+                            pid,
+                            idx,
+                            expected: expected.clone(),
+                            found,
+                        })
+                    }
+                    Some(_) => (),
+                    None => self.decided_log.push(found),
+                }
+            }
+            self.verified_idx.insert(pid, decided_idx);
+        }
+        Ok(())
+    }
+
+    /// Checks that the cluster decided new entries since `decided_idx` and that all servers
+    /// caught up to it.
+    fn check_liveness(&self, decided_idx: usize) -> Result<(), Violation> {
+        let tick = self.report.ticks;
+        if self.decided_log.len() <= decided_idx {
+            return Err(Violation::NoProgress {
+                tick,
+                decided_idx: self.decided_log.len(),
+            });
+        }
+        for (pid, server) in &self.servers {
+            if server.get_decided_idx() < decided_idx {
+                return Err(Violation::NotCaughtUp {
+                    tick,
+                    pid: *pid,
+                    decided_idx: server.get_decided_idx(),
+                    expected: decided_idx,
+                });
+            }
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,71 @@
+use omnipaxos_chaos::ChaosConfig;
+use std::{
+    env,
+    process::ExitCode,
+    str::FromStr,
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+
+const USAGE: &str = "Usage: omnipaxos_chaos [--nodes N] [--ticks N] [--seed N] [--runs N] \
+[--epoch-ticks N] [--quiet-ticks N] [--proposals-per-tick N] [--drop-rate P] [--slow-disk-delay-us N]";
+
+fn parse<T: FromStr>(flag: &str, value: Option<String>) -> Result<T, String> {
+    value
+        .and_then(|v| v.parse().ok())
+        .ok_or_else(|| format!("invalid value for {}\n{}", flag, USAGE))
+}
+
+/// Parses the arguments into the config and the number of runs.
+fn parse_args() -> Result<(ChaosConfig, u64), String> {
+    let mut config = ChaosConfig {
+        seed: SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map_or(0, |d| d.as_nanos() as u64),
+        ..Default::default()
+    };
+    let mut runs = 1;
+    let mut args = env::args().skip(1);
+    while let Some(flag) = args.next() {
+        let value = args.next();
+        match flag.as_str() {
+            "--nodes" => config.nodes = parse(&flag, value)?,
+            "--ticks" => config.ticks = parse(&flag, value)?,
+            "--seed" => config.seed = parse(&flag, value)?,
+            "--runs" => runs = parse(&flag, value)?,
+            "--epoch-ticks" => config.epoch_ticks = parse(&flag, value)?,
+            "--quiet-ticks" => config.quiet_ticks = parse(&flag, value)?,
+            "--proposals-per-tick" => config.proposals_per_tick = parse(&flag, value)?,
+            "--drop-rate" => config.drop_rate = parse(&flag, value)?,
+            "--slow-disk-delay-us" => {
+                config.slow_disk_delay = Duration::from_micros(parse(&flag, value)?)
+            }
+            _ => return Err(USAGE.to_string()),
+        }
+    }
+    if config.nodes == 0 || !(0.0..=1.0).contains(&config.drop_rate) {
+        return Err(USAGE.to_string());
+    }
+    Ok((config, runs))
+}
+
+fn main() -> ExitCode {
+    let (mut config, runs) = match parse_args() {
+        Ok(args) => args,
+        Err(e) => {
+            eprintln!("{}", e);
+            return ExitCode::FAILURE;
+        }
+    };
+    for _ in 0..runs {
+        println!("Running with seed {}", config.seed);
+        match omnipaxos_chaos::run(&config) {
+            Ok(report) => println!("Passed: {}", report),
+            Err(violation) => {
+                eprintln!("Failed with seed {}: {}", config.seed, violation);
+                return ExitCode::FAILURE;
+            }
+        }
+        config.seed = config.seed.wrapping_add(1);
+    }
+    ExitCode::SUCCESS
+}
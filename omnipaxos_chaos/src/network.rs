@@ -0,0 +1,31 @@
+use crate::Value;
+use omnipaxos::messages::Message;
+use std::collections::VecDeque;
+
+/// The transport that carries the messages between the servers of the cluster under test.
+/// Implement it to run the chaos test over your own transport. The harness injects the network
+/// faults itself: it only sends the messages that survive the current partitions and message
+/// loss, and drops received messages whose receiver has crashed in the meantime.
+pub trait Transport {
+    /// Sends `msg` to its receiver.
+    fn send(&mut self, msg: Message<Value>);
+
+    /// Returns the messages that have arrived since the last call.
+    fn receive(&mut self) -> Vec<Message<Value>>;
+}
+
+/// A transport that delivers every sent message in order at the next call to `receive()`.
+#[derive(Default)]
+pub struct InMemoryTransport {
+    in_flight: VecDeque<Message<Value>>,
+}
+
+impl Transport for InMemoryTransport {
+    fn send(&mut self, msg: Message<Value>) {
+        self.in_flight.push_back(msg);
+    }
+
+    fn receive(&mut self) -> Vec<Message<Value>> {
+        self.in_flight.drain(..).collect()
+    }
+}
@@ -0,0 +1,188 @@
+use omnipaxos::{
+    ballot_leader_election::Ballot,
+    storage::{
+        EntriesHint, Entry, LogStorage, StateStorage, StopSign, Storage, StorageOp, StorageResult,
+    },
+};
+use std::{
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Arc, Mutex, MutexGuard,
+    },
+    thread,
+    time::Duration,
+};
+
+/// A storage that wraps another storage and injects faults into it. Clones share the wrapped
+/// storage and the injected faults, so the harness can keep a clone to inject faults and to
+/// recover a crashed server with the state it persisted.
+pub struct FaultyStorage<S> {
+    inner: Arc<Mutex<S>>,
+    write_delay_micros: Arc<AtomicU64>,
+}
+
+impl<S> Clone for FaultyStorage<S> {
+    fn clone(&self) -> Self {
+        Self {
+            inner: self.inner.clone(),
+            write_delay_micros: self.write_delay_micros.clone(),
+        }
+    }
+}
+
+impl<S> FaultyStorage<S> {
+    /// Wraps `storage` without injecting any faults.
+    pub fn new(storage: S) -> Self {
+        Self {
+            inner: Arc::new(Mutex::new(storage)),
+            write_delay_micros: Arc::new(AtomicU64::new(0)),
+        }
+    }
+
+    /// Makes every write to the storage take at least `delay`, e.g., to simulate a slow disk.
+    pub fn set_write_delay(&self, delay: Duration) {
+        self.write_delay_micros
+            .store(delay.as_micros() as u64, Ordering::Relaxed);
+    }
+
+    fn read(&self) -> MutexGuard<'_, S> {
+        self.inner.lock().expect("storage lock poisoned")
+    }
+
+    fn write(&self) -> MutexGuard<'_, S> {
+        let delay = self.write_delay_micros.load(Ordering::Relaxed);
+        if delay > 0 {
+            thread::sleep(Duration::from_micros(delay));
+        }
+        self.read()
+    }
+}
+
+impl<T, S> LogStorage<T> for FaultyStorage<S>
+where
+    T: Entry,
+    S: Storage<T>,
+{
+    fn write_atomically(&mut self, ops: Vec<StorageOp<T>>) -> StorageResult<()> {
+        self.write().write_atomically(ops)
+    }
+
+    fn append_entry(&mut self, entry: T) -> StorageResult<()> {
+        self.write().append_entry(entry)
+    }
+
+    fn append_entries(&mut self, entries: Vec<T>) -> StorageResult<()> {
+        self.write().append_entries(entries)
+    }
+
+    fn append_on_prefix(&mut self, from_idx: usize, entries: Vec<T>) -> StorageResult<()> {
+        self.write().append_on_prefix(from_idx, entries)
+    }
+
+    fn get_entries(&self, from: usize, to: usize) -> StorageResult<Vec<T>> {
+        self.read().get_entries(from, to)
+    }
+
+    fn get_entries_hint(
+        &self,
+        from: usize,
+        to: usize,
+        max_bytes: usize,
+    ) -> StorageResult<EntriesHint<T>> {
+        self.read().get_entries_hint(from, to, max_bytes)
+    }
+
+    fn get_log_len(&self) -> StorageResult<usize> {
+        self.read().get_log_len()
+    }
+
+    fn get_suffix(&self, from: usize) -> StorageResult<Vec<T>> {
+        self.read().get_suffix(from)
+    }
+
+    fn trim(&mut self, idx: usize) -> StorageResult<()> {
+        self.write().trim(idx)
+    }
+
+    fn approximate_size(&self) -> StorageResult<Option<u64>> {
+        self.read().approximate_size()
+    }
+
+    fn verify_entries(&self, from: usize, to: usize) -> StorageResult<()> {
+        self.read().verify_entries(from, to)
+    }
+}
+
+impl<T, S> StateStorage<T> for FaultyStorage<S>
+where
+    T: Entry,
+    S: Storage<T>,
+{
+    fn set_promise(&mut self, n_prom: Ballot) -> StorageResult<()> {
+        self.write().set_promise(n_prom)
+    }
+
+    fn set_decided_idx(&mut self, ld: usize) -> StorageResult<()> {
+        self.write().set_decided_idx(ld)
+    }
+
+    fn get_decided_idx(&self) -> StorageResult<usize> {
+        self.read().get_decided_idx()
+    }
+
+    fn set_accepted_round(&mut self, na: Ballot) -> StorageResult<()> {
+        self.write().set_accepted_round(na)
+    }
+
+    fn get_accepted_round(&self) -> StorageResult<Option<Ballot>> {
+        self.read().get_accepted_round()
+    }
+
+    fn get_promise(&self) -> StorageResult<Option<Ballot>> {
+        self.read().get_promise()
+    }
+
+    fn set_ble_ballot(&mut self, ballot: Ballot) -> StorageResult<()> {
+        self.write().set_ble_ballot(ballot)
+    }
+
+    fn get_ble_ballot(&self) -> StorageResult<Option<Ballot>> {
+        self.read().get_ble_ballot()
+    }
+
+    fn set_standby_promise(&mut self, standby: Option<Ballot>) -> StorageResult<()> {
+        self.write().set_standby_promise(standby)
+    }
+
+    fn get_standby_promise(&self) -> StorageResult<Option<Ballot>> {
+        self.read().get_standby_promise()
+    }
+
+    fn set_stopsign(&mut self, s: Option<StopSign>) -> StorageResult<()> {
+        self.write().set_stopsign(s)
+    }
+
+    fn get_stopsign(&self) -> StorageResult<Option<StopSign>> {
+        self.read().get_stopsign()
+    }
+
+    fn set_compacted_idx(&mut self, idx: usize) -> StorageResult<()> {
+        self.write().set_compacted_idx(idx)
+    }
+
+    fn get_compacted_idx(&self) -> StorageResult<usize> {
+        self.read().get_compacted_idx()
+    }
+
+    fn set_snapshot(&mut self, snapshot: Option<T::Snapshot>) -> StorageResult<()> {
+        self.write().set_snapshot(snapshot)
+    }
+
+    fn get_snapshot(&self) -> StorageResult<Option<T::Snapshot>> {
+        self.read().get_snapshot()
+    }
+
+    fn verify_snapshot(&self) -> StorageResult<()> {
+        self.read().verify_snapshot()
+    }
+}
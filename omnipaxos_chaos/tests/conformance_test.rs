@@ -0,0 +1,70 @@
+use omnipaxos::messages::Message;
+use omnipaxos_chaos::{
+    conformance::{self, ConformanceViolation},
+    run_conformance, InMemoryTransport, Transport, Value,
+};
+
+/// A transport that delivers the messages of every call to `receive()` in reverse order.
+#[derive(Default)]
+struct ReversingTransport(Vec<Message<Value>>);
+
+impl Transport for ReversingTransport {
+    fn send(&mut self, msg: Message<Value>) {
+        self.0.push(msg);
+    }
+
+    fn receive(&mut self) -> Vec<Message<Value>> {
+        self.0.drain(..).rev().collect()
+    }
+}
+
+/// A transport that loses every 100th message.
+#[derive(Default)]
+struct LossyTransport {
+    sent: usize,
+    in_flight: Vec<Message<Value>>,
+}
+
+impl Transport for LossyTransport {
+    fn send(&mut self, msg: Message<Value>) {
+        self.sent += 1;
+        if !self.sent.is_multiple_of(100) {
+            self.in_flight.push(msg);
+        }
+    }
+
+    fn receive(&mut self) -> Vec<Message<Value>> {
+        std::mem::take(&mut self.in_flight)
+    }
+}
+
+/// Runs all conformance cases over the in-memory transport, which delivers the messages in order.
+#[test]
+fn in_memory_conformance_test() {
+    run_conformance(InMemoryTransport::default).unwrap_or_else(|failure| panic!("{}", failure));
+}
+
+/// Verifies that a transport that reorders the messages of a link fails the kit.
+#[test]
+fn reordering_transport_test() {
+    let failure = run_conformance(ReversingTransport::default).unwrap_err();
+    assert_eq!(failure.case, "in_order");
+    assert!(matches!(
+        failure.violation,
+        ConformanceViolation::OutOfOrder { .. }
+    ));
+}
+
+/// Verifies that a transport that loses messages fails the kit.
+#[test]
+fn lossy_transport_test() {
+    let case = conformance::cases()
+        .into_iter()
+        .find(|case| case.name == "in_order")
+        .unwrap();
+    let violation = conformance::run_case(&case, LossyTransport::default()).unwrap_err();
+    assert!(matches!(
+        violation,
+        ConformanceViolation::OutOfOrder { .. } | ConformanceViolation::Lost { .. }
+    ));
+}
@@ -0,0 +1,35 @@
+use omnipaxos_chaos::{run_with, ChaosConfig, FaultyStorage, InMemoryTransport};
+use omnipaxos_storage::memory_storage::MemoryStorage;
+
+/// Runs a short chaos test over several fault epochs. The nightly soak test runs the binary with
+/// many more ticks and seeds.
+#[test]
+fn short_soak_test() {
+    for seed in 0..3 {
+        let config = ChaosConfig {
+            ticks: 6000,
+            seed,
+            ..Default::default()
+        };
+        let report = omnipaxos_chaos::run(&config)
+            .unwrap_or_else(|violation| panic!("seed {}: {}", seed, violation));
+        assert!(report.decided_idx > 0);
+    }
+}
+
+/// Runs the harness over a storage that is itself wrapped, as a user validating their own storage
+/// implementation would.
+#[test]
+fn custom_storage_test() {
+    let config = ChaosConfig {
+        nodes: 3,
+        ticks: 3000,
+        ..Default::default()
+    };
+    run_with(
+        &config,
+        |_| FaultyStorage::new(MemoryStorage::default()),
+        InMemoryTransport::default(),
+    )
+    .unwrap_or_else(|violation| panic!("{}", violation));
+}
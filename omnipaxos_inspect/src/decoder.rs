@@ -0,0 +1,58 @@
+use serde::de::DeserializeOwned;
+use std::{error::Error, fmt::Debug, fmt::Write, marker::PhantomData};
+
+/// Turns the bytes of a persisted entry or snapshot into text. The bytes are encoded as the
+/// storage wrote them, e.g., with bincode by `PersistentStorage`, or with the `ArchiveCodec` of the
+/// application in archives.
+pub trait EntryDecoder {
+    /// Returns the text to print for `bytes`.
+    fn decode(&self, bytes: &[u8]) -> Result<String, Box<dyn Error>>;
+}
+
+/// Prints the bytes in hexadecimal. Works for any encoding.
+#[derive(Copy, Clone, Debug, Default)]
+pub struct HexDecoder;
+
+impl EntryDecoder for HexDecoder {
+    fn decode(&self, bytes: &[u8]) -> Result<String, Box<dyn Error>> {
+        let mut hex = String::with_capacity(bytes.len() * 2);
+        for b in bytes {
+            let _ = write!(hex, "{:02x}", b);
+        }
+        Ok(hex)
+    }
+}
+
+/// Prints the bytes as UTF-8 text, e.g., for entries encoded as JSON.
+#[derive(Copy, Clone, Debug, Default)]
+pub struct Utf8Decoder;
+
+impl EntryDecoder for Utf8Decoder {
+    fn decode(&self, bytes: &[u8]) -> Result<String, Box<dyn Error>> {
+        Ok(std::str::from_utf8(bytes)?.to_string())
+    }
+}
+
+/// Deserializes the bytes into a `T` with bincode, as written by `PersistentStorage`, and prints it
+/// with its `Debug` implementation.
+pub struct BincodeDecoder<T>(PhantomData<T>);
+
+impl<T> BincodeDecoder<T> {
+    /// Creates a decoder for entries of type `T`.
+    pub fn new() -> Self {
+        Self(PhantomData)
+    }
+}
+
+impl<T> Default for BincodeDecoder<T> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<T: DeserializeOwned + Debug> EntryDecoder for BincodeDecoder<T> {
+    fn decode(&self, bytes: &[u8]) -> Result<String, Box<dyn Error>> {
+        let value: T = bincode::deserialize(bytes)?;
+        Ok(format!("{:?}", value))
+    }
+}
@@ -0,0 +1,172 @@
+//! A tool to inspect the persisted state of an [OmniPaxos](https://crates.io/crates/omnipaxos)
+//! server, e.g., while debugging an incident. It prints the promise, the rounds, the decided and
+//! compacted indexes, and the StopSign of a storage, and dumps ranges of its entries.
+//!
+//! The `omnipaxos-inspect` binary prints the entries in hexadecimal or as UTF-8 text. To print them
+//! as values of the application, build a binary that calls [`main_with`] with an [`EntryDecoder`],
+//! e.g., a [`BincodeDecoder`] of the entry type for a `PersistentStorage`.
+
+#![deny(missing_docs)]
+/// Decoders that turn the encoded entries into text.
+pub mod decoder;
+/// Readers of the persisted state of the storage backends.
+pub mod source;
+
+pub use decoder::{BincodeDecoder, EntryDecoder, HexDecoder, Utf8Decoder};
+pub use source::{
+    ArchiveSource, InspectResult, PersistentStorageSource, StorageSource, StorageSummary,
+};
+use std::{
+    env,
+    io::{self, Write},
+    path::{Path, PathBuf},
+    process::ExitCode,
+    str::FromStr,
+};
+
+const USAGE: &str = "Usage: omnipaxos-inspect <rocksdb|archive> <PATH> [--from IDX] [--to IDX] \
+[--format hex|utf8] [--snapshot]";
+
+/// The storage backends that can be inspected.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum Backend {
+    /// The RocksDB directory of a `PersistentStorage`.
+    RocksDb,
+    /// An archive written by `write_archive`, e.g., a dump of a `MemoryStorage`.
+    Archive,
+}
+
+/// The arguments of the inspect tool.
+/// # Fields
+/// * `backend`: The backend of the storage.
+/// * `path`: The path to the storage.
+/// * `from`: The index of the first entry to dump. Defaults to the compacted index.
+/// * `to`: The index after the last entry to dump. Defaults to the end of the log.
+/// * `dump_entries`: Whether to dump entries. Set if `from` or `to` is given.
+/// * `dump_snapshot`: Whether to dump the snapshot.
+/// * `format`: The decoder selected with `--format`, which replaces the decoder of the binary.
+pub struct InspectArgs {
+    /// The backend of the storage.
+    pub backend: Backend,
+    /// The path to the storage.
+    pub path: PathBuf,
+    /// The index of the first entry to dump.
+    pub from: Option<usize>,
+    /// The index after the last entry to dump.
+    pub to: Option<usize>,
+    /// Whether to dump entries.
+    pub dump_entries: bool,
+    /// Whether to dump the snapshot.
+    pub dump_snapshot: bool,
+    /// The decoder selected with `--format`.
+    pub format: Option<Box<dyn EntryDecoder>>,
+}
+
+fn parse<T: FromStr>(flag: &str, value: Option<String>) -> Result<T, String> {
+    value
+        .and_then(|v| v.parse().ok())
+        .ok_or_else(|| format!("invalid value for {}\n{}", flag, USAGE))
+}
+
+/// Parses the arguments of the inspect tool, without the name of the binary.
+pub fn parse_args<I: IntoIterator<Item = String>>(args: I) -> Result<InspectArgs, String> {
+    let mut args = args.into_iter();
+    let backend = match args.next().as_deref() {
+        Some("rocksdb") => Backend::RocksDb,
+        Some("archive") => Backend::Archive,
+        _ => return Err(USAGE.to_string()),
+    };
+    let path = args.next().ok_or_else(|| USAGE.to_string())?.into();
+    let mut parsed = InspectArgs {
+        backend,
+        path,
+        from: None,
+        to: None,
+        dump_entries: false,
+        dump_snapshot: false,
+        format: None,
+    };
+    while let Some(flag) = args.next() {
+        match flag.as_str() {
+            "--from" => parsed.from = Some(parse(&flag, args.next())?),
+            "--to" => parsed.to = Some(parse(&flag, args.next())?),
+            "--snapshot" => parsed.dump_snapshot = true,
+            "--format" => {
+                let decoder: Box<dyn EntryDecoder> = match args.next().as_deref() {
+                    Some("hex") => Box::new(HexDecoder),
+                    Some("utf8") => Box::new(Utf8Decoder),
+                    _ => return Err(format!("invalid value for {}\n{}", flag, USAGE)),
+                };
+                parsed.format = Some(decoder);
+            }
+            _ => return Err(USAGE.to_string()),
+        }
+    }
+    parsed.dump_entries = parsed.from.is_some() || parsed.to.is_some();
+    Ok(parsed)
+}
+
+/// Opens the storage at `path`.
+pub fn open(backend: Backend, path: &Path) -> InspectResult<Box<dyn StorageSource>> {
+    Ok(match backend {
+        Backend::RocksDb => Box::new(PersistentStorageSource::open(path)?),
+        Backend::Archive => Box::new(ArchiveSource::open(path)?),
+    })
+}
+
+/// Writes the summary of `source` to `out`, followed by the snapshot and the entries in the range
+/// of `args` if requested. Entries that `decoder` fails to decode are printed with the error
+/// instead, so that a single corrupt entry does not hide the rest of the log.
+pub fn inspect(
+    source: &dyn StorageSource,
+    args: &InspectArgs,
+    decoder: &dyn EntryDecoder,
+    out: &mut dyn Write,
+) -> InspectResult<()> {
+    let summary = source.summary()?;
+    writeln!(out, "{}", summary)?;
+    let decode = |bytes: &[u8]| match decoder.decode(bytes) {
+        Ok(text) => text,
+        Err(e) => format!("<failed to decode {} bytes: {}>", bytes.len(), e),
+    };
+    if args.dump_snapshot {
+        if let Some(snapshot) = source.snapshot()? {
+            writeln!(out, "\nsnapshot at {}:", summary.compacted_idx)?;
+            writeln!(out, "{}", decode(&snapshot))?;
+        }
+    }
+    if args.dump_entries {
+        let from = args.from.unwrap_or(summary.compacted_idx);
+        let to = args.to.unwrap_or(summary.log_end_idx);
+        writeln!(out, "\nentries [{}, {}):", from, to)?;
+        for (idx, bytes) in source.entries(from, to)? {
+            writeln!(out, "{}: {}", idx, decode(&bytes))?;
+        }
+    }
+    Ok(())
+}
+
+/// Runs the inspect tool with the arguments of the process, and prints the entries with
+/// `decoder` unless another format is selected with `--format`.
+pub fn main_with(decoder: &dyn EntryDecoder) -> ExitCode {
+    let args = match parse_args(env::args().skip(1)) {
+        Ok(args) => args,
+        Err(e) => {
+            eprintln!("{}", e);
+            return ExitCode::FAILURE;
+        }
+    };
+    let decoder = match &args.format {
+        Some(format) => format.as_ref(),
+        None => decoder,
+    };
+    let result = open(args.backend, &args.path)
+        .and_then(|source| inspect(source.as_ref(), &args, decoder, &mut io::stdout().lock()));
+    match result {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(e) => {
+            eprintln!("Failed to inspect {}: {}", args.path.display(), e);
+            ExitCode::FAILURE
+        }
+    }
+}
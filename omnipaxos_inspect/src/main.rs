@@ -0,0 +1,6 @@
+use omnipaxos_inspect::HexDecoder;
+use std::process::ExitCode;
+
+fn main() -> ExitCode {
+    omnipaxos_inspect::main_with(&HexDecoder)
+}
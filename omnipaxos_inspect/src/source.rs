@@ -0,0 +1,235 @@
+use omnipaxos::{
+    ballot_leader_election::Ballot,
+    storage::{
+        archive::{ArchiveReader, ArchiveRecord},
+        StopSign,
+    },
+};
+use omnipaxos_storage::persistent_storage::{
+    ACC, BLE_BALLOT, DECIDE, LOG, NPROM, SNAPSHOT, STOPSIGN, TRIM,
+};
+use rocksdb::{Options, DB};
+use serde::de::DeserializeOwned;
+use std::{error::Error, fmt, fs::File, io::BufReader, path::Path};
+
+/// The result of reading a storage.
+pub type InspectResult<T> = Result<T, Box<dyn Error>>;
+
+/// The persisted state of a server, apart from the entries themselves.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct StorageSummary {
+    /// The promised ballot.
+    pub promise: Option<Ballot>,
+    /// The ballot in which the log was last accepted.
+    pub accepted_round: Option<Ballot>,
+    /// The highest ballot used in the leader election.
+    pub ble_ballot: Option<Ballot>,
+    /// The decided index.
+    pub decided_idx: usize,
+    /// The compacted index.
+    pub compacted_idx: usize,
+    /// The index after the last entry of the log.
+    pub log_end_idx: usize,
+    /// The size of the encoded snapshot in bytes, if there is one.
+    pub snapshot_size: Option<usize>,
+    /// The StopSign at the end of the log.
+    pub stopsign: Option<StopSign>,
+}
+
+impl fmt::Display for StorageSummary {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let ballot = |b: &Option<Ballot>| match b {
+            Some(b) => format!(
+                "config_id: {}, n: {}, priority: {}, pid: {}",
+                b.config_id, b.n, b.priority, b.pid
+            ),
+            None => "none".to_string(),
+        };
+        writeln!(f, "promise:                {}", ballot(&self.promise))?;
+        writeln!(
+            f,
+            "accepted round:         {}",
+            ballot(&self.accepted_round)
+        )?;
+        writeln!(f, "leader election ballot: {}", ballot(&self.ble_ballot))?;
+        writeln!(f, "decided index:          {}", self.decided_idx)?;
+        writeln!(f, "compacted index:        {}", self.compacted_idx)?;
+        writeln!(f, "log end index:          {}", self.log_end_idx)?;
+        match self.snapshot_size {
+            Some(size) => writeln!(f, "snapshot:               {} bytes", size)?,
+            None => writeln!(f, "snapshot:               none")?,
+        }
+        match &self.stopsign {
+            Some(ss) => write!(
+                f,
+                "stopsign:               config_id: {}, nodes: {:?}",
+                ss.next_config.configuration_id, ss.next_config.nodes
+            ),
+            None => write!(f, "stopsign:               none"),
+        }
+    }
+}
+
+/// A storage whose state can be inspected. The entries are returned as the bytes the storage
+/// persisted, so that a source works for any type of entries.
+pub trait StorageSource {
+    /// Reads the persisted state.
+    fn summary(&self) -> InspectResult<StorageSummary>;
+
+    /// Reads the encoded entries in the range `[from, to)` together with their indexes. Entries
+    /// that are compacted or beyond the end of the log are left out.
+    fn entries(&self, from: usize, to: usize) -> InspectResult<Vec<(usize, Vec<u8>)>>;
+
+    /// Reads the encoded snapshot, if there is one.
+    fn snapshot(&self) -> InspectResult<Option<Vec<u8>>>;
+}
+
+/// Reads the RocksDB directory of a `PersistentStorage`. The database is opened read-only, so it
+/// can be inspected while the server is running, although the state might then be slightly behind.
+pub struct PersistentStorageSource {
+    db: DB,
+}
+
+impl PersistentStorageSource {
+    /// Opens the database at `path`.
+    pub fn open<P: AsRef<Path>>(path: P) -> InspectResult<Self> {
+        let db = DB::open_cf_for_read_only(&Options::default(), path, [LOG], false)?;
+        Ok(Self { db })
+    }
+
+    fn deserialize<V: DeserializeOwned>(&self, key: &[u8]) -> InspectResult<Option<V>> {
+        match self.db.get_pinned(key)? {
+            Some(bytes) => Ok(Some(bincode::deserialize(&bytes)?)),
+            None => Ok(None),
+        }
+    }
+
+    fn index(&self, key: &[u8]) -> InspectResult<usize> {
+        match self.db.get_pinned(key)? {
+            Some(bytes) => Ok(usize::from_ne_bytes(bytes.as_ref().try_into()?)),
+            None => Ok(0),
+        }
+    }
+
+    fn log_key(key: &[u8]) -> InspectResult<usize> {
+        Ok(usize::from_be_bytes(key.try_into()?))
+    }
+}
+
+impl StorageSource for PersistentStorageSource {
+    fn summary(&self) -> InspectResult<StorageSummary> {
+        let compacted_idx = self.index(TRIM)?;
+        let log = self
+            .db
+            .cf_handle(LOG)
+            .ok_or("missing the log column family")?;
+        let mut iter = self.db.raw_iterator_cf(log);
+        iter.seek_to_last();
+        let log_end_idx = match iter.key() {
+            Some(key) => Self::log_key(key)? + 1,
+            None => compacted_idx,
+        };
+        iter.status()?;
+        Ok(StorageSummary {
+            promise: self.deserialize(NPROM)?,
+            accepted_round: self.deserialize(ACC)?,
+            ble_ballot: self.deserialize(BLE_BALLOT)?,
+            decided_idx: self.index(DECIDE)?,
+            compacted_idx,
+            log_end_idx,
+            snapshot_size: self.snapshot()?.map(|s| s.len()),
+            stopsign: self.deserialize::<Option<StopSign>>(STOPSIGN)?.flatten(),
+        })
+    }
+
+    fn entries(&self, from: usize, to: usize) -> InspectResult<Vec<(usize, Vec<u8>)>> {
+        let log = self
+            .db
+            .cf_handle(LOG)
+            .ok_or("missing the log column family")?;
+        let mut iter = self.db.raw_iterator_cf(log);
+        let mut entries = vec![];
+        iter.seek(from.to_be_bytes());
+        while let (Some(key), Some(value)) = (iter.key(), iter.value()) {
+            let idx = Self::log_key(key)?;
+            if idx >= to {
+                break;
+            }
+            entries.push((idx, value.to_vec()));
+            iter.next();
+        }
+        iter.status()?;
+        Ok(entries)
+    }
+
+    /// Returns the snapshot as the bincode encoding of the `T::Snapshot`. An empty `Option` that
+    /// was persisted is reported as no snapshot.
+    fn snapshot(&self) -> InspectResult<Option<Vec<u8>>> {
+        match self.db.get(SNAPSHOT)? {
+            // `None` is serialized as a single zero byte and `Some` prefixed with a one.
+            Some(bytes) if bytes.first() == Some(&1) => Ok(Some(bytes[1..].to_vec())),
+            _ => Ok(None),
+        }
+    }
+}
+
+/// Reads an archive written by `omnipaxos::storage::archive::write_archive`. As an archive can be
+/// written from any `Storage`, this is also how the state of storages without their own files,
+/// e.g., a `MemoryStorage`, is dumped and inspected. The archive is read into memory when opened.
+pub struct ArchiveSource {
+    summary: StorageSummary,
+    entries: Vec<(usize, Vec<u8>)>,
+    snapshot: Option<Vec<u8>>,
+}
+
+impl ArchiveSource {
+    /// Reads the archive at `path`.
+    pub fn open<P: AsRef<Path>>(path: P) -> InspectResult<Self> {
+        let reader = ArchiveReader::new(BufReader::new(File::open(path)?))?;
+        let mut summary = StorageSummary::default();
+        let mut entries = vec![];
+        let mut snapshot = None;
+        for record in reader {
+            match record? {
+                ArchiveRecord::Metadata(m) => {
+                    summary.promise = m.promise;
+                    summary.accepted_round = m.accepted_round;
+                    summary.ble_ballot = m.ble_ballot;
+                    summary.decided_idx = m.decided_idx;
+                    summary.compacted_idx = m.compacted_idx;
+                }
+                ArchiveRecord::Snapshot { data, .. } => snapshot = Some(data),
+                ArchiveRecord::Entry { idx, data } => entries.push((idx, data)),
+                ArchiveRecord::StopSign(ss) => summary.stopsign = Some(ss),
+            }
+        }
+        summary.log_end_idx = entries
+            .last()
+            .map_or(summary.compacted_idx, |(idx, _)| idx + 1);
+        summary.snapshot_size = snapshot.as_ref().map(Vec::len);
+        Ok(Self {
+            summary,
+            entries,
+            snapshot,
+        })
+    }
+}
+
+impl StorageSource for ArchiveSource {
+    fn summary(&self) -> InspectResult<StorageSummary> {
+        Ok(self.summary.clone())
+    }
+
+    fn entries(&self, from: usize, to: usize) -> InspectResult<Vec<(usize, Vec<u8>)>> {
+        Ok(self
+            .entries
+            .iter()
+            .filter(|(idx, _)| (from..to).contains(idx))
+            .cloned()
+            .collect())
+    }
+
+    fn snapshot(&self) -> InspectResult<Option<Vec<u8>>> {
+        Ok(self.snapshot.clone())
+    }
+}
@@ -0,0 +1,139 @@
+use omnipaxos::{
+    ballot_leader_election::Ballot,
+    storage::{
+        archive::{write_archive, ArchiveCodec},
+        Entry, LogStorage, NoSnapshot, StateStorage, StopSign,
+    },
+    ClusterConfig,
+};
+use omnipaxos_inspect::{
+    inspect, parse_args, ArchiveSource, BincodeDecoder, PersistentStorageSource, StorageSource,
+};
+use omnipaxos_storage::{
+    memory_storage::MemoryStorage,
+    persistent_storage::{PersistentStorage, PersistentStorageConfig},
+};
+use serde::{Deserialize, Serialize};
+use std::{error::Error, fs::File};
+use tempfile::TempDir;
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+struct Value(u64);
+
+impl Entry for Value {
+    type Snapshot = NoSnapshot;
+}
+
+/// Encodes values as little-endian u64s.
+struct LeCodec;
+
+impl ArchiveCodec<Value> for LeCodec {
+    fn encode_entry(&self, entry: &Value) -> Result<Vec<u8>, Box<dyn Error>> {
+        Ok(entry.0.to_le_bytes().to_vec())
+    }
+
+    fn decode_entry(&self, bytes: &[u8]) -> Result<Value, Box<dyn Error>> {
+        Ok(Value(u64::from_le_bytes(bytes.try_into()?)))
+    }
+}
+
+const PROMISE: Ballot = Ballot {
+    config_id: 1,
+    n: 3,
+    priority: 0,
+    pid: 2,
+};
+
+/// Writes the same state to `storage`: 10 entries of which the first 4 are trimmed, 6 decided,
+/// and a StopSign.
+fn fill<S: LogStorage<Value> + StateStorage<Value>>(storage: &mut S) {
+    storage.set_promise(PROMISE).unwrap();
+    storage.set_accepted_round(PROMISE).unwrap();
+    storage
+        .append_entries((0..10).map(Value).collect())
+        .unwrap();
+    storage.set_decided_idx(6).unwrap();
+    storage.trim(4).unwrap();
+    storage.set_compacted_idx(4).unwrap();
+    let next_config = ClusterConfig {
+        configuration_id: 2,
+        nodes: vec![1, 2, 3],
+        ..Default::default()
+    };
+    storage
+        .set_stopsign(Some(StopSign::with(next_config, None)))
+        .unwrap();
+}
+
+fn check_summary(source: &dyn StorageSource) {
+    let summary = source.summary().unwrap();
+    assert_eq!(summary.promise, Some(PROMISE));
+    assert_eq!(summary.accepted_round, Some(PROMISE));
+    assert_eq!(summary.ble_ballot, None);
+    assert_eq!(summary.decided_idx, 6);
+    assert_eq!(summary.compacted_idx, 4);
+    assert_eq!(summary.log_end_idx, 10);
+    assert_eq!(summary.snapshot_size, None);
+    assert_eq!(summary.stopsign.unwrap().next_config.configuration_id, 2);
+}
+
+/// Verifies that the state of a `PersistentStorage` is read without knowing the type of the
+/// entries, and that the entries are dumped with a user-provided decoder.
+#[test]
+fn persistent_storage_test() {
+    let dir = TempDir::new().unwrap();
+    let path = dir.path().join("db").to_string_lossy().to_string();
+    {
+        let config = PersistentStorageConfig::with_path(path.clone());
+        let mut storage = PersistentStorage::<Value>::open(config);
+        fill(&mut storage);
+    }
+    let source = PersistentStorageSource::open(&path).unwrap();
+    check_summary(&source);
+    let entries = source.entries(0, 7).unwrap();
+    let indexes: Vec<_> = entries.iter().map(|(idx, _)| *idx).collect();
+    assert_eq!(indexes, vec![4, 5, 6]);
+
+    let args = parse_args(["rocksdb", &path, "--from", "8"].map(String::from)).unwrap();
+    let mut out = vec![];
+    inspect(&source, &args, &BincodeDecoder::<Value>::new(), &mut out).unwrap();
+    let out = String::from_utf8(out).unwrap();
+    assert!(out.contains("decided index:          6"), "{}", out);
+    assert!(
+        out.contains("entries [8, 10):\n8: Value(8)\n9: Value(9)\n"),
+        "{}",
+        out
+    );
+}
+
+/// Verifies that a dump of a `MemoryStorage` in the archive format is read, and that entries that
+/// fail to decode are reported without aborting the dump.
+#[test]
+fn archive_test() {
+    let mut storage = MemoryStorage::default();
+    fill(&mut storage);
+    let dir = TempDir::new().unwrap();
+    let path = dir.path().join("dump.opxa");
+    write_archive(&storage, &LeCodec, File::create(&path).unwrap()).unwrap();
+
+    let source = ArchiveSource::open(&path).unwrap();
+    check_summary(&source);
+    assert_eq!(
+        source.entries(5, 6).unwrap(),
+        vec![(5, 5u64.to_le_bytes().to_vec())]
+    );
+
+    let path = path.to_string_lossy().to_string();
+    let args =
+        parse_args(["archive", &path, "--to", "5", "--format", "hex"].map(String::from)).unwrap();
+    let mut out = vec![];
+    inspect(&source, &args, args.format.as_deref().unwrap(), &mut out).unwrap();
+    let out = String::from_utf8(out).unwrap();
+    assert!(out.contains("4: 0400000000000000\n"), "{}", out);
+
+    let mut out = vec![];
+    inspect(&source, &args, &BincodeDecoder::<String>::new(), &mut out).unwrap();
+    let out = String::from_utf8(out).unwrap();
+    assert!(out.contains("4: <failed to decode 8 bytes"), "{}", out);
+    assert!(parse_args(["sled", &path].map(String::from)).is_err());
+}
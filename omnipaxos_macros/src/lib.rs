@@ -1,9 +1,18 @@
 use proc_macro::TokenStream;
-use quote::quote;
-use syn::{parse_macro_input, DeriveInput, Ident};
+use quote::{__private::TokenStream as TokenStream2, quote};
+use syn::{parse::ParseStream, parse_macro_input, token, DeriveInput, Ident, Member};
 
 /// Derive macro for declaring an [OmniPaxos](https://crates.io/crates/omnipaxos) log entry type.
 ///
+/// # Attributes
+/// * `snapshot(S)`: (Optional) The snapshot type `S` of the entries, which implements the `Snapshot` trait.
+/// * `snapshot(map(key = k, value = v))`: (Optional) Generates the snapshot type `<Entry>Snapshot`, which maps the
+///   field `k` of every entry to the field `v` of the latest entry with that key. If `value` is omitted, the key is mapped
+///   to the whole entry. An `Option` value keeps deleted keys as `None`, so that deletes are merged correctly.
+///   The generated type also derives `Serialize` and `Deserialize` if the `serde` feature of OmniPaxos is enabled.
+///
+/// Without the `snapshot` attribute, the entries don't use snapshots.
+///
 /// ## Usage
 ///
 /// ```ignore
@@ -13,6 +22,13 @@ use syn::{parse_macro_input, DeriveInput, Ident};
 ///     pub key: String,
 ///     pub value: u64,
 /// }
+///
+/// #[derive(Clone, Debug, Entry)]
+/// #[snapshot(map(key = key, value = value))] // generates `KeyValueSnapshot { map: HashMap<String, Option<u64>> }`
+/// pub struct KeyValue {
+///     pub key: String,
+///     pub value: Option<u64>,
+/// }
 /// ```
 #[proc_macro_derive(Entry, attributes(snapshot))]
 pub fn entry_derive(input: TokenStream) -> TokenStream {
@@ -21,13 +37,16 @@ pub fn entry_derive(input: TokenStream) -> TokenStream {
 
     // Get the name of the struct we're deriving Entry for
     let name = &ast.ident;
-    let snapshot_type = get_snapshot_type(&ast);
+    let (impl_generics, ty_generics, where_clause) = ast.generics.split_for_impl();
+    let (snapshot_type, snapshot_impl) = get_snapshot(&ast);
     // Generate the implementation of Entry using the quote! macro
     let gen = quote! {
-        impl ::omnipaxos::storage::Entry for #name
+        impl #impl_generics ::omnipaxos::storage::Entry for #name #ty_generics #where_clause
         {
             type Snapshot = #snapshot_type;
         }
+
+        #snapshot_impl
     };
 
     // Convert the generated code back into tokens and return them
@@ -69,7 +88,7 @@ pub fn unicache_entry_derive(input: TokenStream) -> TokenStream {
     let name = &ast.ident;
     let (impl_generics, ty_generics, where_clause) = ast.generics.split_for_impl();
 
-    let snapshot_type = get_snapshot_type(&ast);
+    let (snapshot_type, snapshot_impl) = get_snapshot(&ast);
     let mut cache_type = None;
 
     match ast.data {
@@ -155,6 +174,8 @@ pub fn unicache_entry_derive(input: TokenStream) -> TokenStream {
             quote! {
                 use ::omnipaxos::unicache::FieldCache;      // TODO remove this and use fully qualified path in the method calls instead?
 
+                #snapshot_impl
+
                 impl #impl_generics Entry for #name #ty_generics #where_clause {
                     type Snapshot = #snapshot_type;
                     type Encoded = (#(#encodable_field_attr_types,)*);
@@ -206,18 +227,126 @@ fn suffix(ident: &Ident, name: &str) -> syn::Ident {
     syn::Ident::new(format!("{}{}", ident, name).as_str(), ident.span())
 }
 
-fn get_snapshot_type(ast: &DeriveInput) -> quote::__private::TokenStream {
-    let snapshot_type = ast
+/// Returns the snapshot type declared with the `snapshot` attribute and the items generated for it.
+fn get_snapshot(ast: &DeriveInput) -> (TokenStream2, TokenStream2) {
+    let attr = ast
         .attrs
         .iter()
-        .find_map(|attr| {
-            if attr.path().is_ident("snapshot") {
-                let t = attr.parse_args::<syn::Type>().expect("Expected type");
-                Some(quote!(#t))
+        .find(|attr| attr.path().is_ident("snapshot"));
+    let attr = match attr {
+        Some(attr) => attr,
+        None => return (quote!(::omnipaxos::storage::NoSnapshot), quote!()),
+    };
+    let is_map = attr
+        .parse_args_with(|input: ParseStream| {
+            let ident: Ident = input.parse()?;
+            let is_map = ident == "map" && input.peek(token::Paren);
+            input.parse::<TokenStream2>()?;
+            Ok(is_map)
+        })
+        .unwrap_or(false);
+    if !is_map {
+        let t = attr.parse_args::<syn::Type>().expect("Expected type");
+        return (quote!(#t), quote!());
+    }
+    let mut key = None;
+    let mut value = None;
+    attr.parse_nested_meta(|meta| {
+        meta.parse_nested_meta(|meta| {
+            let member: Member = meta.value()?.parse()?;
+            if meta.path.is_ident("key") {
+                key = Some(member);
+            } else if meta.path.is_ident("value") {
+                value = Some(member);
             } else {
-                None
+                let path = meta.path;
+                panic!("Found unexpected attribute `{}`", quote!(#path))
             }
+            Ok(())
         })
-        .unwrap_or_else(|| quote!(::omnipaxos::storage::NoSnapshot));
-    snapshot_type
+    })
+    .unwrap_or_else(|e| panic!("Expected a valid attribute {}: {}", quote!(#attr), e));
+    let key = key.expect("Expected a `key` field in `map(...)`");
+    map_snapshot(ast, &key, value.as_ref())
+}
+
+/// Generates a snapshot type that maps the `key` field of the entries to their `value` field, or
+/// to the whole entry if there is no `value`.
+fn map_snapshot(
+    ast: &DeriveInput,
+    key: &Member,
+    value: Option<&Member>,
+) -> (TokenStream2, TokenStream2) {
+    let name = &ast.ident;
+    let vis = &ast.vis;
+    if !ast.generics.params.is_empty() {
+        panic!("`map` snapshots are not supported for generic entries");
+    }
+    let fields = match &ast.data {
+        syn::Data::Struct(data) => &data.fields,
+        _ => panic!("`map` snapshots are only supported for structs"),
+    };
+    let field_type = |member: &Member| {
+        fields
+            .iter()
+            .enumerate()
+            .find_map(|(i, field)| {
+                let found = match (member, &field.ident) {
+                    (Member::Named(m), Some(ident)) => m == ident,
+                    (Member::Unnamed(m), None) => m.index as usize == i,
+                    _ => false,
+                };
+                found.then_some(&field.ty)
+            })
+            .unwrap_or_else(|| panic!("No field `{}` in `{}`", quote!(#member), name))
+    };
+    let key_type = field_type(key);
+    let (value_type, value_expr) = match value {
+        Some(value) => {
+            let ty = field_type(value);
+            (quote!(#ty), quote!(entry.#value.clone()))
+        }
+        None => (quote!(#name), quote!(entry.clone())),
+    };
+    let snapshot_name = suffix(name, "Snapshot");
+    let serde_attrs = if cfg!(feature = "serde") {
+        quote! {
+            #[derive(::omnipaxos::__private::serde::Serialize, ::omnipaxos::__private::serde::Deserialize)]
+            #[serde(crate = "::omnipaxos::__private::serde")]
+        }
+    } else {
+        quote!()
+    };
+    let doc = format!(
+        "The latest value of every key in a log of [`{}`] entries. Generated by `#[derive(Entry)]`.",
+        name
+    );
+    let items = quote! {
+        #[doc = #doc]
+        #[derive(Clone, Debug, Default)]
+        #serde_attrs
+        #vis struct #snapshot_name {
+            /// The latest value of every key.
+            pub map: ::std::collections::HashMap<#key_type, #value_type>,
+        }
+
+        impl ::omnipaxos::storage::Snapshot<#name> for #snapshot_name {
+            fn create(entries: &[#name]) -> Self {
+                let mut map = ::std::collections::HashMap::new();
+                for entry in entries {
+                    map.insert(entry.#key.clone(), #value_expr);
+                }
+                Self { map }
+            }
+
+            fn merge(&mut self, delta: Self) {
+                self.map.extend(delta.map);
+            }
+
+            fn use_snapshots() -> bool {
+                true
+            }
+        }
+    };
+    (quote!(#snapshot_name), items)
 }
@@ -26,3 +26,90 @@ fn build_op_test() {
     let _omnipaxos: OmniPaxos<TestEntry, MemoryStorage<TestEntry>> =
         config.build(MemoryStorage::default()).unwrap();
 }
+
+#[test]
+fn map_snapshot_test() {
+    use omnipaxos::{
+        macros::Entry, storage::Snapshot, util::LogEntry, ClusterConfig, OmniPaxos,
+        OmniPaxosConfig, ServerConfig,
+    };
+    use omnipaxos_storage::memory_storage::MemoryStorage;
+    use serde::{Deserialize, Serialize};
+
+    #[derive(Clone, Debug, PartialEq, Entry)]
+    #[snapshot(map(key = key, value = value))]
+    struct KeyValue {
+        key: String,
+        value: Option<u64>,
+    }
+
+    #[derive(Clone, Debug, PartialEq, Serialize, Deserialize, Entry)]
+    #[snapshot(map(key = 0))]
+    struct Record(u64, String);
+
+    let put = |key: &str, value| KeyValue {
+        key: key.to_string(),
+        value,
+    };
+    let mut snapshot = KeyValueSnapshot::create(&[put("a", Some(1)), put("b", Some(2))]);
+    snapshot.merge(KeyValueSnapshot::create(&[
+        put("a", Some(3)),
+        put("b", None),
+        put("c", Some(4)),
+    ]));
+    assert_eq!(snapshot.map.len(), 3);
+    assert_eq!(snapshot.map["a"], Some(3));
+    assert_eq!(snapshot.map["b"], None);
+    assert_eq!(snapshot.map["c"], Some(4));
+    assert!(KeyValueSnapshot::use_snapshots());
+
+    let records = [Record(1, "a".to_string()), Record(1, "b".to_string())];
+    let snapshot = RecordSnapshot::create(&records);
+    assert_eq!(snapshot.map.len(), 1);
+    assert_eq!(snapshot.map[&1], records[1]);
+
+    // The generated snapshot compacts the log of a cluster
+    let cluster_config = ClusterConfig {
+        configuration_id: 1,
+        nodes: vec![1, 2, 3],
+        ..Default::default()
+    };
+    let mut nodes: Vec<OmniPaxos<KeyValue, MemoryStorage<KeyValue>>> = cluster_config
+        .nodes
+        .iter()
+        .map(|pid| {
+            let config = OmniPaxosConfig {
+                cluster_config: cluster_config.clone(),
+                server_config: ServerConfig {
+                    pid: *pid,
+                    ..Default::default()
+                },
+            };
+            config.build(MemoryStorage::default()).unwrap()
+        })
+        .collect();
+    let run = |nodes: &mut Vec<OmniPaxos<_, _>>| {
+        for _ in 0..100 {
+            let mut msgs = vec![];
+            for op in nodes.iter_mut() {
+                op.tick().expect("Failed to tick");
+                msgs.append(&mut op.outgoing_messages());
+            }
+            for msg in msgs {
+                nodes[msg.get_receiver() as usize - 1]
+                    .handle_incoming(msg)
+                    .expect("Failed to handle message");
+            }
+        }
+    };
+    run(&mut nodes);
+    nodes[0].append(put("a", Some(1))).unwrap();
+    nodes[0].append(put("a", Some(2))).unwrap();
+    run(&mut nodes);
+    assert_eq!(nodes[0].get_decided_idx(), 2);
+    nodes[0].snapshot(None, true).unwrap();
+    match nodes[0].read(0) {
+        Some(LogEntry::Snapshotted(s)) => assert_eq!(s.snapshot.map["a"], Some(2)),
+        e => panic!("Expected a snapshotted entry, got {:?}", e),
+    }
+}
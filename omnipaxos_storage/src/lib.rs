@@ -7,3 +7,9 @@ pub mod memory_storage;
 
 /// an on-disk storage implementation with persistence for the replica state and the log.
 pub mod persistent_storage;
+
+/// an in-memory storage implementation with a fixed capacity for embedded environments.
+pub mod ring_buffer_storage;
+
+/// an in-memory mirror of another storage implementation that persists to it in the background.
+pub mod write_behind_storage;
@@ -1,6 +1,6 @@
 use omnipaxos::{
     ballot_leader_election::Ballot,
-    storage::{Entry, StopSign, Storage, StorageOp, StorageResult},
+    storage::{EntriesHint, Entry, LogStorage, StateStorage, StopSign, StorageOp, StorageResult},
 };
 /// An in-memory storage implementation for SequencePaxos.
 #[derive(Clone)]
@@ -14,6 +14,10 @@ where
     n_prom: Option<Ballot>,
     /// Last accepted round.
     acc_round: Option<Ballot>,
+    /// Highest ballot used in the leader election.
+    ble_ballot: Option<Ballot>,
+    /// The standby promise.
+    standby_promise: Option<Ballot>,
     /// Length of the decided log.
     ld: usize,
     /// Garbage collected index.
@@ -26,7 +30,7 @@ where
     stopsign: Option<StopSign>,
 }
 
-impl<T> Storage<T> for MemoryStorage<T>
+impl<T> LogStorage<T> for MemoryStorage<T>
 where
     T: Entry,
 {
@@ -66,6 +70,70 @@ where
         self.append_entries(entries)
     }
 
+    fn get_entries(&self, from: usize, to: usize) -> StorageResult<Vec<T>> {
+        let from = from - self.trimmed_idx;
+        let to = to - self.trimmed_idx;
+        Ok(self.log.get(from..to).unwrap_or(&[]).to_vec())
+    }
+
+    fn get_entries_hint(
+        &self,
+        from: usize,
+        to: usize,
+        max_bytes: usize,
+    ) -> StorageResult<EntriesHint<T>> {
+        let log = match self
+            .log
+            .get((from - self.trimmed_idx)..(to - self.trimmed_idx))
+        {
+            Some(log) => log,
+            None => return Ok(EntriesHint::with(vec![], from, to)),
+        };
+        let mut bytes = 0;
+        let len = log
+            .iter()
+            .position(|entry| {
+                bytes += entry.size_hint();
+                bytes > max_bytes
+            })
+            .map_or(log.len(), |i| i.max(1));
+        Ok(EntriesHint::with(log[..len].to_vec(), from, to))
+    }
+
+    fn get_entry_ref(&self, idx: usize) -> StorageResult<Option<&T>> {
+        Ok(idx
+            .checked_sub(self.trimmed_idx)
+            .and_then(|i| self.log.get(i)))
+    }
+
+    fn get_log_len(&self) -> StorageResult<usize> {
+        Ok(self.log.len())
+    }
+
+    fn get_suffix(&self, from: usize) -> StorageResult<Vec<T>> {
+        Ok(match self.log.get((from - self.trimmed_idx)..) {
+            Some(s) => s.to_vec(),
+            None => vec![],
+        })
+    }
+
+    fn trim(&mut self, trimmed_idx: usize) -> StorageResult<()> {
+        let to_trim = (trimmed_idx - self.trimmed_idx).min(self.log.len());
+        self.log.drain(0..to_trim);
+        self.trimmed_idx = trimmed_idx;
+        Ok(())
+    }
+
+    /// The size of the stored entries, not counting any heap memory owned by them.
+    fn approximate_size(&self) -> StorageResult<Option<u64>> {
+        Ok(Some((self.log.len() * std::mem::size_of::<T>()) as u64))
+    }
+}
+
+impl<T> StateStorage<T> for MemoryStorage<T>
+where
+    T: Entry,
+{
     fn set_promise(&mut self, n_prom: Ballot) -> StorageResult<()> {
         self.n_prom = Some(n_prom);
         Ok(())
@@ -89,25 +157,26 @@ where
         Ok(self.acc_round)
     }
 
-    fn get_entries(&self, from: usize, to: usize) -> StorageResult<Vec<T>> {
-        let from = from - self.trimmed_idx;
-        let to = to - self.trimmed_idx;
-        Ok(self.log.get(from..to).unwrap_or(&[]).to_vec())
+    fn get_promise(&self) -> StorageResult<Option<Ballot>> {
+        Ok(self.n_prom)
     }
 
-    fn get_log_len(&self) -> StorageResult<usize> {
-        Ok(self.log.len())
+    fn set_ble_ballot(&mut self, ballot: Ballot) -> StorageResult<()> {
+        self.ble_ballot = Some(ballot);
+        Ok(())
     }
 
-    fn get_suffix(&self, from: usize) -> StorageResult<Vec<T>> {
-        Ok(match self.log.get((from - self.trimmed_idx)..) {
-            Some(s) => s.to_vec(),
-            None => vec![],
-        })
+    fn get_ble_ballot(&self) -> StorageResult<Option<Ballot>> {
+        Ok(self.ble_ballot)
     }
 
-    fn get_promise(&self) -> StorageResult<Option<Ballot>> {
-        Ok(self.n_prom)
+    fn set_standby_promise(&mut self, standby: Option<Ballot>) -> StorageResult<()> {
+        self.standby_promise = standby;
+        Ok(())
+    }
+
+    fn get_standby_promise(&self) -> StorageResult<Option<Ballot>> {
+        Ok(self.standby_promise)
     }
 
     fn set_stopsign(&mut self, s: Option<StopSign>) -> StorageResult<()> {
@@ -119,13 +188,6 @@ where
         Ok(self.stopsign.clone())
     }
 
-    fn trim(&mut self, trimmed_idx: usize) -> StorageResult<()> {
-        let to_trim = (trimmed_idx - self.trimmed_idx).min(self.log.len());
-        self.log.drain(0..to_trim);
-        self.trimmed_idx = trimmed_idx;
-        Ok(())
-    }
-
     fn set_compacted_idx(&mut self, compact_idx: usize) -> StorageResult<()> {
         self.compacted_idx = compact_idx;
         Ok(())
@@ -145,12 +207,21 @@ where
     }
 }
 
+impl<T: Entry> MemoryStorage<T> {
+    /// The index after the last entry of the log, including the trimmed entries.
+    pub(crate) fn log_end(&self) -> usize {
+        self.trimmed_idx + self.log.len()
+    }
+}
+
 impl<T: Entry> Default for MemoryStorage<T> {
     fn default() -> Self {
         Self {
             log: vec![],
             n_prom: None,
             acc_round: None,
+            ble_ballot: None,
+            standby_promise: None,
             ld: 0,
             trimmed_idx: 0,
             compacted_idx: 0,
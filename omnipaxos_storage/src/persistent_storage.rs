@@ -1,20 +1,35 @@
 use omnipaxos::{
     ballot_leader_election::Ballot,
-    storage::{Entry, StopSign, Storage, StorageOp, StorageResult},
+    storage::{EntriesHint, Entry, LogStorage, StateStorage, StopSign, StorageOp, StorageResult},
+};
+use rocksdb::{
+    properties::{CUR_SIZE_ALL_MEM_TABLES, TOTAL_SST_FILES_SIZE},
+    ColumnFamilyDescriptor, ColumnFamilyRef, Options, WriteBatchWithTransaction, DB,
 };
-use rocksdb::{ColumnFamilyDescriptor, ColumnFamilyRef, Options, WriteBatchWithTransaction, DB};
 use serde::{Deserialize, Serialize};
 use std::marker::PhantomData;
 use zerocopy::{AsBytes, FromBytes};
 
 const DEFAULT: &str = "/default_storage/";
-const LOG: &str = "log";
-const NPROM: &[u8] = b"NPROM";
-const ACC: &[u8] = b"ACC";
-const DECIDE: &[u8] = b"DECIDE";
-const TRIM: &[u8] = b"TRIM";
-const STOPSIGN: &[u8] = b"STOPSIGN";
-const SNAPSHOT: &[u8] = b"SNAPSHOT";
+/// The column family of the log. The key of an entry is its index as a big endian `usize`, and
+/// the value is the entry serialized with bincode.
+pub const LOG: &str = "log";
+/// The key of the promise, serialized with bincode.
+pub const NPROM: &[u8] = b"NPROM";
+/// The key of the ballot of the leader election, serialized with bincode.
+pub const BLE_BALLOT: &[u8] = b"BLE_BALLOT";
+/// The key of the `Option<Ballot>` of the standby promise, serialized with bincode.
+pub const STANDBY_PROMISE: &[u8] = b"STANDBY_PROMISE";
+/// The key of the accepted round, serialized with bincode.
+pub const ACC: &[u8] = b"ACC";
+/// The key of the decided index as a native endian `usize`.
+pub const DECIDE: &[u8] = b"DECIDE";
+/// The key of the compacted index as a native endian `usize`.
+pub const TRIM: &[u8] = b"TRIM";
+/// The key of the `Option<StopSign>`, serialized with bincode.
+pub const STOPSIGN: &[u8] = b"STOPSIGN";
+/// The key of the `Option<T::Snapshot>`, serialized with bincode.
+pub const SNAPSHOT: &[u8] = b"SNAPSHOT";
 
 // Configuration for `PersistentStorage`.
 /// # Fields
@@ -270,7 +285,7 @@ impl std::fmt::Display for ErrHelper {
     }
 }
 
-impl<T> Storage<T> for PersistentStorage<T>
+impl<T> LogStorage<T> for PersistentStorage<T>
 where
     T: Entry + Serialize + for<'a> Deserialize<'a>,
     T::Snapshot: Serialize + for<'a> Deserialize<'a>,
@@ -350,6 +365,33 @@ where
         Ok(entries)
     }
 
+    fn get_entries_hint(
+        &self,
+        from: usize,
+        to: usize,
+        max_bytes: usize,
+    ) -> StorageResult<EntriesHint<T>> {
+        if to > self.next_log_key || from >= to {
+            return Ok(EntriesHint::with(vec![], from, to));
+        }
+        // Stops at the first entry whose encoding exceeds `max_bytes`, so that only the returned
+        // entries are deserialized.
+        let mut iter = self.db.raw_iterator_cf(self.get_log_handle());
+        let mut entries = vec![];
+        let mut bytes = 0;
+        iter.seek(from.to_be_bytes());
+        for _ in from..to {
+            let entry_bytes = iter.value().ok_or(ErrHelper {})?;
+            bytes += entry_bytes.len();
+            if bytes > max_bytes && !entries.is_empty() {
+                break;
+            }
+            entries.push(bincode::deserialize(entry_bytes)?);
+            iter.next();
+        }
+        Ok(EntriesHint::with(entries, from, to))
+    }
+
     fn get_log_len(&self) -> StorageResult<usize> {
         Ok(self.next_log_key - self.get_compacted_idx()?)
     }
@@ -358,6 +400,38 @@ where
         self.get_entries(from, self.next_log_key)
     }
 
+    /// The size of the SST files and memtables of the log and the metadata.
+    fn approximate_size(&self) -> StorageResult<Option<u64>> {
+        let mut size = 0;
+        for property in [TOTAL_SST_FILES_SIZE, CUR_SIZE_ALL_MEM_TABLES] {
+            size += self.db.property_int_value(property)?.unwrap_or(0);
+            size += self
+                .db
+                .property_int_value_cf(self.get_log_handle(), property)?
+                .unwrap_or(0);
+        }
+        Ok(Some(size))
+    }
+
+    /// Syncs the write-ahead log of RocksDB to disk, which is not done on every write by default.
+    fn flush(&self) -> StorageResult<()> {
+        Ok(self.db.flush_wal(true)?)
+    }
+
+    fn trim(&mut self, trimmed_idx: usize) -> StorageResult<()> {
+        let from_key = 0_usize.to_be_bytes();
+        let to_key = trimmed_idx.to_be_bytes();
+        self.db
+            .delete_range_cf(self.get_log_handle(), from_key, to_key)?;
+        Ok(())
+    }
+}
+
+impl<T> StateStorage<T> for PersistentStorage<T>
+where
+    T: Entry + Serialize + for<'a> Deserialize<'a>,
+    T::Snapshot: Serialize + for<'a> Deserialize<'a>,
+{
     fn get_promise(&self) -> StorageResult<Option<Ballot>> {
         let promise = self.db.get_pinned(NPROM)?;
         match promise {
@@ -372,6 +446,34 @@ where
         Ok(())
     }
 
+    fn get_ble_ballot(&self) -> StorageResult<Option<Ballot>> {
+        let ballot = self.db.get_pinned(BLE_BALLOT)?;
+        match ballot {
+            Some(pinned_bytes) => Ok(Some(bincode::deserialize(&pinned_bytes)?)),
+            None => Ok(None),
+        }
+    }
+
+    fn set_ble_ballot(&mut self, ballot: Ballot) -> StorageResult<()> {
+        let ballot_bytes = bincode::serialize(&ballot)?;
+        self.db.put(BLE_BALLOT, ballot_bytes)?;
+        Ok(())
+    }
+
+    fn get_standby_promise(&self) -> StorageResult<Option<Ballot>> {
+        let standby = self.db.get_pinned(STANDBY_PROMISE)?;
+        match standby {
+            Some(pinned_bytes) => Ok(bincode::deserialize(&pinned_bytes)?),
+            None => Ok(None),
+        }
+    }
+
+    fn set_standby_promise(&mut self, standby: Option<Ballot>) -> StorageResult<()> {
+        let standby_bytes = bincode::serialize(&standby)?;
+        self.db.put(STANDBY_PROMISE, standby_bytes)?;
+        Ok(())
+    }
+
     fn get_decided_idx(&self) -> StorageResult<usize> {
         let decided = self.db.get_pinned(DECIDE)?;
         match decided {
@@ -445,12 +547,4 @@ where
         self.db.put(SNAPSHOT, s)?;
         Ok(())
     }
-
-    fn trim(&mut self, trimmed_idx: usize) -> StorageResult<()> {
-        let from_key = 0_usize.to_be_bytes();
-        let to_key = trimmed_idx.to_be_bytes();
-        self.db
-            .delete_range_cf(self.get_log_handle(), from_key, to_key)?;
-        Ok(())
-    }
 }
@@ -0,0 +1,259 @@
+use omnipaxos::{
+    ballot_leader_election::Ballot,
+    storage::{Entry, LogStorage, StateStorage, StopSign, StorageOp, StorageResult},
+};
+use std::collections::VecDeque;
+
+/// An in-memory storage that holds at most a fixed number of log entries, for embedded or
+/// real-time targets that can't let the log grow. The buffer for the entries is allocated once
+/// when the storage is created and never grows, so appending and compacting the log does not
+/// allocate.
+///
+/// The storage reports its capacity to OmniPaxos with [`LogStorage::get_capacity`], which makes the
+/// server compact its decided log before the buffer is full and refuse new proposals while the
+/// entries that can't be compacted yet fill it. The entries should therefore support snapshots,
+/// so that every server can compact its log by itself. Otherwise, only the leader can trim the
+/// log once all servers have accepted the entries. The compacted entries must be removed
+/// immediately, i.e., with the default `TrimMode::Immediate`. An append that would exceed the
+/// capacity fails without changing the storage.
+pub struct RingBufferStorage<T>
+where
+    T: Entry,
+{
+    /// The entries after the trimmed index.
+    log: VecDeque<T>,
+    /// The maximum number of entries in `log`.
+    capacity: usize,
+    /// Last promised round.
+    n_prom: Option<Ballot>,
+    /// Last accepted round.
+    acc_round: Option<Ballot>,
+    /// Highest ballot used in the leader election.
+    ble_ballot: Option<Ballot>,
+    /// The standby promise.
+    standby_promise: Option<Ballot>,
+    /// Length of the decided log.
+    ld: usize,
+    /// Garbage collected index.
+    trimmed_idx: usize,
+    /// Stored compact index
+    compacted_idx: usize,
+    /// Stored snapshot
+    snapshot: Option<T::Snapshot>,
+    /// Stored StopSign
+    stopsign: Option<StopSign>,
+}
+
+impl<T> RingBufferStorage<T>
+where
+    T: Entry,
+{
+    /// Creates a storage that holds at most `capacity` log entries.
+    pub fn with_capacity(capacity: usize) -> Self {
+        Self {
+            log: VecDeque::with_capacity(capacity),
+            capacity,
+            n_prom: None,
+            acc_round: None,
+            ble_ballot: None,
+            standby_promise: None,
+            ld: 0,
+            trimmed_idx: 0,
+            compacted_idx: 0,
+            snapshot: None,
+            stopsign: None,
+        }
+    }
+
+    /// Fails if `len` entries exceed the capacity.
+    fn check_capacity(&self, len: usize) -> StorageResult<()> {
+        if len > self.capacity {
+            Err(format!(
+                "{} entries exceed the capacity of {} entries, the log must be compacted first",
+                len, self.capacity
+            )
+            .into())
+        } else {
+            Ok(())
+        }
+    }
+
+    /// The number of entries in the log after applying `ops`.
+    fn log_len_after(&self, ops: &[StorageOp<T>]) -> usize {
+        let mut trimmed_idx = self.trimmed_idx;
+        let mut len = self.log.len();
+        for op in ops {
+            match op {
+                StorageOp::AppendEntry(_) => len += 1,
+                StorageOp::AppendEntries(entries) => len += entries.len(),
+                StorageOp::AppendOnPrefix(from_idx, entries) => {
+                    len = len.min(from_idx - trimmed_idx) + entries.len()
+                }
+                StorageOp::Trim(idx) => {
+                    len -= (idx - trimmed_idx).min(len);
+                    trimmed_idx = *idx;
+                }
+                _ => {}
+            }
+        }
+        len
+    }
+}
+
+impl<T> LogStorage<T> for RingBufferStorage<T>
+where
+    T: Entry,
+{
+    fn write_atomically(&mut self, ops: Vec<StorageOp<T>>) -> StorageResult<()> {
+        // Checked upfront so that a failed write leaves the storage unchanged, and a write that
+        // trims the log after appending to it only needs to fit in the end
+        self.check_capacity(self.log_len_after(&ops))?;
+        for op in ops {
+            match op {
+                StorageOp::AppendEntry(entry) => self.log.push_back(entry),
+                StorageOp::AppendEntries(entries) => self.log.extend(entries),
+                StorageOp::AppendOnPrefix(from_idx, entries) => {
+                    self.log.truncate(from_idx - self.trimmed_idx);
+                    self.log.extend(entries);
+                }
+                StorageOp::SetPromise(bal) => self.set_promise(bal)?,
+                StorageOp::SetDecidedIndex(idx) => self.set_decided_idx(idx)?,
+                StorageOp::SetAcceptedRound(bal) => self.set_accepted_round(bal)?,
+                StorageOp::SetCompactedIdx(idx) => self.set_compacted_idx(idx)?,
+                StorageOp::Trim(idx) => self.trim(idx)?,
+                StorageOp::SetStopsign(ss) => self.set_stopsign(ss)?,
+                StorageOp::SetSnapshot(snap) => self.set_snapshot(snap)?,
+            }
+        }
+        Ok(())
+    }
+
+    fn append_entry(&mut self, entry: T) -> StorageResult<()> {
+        self.check_capacity(self.log.len() + 1)?;
+        self.log.push_back(entry);
+        Ok(())
+    }
+
+    fn append_entries(&mut self, entries: Vec<T>) -> StorageResult<()> {
+        self.check_capacity(self.log.len() + entries.len())?;
+        self.log.extend(entries);
+        Ok(())
+    }
+
+    fn append_on_prefix(&mut self, from_idx: usize, entries: Vec<T>) -> StorageResult<()> {
+        let prefix_len = (from_idx - self.trimmed_idx).min(self.log.len());
+        self.check_capacity(prefix_len + entries.len())?;
+        self.log.truncate(prefix_len);
+        self.log.extend(entries);
+        Ok(())
+    }
+
+    fn get_entries(&self, from: usize, to: usize) -> StorageResult<Vec<T>> {
+        let from = (from - self.trimmed_idx).min(self.log.len());
+        let to = (to - self.trimmed_idx).min(self.log.len());
+        Ok(self.log.range(from..to.max(from)).cloned().collect())
+    }
+
+    fn get_entry_ref(&self, idx: usize) -> StorageResult<Option<&T>> {
+        Ok(idx
+            .checked_sub(self.trimmed_idx)
+            .and_then(|i| self.log.get(i)))
+    }
+
+    fn get_log_len(&self) -> StorageResult<usize> {
+        Ok(self.log.len())
+    }
+
+    fn get_suffix(&self, from: usize) -> StorageResult<Vec<T>> {
+        let from = (from - self.trimmed_idx).min(self.log.len());
+        Ok(self.log.range(from..).cloned().collect())
+    }
+
+    fn trim(&mut self, trimmed_idx: usize) -> StorageResult<()> {
+        let to_trim = (trimmed_idx - self.trimmed_idx).min(self.log.len());
+        self.log.drain(0..to_trim);
+        self.trimmed_idx = trimmed_idx;
+        Ok(())
+    }
+
+    fn get_capacity(&self) -> StorageResult<Option<usize>> {
+        Ok(Some(self.capacity))
+    }
+}
+
+impl<T> StateStorage<T> for RingBufferStorage<T>
+where
+    T: Entry,
+{
+    fn set_promise(&mut self, n_prom: Ballot) -> StorageResult<()> {
+        self.n_prom = Some(n_prom);
+        Ok(())
+    }
+
+    fn set_decided_idx(&mut self, ld: usize) -> StorageResult<()> {
+        self.ld = ld;
+        Ok(())
+    }
+
+    fn get_decided_idx(&self) -> StorageResult<usize> {
+        Ok(self.ld)
+    }
+
+    fn set_accepted_round(&mut self, na: Ballot) -> StorageResult<()> {
+        self.acc_round = Some(na);
+        Ok(())
+    }
+
+    fn get_accepted_round(&self) -> StorageResult<Option<Ballot>> {
+        Ok(self.acc_round)
+    }
+
+    fn get_promise(&self) -> StorageResult<Option<Ballot>> {
+        Ok(self.n_prom)
+    }
+
+    fn set_ble_ballot(&mut self, ballot: Ballot) -> StorageResult<()> {
+        self.ble_ballot = Some(ballot);
+        Ok(())
+    }
+
+    fn get_ble_ballot(&self) -> StorageResult<Option<Ballot>> {
+        Ok(self.ble_ballot)
+    }
+
+    fn set_standby_promise(&mut self, standby: Option<Ballot>) -> StorageResult<()> {
+        self.standby_promise = standby;
+        Ok(())
+    }
+
+    fn get_standby_promise(&self) -> StorageResult<Option<Ballot>> {
+        Ok(self.standby_promise)
+    }
+
+    fn set_stopsign(&mut self, s: Option<StopSign>) -> StorageResult<()> {
+        self.stopsign = s;
+        Ok(())
+    }
+
+    fn get_stopsign(&self) -> StorageResult<Option<StopSign>> {
+        Ok(self.stopsign.clone())
+    }
+
+    fn set_compacted_idx(&mut self, compact_idx: usize) -> StorageResult<()> {
+        self.compacted_idx = compact_idx;
+        Ok(())
+    }
+
+    fn get_compacted_idx(&self) -> StorageResult<usize> {
+        Ok(self.compacted_idx)
+    }
+
+    fn set_snapshot(&mut self, snapshot: Option<T::Snapshot>) -> StorageResult<()> {
+        self.snapshot = snapshot;
+        Ok(())
+    }
+
+    fn get_snapshot(&self) -> StorageResult<Option<T::Snapshot>> {
+        Ok(self.snapshot.clone())
+    }
+}
@@ -0,0 +1,443 @@
+use crate::memory_storage::MemoryStorage;
+use omnipaxos::{
+    ballot_leader_election::Ballot,
+    storage::{
+        EntriesHint, Entry, LogStorage, StateStorage, StopSign, Storage, StorageOp, StorageResult,
+    },
+};
+use std::{
+    collections::VecDeque,
+    sync::{Arc, Condvar, Mutex, MutexGuard},
+    thread::{self, JoinHandle},
+};
+
+const WAL_POISONED_MSG: &str = "WAL of the write-behind storage poisoned";
+
+/// A write that has been applied to the in-memory log but not to the backend yet.
+struct WalEntry<T: Entry> {
+    ops: Vec<StorageOp<T>>,
+    /// The lowest index from which this write replaces the log in the backend.
+    overwritten_idx: usize,
+    /// The index after the last entry of the log once this write is persisted.
+    log_end: usize,
+}
+
+/// The queue of writes that the persisting thread applies to the backend.
+struct Wal<T: Entry> {
+    pending: VecDeque<WalEntry<T>>,
+    /// The `overwritten_idx` and `log_end` of the writes that are being persisted.
+    in_flight: Option<(usize, usize)>,
+    /// The index after the last entry of the log in the backend.
+    persisted_log_end: usize,
+    error: Option<String>,
+    closed: bool,
+}
+
+impl<T: Entry> Wal<T> {
+    fn check_error(&self) -> StorageResult<()> {
+        match &self.error {
+            Some(e) => Err(format!("Failed to persist a write in the background: {}", e).into()),
+            None => Ok(()),
+        }
+    }
+
+    fn is_empty(&self) -> bool {
+        self.pending.is_empty() && self.in_flight.is_none()
+    }
+}
+
+type SharedWal<T> = Arc<(Mutex<Wal<T>>, Condvar)>;
+
+/// A storage that keeps the whole state in memory and persists it to a `backend` storage in the
+/// background (write-behind). All reads are served from memory. Writes return as soon as they
+/// are applied in memory and queued, and a background thread applies the queued writes to the
+/// backend in order, merging all writes queued in the meantime into a single
+/// `write_atomically()` call.
+///
+/// The log is only reported as accepted to the leader once it is durable in the backend, see
+/// [`LogStorage::get_durable_idx`]. The promise, the accepted round, the leader election ballot
+/// and the standby promise are written through instead: a write that changes them waits for the
+/// queued writes and returns once it is durable in the backend, so a server that crashes never
+/// recovers an older promise than it sent. Together, a decided entry is never lost if a minority
+/// of the servers crash. Use [`LogStorage::flush`] to wait until all writes are durable. If the
+/// backend fails to persist a queued write, all further writes and
+/// [`LogStorage::get_durable_idx`] return an error.
+pub struct WriteBehindStorage<T, S>
+where
+    T: Entry,
+    S: Storage<T>,
+{
+    mirror: MemoryStorage<T>,
+    backend: Arc<Mutex<S>>,
+    wal: SharedWal<T>,
+    persister: Option<JoinHandle<()>>,
+}
+
+impl<T, S> WriteBehindStorage<T, S>
+where
+    T: Entry + Send + 'static,
+    T::Snapshot: Send,
+    S: Storage<T> + Send + 'static,
+{
+    /// Creates a write-behind storage that persists to `backend`. The state of `backend` is read
+    /// into memory, except for the entries before its compacted index.
+    pub fn with(backend: S) -> StorageResult<Self> {
+        let mut mirror = MemoryStorage::default();
+        let compacted_idx = backend.get_compacted_idx()?;
+        mirror.trim(compacted_idx)?;
+        let mut ops = vec![
+            StorageOp::AppendEntries(backend.get_suffix(compacted_idx)?),
+            StorageOp::SetDecidedIndex(backend.get_decided_idx()?),
+            StorageOp::SetCompactedIdx(compacted_idx),
+            StorageOp::SetStopsign(backend.get_stopsign()?),
+            StorageOp::SetSnapshot(backend.get_snapshot()?),
+        ];
+        if let Some(n_prom) = backend.get_promise()? {
+            ops.push(StorageOp::SetPromise(n_prom));
+        }
+        if let Some(na) = backend.get_accepted_round()? {
+            ops.push(StorageOp::SetAcceptedRound(na));
+        }
+        mirror.write_atomically(ops)?;
+        if let Some(ballot) = backend.get_ble_ballot()? {
+            mirror.set_ble_ballot(ballot)?;
+        }
+        mirror.set_standby_promise(backend.get_standby_promise()?)?;
+
+        let backend = Arc::new(Mutex::new(backend));
+        let wal: SharedWal<T> = Arc::new((
+            Mutex::new(Wal {
+                pending: VecDeque::new(),
+                in_flight: None,
+                persisted_log_end: mirror.log_end(),
+                error: None,
+                closed: false,
+            }),
+            Condvar::new(),
+        ));
+        let persister = {
+            let backend = Arc::clone(&backend);
+            let wal = Arc::clone(&wal);
+            thread::Builder::new()
+                .name("omnipaxos-write-behind".to_string())
+                .spawn(move || persist(&backend, &wal))?
+        };
+        Ok(Self {
+            mirror,
+            backend,
+            wal,
+            persister: Some(persister),
+        })
+    }
+}
+
+impl<T, S> WriteBehindStorage<T, S>
+where
+    T: Entry,
+    S: Storage<T>,
+{
+    /// Returns the number of writes that are queued but not yet being persisted.
+    pub fn num_pending_writes(&self) -> usize {
+        self.lock_wal().pending.len()
+    }
+
+    fn lock_wal(&self) -> MutexGuard<'_, Wal<T>> {
+        self.wal.0.lock().expect(WAL_POISONED_MSG)
+    }
+
+    fn enqueue(&mut self, ops: Vec<StorageOp<T>>, overwritten_idx: usize) {
+        let entry = WalEntry {
+            ops,
+            overwritten_idx,
+            log_end: self.mirror.log_end(),
+        };
+        self.lock_wal().pending.push_back(entry);
+        self.wal.1.notify_all();
+    }
+
+    /// Waits for the queued writes and applies `write` to the backend, so that it is durable once
+    /// this returns, and then to the in-memory state with `mirror_write`. Neither is changed if
+    /// the backend fails.
+    fn write_through<W, M>(&mut self, write: W, mirror_write: M) -> StorageResult<()>
+    where
+        W: FnOnce(&mut S) -> StorageResult<()>,
+        M: FnOnce(&mut MemoryStorage<T>) -> StorageResult<()>,
+    {
+        // The queue stays empty until this returns, since only `&mut self` enqueues writes
+        self.flush()?;
+        write(&mut *self.backend.lock().expect(WAL_POISONED_MSG))?;
+        mirror_write(&mut self.mirror)?;
+        self.lock_wal().persisted_log_end = self.mirror.log_end();
+        Ok(())
+    }
+
+    /// Whether `ops` change the promise or the accepted round, which must be durable before the
+    /// server acknowledges them. Rewriting an unchanged value doesn't have to wait, since the
+    /// value is already durable.
+    fn must_write_through(&self, ops: &[StorageOp<T>]) -> bool {
+        ops.iter().any(|op| match op {
+            StorageOp::SetPromise(n_prom) => self.mirror.get_promise().ok() != Some(Some(*n_prom)),
+            StorageOp::SetAcceptedRound(na) => {
+                self.mirror.get_accepted_round().ok() != Some(Some(*na))
+            }
+            _ => false,
+        })
+    }
+}
+
+/// Applies the queued writes to `backend` until the storage is dropped and the queue is empty,
+/// or a write fails.
+fn persist<T, S>(backend: &Mutex<S>, wal: &(Mutex<Wal<T>>, Condvar))
+where
+    T: Entry,
+    S: Storage<T>,
+{
+    let (wal, cond) = wal;
+    loop {
+        let mut guard = wal.lock().expect(WAL_POISONED_MSG);
+        while guard.pending.is_empty() && !guard.closed {
+            guard = cond.wait(guard).expect(WAL_POISONED_MSG);
+        }
+        if guard.pending.is_empty() {
+            return;
+        }
+        let entries: Vec<WalEntry<T>> = guard.pending.drain(..).collect();
+        let overwritten_idx = entries.iter().map(|e| e.overwritten_idx).min().unwrap();
+        let log_end = entries.last().unwrap().log_end;
+        guard.in_flight = Some((overwritten_idx, log_end));
+        drop(guard);
+
+        let result = write_entries(&mut *backend.lock().expect(WAL_POISONED_MSG), entries);
+        let mut guard = wal.lock().expect(WAL_POISONED_MSG);
+        guard.in_flight = None;
+        let failed = match result {
+            Ok(()) => {
+                guard.persisted_log_end = log_end;
+                false
+            }
+            Err(e) => {
+                guard.error = Some(e.to_string());
+                true
+            }
+        };
+        cond.notify_all();
+        if failed {
+            return;
+        }
+    }
+}
+
+fn write_entries<T, S>(backend: &mut S, entries: Vec<WalEntry<T>>) -> StorageResult<()>
+where
+    T: Entry,
+    S: Storage<T>,
+{
+    let ops = entries.into_iter().flat_map(|e| e.ops).collect();
+    backend.write_atomically(ops)
+}
+
+impl<T, S> LogStorage<T> for WriteBehindStorage<T, S>
+where
+    T: Entry,
+    S: Storage<T>,
+{
+    fn write_atomically(&mut self, ops: Vec<StorageOp<T>>) -> StorageResult<()> {
+        self.lock_wal().check_error()?;
+        if self.must_write_through(&ops) {
+            let mirror_ops = ops.clone();
+            return self.write_through(
+                |backend| backend.write_atomically(ops),
+                |mirror| mirror.write_atomically(mirror_ops),
+            );
+        }
+        let overwritten_idx = ops
+            .iter()
+            .filter_map(|op| match op {
+                StorageOp::AppendOnPrefix(from_idx, _) => Some(*from_idx),
+                _ => None,
+            })
+            .min()
+            .unwrap_or(usize::MAX);
+        self.mirror.write_atomically(ops.clone())?;
+        self.enqueue(ops, overwritten_idx);
+        Ok(())
+    }
+
+    fn append_entry(&mut self, entry: T) -> StorageResult<()> {
+        self.write_atomically(vec![StorageOp::AppendEntry(entry)])
+    }
+
+    fn append_entries(&mut self, entries: Vec<T>) -> StorageResult<()> {
+        self.write_atomically(vec![StorageOp::AppendEntries(entries)])
+    }
+
+    fn append_on_prefix(&mut self, from_idx: usize, entries: Vec<T>) -> StorageResult<()> {
+        self.write_atomically(vec![StorageOp::AppendOnPrefix(from_idx, entries)])
+    }
+
+    fn get_entries(&self, from: usize, to: usize) -> StorageResult<Vec<T>> {
+        self.mirror.get_entries(from, to)
+    }
+
+    fn get_entries_hint(
+        &self,
+        from: usize,
+        to: usize,
+        max_bytes: usize,
+    ) -> StorageResult<EntriesHint<T>> {
+        self.mirror.get_entries_hint(from, to, max_bytes)
+    }
+
+    fn get_entry_ref(&self, idx: usize) -> StorageResult<Option<&T>> {
+        self.mirror.get_entry_ref(idx)
+    }
+
+    fn get_log_len(&self) -> StorageResult<usize> {
+        self.mirror.get_log_len()
+    }
+
+    fn get_suffix(&self, from: usize) -> StorageResult<Vec<T>> {
+        self.mirror.get_suffix(from)
+    }
+
+    fn trim(&mut self, idx: usize) -> StorageResult<()> {
+        self.write_atomically(vec![StorageOp::Trim(idx)])
+    }
+
+    /// The size of the backend. Waits for the write that is being persisted, if any.
+    fn approximate_size(&self) -> StorageResult<Option<u64>> {
+        self.backend
+            .lock()
+            .expect(WAL_POISONED_MSG)
+            .approximate_size()
+    }
+
+    /// The capacity of the backend, which has to hold every entry of the in-memory log once it is
+    /// persisted.
+    fn get_capacity(&self) -> StorageResult<Option<usize>> {
+        self.backend.lock().expect(WAL_POISONED_MSG).get_capacity()
+    }
+
+    /// The index after the entries of the log that are durable in the backend, i.e., that have
+    /// been persisted and are not overwritten by a pending write.
+    fn get_durable_idx(&self) -> StorageResult<Option<usize>> {
+        let wal = self.lock_wal();
+        wal.check_error()?;
+        if wal.is_empty() {
+            return Ok(None);
+        }
+        let overwritten_idx = wal
+            .pending
+            .iter()
+            .map(|e| e.overwritten_idx)
+            .chain(wal.in_flight.map(|(overwritten_idx, _)| overwritten_idx))
+            .min()
+            .unwrap_or(usize::MAX);
+        Ok(Some(wal.persisted_log_end.min(overwritten_idx)))
+    }
+
+    /// Blocks until all writes so far are durable in the backend.
+    fn flush(&self) -> StorageResult<()> {
+        let (wal, cond) = &*self.wal;
+        let mut wal = wal.lock().expect(WAL_POISONED_MSG);
+        while !wal.is_empty() && wal.error.is_none() {
+            wal = cond.wait(wal).expect(WAL_POISONED_MSG);
+        }
+        wal.check_error()
+    }
+}
+
+impl<T, S> StateStorage<T> for WriteBehindStorage<T, S>
+where
+    T: Entry,
+    S: Storage<T>,
+{
+    fn set_promise(&mut self, n_prom: Ballot) -> StorageResult<()> {
+        self.write_atomically(vec![StorageOp::SetPromise(n_prom)])
+    }
+
+    fn set_decided_idx(&mut self, ld: usize) -> StorageResult<()> {
+        self.write_atomically(vec![StorageOp::SetDecidedIndex(ld)])
+    }
+
+    fn get_decided_idx(&self) -> StorageResult<usize> {
+        self.mirror.get_decided_idx()
+    }
+
+    fn set_accepted_round(&mut self, na: Ballot) -> StorageResult<()> {
+        self.write_atomically(vec![StorageOp::SetAcceptedRound(na)])
+    }
+
+    fn get_accepted_round(&self) -> StorageResult<Option<Ballot>> {
+        self.mirror.get_accepted_round()
+    }
+
+    fn get_promise(&self) -> StorageResult<Option<Ballot>> {
+        self.mirror.get_promise()
+    }
+
+    fn set_ble_ballot(&mut self, ballot: Ballot) -> StorageResult<()> {
+        self.lock_wal().check_error()?;
+        self.write_through(
+            |backend| backend.set_ble_ballot(ballot),
+            |mirror| mirror.set_ble_ballot(ballot),
+        )
+    }
+
+    fn get_ble_ballot(&self) -> StorageResult<Option<Ballot>> {
+        self.mirror.get_ble_ballot()
+    }
+
+    fn set_standby_promise(&mut self, standby: Option<Ballot>) -> StorageResult<()> {
+        self.lock_wal().check_error()?;
+        self.write_through(
+            |backend| backend.set_standby_promise(standby),
+            |mirror| mirror.set_standby_promise(standby),
+        )
+    }
+
+    fn get_standby_promise(&self) -> StorageResult<Option<Ballot>> {
+        self.mirror.get_standby_promise()
+    }
+
+    fn set_stopsign(&mut self, s: Option<StopSign>) -> StorageResult<()> {
+        self.write_atomically(vec![StorageOp::SetStopsign(s)])
+    }
+
+    fn get_stopsign(&self) -> StorageResult<Option<StopSign>> {
+        self.mirror.get_stopsign()
+    }
+
+    fn set_compacted_idx(&mut self, idx: usize) -> StorageResult<()> {
+        self.write_atomically(vec![StorageOp::SetCompactedIdx(idx)])
+    }
+
+    fn get_compacted_idx(&self) -> StorageResult<usize> {
+        self.mirror.get_compacted_idx()
+    }
+
+    fn set_snapshot(&mut self, snapshot: Option<T::Snapshot>) -> StorageResult<()> {
+        self.write_atomically(vec![StorageOp::SetSnapshot(snapshot)])
+    }
+
+    fn get_snapshot(&self) -> StorageResult<Option<T::Snapshot>> {
+        self.mirror.get_snapshot()
+    }
+}
+
+impl<T, S> Drop for WriteBehindStorage<T, S>
+where
+    T: Entry,
+    S: Storage<T>,
+{
+    /// Persists the pending writes before the storage is dropped.
+    fn drop(&mut self) {
+        if let Ok(mut wal) = self.wal.0.lock() {
+            wal.closed = true;
+        }
+        self.wal.1.notify_all();
+        if let Some(persister) = self.persister.take() {
+            let _ = persister.join();
+        }
+    }
+}